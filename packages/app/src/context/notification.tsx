@@ -105,7 +105,12 @@ export const { use: useNotification, provider: NotificationProvider } = createSi
             session: sessionID,
           })
           const href = `/${base64Encode(directory)}/session/${sessionID}`
-          void platform.notify(language.t("notification.session.responseReady.title"), session?.title ?? sessionID, href)
+          void platform.notify(
+            language.t("notification.session.responseReady.title"),
+            session?.title ?? sessionID,
+            href,
+            "session.responseReady",
+          )
           break
         }
         case "session.error": {
@@ -130,7 +135,7 @@ export const { use: useNotification, provider: NotificationProvider } = createSi
           const href = sessionID
             ? `/${base64Encode(directory)}/session/${sessionID}`
             : `/${base64Encode(directory)}/session`
-          void platform.notify(language.t("notification.session.error.title"), description, href)
+          void platform.notify(language.t("notification.session.error.title"), description, href, "session.error")
           break
         }
       }
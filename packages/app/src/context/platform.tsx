@@ -17,8 +17,8 @@ export type Platform = {
   /** Restart the app  */
   restart(): Promise<void>
 
-  /** Send a system notification (optional deep link) */
-  notify(title: string, description?: string, href?: string): Promise<void>
+  /** Send a system notification (optional deep link); bursts in the same category may be summarized */
+  notify(title: string, description?: string, href?: string, category?: string): Promise<void>
 
   /** Open directory picker dialog (native on Tauri, server-backed on web) */
   openDirectoryPickerDialog?(opts?: { title?: string; multiple?: boolean }): Promise<string | string[] | null>
@@ -5,6 +5,7 @@ import { persisted } from "@/utils/persist"
 import { usePlatform } from "./platform"
 import { useCommand, parseKeybind, matchKeybind } from "./command"
 import { AudioCapture, isAudioCaptureSupported } from "@/utils/audio-capture"
+import { errorMessage } from "@/utils/error"
 
 export type ModelStatus = "not-downloaded" | "downloading" | "ready" | "error"
 export type RecordingMode = "toggle" | "push-to-talk"
@@ -143,7 +144,7 @@ export const { use: useVoice, provider: VoiceProvider } = createSimpleContext({
         setModelStatus("ready")
       } catch (e) {
         setModelStatus("error")
-        setError(e instanceof Error ? e.message : String(e))
+        setError(errorMessage(e))
         console.error("Failed to download model:", e)
       }
     }
@@ -189,7 +190,7 @@ export const { use: useVoice, provider: VoiceProvider } = createSimpleContext({
         refreshDevices()
         setIsRecording(true)
       } catch (e) {
-        setError(e instanceof Error ? e.message : String(e))
+        setError(errorMessage(e))
         console.error("Failed to start recording:", e)
       }
     }
@@ -212,7 +213,7 @@ export const { use: useVoice, provider: VoiceProvider } = createSimpleContext({
         const text = await invoke<string>("stt_stop_and_transcribe")
         setLastTranscription(text)
       } catch (e) {
-        setError(e instanceof Error ? e.message : String(e))
+        setError(errorMessage(e))
         console.error("Failed to transcribe:", e)
       } finally {
         setIsTranscribing(false)
@@ -157,7 +157,7 @@ export default function Layout(props: ParentProps) {
       if (now - lastAlerted < permissionAlertCooldownMs) return
       alertedAtBySession.set(sessionKey, now)
 
-      void platform.notify(language.t("notification.permission.title"), description, href)
+      void platform.notify(language.t("notification.permission.title"), description, href, "permission")
 
       const currentDir = params.dir ? base64Decode(params.dir) : undefined
       const currentSession = params.id
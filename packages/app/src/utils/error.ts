@@ -0,0 +1,17 @@
+// Errors rejected by native commands are objects with a message and an
+// optional remediation hint, see packages/desktop/src-tauri/src/error.rs
+
+type CommandError = {
+  message: string
+  remediation?: string
+}
+
+function isCommandError(e: unknown): e is CommandError {
+  return typeof e === "object" && e !== null && "message" in e && typeof e.message === "string"
+}
+
+/** The message of an error, followed by its remediation hint if it has one */
+export function errorMessage(e: unknown): string {
+  if (!isCommandError(e)) return String(e)
+  return e.remediation ? `${e.message}\n\n${e.remediation}` : e.message
+}
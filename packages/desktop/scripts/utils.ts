@@ -44,7 +44,12 @@ export async function copyBinaryToSidecarFolder(source: string, target = RUST_TA
   const dest = windowsify(`src-tauri/sidecars/opencode-cli-${target}`)
   await $`cp ${source} ${dest}`
 
-  console.log(`Copied ${source} to ${dest}`)
+  // Bundled as a resource too, for the app to fall back to when the sidecar is quarantined
+  await $`mkdir -p src-tauri/sidecars/fallback`
+  const fallback = windowsify("src-tauri/sidecars/fallback/opencode-cli")
+  await $`cp ${source} ${fallback}`
+
+  console.log(`Copied ${source} to ${dest} and ${fallback}`)
 }
 
 export function windowsify(path: string) {
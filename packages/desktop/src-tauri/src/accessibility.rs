@@ -0,0 +1,139 @@
+//! Screen reader support for what happens outside the page.
+//!
+//! `announce` speaks a message through the screen reader, for events the
+//! user can't see happen: the server becoming ready, a transcription
+//! finishing. It goes through an ARIA live region of the focused window,
+//! which every platform's screen reader follows through the webview's
+//! accessibility tree, so no native API is needed per platform. The
+//! frontend announces its own events with `set_accessibility_announcement`.
+//!
+//! On Windows the caption buttons of the overlay title bar are drawn by the
+//! page without names; `titlebar_script` names them.
+
+use serde::Deserialize;
+use tauri::{AppHandle, Manager, WebviewWindow};
+
+use crate::{error::AppError, i18n};
+
+const ANNOUNCER_ID: &str = "aura-announcer";
+const MAX_ANNOUNCEMENT_CHARS: usize = 500;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum Priority {
+    /// Read once the screen reader is idle
+    #[default]
+    Polite,
+    /// Read at once, interrupting
+    Assertive,
+}
+
+impl Priority {
+    fn live(self) -> &'static str {
+        match self {
+            Priority::Polite => "polite",
+            Priority::Assertive => "assertive",
+        }
+    }
+}
+
+/// The window the user is in, else the main one
+fn target_window(app: &AppHandle) -> Option<WebviewWindow> {
+    let windows = app.webview_windows();
+    windows
+        .values()
+        .find(|window| window.is_focused().unwrap_or(false))
+        .or_else(|| windows.get("main"))
+        .or_else(|| windows.values().next())
+        .cloned()
+}
+
+fn announce_script(text: &str, priority: Priority) -> String {
+    let id = format!("{}-{}", ANNOUNCER_ID, priority.live());
+    let role = match priority {
+        Priority::Polite => "status",
+        Priority::Assertive => "alert",
+    };
+    let text = serde_json::to_string(text).unwrap_or_default();
+    // Emptied first, so the same message twice is read twice
+    format!(
+        r#"(() => {{
+  if (!document.body) return;
+  let region = document.getElementById("{id}");
+  if (!region) {{
+    region = document.createElement("div");
+    region.id = "{id}";
+    region.setAttribute("role", "{role}");
+    region.setAttribute("aria-live", "{live}");
+    region.setAttribute("aria-atomic", "true");
+    region.style.cssText = "position:absolute;width:1px;height:1px;margin:-1px;padding:0;overflow:hidden;clip:rect(0 0 0 0);white-space:nowrap;border:0";
+    document.body.appendChild(region);
+  }}
+  region.textContent = "";
+  setTimeout(() => {{ region.textContent = {text}; }}, 100);
+}})();"#,
+        id = id,
+        role = role,
+        live = priority.live(),
+        text = text
+    )
+}
+
+/// Have the screen reader read `text`
+pub fn announce(app: &AppHandle, text: &str, priority: Priority) {
+    let Some(window) = target_window(app) else {
+        return;
+    };
+    if let Err(e) = window.eval(announce_script(text, priority)) {
+        eprintln!("Failed to post announcement: {}", e);
+    }
+}
+
+/// Initialization script naming the caption buttons of the overlay title bar
+#[cfg_attr(not(windows), allow(dead_code))]
+pub fn titlebar_script(app: &AppHandle) -> String {
+    let labels = serde_json::json!({
+        "minimize": i18n::t(app, "titlebar.minimize"),
+        "maximize": i18n::t(app, "titlebar.maximize"),
+        "close": i18n::t(app, "titlebar.close"),
+    });
+    format!(
+        r#"(() => {{
+  const labels = {labels};
+  const name = () => {{
+    for (const [action, label] of Object.entries(labels)) {{
+      const button = document.getElementById("decorum-tb-" + action);
+      if (button && button.getAttribute("aria-label") !== label) {{
+        button.setAttribute("aria-label", label);
+        button.setAttribute("title", label);
+      }}
+    }}
+  }};
+  new MutationObserver(name).observe(document, {{ childList: true, subtree: true }});
+  name();
+}})();"#,
+        labels = labels
+    )
+}
+
+/// Have the screen reader read `text`, for events of the frontend that
+/// happen away from where the user is
+#[tauri::command]
+pub fn set_accessibility_announcement(
+    app: AppHandle,
+    text: String,
+    priority: Option<Priority>,
+) -> Result<(), AppError> {
+    let text = text.trim();
+    if text.is_empty() {
+        return Err(AppError::invalid_input("The announcement is empty"));
+    }
+    if text.chars().count() > MAX_ANNOUNCEMENT_CHARS {
+        return Err(AppError::invalid_input(format!(
+            "Announcements are limited to {} characters",
+            MAX_ANNOUNCEMENT_CHARS
+        )));
+    }
+    announce(&app, text, priority.unwrap_or_default());
+    Ok(())
+}
@@ -0,0 +1,270 @@
+//! Native actions for the command palette.
+//!
+//! Capabilities implemented in Rust are listed with `list_actions` so the
+//! frontend palette can show them next to its own commands, and run by id
+//! with `invoke_action`. Titles come from the `action.<id>` messages of the
+//! native translations. Whether an action is enabled is evaluated when the
+//! list is requested.
+
+use serde::Serialize;
+use serde_json::json;
+use tauri::{AppHandle, Manager, Webview};
+use tauri_plugin_opener::OpenerExt;
+
+use crate::{
+    LogState, audit, cli_sync, error::AppError, i18n, macros, pip, presentation, project_windows,
+    scratchpad, stt, user_scope, window_customizer,
+};
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ActionInfo {
+    pub id: &'static str,
+    /// In the app language
+    pub title: String,
+    pub category: &'static str,
+    /// Extra words the palette should match on
+    pub keywords: &'static [&'static str],
+    pub enabled: bool,
+}
+
+struct Action {
+    id: &'static str,
+    category: &'static str,
+    keywords: &'static [&'static str],
+    enabled: fn(&AppHandle) -> bool,
+}
+
+const ACTIONS: &[Action] = &[
+    Action {
+        id: "server.restart",
+        category: "Server",
+        keywords: &["reload", "sidecar", "relaunch"],
+        enabled: |_| true,
+    },
+    Action {
+        id: "logs.open",
+        category: "Server",
+        keywords: &["debug", "output"],
+        enabled: |_| true,
+    },
+    Action {
+        id: "logs.copy",
+        category: "Server",
+        keywords: &["debug", "clipboard"],
+        enabled: |_| true,
+    },
+    Action {
+        id: "stt.toggleRecording",
+        category: "Speech",
+        keywords: &["dictate", "microphone", "voice", "transcribe"],
+        enabled: |app| {
+            stt_status(app)
+                .is_some_and(|status| matches!(status.model_status, stt::ModelStatus::Ready))
+        },
+    },
+    Action {
+        id: "stt.unloadModels",
+        category: "Speech",
+        keywords: &["memory", "free", "release"],
+        enabled: stt_models_loaded,
+    },
+    Action {
+        id: "scratchpad.dictate",
+        category: "Speech",
+        keywords: &["note", "jot", "capture", "memo", "voice"],
+        enabled: |app| {
+            stt_status(app)
+                .is_some_and(|status| matches!(status.model_status, stt::ModelStatus::Ready))
+        },
+    },
+    Action {
+        id: "window.toggleFullscreen",
+        category: "Window",
+        keywords: &["maximize"],
+        enabled: |_| true,
+    },
+    Action {
+        id: "window.togglePresentation",
+        category: "Window",
+        keywords: &["demo", "projector", "present"],
+        enabled: |_| true,
+    },
+    Action {
+        id: "window.snapLeft",
+        category: "Window",
+        keywords: &["tile", "half", "split", "arrange"],
+        enabled: |_| true,
+    },
+    Action {
+        id: "window.snapRight",
+        category: "Window",
+        keywords: &["tile", "half", "split", "arrange"],
+        enabled: |_| true,
+    },
+    Action {
+        id: "window.snapCenter",
+        category: "Window",
+        keywords: &["tile", "arrange", "middle"],
+        enabled: |_| true,
+    },
+    Action {
+        id: "window.togglePip",
+        category: "Window",
+        keywords: &["picture in picture", "floating", "always on top", "mini"],
+        enabled: |_| true,
+    },
+    Action {
+        id: "window.mergeAllWindows",
+        category: "Window",
+        keywords: &["tabs", "projects", "group"],
+        enabled: |_| cfg!(target_os = "macos"),
+    },
+    Action {
+        id: "cli.syncNow",
+        category: "CLI",
+        keywords: &["update", "install", "terminal"],
+        enabled: |_| true,
+    },
+];
+
+fn title(app: &AppHandle, action: &Action) -> String {
+    i18n::t(app, &format!("action.{}", action.id))
+}
+
+/// `None` too while the state is busy, such as loading models, rather than
+/// holding up the list
+fn stt_status(app: &AppHandle) -> Option<stt::SttStatus> {
+    let state = app.try_state::<stt::SharedSttState>()?;
+    let state = state.try_lock().ok()?;
+    Some(state.get_status())
+}
+
+fn stt_models_loaded(app: &AppHandle) -> bool {
+    app.try_state::<stt::SharedSttState>()
+        .and_then(|state| Some(state.try_lock().ok()?.models_loaded()))
+        .unwrap_or(false)
+}
+
+async fn unload_stt_models(app: &AppHandle) -> Result<(), AppError> {
+    let state = app
+        .try_state::<stt::SharedSttState>()
+        .ok_or("STT state not found")?;
+    let mut state = state.lock().await;
+    state.unload_models()
+}
+
+/// Write the in-memory server logs to a file and open it
+fn open_logs(app: &AppHandle) -> Result<(), String> {
+    let log_state = app.try_state::<LogState>().ok_or("Log state not found")?;
+    let logs = log_state
+        .0
+        .lock()
+        .map_err(|e| format!("Lock error: {}", e))?
+        .iter()
+        .cloned()
+        .collect::<Vec<_>>()
+        .join("");
+
+    let path = user_scope::temp_dir()?.join("aura-server.log");
+    std::fs::write(&path, logs).map_err(|e| format!("Failed to write logs: {}", e))?;
+    app.opener()
+        .open_path(path.to_string_lossy(), None::<&str>)
+        .map_err(|e| format!("Failed to open logs: {}", e))
+}
+
+async fn toggle_recording(app: &AppHandle) -> Result<(), AppError> {
+    let recording = match app.try_state::<stt::SharedSttState>() {
+        Some(state) => state.lock().await.get_status().is_recording,
+        None => false,
+    };
+    if recording {
+        crate::stop_and_enqueue(app, false, None).await?;
+        Ok(())
+    } else {
+        crate::stt_start_recording(app.clone()).await
+    }
+}
+
+fn toggle_presentation(app: &AppHandle) -> Result<(), AppError> {
+    let active = presentation::get_presentation_mode(app.clone())?.active;
+    presentation::set_presentation_mode(app.clone(), !active, None, None).map(|_| ())
+}
+
+fn info(app: &AppHandle, action: &Action) -> ActionInfo {
+    ActionInfo {
+        id: action.id,
+        title: title(app, action),
+        category: action.category,
+        keywords: action.keywords,
+        enabled: (action.enabled)(app),
+    }
+}
+
+/// The actions among `ids` that are enabled, for offering them elsewhere,
+/// like on a notification
+pub fn enabled_actions(app: &AppHandle, ids: &[&str]) -> Vec<ActionInfo> {
+    ACTIONS
+        .iter()
+        .filter(|action| ids.contains(&action.id))
+        .map(|action| info(app, action))
+        .filter(|info| info.enabled)
+        .collect()
+}
+
+/// Native actions with their current enabled state
+#[tauri::command]
+pub fn list_actions(app: AppHandle) -> Vec<ActionInfo> {
+    ACTIONS.iter().map(|action| info(&app, action)).collect()
+}
+
+/// Run a native action by id
+#[tauri::command]
+pub async fn invoke_action(app: AppHandle, webview: Webview, id: String) -> Result<(), AppError> {
+    let result = run(&app, &webview, &id).await;
+    audit::record(&webview, "invoke_action", json!({ "id": id }), &result);
+    if result.is_ok() {
+        macros::record(&app, macros::MacroStep::Action { id });
+    }
+    result
+}
+
+pub async fn run(app: &AppHandle, webview: &Webview, id: &str) -> Result<(), AppError> {
+    let action = ACTIONS
+        .iter()
+        .find(|action| action.id == id)
+        .ok_or_else(|| AppError::not_found(format!("Unknown action: {}", id)))?;
+    if !(action.enabled)(app) {
+        return Err(AppError::unavailable(i18n::t_with(
+            app,
+            "error.actionUnavailable",
+            &[("title", &title(app, action))],
+        )));
+    }
+
+    match action.id {
+        // The server is started once per launch, so restarting it restarts
+        // the app. That doesn't return, so it's logged up front.
+        "server.restart" => {
+            audit::record(webview, "invoke_action", json!({ "id": id }), &Ok(()));
+            crate::kill_sidecar(app.clone());
+            app.restart()
+        }
+        "logs.open" => Ok(open_logs(app)?),
+        "logs.copy" => crate::copy_logs_to_clipboard(app.clone()).await,
+        "stt.toggleRecording" => toggle_recording(app).await,
+        "stt.unloadModels" => unload_stt_models(app).await,
+        "scratchpad.dictate" => scratchpad::dictate(app).await,
+        "window.toggleFullscreen" => presentation::toggle_fullscreen(app.clone(), None).map(|_| ()),
+        "window.togglePresentation" => toggle_presentation(app),
+        "window.snapLeft" => window_customizer::snap(app, webview.window().label(), "left-half"),
+        "window.snapRight" => window_customizer::snap(app, webview.window().label(), "right-half"),
+        "window.snapCenter" => window_customizer::snap(app, webview.window().label(), "center-60"),
+        "window.togglePip" => pip::toggle(app).await,
+        "window.mergeAllWindows" => project_windows::merge_project_windows(app.clone()),
+        "cli.syncNow" => cli_sync::cli_sync_now(app.clone(), webview.clone())
+            .await
+            .map(|_| ()),
+        _ => Err(AppError::not_found(format!("Action {} has no handler", id))),
+    }
+}
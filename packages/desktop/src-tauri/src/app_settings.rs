@@ -0,0 +1,316 @@
+//! The settings as one typed struct.
+//!
+//! `Settings` has the preferences modules read from the settings store,
+//! typed as those modules use them. `load` reads it field by field, so a
+//! stored value of the wrong type falls back to its default without taking
+//! the other settings with it.
+//!
+//! `patch_settings` changes it with a JSON merge patch (RFC 7396): objects
+//! merge key by key and `null` resets a setting to its default. Every field
+//! the patch touches has to deserialize and pass its module's checks, or
+//! nothing is saved and the error lists each rejected field. Settings with
+//! their own commands for checks or effects beyond the store, such as the
+//! proxy or the update source, aren't part of it.
+
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+use std::collections::HashMap;
+use tauri::{AppHandle, Emitter, Webview};
+
+use crate::{
+    SETTINGS_STORE, audit,
+    error::{AppError, FieldError},
+    i18n,
+    meeting_calendar::CalendarSettings,
+    memory_watchdog::WatchdogSettings,
+    notifications::Threshold,
+    policy,
+    retention::RetentionSettings,
+    session_index::IndexSettings,
+    settings, settings_service,
+    stt::{self, RecordingLimits},
+    stt_dev::BackendChoice,
+    stt_profiles::AppProfile,
+    stt_remote::{self, RemoteSettings},
+    transcript_filters::TranscriptFilters,
+    typography::{self, Typography},
+};
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct Settings {
+    pub default_server_url: Option<String>,
+    /// A BCP-47 tag; `None` follows the system
+    pub app_language: Option<String>,
+    pub audit_log_enabled: bool,
+    pub telemetry_disabled: bool,
+    /// Hosts on private networks link previews may be fetched from
+    pub link_preview_allowed_hosts: Vec<String>,
+    /// By notification category
+    pub notification_thresholds: HashMap<String, Threshold>,
+    pub memory_watchdog: WatchdogSettings,
+    pub meeting_calendar: CalendarSettings,
+    pub session_index: IndexSettings,
+    pub retention: RetentionSettings,
+    /// By window label or kind
+    pub window_typography: HashMap<String, Typography>,
+    pub stt_recording_limits: RecordingLimits,
+    /// Recordings are otherwise brought to a steady level
+    pub stt_auto_gain_disabled: bool,
+    /// Project names, APIs and jargon transcription is biased towards
+    pub stt_vocabulary: Vec<String>,
+    pub stt_transcript_filters: TranscriptFilters,
+    pub stt_backend: BackendChoice,
+    /// The user's speech server, used once they agree to send it recordings
+    pub stt_remote: RemoteSettings,
+    /// How dictation is formatted for each app it goes into
+    pub stt_app_profiles: HashMap<String, AppProfile>,
+}
+
+/// Names of the fields, as stored
+fn fields() -> Vec<String> {
+    match serde_json::to_value(Settings::default()) {
+        Ok(Value::Object(fields)) => fields.keys().cloned().collect(),
+        _ => Vec::new(),
+    }
+}
+
+/// Settings with only field `field` set to `value`, or why `value` doesn't
+/// fit the field
+fn parse_field(field: &str, value: &Value) -> Result<Settings, String> {
+    let mut single = Map::new();
+    single.insert(field.to_string(), value.clone());
+    serde_json::from_value(Value::Object(single)).map_err(|e| e.to_string())
+}
+
+fn from_entries(entries: &Map<String, Value>) -> Settings {
+    let valid: Map<String, Value> = fields()
+        .into_iter()
+        .filter_map(|field| {
+            let value = entries.get(&field)?;
+            parse_field(&field, value).ok()?;
+            Some((field, value.clone()))
+        })
+        .collect();
+    serde_json::from_value(Value::Object(valid)).unwrap_or_default()
+}
+
+pub fn load(app: &AppHandle) -> Settings {
+    settings::global_settings(app)
+        .map(|entries| from_entries(&entries))
+        .unwrap_or_default()
+}
+
+/// Whether an administrator's policy sets field `field`
+fn locked(app: &AppHandle, field: &str) -> bool {
+    let policy = policy::current(app);
+    match field {
+        "defaultServerUrl" => policy.server_url.is_some(),
+        "auditLogEnabled" => policy.audit_log,
+        "sttRemote" => policy.disable_remote_transcription,
+        _ => false,
+    }
+}
+
+/// Checks of field `field` of `settings` beyond its type
+fn check_field(field: &str, settings: &Settings) -> Result<(), String> {
+    match field {
+        "defaultServerUrl" => match &settings.default_server_url {
+            Some(url) => tauri::Url::parse(url)
+                .map(|_| ())
+                .map_err(|e| format!("Invalid URL {}: {}", url, e)),
+            None => Ok(()),
+        },
+        "appLanguage" if settings.app_language.as_deref() == Some("") => {
+            Err("The language is empty".to_string())
+        }
+        "linkPreviewAllowedHosts"
+            if settings
+                .link_preview_allowed_hosts
+                .iter()
+                .any(|host| host.trim().is_empty()) =>
+        {
+            Err("Hosts can't be empty".to_string())
+        }
+        "sttVocabulary"
+            if settings
+                .stt_vocabulary
+                .iter()
+                .any(|term| term.trim().is_empty()) =>
+        {
+            Err("Terms can't be empty".to_string())
+        }
+        "sttTranscriptFilters"
+            if settings
+                .stt_transcript_filters
+                .profanity
+                .iter()
+                .any(|word| word.trim().is_empty()) =>
+        {
+            Err("Words can't be empty".to_string())
+        }
+        "windowTypography" => settings
+            .window_typography
+            .values()
+            .try_for_each(typography::validate),
+        "sttRecordingLimits"
+            if settings.stt_recording_limits.max_duration_secs == 0
+                || settings.stt_recording_limits.max_buffer_mb == 0 =>
+        {
+            Err("Recording limits must be greater than zero".to_string())
+        }
+        "sttBackend" if matches!(&settings.stt_backend, BackendChoice::Replay { dir } if dir.as_os_str().is_empty()) => {
+            Err("The replay directory is empty".to_string())
+        }
+        "sttRemote" => match &settings.stt_remote.endpoint {
+            Some(endpoint) => stt_remote::parse_endpoint(endpoint).map(|_| ()),
+            None => Ok(()),
+        },
+        _ => Ok(()),
+    }
+}
+
+/// Apply `patch` to the stored `entries`, leaving them as they were when a
+/// field is rejected
+fn apply_patch(
+    app: &AppHandle,
+    entries: &mut Map<String, Value>,
+    patch: Map<String, Value>,
+) -> Result<(), AppError> {
+    let known = fields();
+    let mut patched = entries.clone();
+    let mut errors = Vec::new();
+    for (field, change) in patch {
+        if !known.contains(&field) {
+            errors.push(FieldError {
+                field,
+                message: "Unknown setting".to_string(),
+            });
+            continue;
+        }
+        if locked(app, &field) {
+            errors.push(FieldError {
+                field,
+                message: policy::locked_error(app).message,
+            });
+            continue;
+        }
+
+        let mut merged = Map::new();
+        if let Some(value) = patched.get(&field) {
+            merged.insert(field.clone(), value.clone());
+        }
+        settings::merge(&mut merged, Map::from_iter([(field.clone(), change)]));
+        match merged.remove(&field) {
+            None => {
+                patched.remove(&field);
+            }
+            Some(value) => {
+                match parse_field(&field, &value).and_then(|parsed| check_field(&field, &parsed)) {
+                    Ok(()) => {
+                        patched.insert(field, value);
+                    }
+                    Err(message) => errors.push(FieldError { field, message }),
+                }
+            }
+        }
+    }
+
+    if !errors.is_empty() {
+        let names: Vec<&str> = errors.iter().map(|error| error.field.as_str()).collect();
+        return Err(
+            AppError::invalid_input(format!("Invalid settings: {}", names.join(", ")))
+                .with_fields(errors),
+        );
+    }
+    *entries = patched;
+    Ok(())
+}
+
+/// What changing `fields` does beyond the store
+async fn apply_effects(app: &AppHandle, fields: &[String]) {
+    for field in fields {
+        match field.as_str() {
+            "appLanguage" => {
+                if let Err(e) = i18n::reload(app) {
+                    eprintln!("Failed to change the app language: {}", e);
+                }
+            }
+            "windowTypography" => typography::apply_to_open_windows(app),
+            "sttRecordingLimits" => crate::load_recording_limits(app).await,
+            "sttAutoGainDisabled" => stt::apply_auto_gain(app).await,
+            "sttVocabulary" => stt::apply_vocabulary(app).await,
+            "sttBackend" | "sttRemote" => stt::apply_backend(app).await,
+            _ => {}
+        }
+    }
+    let _ = app.emit("settings:changed", fields);
+}
+
+pub(crate) async fn apply(app: &AppHandle, patch: Value) -> Result<Settings, AppError> {
+    let Value::Object(patch) = patch else {
+        return Err(AppError::invalid_input(
+            "A settings patch must be a JSON object",
+        ));
+    };
+    let fields: Vec<String> = patch.keys().cloned().collect();
+    let app_for_patch = app.clone();
+    settings_service::update(app, SETTINGS_STORE, move |entries| {
+        apply_patch(&app_for_patch, entries, patch)
+    })
+    .await?;
+
+    apply_effects(app, &fields).await;
+    Ok(load(app))
+}
+
+#[tauri::command]
+pub fn get_settings(app: AppHandle) -> Settings {
+    load(&app)
+}
+
+/// Change settings with a JSON merge patch, returning them as saved
+#[tauri::command]
+pub async fn patch_settings(
+    app: AppHandle,
+    webview: Webview,
+    patch: Value,
+) -> Result<Settings, AppError> {
+    let args = patch.clone();
+    let was_enabled = audit::is_enabled(&app);
+    let result = apply(&app, patch).await;
+    audit::record_change(&webview, was_enabled, "patch_settings", args, &result);
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_load_keeps_valid_fields() {
+        let entries = match json!({
+            "auditLogEnabled": "yes",
+            "linkPreviewAllowedHosts": ["nas.local"],
+            "retention": { "logs": { "maxAgeDays": 7, "maxMb": null } },
+            "theme": "dark",
+        }) {
+            Value::Object(entries) => entries,
+            _ => unreachable!(),
+        };
+        let settings = from_entries(&entries);
+        assert!(!settings.audit_log_enabled);
+        assert_eq!(settings.link_preview_allowed_hosts, ["nas.local"]);
+        assert_eq!(settings.retention.logs.max_age_days, Some(7));
+        assert_eq!(settings.retention.caches.max_mb, Some(1024));
+
+        assert!(parse_field("auditLogEnabled", &json!("yes")).is_err());
+        let limits = parse_field(
+            "sttRecordingLimits",
+            &json!({ "maxDurationSecs": 0, "maxBufferMb": 64 }),
+        )
+        .unwrap();
+        assert!(check_field("sttRecordingLimits", &limits).is_err());
+    }
+}
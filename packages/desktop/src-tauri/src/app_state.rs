@@ -0,0 +1,267 @@
+//! One versioned copy of the state the frontend shows.
+//!
+//! The frontend used to follow the server through `window.__OPENCODE__`
+//! evals, speech-to-text by polling `stt_get_status`, transfers through
+//! `transfer:updated` and settings through `settings:changed`, and had no
+//! way to tell whether it missed one. `AppStateStore` keeps them in one
+//! JSON document with a revision number:
+//!
+//! - `server`: `{ state: "starting" | "ready" | "failed", url?, error? }`
+//! - `stt`: the `SttStatus`
+//! - `transfers`: each `Transfer` by id
+//! - `tasks`: each `Task` by id
+//! - `settings`: the typed `Settings`
+//! - `recoveredRecordings`: each `RecoveredRecording` by id
+//!
+//! Each change bumps the revision and goes out as a `state:delta` event with
+//! the path that changed and its new value, `null` when it was removed. A
+//! frontend that finds a revision missing calls `get_app_state` with the
+//! last one it applied and gets the changes since, or the whole document
+//! once those are no longer kept. The older events are still sent.
+
+use serde::Serialize;
+use serde_json::{Map, Value};
+use std::{collections::VecDeque, sync::Mutex};
+use tauri::{AppHandle, Emitter, Listener, Manager};
+
+use crate::{SETTINGS_STORE, app_settings, error::AppError, settings_service, stt};
+
+const DELTA_EVENT: &str = "state:delta";
+/// Changes kept for `get_app_state` to catch up from
+const MAX_HISTORY: usize = 500;
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Change {
+    pub rev: u64,
+    pub path: Vec<String>,
+    /// `null` when the value was removed
+    pub value: Value,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(tag = "kind", rename_all = "camelCase")]
+pub enum StateSync {
+    /// The whole document as of revision `rev`
+    Snapshot { rev: u64, state: Value },
+    /// What changed up to revision `rev`, in order
+    Delta { rev: u64, changes: Vec<Change> },
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(tag = "state", rename_all = "camelCase")]
+pub enum ServerStatus {
+    Starting,
+    Ready { url: String },
+    Failed { error: String },
+}
+
+#[derive(Default)]
+struct Document {
+    rev: u64,
+    state: Map<String, Value>,
+    history: VecDeque<Change>,
+}
+
+impl Document {
+    /// Set the value at `path`, or remove it with `null`; `None` when that
+    /// changes nothing
+    fn set(&mut self, path: &[&str], value: Value) -> Option<Change> {
+        let (last, parents) = path.split_last()?;
+        let mut object = &mut self.state;
+        for key in parents {
+            let entry = object
+                .entry(key.to_string())
+                .or_insert_with(|| Value::Object(Map::new()));
+            if !entry.is_object() {
+                *entry = Value::Object(Map::new());
+            }
+            object = entry.as_object_mut()?;
+        }
+        if object.get(*last).unwrap_or(&Value::Null) == &value {
+            return None;
+        }
+        if value.is_null() {
+            object.remove(*last);
+        } else {
+            object.insert(last.to_string(), value.clone());
+        }
+
+        self.rev += 1;
+        let change = Change {
+            rev: self.rev,
+            path: path.iter().map(|key| key.to_string()).collect(),
+            value,
+        };
+        self.history.push_back(change.clone());
+        if self.history.len() > MAX_HISTORY {
+            self.history.pop_front();
+        }
+        Some(change)
+    }
+
+    /// What a frontend at revision `rev` needs to be current
+    fn since(&self, rev: Option<u64>) -> StateSync {
+        let covered = |rev: u64| match self.history.front() {
+            Some(oldest) => oldest.rev <= rev + 1,
+            None => rev == self.rev,
+        };
+        match rev {
+            Some(rev) if rev <= self.rev && covered(rev) => StateSync::Delta {
+                rev: self.rev,
+                changes: self
+                    .history
+                    .iter()
+                    .filter(|change| change.rev > rev)
+                    .cloned()
+                    .collect(),
+            },
+            _ => StateSync::Snapshot {
+                rev: self.rev,
+                state: Value::Object(self.state.clone()),
+            },
+        }
+    }
+}
+
+#[derive(Default)]
+pub struct AppStateStore(Mutex<Document>);
+
+/// Set the value at `path` of the document, or remove it with `null`, and
+/// tell the frontend if that changed it
+pub fn publish(app: &AppHandle, path: &[&str], value: impl Serialize) {
+    let value = match serde_json::to_value(value) {
+        Ok(value) => value,
+        Err(e) => {
+            eprintln!("Failed to serialize app state {}: {}", path.join("."), e);
+            return;
+        }
+    };
+    let Some(store) = app.try_state::<AppStateStore>() else {
+        return;
+    };
+    let Ok(mut document) = store.0.lock() else {
+        return;
+    };
+    if let Some(change) = document.set(path, value) {
+        // Sent under the lock, so events go out in revision order
+        let _ = app.emit(
+            DELTA_EVENT,
+            StateSync::Delta {
+                rev: change.rev,
+                changes: vec![change],
+            },
+        );
+    }
+}
+
+pub fn publish_server(app: &AppHandle, status: ServerStatus) {
+    publish(app, &["server"], status);
+}
+
+/// Publish the speech-to-text status; call without holding its lock
+pub async fn publish_stt(app: &AppHandle) {
+    let Some(state) = app.try_state::<stt::SharedSttState>() else {
+        return;
+    };
+    let status = state.lock().await.get_status();
+    publish(app, &["stt"], status);
+}
+
+pub fn publish_settings(app: &AppHandle) {
+    publish(app, &["settings"], app_settings::load(app));
+}
+
+/// Start the document; run once the states it reads are managed
+pub fn init(app: &AppHandle) {
+    app.manage(AppStateStore::default());
+    publish_server(app, ServerStatus::Starting);
+    publish_settings(app);
+    let app_for_stt = app.clone();
+    tauri::async_runtime::spawn(async move { publish_stt(&app_for_stt).await });
+
+    // The plugin sends these while it holds the store, so the settings are
+    // read from a task
+    let app_for_settings = app.clone();
+    app.listen_any("store://change", move |event| {
+        if settings_service::changed_store(event.payload()).as_deref() == Some(SETTINGS_STORE) {
+            let app = app_for_settings.clone();
+            tauri::async_runtime::spawn(async move { publish_settings(&app) });
+        }
+    });
+}
+
+/// The changes since revision `rev`, or the whole state when those are no
+/// longer kept or `rev` is `None`
+#[tauri::command]
+pub fn get_app_state(app: AppHandle, rev: Option<u64>) -> Result<StateSync, AppError> {
+    let store = app
+        .try_state::<AppStateStore>()
+        .ok_or("App state not found")?;
+    let document = store.0.lock().map_err(|e| format!("Lock error: {}", e))?;
+    Ok(document.since(rev))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_since_returns_missed_changes() {
+        let mut document = Document::default();
+        assert!(
+            document
+                .set(&["server"], json!({ "state": "starting" }))
+                .is_some()
+        );
+        assert!(
+            document
+                .set(&["transfers", "a"], json!({ "bytesDone": 1 }))
+                .is_some()
+        );
+        assert!(
+            document
+                .set(&["transfers", "a"], json!({ "bytesDone": 1 }))
+                .is_none()
+        );
+        assert!(document.set(&["transfers", "b"], Value::Null).is_none());
+        assert!(document.set(&["transfers", "a"], Value::Null).is_some());
+
+        match document.since(Some(1)) {
+            StateSync::Delta { rev, changes } => {
+                assert_eq!(rev, 3);
+                assert_eq!(changes.iter().map(|c| c.rev).collect::<Vec<_>>(), [2, 3]);
+                assert_eq!(changes[1].path, ["transfers", "a"]);
+            }
+            sync => panic!("Expected a delta, got {:?}", sync),
+        }
+        assert_eq!(
+            document.since(Some(3)),
+            StateSync::Delta {
+                rev: 3,
+                changes: Vec::new()
+            }
+        );
+        assert_eq!(
+            document.since(None),
+            StateSync::Snapshot {
+                rev: 3,
+                state: json!({ "server": { "state": "starting" }, "transfers": {} }),
+            }
+        );
+
+        for i in 0..MAX_HISTORY as u64 {
+            document.set(&["stt"], json!(i));
+        }
+        assert!(matches!(
+            document.since(Some(2)),
+            StateSync::Snapshot { .. }
+        ));
+        assert!(matches!(document.since(Some(3)), StateSync::Delta { .. }));
+        // From before a restart
+        assert!(matches!(
+            document.since(Some(9999)),
+            StateSync::Snapshot { .. }
+        ));
+    }
+}
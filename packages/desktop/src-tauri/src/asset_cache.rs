@@ -0,0 +1,323 @@
+//! Cached favicons and avatars, served over the `asset` protocol.
+//!
+//! Server lists and commit authors show an icon per row, and fetching them
+//! from the webview on every render is slow and leaks what is being looked
+//! at. Images are fetched once, here, and kept on disk:
+//!
+//! - `asset://localhost/favicon?origin=<url>`: `/favicon.ico` of the origin
+//!   of a configured server or public site
+//! - `asset://localhost/avatar?email=<address>`: the Gravatar of a git author
+//!
+//! (`http://asset.localhost/...` on Windows.) Images are at most
+//! `MAX_ASSET_BYTES`, and the cache is trimmed to `MAX_CACHE_BYTES`, oldest
+//! first. Images that don't exist are remembered for a day, so rows without
+//! an icon don't ask again on each render. `purge_asset_cache` empties it.
+
+use serde::{Deserialize, Serialize};
+use sha2::Digest;
+use std::{
+    path::{Path, PathBuf},
+    time::{SystemTime, UNIX_EPOCH},
+};
+use tauri::{
+    AppHandle, Manager, UriSchemeContext, UriSchemeResponder, Wry,
+    http::{Request, Response, StatusCode, header},
+    path::BaseDirectory,
+};
+
+use crate::{
+    error::{AppError, ErrorKind},
+    link_preview,
+};
+
+pub const SCHEME: &str = "asset";
+const CACHE_DIR: &str = "asset-cache";
+const MAX_ASSET_BYTES: usize = 256 * 1024;
+const MAX_CACHE_BYTES: u64 = 32 * 1024 * 1024;
+const TTL_SECS: u64 = 7 * 24 * 60 * 60;
+const MISSING_TTL_SECS: u64 = 24 * 60 * 60;
+const AVATAR_SIZE: u32 = 128;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Asset {
+    Favicon(reqwest::Url),
+    Avatar(String),
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Entry {
+    fetched_at: u64,
+    /// None when there is no such image
+    content_type: Option<String>,
+}
+
+fn now_secs() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0)
+}
+
+impl Asset {
+    fn from_uri(uri: &str) -> Result<Self, String> {
+        let url = reqwest::Url::parse(uri).map_err(|e| format!("Invalid asset URL: {}", e))?;
+        let param = |name: &str| {
+            url.query_pairs()
+                .find(|(key, _)| key == name)
+                .map(|(_, value)| value.into_owned())
+                .ok_or_else(|| format!("Missing {} in asset URL", name))
+        };
+        match url.path() {
+            "/favicon" => {
+                let origin = param("origin")?;
+                let origin = reqwest::Url::parse(&origin)
+                    .map_err(|e| format!("Invalid origin {}: {}", origin, e))?;
+                let favicon = origin
+                    .join("/favicon.ico")
+                    .map_err(|e| format!("Invalid origin {}: {}", origin, e))?;
+                Ok(Asset::Favicon(favicon))
+            }
+            "/avatar" => Ok(Asset::Avatar(param("email")?.trim().to_lowercase())),
+            path => Err(format!("Unknown asset {}", path)),
+        }
+    }
+
+    fn source(&self) -> Result<reqwest::Url, String> {
+        match self {
+            Asset::Favicon(url) => Ok(url.clone()),
+            Asset::Avatar(email) => {
+                let hash = sha2::Sha256::digest(email.as_bytes());
+                reqwest::Url::parse(&format!(
+                    "https://www.gravatar.com/avatar/{:x}?s={}&d=404",
+                    hash, AVATAR_SIZE
+                ))
+                .map_err(|e| format!("Invalid avatar URL: {}", e))
+            }
+        }
+    }
+
+    fn cache_name(&self) -> String {
+        let key = match self {
+            Asset::Favicon(url) => format!("favicon:{}", url),
+            Asset::Avatar(email) => format!("avatar:{}", email),
+        };
+        blake3::hash(key.as_bytes()).to_hex().to_string()
+    }
+}
+
+fn cache_dir(app: &AppHandle) -> Result<PathBuf, String> {
+    app.path()
+        .resolve(CACHE_DIR, BaseDirectory::AppCache)
+        .map_err(|e| format!("Failed to resolve asset cache path: {}", e))
+}
+
+fn read_cached(dir: &Path, name: &str) -> Option<(Entry, Vec<u8>)> {
+    let entry: Entry =
+        serde_json::from_str(&std::fs::read_to_string(dir.join(format!("{}.json", name))).ok()?)
+            .ok()?;
+    let ttl = match entry.content_type {
+        Some(_) => TTL_SECS,
+        None => MISSING_TTL_SECS,
+    };
+    if now_secs().saturating_sub(entry.fetched_at) >= ttl {
+        return None;
+    }
+    let body = match entry.content_type {
+        Some(_) => std::fs::read(dir.join(format!("{}.bin", name))).ok()?,
+        None => Vec::new(),
+    };
+    Some((entry, body))
+}
+
+fn write_cached(dir: &Path, name: &str, entry: &Entry, body: &[u8]) -> Result<(), String> {
+    std::fs::create_dir_all(dir)
+        .map_err(|e| format!("Failed to create {}: {}", dir.display(), e))?;
+    if entry.content_type.is_some() {
+        std::fs::write(dir.join(format!("{}.bin", name)), body)
+            .map_err(|e| format!("Failed to write cached asset: {}", e))?;
+    }
+    let contents = serde_json::to_string(entry)
+        .map_err(|e| format!("Failed to serialize cached asset: {}", e))?;
+    std::fs::write(dir.join(format!("{}.json", name)), contents)
+        .map_err(|e| format!("Failed to write cached asset: {}", e))
+}
+
+/// Remove the least recently written images past `MAX_CACHE_BYTES`
+fn trim(dir: &Path) {
+    let Ok(entries) = std::fs::read_dir(dir) else {
+        return;
+    };
+    let mut images: Vec<(SystemTime, u64, PathBuf)> = entries
+        .flatten()
+        .filter(|entry| entry.path().extension().is_some_and(|ext| ext == "bin"))
+        .filter_map(|entry| {
+            let metadata = entry.metadata().ok()?;
+            Some((metadata.modified().ok()?, metadata.len(), entry.path()))
+        })
+        .collect();
+    let mut total: u64 = images.iter().map(|(_, size, _)| size).sum();
+    images.sort();
+    for (_, size, path) in images {
+        if total <= MAX_CACHE_BYTES {
+            break;
+        }
+        let _ = std::fs::remove_file(path.with_extension("json"));
+        if std::fs::remove_file(&path).is_ok() {
+            total = total.saturating_sub(size);
+        }
+    }
+}
+
+async fn fetch(app: &AppHandle, asset: &Asset) -> Result<(Entry, Vec<u8>), AppError> {
+    let source = asset.source()?;
+    let mut allowed = link_preview::allowed_hosts(app);
+    // Configured servers are often on the local network
+    if let Asset::Favicon(url) = asset
+        && crate::is_allowed_server(app, url)
+        && let Some(host) = url.host_str()
+    {
+        allowed.push(host.trim_matches(['[', ']']).to_lowercase());
+    }
+
+    let fetched = match link_preview::fetch(source, &allowed, "image/*", MAX_ASSET_BYTES).await {
+        Ok(fetched) if fetched.content_type.starts_with("image/") && !fetched.truncated => {
+            Some(fetched)
+        }
+        Ok(_) => None,
+        Err(e) if e.kind == ErrorKind::NotFound => None,
+        Err(e) => return Err(e),
+    };
+    Ok(match fetched {
+        Some(fetched) => (
+            Entry {
+                fetched_at: now_secs(),
+                content_type: Some(fetched.content_type),
+            },
+            fetched.body,
+        ),
+        None => (
+            Entry {
+                fetched_at: now_secs(),
+                content_type: None,
+            },
+            Vec::new(),
+        ),
+    })
+}
+
+async fn serve(app: &AppHandle, uri: &str) -> Response<Vec<u8>> {
+    let respond = |status: StatusCode, content_type: Option<&str>, body: Vec<u8>| {
+        let mut response = Response::builder()
+            .status(status)
+            .header(header::CACHE_CONTROL, "max-age=3600");
+        if let Some(content_type) = content_type {
+            response = response.header(header::CONTENT_TYPE, content_type);
+        }
+        response.body(body).unwrap_or_default()
+    };
+
+    let asset = match Asset::from_uri(uri) {
+        Ok(asset) => asset,
+        Err(e) => return respond(StatusCode::BAD_REQUEST, None, e.into_bytes()),
+    };
+    let dir = match cache_dir(app) {
+        Ok(dir) => dir,
+        Err(e) => return respond(StatusCode::INTERNAL_SERVER_ERROR, None, e.into_bytes()),
+    };
+    let name = asset.cache_name();
+
+    let (entry, body) = match read_cached(&dir, &name) {
+        Some(cached) => cached,
+        None => match fetch(app, &asset).await {
+            Ok((entry, body)) => {
+                if let Err(e) = write_cached(&dir, &name, &entry, &body) {
+                    eprintln!("{}", e);
+                }
+                trim(&dir);
+                (entry, body)
+            }
+            Err(e) => {
+                let status = match e.kind {
+                    ErrorKind::PermissionDenied => StatusCode::FORBIDDEN,
+                    ErrorKind::InvalidInput => StatusCode::BAD_REQUEST,
+                    _ => StatusCode::BAD_GATEWAY,
+                };
+                return respond(status, None, e.message.into_bytes());
+            }
+        },
+    };
+    match entry.content_type {
+        Some(content_type) => respond(StatusCode::OK, Some(&content_type), body),
+        None => respond(StatusCode::NOT_FOUND, None, Vec::new()),
+    }
+}
+
+/// Handler of the `asset` protocol
+pub fn handle(
+    ctx: UriSchemeContext<'_, Wry>,
+    request: Request<Vec<u8>>,
+    responder: UriSchemeResponder,
+) {
+    let app = ctx.app_handle().clone();
+    let uri = request.uri().to_string();
+    tauri::async_runtime::spawn(async move {
+        responder.respond(serve(&app, &uri).await);
+    });
+}
+
+/// Remove all cached favicons and avatars. Returns the bytes freed.
+#[tauri::command]
+pub fn purge_asset_cache(app: AppHandle) -> Result<u64, AppError> {
+    let dir = cache_dir(&app)?;
+    let freed = std::fs::read_dir(&dir)
+        .map(|entries| {
+            entries
+                .flatten()
+                .filter_map(|entry| entry.metadata().ok())
+                .map(|metadata| metadata.len())
+                .sum()
+        })
+        .unwrap_or(0);
+    match std::fs::remove_dir_all(&dir) {
+        Ok(()) => Ok(freed),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(0),
+        Err(e) => Err(AppError::io(format!(
+            "Failed to remove {}: {}",
+            dir.display(),
+            e
+        ))),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_asset_urls() {
+        let favicon = Asset::from_uri(
+            "asset://localhost/favicon?origin=https%3A%2F%2Fexample.com%3A8443%2Fsome%2Fpage",
+        )
+        .unwrap();
+        assert_eq!(
+            favicon.source().unwrap().as_str(),
+            "https://example.com:8443/favicon.ico"
+        );
+
+        // Windows form; addresses are case-insensitive
+        let avatar =
+            Asset::from_uri("http://asset.localhost/avatar?email=Jane%40Example.com").unwrap();
+        assert_eq!(avatar, Asset::Avatar("jane@example.com".to_string()));
+        assert!(
+            avatar
+                .source()
+                .unwrap()
+                .as_str()
+                .starts_with("https://www.gravatar.com/avatar/")
+        );
+
+        assert!(Asset::from_uri("asset://localhost/other?x=1").is_err());
+        assert!(Asset::from_uri("asset://localhost/avatar").is_err());
+    }
+}
@@ -0,0 +1,230 @@
+//! Crash-safe saves of the settings stores.
+//!
+//! The store plugin saves by writing over the file in place, so a power cut
+//! in the middle of a save leaves it empty or cut short, and the plugin then
+//! starts over with no settings at all. `save` writes the store to a
+//! temporary file instead, flushes it to disk and renames it over the old
+//! one, so the file always holds either the old or the new settings. Once
+//! the new file reads back whole it becomes the last known good copy, kept
+//! next to it as a backup.
+//!
+//! The plugin's own saves are kept off: the settings stores are loaded
+//! without them, the frontend loads its stores the same way and saves them
+//! through `save_store`, and `AtomicStorePlugin`, registered before the
+//! store plugin, saves the stores at exit and closes them, so the plugin
+//! finds none left to save.
+//!
+//! `recover` runs at startup, before anything opens the stores: it puts the
+//! backup back over a store that no longer parses.
+
+use serde_json::Value;
+use std::{
+    collections::{BTreeSet, HashMap},
+    fs::File,
+    io::Write,
+    path::{Path, PathBuf},
+    sync::Mutex,
+};
+use tauri::{AppHandle, RunEvent, Wry, plugin::Plugin};
+use tauri_plugin_store::{StoreExt, resolve_store_path};
+
+use crate::{GLOBAL_STORAGE, SETTINGS_STORE, error::AppError, panic_guard};
+
+const TEMP_EXTENSION: &str = "tmp";
+const BACKUP_EXTENSION: &str = "bak";
+/// The stores checked at startup
+const RECOVERED_STORES: &[&str] = &[SETTINGS_STORE, GLOBAL_STORAGE];
+
+/// Two saves of a store at once would share its temporary file
+static SAVE_LOCK: Mutex<()> = Mutex::new(());
+/// Paths of the stores the frontend saved, to save again at exit
+static FRONTEND_STORES: Mutex<BTreeSet<PathBuf>> = Mutex::new(BTreeSet::new());
+
+fn with_extension(path: &Path, extension: &str) -> PathBuf {
+    let mut name = path.as_os_str().to_os_string();
+    name.push(".");
+    name.push(extension);
+    PathBuf::from(name)
+}
+
+/// Where the last known good copy of the store at `path` is kept
+fn backup_path(path: &Path) -> PathBuf {
+    with_extension(path, BACKUP_EXTENSION)
+}
+
+/// Whether `bytes` are a whole store, as the plugin reads one
+fn is_valid(bytes: &[u8]) -> bool {
+    serde_json::from_slice::<HashMap<String, Value>>(bytes).is_ok()
+}
+
+fn write_synced(path: &Path, bytes: &[u8]) -> std::io::Result<()> {
+    let mut file = File::create(path)?;
+    file.write_all(bytes)?;
+    file.sync_all()
+}
+
+/// Replace the file at `path` with `bytes`, so that it holds either its old
+/// or its new content whenever the write is cut short
+pub(crate) fn write_atomic(path: &Path, bytes: &[u8]) -> Result<(), String> {
+    let parent = path.parent().ok_or("Invalid store path")?;
+    std::fs::create_dir_all(parent)
+        .map_err(|e| format!("Failed to create store directory: {}", e))?;
+
+    let temp = with_extension(path, TEMP_EXTENSION);
+    if let Err(e) = write_synced(&temp, bytes).and_then(|()| std::fs::rename(&temp, path)) {
+        let _ = std::fs::remove_file(&temp);
+        return Err(format!("Failed to write {}: {}", path.display(), e));
+    }
+    // The rename is only on disk once its directory is
+    #[cfg(unix)]
+    if let Ok(dir) = File::open(parent) {
+        let _ = dir.sync_all();
+    }
+    Ok(())
+}
+
+/// Save the store `store` (a name or a path, as for `app.store`) to disk
+pub fn save(app: &AppHandle, store: impl AsRef<Path>) -> Result<(), String> {
+    let path = resolve_store_path(app, store.as_ref())
+        .map_err(|e| format!("Failed to resolve store path: {}", e))?;
+    let entries: HashMap<String, Value> = app
+        .store(&path)
+        .map_err(|e| format!("Failed to open {}: {}", path.display(), e))?
+        .entries()
+        .into_iter()
+        .collect();
+    let bytes = serde_json::to_vec_pretty(&entries)
+        .map_err(|e| format!("Failed to serialize {}: {}", path.display(), e))?;
+
+    let _guard = SAVE_LOCK.lock().map_err(|e| format!("Lock error: {}", e))?;
+    write_atomic(&path, &bytes)?;
+    // Only what reads back whole becomes the last known good copy
+    let written = std::fs::read(&path)
+        .map_err(|e| format!("Failed to read back {}: {}", path.display(), e))?;
+    if !is_valid(&written) {
+        return Err(format!("{} didn't read back whole", path.display()));
+    }
+    write_atomic(&backup_path(&path), &written)
+}
+
+/// Save the store the frontend loaded as `name`
+#[tauri::command]
+pub async fn save_store(app: AppHandle, name: String) -> Result<(), AppError> {
+    let path = resolve_store_path(&app, &name)
+        .map_err(|e| format!("Failed to resolve store path: {}", e))?;
+    // Saving a store that isn't loaded would write an empty one
+    if app.get_store(&path).is_none() {
+        return Err(AppError::not_found(format!("Store {} isn't loaded", name)));
+    }
+    FRONTEND_STORES
+        .lock()
+        .map_err(|e| format!("Lock error: {}", e))?
+        .insert(path.clone());
+    tauri::async_runtime::spawn_blocking(move || save(&app, &path))
+        .await
+        .map_err(panic_guard::join_error)??;
+    Ok(())
+}
+
+/// Save the stores and close them, so the store plugin doesn't save them in
+/// place after
+fn save_and_close(app: &AppHandle) {
+    let mut paths: BTreeSet<PathBuf> = RECOVERED_STORES
+        .iter()
+        .filter_map(|name| resolve_store_path(app, name).ok())
+        .collect();
+    if let Ok(frontend) = FRONTEND_STORES.lock() {
+        paths.extend(frontend.iter().cloned());
+    }
+    for path in paths {
+        let Some(store) = app.get_store(&path) else {
+            continue;
+        };
+        if let Err(e) = save(app, &path) {
+            eprintln!("Failed to save {} at exit: {}", path.display(), e);
+        }
+        store.close_resource();
+    }
+}
+
+/// Saves the stores at exit in place of the store plugin; register it before
+/// that one, whose exit handler runs after
+pub struct AtomicStorePlugin;
+
+impl Plugin<Wry> for AtomicStorePlugin {
+    fn name(&self) -> &'static str {
+        "atomic-store"
+    }
+
+    fn on_event(&mut self, app: &AppHandle, event: &RunEvent) {
+        if let RunEvent::Exit = event {
+            save_and_close(app);
+        }
+    }
+}
+
+/// Put the last known good copy back over the store at `path`
+fn restore(path: &Path) {
+    match std::fs::read(backup_path(path)) {
+        Ok(bytes) if is_valid(&bytes) => match write_atomic(path, &bytes) {
+            Ok(()) => eprintln!("Restored {} from its last known good copy", path.display()),
+            Err(e) => eprintln!("Failed to restore {}: {}", path.display(), e),
+        },
+        _ => eprintln!(
+            "{} is corrupt and has no good copy to restore",
+            path.display()
+        ),
+    }
+}
+
+/// Restore the settings stores a crash left unreadable, back up the ones
+/// that are fine, and load them with the plugin's own saves turned off, as
+/// those would write in place again. Run before anything opens them.
+pub fn recover(app: &AppHandle) {
+    for name in RECOVERED_STORES {
+        let Ok(path) = resolve_store_path(app, name) else {
+            continue;
+        };
+        let _ = std::fs::remove_file(with_extension(&path, TEMP_EXTENSION));
+        match std::fs::read(&path) {
+            Ok(bytes) if !is_valid(&bytes) => restore(&path),
+            // A save that didn't read back whole made no copy
+            Ok(bytes) => {
+                let backup = backup_path(&path);
+                if std::fs::read(&backup).ok().as_deref() != Some(bytes.as_slice())
+                    && let Err(e) = write_atomic(&backup, &bytes)
+                {
+                    eprintln!("Failed to back up {}: {}", path.display(), e);
+                }
+            }
+            Err(_) => {}
+        }
+
+        if let Err(e) = app.store_builder(name).disable_auto_save().build() {
+            eprintln!("Failed to load {}: {}", name, e);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_write_atomic_replaces_whole_file() {
+        let dir = std::env::temp_dir().join(format!("aura-atomic-store-{}", uuid::Uuid::new_v4()));
+        let path = dir.join("settings.dat");
+
+        write_atomic(&path, br#"{"a": 1}"#).unwrap();
+        write_atomic(&path, br#"{"a": 2}"#).unwrap();
+        assert_eq!(std::fs::read(&path).unwrap(), br#"{"a": 2}"#);
+        assert!(!with_extension(&path, TEMP_EXTENSION).exists());
+        assert_eq!(backup_path(&path), dir.join("settings.dat.bak"));
+
+        assert!(is_valid(br#"{"a": 2}"#));
+        assert!(!is_valid(br#"{"a": "#));
+        assert!(!is_valid(b""));
+
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+}
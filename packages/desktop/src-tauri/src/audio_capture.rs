@@ -0,0 +1,538 @@
+//! Native audio capture for dictation and meeting transcription.
+//!
+//! The webview normally records the microphone and pushes samples through
+//! `stt_push_audio`. For calls we also need what the *other* side says, which
+//! the webview can't reach, so this module captures one or more native
+//! sources (microphone and/or system audio loopback), mixes them down to
+//! 16 kHz mono and feeds the result into the regular STT buffer.
+//!
+//! System audio support per platform:
+//! - Windows: WASAPI loopback on the default output device
+//! - macOS: a virtual loopback device (BlackHole, Loopback, Soundflower)
+//! - Linux: the PulseAudio/PipeWire "Monitor of ..." source
+
+use cpal::{
+    FromSample, Sample, SampleFormat, SizedSample,
+    traits::{DeviceTrait, HostTrait, StreamTrait},
+};
+use serde::{Deserialize, Serialize};
+use std::{
+    sync::{Arc, Mutex, mpsc},
+    thread::JoinHandle,
+    time::Duration,
+};
+use tauri::{AppHandle, Emitter, Manager};
+
+use crate::{app_state, audio_levels, error::AppError, stt};
+
+/// Sample rate expected by the STT preprocessor
+const TARGET_SAMPLE_RATE: u32 = 16_000;
+/// How often mixed audio is flushed into the STT buffer, often enough for
+/// each input level to be metered as it comes
+const MIX_INTERVAL: Duration = Duration::from_millis(1000 / audio_levels::LEVELS_PER_SECOND as u64);
+
+#[cfg(target_os = "macos")]
+const MACOS_LOOPBACK_DEVICES: &[&str] = &["BlackHole", "Loopback Audio", "Soundflower"];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum CaptureSource {
+    Microphone,
+    System,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CaptureSourceConfig {
+    pub source: CaptureSource,
+    /// Linear gain applied before mixing
+    #[serde(default = "default_gain")]
+    pub gain: f32,
+}
+
+fn default_gain() -> f32 {
+    1.0
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CaptureSourceInfo {
+    pub source: CaptureSource,
+    pub available: bool,
+    pub device_name: Option<String>,
+    /// Setup guidance when the source isn't available on this machine
+    pub hint: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CaptureState {
+    pub active: bool,
+    pub sources: Vec<CaptureSourceConfig>,
+}
+
+struct CaptureSession {
+    sources: Vec<CaptureSourceConfig>,
+    stop_tx: mpsc::Sender<()>,
+    thread: JoinHandle<()>,
+}
+
+#[derive(Default)]
+pub struct AudioCaptureState(Mutex<Option<CaptureSession>>);
+
+/// Incremental linear resampler that keeps its phase across callbacks
+struct LinearResampler {
+    step: f64,
+    position: f64,
+    last: f32,
+}
+
+impl LinearResampler {
+    fn new(source_rate: u32) -> Self {
+        Self {
+            step: source_rate as f64 / TARGET_SAMPLE_RATE as f64,
+            position: 0.0,
+            last: 0.0,
+        }
+    }
+
+    fn process(&mut self, input: &[f32], output: &mut Vec<f32>) {
+        // `position` is relative to `last`, which sits at index -1 of `input`
+        while self.position < input.len() as f64 {
+            let index = self.position.floor();
+            let frac = (self.position - index) as f32;
+            let index = index as isize;
+            let a = if index == 0 {
+                self.last
+            } else {
+                input[(index - 1) as usize]
+            };
+            let b = input[index as usize];
+            output.push(a + (b - a) * frac);
+            self.position += self.step;
+        }
+        self.position -= input.len() as f64;
+        if let Some(&last) = input.last() {
+            self.last = last;
+        }
+    }
+}
+
+type SourceBuffer = Arc<Mutex<Vec<f32>>>;
+
+fn device_name(device: &cpal::Device) -> String {
+    device
+        .name()
+        .unwrap_or_else(|_| "Unknown device".to_string())
+}
+
+#[cfg(not(target_os = "windows"))]
+fn find_input_device(host: &cpal::Host, matches: impl Fn(&str) -> bool) -> Option<cpal::Device> {
+    host.input_devices()
+        .ok()?
+        .find(|device| device.name().is_ok_and(|name| matches(&name)))
+}
+
+/// Resolve the device for a source together with the config to open it with
+fn resolve_device(
+    host: &cpal::Host,
+    source: CaptureSource,
+) -> Result<(cpal::Device, cpal::SupportedStreamConfig), String> {
+    match source {
+        CaptureSource::Microphone => {
+            let device = host.default_input_device().ok_or("No microphone found")?;
+            let config = device
+                .default_input_config()
+                .map_err(|e| format!("Failed to get microphone config: {}", e))?;
+            Ok((device, config))
+        }
+        CaptureSource::System => resolve_system_device(host),
+    }
+}
+
+#[cfg(target_os = "windows")]
+fn resolve_system_device(
+    host: &cpal::Host,
+) -> Result<(cpal::Device, cpal::SupportedStreamConfig), String> {
+    // WASAPI opens output devices in loopback mode when used as an input stream
+    let device = host.default_output_device().ok_or_else(system_audio_hint)?;
+    let config = device
+        .default_output_config()
+        .map_err(|e| format!("Failed to get output device config: {}", e))?;
+    Ok((device, config))
+}
+
+#[cfg(target_os = "macos")]
+fn resolve_system_device(
+    host: &cpal::Host,
+) -> Result<(cpal::Device, cpal::SupportedStreamConfig), String> {
+    let device = find_input_device(host, |name| {
+        MACOS_LOOPBACK_DEVICES
+            .iter()
+            .any(|candidate| name.contains(candidate))
+    })
+    .ok_or_else(system_audio_hint)?;
+    let config = device
+        .default_input_config()
+        .map_err(|e| format!("Failed to get loopback device config: {}", e))?;
+    Ok((device, config))
+}
+
+#[cfg(not(any(target_os = "windows", target_os = "macos")))]
+fn resolve_system_device(
+    host: &cpal::Host,
+) -> Result<(cpal::Device, cpal::SupportedStreamConfig), String> {
+    let device = find_input_device(host, |name| name.to_lowercase().contains("monitor"))
+        .ok_or_else(system_audio_hint)?;
+    let config = device
+        .default_input_config()
+        .map_err(|e| format!("Failed to get monitor source config: {}", e))?;
+    Ok((device, config))
+}
+
+fn system_audio_hint() -> String {
+    if cfg!(target_os = "macos") {
+        "System audio capture needs a virtual loopback device. Install BlackHole \
+         (https://existential.audio/blackhole/), then add it to a Multi-Output Device \
+         in Audio MIDI Setup so you can still hear the call."
+            .to_string()
+    } else if cfg!(target_os = "windows") {
+        "No audio output device is available for loopback capture.".to_string()
+    } else {
+        "No monitor source found. With PulseAudio or PipeWire, make sure the \
+         \"Monitor of\" source for your output device is enabled."
+            .to_string()
+    }
+}
+
+fn build_stream<T>(
+    device: &cpal::Device,
+    config: &cpal::StreamConfig,
+    buffer: SourceBuffer,
+) -> Result<cpal::Stream, String>
+where
+    T: SizedSample,
+    f32: FromSample<T>,
+{
+    let channels = config.channels.max(1) as usize;
+    let mut resampler = LinearResampler::new(config.sample_rate.0);
+    let mut mono = Vec::new();
+    let mut resampled = Vec::new();
+
+    device
+        .build_input_stream(
+            config,
+            move |data: &[T], _: &cpal::InputCallbackInfo| {
+                mono.clear();
+                mono.extend(data.chunks(channels).map(|frame| {
+                    frame.iter().map(|s| f32::from_sample(*s)).sum::<f32>() / frame.len() as f32
+                }));
+                resampled.clear();
+                resampler.process(&mono, &mut resampled);
+                if let Ok(mut buffer) = buffer.lock() {
+                    buffer.extend_from_slice(&resampled);
+                }
+            },
+            |e| eprintln!("Audio capture stream error: {}", e),
+            None,
+        )
+        .map_err(|e| format!("Failed to open audio stream: {}", e))
+}
+
+fn open_source(
+    host: &cpal::Host,
+    source: CaptureSource,
+    buffer: SourceBuffer,
+) -> Result<cpal::Stream, String> {
+    let (device, supported) = resolve_device(host, source)?;
+    println!(
+        "Capturing {:?} from {} ({} Hz, {} ch)",
+        source,
+        device_name(&device),
+        supported.sample_rate().0,
+        supported.channels()
+    );
+
+    let config = supported.config();
+    let stream = match supported.sample_format() {
+        SampleFormat::F32 => build_stream::<f32>(&device, &config, buffer),
+        SampleFormat::I16 => build_stream::<i16>(&device, &config, buffer),
+        SampleFormat::U16 => build_stream::<u16>(&device, &config, buffer),
+        SampleFormat::I32 => build_stream::<i32>(&device, &config, buffer),
+        format => Err(format!("Unsupported sample format: {}", format)),
+    }?;
+    stream
+        .play()
+        .map_err(|e| format!("Failed to start audio stream: {}", e))?;
+    Ok(stream)
+}
+
+/// Mix whatever every source has produced so far. Sources that lag behind
+/// (e.g. WASAPI loopback delivers nothing while nothing is playing) are
+/// padded with silence so the microphone isn't held back.
+fn mix(buffers: &[(SourceBuffer, f32)]) -> Vec<f32> {
+    let mut mixed: Vec<f32> = Vec::new();
+    for (buffer, gain) in buffers {
+        let samples = match buffer.lock() {
+            Ok(mut buffer) => std::mem::take(&mut *buffer),
+            Err(_) => continue,
+        };
+        if samples.len() > mixed.len() {
+            mixed.resize(samples.len(), 0.0);
+        }
+        for (out, sample) in mixed.iter_mut().zip(samples) {
+            *out += sample * gain;
+        }
+    }
+    for sample in &mut mixed {
+        *sample = sample.clamp(-1.0, 1.0);
+    }
+    mixed
+}
+
+/// Owns the (non-`Send`) cpal streams for the lifetime of a capture session
+fn run_capture(
+    app: AppHandle,
+    sources: Vec<CaptureSourceConfig>,
+    ready_tx: mpsc::Sender<Result<(), String>>,
+    stop_rx: mpsc::Receiver<()>,
+) {
+    let host = cpal::default_host();
+    let mut streams = Vec::new();
+    let mut buffers = Vec::new();
+
+    for config in &sources {
+        let buffer = SourceBuffer::default();
+        match open_source(&host, config.source, buffer.clone()) {
+            Ok(stream) => {
+                streams.push(stream);
+                buffers.push((buffer, config.gain.max(0.0)));
+            }
+            Err(e) => {
+                let _ = ready_tx.send(Err(e));
+                return;
+            }
+        }
+    }
+    let _ = ready_tx.send(Ok(()));
+
+    let Some(stt_state) = app.try_state::<stt::SharedSttState>() else {
+        return;
+    };
+
+    loop {
+        let stopped = match stop_rx.recv_timeout(MIX_INTERVAL) {
+            Err(mpsc::RecvTimeoutError::Timeout) => false,
+            Ok(()) | Err(mpsc::RecvTimeoutError::Disconnected) => true,
+        };
+
+        let samples = mix(&buffers);
+        if !samples.is_empty() {
+            // A thread of its own, off the async runtime
+            let pushed = {
+                let mut state = stt_state.blocking_lock();
+                if state.accepts_audio() {
+                    audio_levels::observe(&app, &samples);
+                }
+                let pushed = state.push_audio(samples);
+                if state.take_hint_change() {
+                    let app = app.clone();
+                    tauri::async_runtime::spawn(async move { app_state::publish_stt(&app).await });
+                }
+                pushed
+            };
+            match pushed {
+                Ok(Some(warning)) => {
+                    stt::emit_recording_warning(&app, &warning);
+                    if warning.stopped {
+                        break;
+                    }
+                }
+                Ok(None) => {}
+                // Recording was stopped elsewhere, e.g. by a direct stt_stop_and_transcribe
+                Err(_) => break,
+            }
+        }
+
+        if stopped {
+            break;
+        }
+    }
+
+    drop(streams);
+    emit_capture_state(&app, false, Vec::new());
+}
+
+fn emit_capture_state(app: &AppHandle, active: bool, sources: Vec<CaptureSourceConfig>) {
+    let _ = app.emit("stt:capture-state", CaptureState { active, sources });
+}
+
+/// Stop the native capture (if any), flushing its remaining audio into the STT buffer
+pub fn stop_capture(app: &AppHandle) -> Result<(), String> {
+    let state = app
+        .try_state::<AudioCaptureState>()
+        .ok_or("Audio capture state not found")?;
+    let session = state
+        .0
+        .lock()
+        .map_err(|e| format!("Lock error: {}", e))?
+        .take();
+
+    if let Some(session) = session {
+        let _ = session.stop_tx.send(());
+        session
+            .thread
+            .join()
+            .map_err(|_| "Audio capture thread panicked".to_string())?;
+    }
+    Ok(())
+}
+
+/// Stop the native capture off the async runtime, as it waits for the
+/// capture thread
+async fn stop_capture_blocking(app: &AppHandle) -> Result<(), String> {
+    let app = app.clone();
+    tauri::async_runtime::spawn_blocking(move || stop_capture(&app))
+        .await
+        .map_err(|e| format!("Audio capture task failed: {}", e))?
+}
+
+#[tauri::command]
+pub async fn stt_list_capture_sources() -> Result<Vec<CaptureSourceInfo>, AppError> {
+    // Enumerating devices can take a while, as drivers wake up
+    Ok(tauri::async_runtime::spawn_blocking(list_capture_sources)
+        .await
+        .map_err(|e| format!("Audio capture task failed: {}", e))?)
+}
+
+fn list_capture_sources() -> Vec<CaptureSourceInfo> {
+    let host = cpal::default_host();
+    [CaptureSource::Microphone, CaptureSource::System]
+        .into_iter()
+        .map(|source| match resolve_device(&host, source) {
+            Ok((device, _)) => CaptureSourceInfo {
+                source,
+                available: true,
+                device_name: Some(device_name(&device)),
+                hint: None,
+            },
+            Err(e) => CaptureSourceInfo {
+                source,
+                available: false,
+                device_name: None,
+                hint: Some(e),
+            },
+        })
+        .collect()
+}
+
+/// Start recording from native sources instead of webview-pushed audio
+#[tauri::command]
+pub async fn stt_start_capture(
+    app: AppHandle,
+    sources: Vec<CaptureSourceConfig>,
+) -> Result<(), AppError> {
+    if sources.is_empty() {
+        return Err(AppError::invalid_input("No capture sources selected"));
+    }
+    stop_capture_blocking(&app).await?;
+
+    stt::start_recording(&app).await?;
+    app_state::publish_stt(&app).await;
+
+    let (ready_tx, ready_rx) = mpsc::channel();
+    let (stop_tx, stop_rx) = mpsc::channel();
+    let thread = {
+        let app = app.clone();
+        let sources = sources.clone();
+        std::thread::Builder::new()
+            .name("audio-capture".to_string())
+            .spawn(move || run_capture(app, sources, ready_tx, stop_rx))
+            .map_err(|e| format!("Failed to spawn audio capture thread: {}", e))?
+    };
+
+    let ready = tauri::async_runtime::spawn_blocking(move || ready_rx.recv())
+        .await
+        .map_err(|e| format!("Audio capture task failed: {}", e))?
+        .map_err(|_| "Audio capture thread exited unexpectedly".to_string())
+        .and_then(|result| result);
+    if let Err(e) = ready {
+        let _ = tauri::async_runtime::spawn_blocking(move || thread.join()).await;
+        if let Some(stt_state) = app.try_state::<stt::SharedSttState>() {
+            stt_state.lock().await.stop_recording();
+        }
+        app_state::publish_stt(&app).await;
+        return Err(e.into());
+    }
+
+    let state = app
+        .try_state::<AudioCaptureState>()
+        .ok_or("Audio capture state not found")?;
+    state
+        .0
+        .lock()
+        .map_err(|e| format!("Lock error: {}", e))?
+        .replace(CaptureSession {
+            sources: sources.clone(),
+            stop_tx,
+            thread,
+        });
+    emit_capture_state(&app, true, sources);
+
+    Ok(())
+}
+
+/// Stop native capture without transcribing; the audio stays in the STT buffer
+#[tauri::command]
+pub async fn stt_stop_capture(app: AppHandle) -> Result<(), AppError> {
+    Ok(stop_capture_blocking(&app).await?)
+}
+
+#[tauri::command]
+pub fn stt_get_capture_state(app: AppHandle) -> Result<CaptureState, AppError> {
+    let state = app
+        .try_state::<AudioCaptureState>()
+        .ok_or("Audio capture state not found")?;
+    let session = state.0.lock().map_err(|e| format!("Lock error: {}", e))?;
+    Ok(match session.as_ref() {
+        Some(session) if !session.thread.is_finished() => CaptureState {
+            active: true,
+            sources: session.sources.clone(),
+        },
+        _ => CaptureState {
+            active: false,
+            sources: Vec::new(),
+        },
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_resampler_keeps_phase_across_chunks() {
+        let input: Vec<f32> = (0..480).map(|i| i as f32).collect();
+
+        let mut whole = Vec::new();
+        LinearResampler::new(48_000).process(&input, &mut whole);
+
+        let mut chunked = Vec::new();
+        let mut resampler = LinearResampler::new(48_000);
+        for chunk in input.chunks(7) {
+            resampler.process(chunk, &mut chunked);
+        }
+
+        assert_eq!(whole.len(), 160);
+        assert_eq!(whole, chunked);
+    }
+
+    #[test]
+    fn test_mix_pads_shorter_sources() {
+        let mic = SourceBuffer::new(Mutex::new(vec![0.5, 0.5, 0.5]));
+        let system = SourceBuffer::new(Mutex::new(vec![0.75]));
+
+        let mixed = mix(&[(mic, 1.0), (system, 1.0)]);
+        assert_eq!(mixed, vec![1.0, 0.5, 0.5]);
+    }
+}
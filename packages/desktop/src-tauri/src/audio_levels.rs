@@ -0,0 +1,125 @@
+//! Input levels of a recording, for a live meter.
+//!
+//! While a webview is subscribed with `stt_subscribe_levels`, audio going
+//! into the recording is metered in windows of 1/30 s, and each window's RMS
+//! and peak are emitted to it as `stt:level`, so it can draw a waveform
+//! without the samples being sent to it.
+
+use serde::Serialize;
+use std::{collections::HashSet, sync::Mutex};
+use tauri::{AppHandle, Emitter, Manager, Webview};
+
+use crate::error::AppError;
+
+pub const LEVELS_PER_SECOND: usize = 30;
+const WINDOW_SAMPLES: usize = 16_000 / LEVELS_PER_SECOND;
+
+/// Payload of `stt:level`, both from 0 to 1
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+pub struct Level {
+    pub rms: f32,
+    pub peak: f32,
+}
+
+#[derive(Default)]
+struct Meter {
+    sum_squares: f32,
+    peak: f32,
+    samples: usize,
+}
+
+impl Meter {
+    /// The levels of the windows `samples` complete
+    fn feed(&mut self, samples: &[f32]) -> Vec<Level> {
+        let mut levels = Vec::new();
+        for sample in samples {
+            self.sum_squares += sample * sample;
+            self.peak = self.peak.max(sample.abs());
+            self.samples += 1;
+            if self.samples == WINDOW_SAMPLES {
+                levels.push(Level {
+                    rms: (self.sum_squares / WINDOW_SAMPLES as f32).sqrt(),
+                    peak: self.peak.min(1.0),
+                });
+                *self = Self::default();
+            }
+        }
+        levels
+    }
+}
+
+#[derive(Default)]
+pub struct LevelState {
+    /// Labels of the subscribed webviews
+    subscribers: Mutex<HashSet<String>>,
+    meter: Mutex<Meter>,
+}
+
+/// Meter `samples` going into the recording, for subscribers
+pub fn observe(app: &AppHandle, samples: &[f32]) {
+    let Some(state) = app.try_state::<LevelState>() else {
+        return;
+    };
+    let subscribers = match state.subscribers.lock() {
+        Ok(subscribers) if !subscribers.is_empty() => subscribers.clone(),
+        _ => return,
+    };
+    let Ok(levels) = state.meter.lock().map(|mut meter| meter.feed(samples)) else {
+        return;
+    };
+    for level in levels {
+        for label in &subscribers {
+            let _ = app.emit_to(label.as_str(), "stt:level", level);
+        }
+    }
+}
+
+/// Start metering a new recording
+pub fn reset(app: &AppHandle) {
+    if let Some(state) = app.try_state::<LevelState>()
+        && let Ok(mut meter) = state.meter.lock()
+    {
+        *meter = Meter::default();
+    }
+}
+
+/// Start or stop sending this webview the levels of recordings
+#[tauri::command]
+pub fn stt_subscribe_levels(
+    app: AppHandle,
+    webview: Webview,
+    subscribe: bool,
+) -> Result<(), AppError> {
+    let state = app
+        .try_state::<LevelState>()
+        .ok_or("Level state not found")?;
+    let mut subscribers = state
+        .subscribers
+        .lock()
+        .map_err(|e| format!("Lock error: {}", e))?;
+    if subscribe {
+        subscribers.insert(webview.label().to_string());
+    } else {
+        subscribers.remove(webview.label());
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_meter_windows() {
+        let mut meter = Meter::default();
+        assert!(meter.feed(&[0.5; WINDOW_SAMPLES - 1]).is_empty());
+
+        let levels = meter.feed(&[0.5, -2.0]);
+        assert_eq!(levels.len(), 1);
+        assert!((levels[0].rms - 0.5).abs() < 1e-4);
+        assert_eq!(levels[0].peak, 0.5);
+
+        let levels = meter.feed(&[0.0; WINDOW_SAMPLES - 1]);
+        assert_eq!(levels[0].peak, 1.0);
+    }
+}
@@ -0,0 +1,269 @@
+//! Audit log of privileged commands.
+//!
+//! Some organizations need a record of what the app was asked to do before
+//! they allow it on their machines. When the log is on, commands that install
+//! software, delete data, export files or change where data goes or what
+//! pages may access append an entry (calling window, a summary of the
+//! arguments, the outcome) to a JSON Lines file in the app's local data
+//! directory.
+//!
+//! The file is only ever appended to, and purging local data leaves it in
+//! place. Turning the log off is itself logged.
+
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+use std::{
+    io::Write,
+    path::PathBuf,
+    sync::Mutex,
+    time::{SystemTime, UNIX_EPOCH},
+};
+use tauri::{AppHandle, Manager, Webview, path::BaseDirectory};
+use tauri_plugin_store::StoreExt;
+
+use crate::{SETTINGS_STORE, atomic_store, error::AppError, policy};
+
+const ENABLED_KEY: &str = "auditLogEnabled";
+const AUDIT_DIR: &str = "audit";
+const AUDIT_FILE: &str = "audit.jsonl";
+/// Longer string arguments are cut, so file contents and the like don't end
+/// up in the log
+const MAX_STRING_CHARS: usize = 120;
+/// Longer array arguments are logged as their length
+const MAX_ARRAY_ITEMS: usize = 8;
+const DEFAULT_LIMIT: usize = 500;
+
+/// Serializes appends, so concurrent entries don't interleave
+#[derive(Default)]
+pub struct AuditState(Mutex<()>);
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AuditEntry {
+    /// Milliseconds since the Unix epoch
+    pub timestamp: u64,
+    /// Label of the calling window
+    pub window: String,
+    /// Origin of the page that made the call
+    pub origin: Option<String>,
+    pub command: String,
+    pub args: Value,
+    pub ok: bool,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<AppError>,
+}
+
+fn now_millis() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_millis() as u64)
+        .unwrap_or(0)
+}
+
+fn is_secret(key: &str) -> bool {
+    let key = key.to_lowercase();
+    ["password", "secret", "token"]
+        .iter()
+        .any(|word| key.contains(word))
+}
+
+/// Arguments as logged: secrets redacted, long strings cut and long arrays
+/// replaced by their length
+fn summarize(value: &Value) -> Value {
+    match value {
+        Value::String(text) if text.chars().count() > MAX_STRING_CHARS => {
+            let cut: String = text.chars().take(MAX_STRING_CHARS).collect();
+            Value::String(format!("{}… ({} chars)", cut, text.chars().count()))
+        }
+        Value::Array(items) if items.len() > MAX_ARRAY_ITEMS => {
+            Value::String(format!("[{} items]", items.len()))
+        }
+        Value::Array(items) => Value::Array(items.iter().map(summarize).collect()),
+        Value::Object(entries) => Value::Object(
+            entries
+                .iter()
+                .map(|(key, value)| {
+                    let value = if is_secret(key) && !value.is_null() {
+                        Value::String("[redacted]".to_string())
+                    } else {
+                        summarize(value)
+                    };
+                    (key.clone(), value)
+                })
+                .collect::<Map<_, _>>(),
+        ),
+        value => value.clone(),
+    }
+}
+
+fn audit_path(app: &AppHandle) -> Result<PathBuf, String> {
+    app.path()
+        .resolve(AUDIT_DIR, BaseDirectory::AppLocalData)
+        .map(|dir| dir.join(AUDIT_FILE))
+        .map_err(|e| format!("Failed to resolve audit log directory: {}", e))
+}
+
+pub fn is_enabled(app: &AppHandle) -> bool {
+    if policy::current(app).audit_log {
+        return true;
+    }
+    app.store(SETTINGS_STORE)
+        .ok()
+        .and_then(|store| store.get(ENABLED_KEY))
+        .and_then(|value| value.as_bool())
+        .unwrap_or(false)
+}
+
+fn append(app: &AppHandle, entry: &AuditEntry) -> Result<(), String> {
+    let state = app
+        .try_state::<AuditState>()
+        .ok_or("Audit state not found")?;
+    let _guard = state.0.lock().map_err(|e| format!("Lock error: {}", e))?;
+
+    let path = audit_path(app)?;
+    if let Some(dir) = path.parent() {
+        std::fs::create_dir_all(dir)
+            .map_err(|e| format!("Failed to create {}: {}", dir.display(), e))?;
+    }
+    let mut options = std::fs::OpenOptions::new();
+    options.create(true).append(true);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::OpenOptionsExt;
+        options.mode(0o600);
+    }
+    let mut file = options
+        .open(&path)
+        .map_err(|e| format!("Failed to open {}: {}", path.display(), e))?;
+
+    let mut line =
+        serde_json::to_string(entry).map_err(|e| format!("Failed to serialize entry: {}", e))?;
+    line.push('\n');
+    file.write_all(line.as_bytes())
+        .map_err(|e| format!("Failed to write {}: {}", path.display(), e))
+}
+
+fn write_entry<T>(webview: &Webview, command: &str, args: Value, result: &Result<T, AppError>) {
+    let entry = AuditEntry {
+        timestamp: now_millis(),
+        window: webview.window().label().to_string(),
+        origin: webview
+            .url()
+            .ok()
+            .map(|url| url.origin().ascii_serialization()),
+        command: command.to_string(),
+        args: summarize(&args),
+        ok: result.is_ok(),
+        error: result.as_ref().err().cloned(),
+    };
+    if let Err(e) = append(webview.app_handle(), &entry) {
+        eprintln!("Failed to write audit log: {}", e);
+    }
+}
+
+/// Log a privileged command call and its outcome, if the audit log is on
+pub fn record<T>(webview: &Webview, command: &str, args: Value, result: &Result<T, AppError>) {
+    if is_enabled(webview.app_handle()) {
+        write_entry(webview, command, args, result);
+    }
+}
+
+/// Like `record`, for a command that may have turned the log off:
+/// `was_enabled` is whether it was on before, which still logs the command
+pub fn record_change<T>(
+    webview: &Webview,
+    was_enabled: bool,
+    command: &str,
+    args: Value,
+    result: &Result<T, AppError>,
+) {
+    if was_enabled || is_enabled(webview.app_handle()) {
+        write_entry(webview, command, args, result);
+    }
+}
+
+/// The most recent `limit` entries (500 by default), oldest first
+#[tauri::command]
+pub fn get_audit_log(app: AppHandle, limit: Option<usize>) -> Result<Vec<AuditEntry>, AppError> {
+    let path = audit_path(&app)?;
+    let content = match std::fs::read_to_string(&path) {
+        Ok(content) => content,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(e) => {
+            return Err(AppError::io(format!(
+                "Failed to read {}: {}",
+                path.display(),
+                e
+            )));
+        }
+    };
+    let entries: Vec<AuditEntry> = content
+        .lines()
+        .filter_map(|line| serde_json::from_str(line).ok())
+        .collect();
+    let skip = entries.len().saturating_sub(limit.unwrap_or(DEFAULT_LIMIT));
+    Ok(entries.into_iter().skip(skip).collect())
+}
+
+#[tauri::command]
+pub fn get_audit_log_enabled(app: AppHandle) -> bool {
+    is_enabled(&app)
+}
+
+#[tauri::command]
+pub fn set_audit_log_enabled(
+    app: AppHandle,
+    webview: Webview,
+    enabled: bool,
+) -> Result<(), AppError> {
+    if policy::current(&app).audit_log {
+        return Err(policy::locked_error(&app));
+    }
+    let was_enabled = is_enabled(&app);
+    let result = app
+        .store(SETTINGS_STORE)
+        .map_err(|e| format!("Failed to open settings store: {}", e))
+        .and_then(|store| {
+            store.set(ENABLED_KEY, serde_json::json!(enabled));
+            atomic_store::save(&app, SETTINGS_STORE)
+                .map_err(|e| format!("Failed to save settings: {}", e))
+        })
+        .map_err(AppError::from);
+    if was_enabled || enabled {
+        write_entry(
+            &webview,
+            "set_audit_log_enabled",
+            serde_json::json!({ "enabled": enabled }),
+            &result,
+        );
+    }
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_summarize_redacts_and_cuts() {
+        let long = "x".repeat(MAX_STRING_CHARS + 5);
+        let summary = summarize(&json!({
+            "url": "https://example.com",
+            "password": "hunter2",
+            "accessToken": null,
+            "text": long,
+            "paths": (0..20).collect::<Vec<_>>(),
+            "ids": [1, 2],
+        }));
+        assert_eq!(summary["url"], "https://example.com");
+        assert_eq!(summary["password"], "[redacted]");
+        assert_eq!(summary["accessToken"], Value::Null);
+        assert_eq!(
+            summary["text"],
+            format!("{}… (125 chars)", "x".repeat(MAX_STRING_CHARS))
+        );
+        assert_eq!(summary["paths"], "[20 items]");
+        assert_eq!(summary["ids"], json!([1, 2]));
+    }
+}
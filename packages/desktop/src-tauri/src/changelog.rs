@@ -0,0 +1,287 @@
+//! Release notes for the app and the CLI, which are released together.
+//!
+//! Notes come from the GitHub releases of the project and are cached so the
+//! changelog still shows offline. When the app starts on a newer version than
+//! last time, or a CLI sync installs a newer CLI, a `whats-new` event carries
+//! the notes of everything in between.
+
+use serde::{Deserialize, Serialize};
+use std::{
+    path::PathBuf,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+use tauri::{AppHandle, Emitter, Manager, path::BaseDirectory};
+use tauri_plugin_store::StoreExt;
+
+use crate::{SETTINGS_STORE, atomic_store, error::AppError};
+
+const RELEASES_URL: &str = "https://api.github.com/repos/joyi-ai/Aura/releases?per_page=50";
+pub(crate) const CACHE_FILE: &str = "changelog-cache.json";
+const CACHE_TTL_SECS: u64 = 6 * 60 * 60;
+const LAST_SEEN_VERSION_KEY: &str = "lastSeenVersion";
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ReleaseNote {
+    pub version: String,
+    pub name: String,
+    /// Markdown release notes
+    pub body: String,
+    pub published_at: Option<String>,
+    pub url: String,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct WhatsNew {
+    /// "app" or "cli"
+    pub component: String,
+    pub previous_version: String,
+    pub version: String,
+    pub notes: Vec<ReleaseNote>,
+}
+
+#[derive(Deserialize)]
+struct GithubRelease {
+    tag_name: String,
+    name: Option<String>,
+    body: Option<String>,
+    published_at: Option<String>,
+    html_url: String,
+    draft: bool,
+}
+
+#[derive(Serialize, Deserialize)]
+struct ChangelogCache {
+    fetched_at: u64,
+    releases: Vec<ReleaseNote>,
+}
+
+fn now_secs() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0)
+}
+
+fn parse_version(version: &str) -> Option<semver::Version> {
+    semver::Version::parse(version.trim().trim_start_matches('v')).ok()
+}
+
+fn cache_path(app: &AppHandle) -> Result<PathBuf, String> {
+    app.path()
+        .resolve(CACHE_FILE, BaseDirectory::AppCache)
+        .map_err(|e| format!("Failed to resolve changelog cache path: {}", e))
+}
+
+fn read_cache(app: &AppHandle) -> Option<ChangelogCache> {
+    let contents = std::fs::read_to_string(cache_path(app).ok()?).ok()?;
+    serde_json::from_str(&contents).ok()
+}
+
+fn write_cache(app: &AppHandle, cache: &ChangelogCache) -> Result<(), String> {
+    let path = cache_path(app)?;
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent)
+            .map_err(|e| format!("Failed to create {}: {}", parent.display(), e))?;
+    }
+    let contents = serde_json::to_string(cache)
+        .map_err(|e| format!("Failed to serialize changelog cache: {}", e))?;
+    std::fs::write(&path, contents).map_err(|e| format!("Failed to write changelog cache: {}", e))
+}
+
+async fn fetch_releases() -> Result<Vec<ReleaseNote>, String> {
+    let response = reqwest::Client::builder()
+        .timeout(Duration::from_secs(15))
+        .build()
+        .map_err(|e| format!("Failed to create HTTP client: {}", e))?
+        .get(RELEASES_URL)
+        .header(reqwest::header::USER_AGENT, "Aura-Desktop")
+        .header(reqwest::header::ACCEPT, "application/vnd.github+json")
+        .send()
+        .await
+        .map_err(|e| format!("Failed to fetch release notes: {}", e))?;
+    if !response.status().is_success() {
+        return Err(format!(
+            "Failed to fetch release notes: HTTP {}",
+            response.status()
+        ));
+    }
+    let releases: Vec<GithubRelease> = response
+        .json()
+        .await
+        .map_err(|e| format!("Failed to parse release notes: {}", e))?;
+
+    Ok(releases
+        .into_iter()
+        .filter(|release| !release.draft)
+        .filter_map(|release| {
+            let version = parse_version(&release.tag_name)?.to_string();
+            Some(ReleaseNote {
+                name: release.name.unwrap_or_else(|| version.clone()),
+                version,
+                body: release.body.unwrap_or_default(),
+                published_at: release.published_at,
+                url: release.html_url,
+            })
+        })
+        .collect())
+}
+
+/// All known releases, from the cache while it's fresh. A stale cache is
+/// still used when fetching fails.
+async fn releases(app: &AppHandle) -> Result<Vec<ReleaseNote>, String> {
+    let cache = read_cache(app);
+    if let Some(cache) = &cache
+        && now_secs().saturating_sub(cache.fetched_at) < CACHE_TTL_SECS
+    {
+        return Ok(cache.releases.clone());
+    }
+
+    match fetch_releases().await {
+        Ok(releases) => {
+            let fresh = ChangelogCache {
+                fetched_at: now_secs(),
+                releases,
+            };
+            if let Err(e) = write_cache(app, &fresh) {
+                eprintln!("Failed to cache release notes: {}", e);
+            }
+            Ok(fresh.releases)
+        }
+        Err(e) => match cache {
+            Some(cache) => {
+                eprintln!("{}, using cached release notes", e);
+                Ok(cache.releases)
+            }
+            None => Err(e),
+        },
+    }
+}
+
+/// Releases newer than `since` up to and including `until`, newest first
+fn notes_between(
+    releases: Vec<ReleaseNote>,
+    since: Option<&semver::Version>,
+    until: &semver::Version,
+) -> Vec<ReleaseNote> {
+    let mut notes: Vec<(semver::Version, ReleaseNote)> = releases
+        .into_iter()
+        .filter_map(|note| Some((parse_version(&note.version)?, note)))
+        .filter(|(version, _)| since.is_none_or(|since| version > since) && version <= until)
+        .collect();
+    notes.sort_by(|(a, _), (b, _)| b.cmp(a));
+    notes.into_iter().map(|(_, note)| note).collect()
+}
+
+/// Emit `whats-new` for an update from `previous_version` to `version`
+pub async fn announce_update(
+    app: &AppHandle,
+    component: &str,
+    previous_version: &str,
+    version: &str,
+) -> Result<(), String> {
+    let (Some(previous), Some(current)) = (parse_version(previous_version), parse_version(version))
+    else {
+        return Err(format!(
+            "Invalid versions {} -> {}",
+            previous_version, version
+        ));
+    };
+    if current <= previous {
+        return Ok(());
+    }
+
+    let notes = notes_between(releases(app).await?, Some(&previous), &current);
+    let _ = app.emit(
+        "whats-new",
+        WhatsNew {
+            component: component.to_string(),
+            previous_version: previous.to_string(),
+            version: current.to_string(),
+            notes,
+        },
+    );
+    Ok(())
+}
+
+/// Announce an app update if this launch runs a newer version than the last
+/// one. Fresh installs just record the version.
+pub fn check_app_update(app: &AppHandle) {
+    let app = app.clone();
+    tauri::async_runtime::spawn(async move {
+        let version = app.package_info().version.to_string();
+        let Ok(store) = app.store(SETTINGS_STORE) else {
+            return;
+        };
+        let last_seen = store
+            .get(LAST_SEEN_VERSION_KEY)
+            .and_then(|value| value.as_str().map(str::to_string));
+        if last_seen.as_deref() == Some(version.as_str()) {
+            return;
+        }
+
+        if let Some(last_seen) = last_seen
+            && let Err(e) = announce_update(&app, "app", &last_seen, &version).await
+        {
+            // Keep the last seen version so the next launch tries again
+            eprintln!("Failed to announce app update: {}", e);
+            return;
+        }
+
+        store.set(LAST_SEEN_VERSION_KEY, serde_json::json!(version));
+        if let Err(e) = atomic_store::save(&app, SETTINGS_STORE) {
+            eprintln!("Failed to save settings: {}", e);
+        }
+    });
+}
+
+/// Release notes of every release after `since_version` up to the running
+/// version, newest first. Without `since_version`, all of them.
+#[tauri::command]
+pub async fn get_changelog(
+    app: AppHandle,
+    since_version: Option<String>,
+) -> Result<Vec<ReleaseNote>, AppError> {
+    let since = match since_version.as_deref() {
+        Some(since) => {
+            Some(parse_version(since).ok_or_else(|| format!("Invalid version: {}", since))?)
+        }
+        None => None,
+    };
+    let current = app.package_info().version.clone();
+    Ok(notes_between(
+        releases(&app).await?,
+        since.as_ref(),
+        &current,
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn note(version: &str) -> ReleaseNote {
+        ReleaseNote {
+            version: version.to_string(),
+            name: version.to_string(),
+            body: String::new(),
+            published_at: None,
+            url: String::new(),
+        }
+    }
+
+    #[test]
+    fn test_notes_between_filters_and_sorts() {
+        let releases = vec![note("1.1.0"), note("1.3.0"), note("1.0.0"), note("1.2.0")];
+        let since = parse_version("v1.0.0").unwrap();
+        let until = parse_version("1.2.0").unwrap();
+
+        let versions: Vec<String> = notes_between(releases.clone(), Some(&since), &until)
+            .into_iter()
+            .map(|note| note.version)
+            .collect();
+        assert_eq!(versions, ["1.2.0", "1.1.0"]);
+        assert_eq!(notes_between(releases, None, &until).len(), 3);
+    }
+}
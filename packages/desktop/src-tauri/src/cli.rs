@@ -1,8 +1,50 @@
-use tauri::{AppHandle, Manager, path::BaseDirectory};
+use serde_json::Value;
+use std::path::{Path, PathBuf};
+use tauri::{AppHandle, Manager, Webview, path::BaseDirectory};
 use tauri_plugin_shell::{ShellExt, process::Command};
 
+use crate::{
+    audit,
+    error::AppError,
+    hash::{HashAlgorithm, hash_path},
+    test_mode,
+};
+
 const CLI_INSTALL_DIR: &str = ".opencode/bin";
 const CLI_BINARY_NAME: &str = "opencode";
+const SIDECAR_MANIFEST: &str = "sidecar-manifest.json";
+/// Copy of the sidecar shipped as a resource, started when the sidecar next
+/// to the app binary is missing or won't run, as after an antivirus
+/// quarantined it
+const FALLBACK_SIDECAR: &str = "sidecars/fallback/opencode-cli";
+
+/// Which copy of the sidecar to start
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Sidecar {
+    Primary,
+    Fallback,
+}
+
+impl Sidecar {
+    pub fn binary_path(self, app: &AppHandle) -> Result<PathBuf, String> {
+        match self {
+            Self::Primary => {
+                Ok(test_mode::mock_sidecar().unwrap_or_else(|| get_sidecar_binary_path(app)))
+            }
+            Self::Fallback => get_fallback_sidecar_path(app)
+                .ok_or_else(|| "No bundled sidecar to fall back to".to_string()),
+        }
+    }
+}
+
+/// Size and hash of the sidecar shipped with an app version, recorded by
+/// `sync_cli` and checked before the sidecar is spawned
+#[derive(Debug, Clone, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+struct SidecarManifest {
+    version: String,
+    size: u64,
+    blake3: String,
+}
 
 #[derive(serde::Deserialize)]
 pub struct ServerConfig {
@@ -42,6 +84,203 @@ pub fn get_sidecar_path(app: &tauri::AppHandle) -> std::path::PathBuf {
         .join("opencode-cli")
 }
 
+/// The sidecar executable itself, including the extension on Windows
+pub fn get_sidecar_binary_path(app: &AppHandle) -> PathBuf {
+    get_sidecar_path(app).with_extension(std::env::consts::EXE_EXTENSION)
+}
+
+/// The bundled fallback sidecar, if this build ships one
+pub fn get_fallback_sidecar_path(app: &AppHandle) -> Option<PathBuf> {
+    app.path()
+        .resolve(FALLBACK_SIDECAR, BaseDirectory::Resource)
+        .ok()
+        .map(|path| path.with_extension(std::env::consts::EXE_EXTENSION))
+        .filter(|path| path.exists())
+}
+
+fn get_manifest_path(app: &AppHandle) -> Result<PathBuf, String> {
+    app.path()
+        .resolve(SIDECAR_MANIFEST, BaseDirectory::AppLocalData)
+        .map_err(|e| format!("Failed to resolve sidecar manifest path: {}", e))
+}
+
+fn fingerprint(app: &AppHandle, path: &Path) -> Result<SidecarManifest, String> {
+    let metadata =
+        std::fs::metadata(path).map_err(|e| format!("Failed to read {}: {}", path.display(), e))?;
+    Ok(SidecarManifest {
+        version: app.package_info().version.to_string(),
+        size: metadata.len(),
+        blake3: hash_path(path, HashAlgorithm::Blake3)?,
+    })
+}
+
+/// The manifest recorded for the running app version, if any
+fn read_manifest(app: &AppHandle) -> Option<SidecarManifest> {
+    let contents = std::fs::read_to_string(get_manifest_path(app).ok()?).ok()?;
+    serde_json::from_str::<SidecarManifest>(&contents)
+        .ok()
+        .filter(|manifest| manifest.version == app.package_info().version.to_string())
+}
+
+/// Record the sidecar of this app version, unless it's already recorded
+fn record_sidecar_manifest(app: &AppHandle) -> Result<(), String> {
+    if read_manifest(app).is_some() {
+        return Ok(());
+    }
+
+    let manifest = fingerprint(app, &get_sidecar_binary_path(app))?;
+    let path = get_manifest_path(app)?;
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent)
+            .map_err(|e| format!("Failed to create {}: {}", parent.display(), e))?;
+    }
+    let contents = serde_json::to_string_pretty(&manifest)
+        .map_err(|e| format!("Failed to serialize sidecar manifest: {}", e))?;
+    std::fs::write(&path, contents).map_err(|e| format!("Failed to write sidecar manifest: {}", e))
+}
+
+fn check_against(app: &AppHandle, path: &Path, manifest: &SidecarManifest) -> Result<(), String> {
+    let size = std::fs::metadata(path)
+        .map_err(|e| format!("Failed to read {}: {}", path.display(), e))?
+        .len();
+    if size != manifest.size {
+        return Err(format!(
+            "{} is truncated or incomplete ({} bytes, expected {})",
+            path.display(),
+            size,
+            manifest.size
+        ));
+    }
+    if fingerprint(app, path)?.blake3 != manifest.blake3 {
+        return Err(format!("{} has been modified", path.display()));
+    }
+    Ok(())
+}
+
+/// Check the sidecar against the manifest written by `sync_cli`. Without a
+/// manifest for this app version there is nothing to check against yet.
+pub fn verify_sidecar(app: &AppHandle) -> Result<(), String> {
+    if test_mode::mock_sidecar().is_some() {
+        return Ok(());
+    }
+    let sidecar = get_sidecar_binary_path(app);
+    if !sidecar.exists() {
+        return Err(format!("{} is missing", sidecar.display()));
+    }
+    match read_manifest(app) {
+        Some(manifest) => check_against(app, &sidecar, &manifest),
+        None => Ok(()),
+    }
+}
+
+/// Copy `from` next to `target` and verify the copy, returning the staged path
+fn stage_copy(
+    app: &AppHandle,
+    from: &Path,
+    target: &Path,
+    manifest: &SidecarManifest,
+) -> Result<PathBuf, String> {
+    let staged = target.with_extension("download");
+    let result = std::fs::copy(from, &staged)
+        .map_err(|e| format!("Failed to copy {}: {}", from.display(), e))
+        .and_then(|_| {
+            #[cfg(unix)]
+            {
+                use std::os::unix::fs::PermissionsExt;
+                std::fs::set_permissions(&staged, std::fs::Permissions::from_mode(0o755))
+                    .map_err(|e| format!("Failed to set permissions: {}", e))?;
+            }
+            check_against(app, &staged, manifest)
+        });
+    if let Err(e) = result {
+        let _ = std::fs::remove_file(&staged);
+        return Err(e);
+    }
+    Ok(staged)
+}
+
+/// Where an update waits when the binary it replaces is in use
+fn pending_path(target: &Path) -> PathBuf {
+    target.with_extension("pending")
+}
+
+/// Windows refuses to replace an executable while it's running
+fn is_in_use(e: &std::io::Error) -> bool {
+    const ERROR_ACCESS_DENIED: i32 = 5;
+    const ERROR_SHARING_VIOLATION: i32 = 32;
+    cfg!(windows)
+        && matches!(
+            e.raw_os_error(),
+            Some(ERROR_ACCESS_DENIED | ERROR_SHARING_VIOLATION)
+        )
+}
+
+#[derive(Debug, PartialEq, Eq)]
+enum Swap {
+    Replaced,
+    /// Left as a pending update, applied on the next launch
+    Staged,
+}
+
+/// Atomically move a staged binary over `target`
+fn swap_into_place(staged: &Path, target: &Path) -> Result<Swap, String> {
+    match std::fs::rename(staged, target) {
+        Ok(()) => Ok(Swap::Replaced),
+        Err(e) if is_in_use(&e) => {
+            std::fs::rename(staged, pending_path(target)).map_err(|e| {
+                let _ = std::fs::remove_file(staged);
+                format!("Failed to stage update for {}: {}", target.display(), e)
+            })?;
+            Ok(Swap::Staged)
+        }
+        Err(e) => {
+            let _ = std::fs::remove_file(staged);
+            Err(format!("Failed to replace {}: {}", target.display(), e))
+        }
+    }
+}
+
+/// Finish an update a previous sync had to leave pending. Pending copies that
+/// no longer match this version's manifest are dropped.
+fn apply_pending_update(app: &AppHandle, target: &Path) -> Result<(), String> {
+    let pending = pending_path(target);
+    if !pending.exists() {
+        return Ok(());
+    }
+    if let Some(manifest) = read_manifest(app)
+        && let Err(e) = check_against(app, &pending, &manifest)
+    {
+        let _ = std::fs::remove_file(&pending);
+        return Err(format!("Discarded pending update: {}", e));
+    }
+    match swap_into_place(&pending, target)? {
+        Swap::Replaced => {
+            println!("Applied pending update to {}", target.display());
+            Ok(())
+        }
+        Swap::Staged => Err(format!("{} is still in use", target.display())),
+    }
+}
+
+/// Restore the sidecar from the installed CLI, which `sync_cli` copied from a
+/// verified sidecar
+pub fn resync_sidecar(app: &AppHandle) -> Result<(), String> {
+    let manifest = read_manifest(app)
+        .ok_or_else(|| "No sidecar manifest to restore from, reinstall OpenCode".to_string())?;
+    let cli_path =
+        get_cli_install_path().ok_or_else(|| "Could not determine CLI install path".to_string())?;
+    check_against(app, &cli_path, &manifest)
+        .map_err(|e| format!("Installed CLI can't be used to restore the sidecar: {}", e))?;
+
+    let sidecar = get_sidecar_binary_path(app);
+    let staged = stage_copy(app, &cli_path, &sidecar, &manifest)?;
+    if swap_into_place(&staged, &sidecar)? == Swap::Staged {
+        return Err("The sidecar is in use, restart OpenCode to finish restoring it".to_string());
+    }
+
+    verify_sidecar(app)
+}
+
 fn is_cli_installed() -> bool {
     get_cli_install_path()
         .map(|path| path.exists())
@@ -51,19 +290,27 @@ fn is_cli_installed() -> bool {
 const INSTALL_SCRIPT: &str = include_str!("../../../../install");
 
 #[tauri::command]
-pub fn install_cli(app: tauri::AppHandle) -> Result<String, String> {
+pub fn install_cli(app: tauri::AppHandle, webview: Webview) -> Result<String, AppError> {
+    let result = install(&app);
+    audit::record(&webview, "install_cli", Value::Null, &result);
+    result
+}
+
+fn install(app: &AppHandle) -> Result<String, AppError> {
     if cfg!(not(unix)) {
-        return Err("CLI installation is only supported on macOS & Linux".to_string());
+        return Err(AppError::unavailable(
+            "CLI installation is only supported on macOS & Linux",
+        ));
     }
 
-    let sidecar = get_sidecar_path(&app);
+    let sidecar = get_sidecar_path(app);
     if !sidecar.exists() {
-        return Err("Sidecar binary not found".to_string());
+        return Err(AppError::not_found("Sidecar binary not found"));
     }
 
     let temp_script = std::env::temp_dir().join("opencode-install.sh");
     std::fs::write(&temp_script, INSTALL_SCRIPT)
-        .map_err(|e| format!("Failed to write install script: {}", e))?;
+        .map_err(|e| AppError::io(format!("Failed to write install script: {}", e)))?;
 
     #[cfg(unix)]
     {
@@ -82,7 +329,7 @@ pub fn install_cli(app: tauri::AppHandle) -> Result<String, String> {
 
     if !output.status.success() {
         let stderr = String::from_utf8_lossy(&output.stderr);
-        return Err(format!("Install script failed: {}", stderr));
+        return Err(format!("Install script failed: {}", stderr).into());
     }
 
     let install_path =
@@ -91,20 +338,57 @@ pub fn install_cli(app: tauri::AppHandle) -> Result<String, String> {
     Ok(install_path.to_string_lossy().to_string())
 }
 
-pub fn sync_cli(app: tauri::AppHandle) -> Result<(), String> {
+/// What a CLI sync ended up doing
+#[derive(Debug, Clone, serde::Serialize)]
+#[serde(
+    tag = "type",
+    rename_all = "camelCase",
+    rename_all_fields = "camelCase"
+)]
+pub enum SyncOutcome {
+    Skipped {
+        reason: String,
+    },
+    UpToDate {
+        version: String,
+    },
+    Synced {
+        version: String,
+        previous_version: String,
+    },
+    /// The installed CLI was in use; the update is applied on the next launch
+    Pending {
+        version: String,
+    },
+}
+
+pub fn sync_cli(app: tauri::AppHandle, on_progress: impl Fn(&str)) -> Result<SyncOutcome, String> {
     if cfg!(debug_assertions) {
         println!("Skipping CLI sync for debug build");
-        return Ok(());
+        return Ok(SyncOutcome::Skipped {
+            reason: "Debug build".to_string(),
+        });
+    }
+
+    if let Err(e) = record_sidecar_manifest(&app) {
+        eprintln!("Failed to record sidecar manifest: {}", e);
     }
 
     if !is_cli_installed() {
         println!("No CLI installation found, skipping sync");
-        return Ok(());
+        return Ok(SyncOutcome::Skipped {
+            reason: "CLI is not installed".to_string(),
+        });
     }
 
     let cli_path =
         get_cli_install_path().ok_or_else(|| "Could not determine CLI install path".to_string())?;
 
+    on_progress("Checking installed CLI");
+    if let Err(e) = apply_pending_update(&app, &cli_path) {
+        eprintln!("Failed to apply pending CLI update: {}", e);
+    }
+
     let output = std::process::Command::new(&cli_path)
         .arg("--version")
         .output()
@@ -125,7 +409,9 @@ pub fn sync_cli(app: tauri::AppHandle) -> Result<(), String> {
             "CLI version {} is up to date (app version: {}), skipping sync",
             cli_version, app_version
         );
-        return Ok(());
+        return Ok(SyncOutcome::UpToDate {
+            version: cli_version.to_string(),
+        });
     }
 
     println!(
@@ -133,22 +419,52 @@ pub fn sync_cli(app: tauri::AppHandle) -> Result<(), String> {
         cli_version, app_version
     );
 
-    install_cli(app)?;
+    // Copy to a temp path and swap it in, so a sync that's interrupted or runs
+    // while the CLI is serving never leaves a half-written binary behind
+    let sidecar = get_sidecar_binary_path(&app);
+    let manifest = match read_manifest(&app) {
+        Some(manifest) => manifest,
+        None => fingerprint(&app, &sidecar)?,
+    };
+    on_progress("Copying CLI");
+    let staged = stage_copy(&app, &sidecar, &cli_path, &manifest)?;
+    on_progress("Installing CLI");
+    if swap_into_place(&staged, &cli_path)? == Swap::Staged {
+        println!("CLI is in use, update will be applied on next launch");
+        return Ok(SyncOutcome::Pending {
+            version: app_version.to_string(),
+        });
+    }
 
     println!("Synced installed CLI");
 
-    Ok(())
+    Ok(SyncOutcome::Synced {
+        version: app_version.to_string(),
+        previous_version: cli_version.to_string(),
+    })
 }
 
 fn get_user_shell() -> String {
     std::env::var("SHELL").unwrap_or_else(|_| "/bin/sh".to_string())
 }
 
-pub fn create_command(app: &tauri::AppHandle, args: &str) -> Command {
+/// Environment the sidecar is given on top of the inherited one
+pub fn sidecar_env(app: &AppHandle) -> [(&'static str, std::ffi::OsString); 3] {
     let state_dir = app
         .path()
         .resolve("", BaseDirectory::AppLocalData)
         .expect("Failed to resolve app local data dir");
+    [
+        ("OPENCODE_EXPERIMENTAL_ICON_DISCOVERY", "true".into()),
+        ("OPENCODE_CLIENT", "desktop".into()),
+        ("XDG_STATE_HOME", state_dir.into_os_string()),
+    ]
+}
+
+pub fn create_command(app: &tauri::AppHandle, args: &str) -> Command {
+    if let Some(mock) = test_mode::mock_sidecar() {
+        return binary_command(app, &mock, args);
+    }
 
     #[cfg(target_os = "windows")]
     return app
@@ -156,26 +472,52 @@ pub fn create_command(app: &tauri::AppHandle, args: &str) -> Command {
         .sidecar("opencode-cli")
         .unwrap()
         .args(args.split_whitespace())
-        .env("OPENCODE_EXPERIMENTAL_ICON_DISCOVERY", "true")
-        .env("OPENCODE_CLIENT", "desktop")
-        .env("XDG_STATE_HOME", &state_dir);
+        .envs(sidecar_env(app));
 
     #[cfg(not(target_os = "windows"))]
-    return {
-        let sidecar = get_sidecar_path(app);
-        let shell = get_user_shell();
-
-        let cmd = if shell.ends_with("/nu") {
-            format!("^\"{}\" {}", sidecar.display(), args)
-        } else {
-            format!("\"{}\" {}", sidecar.display(), args)
-        };
-
-        app.shell()
-            .command(&shell)
-            .env("OPENCODE_EXPERIMENTAL_ICON_DISCOVERY", "true")
-            .env("OPENCODE_CLIENT", "desktop")
-            .env("XDG_STATE_HOME", &state_dir)
-            .args(["-il", "-c", &cmd])
+    return login_shell_command(app, &get_sidecar_path(app), args);
+}
+
+/// Run `sidecar` through the user's login shell, for the environment they
+/// set up there
+#[cfg(not(target_os = "windows"))]
+fn login_shell_command(app: &AppHandle, sidecar: &Path, args: &str) -> Command {
+    let shell = get_user_shell();
+
+    let cmd = if shell.ends_with("/nu") {
+        format!("^\"{}\" {}", sidecar.display(), args)
+    } else {
+        format!("\"{}\" {}", sidecar.display(), args)
     };
+
+    app.shell()
+        .command(&shell)
+        .envs(sidecar_env(app))
+        .args(["-il", "-c", &cmd])
+}
+
+/// Run `binary` directly
+fn binary_command(app: &AppHandle, binary: &Path, args: &str) -> Command {
+    app.shell()
+        .command(binary)
+        .args(args.split_whitespace())
+        .envs(sidecar_env(app))
+}
+
+/// `create_command` for either copy of the sidecar
+pub fn create_command_for(
+    app: &AppHandle,
+    sidecar: Sidecar,
+    args: &str,
+) -> Result<Command, String> {
+    if sidecar == Sidecar::Primary {
+        return Ok(create_command(app, args));
+    }
+    let binary = sidecar.binary_path(app)?;
+
+    #[cfg(target_os = "windows")]
+    return Ok(binary_command(app, &binary, args));
+
+    #[cfg(not(target_os = "windows"))]
+    return Ok(login_shell_command(app, &binary, args));
 }
@@ -0,0 +1,298 @@
+//! When the installed CLI is synced with the bundled one.
+//!
+//! The sync policy decides whether it happens on every launch, at most once a
+//! day or only on request through `cli_sync_now`. Every run reports progress
+//! and its result as `cli:sync` events, so failures show up in the UI.
+
+use serde::{Deserialize, Serialize};
+use std::{
+    sync::Mutex,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+use tauri::{AppHandle, Emitter, Manager, Webview};
+use tauri_plugin_store::StoreExt;
+
+use crate::{
+    SETTINGS_STORE, atomic_store, audit, changelog,
+    cli::{self, SyncOutcome},
+    error::AppError,
+    i18n,
+    release_channel::{self, Channel},
+    transfers::{self, TransferKind},
+};
+
+const SYNC_POLICY_KEY: &str = "cliSyncPolicy";
+const LAST_SYNC_KEY: &str = "cliLastSync";
+const DAY_SECS: u64 = 24 * 60 * 60;
+/// How often the daily policy checks whether a day has passed
+const DAILY_CHECK_INTERVAL: Duration = Duration::from_secs(60 * 60);
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum SyncPolicy {
+    #[default]
+    OnLaunch,
+    Daily,
+    Never,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(tag = "type", rename_all = "camelCase")]
+pub enum CliSyncEvent {
+    Started,
+    Progress { message: String },
+    Finished { outcome: SyncOutcome },
+    Failed { error: String },
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CliSyncStatus {
+    pub policy: SyncPolicy,
+    pub running: bool,
+    /// Unix seconds of the last sync that didn't fail
+    pub last_synced_at: Option<u64>,
+    pub last_result: Option<CliSyncEvent>,
+}
+
+#[derive(Default)]
+struct CliSync {
+    running: bool,
+    /// Whether the daily schedule loop is running
+    daily_scheduled: bool,
+    last_result: Option<CliSyncEvent>,
+}
+
+#[derive(Default)]
+pub struct CliSyncState(Mutex<CliSync>);
+
+fn now_secs() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0)
+}
+
+/// On launch for stable; other channels sync only on request, so a beta
+/// doesn't replace the CLI stable installed
+fn default_policy(app: &AppHandle) -> SyncPolicy {
+    match release_channel::current(app) {
+        Channel::Stable => SyncPolicy::OnLaunch,
+        Channel::Beta | Channel::Dev => SyncPolicy::Never,
+    }
+}
+
+fn load_policy(app: &AppHandle) -> SyncPolicy {
+    app.store(SETTINGS_STORE)
+        .ok()
+        .and_then(|store| store.get(SYNC_POLICY_KEY))
+        .and_then(|value| serde_json::from_value(value).ok())
+        .unwrap_or_else(|| default_policy(app))
+}
+
+fn last_synced_at(app: &AppHandle) -> Option<u64> {
+    app.store(SETTINGS_STORE)
+        .ok()
+        .and_then(|store| store.get(LAST_SYNC_KEY))
+        .and_then(|value| value.as_u64())
+}
+
+fn save_last_synced_at(app: &AppHandle, secs: u64) -> Result<(), String> {
+    let store = app
+        .store(SETTINGS_STORE)
+        .map_err(|e| format!("Failed to open settings store: {}", e))?;
+    store.set(LAST_SYNC_KEY, serde_json::json!(secs));
+    atomic_store::save(app, SETTINGS_STORE).map_err(|e| format!("Failed to save settings: {}", e))
+}
+
+/// Whether the daily policy is due, given the last sync time
+fn is_due(last_synced_at: Option<u64>, now: u64) -> bool {
+    last_synced_at.is_none_or(|last| now.saturating_sub(last) >= DAY_SECS)
+}
+
+fn emit(app: &AppHandle, event: CliSyncEvent) {
+    let _ = app.emit("cli:sync", event);
+}
+
+/// Run one sync, unless one is already running
+async fn run(app: &AppHandle) -> Result<SyncOutcome, String> {
+    let state = app
+        .try_state::<CliSyncState>()
+        .ok_or("CLI sync state not found")?;
+    {
+        let mut sync = state.0.lock().map_err(|e| format!("Lock error: {}", e))?;
+        if sync.running {
+            return Err("A CLI sync is already running".to_string());
+        }
+        sync.running = true;
+    }
+    emit(app, CliSyncEvent::Started);
+    let transfer = transfers::track(app, TransferKind::Copy, i18n::t(app, "transfer.cliSync"));
+
+    let sync_app = app.clone();
+    let result = tauri::async_runtime::spawn_blocking(move || {
+        let progress_app = sync_app.clone();
+        cli::sync_cli(sync_app, move |message| {
+            emit(
+                &progress_app,
+                CliSyncEvent::Progress {
+                    message: message.to_string(),
+                },
+            )
+        })
+    })
+    .await
+    .map_err(|e| format!("CLI sync task failed: {}", e))
+    .and_then(|result| result);
+
+    if let Ok(id) = &transfer {
+        let error = result.as_ref().err().map(|e| AppError::from(e.clone()));
+        transfers::finish(app, id, error.as_ref());
+    }
+    let event = match &result {
+        Ok(outcome) => {
+            if let Err(e) = save_last_synced_at(app, now_secs()) {
+                eprintln!("Failed to record CLI sync time: {}", e);
+            }
+            if let SyncOutcome::Synced {
+                version,
+                previous_version,
+            } = outcome
+            {
+                let app = app.clone();
+                let (version, previous_version) = (version.clone(), previous_version.clone());
+                tauri::async_runtime::spawn(async move {
+                    if let Err(e) =
+                        changelog::announce_update(&app, "cli", &previous_version, &version).await
+                    {
+                        eprintln!("Failed to announce CLI update: {}", e);
+                    }
+                });
+            }
+            CliSyncEvent::Finished {
+                outcome: outcome.clone(),
+            }
+        }
+        Err(error) => {
+            eprintln!("Failed to sync CLI: {}", error);
+            CliSyncEvent::Failed {
+                error: error.clone(),
+            }
+        }
+    };
+    if let Ok(mut sync) = state.0.lock() {
+        sync.running = false;
+        sync.last_result = Some(event.clone());
+    }
+    emit(app, event);
+    result
+}
+
+/// Mark the daily schedule as running or stopped. Returns false if it was
+/// already in that state.
+fn set_daily_scheduled(app: &AppHandle, scheduled: bool) -> bool {
+    let Some(state) = app.try_state::<CliSyncState>() else {
+        return false;
+    };
+    let Ok(mut sync) = state.0.lock() else {
+        return false;
+    };
+    let changed = sync.daily_scheduled != scheduled;
+    sync.daily_scheduled = scheduled;
+    changed
+}
+
+/// Schedule syncs according to the policy, for the lifetime of the app
+pub fn start(app: &AppHandle) {
+    let app = app.clone();
+    tauri::async_runtime::spawn(async move {
+        match load_policy(&app) {
+            SyncPolicy::OnLaunch => {
+                let _ = run(&app).await;
+            }
+            SyncPolicy::Never => {}
+            SyncPolicy::Daily => {
+                if !set_daily_scheduled(&app, true) {
+                    return;
+                }
+                // The policy can change while the app is running
+                while load_policy(&app) == SyncPolicy::Daily {
+                    if is_due(last_synced_at(&app), now_secs()) {
+                        let _ = run(&app).await;
+                    }
+                    tokio::time::sleep(DAILY_CHECK_INTERVAL).await;
+                }
+                set_daily_scheduled(&app, false);
+            }
+        }
+    });
+}
+
+/// Sync the installed CLI right away, regardless of the policy
+#[tauri::command]
+pub async fn cli_sync_now(app: AppHandle, webview: Webview) -> Result<SyncOutcome, AppError> {
+    let result = run(&app).await.map_err(AppError::from);
+    audit::record(&webview, "cli_sync_now", serde_json::Value::Null, &result);
+    result
+}
+
+#[tauri::command]
+pub fn get_cli_sync_status(app: AppHandle) -> Result<CliSyncStatus, AppError> {
+    let state = app
+        .try_state::<CliSyncState>()
+        .ok_or("CLI sync state not found")?;
+    let sync = state.0.lock().map_err(|e| format!("Lock error: {}", e))?;
+    Ok(CliSyncStatus {
+        policy: load_policy(&app),
+        running: sync.running,
+        last_synced_at: last_synced_at(&app),
+        last_result: sync.last_result.clone(),
+    })
+}
+
+/// Change the sync policy. Switching to daily starts the daily schedule
+/// right away if it isn't running yet.
+#[tauri::command]
+pub fn set_cli_sync_policy(
+    app: AppHandle,
+    webview: Webview,
+    policy: SyncPolicy,
+) -> Result<(), AppError> {
+    let result = save_policy(&app, policy);
+    audit::record(
+        &webview,
+        "set_cli_sync_policy",
+        serde_json::json!({ "policy": policy }),
+        &result,
+    );
+    result
+}
+
+fn save_policy(app: &AppHandle, policy: SyncPolicy) -> Result<(), AppError> {
+    let store = app
+        .store(SETTINGS_STORE)
+        .map_err(|e| format!("Failed to open settings store: {}", e))?;
+    store.set(SYNC_POLICY_KEY, serde_json::json!(policy));
+    atomic_store::save(app, SETTINGS_STORE)
+        .map_err(|e| format!("Failed to save settings: {}", e))?;
+
+    if policy == SyncPolicy::Daily {
+        start(app);
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_daily_sync_is_due_after_a_day() {
+        let now = 10 * DAY_SECS;
+        assert!(is_due(None, now));
+        assert!(is_due(Some(now - DAY_SECS), now));
+        assert!(!is_due(Some(now - DAY_SECS + 1), now));
+        // Clock moved backwards
+        assert!(!is_due(Some(now + 60), now));
+    }
+}
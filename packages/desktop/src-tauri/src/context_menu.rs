@@ -0,0 +1,266 @@
+//! Native context menus built by the frontend.
+//!
+//! `show_context_menu` pops up a native menu at the cursor over the calling
+//! window and resolves to the id of the item picked. Items can be nested in
+//! submenus, checked and given an accelerator, shown as a hint only:
+//!
+//! ```json
+//! [{ "type": "item", "id": "copy", "text": "Copy", "accelerator": "CmdOrCtrl+C" },
+//!  { "type": "separator" },
+//!  { "type": "check", "id": "wrap", "text": "Wrap lines", "checked": true },
+//!  { "type": "submenu", "text": "Copy as", "items": [
+//!    { "type": "item", "id": "copy-markdown", "text": "Markdown" }] }]
+//! ```
+//!
+//! Native menus don't report being dismissed, so a menu closed without a
+//! pick resolves to `null` when the next menu opens, or after a while.
+
+use serde::Deserialize;
+use std::{
+    sync::{
+        Mutex,
+        atomic::{AtomicU64, Ordering},
+    },
+    time::Duration,
+};
+use tauri::{
+    AppHandle, Manager, Webview, Wry,
+    menu::{CheckMenuItem, IsMenuItem, Menu, MenuEvent, MenuItem, PredefinedMenuItem, Submenu},
+};
+use tokio::sync::oneshot;
+
+use crate::error::AppError;
+
+/// How long an open menu is waited on before it counts as dismissed
+const MENU_TIMEOUT: Duration = Duration::from_secs(120);
+/// Deepest nesting of submenus accepted
+const MAX_DEPTH: usize = 8;
+/// Ids given to the native items start with this, to tell them from the
+/// ids of other menus
+const ID_PREFIX: &str = "context-menu:";
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(
+    tag = "type",
+    rename_all = "camelCase",
+    rename_all_fields = "camelCase"
+)]
+pub enum ContextMenuItem {
+    Item {
+        id: String,
+        text: String,
+        #[serde(default = "enabled")]
+        enabled: bool,
+        accelerator: Option<String>,
+    },
+    Check {
+        id: String,
+        text: String,
+        #[serde(default = "enabled")]
+        enabled: bool,
+        #[serde(default)]
+        checked: bool,
+        accelerator: Option<String>,
+    },
+    Submenu {
+        text: String,
+        #[serde(default = "enabled")]
+        enabled: bool,
+        items: Vec<ContextMenuItem>,
+    },
+    Separator,
+}
+
+fn enabled() -> bool {
+    true
+}
+
+struct OpenMenu {
+    serial: u64,
+    picked: oneshot::Sender<Option<String>>,
+}
+
+/// The menu waiting for a pick, if any
+#[derive(Default)]
+pub struct ContextMenuState {
+    open: Mutex<Option<OpenMenu>>,
+    next_serial: AtomicU64,
+}
+
+/// The native id of item `id` of menu `serial`
+fn native_id(serial: u64, id: &str) -> String {
+    format!("{}{}:{}", ID_PREFIX, serial, id)
+}
+
+/// Menu serial and item id of a native id made by `native_id`
+fn parse_native_id(native: &str) -> Option<(u64, &str)> {
+    let (serial, id) = native.strip_prefix(ID_PREFIX)?.split_once(':')?;
+    Some((serial.parse().ok()?, id))
+}
+
+fn build_items(
+    app: &AppHandle,
+    serial: u64,
+    items: &[ContextMenuItem],
+    depth: usize,
+) -> Result<Vec<Box<dyn IsMenuItem<Wry>>>, AppError> {
+    if depth > MAX_DEPTH {
+        return Err(AppError::invalid_input("Context menu is nested too deeply"));
+    }
+    let failed = |e: tauri::Error| AppError::from(format!("Failed to build context menu: {}", e));
+    items
+        .iter()
+        .map(|item| -> Result<Box<dyn IsMenuItem<Wry>>, AppError> {
+            Ok(match item {
+                ContextMenuItem::Item {
+                    id,
+                    text,
+                    enabled,
+                    accelerator,
+                } => Box::new(
+                    MenuItem::with_id(
+                        app,
+                        native_id(serial, id),
+                        text,
+                        *enabled,
+                        accelerator.as_deref(),
+                    )
+                    .map_err(failed)?,
+                ),
+                ContextMenuItem::Check {
+                    id,
+                    text,
+                    enabled,
+                    checked,
+                    accelerator,
+                } => Box::new(
+                    CheckMenuItem::with_id(
+                        app,
+                        native_id(serial, id),
+                        text,
+                        *enabled,
+                        *checked,
+                        accelerator.as_deref(),
+                    )
+                    .map_err(failed)?,
+                ),
+                ContextMenuItem::Submenu {
+                    text,
+                    enabled,
+                    items,
+                } => {
+                    let children = build_items(app, serial, items, depth + 1)?;
+                    let children: Vec<&dyn IsMenuItem<Wry>> =
+                        children.iter().map(|child| child.as_ref()).collect();
+                    Box::new(Submenu::with_items(app, text, *enabled, &children).map_err(failed)?)
+                }
+                ContextMenuItem::Separator => {
+                    Box::new(PredefinedMenuItem::separator(app).map_err(failed)?)
+                }
+            })
+        })
+        .collect()
+}
+
+fn state(app: &AppHandle) -> Result<tauri::State<'_, ContextMenuState>, AppError> {
+    Ok(app
+        .try_state::<ContextMenuState>()
+        .ok_or("Context menu state not found")?)
+}
+
+/// Resolve the open menu with the item picked, for menu events of context
+/// menus
+pub fn handle_menu_event(app: &AppHandle, event: &MenuEvent) {
+    let Some((serial, id)) = parse_native_id(event.id().as_ref()) else {
+        return;
+    };
+    let Ok(state) = state(app) else {
+        return;
+    };
+    let Ok(mut open) = state.open.lock() else {
+        return;
+    };
+    if open.as_ref().is_some_and(|menu| menu.serial == serial)
+        && let Some(menu) = open.take()
+    {
+        let _ = menu.picked.send(Some(id.to_string()));
+    }
+}
+
+fn popup(
+    app: &AppHandle,
+    webview: &Webview,
+    serial: u64,
+    items: &[ContextMenuItem],
+) -> Result<(), AppError> {
+    let children = build_items(app, serial, items, 0)?;
+    let children: Vec<&dyn IsMenuItem<Wry>> = children.iter().map(|child| child.as_ref()).collect();
+    let menu = Menu::with_items(app, &children)
+        .map_err(|e| format!("Failed to build context menu: {}", e))?;
+    webview
+        .window()
+        .popup_menu(&menu)
+        .map_err(|e| format!("Failed to show context menu: {}", e).into())
+}
+
+/// Show a context menu at the cursor and return the id of the item picked,
+/// or `None` if it was dismissed
+#[tauri::command]
+pub async fn show_context_menu(
+    app: AppHandle,
+    webview: Webview,
+    items: Vec<ContextMenuItem>,
+) -> Result<Option<String>, AppError> {
+    if items.is_empty() {
+        return Err(AppError::invalid_input("The context menu has no items"));
+    }
+    let state = state(&app)?;
+    let serial = state.next_serial.fetch_add(1, Ordering::Relaxed);
+    let (picked_tx, picked_rx) = oneshot::channel();
+    {
+        let mut open = state
+            .open
+            .lock()
+            .map_err(|e| format!("Lock error: {}", e))?;
+        // Replacing the previous menu drops its sender, resolving it to None
+        *open = Some(OpenMenu {
+            serial,
+            picked: picked_tx,
+        });
+    }
+    popup(&app, &webview, serial, &items)?;
+
+    let picked = tokio::time::timeout(MENU_TIMEOUT, picked_rx).await;
+    if let Ok(mut open) = state.open.lock()
+        && open.as_ref().is_some_and(|menu| menu.serial == serial)
+    {
+        *open = None;
+    }
+    Ok(picked.ok().and_then(Result::ok).flatten())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_native_ids_round_trip() {
+        let native = native_id(7, "copy:as-markdown");
+        assert_eq!(parse_native_id(&native), Some((7, "copy:as-markdown")));
+        assert_eq!(parse_native_id("quit"), None);
+
+        let items: Vec<ContextMenuItem> = serde_json::from_value(serde_json::json!([
+            { "type": "check", "id": "wrap", "text": "Wrap lines", "checked": true },
+            { "type": "submenu", "text": "Copy as", "items": [{ "type": "separator" }] },
+        ]))
+        .unwrap();
+        assert!(matches!(
+            items[0],
+            ContextMenuItem::Check {
+                enabled: true,
+                checked: true,
+                ..
+            }
+        ));
+    }
+}
@@ -0,0 +1,449 @@
+//! Import of conversation archives from other chat tools.
+//!
+//! `import_conversations` reads the `conversations.json` of a ChatGPT or
+//! Claude data export and recreates each conversation as a session on the
+//! connected server: a session with the conversation's title, and its
+//! messages posted in order without asking the agent for a reply. Replies
+//! of the other tool are posted as text under its name, since the server
+//! only takes messages from the user.
+//!
+//! Conversations already imported into a server are skipped, going by the
+//! ids kept under `importedConversations` in the settings, so an archive can
+//! be imported again after a failure. Progress is sent as `import:progress`.
+
+use serde::Serialize;
+use serde_json::{Map, Value, json};
+use std::time::Duration;
+use tauri::{AppHandle, Emitter, Webview};
+use tauri_plugin_store::StoreExt;
+
+use crate::{SETTINGS_STORE, ServerReadyData, atomic_store, audit, error::AppError, url_origin};
+
+const IMPORTED_KEY: &str = "importedConversations";
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(30);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub enum ArchiveSource {
+    Chatgpt,
+    Claude,
+}
+
+impl ArchiveSource {
+    fn key(self) -> &'static str {
+        match self {
+            ArchiveSource::Chatgpt => "chatgpt",
+            ArchiveSource::Claude => "claude",
+        }
+    }
+
+    fn assistant_name(self) -> &'static str {
+        match self {
+            ArchiveSource::Chatgpt => "ChatGPT",
+            ArchiveSource::Claude => "Claude",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Role {
+    User,
+    Assistant,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct ArchivedMessage {
+    role: Role,
+    text: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Conversation {
+    source: ArchiveSource,
+    id: String,
+    title: String,
+    messages: Vec<ArchivedMessage>,
+}
+
+impl Conversation {
+    /// Identifies the conversation among everything imported into a server
+    fn key(&self) -> String {
+        format!("{}:{}", self.source.key(), self.id)
+    }
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ImportProgress {
+    pub total: usize,
+    pub done: usize,
+    pub imported: usize,
+    /// Imported before
+    pub skipped: usize,
+    pub failed: usize,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ImportSummary {
+    #[serde(flatten)]
+    pub progress: ImportProgress,
+    /// Why conversations failed, by title
+    pub errors: Vec<String>,
+}
+
+fn text_of(value: &Value) -> Option<&str> {
+    value.as_str().filter(|text| !text.trim().is_empty())
+}
+
+/// The branch of a ChatGPT conversation that was last shown, root first
+fn parse_chatgpt(conversation: &Map<String, Value>) -> Option<Conversation> {
+    let mapping = conversation.get("mapping")?.as_object()?;
+    let id = conversation
+        .get("conversation_id")
+        .or_else(|| conversation.get("id"))?
+        .as_str()?;
+
+    let mut messages = Vec::new();
+    let mut node_id = conversation.get("current_node").and_then(Value::as_str);
+    // The mapping is a tree; bounded in case it has a cycle
+    for _ in 0..mapping.len() {
+        let Some(node) = node_id.and_then(|id| mapping.get(id)) else {
+            break;
+        };
+        if let Some(message) = node.get("message") {
+            let role = match message.pointer("/author/role").and_then(Value::as_str) {
+                Some("user") => Some(Role::User),
+                Some("assistant") => Some(Role::Assistant),
+                _ => None,
+            };
+            let text = message
+                .pointer("/content/parts")
+                .and_then(Value::as_array)
+                .map(|parts| {
+                    parts
+                        .iter()
+                        .filter_map(text_of)
+                        .collect::<Vec<_>>()
+                        .join("\n\n")
+                })
+                .filter(|text| !text.is_empty());
+            if let (Some(role), Some(text)) = (role, text) {
+                messages.push(ArchivedMessage { role, text });
+            }
+        }
+        node_id = node.get("parent").and_then(Value::as_str);
+    }
+    messages.reverse();
+
+    Some(Conversation {
+        source: ArchiveSource::Chatgpt,
+        id: id.to_string(),
+        title: conversation
+            .get("title")
+            .and_then(text_of)
+            .unwrap_or("ChatGPT conversation")
+            .to_string(),
+        messages,
+    })
+}
+
+fn parse_claude(conversation: &Map<String, Value>) -> Option<Conversation> {
+    let chat_messages = conversation.get("chat_messages")?.as_array()?;
+    let id = conversation.get("uuid")?.as_str()?;
+
+    let messages = chat_messages
+        .iter()
+        .filter_map(|message| {
+            let role = match message.get("sender")?.as_str()? {
+                "human" => Role::User,
+                "assistant" => Role::Assistant,
+                _ => return None,
+            };
+            let text = match message.get("text").and_then(text_of) {
+                Some(text) => text.to_string(),
+                None => message
+                    .get("content")?
+                    .as_array()?
+                    .iter()
+                    .filter(|part| part.get("type").and_then(Value::as_str) == Some("text"))
+                    .filter_map(|part| part.get("text").and_then(text_of))
+                    .collect::<Vec<_>>()
+                    .join("\n\n"),
+            };
+            (!text.is_empty()).then_some(ArchivedMessage { role, text })
+        })
+        .collect();
+
+    Some(Conversation {
+        source: ArchiveSource::Claude,
+        id: id.to_string(),
+        title: conversation
+            .get("name")
+            .and_then(text_of)
+            .unwrap_or("Claude conversation")
+            .to_string(),
+        messages,
+    })
+}
+
+/// Conversations with messages in an export's `conversations.json`
+fn parse_archive(contents: &str) -> Result<Vec<Conversation>, AppError> {
+    let archive: Value = serde_json::from_str(contents)
+        .map_err(|e| AppError::invalid_input(format!("Not a conversation export: {}", e)))?;
+    let conversations = archive.as_array().ok_or_else(|| {
+        AppError::invalid_input("Not a conversation export: expected a list of conversations")
+    })?;
+    Ok(conversations
+        .iter()
+        .filter_map(Value::as_object)
+        .filter_map(|conversation| {
+            parse_chatgpt(conversation).or_else(|| parse_claude(conversation))
+        })
+        .filter(|conversation| !conversation.messages.is_empty())
+        .collect())
+}
+
+/// Keys of the conversations imported into the server at `origin`, with
+/// the session each became
+fn imported(app: &AppHandle, origin: &str) -> Map<String, Value> {
+    app.store(SETTINGS_STORE)
+        .ok()
+        .and_then(|store| store.get(IMPORTED_KEY))
+        .and_then(|servers| servers.get(origin)?.as_object().cloned())
+        .unwrap_or_default()
+}
+
+fn mark_imported(
+    app: &AppHandle,
+    origin: &str,
+    key: String,
+    session_id: &str,
+) -> Result<(), String> {
+    let store = app
+        .store(SETTINGS_STORE)
+        .map_err(|e| format!("Failed to open settings store: {}", e))?;
+    let mut servers = match store.get(IMPORTED_KEY) {
+        Some(Value::Object(servers)) => servers,
+        _ => Map::new(),
+    };
+    let mut conversations = imported(app, origin);
+    conversations.insert(key, Value::String(session_id.to_string()));
+    servers.insert(origin.to_string(), Value::Object(conversations));
+    store.set(IMPORTED_KEY, Value::Object(servers));
+    atomic_store::save(app, SETTINGS_STORE).map_err(|e| format!("Failed to save settings: {}", e))
+}
+
+struct Server {
+    url: reqwest::Url,
+    password: Option<String>,
+    client: reqwest::Client,
+}
+
+impl Server {
+    fn new(app: &AppHandle, server: &ServerReadyData) -> Result<Self, String> {
+        let url = reqwest::Url::parse(&server.url)
+            .map_err(|e| format!("Invalid server URL {}: {}", server.url, e))?;
+        let client = crate::server_client(app, &url, REQUEST_TIMEOUT)?;
+        Ok(Self {
+            url,
+            password: server.password.clone(),
+            client,
+        })
+    }
+
+    fn request(
+        &self,
+        method: reqwest::Method,
+        segments: &[&str],
+    ) -> Result<reqwest::RequestBuilder, String> {
+        let endpoint = crate::server_endpoint(&self.url, segments)?;
+        let request = self.client.request(method, endpoint);
+        Ok(match &self.password {
+            Some(password) => request.basic_auth("opencode", Some(password)),
+            None => request,
+        })
+    }
+
+    async fn send(&self, request: reqwest::RequestBuilder) -> Result<Value, String> {
+        let response = request
+            .send()
+            .await
+            .map_err(|e| format!("Request to the server failed: {}", e))?;
+        if !response.status().is_success() {
+            return Err(format!("The server refused: HTTP {}", response.status()));
+        }
+        response
+            .json()
+            .await
+            .map_err(|e| format!("Invalid response from the server: {}", e))
+    }
+
+    async fn create_session(&self, title: &str) -> Result<String, String> {
+        let request = self
+            .request(reqwest::Method::POST, &["session"])?
+            .json(&json!({ "title": title }));
+        let session = self.send(request).await?;
+        session
+            .get("id")
+            .and_then(Value::as_str)
+            .map(String::from)
+            .ok_or_else(|| "The server didn't return a session id".to_string())
+    }
+
+    async fn post_message(&self, session_id: &str, text: &str) -> Result<(), String> {
+        let request = self
+            .request(reqwest::Method::POST, &["session", session_id, "message"])?
+            .json(&json!({
+                "noReply": true,
+                "parts": [{ "type": "text", "text": text }],
+            }));
+        self.send(request).await.map(|_| ())
+    }
+
+    async fn delete_session(&self, session_id: &str) {
+        if let Ok(request) = self.request(reqwest::Method::DELETE, &["session", session_id]) {
+            let _ = request.send().await;
+        }
+    }
+
+    async fn import(&self, conversation: &Conversation) -> Result<String, String> {
+        let session_id = self.create_session(&conversation.title).await?;
+        for message in &conversation.messages {
+            let text = match message.role {
+                Role::User => message.text.clone(),
+                Role::Assistant => format!(
+                    "**{}:**\n\n{}",
+                    conversation.source.assistant_name(),
+                    message.text
+                ),
+            };
+            if let Err(e) = self.post_message(&session_id, &text).await {
+                // Half a conversation would be skipped on the next import
+                self.delete_session(&session_id).await;
+                return Err(e);
+            }
+        }
+        Ok(session_id)
+    }
+}
+
+async fn import(app: &AppHandle, path: &str) -> Result<ImportSummary, AppError> {
+    let contents = std::fs::read_to_string(path)
+        .map_err(|e| AppError::io(format!("Failed to read {}: {}", path, e)))?;
+    let conversations = parse_archive(&contents)?;
+
+    let server_data = crate::connected_server(app).await?;
+    let server = Server::new(app, &server_data)?;
+    let origin = url_origin(&server.url);
+    let already = imported(app, &origin);
+
+    let mut progress = ImportProgress {
+        total: conversations.len(),
+        ..Default::default()
+    };
+    let mut errors = Vec::new();
+    for conversation in &conversations {
+        let key = conversation.key();
+        if already.contains_key(&key) {
+            progress.skipped += 1;
+        } else {
+            match server.import(conversation).await {
+                Ok(session_id) => {
+                    mark_imported(app, &origin, key, &session_id)?;
+                    progress.imported += 1;
+                }
+                Err(e) => {
+                    progress.failed += 1;
+                    errors.push(format!("{}: {}", conversation.title, e));
+                }
+            }
+        }
+        progress.done += 1;
+        let _ = app.emit("import:progress", &progress);
+    }
+
+    Ok(ImportSummary { progress, errors })
+}
+
+/// Import the conversations of a ChatGPT or Claude export into the
+/// connected server. `path` is the export's `conversations.json`.
+#[tauri::command]
+pub async fn import_conversations(
+    app: AppHandle,
+    webview: Webview,
+    path: String,
+) -> Result<ImportSummary, AppError> {
+    let result = import(&app, &path).await;
+    audit::record(
+        &webview,
+        "import_conversations",
+        json!({ "path": path }),
+        &result,
+    );
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_chatgpt_follows_current_branch() {
+        let archive = json!([{
+            "title": "Rust lifetimes",
+            "conversation_id": "c1",
+            "current_node": "n3",
+            "mapping": {
+                "root": { "message": null, "parent": null },
+                "n1": {
+                    "message": { "author": { "role": "user" }, "content": { "parts": ["Why?"] } },
+                    "parent": "root"
+                },
+                "n2": {
+                    "message": { "author": { "role": "assistant" }, "content": { "parts": ["Old answer"] } },
+                    "parent": "n1"
+                },
+                "n3": {
+                    "message": { "author": { "role": "assistant" }, "content": { "parts": ["Because"] } },
+                    "parent": "n1"
+                }
+            }
+        }]);
+        let conversations = parse_archive(&archive.to_string()).unwrap();
+        assert_eq!(conversations.len(), 1);
+        assert_eq!(conversations[0].key(), "chatgpt:c1");
+        assert_eq!(
+            conversations[0].messages,
+            vec![
+                ArchivedMessage {
+                    role: Role::User,
+                    text: "Why?".to_string()
+                },
+                ArchivedMessage {
+                    role: Role::Assistant,
+                    text: "Because".to_string()
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_parse_claude() {
+        let archive = json!([
+            {
+                "uuid": "u1",
+                "name": "",
+                "chat_messages": [
+                    { "sender": "human", "text": "Hello" },
+                    { "sender": "assistant", "text": "", "content": [{ "type": "text", "text": "Hi" }] }
+                ]
+            },
+            { "uuid": "u2", "name": "Empty", "chat_messages": [] }
+        ]);
+        let conversations = parse_archive(&archive.to_string()).unwrap();
+        assert_eq!(conversations.len(), 1);
+        assert_eq!(conversations[0].title, "Claude conversation");
+        assert_eq!(conversations[0].messages[1].text, "Hi");
+    }
+}
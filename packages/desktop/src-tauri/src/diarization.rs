@@ -0,0 +1,455 @@
+//! Speaker diarization for transcripts using a WeSpeaker ResNet34 ONNX model.
+//!
+//! Each transcript segment long enough to carry a voice print is turned into
+//! a speaker embedding, the embeddings are grouped with agglomerative
+//! clustering, and segments are labelled "Speaker 1", "Speaker 2", ... in
+//! order of first appearance. Runs on the ONNX Runtime shared with STT.
+
+use ort::{session::Session, value::TensorRef};
+use rustfft::{FftPlanner, num_complex::Complex};
+use serde::Serialize;
+use std::{
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
+};
+use tauri::{AppHandle, Emitter, Manager, path::BaseDirectory};
+
+use crate::{
+    error::AppError,
+    onnx_runtime,
+    stt::{ModelStatus, TranscriptSegment, download_model_files},
+};
+
+const MODEL_NAME: &str = "wespeaker-voxceleb-resnet34-LM";
+const HF_BASE_URL: &str =
+    "https://huggingface.co/Wespeaker/wespeaker-voxceleb-resnet34-LM/resolve/main";
+
+/// (remote path, local file name)
+const MODEL_FILES: &[(&str, &str)] = &[("voxceleb_resnet34_LM.onnx", "speaker.onnx")];
+
+const SAMPLE_RATE: usize = 16_000;
+/// Kaldi-style fbank: 25 ms windows every 10 ms, 80 mel bins
+const FRAME_LENGTH: usize = 400;
+const FRAME_SHIFT: usize = 160;
+const FFT_SIZE: usize = 512;
+const NUM_MEL_BINS: usize = 80;
+const MEL_LOW_FREQ: f32 = 20.0;
+const PREEMPHASIS: f32 = 0.97;
+
+/// Segments shorter than this don't produce a reliable embedding and inherit
+/// the speaker of their nearest neighbour instead
+const MIN_SEGMENT_SECONDS: f32 = 1.0;
+/// Clusters stop merging once their average cosine similarity drops below this
+const SAME_SPEAKER_THRESHOLD: f32 = 0.5;
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DiarizationStatus {
+    pub model_status: ModelStatus,
+}
+
+pub struct DiarizationState {
+    session: Option<Arc<Mutex<Session>>>,
+    model_status: ModelStatus,
+    model_dir: PathBuf,
+}
+
+impl DiarizationState {
+    pub fn new(model_dir: PathBuf) -> Self {
+        let mut state = Self {
+            session: None,
+            model_status: ModelStatus::NotDownloaded,
+            model_dir,
+        };
+
+        if Self::are_models_downloaded(&state.model_dir)
+            && let Err(e) = state.load_models()
+        {
+            state.model_status = ModelStatus::Error { message: e };
+        }
+
+        state
+    }
+
+    fn are_models_downloaded(model_dir: &Path) -> bool {
+        MODEL_FILES
+            .iter()
+            .all(|(_, file)| model_dir.join(file).exists())
+    }
+
+    pub fn get_status(&self) -> DiarizationStatus {
+        DiarizationStatus {
+            model_status: self.model_status.clone(),
+        }
+    }
+
+    pub fn load_models(&mut self) -> Result<(), String> {
+        if !Self::are_models_downloaded(&self.model_dir) {
+            return Err("Models not downloaded".to_string());
+        }
+
+        let session = onnx_runtime::build_session(&self.model_dir.join("speaker.onnx"), "speaker")?;
+
+        self.session = Some(Arc::new(Mutex::new(session)));
+        self.model_status = ModelStatus::Ready;
+        Ok(())
+    }
+
+    pub fn embedder(&self) -> Result<SpeakerEmbedder, String> {
+        if !matches!(self.model_status, ModelStatus::Ready) {
+            return Err(
+                "Diarization model not ready. Please download the model first.".to_string(),
+            );
+        }
+        Ok(SpeakerEmbedder {
+            session: self
+                .session
+                .as_ref()
+                .ok_or("Speaker model not loaded")?
+                .clone(),
+        })
+    }
+}
+
+pub struct SpeakerEmbedder {
+    session: Arc<Mutex<Session>>,
+}
+
+impl SpeakerEmbedder {
+    fn embed(&self, audio: &[f32]) -> Result<Vec<f32>, String> {
+        let feats = fbank(audio);
+        let num_frames = feats.len() / NUM_MEL_BINS;
+        let feats = ndarray::ArrayView3::from_shape((1, num_frames, NUM_MEL_BINS), &feats)
+            .map_err(|e| format!("Failed to create fbank array: {}", e))?;
+        let feats_tensor = TensorRef::from_array_view(feats)
+            .map_err(|e| format!("Failed to create fbank tensor: {}", e))?;
+
+        let mut session = self
+            .session
+            .lock()
+            .map_err(|e| format!("Lock error: {}", e))?;
+        let outputs = session
+            .run(ort::inputs![feats_tensor])
+            .map_err(|e| format!("Failed to run speaker model: {}", e))?;
+        let (_, embedding) = outputs[0]
+            .try_extract_tensor::<f32>()
+            .map_err(|e| format!("Failed to extract speaker embedding: {}", e))?;
+
+        let mut embedding = embedding.to_vec();
+        normalize(&mut embedding);
+        Ok(embedding)
+    }
+
+    /// Fill in `speaker` for every segment. `audio` is the 16 kHz recording
+    /// the segments were transcribed from.
+    pub fn label_speakers(
+        &self,
+        audio: &[f32],
+        segments: &mut [TranscriptSegment],
+        max_speakers: Option<usize>,
+    ) -> Result<(), String> {
+        let mut embedded = Vec::new();
+        let mut embeddings = Vec::new();
+        for (index, segment) in segments.iter().enumerate() {
+            if segment.end - segment.start < MIN_SEGMENT_SECONDS {
+                continue;
+            }
+            let start = ((segment.start * SAMPLE_RATE as f32) as usize).min(audio.len());
+            let end = ((segment.end * SAMPLE_RATE as f32) as usize).min(audio.len());
+            if end - start < FRAME_LENGTH {
+                continue;
+            }
+            embeddings.push(self.embed(&audio[start..end])?);
+            embedded.push(index);
+        }
+
+        // Too little speech to tell voices apart: treat it as one speaker
+        let clusters = if embeddings.is_empty() {
+            Vec::new()
+        } else {
+            cluster(&embeddings, max_speakers)
+        };
+
+        let mut speakers: Vec<Option<usize>> = vec![None; segments.len()];
+        for (&index, &cluster) in embedded.iter().zip(&clusters) {
+            speakers[index] = Some(cluster);
+        }
+        let speakers = fill_from_neighbours(&speakers);
+
+        // Number speakers by first appearance
+        let mut order: Vec<usize> = Vec::new();
+        for (segment, speaker) in segments.iter_mut().zip(speakers) {
+            let position = match order.iter().position(|&c| c == speaker) {
+                Some(position) => position,
+                None => {
+                    order.push(speaker);
+                    order.len() - 1
+                }
+            };
+            segment.speaker = Some(format!("Speaker {}", position + 1));
+        }
+
+        Ok(())
+    }
+}
+
+fn normalize(vector: &mut [f32]) {
+    let norm = vector.iter().map(|v| v * v).sum::<f32>().sqrt();
+    if norm > 0.0 {
+        vector.iter_mut().for_each(|v| *v /= norm);
+    }
+}
+
+fn mel_scale(freq: f32) -> f32 {
+    1127.0 * (1.0 + freq / 700.0).ln()
+}
+
+/// Triangular mel filters over the `FFT_SIZE / 2 + 1` power spectrum bins
+fn mel_filters() -> Vec<Vec<f32>> {
+    let nyquist = SAMPLE_RATE as f32 / 2.0;
+    let mel_low = mel_scale(MEL_LOW_FREQ);
+    let mel_high = mel_scale(nyquist);
+    let mel_step = (mel_high - mel_low) / (NUM_MEL_BINS + 1) as f32;
+    let bin_width = SAMPLE_RATE as f32 / FFT_SIZE as f32;
+
+    (0..NUM_MEL_BINS)
+        .map(|m| {
+            let left = mel_low + m as f32 * mel_step;
+            let center = left + mel_step;
+            let right = center + mel_step;
+            (0..FFT_SIZE / 2 + 1)
+                .map(|bin| {
+                    let mel = mel_scale(bin as f32 * bin_width);
+                    if mel > left && mel < right {
+                        if mel <= center {
+                            (mel - left) / (center - left)
+                        } else {
+                            (right - mel) / (right - center)
+                        }
+                    } else {
+                        0.0
+                    }
+                })
+                .collect()
+        })
+        .collect()
+}
+
+/// Kaldi-compatible log mel filterbank with per-utterance mean normalization,
+/// flattened as `[frames, NUM_MEL_BINS]`
+fn fbank(audio: &[f32]) -> Vec<f32> {
+    if audio.len() < FRAME_LENGTH {
+        return Vec::new();
+    }
+    let num_frames = 1 + (audio.len() - FRAME_LENGTH) / FRAME_SHIFT;
+    let filters = mel_filters();
+    let window: Vec<f32> = (0..FRAME_LENGTH)
+        .map(|i| {
+            let hann = 0.5
+                - 0.5 * (2.0 * std::f32::consts::PI * i as f32 / (FRAME_LENGTH - 1) as f32).cos();
+            hann.powf(0.85)
+        })
+        .collect();
+    let fft = FftPlanner::<f32>::new().plan_fft_forward(FFT_SIZE);
+
+    let mut feats = Vec::with_capacity(num_frames * NUM_MEL_BINS);
+    let mut frame = vec![0f32; FRAME_LENGTH];
+    let mut buffer = vec![Complex::new(0f32, 0f32); FFT_SIZE];
+    for index in 0..num_frames {
+        // Kaldi features are computed on 16-bit sample magnitudes
+        let offset = index * FRAME_SHIFT;
+        for (out, sample) in frame.iter_mut().zip(&audio[offset..offset + FRAME_LENGTH]) {
+            *out = sample * 32768.0;
+        }
+        let mean = frame.iter().sum::<f32>() / FRAME_LENGTH as f32;
+        frame.iter_mut().for_each(|s| *s -= mean);
+        for i in (1..FRAME_LENGTH).rev() {
+            frame[i] -= PREEMPHASIS * frame[i - 1];
+        }
+        frame[0] -= PREEMPHASIS * frame[0];
+
+        for (i, value) in buffer.iter_mut().enumerate() {
+            *value = Complex::new(frame.get(i).map_or(0.0, |s| s * window[i]), 0.0);
+        }
+        fft.process(&mut buffer);
+
+        let power: Vec<f32> = buffer[..FFT_SIZE / 2 + 1]
+            .iter()
+            .map(|c| c.norm_sqr())
+            .collect();
+        feats.extend(filters.iter().map(|filter| {
+            let energy: f32 = filter.iter().zip(&power).map(|(w, p)| w * p).sum();
+            energy.max(f32::EPSILON).ln()
+        }));
+    }
+
+    for bin in 0..NUM_MEL_BINS {
+        let mean = (0..num_frames)
+            .map(|frame| feats[frame * NUM_MEL_BINS + bin])
+            .sum::<f32>()
+            / num_frames as f32;
+        for frame in 0..num_frames {
+            feats[frame * NUM_MEL_BINS + bin] -= mean;
+        }
+    }
+
+    feats
+}
+
+fn dot(a: &[f32], b: &[f32]) -> f32 {
+    a.iter().zip(b).map(|(x, y)| x * y).sum()
+}
+
+/// Average-linkage agglomerative clustering on cosine similarity of
+/// L2-normalized embeddings. Returns a cluster id per embedding.
+fn cluster(embeddings: &[Vec<f32>], max_speakers: Option<usize>) -> Vec<usize> {
+    let n = embeddings.len();
+    let max_speakers = max_speakers.unwrap_or(n).max(1);
+    let similarity: Vec<Vec<f32>> = embeddings
+        .iter()
+        .map(|a| embeddings.iter().map(|b| dot(a, b)).collect())
+        .collect();
+    let mut clusters: Vec<Vec<usize>> = (0..n).map(|i| vec![i]).collect();
+
+    while clusters.len() > 1 {
+        let mut best = (0, 0, f32::MIN);
+        for i in 0..clusters.len() {
+            for j in i + 1..clusters.len() {
+                let total: f32 = clusters[i]
+                    .iter()
+                    .flat_map(|&a| clusters[j].iter().map(move |&b| (a, b)))
+                    .map(|(a, b)| similarity[a][b])
+                    .sum();
+                let average = total / (clusters[i].len() * clusters[j].len()) as f32;
+                if average > best.2 {
+                    best = (i, j, average);
+                }
+            }
+        }
+
+        let (i, j, score) = best;
+        if score < SAME_SPEAKER_THRESHOLD && clusters.len() <= max_speakers {
+            break;
+        }
+        let merged = clusters.remove(j);
+        clusters[i].extend(merged);
+    }
+
+    let mut labels = vec![0; n];
+    for (label, members) in clusters.iter().enumerate() {
+        for &member in members {
+            labels[member] = label;
+        }
+    }
+    labels
+}
+
+/// Give unlabelled segments the speaker of the closest labelled one, preferring
+/// the previous segment; everything is speaker 0 if nothing was labelled
+fn fill_from_neighbours(speakers: &[Option<usize>]) -> Vec<usize> {
+    (0..speakers.len())
+        .map(|index| {
+            speakers[..=index]
+                .iter()
+                .rev()
+                .zip(0..)
+                .chain(speakers[index..].iter().zip(0..))
+                .filter_map(|(speaker, distance)| speaker.map(|s| (distance, s)))
+                .min_by_key(|(distance, _)| *distance)
+                .map_or(0, |(_, speaker)| speaker)
+        })
+        .collect()
+}
+
+pub type SharedDiarizationState = Arc<Mutex<DiarizationState>>;
+
+pub fn get_model_dir(app: &AppHandle) -> PathBuf {
+    app.path()
+        .resolve(
+            format!("models/{}", MODEL_NAME),
+            BaseDirectory::AppLocalData,
+        )
+        .expect("Failed to resolve diarization model directory")
+}
+
+pub fn init_diarization_state(app: &AppHandle) -> SharedDiarizationState {
+    Arc::new(Mutex::new(DiarizationState::new(get_model_dir(app))))
+}
+
+pub fn speaker_embedder(app: &AppHandle) -> Result<SpeakerEmbedder, String> {
+    let state = app
+        .try_state::<SharedDiarizationState>()
+        .ok_or("Diarization state not found")?;
+    let state = state.lock().map_err(|e| format!("Lock error: {}", e))?;
+    state.embedder()
+}
+
+#[tauri::command]
+pub async fn diarization_get_status(app: AppHandle) -> Result<DiarizationStatus, AppError> {
+    let state = app
+        .try_state::<SharedDiarizationState>()
+        .ok_or("Diarization state not found")?;
+    let state = state.lock().map_err(|e| format!("Lock error: {}", e))?;
+    Ok(state.get_status())
+}
+
+#[tauri::command]
+pub async fn diarization_download_model(app: AppHandle) -> Result<(), AppError> {
+    {
+        let state = app.state::<SharedDiarizationState>();
+        let mut state = state.lock().map_err(|e| format!("Lock error: {}", e))?;
+        if matches!(state.model_status, ModelStatus::Ready) {
+            return Ok(());
+        }
+        state.model_status = ModelStatus::Downloading { progress: 0.0 };
+    }
+
+    let model_dir = get_model_dir(&app);
+    let result = download_model_files(&app, HF_BASE_URL, MODEL_FILES, &model_dir, |progress| {
+        let _ = app.emit("diarization:download-progress", progress);
+        if let Ok(mut state) = app.state::<SharedDiarizationState>().lock() {
+            state.model_status = ModelStatus::Downloading { progress };
+        }
+    })
+    .await;
+
+    let state = app.state::<SharedDiarizationState>();
+    let mut state = state.lock().map_err(|e| format!("Lock error: {}", e))?;
+    state.model_dir = model_dir;
+    if let Err(e) = result.and_then(|_| Ok(state.load_models()?)) {
+        state.model_status = ModelStatus::Error {
+            message: e.message.clone(),
+        };
+        return Err(e);
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_cluster_separates_distinct_voices() {
+        let a = vec![1.0, 0.0, 0.0];
+        let a2 = vec![0.96, 0.28, 0.0];
+        let b = vec![0.0, 0.0, 1.0];
+        let labels = cluster(&[a.clone(), b.clone(), a2, b], None);
+        assert_eq!(labels[0], labels[2]);
+        assert_eq!(labels[1], labels[3]);
+        assert_ne!(labels[0], labels[1]);
+
+        // A speaker cap forces merging past the similarity threshold
+        let capped = cluster(&[a, vec![0.0, 0.0, 1.0]], Some(1));
+        assert_eq!(capped, vec![0, 0]);
+    }
+
+    #[test]
+    fn test_fill_from_neighbours_prefers_previous_segment() {
+        assert_eq!(
+            fill_from_neighbours(&[None, Some(1), None, Some(0), None]),
+            vec![1, 1, 1, 0, 0]
+        );
+        assert_eq!(fill_from_neighbours(&[None, None]), vec![0, 0]);
+    }
+}
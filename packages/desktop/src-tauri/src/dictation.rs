@@ -0,0 +1,207 @@
+//! Multi-segment dictation sessions.
+//!
+//! Between `stt_begin_session` and `stt_end_session` every record/stop cycle
+//! becomes a segment of one logical dictation. Segments are transcribed as
+//! they are stopped (through the regular transcription queue, so they finish
+//! in order) and joined into a single text, fixing up the punctuation at each
+//! boundary since every segment is transcribed without knowing its neighbours.
+
+use serde::Serialize;
+use std::sync::{Arc, Mutex};
+use tauri::{AppHandle, Emitter, Manager};
+use tokio::sync::Notify;
+
+use crate::error::AppError;
+
+struct DictationSession {
+    id: String,
+    segments: Vec<String>,
+    /// Segments stopped but not yet transcribed
+    pending: usize,
+}
+
+#[derive(Default)]
+pub struct DictationState {
+    session: Mutex<Option<DictationSession>>,
+    /// Signalled whenever a pending segment finishes
+    segment_finished: Arc<Notify>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DictationSessionEvent {
+    pub id: String,
+    /// Text of the segments transcribed so far, joined
+    pub text: String,
+    pub pending_segments: usize,
+}
+
+fn ends_sentence(c: char) -> bool {
+    matches!(c, '.' | '!' | '?' | '…')
+}
+
+fn ends_clause(c: char) -> bool {
+    matches!(c, ',' | ';' | ':' | '-' | '–' | '—')
+}
+
+fn capitalize_first(text: &str) -> String {
+    let mut chars = text.chars();
+    match chars.next() {
+        Some(first) => first.to_uppercase().chain(chars).collect(),
+        None => String::new(),
+    }
+}
+
+/// Join segment transcripts into one text. A segment that ends mid-sentence
+/// is closed with a period if the next one starts a new sentence, and a
+/// segment that follows a finished sentence is capitalized.
+pub fn join_segments(segments: &[String]) -> String {
+    let mut text = String::new();
+    for segment in segments.iter().map(|s| s.trim()).filter(|s| !s.is_empty()) {
+        let Some(last) = text.chars().last() else {
+            text.push_str(segment);
+            continue;
+        };
+
+        let starts_upper = segment.chars().next().is_some_and(char::is_uppercase);
+        if ends_sentence(last) {
+            text.push(' ');
+            text.push_str(&capitalize_first(segment));
+        } else {
+            if starts_upper && !ends_clause(last) {
+                text.push('.');
+            }
+            text.push(' ');
+            text.push_str(segment);
+        }
+    }
+    text
+}
+
+fn emit_session(app: &AppHandle, session: &DictationSession) {
+    let _ = app.emit(
+        "stt:session",
+        DictationSessionEvent {
+            id: session.id.clone(),
+            text: join_segments(&session.segments),
+            pending_segments: session.pending,
+        },
+    );
+}
+
+/// Count a newly stopped recording towards the active session, if any.
+/// Returns the session id to pass back to `finish_segment`.
+pub fn reserve_segment(app: &AppHandle) -> Option<String> {
+    let state = app.try_state::<DictationState>()?;
+    let mut session = state.session.lock().ok()?;
+    let session = session.as_mut()?;
+    session.pending += 1;
+    Some(session.id.clone())
+}
+
+/// Record the transcript of a reserved segment; `None` if it failed
+pub fn finish_segment(app: &AppHandle, session_id: &str, text: Option<&str>) {
+    let Some(state) = app.try_state::<DictationState>() else {
+        return;
+    };
+    let Ok(mut session) = state.session.lock() else {
+        return;
+    };
+    // The session may have been replaced since the segment was reserved
+    let Some(session) = session.as_mut().filter(|s| s.id == session_id) else {
+        return;
+    };
+
+    session.pending = session.pending.saturating_sub(1);
+    if let Some(text) = text {
+        session.segments.push(text.to_string());
+    }
+    emit_session(app, session);
+    state.segment_finished.notify_one();
+}
+
+/// Start a dictation session; subsequent recordings are collected into it
+#[tauri::command]
+pub fn stt_begin_session(app: AppHandle) -> Result<String, AppError> {
+    let state = app
+        .try_state::<DictationState>()
+        .ok_or("Dictation state not found")?;
+    let mut session = state
+        .session
+        .lock()
+        .map_err(|e| format!("Lock error: {}", e))?;
+    if session.is_some() {
+        return Err(AppError::busy("A dictation session is already active"));
+    }
+
+    let id = uuid::Uuid::new_v4().to_string();
+    let new_session = DictationSession {
+        id: id.clone(),
+        segments: Vec::new(),
+        pending: 0,
+    };
+    emit_session(&app, &new_session);
+    *session = Some(new_session);
+    Ok(id)
+}
+
+/// End the active session once its pending segments are transcribed and
+/// return the joined text
+#[tauri::command]
+pub async fn stt_end_session(app: AppHandle) -> Result<String, AppError> {
+    let state = app
+        .try_state::<DictationState>()
+        .ok_or("Dictation state not found")?;
+
+    loop {
+        {
+            let mut session = state
+                .session
+                .lock()
+                .map_err(|e| format!("Lock error: {}", e))?;
+            let Some(active) = session.as_ref() else {
+                return Err(AppError::invalid_input("No dictation session is active"));
+            };
+            if active.pending == 0 {
+                let ended = session.take().expect("session checked above");
+                return Ok(join_segments(&ended.segments));
+            }
+        }
+        state.segment_finished.notified().await;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn join(segments: &[&str]) -> String {
+        join_segments(&segments.iter().map(|s| s.to_string()).collect::<Vec<_>>())
+    }
+
+    #[test]
+    fn test_join_segments_punctuates_boundaries() {
+        assert_eq!(
+            join(&["Fix the login bug", "Then add a test."]),
+            "Fix the login bug. Then add a test."
+        );
+        assert_eq!(
+            join(&["Fix the login bug.", "then add a test."]),
+            "Fix the login bug. Then add a test."
+        );
+        assert_eq!(
+            join(&["Fix the login bug and", "add a test."]),
+            "Fix the login bug and add a test."
+        );
+        assert_eq!(
+            join(&["First, the parser,", "The lexer"]),
+            "First, the parser, The lexer"
+        );
+    }
+
+    #[test]
+    fn test_join_segments_skips_empty() {
+        assert_eq!(join(&["", "  Hello.  ", " ", "World."]), "Hello. World.");
+        assert_eq!(join(&[]), "");
+    }
+}
@@ -0,0 +1,531 @@
+//! HTML rendering of unified diffs.
+//!
+//! Agent output is mostly patches, and diffing them line by line in the
+//! webview is slow, so diffs are rendered here: as one column (`inline`) or
+//! old and new next to each other (`sideBySide`), with line numbers from the
+//! hunk headers and the changed parts of modified lines marked. Rendered
+//! markdown can have its diff blocks replaced with this (see
+//! `markdown::render_diff_blocks`).
+//!
+//! The output is a `table.diff` with `diff-file`, `diff-hunk`, `diff-add`,
+//! `diff-del`, `diff-context` and `diff-note` rows; changed parts of lines
+//! are in `mark` elements.
+
+use regex::Regex;
+use serde::Deserialize;
+use std::sync::LazyLock;
+
+/// Lines whose tokens multiply past this aren't compared for changed parts
+const MAX_INTRALINE_COST: usize = 40_000;
+
+static HUNK_HEADER: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(r"^@@ -(\d+)(?:,(\d+))? \+(\d+)(?:,(\d+))? @@").expect("valid hunk header regex")
+});
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum DiffView {
+    #[default]
+    Inline,
+    SideBySide,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Kind {
+    Context,
+    Added,
+    Removed,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Line {
+    kind: Kind,
+    old: Option<usize>,
+    new: Option<usize>,
+    text: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Row {
+    File(String),
+    Hunk(String),
+    Line(Line),
+    /// `\ No newline at end of file`
+    Note(String),
+}
+
+/// Text of a line split into (part, changed)
+type Parts = Vec<(String, bool)>;
+
+/// Escaped as comrak escapes text, so rendered code can be found again
+pub fn escape(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '"' => escaped.push_str("&quot;"),
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            _ => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+/// Whether unlabeled text is a unified diff: file headers followed by a hunk
+pub fn is_unified_diff(text: &str) -> bool {
+    let mut old_header = false;
+    let mut new_header = false;
+    for line in text.lines() {
+        if line.starts_with("--- ") {
+            old_header = true;
+        } else if old_header && line.starts_with("+++ ") {
+            new_header = true;
+        } else if new_header && HUNK_HEADER.is_match(line) {
+            return true;
+        }
+    }
+    false
+}
+
+fn file_path(header: &str) -> Option<String> {
+    // `+++ b/src/main.rs\t2024-01-01 ...`
+    let path = header.split('\t').next()?.trim();
+    if path == "/dev/null" {
+        return None;
+    }
+    let path = path
+        .strip_prefix("a/")
+        .or_else(|| path.strip_prefix("b/"))
+        .unwrap_or(path);
+    Some(path.to_string())
+}
+
+fn parse(diff: &str) -> Vec<Row> {
+    let mut rows = Vec::new();
+    let mut old_path: Option<String> = None;
+    // Lines left in the current hunk, per side
+    let (mut old_left, mut new_left) = (0usize, 0usize);
+    let (mut old_line, mut new_line): (Option<usize>, Option<usize>) = (None, None);
+
+    for raw in diff.lines() {
+        let in_hunk = old_left > 0 || new_left > 0;
+        if !in_hunk {
+            if let Some(caps) = HUNK_HEADER.captures(raw) {
+                let number = |i: usize, default: usize| {
+                    caps.get(i)
+                        .and_then(|m| m.as_str().parse().ok())
+                        .unwrap_or(default)
+                };
+                old_line = Some(number(1, 1));
+                old_left = number(2, 1);
+                new_line = Some(number(3, 1));
+                new_left = number(4, 1);
+                rows.push(Row::Hunk(raw.to_string()));
+                continue;
+            }
+            if let Some(header) = raw.strip_prefix("diff --git ") {
+                let path = header.rsplit(" b/").next().unwrap_or(header).to_string();
+                rows.push(Row::File(path));
+                old_path = None;
+                continue;
+            }
+            if let Some(header) = raw.strip_prefix("--- ") {
+                old_path = Some(file_path(header).unwrap_or_default());
+                continue;
+            }
+            if let Some(header) = raw.strip_prefix("+++ ")
+                && let Some(old) = old_path.take()
+            {
+                if !matches!(rows.last(), Some(Row::File(_))) {
+                    let path = file_path(header).unwrap_or(old);
+                    rows.push(Row::File(path));
+                }
+                continue;
+            }
+            if [
+                "index ",
+                "new file mode",
+                "deleted file mode",
+                "old mode",
+                "new mode",
+                "similarity index",
+                "dissimilarity index",
+                "rename from",
+                "rename to",
+                "copy from",
+                "copy to",
+                "Binary files",
+            ]
+            .iter()
+            .any(|prefix| raw.starts_with(prefix))
+            {
+                continue;
+            }
+        }
+
+        if raw.starts_with('\\') {
+            rows.push(Row::Note(raw.to_string()));
+            continue;
+        }
+        let (kind, text) = match raw.chars().next() {
+            Some('+') => (Kind::Added, &raw[1..]),
+            Some('-') => (Kind::Removed, &raw[1..]),
+            Some(' ') => (Kind::Context, &raw[1..]),
+            _ => (Kind::Context, raw),
+        };
+        let (old, new) = match kind {
+            Kind::Context => (old_line, new_line),
+            Kind::Added => (None, new_line),
+            Kind::Removed => (old_line, None),
+        };
+        if kind != Kind::Added {
+            old_line = old_line.map(|n| n + 1);
+            old_left = old_left.saturating_sub(1);
+        }
+        if kind != Kind::Removed {
+            new_line = new_line.map(|n| n + 1);
+            new_left = new_left.saturating_sub(1);
+        }
+        rows.push(Row::Line(Line {
+            kind,
+            old,
+            new,
+            text: text.to_string(),
+        }));
+    }
+    rows
+}
+
+/// Words, runs of whitespace and single other characters
+fn tokens(text: &str) -> Vec<&str> {
+    let mut tokens = Vec::new();
+    let mut chars = text.char_indices().peekable();
+    while let Some((i, c)) = chars.next() {
+        let word = c.is_alphanumeric() || c == '_';
+        let space = c.is_whitespace();
+        if word || space {
+            while let Some(&(_, next)) = chars.peek() {
+                let same = if word {
+                    next.is_alphanumeric() || next == '_'
+                } else {
+                    next.is_whitespace()
+                };
+                if !same {
+                    break;
+                }
+                chars.next();
+            }
+        }
+        let end = chars.peek().map_or(text.len(), |&(j, _)| j);
+        tokens.push(&text[i..end]);
+    }
+    tokens
+}
+
+fn push_part(parts: &mut Parts, token: &str, changed: bool) {
+    match parts.last_mut() {
+        Some((text, last)) if *last == changed => text.push_str(token),
+        _ => parts.push((token.to_string(), changed)),
+    }
+}
+
+/// Parts of a removed and an added line, with what differs between them
+/// marked. None when they have nothing but whitespace in common, or are too
+/// long to compare.
+fn intraline(old: &str, new: &str) -> Option<(Parts, Parts)> {
+    let a = tokens(old);
+    let b = tokens(new);
+    if a.len() * b.len() > MAX_INTRALINE_COST {
+        return None;
+    }
+
+    // Longest common subsequence of tokens
+    let mut lcs = vec![vec![0u32; b.len() + 1]; a.len() + 1];
+    for i in (0..a.len()).rev() {
+        for j in (0..b.len()).rev() {
+            lcs[i][j] = if a[i] == b[j] {
+                lcs[i + 1][j + 1] + 1
+            } else {
+                lcs[i + 1][j].max(lcs[i][j + 1])
+            };
+        }
+    }
+
+    let (mut old_parts, mut new_parts) = (Parts::new(), Parts::new());
+    let mut common = false;
+    let (mut i, mut j) = (0, 0);
+    while i < a.len() || j < b.len() {
+        if i < a.len() && j < b.len() && a[i] == b[j] {
+            common |= !a[i].trim().is_empty();
+            push_part(&mut old_parts, a[i], false);
+            push_part(&mut new_parts, b[j], false);
+            i += 1;
+            j += 1;
+        } else if j < b.len() && (i == a.len() || lcs[i][j + 1] >= lcs[i + 1][j]) {
+            push_part(&mut new_parts, b[j], true);
+            j += 1;
+        } else {
+            push_part(&mut old_parts, a[i], true);
+            i += 1;
+        }
+    }
+    common.then_some((old_parts, new_parts))
+}
+
+fn render_parts(parts: &[(String, bool)]) -> String {
+    parts
+        .iter()
+        .map(|(text, changed)| {
+            if *changed {
+                format!("<mark>{}</mark>", escape(text))
+            } else {
+                escape(text)
+            }
+        })
+        .collect()
+}
+
+/// Rendered text of each line, with changed parts marked on removed lines
+/// directly followed by added ones
+fn line_html(rows: &[Row]) -> Vec<Option<String>> {
+    let mut html: Vec<Option<String>> = rows
+        .iter()
+        .map(|row| match row {
+            Row::Line(line) => Some(escape(&line.text)),
+            _ => None,
+        })
+        .collect();
+
+    let kind = |i: usize| match rows.get(i) {
+        Some(Row::Line(line)) => Some(line.kind),
+        _ => None,
+    };
+    let mut i = 0;
+    while i < rows.len() {
+        if kind(i) != Some(Kind::Removed) {
+            i += 1;
+            continue;
+        }
+        let removed_start = i;
+        while kind(i) == Some(Kind::Removed) {
+            i += 1;
+        }
+        let added_start = i;
+        while kind(i) == Some(Kind::Added) {
+            i += 1;
+        }
+        let pairs = (added_start - removed_start).min(i - added_start);
+        for k in 0..pairs {
+            let (Row::Line(old), Row::Line(new)) =
+                (&rows[removed_start + k], &rows[added_start + k])
+            else {
+                continue;
+            };
+            if let Some((old_parts, new_parts)) = intraline(&old.text, &new.text) {
+                html[removed_start + k] = Some(render_parts(&old_parts));
+                html[added_start + k] = Some(render_parts(&new_parts));
+            }
+        }
+    }
+    html
+}
+
+fn number(n: Option<usize>) -> String {
+    n.map(|n| n.to_string()).unwrap_or_default()
+}
+
+fn kind_class(kind: Kind) -> (&'static str, &'static str) {
+    match kind {
+        Kind::Context => ("diff-context", " "),
+        Kind::Added => ("diff-add", "+"),
+        Kind::Removed => ("diff-del", "-"),
+    }
+}
+
+fn render_inline(rows: &[Row]) -> String {
+    let html = line_html(rows);
+    let mut out = String::from("<table class=\"diff diff-inline\"><tbody>\n");
+    for (row, text) in rows.iter().zip(html) {
+        match row {
+            Row::File(path) => out.push_str(&format!(
+                "<tr class=\"diff-file\"><td colspan=\"4\">{}</td></tr>\n",
+                escape(path)
+            )),
+            Row::Hunk(header) => out.push_str(&format!(
+                "<tr class=\"diff-hunk\"><td colspan=\"4\">{}</td></tr>\n",
+                escape(header)
+            )),
+            Row::Note(note) => out.push_str(&format!(
+                "<tr class=\"diff-note\"><td colspan=\"4\">{}</td></tr>\n",
+                escape(note)
+            )),
+            Row::Line(line) => {
+                let (class, sign) = kind_class(line.kind);
+                out.push_str(&format!(
+                    "<tr class=\"{}\"><td class=\"diff-num\">{}</td><td class=\"diff-num\">{}</td><td class=\"diff-sign\">{}</td><td class=\"diff-code\">{}</td></tr>\n",
+                    class,
+                    number(line.old),
+                    number(line.new),
+                    sign,
+                    text.unwrap_or_default()
+                ));
+            }
+        }
+    }
+    out.push_str("</tbody></table>\n");
+    out
+}
+
+/// Number and text cells of one side; `old` picks the line number
+fn side_cells(side: Option<(&Line, &str)>, old: bool) -> String {
+    match side {
+        Some((line, text)) => {
+            let (class, _) = kind_class(line.kind);
+            format!(
+                "<td class=\"diff-num {}\">{}</td><td class=\"diff-code {}\">{}</td>",
+                class,
+                number(if old { line.old } else { line.new }),
+                class,
+                text
+            )
+        }
+        None => "<td class=\"diff-num diff-empty\"></td><td class=\"diff-code diff-empty\"></td>"
+            .to_string(),
+    }
+}
+
+fn render_side_by_side(rows: &[Row]) -> String {
+    let html = line_html(rows);
+    let mut out = String::from("<table class=\"diff diff-side-by-side\"><tbody>\n");
+    let full_row = |out: &mut String, class: &str, text: &str| {
+        out.push_str(&format!(
+            "<tr class=\"{}\"><td colspan=\"4\">{}</td></tr>\n",
+            class,
+            escape(text)
+        ));
+    };
+
+    let mut i = 0;
+    while i < rows.len() {
+        let line = match &rows[i] {
+            Row::File(path) => {
+                full_row(&mut out, "diff-file", path);
+                i += 1;
+                continue;
+            }
+            Row::Hunk(header) => {
+                full_row(&mut out, "diff-hunk", header);
+                i += 1;
+                continue;
+            }
+            Row::Note(note) => {
+                full_row(&mut out, "diff-note", note);
+                i += 1;
+                continue;
+            }
+            Row::Line(line) => line,
+        };
+        let text = html[i].as_deref().unwrap_or_default();
+        if line.kind == Kind::Context {
+            out.push_str(&format!(
+                "<tr class=\"diff-context\">{}{}</tr>\n",
+                side_cells(Some((line, text)), true),
+                side_cells(Some((line, text)), false)
+            ));
+            i += 1;
+            continue;
+        }
+
+        // A change: removed lines on the left, added lines on the right
+        let mut removed = Vec::new();
+        let mut added = Vec::new();
+        while let Some(Row::Line(line)) = rows.get(i) {
+            let text = html[i].as_deref().unwrap_or_default();
+            match line.kind {
+                Kind::Removed if added.is_empty() => removed.push((line, text)),
+                Kind::Added => added.push((line, text)),
+                _ => break,
+            }
+            i += 1;
+        }
+        for k in 0..removed.len().max(added.len()) {
+            out.push_str(&format!(
+                "<tr class=\"diff-change\">{}{}</tr>\n",
+                side_cells(removed.get(k).copied(), true),
+                side_cells(added.get(k).copied(), false)
+            ));
+        }
+    }
+    out.push_str("</tbody></table>\n");
+    out
+}
+
+/// HTML of a unified diff, or of bare `+`/`-` lines as in a ```diff block
+pub fn render(diff: &str, view: DiffView) -> String {
+    let rows = parse(diff);
+    match view {
+        DiffView::Inline => render_inline(&rows),
+        DiffView::SideBySide => render_side_by_side(&rows),
+    }
+}
+
+#[tauri::command]
+pub fn render_diff(diff: String, view: Option<DiffView>) -> String {
+    render(&diff, view.unwrap_or_default())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PATCH: &str = "diff --git a/src/main.rs b/src/main.rs\n\
+                         index 83db48f..bf269f4 100644\n\
+                         --- a/src/main.rs\n\
+                         +++ b/src/main.rs\n\
+                         @@ -10,3 +10,3 @@ fn main() {\n \
+                         let a = 1;\n\
+                         -    let name = \"old\";\n\
+                         +    let name = \"new\";\n \
+                         }\n";
+
+    #[test]
+    fn test_parse_numbers_lines_from_hunks() {
+        assert!(is_unified_diff(PATCH));
+        assert!(!is_unified_diff("- a list\n- of items\n"));
+
+        let rows = parse(PATCH);
+        assert_eq!(rows[0], Row::File("src/main.rs".to_string()));
+        assert!(matches!(&rows[1], Row::Hunk(header) if header.starts_with("@@ -10,3")));
+        let lines: Vec<_> = rows
+            .iter()
+            .filter_map(|row| match row {
+                Row::Line(line) => Some((line.kind, line.old, line.new)),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(
+            lines,
+            vec![
+                (Kind::Context, Some(10), Some(10)),
+                (Kind::Removed, Some(11), None),
+                (Kind::Added, None, Some(11)),
+                (Kind::Context, Some(12), Some(12)),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_changed_parts_are_marked() {
+        let html = render(PATCH, DiffView::Inline);
+        assert!(html.contains("&quot;<mark>old</mark>&quot;"));
+        assert!(html.contains("&quot;<mark>new</mark>&quot;"));
+        assert!(html.contains("let name = "));
+
+        let html = render(PATCH, DiffView::SideBySide);
+        assert_eq!(html.matches("<tr class=\"diff-change\">").count(), 1);
+
+        // Nothing in common: whole lines, no marks
+        assert!(!render("-foo\n+bar\n", DiffView::Inline).contains("<mark>"));
+    }
+}
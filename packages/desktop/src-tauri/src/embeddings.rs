@@ -0,0 +1,445 @@
+//! Local sentence embeddings using the all-MiniLM-L6-v2 ONNX model.
+//!
+//! Reuses the ONNX Runtime already linked for STT and the same on-demand
+//! model download flow. Texts are tokenized with a built-in WordPiece
+//! tokenizer, mean-pooled and L2-normalized, so cosine similarity between two
+//! embeddings is a plain dot product.
+
+use ort::{session::Session, value::TensorRef};
+use serde::Serialize;
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
+};
+use tauri::{AppHandle, Emitter, Manager, path::BaseDirectory};
+use unicode_normalization::{UnicodeNormalization, char::is_combining_mark};
+
+use crate::{
+    error::AppError,
+    onnx_runtime,
+    stt::{ModelStatus, download_model_files},
+};
+
+const MODEL_NAME: &str = "all-MiniLM-L6-v2";
+const HF_BASE_URL: &str =
+    "https://huggingface.co/sentence-transformers/all-MiniLM-L6-v2/resolve/main";
+
+/// (remote path, local file name)
+const MODEL_FILES: &[(&str, &str)] = &[
+    ("onnx/model.onnx", "model.onnx"),
+    ("vocab.txt", "vocab.txt"),
+];
+
+/// Dimension of the produced embeddings
+const EMBEDDING_DIM: usize = 384;
+/// Longest token sequence fed to the model, including [CLS] and [SEP]
+const MAX_SEQUENCE_LENGTH: usize = 256;
+/// Number of texts run through the model at once
+const BATCH_SIZE: usize = 32;
+/// Words longer than this are mapped straight to [UNK], as in BERT
+const MAX_WORD_CHARS: usize = 100;
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct EmbeddingStatus {
+    pub model_status: ModelStatus,
+}
+
+/// Uncased BERT WordPiece tokenizer
+pub struct WordPieceTokenizer {
+    vocab: HashMap<String, i64>,
+    unk_id: i64,
+    cls_id: i64,
+    sep_id: i64,
+}
+
+impl WordPieceTokenizer {
+    pub fn from_vocab(content: &str) -> Result<Self, String> {
+        let vocab: HashMap<String, i64> = content
+            .lines()
+            .enumerate()
+            .map(|(id, token)| (token.to_string(), id as i64))
+            .collect();
+
+        let special = |token: &str| {
+            vocab
+                .get(token)
+                .copied()
+                .ok_or_else(|| format!("Vocabulary is missing {}", token))
+        };
+
+        Ok(Self {
+            unk_id: special("[UNK]")?,
+            cls_id: special("[CLS]")?,
+            sep_id: special("[SEP]")?,
+            vocab,
+        })
+    }
+
+    /// Lowercase, strip accents and split on whitespace and punctuation
+    fn basic_tokenize(text: &str) -> Vec<String> {
+        let mut words = Vec::new();
+        let mut current = String::new();
+
+        let normalized = text
+            .to_lowercase()
+            .nfd()
+            .filter(|c| !is_combining_mark(*c))
+            .collect::<String>();
+
+        for c in normalized.chars() {
+            if c.is_whitespace() || c.is_control() {
+                if !current.is_empty() {
+                    words.push(std::mem::take(&mut current));
+                }
+            } else if is_punctuation(c) || is_cjk(c) {
+                if !current.is_empty() {
+                    words.push(std::mem::take(&mut current));
+                }
+                words.push(c.to_string());
+            } else {
+                current.push(c);
+            }
+        }
+        if !current.is_empty() {
+            words.push(current);
+        }
+
+        words
+    }
+
+    /// Greedy longest-match-first split of a word into vocabulary pieces
+    fn word_pieces(&self, word: &str, ids: &mut Vec<i64>) {
+        let chars: Vec<char> = word.chars().collect();
+        if chars.len() > MAX_WORD_CHARS {
+            ids.push(self.unk_id);
+            return;
+        }
+
+        let mut pieces = Vec::new();
+        let mut start = 0;
+        while start < chars.len() {
+            let mut end = chars.len();
+            let mut found = None;
+            while start < end {
+                let mut piece: String = chars[start..end].iter().collect();
+                if start > 0 {
+                    piece.insert_str(0, "##");
+                }
+                if let Some(id) = self.vocab.get(&piece) {
+                    found = Some(*id);
+                    break;
+                }
+                end -= 1;
+            }
+
+            let Some(id) = found else {
+                ids.push(self.unk_id);
+                return;
+            };
+            pieces.push(id);
+            start = end;
+        }
+
+        ids.extend(pieces);
+    }
+
+    /// Encode text as `[CLS] tokens... [SEP]`, truncated to `max_len` ids
+    pub fn encode(&self, text: &str, max_len: usize) -> Vec<i64> {
+        let mut ids = vec![self.cls_id];
+        for word in Self::basic_tokenize(text) {
+            self.word_pieces(&word, &mut ids);
+            if ids.len() >= max_len - 1 {
+                break;
+            }
+        }
+        ids.truncate(max_len - 1);
+        ids.push(self.sep_id);
+        ids
+    }
+}
+
+fn is_punctuation(c: char) -> bool {
+    c.is_ascii_punctuation() || (!c.is_alphanumeric() && !c.is_whitespace() && !c.is_control())
+}
+
+fn is_cjk(c: char) -> bool {
+    matches!(c as u32,
+        0x4E00..=0x9FFF
+        | 0x3400..=0x4DBF
+        | 0x20000..=0x2A6DF
+        | 0xF900..=0xFAFF
+        | 0x2F800..=0x2FA1F)
+}
+
+pub struct EmbeddingState {
+    session: Option<Arc<Mutex<Session>>>,
+    tokenizer: Option<Arc<WordPieceTokenizer>>,
+    model_status: ModelStatus,
+    model_dir: PathBuf,
+}
+
+impl EmbeddingState {
+    pub fn new(model_dir: PathBuf) -> Self {
+        let mut state = Self {
+            session: None,
+            tokenizer: None,
+            model_status: ModelStatus::NotDownloaded,
+            model_dir,
+        };
+
+        if Self::are_models_downloaded(&state.model_dir)
+            && let Err(e) = state.load_models()
+        {
+            state.model_status = ModelStatus::Error { message: e };
+        }
+
+        state
+    }
+
+    fn are_models_downloaded(model_dir: &Path) -> bool {
+        MODEL_FILES
+            .iter()
+            .all(|(_, file)| model_dir.join(file).exists())
+    }
+
+    pub fn get_status(&self) -> EmbeddingStatus {
+        EmbeddingStatus {
+            model_status: self.model_status.clone(),
+        }
+    }
+
+    pub fn load_models(&mut self) -> Result<(), String> {
+        if !Self::are_models_downloaded(&self.model_dir) {
+            return Err("Models not downloaded".to_string());
+        }
+
+        let vocab = std::fs::read_to_string(self.model_dir.join("vocab.txt"))
+            .map_err(|e| format!("Failed to read vocab: {}", e))?;
+        let tokenizer = WordPieceTokenizer::from_vocab(&vocab)?;
+
+        let session = onnx_runtime::build_session(&self.model_dir.join("model.onnx"), "embedding")?;
+
+        self.session = Some(Arc::new(Mutex::new(session)));
+        self.tokenizer = Some(Arc::new(tokenizer));
+        self.model_status = ModelStatus::Ready;
+        Ok(())
+    }
+
+    pub fn embedder(&self) -> Result<Embedder, String> {
+        if !matches!(self.model_status, ModelStatus::Ready) {
+            return Err("Embedding model not ready. Please download the model first.".to_string());
+        }
+
+        Ok(Embedder {
+            session: self
+                .session
+                .as_ref()
+                .ok_or("Embedding model not loaded")?
+                .clone(),
+            tokenizer: self
+                .tokenizer
+                .as_ref()
+                .ok_or("Tokenizer not loaded")?
+                .clone(),
+        })
+    }
+}
+
+pub struct Embedder {
+    session: Arc<Mutex<Session>>,
+    tokenizer: Arc<WordPieceTokenizer>,
+}
+
+impl Embedder {
+    pub fn embed(&self, texts: &[String]) -> Result<Vec<Vec<f32>>, String> {
+        let mut embeddings = Vec::with_capacity(texts.len());
+        for batch in texts.chunks(BATCH_SIZE) {
+            embeddings.extend(self.embed_batch(batch)?);
+        }
+        Ok(embeddings)
+    }
+
+    fn embed_batch(&self, texts: &[String]) -> Result<Vec<Vec<f32>>, String> {
+        let encoded: Vec<Vec<i64>> = texts
+            .iter()
+            .map(|text| self.tokenizer.encode(text, MAX_SEQUENCE_LENGTH))
+            .collect();
+        let batch = encoded.len();
+        let seq_len = encoded.iter().map(Vec::len).max().unwrap_or(0);
+
+        // Right-pad with zeros ([PAD]) and mask the padding out
+        let mut input_ids = ndarray::Array2::<i64>::zeros((batch, seq_len));
+        let mut attention_mask = ndarray::Array2::<i64>::zeros((batch, seq_len));
+        let token_type_ids = ndarray::Array2::<i64>::zeros((batch, seq_len));
+        for (row, ids) in encoded.iter().enumerate() {
+            for (col, id) in ids.iter().enumerate() {
+                input_ids[[row, col]] = *id;
+                attention_mask[[row, col]] = 1;
+            }
+        }
+
+        let input_ids_tensor = TensorRef::from_array_view(input_ids.view())
+            .map_err(|e| format!("Failed to create input_ids tensor: {}", e))?;
+        let attention_mask_tensor = TensorRef::from_array_view(attention_mask.view())
+            .map_err(|e| format!("Failed to create attention_mask tensor: {}", e))?;
+        let token_type_ids_tensor = TensorRef::from_array_view(token_type_ids.view())
+            .map_err(|e| format!("Failed to create token_type_ids tensor: {}", e))?;
+
+        let mut session = self
+            .session
+            .lock()
+            .map_err(|e| format!("Lock error: {}", e))?;
+        let outputs = session
+            .run(ort::inputs![
+                "input_ids" => input_ids_tensor,
+                "attention_mask" => attention_mask_tensor,
+                "token_type_ids" => token_type_ids_tensor
+            ])
+            .map_err(|e| format!("Failed to run embedding model: {}", e))?;
+
+        // last_hidden_state: [batch, seq, hidden]
+        let (shape, hidden) = outputs[0]
+            .try_extract_tensor::<f32>()
+            .map_err(|e| format!("Failed to extract embeddings: {}", e))?;
+        let hidden_size = shape[2] as usize;
+        // Vectors of a different size would silently corrupt the persisted search index
+        if hidden_size != EMBEDDING_DIM {
+            return Err(format!(
+                "Unexpected embedding size {} (expected {})",
+                hidden_size, EMBEDDING_DIM
+            ));
+        }
+
+        let mut embeddings = Vec::with_capacity(batch);
+        for (row, ids) in encoded.iter().enumerate() {
+            let mut pooled = vec![0f32; hidden_size];
+            for token in 0..ids.len() {
+                let offset = (row * seq_len + token) * hidden_size;
+                for (value, h) in pooled.iter_mut().zip(&hidden[offset..offset + hidden_size]) {
+                    *value += h;
+                }
+            }
+            for value in pooled.iter_mut() {
+                *value /= ids.len() as f32;
+            }
+            normalize(&mut pooled);
+            embeddings.push(pooled);
+        }
+
+        Ok(embeddings)
+    }
+}
+
+fn normalize(vector: &mut [f32]) {
+    let norm = vector.iter().map(|v| v * v).sum::<f32>().sqrt();
+    if norm > f32::EPSILON {
+        for value in vector.iter_mut() {
+            *value /= norm;
+        }
+    }
+}
+
+pub type SharedEmbeddingState = Arc<Mutex<EmbeddingState>>;
+
+pub fn get_model_dir(app: &AppHandle) -> PathBuf {
+    app.path()
+        .resolve(
+            format!("models/{}", MODEL_NAME),
+            BaseDirectory::AppLocalData,
+        )
+        .expect("Failed to resolve embedding model directory")
+}
+
+pub fn init_embedding_state(app: &AppHandle) -> SharedEmbeddingState {
+    Arc::new(Mutex::new(EmbeddingState::new(get_model_dir(app))))
+}
+
+/// Embed texts off the async runtime using the loaded model
+pub async fn embed(app: &AppHandle, texts: Vec<String>) -> Result<Vec<Vec<f32>>, String> {
+    let embedder = {
+        let state = app
+            .try_state::<SharedEmbeddingState>()
+            .ok_or("Embedding state not found")?;
+        let state = state.lock().map_err(|e| format!("Lock error: {}", e))?;
+        state.embedder()?
+    };
+
+    tauri::async_runtime::spawn_blocking(move || embedder.embed(&texts))
+        .await
+        .map_err(|e| format!("Embedding task failed: {}", e))?
+}
+
+#[tauri::command]
+pub async fn embeddings_get_status(app: AppHandle) -> Result<EmbeddingStatus, AppError> {
+    let state = app
+        .try_state::<SharedEmbeddingState>()
+        .ok_or("Embedding state not found")?;
+    let state = state.lock().map_err(|e| format!("Lock error: {}", e))?;
+    Ok(state.get_status())
+}
+
+#[tauri::command]
+pub async fn embeddings_download_model(app: AppHandle) -> Result<(), AppError> {
+    {
+        let state = app.state::<SharedEmbeddingState>();
+        let mut state = state.lock().map_err(|e| format!("Lock error: {}", e))?;
+        if matches!(state.model_status, ModelStatus::Ready) {
+            return Ok(());
+        }
+        state.model_status = ModelStatus::Downloading { progress: 0.0 };
+    }
+
+    let model_dir = get_model_dir(&app);
+    let result = download_model_files(&app, HF_BASE_URL, MODEL_FILES, &model_dir, |progress| {
+        let _ = app.emit("embeddings:download-progress", progress);
+        if let Ok(mut state) = app.state::<SharedEmbeddingState>().lock() {
+            state.model_status = ModelStatus::Downloading { progress };
+        }
+    })
+    .await;
+
+    let state = app.state::<SharedEmbeddingState>();
+    let mut state = state.lock().map_err(|e| format!("Lock error: {}", e))?;
+    state.model_dir = model_dir;
+    if let Err(e) = result.and_then(|_| Ok(state.load_models()?)) {
+        state.model_status = ModelStatus::Error {
+            message: e.message.clone(),
+        };
+        return Err(e);
+    }
+    Ok(())
+}
+
+#[tauri::command]
+pub async fn embed_texts(app: AppHandle, texts: Vec<String>) -> Result<Vec<Vec<f32>>, AppError> {
+    Ok(embed(&app, texts).await?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tokenizer() -> WordPieceTokenizer {
+        let vocab = [
+            "[PAD]", "[UNK]", "[CLS]", "[SEP]", "hello", ",", "world", "un", "##aff", "##able",
+            "cafe",
+        ];
+        WordPieceTokenizer::from_vocab(&vocab.join("\n")).unwrap()
+    }
+
+    #[test]
+    fn test_encode_splits_punctuation_and_wordpieces() {
+        let ids = tokenizer().encode("Hello, unaffable world", 16);
+        assert_eq!(ids, vec![2, 4, 5, 7, 8, 9, 6, 3]);
+    }
+
+    #[test]
+    fn test_encode_strips_accents_and_truncates() {
+        let tokenizer = tokenizer();
+        assert_eq!(tokenizer.encode("Café", 16), vec![2, 10, 3]);
+        assert_eq!(tokenizer.encode("hello hello hello", 3), vec![2, 4, 3]);
+        assert_eq!(tokenizer.encode("xyz", 16), vec![2, 1, 3]);
+    }
+}
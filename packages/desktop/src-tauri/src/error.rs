@@ -0,0 +1,173 @@
+//! The error type commands return.
+//!
+//! Commands fail with an `AppError` instead of a bare message so the frontend
+//! can decide how to present a failure from its `kind`, offer a retry when
+//! it is `retryable`, and show the `remediation` hint, without matching on
+//! message text. Errors converted from plain strings (helpers that still
+//! return `Result<T, String>`) are `internal`.
+
+use serde::{Deserialize, Serialize};
+use std::fmt;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum ErrorKind {
+    /// A file, window, session or item doesn't exist
+    NotFound,
+    /// An argument was rejected, or the call doesn't fit the current state
+    InvalidInput,
+    /// The OS, the user or an administrator's policy refused it
+    PermissionDenied,
+    /// A server or download couldn't be reached
+    Network,
+    /// Reading or writing local files failed
+    Io,
+    /// Something else is in progress
+    Busy,
+    /// A feature isn't ready or supported here
+    Unavailable,
+    /// The user cancelled
+    Cancelled,
+    Internal,
+}
+
+impl ErrorKind {
+    /// Whether the same call may succeed when tried again
+    fn retryable(self) -> bool {
+        matches!(self, Self::Network | Self::Io | Self::Busy)
+    }
+}
+
+/// An argument field that was rejected, and why
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct FieldError {
+    pub field: String,
+    pub message: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AppError {
+    pub kind: ErrorKind,
+    pub message: String,
+    pub retryable: bool,
+    /// What the user can do about it, in the app language
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub remediation: Option<String>,
+    /// The fields that were rejected, so a form can show each error next to
+    /// its field
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub fields: Vec<FieldError>,
+}
+
+impl AppError {
+    pub fn new(kind: ErrorKind, message: impl Into<String>) -> Self {
+        Self {
+            kind,
+            message: message.into(),
+            retryable: kind.retryable(),
+            remediation: None,
+            fields: Vec::new(),
+        }
+    }
+
+    pub fn not_found(message: impl Into<String>) -> Self {
+        Self::new(ErrorKind::NotFound, message)
+    }
+
+    pub fn invalid_input(message: impl Into<String>) -> Self {
+        Self::new(ErrorKind::InvalidInput, message)
+    }
+
+    pub fn permission_denied(message: impl Into<String>) -> Self {
+        Self::new(ErrorKind::PermissionDenied, message)
+    }
+
+    pub fn network(message: impl Into<String>) -> Self {
+        Self::new(ErrorKind::Network, message)
+    }
+
+    pub fn io(message: impl Into<String>) -> Self {
+        Self::new(ErrorKind::Io, message)
+    }
+
+    pub fn busy(message: impl Into<String>) -> Self {
+        Self::new(ErrorKind::Busy, message)
+    }
+
+    pub fn unavailable(message: impl Into<String>) -> Self {
+        Self::new(ErrorKind::Unavailable, message)
+    }
+
+    pub fn cancelled(message: impl Into<String>) -> Self {
+        Self::new(ErrorKind::Cancelled, message)
+    }
+
+    pub fn with_remediation(mut self, remediation: impl Into<String>) -> Self {
+        self.remediation = Some(remediation.into());
+        self
+    }
+
+    pub fn with_fields(mut self, fields: Vec<FieldError>) -> Self {
+        self.fields = fields;
+        self
+    }
+}
+
+impl fmt::Display for AppError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.message)
+    }
+}
+
+impl std::error::Error for AppError {}
+
+impl From<String> for AppError {
+    fn from(message: String) -> Self {
+        Self::new(ErrorKind::Internal, message)
+    }
+}
+
+impl From<&str> for AppError {
+    fn from(message: &str) -> Self {
+        Self::new(ErrorKind::Internal, message)
+    }
+}
+
+/// Commands calling other commands from helpers that return plain strings
+impl From<AppError> for String {
+    fn from(error: AppError) -> Self {
+        error.message
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_serializes_for_the_frontend() {
+        let error = AppError::network("Failed to connect to server")
+            .with_remediation("Check your connection");
+        assert_eq!(
+            serde_json::to_value(&error).unwrap(),
+            serde_json::json!({
+                "kind": "network",
+                "message": "Failed to connect to server",
+                "retryable": true,
+                "remediation": "Check your connection",
+            })
+        );
+
+        let error = AppError::from("Lock error");
+        assert_eq!(error.kind, ErrorKind::Internal);
+        assert!(!error.retryable);
+        assert!(
+            serde_json::to_value(&error)
+                .unwrap()
+                .get("remediation")
+                .is_none()
+        );
+    }
+}
@@ -0,0 +1,368 @@
+//! Local files streamed to the webview over the `stream` protocol.
+//!
+//! Attachments, transcripts and exported pages can be far larger than what
+//! fits through IPC as base64. `stream_file` grants the webview a URL for
+//! one file instead, which `img`, `audio`, `video`, `iframe` and `fetch` load
+//! like any other, with range requests so media can seek without reading the
+//! whole file. Only granted files can be read: URLs carry a random token, not
+//! the path, and last until the app quits or `revoke_file_stream`. Responses
+//! allow the app's own origin, so its pages can `fetch` them across schemes.
+//!
+//! URLs are `stream://localhost/<token>/<file name>`
+//! (`http://stream.localhost/...` on Windows).
+
+use std::{
+    collections::HashMap,
+    fs::File,
+    io::{Read, Seek, SeekFrom},
+    path::{Path, PathBuf},
+    sync::Mutex,
+};
+use tauri::{
+    AppHandle, Manager, UriSchemeContext, UriSchemeResponder, Wry,
+    http::{Method, Request, Response, StatusCode, header, response::Builder},
+};
+
+use crate::error::AppError;
+
+pub const SCHEME: &str = "stream";
+/// Open-ended ranges (`bytes=N-`) are answered with at most this much, as
+/// media elements ask for the rest of the file when they mean "some more"
+const MAX_OPEN_RANGE_BYTES: u64 = 8 * 1024 * 1024;
+/// Origins of the app's pages: `tauri://localhost`, and `tauri.localhost` on
+/// Windows over either scheme
+const APP_ORIGINS: &[&str] = &[
+    "tauri://localhost",
+    "http://tauri.localhost",
+    "https://tauri.localhost",
+];
+
+#[derive(Default)]
+pub struct StreamState(Mutex<HashMap<String, PathBuf>>);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum ByteRange {
+    Full,
+    /// Inclusive
+    Partial(u64, u64),
+    Unsatisfiable,
+}
+
+/// The first range of a `Range` header. Ranges that aren't understood are
+/// ignored, and the whole file is sent.
+fn parse_range(header: Option<&str>, len: u64) -> ByteRange {
+    let Some(spec) = header
+        .and_then(|header| header.trim().strip_prefix("bytes="))
+        .and_then(|ranges| ranges.split(',').next())
+    else {
+        return ByteRange::Full;
+    };
+    let Some((start, end)) = spec.trim().split_once('-') else {
+        return ByteRange::Full;
+    };
+    let (start, end) = (start.trim(), end.trim());
+
+    if start.is_empty() {
+        // The last `end` bytes
+        return match end.parse::<u64>() {
+            Ok(0) => ByteRange::Unsatisfiable,
+            Ok(_) if len == 0 => ByteRange::Unsatisfiable,
+            Ok(suffix) => ByteRange::Partial(len.saturating_sub(suffix), len - 1),
+            Err(_) => ByteRange::Full,
+        };
+    }
+    let Ok(start) = start.parse::<u64>() else {
+        return ByteRange::Full;
+    };
+    if start >= len {
+        return ByteRange::Unsatisfiable;
+    }
+    if end.is_empty() {
+        return ByteRange::Partial(start, (start + MAX_OPEN_RANGE_BYTES).min(len) - 1);
+    }
+    match end.parse::<u64>() {
+        Ok(end) if end >= start => ByteRange::Partial(start, end.min(len - 1)),
+        _ => ByteRange::Full,
+    }
+}
+
+fn content_type(path: &Path) -> &'static str {
+    let extension = path
+        .extension()
+        .and_then(|ext| ext.to_str())
+        .map(|ext| ext.to_lowercase())
+        .unwrap_or_default();
+    match extension.as_str() {
+        "html" | "htm" => "text/html; charset=utf-8",
+        "txt" | "log" | "md" | "srt" | "vtt" => "text/plain; charset=utf-8",
+        "json" => "application/json",
+        "csv" => "text/csv; charset=utf-8",
+        "pdf" => "application/pdf",
+        "png" => "image/png",
+        "jpg" | "jpeg" => "image/jpeg",
+        "gif" => "image/gif",
+        "webp" => "image/webp",
+        "svg" => "image/svg+xml",
+        "mp3" => "audio/mpeg",
+        "wav" => "audio/wav",
+        "ogg" | "oga" => "audio/ogg",
+        "m4a" => "audio/mp4",
+        "flac" => "audio/flac",
+        "mp4" => "video/mp4",
+        "webm" => "video/webm",
+        _ => "application/octet-stream",
+    }
+}
+
+fn stream_url(token: &str, name: &str) -> Result<String, String> {
+    let base = if cfg!(windows) {
+        format!("http://{}.localhost/", SCHEME)
+    } else {
+        format!("{}://localhost/", SCHEME)
+    };
+    let mut url =
+        reqwest::Url::parse(&base).map_err(|e| format!("Failed to build stream URL: {}", e))?;
+    url.path_segments_mut()
+        .map_err(|_| "Failed to build stream URL".to_string())?
+        .push(token)
+        .push(name);
+    Ok(url.into())
+}
+
+/// Token of a stream URL: its first path segment
+fn token_of(url: &str) -> Option<String> {
+    reqwest::Url::parse(url)
+        .ok()?
+        .path_segments()?
+        .next()
+        .filter(|token| !token.is_empty())
+        .map(String::from)
+}
+
+fn read_range(path: &Path, range: ByteRange) -> Result<(u64, Vec<u8>), std::io::Error> {
+    let mut file = File::open(path)?;
+    let len = file.metadata()?.len();
+    let body = match range {
+        ByteRange::Partial(start, end) => {
+            file.seek(SeekFrom::Start(start))?;
+            let mut body = Vec::with_capacity((end - start + 1) as usize);
+            file.take(end - start + 1).read_to_end(&mut body)?;
+            body
+        }
+        ByteRange::Full => {
+            let mut body = Vec::with_capacity(len as usize);
+            file.read_to_end(&mut body)?;
+            body
+        }
+        ByteRange::Unsatisfiable => Vec::new(),
+    };
+    Ok((len, body))
+}
+
+/// The origin of `request` when it comes from one of the app's pages, or
+/// from the dev server in debug builds
+fn app_origin(app: &AppHandle, request: &Request<Vec<u8>>) -> Option<String> {
+    let origin = request.headers().get(header::ORIGIN)?.to_str().ok()?;
+    let dev_origin = app
+        .config()
+        .build
+        .dev_url
+        .as_ref()
+        .filter(|_| cfg!(debug_assertions))
+        .map(|url| url.origin().ascii_serialization());
+    (APP_ORIGINS.contains(&origin) || dev_origin.as_deref() == Some(origin))
+        .then(|| origin.to_string())
+}
+
+/// A response `origin`, if any, may read
+fn response(origin: Option<&str>) -> Builder {
+    let builder = Response::builder().header(header::VARY, header::ORIGIN.as_str());
+    match origin {
+        Some(origin) => builder
+            .header(header::ACCESS_CONTROL_ALLOW_ORIGIN, origin)
+            .header(
+                header::ACCESS_CONTROL_EXPOSE_HEADERS,
+                "Accept-Ranges, Content-Length, Content-Range",
+            ),
+        None => builder,
+    }
+}
+
+fn serve(path: &Path, request: &Request<Vec<u8>>, origin: Option<&str>) -> Response<Vec<u8>> {
+    let error = |status: StatusCode| {
+        response(origin)
+            .status(status)
+            .body(Vec::new())
+            .unwrap_or_default()
+    };
+    if request.method() == Method::OPTIONS {
+        // The preflight of a `fetch` with a `Range` header
+        return response(origin)
+            .status(StatusCode::NO_CONTENT)
+            .header(header::ACCESS_CONTROL_ALLOW_METHODS, "GET, HEAD")
+            .header(header::ACCESS_CONTROL_ALLOW_HEADERS, "Range")
+            .body(Vec::new())
+            .unwrap_or_default();
+    }
+    let len = match std::fs::metadata(path) {
+        Ok(metadata) => metadata.len(),
+        Err(_) => return error(StatusCode::NOT_FOUND),
+    };
+    let range = parse_range(
+        request
+            .headers()
+            .get(header::RANGE)
+            .and_then(|value| value.to_str().ok()),
+        len,
+    );
+    if range == ByteRange::Unsatisfiable {
+        return response(origin)
+            .status(StatusCode::RANGE_NOT_SATISFIABLE)
+            .header(header::CONTENT_RANGE, format!("bytes */{}", len))
+            .body(Vec::new())
+            .unwrap_or_default();
+    }
+
+    let head = request.method() == Method::HEAD;
+    let (len, body) = if head {
+        (len, Vec::new())
+    } else {
+        match read_range(path, range) {
+            Ok(read) => read,
+            Err(_) => return error(StatusCode::INTERNAL_SERVER_ERROR),
+        }
+    };
+    let builder = response(origin)
+        .header(header::CONTENT_TYPE, content_type(path))
+        .header(header::ACCEPT_RANGES, "bytes")
+        .header(header::CACHE_CONTROL, "no-cache");
+    let builder = match range {
+        ByteRange::Partial(start, end) => builder
+            .status(StatusCode::PARTIAL_CONTENT)
+            .header(
+                header::CONTENT_RANGE,
+                format!("bytes {}-{}/{}", start, end, len),
+            )
+            .header(header::CONTENT_LENGTH, end - start + 1),
+        _ => builder
+            .status(StatusCode::OK)
+            .header(header::CONTENT_LENGTH, len),
+    };
+    builder.body(body).unwrap_or_default()
+}
+
+/// Handler of the `stream` protocol
+pub fn handle(
+    ctx: UriSchemeContext<'_, Wry>,
+    request: Request<Vec<u8>>,
+    responder: UriSchemeResponder,
+) {
+    let origin = app_origin(ctx.app_handle(), &request);
+    let path = token_of(&request.uri().to_string()).and_then(|token| {
+        let state = ctx.app_handle().try_state::<StreamState>()?;
+        let files = state.0.lock().ok()?;
+        files.get(&token).cloned()
+    });
+    let Some(path) = path else {
+        responder.respond(
+            response(origin.as_deref())
+                .status(StatusCode::NOT_FOUND)
+                .body(Vec::new())
+                .unwrap_or_default(),
+        );
+        return;
+    };
+    // Reading can take a while, and would block the webview's thread
+    tauri::async_runtime::spawn_blocking(move || {
+        responder.respond(serve(&path, &request, origin.as_deref()));
+    });
+}
+
+/// A URL the webview can load `path` from
+#[tauri::command]
+pub fn stream_file(app: AppHandle, path: String) -> Result<String, AppError> {
+    let path = std::fs::canonicalize(&path)
+        .map_err(|e| AppError::not_found(format!("Failed to open {}: {}", path, e)))?;
+    if !path.is_file() {
+        return Err(AppError::invalid_input(format!(
+            "{} is not a file",
+            path.display()
+        )));
+    }
+    let name = path
+        .file_name()
+        .map(|name| name.to_string_lossy().into_owned())
+        .unwrap_or_default();
+
+    let token = uuid::Uuid::new_v4().simple().to_string();
+    let url = stream_url(&token, &name)?;
+    let state = app
+        .try_state::<StreamState>()
+        .ok_or("Stream state not found")?;
+    state
+        .0
+        .lock()
+        .map_err(|e| format!("Lock error: {}", e))?
+        .insert(token, path);
+    Ok(url)
+}
+
+/// Stop serving a URL from `stream_file`
+#[tauri::command]
+pub fn revoke_file_stream(app: AppHandle, url: String) -> Result<(), AppError> {
+    let token = token_of(&url)
+        .ok_or_else(|| AppError::invalid_input(format!("Not a stream URL: {}", url)))?;
+    let state = app
+        .try_state::<StreamState>()
+        .ok_or("Stream state not found")?;
+    state
+        .0
+        .lock()
+        .map_err(|e| format!("Lock error: {}", e))?
+        .remove(&token);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_range() {
+        assert_eq!(parse_range(None, 100), ByteRange::Full);
+        assert_eq!(
+            parse_range(Some("bytes=0-9"), 100),
+            ByteRange::Partial(0, 9)
+        );
+        assert_eq!(
+            parse_range(Some("bytes=90-200"), 100),
+            ByteRange::Partial(90, 99)
+        );
+        assert_eq!(
+            parse_range(Some("bytes=-10"), 100),
+            ByteRange::Partial(90, 99)
+        );
+        assert_eq!(
+            parse_range(Some("bytes=10-"), 100),
+            ByteRange::Partial(10, 99)
+        );
+        assert_eq!(
+            parse_range(Some("bytes=0-"), 3 * MAX_OPEN_RANGE_BYTES),
+            ByteRange::Partial(0, MAX_OPEN_RANGE_BYTES - 1)
+        );
+        assert_eq!(
+            parse_range(Some("bytes=100-"), 100),
+            ByteRange::Unsatisfiable
+        );
+        assert_eq!(parse_range(Some("bytes=-0"), 100), ByteRange::Unsatisfiable);
+        assert_eq!(parse_range(Some("bytes=5-2"), 100), ByteRange::Full);
+        assert_eq!(parse_range(Some("items=0-1"), 100), ByteRange::Full);
+    }
+
+    #[test]
+    fn test_stream_urls() {
+        let url = stream_url("abc", "my notes #1.md").unwrap();
+        assert!(url.ends_with("/abc/my%20notes%20%231.md"));
+        assert_eq!(token_of(&url).as_deref(), Some("abc"));
+    }
+}
@@ -0,0 +1,198 @@
+//! Do Not Disturb and focus mode awareness.
+//!
+//! While the OS is in Do Not Disturb or a focus mode, notifications are
+//! suppressed, and anything else that would interrupt the user should check
+//! `is_active` first. The state is polled in the background and each change
+//! is emitted as `focus:changed`, so the frontend can show that the app is
+//! muted.
+//!
+//! Detection per platform:
+//! - Windows: the user notification state (quiet hours, focus assist,
+//!   presentations and full-screen apps)
+//! - macOS: focus assertions in `~/Library/DoNotDisturb/DB/Assertions.json`,
+//!   which covers focus modes turned on by hand but not all scheduled ones
+//! - Linux: the `Inhibited` property of the notification server (KDE,
+//!   others), else GNOME's `show-banners` setting
+
+use serde::Serialize;
+use std::{sync::Mutex, time::Duration};
+use tauri::{AppHandle, Emitter, Manager};
+
+use crate::error::AppError;
+
+const POLL_INTERVAL: Duration = Duration::from_secs(10);
+
+/// Presentations and full-screen apps are only reported on Windows
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+#[cfg_attr(not(windows), allow(dead_code))]
+pub enum FocusReason {
+    DoNotDisturb,
+    Presentation,
+    FullScreen,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct FocusStatus {
+    pub active: bool,
+    pub reason: Option<FocusReason>,
+}
+
+#[derive(Default)]
+pub struct FocusState(Mutex<FocusStatus>);
+
+impl From<Option<FocusReason>> for FocusStatus {
+    fn from(reason: Option<FocusReason>) -> Self {
+        Self {
+            active: reason.is_some(),
+            reason,
+        }
+    }
+}
+
+#[cfg(windows)]
+fn detect() -> Option<FocusReason> {
+    use windows::Win32::UI::Shell::{
+        QUNS_BUSY, QUNS_PRESENTATION_MODE, QUNS_QUIET_TIME, QUNS_RUNNING_D3D_FULL_SCREEN,
+        SHQueryUserNotificationState,
+    };
+
+    let state = unsafe { SHQueryUserNotificationState() }.ok()?;
+    match state {
+        QUNS_QUIET_TIME => Some(FocusReason::DoNotDisturb),
+        QUNS_PRESENTATION_MODE => Some(FocusReason::Presentation),
+        QUNS_BUSY | QUNS_RUNNING_D3D_FULL_SCREEN => Some(FocusReason::FullScreen),
+        _ => None,
+    }
+}
+
+/// Whether `Assertions.json` holds an active focus assertion
+#[cfg(any(target_os = "macos", test))]
+fn has_focus_assertion(content: &str) -> bool {
+    serde_json::from_str::<serde_json::Value>(content)
+        .ok()
+        .and_then(|json| json.get("data").and_then(|data| data.as_array()).cloned())
+        .is_some_and(|data| {
+            data.iter().any(|entry| {
+                entry
+                    .get("storeAssertionRecords")
+                    .and_then(|records| records.as_array())
+                    .is_some_and(|records| !records.is_empty())
+            })
+        })
+}
+
+#[cfg(target_os = "macos")]
+fn detect() -> Option<FocusReason> {
+    let home = std::env::var_os("HOME")?;
+    let path = std::path::Path::new(&home).join("Library/DoNotDisturb/DB/Assertions.json");
+    let content = std::fs::read_to_string(path).ok()?;
+    has_focus_assertion(&content).then_some(FocusReason::DoNotDisturb)
+}
+
+#[cfg(target_os = "linux")]
+fn command_output(program: &str, args: &[&str]) -> Option<String> {
+    std::process::Command::new(program)
+        .args(args)
+        .output()
+        .ok()
+        .filter(|output| output.status.success())
+        .map(|output| String::from_utf8_lossy(&output.stdout).into_owned())
+}
+
+#[cfg(target_os = "linux")]
+fn detect() -> Option<FocusReason> {
+    let inhibited = command_output(
+        "busctl",
+        &[
+            "--user",
+            "get-property",
+            "org.freedesktop.Notifications",
+            "/org/freedesktop/Notifications",
+            "org.freedesktop.Notifications",
+            "Inhibited",
+        ],
+    )
+    .map(|out| out.trim() == "b true");
+    let quiet = match inhibited {
+        Some(inhibited) => inhibited,
+        None => command_output(
+            "gsettings",
+            &["get", "org.gnome.desktop.notifications", "show-banners"],
+        )
+        .is_some_and(|out| out.trim() == "false"),
+    };
+    quiet.then_some(FocusReason::DoNotDisturb)
+}
+
+#[cfg(not(any(windows, target_os = "macos", target_os = "linux")))]
+fn detect() -> Option<FocusReason> {
+    None
+}
+
+/// Whether the OS is in Do Not Disturb or a focus mode, as of the last poll
+pub fn is_active(app: &AppHandle) -> bool {
+    app.try_state::<FocusState>()
+        .and_then(|state| state.0.lock().ok().map(|status| status.active))
+        .unwrap_or(false)
+}
+
+async fn poll(app: &AppHandle) -> Result<(), String> {
+    let status: FocusStatus = tauri::async_runtime::spawn_blocking(detect)
+        .await
+        .map_err(|e| format!("Focus detection task failed: {}", e))?
+        .into();
+    let state = app
+        .try_state::<FocusState>()
+        .ok_or("Focus state not found")?;
+    let changed = {
+        let mut current = state.0.lock().map_err(|e| format!("Lock error: {}", e))?;
+        let changed = *current != status;
+        *current = status.clone();
+        changed
+    };
+    if changed {
+        let _ = app.emit("focus:changed", &status);
+    }
+    Ok(())
+}
+
+/// Keep the focus state current for the lifetime of the app
+pub fn start(app: &AppHandle) {
+    let app = app.clone();
+    tauri::async_runtime::spawn(async move {
+        loop {
+            if let Err(e) = poll(&app).await {
+                eprintln!("Failed to detect focus mode: {}", e);
+            }
+            tokio::time::sleep(POLL_INTERVAL).await;
+        }
+    });
+}
+
+#[tauri::command]
+pub fn get_focus_state(app: AppHandle) -> Result<FocusStatus, AppError> {
+    let state = app
+        .try_state::<FocusState>()
+        .ok_or("Focus state not found")?;
+    let status = state.0.lock().map_err(|e| format!("Lock error: {}", e))?;
+    Ok(status.clone())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_focus_assertions() {
+        let active = r#"{"data":[{"storeAssertionRecords":[{"assertionDetails":
+            {"assertionDetailsModeIdentifier":"com.apple.donotdisturb.mode.default"}}]}]}"#;
+        assert!(has_focus_assertion(active));
+        assert!(!has_focus_assertion(
+            r#"{"data":[{"storeAssertionRecords":[]}]}"#
+        ));
+        assert!(!has_focus_assertion(r#"{"data":[{}]}"#));
+        assert!(!has_focus_assertion("not json"));
+    }
+}
@@ -0,0 +1,110 @@
+//! Hashing helpers for verifying downloaded artifacts.
+//!
+//! Supports BLAKE3 and SHA-256, returning lowercase hex digests. Files are
+//! streamed through the hasher so multi-GB model weights never need to be
+//! loaded into memory at once.
+
+use serde::Deserialize;
+use sha2::Digest;
+use std::{fs::File, io::Read, path::Path};
+
+use crate::error::AppError;
+
+const READ_BUFFER_SIZE: usize = 64 * 1024;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum HashAlgorithm {
+    Blake3,
+    Sha256,
+}
+
+enum Hasher {
+    Blake3(Box<blake3::Hasher>),
+    Sha256(sha2::Sha256),
+}
+
+impl Hasher {
+    fn new(algo: HashAlgorithm) -> Self {
+        match algo {
+            HashAlgorithm::Blake3 => Self::Blake3(Box::new(blake3::Hasher::new())),
+            HashAlgorithm::Sha256 => Self::Sha256(sha2::Sha256::new()),
+        }
+    }
+
+    fn update(&mut self, data: &[u8]) {
+        match self {
+            Self::Blake3(hasher) => {
+                hasher.update(data);
+            }
+            Self::Sha256(hasher) => hasher.update(data),
+        }
+    }
+
+    fn finalize_hex(self) -> String {
+        match self {
+            Self::Blake3(hasher) => hasher.finalize().to_hex().to_string(),
+            Self::Sha256(hasher) => format!("{:x}", hasher.finalize()),
+        }
+    }
+}
+
+/// Hash an in-memory buffer and return the lowercase hex digest
+pub fn hash_bytes(data: &[u8], algo: HashAlgorithm) -> String {
+    let mut hasher = Hasher::new(algo);
+    hasher.update(data);
+    hasher.finalize_hex()
+}
+
+/// Hash a file by streaming it in fixed-size chunks
+pub fn hash_path(path: &Path, algo: HashAlgorithm) -> Result<String, String> {
+    let mut file = File::open(path)
+        .map_err(|e| format!("Failed to open {}: {}", path.display(), e))?;
+
+    let mut hasher = Hasher::new(algo);
+    let mut buffer = vec![0u8; READ_BUFFER_SIZE];
+    loop {
+        let read = file
+            .read(&mut buffer)
+            .map_err(|e| format!("Failed to read {}: {}", path.display(), e))?;
+        if read == 0 {
+            break;
+        }
+        hasher.update(&buffer[..read]);
+    }
+
+    Ok(hasher.finalize_hex())
+}
+
+#[tauri::command]
+pub async fn hash_file(path: String, algo: HashAlgorithm) -> Result<String, AppError> {
+    Ok(tauri::async_runtime::spawn_blocking(move || hash_path(Path::new(&path), algo))
+        .await
+        .map_err(|e| format!("Hash task failed: {}", e))??)
+}
+
+#[tauri::command]
+pub fn hash_text(content: String, algo: HashAlgorithm) -> String {
+    hash_bytes(content.as_bytes(), algo)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_sha256_known_vector() {
+        assert_eq!(
+            hash_bytes(b"abc", HashAlgorithm::Sha256),
+            "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad"
+        );
+    }
+
+    #[test]
+    fn test_blake3_known_vector() {
+        assert_eq!(
+            hash_bytes(b"", HashAlgorithm::Blake3),
+            "af1349b9f5f9a1a6a0404dea36dcc9499bcb25c9adc112b7cc9a93cae41f3262"
+        );
+    }
+}
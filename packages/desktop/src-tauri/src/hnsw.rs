@@ -0,0 +1,295 @@
+//! Hierarchical Navigable Small World graph for approximate nearest-neighbour
+//! search over L2-normalized embeddings.
+//!
+//! Distances are cosine distances (`1 - dot`), which assumes every inserted
+//! and queried vector has unit length. The whole graph is serde-serializable
+//! so it can be persisted next to the documents it indexes.
+
+use serde::{Deserialize, Serialize};
+use std::{
+    cmp::{Ordering, Reverse},
+    collections::{BinaryHeap, HashSet},
+};
+
+const DEFAULT_M: usize = 16;
+const DEFAULT_EF_CONSTRUCTION: usize = 100;
+
+#[derive(Clone, Copy, PartialEq)]
+struct Candidate {
+    distance: f32,
+    id: u32,
+}
+
+impl Eq for Candidate {}
+
+impl Ord for Candidate {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.distance
+            .total_cmp(&other.distance)
+            .then(self.id.cmp(&other.id))
+    }
+}
+
+impl PartialOrd for Candidate {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Hnsw {
+    /// Max neighbours per node on upper layers (layer 0 allows twice as many)
+    m: usize,
+    ef_construction: usize,
+    vectors: Vec<Vec<f32>>,
+    /// `links[node][layer]` are the neighbours of `node` on `layer`
+    links: Vec<Vec<Vec<u32>>>,
+    entry_point: Option<u32>,
+    max_layer: usize,
+    rng_state: u64,
+}
+
+impl Default for Hnsw {
+    fn default() -> Self {
+        Self::new(DEFAULT_M, DEFAULT_EF_CONSTRUCTION)
+    }
+}
+
+impl Hnsw {
+    pub fn new(m: usize, ef_construction: usize) -> Self {
+        Self {
+            m: m.max(2),
+            ef_construction: ef_construction.max(1),
+            vectors: Vec::new(),
+            links: Vec::new(),
+            entry_point: None,
+            max_layer: 0,
+            rng_state: 0x2545_f491_4f6c_dd1d,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.vectors.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.vectors.is_empty()
+    }
+
+    fn distance(a: &[f32], b: &[f32]) -> f32 {
+        1.0 - a.iter().zip(b).map(|(x, y)| x * y).sum::<f32>()
+    }
+
+    fn max_links(&self, layer: usize) -> usize {
+        if layer == 0 { self.m * 2 } else { self.m }
+    }
+
+    /// xorshift64*, kept inline so the graph layout is reproducible after a reload
+    fn next_random(&mut self) -> f64 {
+        self.rng_state ^= self.rng_state >> 12;
+        self.rng_state ^= self.rng_state << 25;
+        self.rng_state ^= self.rng_state >> 27;
+        let value = self.rng_state.wrapping_mul(0x2545_f491_4f6c_dd1d);
+        (value >> 11) as f64 / (1u64 << 53) as f64
+    }
+
+    fn random_layer(&mut self) -> usize {
+        let ml = 1.0 / (self.m as f64).ln();
+        let uniform = self.next_random().max(f64::MIN_POSITIVE);
+        (-uniform.ln() * ml).floor() as usize
+    }
+
+    /// Best-first search of a single layer, returning up to `ef` closest nodes (nearest first)
+    fn search_layer(
+        &self,
+        query: &[f32],
+        entry: &[u32],
+        ef: usize,
+        layer: usize,
+    ) -> Vec<Candidate> {
+        let mut visited: HashSet<u32> = entry.iter().copied().collect();
+        let mut candidates = BinaryHeap::new();
+        let mut results = BinaryHeap::new();
+
+        for &id in entry {
+            let candidate = Candidate {
+                distance: Self::distance(query, &self.vectors[id as usize]),
+                id,
+            };
+            candidates.push(Reverse(candidate));
+            results.push(candidate);
+        }
+
+        while let Some(Reverse(current)) = candidates.pop() {
+            let furthest = results
+                .peek()
+                .map(|c: &Candidate| c.distance)
+                .unwrap_or(f32::MAX);
+            if current.distance > furthest && results.len() >= ef {
+                break;
+            }
+
+            let Some(neighbours) = self.links[current.id as usize].get(layer) else {
+                continue;
+            };
+            for &neighbour in neighbours {
+                if !visited.insert(neighbour) {
+                    continue;
+                }
+                let candidate = Candidate {
+                    distance: Self::distance(query, &self.vectors[neighbour as usize]),
+                    id: neighbour,
+                };
+                let furthest = results.peek().map(|c| c.distance).unwrap_or(f32::MAX);
+                if results.len() < ef || candidate.distance < furthest {
+                    candidates.push(Reverse(candidate));
+                    results.push(candidate);
+                    if results.len() > ef {
+                        results.pop();
+                    }
+                }
+            }
+        }
+
+        results.into_sorted_vec()
+    }
+
+    /// Drop the furthest links of `node` on `layer` so it stays within the degree limit
+    fn prune(&mut self, node: u32, layer: usize) {
+        let max = self.max_links(layer);
+        if self.links[node as usize][layer].len() <= max {
+            return;
+        }
+
+        let base = &self.vectors[node as usize];
+        let mut scored: Vec<Candidate> = self.links[node as usize][layer]
+            .iter()
+            .map(|&id| Candidate {
+                distance: Self::distance(base, &self.vectors[id as usize]),
+                id,
+            })
+            .collect();
+        scored.sort();
+        scored.truncate(max);
+        self.links[node as usize][layer] = scored.into_iter().map(|c| c.id).collect();
+    }
+
+    /// Insert a vector and return its id (ids are assigned sequentially from 0)
+    pub fn insert(&mut self, vector: Vec<f32>) -> u32 {
+        let id = self.vectors.len() as u32;
+        let layer = self.random_layer();
+        self.vectors.push(vector);
+        self.links.push(vec![Vec::new(); layer + 1]);
+
+        let Some(mut entry) = self.entry_point else {
+            self.entry_point = Some(id);
+            self.max_layer = layer;
+            return id;
+        };
+
+        let query = self.vectors[id as usize].clone();
+
+        // Greedily descend through the layers above the new node's top layer
+        for current in (layer + 1..=self.max_layer).rev() {
+            entry = self.search_layer(&query, &[entry], 1, current)[0].id;
+        }
+
+        let mut entries = vec![entry];
+        for current in (0..=layer.min(self.max_layer)).rev() {
+            let found = self.search_layer(&query, &entries, self.ef_construction, current);
+            let neighbours: Vec<u32> = found
+                .iter()
+                .take(self.max_links(current))
+                .map(|c| c.id)
+                .collect();
+
+            for &neighbour in &neighbours {
+                self.links[neighbour as usize][current].push(id);
+                self.prune(neighbour, current);
+            }
+            self.links[id as usize][current] = neighbours;
+            entries = found.into_iter().map(|c| c.id).collect();
+        }
+
+        if layer > self.max_layer {
+            self.max_layer = layer;
+            self.entry_point = Some(id);
+        }
+
+        id
+    }
+
+    /// A graph of only the vectors `keep` accepts, with ids reassigned in
+    /// the same order
+    pub fn retain(&self, keep: impl Fn(u32) -> bool) -> Self {
+        let mut graph = Self::new(self.m, self.ef_construction);
+        for (id, vector) in self.vectors.iter().enumerate() {
+            if keep(id as u32) {
+                graph.insert(vector.clone());
+            }
+        }
+        graph
+    }
+
+    /// Return up to `k` `(id, distance)` pairs, nearest first
+    pub fn search(&self, query: &[f32], k: usize, ef: usize) -> Vec<(u32, f32)> {
+        let Some(mut entry) = self.entry_point else {
+            return Vec::new();
+        };
+
+        for layer in (1..=self.max_layer).rev() {
+            entry = self.search_layer(query, &[entry], 1, layer)[0].id;
+        }
+
+        self.search_layer(query, &[entry], ef.max(k), 0)
+            .into_iter()
+            .take(k)
+            .map(|c| (c.id, c.distance))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn unit(angle: f32) -> Vec<f32> {
+        vec![angle.cos(), angle.sin()]
+    }
+
+    #[test]
+    fn test_search_finds_nearest_neighbours() {
+        let mut index = Hnsw::new(4, 32);
+        for i in 0..200 {
+            index.insert(unit(i as f32 * 0.03));
+        }
+
+        let results = index.search(&unit(3.0), 3, 32);
+        let ids: Vec<u32> = results.iter().map(|(id, _)| *id).collect();
+        assert_eq!(ids[0], 100);
+        assert!(ids.contains(&99) && ids.contains(&101));
+    }
+
+    #[test]
+    fn test_roundtrips_through_serde() {
+        let mut index = Hnsw::default();
+        index.insert(unit(0.0));
+        index.insert(unit(1.0));
+
+        let json = serde_json::to_string(&index).unwrap();
+        let restored: Hnsw = serde_json::from_str(&json).unwrap();
+        assert_eq!(restored.search(&unit(0.9), 1, 8)[0].0, 1);
+    }
+
+    #[test]
+    fn test_retain_renumbers() {
+        let mut index = Hnsw::default();
+        for i in 0..10 {
+            index.insert(unit(i as f32 * 0.3));
+        }
+
+        let kept = index.retain(|id| id % 2 == 1);
+        assert_eq!(kept.len(), 5);
+        assert_eq!(kept.search(&unit(0.9), 1, 8)[0].0, 1);
+    }
+}
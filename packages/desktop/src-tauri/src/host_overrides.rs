@@ -0,0 +1,244 @@
+//! Host to address overrides of server profiles, like `curl --resolve`.
+//!
+//! Some internal servers only resolve on the VPN, and away from it users
+//! reach them by address. Overrides are kept per server under
+//! `serverHostOverrides` in the settings, as
+//! `{ "<server origin>": { "<host>": "<address>" } }`. They apply to health
+//! checks of that server and, on Windows, to the webview as host resolver
+//! rules, which WebView2 only reads at launch. WKWebView and WebKitGTK have
+//! no such rules, so there the webview still resolves through DNS.
+
+use serde_json::Value;
+#[cfg(windows)]
+use std::sync::OnceLock;
+use std::{
+    collections::BTreeMap,
+    net::{IpAddr, SocketAddr},
+};
+use tauri::{AppHandle, Webview};
+use tauri_plugin_store::StoreExt;
+
+use crate::{SETTINGS_STORE, atomic_store, audit, error::AppError, url_origin};
+
+const HOST_OVERRIDES_KEY: &str = "serverHostOverrides";
+/// What wry passes to WebView2 when no arguments are given; setting any
+/// replaces them
+#[cfg(windows)]
+const WEBVIEW2_DEFAULT_ARGS: &str =
+    "--disable-features=msWebOOUI,msPdfOOUI,msSmartScreenProtection";
+
+pub type HostOverrides = BTreeMap<String, IpAddr>;
+
+/// Overrides of all servers, by origin. Entries that don't parse are left
+/// out.
+pub fn all(app: &AppHandle) -> BTreeMap<String, HostOverrides> {
+    let Some(Value::Object(servers)) = app
+        .store(SETTINGS_STORE)
+        .ok()
+        .and_then(|store| store.get(HOST_OVERRIDES_KEY))
+    else {
+        return BTreeMap::new();
+    };
+    servers
+        .into_iter()
+        .filter_map(|(origin, hosts)| {
+            let hosts = hosts
+                .as_object()?
+                .iter()
+                .filter_map(|(host, address)| {
+                    Some((host.to_lowercase(), address.as_str()?.parse().ok()?))
+                })
+                .collect();
+            Some((origin, hosts))
+        })
+        .collect()
+}
+
+/// Overrides of the server at `url`
+pub fn for_server(app: &AppHandle, url: &reqwest::Url) -> HostOverrides {
+    all(app).remove(&url_origin(url)).unwrap_or_default()
+}
+
+/// Resolve overridden hosts to their addresses in `builder`'s client
+pub fn apply(
+    mut builder: reqwest::ClientBuilder,
+    overrides: &HostOverrides,
+) -> reqwest::ClientBuilder {
+    for (host, address) in overrides {
+        // The port of the URL is used
+        builder = builder.resolve(host, SocketAddr::new(*address, 0));
+    }
+    builder
+}
+
+/// Chromium's `--host-resolver-rules` for `overrides`
+#[cfg_attr(not(windows), allow(dead_code))]
+fn resolver_rules(overrides: &HostOverrides) -> String {
+    overrides
+        .iter()
+        .map(|(host, address)| match address {
+            IpAddr::V4(address) => format!("MAP {} {}", host, address),
+            IpAddr::V6(address) => format!("MAP {} [{}]", host, address),
+        })
+        .collect::<Vec<_>>()
+        .join(",")
+}
+
+/// WebView2 arguments of the app's webviews, taken when the first one is
+/// created
+#[cfg(windows)]
+static BROWSER_ARGS: OnceLock<Option<String>> = OnceLock::new();
+
+/// WebView2 arguments that apply the overrides of all servers
+#[cfg(windows)]
+fn browser_args(app: &AppHandle) -> Option<String> {
+    let mut overrides = HostOverrides::new();
+    for hosts in all(app).into_values() {
+        overrides.extend(hosts);
+    }
+    if overrides.is_empty() {
+        return None;
+    }
+    let mut args = format!(
+        "{} --host-resolver-rules=\"{}\"",
+        WEBVIEW2_DEFAULT_ARGS,
+        resolver_rules(&overrides)
+    );
+    // Arguments replace the proxy set with `proxy_url`
+    if let Some(proxy) = crate::proxy::webview_proxy(app) {
+        args.push_str(&format!(
+            " --proxy-server={}://{}:{}",
+            proxy.scheme(),
+            proxy.host_str().unwrap_or_default(),
+            proxy.port_or_known_default().unwrap_or_default()
+        ));
+    }
+    Some(args)
+}
+
+/// Create the webview of `builder` with the WebView2 arguments of the app.
+/// All its webviews share one browser process, which fails to create those
+/// with other arguments, so overrides changed since launch wait for a
+/// restart.
+#[cfg(windows)]
+pub fn with_browser_args<'a, R: tauri::Runtime, M: tauri::Manager<R>>(
+    app: &AppHandle,
+    builder: tauri::WebviewWindowBuilder<'a, R, M>,
+) -> tauri::WebviewWindowBuilder<'a, R, M> {
+    match BROWSER_ARGS.get_or_init(|| browser_args(app)) {
+        Some(args) => builder.additional_browser_args(args),
+        None => builder,
+    }
+}
+
+/// Overrides as given by the user or a profile file, checked
+pub fn parse_overrides(overrides: BTreeMap<String, String>) -> Result<HostOverrides, AppError> {
+    overrides
+        .into_iter()
+        .map(|(host, address)| {
+            let host = host.trim().to_lowercase();
+            let valid = !host.is_empty()
+                && host
+                    .chars()
+                    .all(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '.' | '_'));
+            if !valid {
+                return Err(AppError::invalid_input(format!(
+                    "Invalid host name {}",
+                    host
+                )));
+            }
+            let address = address.trim().parse().map_err(|_| {
+                AppError::invalid_input(format!("Invalid address {} for {}", address, host))
+            })?;
+            Ok((host, address))
+        })
+        .collect()
+}
+
+fn server_origin(server_url: &str) -> Result<String, AppError> {
+    reqwest::Url::parse(server_url)
+        .map(|url| url_origin(&url))
+        .map_err(|e| AppError::invalid_input(format!("Invalid server URL {}: {}", server_url, e)))
+}
+
+#[tauri::command]
+pub fn get_server_host_overrides(
+    app: AppHandle,
+    server_url: String,
+) -> Result<HostOverrides, AppError> {
+    let origin = server_origin(&server_url)?;
+    Ok(all(&app).remove(&origin).unwrap_or_default())
+}
+
+/// Replace the overrides of a server; none removes them
+#[tauri::command]
+pub fn set_server_host_overrides(
+    app: AppHandle,
+    webview: Webview,
+    server_url: String,
+    overrides: BTreeMap<String, String>,
+) -> Result<(), AppError> {
+    let args = serde_json::json!({ "serverUrl": server_url, "overrides": overrides });
+    let result = save_overrides(&app, &server_url, overrides);
+    audit::record(&webview, "set_server_host_overrides", args, &result);
+    result
+}
+
+fn save_overrides(
+    app: &AppHandle,
+    server_url: &str,
+    overrides: BTreeMap<String, String>,
+) -> Result<(), AppError> {
+    let overrides = parse_overrides(overrides)?;
+    replace(app, server_url, overrides)
+}
+
+/// Replace the overrides of the server at `server_url`; none removes them
+pub fn replace(
+    app: &AppHandle,
+    server_url: &str,
+    overrides: HostOverrides,
+) -> Result<(), AppError> {
+    let origin = server_origin(server_url)?;
+    let mut servers = all(app);
+    if overrides.is_empty() {
+        servers.remove(&origin);
+    } else {
+        servers.insert(origin, overrides);
+    }
+    let store = app
+        .store(SETTINGS_STORE)
+        .map_err(|e| format!("Failed to open settings store: {}", e))?;
+    if servers.is_empty() {
+        store.delete(HOST_OVERRIDES_KEY);
+    } else {
+        let value = serde_json::to_value(&servers)
+            .map_err(|e| format!("Failed to serialize host overrides: {}", e))?;
+        store.set(HOST_OVERRIDES_KEY, value);
+    }
+    atomic_store::save(app, SETTINGS_STORE)
+        .map_err(|e| AppError::io(format!("Failed to save settings: {}", e)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_overrides_and_rules() {
+        let overrides = parse_overrides(BTreeMap::from([
+            (" Git.Corp.example ".to_string(), "10.0.0.5".to_string()),
+            ("v6.corp.example".to_string(), "fd00::5".to_string()),
+        ]))
+        .unwrap();
+        assert_eq!(
+            resolver_rules(&overrides),
+            "MAP git.corp.example 10.0.0.5,MAP v6.corp.example [fd00::5]"
+        );
+
+        let bad_host = BTreeMap::from([("a b".to_string(), "10.0.0.5".to_string())]);
+        assert!(parse_overrides(bad_host).is_err());
+        let bad_address = BTreeMap::from([("corp".to_string(), "vpn.corp".to_string())]);
+        assert!(parse_overrides(bad_address).is_err());
+    }
+}
@@ -0,0 +1,256 @@
+//! Translations of text shown by the native side.
+//!
+//! Dialogs, palette action titles and errors meant for users are looked up
+//! in flat JSON catalogs (`locales/<language>.json`) bundled as resources.
+//! Messages take `{name}` placeholders. English is compiled in and fills in
+//! for missing languages and keys.
+//!
+//! The language follows the system locale until the frontend sets one with
+//! `set_app_language`, usually to match its own locale.
+
+use serde_json::Value;
+use std::{collections::HashMap, path::PathBuf, sync::RwLock};
+use tauri::{AppHandle, Emitter, Manager, path::BaseDirectory};
+use tauri_plugin_store::StoreExt;
+
+use crate::{SETTINGS_STORE, atomic_store, error::AppError};
+
+const LANGUAGE_KEY: &str = "appLanguage";
+const LOCALES_DIR: &str = "locales";
+const FALLBACK_LANGUAGE: &str = "en";
+const FALLBACK_CATALOG: &str = include_str!("../locales/en.json");
+
+type Messages = HashMap<String, String>;
+
+fn parse_catalog(content: &str) -> Result<Messages, String> {
+    let value: Value =
+        serde_json::from_str(content).map_err(|e| format!("Failed to parse catalog: {}", e))?;
+    let Value::Object(entries) = value else {
+        return Err("Catalog must contain a JSON object".to_string());
+    };
+    Ok(entries
+        .into_iter()
+        .filter_map(|(key, value)| value.as_str().map(|text| (key, text.to_string())))
+        .collect())
+}
+
+struct Catalog {
+    language: String,
+    messages: Messages,
+    fallback: Messages,
+}
+
+impl Catalog {
+    fn english() -> Self {
+        let fallback = parse_catalog(FALLBACK_CATALOG).unwrap_or_default();
+        Self {
+            language: FALLBACK_LANGUAGE.to_string(),
+            messages: Messages::new(),
+            fallback,
+        }
+    }
+
+    fn lookup<'a>(&'a self, key: &'a str) -> &'a str {
+        self.messages
+            .get(key)
+            .or_else(|| self.fallback.get(key))
+            .map(String::as_str)
+            .unwrap_or(key)
+    }
+}
+
+pub struct I18nState(RwLock<Catalog>);
+
+impl Default for I18nState {
+    fn default() -> Self {
+        Self(RwLock::new(Catalog::english()))
+    }
+}
+
+/// Fill `{name}` placeholders of a message
+fn format_message(template: &str, args: &[(&str, &str)]) -> String {
+    args.iter()
+        .fold(template.to_string(), |text, (name, value)| {
+            text.replace(&format!("{{{}}}", name), value)
+        })
+}
+
+/// The best bundled language for a BCP-47 tag: the exact tag, then the
+/// language without region or script, then English
+fn match_language(requested: &str, available: &[String]) -> String {
+    let requested = requested.replace('_', "-").to_lowercase();
+    let primary = requested.split('-').next().unwrap_or_default();
+    available
+        .iter()
+        .find(|language| language.to_lowercase() == requested)
+        .or_else(|| {
+            available
+                .iter()
+                .find(|language| language.to_lowercase() == primary)
+        })
+        .cloned()
+        .unwrap_or_else(|| FALLBACK_LANGUAGE.to_string())
+}
+
+fn locales_dir(app: &AppHandle) -> Option<PathBuf> {
+    app.path()
+        .resolve(LOCALES_DIR, BaseDirectory::Resource)
+        .ok()
+}
+
+fn available_languages(app: &AppHandle) -> Vec<String> {
+    let mut languages: Vec<String> = locales_dir(app)
+        .and_then(|dir| std::fs::read_dir(dir).ok())
+        .map(|entries| {
+            entries
+                .filter_map(Result::ok)
+                .map(|entry| entry.path())
+                .filter(|path| path.extension().is_some_and(|ext| ext == "json"))
+                .filter_map(|path| Some(path.file_stem()?.to_string_lossy().to_string()))
+                .collect()
+        })
+        .unwrap_or_default();
+    if !languages
+        .iter()
+        .any(|language| language == FALLBACK_LANGUAGE)
+    {
+        languages.push(FALLBACK_LANGUAGE.to_string());
+    }
+    languages.sort();
+    languages
+}
+
+fn load(app: &AppHandle, requested: &str) -> Catalog {
+    let mut catalog = Catalog::english();
+    let language = match_language(requested, &available_languages(app));
+    if language == FALLBACK_LANGUAGE {
+        return catalog;
+    }
+
+    let path = locales_dir(app).map(|dir| dir.join(format!("{}.json", language)));
+    let loaded = path
+        .as_ref()
+        .ok_or_else(|| "Failed to resolve locales directory".to_string())
+        .and_then(|path| {
+            std::fs::read_to_string(path)
+                .map_err(|e| format!("Failed to read {}: {}", path.display(), e))
+        })
+        .and_then(|content| parse_catalog(&content));
+    match loaded {
+        Ok(messages) => {
+            catalog.language = language;
+            catalog.messages = messages;
+        }
+        Err(e) => eprintln!("Failed to load {} translations: {}", language, e),
+    }
+    catalog
+}
+
+fn stored_language(app: &AppHandle) -> Option<String> {
+    app.store(SETTINGS_STORE)
+        .ok()
+        .and_then(|store| store.get(LANGUAGE_KEY))
+        .and_then(|value| value.as_str().map(String::from))
+}
+
+fn requested_language(app: &AppHandle) -> String {
+    stored_language(app)
+        .or_else(tauri_plugin_os::locale)
+        .unwrap_or_else(|| FALLBACK_LANGUAGE.to_string())
+}
+
+/// Load the catalog of the stored or system language
+pub fn init(app: &AppHandle) {
+    let catalog = load(app, &requested_language(app));
+    if let Some(state) = app.try_state::<I18nState>()
+        && let Ok(mut current) = state.0.write()
+    {
+        *current = catalog;
+    }
+}
+
+/// Load the catalog of the language again after the stored one changed,
+/// returning the language used
+pub fn reload(app: &AppHandle) -> Result<String, String> {
+    let catalog = load(app, &requested_language(app));
+    let resolved = catalog.language.clone();
+    let state = app.try_state::<I18nState>().ok_or("I18n state not found")?;
+    *state.0.write().map_err(|e| format!("Lock error: {}", e))? = catalog;
+    let _ = app.emit("i18n:language-changed", &resolved);
+    Ok(resolved)
+}
+
+/// The message for `key` in the app language
+pub fn t(app: &AppHandle, key: &str) -> String {
+    t_with(app, key, &[])
+}
+
+/// The message for `key` in the app language with placeholders filled
+pub fn t_with(app: &AppHandle, key: &str, args: &[(&str, &str)]) -> String {
+    let Some(state) = app.try_state::<I18nState>() else {
+        return format_message(key, args);
+    };
+    let Ok(catalog) = state.0.read() else {
+        return format_message(key, args);
+    };
+    format_message(catalog.lookup(key), args)
+}
+
+/// The language native text is shown in
+#[tauri::command]
+pub fn get_app_language(app: AppHandle) -> Result<String, AppError> {
+    let state = app.try_state::<I18nState>().ok_or("I18n state not found")?;
+    let catalog = state.0.read().map_err(|e| format!("Lock error: {}", e))?;
+    Ok(catalog.language.clone())
+}
+
+/// Languages with bundled translations
+#[tauri::command]
+pub fn list_app_languages(app: AppHandle) -> Vec<String> {
+    available_languages(&app)
+}
+
+/// Show native text in `language` (a BCP-47 tag), or in the system language
+/// when None. Returns the language used, which falls back to the closest
+/// bundled one.
+#[tauri::command]
+pub fn set_app_language(app: AppHandle, language: Option<String>) -> Result<String, AppError> {
+    let store = app
+        .store(SETTINGS_STORE)
+        .map_err(|e| format!("Failed to open settings store: {}", e))?;
+    match &language {
+        Some(language) => store.set(LANGUAGE_KEY, serde_json::json!(language)),
+        None => {
+            store.delete(LANGUAGE_KEY);
+        }
+    }
+    atomic_store::save(&app, SETTINGS_STORE)
+        .map_err(|e| format!("Failed to save settings: {}", e))?;
+    Ok(reload(&app)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_match_language() {
+        let available: Vec<String> = ["de", "en", "pt-BR"].map(String::from).to_vec();
+        assert_eq!(match_language("de-AT", &available), "de");
+        assert_eq!(match_language("pt_br", &available), "pt-BR");
+        assert_eq!(match_language("ko", &available), "en");
+    }
+
+    #[test]
+    fn test_fallback_catalog_fills_placeholders() {
+        let catalog = Catalog::english();
+        assert_eq!(
+            format_message(
+                catalog.lookup("dialog.permission.message"),
+                &[("origin", "https://meet.example"), ("devices", "camera")]
+            ),
+            "https://meet.example wants to use your camera."
+        );
+        assert_eq!(catalog.lookup("missing.key"), "missing.key");
+    }
+}
@@ -0,0 +1,136 @@
+//! Native image helpers for attachment previews.
+//!
+//! Thumbnails are decoded and downscaled in Rust so the webview only ever
+//! receives a small PNG instead of the multi-MB original. Re-encoding drops
+//! all EXIF/XMP metadata, after the EXIF orientation has been applied.
+
+use base64::Engine;
+use image::{DynamicImage, ImageDecoder, ImageReader, imageops::FilterType};
+use serde::Serialize;
+use std::{io::Cursor, path::Path};
+
+use crate::error::AppError;
+
+const MAX_THUMBNAIL_PX: u32 = 1024;
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Thumbnail {
+    pub width: u32,
+    pub height: u32,
+    /// PNG encoded as a `data:` URL, ready to be used as an `<img>` source
+    pub data_url: String,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ImageMetadata {
+    pub width: u32,
+    pub height: u32,
+    pub format: Option<String>,
+    pub color_type: String,
+    pub file_size: u64,
+}
+
+fn open_reader(path: &Path) -> Result<ImageReader<std::io::BufReader<std::fs::File>>, String> {
+    ImageReader::open(path)
+        .map_err(|e| format!("Failed to open image {}: {}", path.display(), e))?
+        .with_guessed_format()
+        .map_err(|e| format!("Failed to detect image format: {}", e))
+}
+
+/// Decode an image and rotate/flip it according to its EXIF orientation
+fn decode_oriented(path: &Path) -> Result<DynamicImage, String> {
+    let mut decoder = open_reader(path)?
+        .into_decoder()
+        .map_err(|e| format!("Failed to decode image: {}", e))?;
+    let orientation = decoder
+        .orientation()
+        .map_err(|e| format!("Failed to read image orientation: {}", e))?;
+
+    let mut image =
+        DynamicImage::from_decoder(decoder).map_err(|e| format!("Failed to decode image: {}", e))?;
+    image.apply_orientation(orientation);
+    Ok(image)
+}
+
+pub fn thumbnail(path: &Path, max_px: u32) -> Result<Thumbnail, String> {
+    encode_thumbnail(decode_oriented(path)?, max_px)
+}
+
+/// Downscale an image to fit in `max_px` and encode it as a PNG data URL
+pub fn encode_thumbnail(image: DynamicImage, max_px: u32) -> Result<Thumbnail, String> {
+    let max_px = max_px.clamp(1, MAX_THUMBNAIL_PX);
+    let image = if image.width() > max_px || image.height() > max_px {
+        image.resize(max_px, max_px, FilterType::Triangle)
+    } else {
+        image
+    };
+
+    let mut png = Vec::new();
+    image
+        .write_to(&mut Cursor::new(&mut png), image::ImageFormat::Png)
+        .map_err(|e| format!("Failed to encode thumbnail: {}", e))?;
+
+    Ok(Thumbnail {
+        width: image.width(),
+        height: image.height(),
+        data_url: format!(
+            "data:image/png;base64,{}",
+            base64::engine::general_purpose::STANDARD.encode(&png)
+        ),
+    })
+}
+
+pub fn metadata(path: &Path) -> Result<ImageMetadata, String> {
+    let file_size = std::fs::metadata(path)
+        .map_err(|e| format!("Failed to read file metadata: {}", e))?
+        .len();
+
+    let reader = open_reader(path)?;
+    let format = reader
+        .format()
+        .map(|f| f.extensions_str().first().copied().unwrap_or("unknown").to_string());
+
+    let mut decoder = reader
+        .into_decoder()
+        .map_err(|e| format!("Failed to decode image: {}", e))?;
+    let (width, height) = decoder.dimensions();
+    let color_type = format!("{:?}", decoder.color_type());
+
+    // Report dimensions as displayed, i.e. after EXIF rotation
+    let rotated = matches!(
+        decoder.orientation(),
+        Ok(image::metadata::Orientation::Rotate90
+            | image::metadata::Orientation::Rotate270
+            | image::metadata::Orientation::Rotate90FlipH
+            | image::metadata::Orientation::Rotate270FlipH)
+    );
+    let (width, height) = if rotated {
+        (height, width)
+    } else {
+        (width, height)
+    };
+
+    Ok(ImageMetadata {
+        width,
+        height,
+        format,
+        color_type,
+        file_size,
+    })
+}
+
+#[tauri::command]
+pub async fn generate_thumbnail(path: String, max_px: u32) -> Result<Thumbnail, AppError> {
+    Ok(tauri::async_runtime::spawn_blocking(move || thumbnail(Path::new(&path), max_px))
+        .await
+        .map_err(|e| format!("Thumbnail task failed: {}", e))??)
+}
+
+#[tauri::command]
+pub async fn get_image_metadata(path: String) -> Result<ImageMetadata, AppError> {
+    Ok(tauri::async_runtime::spawn_blocking(move || metadata(Path::new(&path)))
+        .await
+        .map_err(|e| format!("Image metadata task failed: {}", e))??)
+}
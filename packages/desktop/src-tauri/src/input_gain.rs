@@ -0,0 +1,157 @@
+//! Gain control and level checks on recorded audio.
+//!
+//! A mic set too quiet leaves speech close to the noise floor, and one set
+//! too loud clips it; either costs transcription accuracy. `InputGain` brings
+//! speech towards a steady level as it is recorded, and notes when the input
+//! itself looks misconfigured so the user can be told to fix it.
+
+use serde::{Deserialize, Serialize};
+
+/// Gain is adjusted every 10 ms
+const BLOCK_SAMPLES: usize = 16_000 / 100;
+const TARGET_RMS: f32 = 0.1;
+/// Blocks quieter than this are taken for silence and leave the gain alone
+const NOISE_FLOOR_RMS: f32 = 0.003;
+const MIN_GAIN: f32 = 0.5;
+const MAX_GAIN: f32 = 10.0;
+/// How far the gain moves towards its target each block: quickly down so
+/// loud speech doesn't clip, slowly up so pauses don't bring up the noise
+const ATTACK: f32 = 0.5;
+const RELEASE: f32 = 0.02;
+const CLIP_LEVEL: f32 = 0.99;
+/// Input is checked over windows of 3 s
+const CHECK_SAMPLES: usize = 3 * 16_000;
+/// Share of clipped samples in a window for the input to be too loud
+const CLIPPING_SHARE: f32 = 0.001;
+/// Input that was never louder than this in a recording is too quiet
+const QUIET_RMS: f32 = 0.02;
+
+/// What is wrong with the input level, in `SttStatus`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum InputHint {
+    TooQuiet,
+    TooLoud,
+}
+
+pub struct InputGain {
+    enabled: bool,
+    gain: f32,
+    /// Of the current check window
+    checked: usize,
+    clipped: usize,
+    /// RMS of the loudest block of the recording
+    loudest: f32,
+    hint: Option<InputHint>,
+}
+
+impl Default for InputGain {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            gain: 1.0,
+            checked: 0,
+            clipped: 0,
+            loudest: 0.0,
+            hint: None,
+        }
+    }
+}
+
+fn rms(samples: &[f32]) -> f32 {
+    (samples.iter().map(|s| s * s).sum::<f32>() / samples.len().max(1) as f32).sqrt()
+}
+
+impl InputGain {
+    /// Whether the gain is adjusted; the input is checked either way
+    pub fn set_enabled(&mut self, enabled: bool) {
+        self.enabled = enabled;
+    }
+
+    /// Start on a new recording
+    pub fn reset(&mut self) {
+        *self = Self {
+            enabled: self.enabled,
+            ..Self::default()
+        };
+    }
+
+    pub fn hint(&self) -> Option<InputHint> {
+        self.hint
+    }
+
+    /// Check and adjust `samples`, returning whether the hint changed
+    pub fn process(&mut self, samples: &mut [f32]) -> bool {
+        for block in samples.chunks_mut(BLOCK_SAMPLES) {
+            let level = rms(block);
+            self.checked += block.len();
+            self.clipped += block.iter().filter(|s| s.abs() >= CLIP_LEVEL).count();
+            self.loudest = self.loudest.max(level);
+            if self.enabled {
+                self.apply_gain(block, level);
+            }
+        }
+        if self.checked < CHECK_SAMPLES {
+            return false;
+        }
+
+        let hint = if self.clipped as f32 > self.checked as f32 * CLIPPING_SHARE {
+            Some(InputHint::TooLoud)
+        } else if self.loudest < QUIET_RMS {
+            Some(InputHint::TooQuiet)
+        } else {
+            None
+        };
+        self.checked = 0;
+        self.clipped = 0;
+        let changed = hint != self.hint;
+        self.hint = hint;
+        changed
+    }
+
+    /// Ramp the gain across `block` towards what brings it to the target
+    fn apply_gain(&mut self, block: &mut [f32], level: f32) {
+        let mut next = self.gain;
+        if level > NOISE_FLOOR_RMS {
+            let target = (TARGET_RMS / level).clamp(MIN_GAIN, MAX_GAIN);
+            let rate = if target < self.gain { ATTACK } else { RELEASE };
+            next += (target - self.gain) * rate;
+        }
+        let step = (next - self.gain) / block.len() as f32;
+        for (i, sample) in block.iter_mut().enumerate() {
+            *sample = (*sample * (self.gain + step * i as f32)).clamp(-1.0, 1.0);
+        }
+        self.gain = next;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_gain_and_hints() {
+        let mut quiet = InputGain::default();
+        let mut samples = vec![0.01; CHECK_SAMPLES];
+        assert!(quiet.process(&mut samples));
+        assert_eq!(quiet.hint(), Some(InputHint::TooQuiet));
+        assert!(rms(&samples[CHECK_SAMPLES - BLOCK_SAMPLES..]) > 0.05);
+
+        let mut loud = InputGain::default();
+        let mut samples = vec![0.3; CHECK_SAMPLES];
+        samples[..100].fill(1.0);
+        assert!(loud.process(&mut samples));
+        assert_eq!(loud.hint(), Some(InputHint::TooLoud));
+        assert!((samples[CHECK_SAMPLES - 1] - 0.3 * MIN_GAIN).abs() < 1e-3);
+
+        let mut samples = vec![0.3; CHECK_SAMPLES];
+        assert!(loud.process(&mut samples));
+        assert_eq!(loud.hint(), None);
+
+        let mut off = InputGain::default();
+        off.set_enabled(false);
+        let mut samples = vec![0.01; BLOCK_SAMPLES];
+        assert!(!off.process(&mut samples));
+        assert_eq!(samples[0], 0.01);
+    }
+}
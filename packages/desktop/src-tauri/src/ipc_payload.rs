@@ -0,0 +1,146 @@
+//! Delivery of large command results.
+//!
+//! Big results (logs, rendered markdown, search hits) stall the webview while
+//! they are serialized, sent and parsed as JSON. Commands that can return a
+//! lot take an optional `delivery` argument so callers can pick how the
+//! result comes back:
+//!
+//! - `json` (default): plain JSON, as before
+//! - `gzip`: gzip-compressed JSON as an `ArrayBuffer`, for
+//!   `DecompressionStream("gzip")`
+//! - `auto`: JSON when small, gzip past `COMPRESS_THRESHOLD`; callers check
+//!   for an `ArrayBuffer`
+//! - `file`: the JSON is written to a temp file and `{ path, bytes }` is
+//!   returned, for results too big to hold twice
+//!
+//! Temp files are removed once they are a few minutes old.
+
+use flate2::{Compression, write::GzEncoder};
+use serde::{Deserialize, Serialize};
+use std::{io::Write, path::PathBuf, time::Duration};
+use tauri::ipc::{InvokeResponseBody, Response};
+
+/// Serialized results past this size are compressed with `auto`
+pub const COMPRESS_THRESHOLD: usize = 64 * 1024;
+const PAYLOAD_DIR: &str = "aura-ipc";
+/// Payload files the frontend hasn't read by then are removed
+const PAYLOAD_MAX_AGE: Duration = Duration::from_secs(5 * 60);
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum Delivery {
+    #[default]
+    Json,
+    Gzip,
+    Auto,
+    File,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct PayloadFile {
+    path: String,
+    bytes: usize,
+}
+
+fn gzip(data: &[u8]) -> Result<Vec<u8>, String> {
+    let mut encoder = GzEncoder::new(Vec::with_capacity(data.len() / 4), Compression::fast());
+    encoder
+        .write_all(data)
+        .and_then(|_| encoder.finish())
+        .map_err(|e| format!("Failed to compress result: {}", e))
+}
+
+fn payload_dir() -> Result<PathBuf, String> {
+    Ok(crate::user_scope::temp_dir()?.join(PAYLOAD_DIR))
+}
+
+fn remove_stale_payloads() {
+    let Some(entries) = payload_dir()
+        .ok()
+        .and_then(|dir| std::fs::read_dir(dir).ok())
+    else {
+        return;
+    };
+    for entry in entries.filter_map(Result::ok) {
+        let stale = entry
+            .metadata()
+            .and_then(|metadata| metadata.modified())
+            .ok()
+            .and_then(|modified| modified.elapsed().ok())
+            .is_some_and(|age| age > PAYLOAD_MAX_AGE);
+        if stale {
+            let _ = std::fs::remove_file(entry.path());
+        }
+    }
+}
+
+fn write_payload(json: String) -> Result<PayloadFile, String> {
+    remove_stale_payloads();
+    let dir = payload_dir()?;
+    std::fs::create_dir_all(&dir)
+        .map_err(|e| format!("Failed to create {}: {}", dir.display(), e))?;
+    // The temp dir can be shared with other users
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        std::fs::set_permissions(&dir, std::fs::Permissions::from_mode(0o700))
+            .map_err(|e| format!("Failed to restrict {}: {}", dir.display(), e))?;
+    }
+    let path = dir.join(format!("{}.json", uuid::Uuid::new_v4()));
+    std::fs::write(&path, &json)
+        .map_err(|e| format!("Failed to write {}: {}", path.display(), e))?;
+    Ok(PayloadFile {
+        path: path.to_string_lossy().to_string(),
+        bytes: json.len(),
+    })
+}
+
+fn encode(json: String, delivery: Delivery) -> Result<InvokeResponseBody, String> {
+    match delivery {
+        Delivery::Json => Ok(InvokeResponseBody::Json(json)),
+        Delivery::Auto if json.len() < COMPRESS_THRESHOLD => Ok(InvokeResponseBody::Json(json)),
+        Delivery::Gzip | Delivery::Auto => gzip(json.as_bytes()).map(InvokeResponseBody::Raw),
+        Delivery::File => {
+            let file = write_payload(json)?;
+            serde_json::to_string(&file)
+                .map(InvokeResponseBody::Json)
+                .map_err(|e| format!("Failed to serialize result: {}", e))
+        }
+    }
+}
+
+/// Serialize a command result the way the caller asked for
+pub fn deliver<T: Serialize>(value: &T, delivery: Option<Delivery>) -> Result<Response, String> {
+    let json =
+        serde_json::to_string(value).map_err(|e| format!("Failed to serialize result: {}", e))?;
+    encode(json, delivery.unwrap_or_default()).map(Response::new)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use flate2::read::GzDecoder;
+    use std::io::Read;
+
+    #[test]
+    fn test_auto_compresses_large_results_only() {
+        let small = "\"ok\"".to_string();
+        assert!(matches!(
+            encode(small, Delivery::Auto),
+            Ok(InvokeResponseBody::Json(json)) if json == "\"ok\""
+        ));
+
+        let large = serde_json::to_string(&"log line\n".repeat(COMPRESS_THRESHOLD)).unwrap();
+        let Ok(InvokeResponseBody::Raw(bytes)) = encode(large.clone(), Delivery::Auto) else {
+            panic!("expected compressed bytes");
+        };
+        assert!(bytes.len() < large.len() / 10);
+
+        let mut decoded = String::new();
+        GzDecoder::new(bytes.as_slice())
+            .read_to_string(&mut decoded)
+            .unwrap();
+        assert_eq!(decoded, large);
+    }
+}
@@ -0,0 +1,333 @@
+//! Secrets kept by the system rather than in the settings.
+//!
+//! They go in the Keychain on macOS, the Credential Manager on Windows and
+//! the Secret Service (through `secret-tool`) on Linux, under the app
+//! identifier, so each channel has its own, and the name of the secret.
+
+use tauri::AppHandle;
+
+pub fn get(app: &AppHandle, name: &str) -> Result<Option<String>, String> {
+    platform::get(&app.config().identifier, name)
+}
+
+pub fn set(app: &AppHandle, name: &str, secret: &str) -> Result<(), String> {
+    platform::set(&app.config().identifier, name, secret)
+}
+
+/// Remove secret `name`; one that isn't there is fine
+pub fn delete(app: &AppHandle, name: &str) -> Result<(), String> {
+    platform::delete(&app.config().identifier, name)
+}
+
+#[cfg(target_os = "macos")]
+mod platform {
+    use std::ffi::{c_char, c_void};
+
+    const ERR_SEC_ITEM_NOT_FOUND: i32 = -25300;
+
+    #[link(name = "CoreFoundation", kind = "framework")]
+    unsafe extern "C" {
+        fn CFRelease(cf: *const c_void);
+    }
+
+    #[link(name = "Security", kind = "framework")]
+    unsafe extern "C" {
+        fn SecKeychainFindGenericPassword(
+            keychain: *const c_void,
+            service_len: u32,
+            service: *const c_char,
+            account_len: u32,
+            account: *const c_char,
+            password_len: *mut u32,
+            password: *mut *mut c_void,
+            item: *mut *mut c_void,
+        ) -> i32;
+        fn SecKeychainAddGenericPassword(
+            keychain: *const c_void,
+            service_len: u32,
+            service: *const c_char,
+            account_len: u32,
+            account: *const c_char,
+            password_len: u32,
+            password: *const c_void,
+            item: *mut *mut c_void,
+        ) -> i32;
+        fn SecKeychainItemModifyAttributesAndData(
+            item: *mut c_void,
+            attributes: *const c_void,
+            length: u32,
+            data: *const c_void,
+        ) -> i32;
+        fn SecKeychainItemDelete(item: *mut c_void) -> i32;
+        fn SecKeychainItemFreeContent(attributes: *const c_void, data: *mut c_void) -> i32;
+    }
+
+    /// The item for `service` and `account`, with its secret when `read`
+    fn find(
+        service: &str,
+        account: &str,
+        read: bool,
+    ) -> Result<Option<(*mut c_void, Vec<u8>)>, String> {
+        let mut len = 0;
+        let mut data = std::ptr::null_mut();
+        let mut item = std::ptr::null_mut();
+        let status = unsafe {
+            SecKeychainFindGenericPassword(
+                std::ptr::null(),
+                service.len() as u32,
+                service.as_ptr().cast(),
+                account.len() as u32,
+                account.as_ptr().cast(),
+                if read { &mut len } else { std::ptr::null_mut() },
+                if read {
+                    &mut data
+                } else {
+                    std::ptr::null_mut()
+                },
+                &mut item,
+            )
+        };
+        match status {
+            0 => {}
+            ERR_SEC_ITEM_NOT_FOUND => return Ok(None),
+            status => return Err(format!("Failed to read from the keychain: {}", status)),
+        }
+        let mut secret = Vec::new();
+        if !data.is_null() {
+            secret =
+                unsafe { std::slice::from_raw_parts(data.cast::<u8>(), len as usize) }.to_vec();
+            unsafe { SecKeychainItemFreeContent(std::ptr::null(), data) };
+        }
+        Ok(Some((item, secret)))
+    }
+
+    pub fn get(service: &str, account: &str) -> Result<Option<String>, String> {
+        let Some((item, secret)) = find(service, account, true)? else {
+            return Ok(None);
+        };
+        unsafe { CFRelease(item) };
+        String::from_utf8(secret)
+            .map(Some)
+            .map_err(|e| format!("Invalid secret in the keychain: {}", e))
+    }
+
+    pub fn set(service: &str, account: &str, secret: &str) -> Result<(), String> {
+        let status = match find(service, account, false)? {
+            Some((item, _)) => unsafe {
+                let status = SecKeychainItemModifyAttributesAndData(
+                    item,
+                    std::ptr::null(),
+                    secret.len() as u32,
+                    secret.as_ptr().cast(),
+                );
+                CFRelease(item);
+                status
+            },
+            None => unsafe {
+                SecKeychainAddGenericPassword(
+                    std::ptr::null(),
+                    service.len() as u32,
+                    service.as_ptr().cast(),
+                    account.len() as u32,
+                    account.as_ptr().cast(),
+                    secret.len() as u32,
+                    secret.as_ptr().cast(),
+                    std::ptr::null_mut(),
+                )
+            },
+        };
+        if status != 0 {
+            return Err(format!("Failed to write to the keychain: {}", status));
+        }
+        Ok(())
+    }
+
+    pub fn delete(service: &str, account: &str) -> Result<(), String> {
+        let Some((item, _)) = find(service, account, false)? else {
+            return Ok(());
+        };
+        let status = unsafe {
+            let status = SecKeychainItemDelete(item);
+            CFRelease(item);
+            status
+        };
+        if status != 0 {
+            return Err(format!("Failed to delete from the keychain: {}", status));
+        }
+        Ok(())
+    }
+}
+
+#[cfg(windows)]
+mod platform {
+    use std::ffi::c_void;
+
+    const CRED_TYPE_GENERIC: u32 = 1;
+    const CRED_PERSIST_LOCAL_MACHINE: u32 = 2;
+    const ERROR_NOT_FOUND: i32 = 1168;
+
+    #[repr(C)]
+    struct Credential {
+        flags: u32,
+        kind: u32,
+        target_name: *mut u16,
+        comment: *mut u16,
+        last_written: [u32; 2],
+        blob_size: u32,
+        blob: *mut u8,
+        persist: u32,
+        attribute_count: u32,
+        attributes: *mut c_void,
+        target_alias: *mut u16,
+        user_name: *mut u16,
+    }
+
+    #[link(name = "advapi32")]
+    unsafe extern "system" {
+        fn CredReadW(
+            target: *const u16,
+            kind: u32,
+            flags: u32,
+            credential: *mut *mut Credential,
+        ) -> i32;
+        fn CredWriteW(credential: *const Credential, flags: u32) -> i32;
+        fn CredDeleteW(target: *const u16, kind: u32, flags: u32) -> i32;
+        fn CredFree(buffer: *const c_void);
+    }
+
+    fn wide(value: &str) -> Vec<u16> {
+        value.encode_utf16().chain(std::iter::once(0)).collect()
+    }
+
+    fn target(service: &str, account: &str) -> Vec<u16> {
+        wide(&format!("{}/{}", service, account))
+    }
+
+    pub fn get(service: &str, account: &str) -> Result<Option<String>, String> {
+        let target = target(service, account);
+        let mut credential = std::ptr::null_mut();
+        if unsafe { CredReadW(target.as_ptr(), CRED_TYPE_GENERIC, 0, &mut credential) } == 0 {
+            let error = std::io::Error::last_os_error();
+            if error.raw_os_error() == Some(ERROR_NOT_FOUND) {
+                return Ok(None);
+            }
+            return Err(format!(
+                "Failed to read from the Credential Manager: {}",
+                error
+            ));
+        }
+        let secret = unsafe {
+            let credential = &*credential;
+            std::slice::from_raw_parts(credential.blob, credential.blob_size as usize).to_vec()
+        };
+        unsafe { CredFree(credential.cast()) };
+        String::from_utf8(secret)
+            .map(Some)
+            .map_err(|e| format!("Invalid secret in the Credential Manager: {}", e))
+    }
+
+    pub fn set(service: &str, account: &str, secret: &str) -> Result<(), String> {
+        let mut target = target(service, account);
+        let mut user_name = wide(account);
+        let mut blob = secret.as_bytes().to_vec();
+        let credential = Credential {
+            flags: 0,
+            kind: CRED_TYPE_GENERIC,
+            target_name: target.as_mut_ptr(),
+            comment: std::ptr::null_mut(),
+            last_written: [0; 2],
+            blob_size: blob.len() as u32,
+            blob: blob.as_mut_ptr(),
+            persist: CRED_PERSIST_LOCAL_MACHINE,
+            attribute_count: 0,
+            attributes: std::ptr::null_mut(),
+            target_alias: std::ptr::null_mut(),
+            user_name: user_name.as_mut_ptr(),
+        };
+        if unsafe { CredWriteW(&credential, 0) } == 0 {
+            return Err(format!(
+                "Failed to write to the Credential Manager: {}",
+                std::io::Error::last_os_error()
+            ));
+        }
+        Ok(())
+    }
+
+    pub fn delete(service: &str, account: &str) -> Result<(), String> {
+        let target = target(service, account);
+        if unsafe { CredDeleteW(target.as_ptr(), CRED_TYPE_GENERIC, 0) } == 0 {
+            let error = std::io::Error::last_os_error();
+            if error.raw_os_error() != Some(ERROR_NOT_FOUND) {
+                return Err(format!(
+                    "Failed to delete from the Credential Manager: {}",
+                    error
+                ));
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(not(any(target_os = "macos", windows)))]
+mod platform {
+    use std::{
+        io::Write,
+        process::{Command, Stdio},
+    };
+
+    fn secret_tool(args: &[&str], input: Option<&str>) -> Result<std::process::Output, String> {
+        let mut child = Command::new("secret-tool")
+            .args(args)
+            .stdin(if input.is_some() {
+                Stdio::piped()
+            } else {
+                Stdio::null()
+            })
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()
+            .map_err(|e| format!("Failed to run secret-tool: {}", e))?;
+        if let (Some(input), Some(mut stdin)) = (input, child.stdin.take()) {
+            // On stdin, as arguments can be seen by other processes
+            stdin
+                .write_all(input.as_bytes())
+                .map_err(|e| format!("Failed to pass the secret to secret-tool: {}", e))?;
+        }
+        child
+            .wait_with_output()
+            .map_err(|e| format!("Failed to run secret-tool: {}", e))
+    }
+
+    pub fn get(service: &str, account: &str) -> Result<Option<String>, String> {
+        let output = secret_tool(&["lookup", "service", service, "account", account], None)?;
+        // Nothing is printed for a secret that isn't there
+        if output.stdout.is_empty() {
+            return Ok(None);
+        }
+        String::from_utf8(output.stdout)
+            .map(Some)
+            .map_err(|e| format!("Invalid secret in the keyring: {}", e))
+    }
+
+    pub fn set(service: &str, account: &str, secret: &str) -> Result<(), String> {
+        let label = format!("--label={} {}", service, account);
+        let output = secret_tool(
+            &["store", &label, "service", service, "account", account],
+            Some(secret),
+        )?;
+        if !output.status.success() {
+            return Err(format!(
+                "Failed to write to the keyring: {}",
+                String::from_utf8_lossy(&output.stderr).trim()
+            ));
+        }
+        Ok(())
+    }
+
+    pub fn delete(service: &str, account: &str) -> Result<(), String> {
+        // Fails when there is nothing to clear, as there can't be without
+        // secret-tool
+        let _ = secret_tool(&["clear", "service", service, "account", account], None);
+        Ok(())
+    }
+}
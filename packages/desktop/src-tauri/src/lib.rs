@@ -1,11 +1,96 @@
+mod accessibility;
+mod actions;
+mod app_settings;
+mod app_state;
+mod asset_cache;
+mod atomic_store;
+mod audio_capture;
+mod audio_levels;
+mod audit;
+mod changelog;
 mod cli;
+mod cli_sync;
+mod connection_diagnostics;
+mod context_menu;
+mod conversation_import;
+mod diarization;
+mod diff;
+mod dictation;
+mod embeddings;
+mod error;
+mod file_stream;
+mod focus;
+mod hash;
+mod hnsw;
+mod host_overrides;
+mod i18n;
+mod images;
+mod input_gain;
+mod ipc_payload;
 mod stt;
 #[cfg(windows)]
 mod job_object;
+mod keychain;
+mod link_preview;
+mod macros;
 mod markdown;
+mod meeting_calendar;
+mod memory_watchdog;
+mod metered;
+mod model_source;
+mod notifications;
+mod ocr;
+mod onnx_runtime;
+mod outbox;
+mod panic_guard;
+mod pdf;
+mod perf;
+mod pip;
+mod policy;
+mod power;
+mod presentation;
+mod privacy;
+mod project_windows;
+mod proxy;
+mod recording_recovery;
+mod redact;
+mod release_channel;
+mod retention;
+mod retry;
+mod scratchpad;
+mod security_software;
+mod self_test;
+mod semantic_search;
+mod server_cache;
+mod server_profiles;
+mod session_index;
+mod settings;
+mod settings_service;
+mod sidecar_sandbox;
+mod stdin_bridge;
+mod stt_dev;
+mod stt_profiles;
+mod stt_remote;
+mod stt_updates;
+mod tasks;
+mod test_mode;
+mod transcript_filters;
+mod transcription_queue;
+mod transfers;
+mod trash;
+mod typography;
+mod update_source;
+mod user_scope;
+mod vault_export;
+mod vocabulary_bias;
+mod webview_permissions;
+mod window_controls;
 mod window_customizer;
+mod window_placement;
+mod window_thumbnail;
+mod workspace_trust;
 
-use cli::{install_cli, sync_cli};
+use cli::install_cli;
 use tauri_plugin_clipboard_manager::ClipboardExt;
 use futures::FutureExt;
 use futures::future;
@@ -13,19 +98,22 @@ use futures::future;
 use job_object::*;
 use std::{
     collections::VecDeque,
-    net::TcpListener,
     sync::{Arc, Mutex},
     time::{Duration, Instant},
 };
-use tauri::{AppHandle, LogicalSize, Manager, RunEvent, State, WebviewUrl, WebviewWindow};
+use tauri::{AppHandle, Manager, RunEvent, State, WebviewUrl, WebviewWindow, WindowEvent};
 #[cfg(windows)]
 use tauri_plugin_decorum::WebviewWindowExt;
-use tauri_plugin_dialog::{DialogExt, MessageDialogButtons, MessageDialogResult};
-use tauri_plugin_shell::process::{CommandChild, CommandEvent};
+use tauri_plugin_dialog::{
+    DialogExt, MessageDialogButtons, MessageDialogKind, MessageDialogResult,
+};
+use tauri_plugin_shell::process::{CommandChild, CommandEvent, TerminatedPayload};
 use tauri_plugin_shell::ShellExt;
-use tauri_plugin_store::StoreExt;
 use tokio::sync::oneshot;
 
+use crate::error::AppError;
+use crate::retry::RetryPolicy;
+use crate::webview_permissions::WebviewPermissionsPlugin;
 use crate::window_customizer::PinchZoomDisablePlugin;
 
 #[derive(Clone, serde::Serialize)]
@@ -59,15 +147,6 @@ impl ServerState {
 #[derive(Clone)]
 struct LogState(Arc<Mutex<VecDeque<String>>>);
 
-#[derive(Default)]
-struct AllowedServerCache {
-    list: Vec<String>,
-    origins: Vec<String>,
-}
-
-#[derive(Default)]
-struct AllowedServerState(Mutex<AllowedServerCache>);
-
 const MAX_LOG_ENTRIES: usize = 200;
 const GLOBAL_STORAGE: &str = "opencode.global.dat";
 const SETTINGS_STORE: &str = "opencode.settings.dat";
@@ -82,32 +161,23 @@ fn url_origin(url: &tauri::Url) -> String {
     )
 }
 
-fn parse_server_origins(list: &[String]) -> Vec<String> {
-    let mut origins = Vec::new();
-    for server in list {
-        let parsed = tauri::Url::parse(server);
-        if let Ok(parsed) = parsed {
-            origins.push(url_origin(&parsed));
-        }
+/// Navigation policy of app windows: app and server pages load in place,
+/// other web pages open in the default browser.
+fn allow_navigation(app: &AppHandle, url: &tauri::Url) -> bool {
+    // Allow internal navigation (tauri:// scheme)
+    if url.scheme() == "tauri" {
+        return true;
     }
-    origins
-}
-
-fn allowed_server_origins(app: &AppHandle, servers: &[String]) -> Vec<String> {
-    let state = app.try_state::<AllowedServerState>();
-    if let Some(state) = state {
-        let cache = state.0.lock();
-        if let Ok(mut cache) = cache {
-            if cache.list == servers {
-                return cache.origins.clone();
-            }
-            let origins = parse_server_origins(servers);
-            cache.list = servers.to_vec();
-            cache.origins = origins.clone();
-            return origins;
-        }
+    // Allow navigation to configured servers (localhost, 127.0.0.1, or remote)
+    if is_allowed_server(app, url) {
+        return true;
     }
-    parse_server_origins(servers)
+    // Open external http/https URLs in default browser
+    if url.scheme() == "http" || url.scheme() == "https" {
+        let _ = app.shell().open(url.as_str(), None);
+        return false; // Cancel internal navigation
+    }
+    true
 }
 
 /// Check if a URL's origin matches any configured server in the store.
@@ -120,42 +190,12 @@ fn is_allowed_server(app: &AppHandle, url: &tauri::Url) -> bool {
         }
     }
 
-    // Try to read the server list from the store
-    let Ok(store) = app.store(GLOBAL_STORAGE) else {
-        return false;
-    };
-
-    let Some(server_data) = store.get("server") else {
-        return false;
-    };
-
-    // Parse the server list from the stored JSON
-    let Some(list) = server_data.get("list").and_then(|v| v.as_array()) else {
-        return false;
-    };
-
-    let mut servers = Vec::new();
-    for server in list {
-        let Some(server_url) = server.as_str() else {
-            continue;
-        };
-        servers.push(server_url.to_string());
-    }
-    if servers.is_empty() {
-        return false;
-    }
-
-    // Get the origin of the navigation URL (scheme + host + port)
+    // Compare the origin of the navigation URL (scheme + host + port) with
+    // those of the configured servers
     let url_origin = url_origin(url);
-
-    let origins = allowed_server_origins(app, &servers);
-    for origin in origins {
-        if url_origin == origin {
-            return true;
-        }
-    }
-
-    false
+    settings_service::snapshot(app)
+        .server_origins
+        .contains(&url_origin)
 }
 
 #[tauri::command]
@@ -180,8 +220,15 @@ fn kill_sidecar(app: AppHandle) {
     println!("Killed server");
 }
 
+/// Process id of the sidecar, while it runs
+fn sidecar_pid(app: &AppHandle) -> Option<u32> {
+    let server_state = app.try_state::<ServerState>()?;
+    let child = server_state.child.lock().ok()?;
+    child.as_ref().map(|child| child.pid())
+}
+
 #[tauri::command]
-async fn copy_logs_to_clipboard(app: AppHandle) -> Result<(), String> {
+async fn copy_logs_to_clipboard(app: AppHandle) -> Result<(), AppError> {
     let log_state = app.try_state::<LogState>().ok_or("Log state not found")?;
 
     let logs = log_state
@@ -189,7 +236,7 @@ async fn copy_logs_to_clipboard(app: AppHandle) -> Result<(), String> {
         .lock()
         .map_err(|_| "Failed to acquire log lock")?;
 
-    let log_text = logs.iter().cloned().collect::<Vec<_>>().join("");
+    let log_text = redact::redact(&app, &logs.iter().cloned().collect::<Vec<_>>().join(""));
 
     app.clipboard()
         .write_text(log_text)
@@ -198,8 +245,7 @@ async fn copy_logs_to_clipboard(app: AppHandle) -> Result<(), String> {
     Ok(())
 }
 
-#[tauri::command]
-async fn get_logs(app: AppHandle) -> Result<String, String> {
+fn collect_logs(app: &AppHandle) -> Result<String, String> {
     let log_state = app.try_state::<LogState>().ok_or("Log state not found")?;
 
     let logs = log_state
@@ -210,71 +256,264 @@ async fn get_logs(app: AppHandle) -> Result<String, String> {
     Ok(logs.iter().cloned().collect::<Vec<_>>().join(""))
 }
 
+#[tauri::command]
+async fn get_logs(
+    app: AppHandle,
+    delivery: Option<ipc_payload::Delivery>,
+) -> Result<tauri::ipc::Response, AppError> {
+    let logs = redact::redact(&app, &collect_logs(&app)?);
+    Ok(ipc_payload::deliver(&logs, delivery)?)
+}
+
 // ============================================================================
 // Speech-to-Text Commands
 // ============================================================================
 
 #[tauri::command]
-async fn stt_get_status(app: AppHandle) -> Result<stt::SttStatus, String> {
+async fn stt_get_status(app: AppHandle) -> Result<stt::SttStatus, AppError> {
     let state = app
         .try_state::<stt::SharedSttState>()
         .ok_or("STT state not found")?;
-    let state = state.lock().map_err(|e| format!("Lock error: {}", e))?;
+    let state = state.lock().await;
     Ok(state.get_status())
 }
 
 #[tauri::command]
-async fn stt_download_model(app: AppHandle) -> Result<(), String> {
+async fn stt_download_model(app: AppHandle) -> Result<(), AppError> {
     stt::download_models(app).await
 }
 
 #[tauri::command]
-async fn stt_start_recording(app: AppHandle) -> Result<(), String> {
+async fn stt_reload_models(app: AppHandle) -> Result<(), AppError> {
+    stt::reload_models(&app).await
+}
+
+#[tauri::command]
+async fn stt_start_recording(app: AppHandle) -> Result<(), AppError> {
+    let result = stt::start_recording(&app).await;
+    app_state::publish_stt(&app).await;
+    result.map_err(|e| e.with_remediation(i18n::t(&app, "remediation.downloadSpeechModel")))
+}
+
+#[tauri::command]
+async fn stt_push_audio(app: AppHandle, samples: Vec<f32>) -> Result<(), AppError> {
     let state = app
         .try_state::<stt::SharedSttState>()
         .ok_or("STT state not found")?;
-    let mut state = state.lock().map_err(|e| format!("Lock error: {}", e))?;
-    state.start_recording()
+    let (warning, hint_changed) = {
+        let mut state = state.lock().await;
+        if state.accepts_audio() {
+            audio_levels::observe(&app, &samples);
+        }
+        (state.push_audio(samples)?, state.take_hint_change())
+    };
+    if let Some(warning) = warning {
+        stt::emit_recording_warning(&app, &warning);
+    }
+    if hint_changed {
+        app_state::publish_stt(&app).await;
+    }
+    Ok(())
 }
 
 #[tauri::command]
-async fn stt_push_audio(app: AppHandle, samples: Vec<f32>) -> Result<(), String> {
+async fn stt_pause_recording(app: AppHandle) -> Result<(), AppError> {
     let state = app
         .try_state::<stt::SharedSttState>()
         .ok_or("STT state not found")?;
-    let mut state = state.lock().map_err(|e| format!("Lock error: {}", e))?;
-    state.push_audio(samples)
+    let result = state.lock().await.pause_recording();
+    app_state::publish_stt(&app).await;
+    result
 }
 
 #[tauri::command]
-async fn stt_stop_and_transcribe(app: AppHandle) -> Result<String, String> {
+async fn stt_resume_recording(app: AppHandle) -> Result<(), AppError> {
     let state = app
         .try_state::<stt::SharedSttState>()
         .ok_or("STT state not found")?;
+    let result = state.lock().await.resume_recording();
+    app_state::publish_stt(&app).await;
+    result
+}
 
-    let (audio, inference) = {
-        let mut state = state.lock().map_err(|e| format!("Lock error: {}", e))?;
-        let audio = state.stop_recording();
-        let inference = state.inference()?;
-        (audio, inference)
+#[tauri::command]
+async fn stt_get_recording_limits(app: AppHandle) -> Result<stt::RecordingLimits, AppError> {
+    let state = app
+        .try_state::<stt::SharedSttState>()
+        .ok_or("STT state not found")?;
+    let state = state.lock().await;
+    Ok(state.recording_limits())
+}
+
+#[tauri::command]
+async fn stt_set_recording_limits(
+    app: AppHandle,
+    limits: stt::RecordingLimits,
+) -> Result<(), AppError> {
+    app_settings::apply(&app, serde_json::json!({ "sttRecordingLimits": limits }))
+        .await
+        .map(|_| ())
+}
+
+/// Apply the persisted recording limits, once changed
+async fn load_recording_limits(app: &AppHandle) {
+    let limits = app_settings::load(app).stt_recording_limits;
+    if let Some(state) = app.try_state::<stt::SharedSttState>() {
+        state.lock().await.set_recording_limits(limits);
+    }
+}
+
+#[tauri::command]
+async fn stt_stop_and_transcribe(app: AppHandle) -> Result<String, AppError> {
+    Ok(stop_and_transcribe(&app).await?)
+}
+
+/// Like `stt_stop_and_transcribe`, but returns timed segments, optionally
+/// labelled by speaker
+#[tauri::command]
+async fn stt_stop_and_transcribe_detailed(
+    app: AppHandle,
+    diarize: Option<bool>,
+    max_speakers: Option<usize>,
+) -> Result<stt::Transcription, AppError> {
+    Ok(stop_and_transcribe_detailed(&app, diarize.unwrap_or(false), max_speakers).await?)
+}
+
+/// Stop recording and queue the transcription without waiting for it. Returns
+/// the job id; the result arrives as a `stt:job` event.
+#[tauri::command]
+async fn stt_stop_and_enqueue(
+    app: AppHandle,
+    diarize: Option<bool>,
+    max_speakers: Option<usize>,
+) -> Result<u64, AppError> {
+    let (id, _) = stop_and_enqueue(&app, diarize.unwrap_or(false), max_speakers).await?;
+    Ok(id)
+}
+
+/// Dictate straight into a session: transcribe, then post the text as a prompt
+/// to the connected server without a round trip through the frontend
+#[tauri::command]
+async fn stt_transcribe_and_send(
+    app: AppHandle,
+    session_id: String,
+    prefix: Option<String>,
+) -> Result<String, AppError> {
+    let text = transcribe_and_send(&app, &session_id, prefix.as_deref()).await?;
+    macros::record(&app, macros::MacroStep::SendTranscript { session_id, prefix });
+    Ok(text)
+}
+
+async fn transcribe_and_send(
+    app: &AppHandle,
+    session_id: &str,
+    prefix: Option<&str>,
+) -> Result<String, AppError> {
+    let text = stop_and_transcribe(app).await?;
+    if text.is_empty() {
+        return Ok(text);
+    }
+
+    let prompt = match prefix {
+        Some(prefix) if !prefix.trim().is_empty() => format!("{}\n\n{}", prefix.trim_end(), text),
+        _ => text.clone(),
     };
 
-    tauri::async_runtime::spawn_blocking(move || inference.transcribe(&audio))
+    let server = connected_server(app).await?;
+    send_prompt_to_session(app, &server, session_id, &prompt)
         .await
-        .map_err(|e| format!("Transcription task failed: {}", e))?
+        .map_err(|e| server_error(app, e))?;
+
+    Ok(text)
+}
+
+async fn stop_and_transcribe(app: &AppHandle) -> Result<String, String> {
+    Ok(stop_and_transcribe_detailed(app, false, None).await?.text)
+}
+
+async fn stop_and_transcribe_detailed(
+    app: &AppHandle,
+    diarize: bool,
+    max_speakers: Option<usize>,
+) -> Result<stt::Transcription, String> {
+    let (_, result) = stop_and_enqueue(app, diarize, max_speakers).await?;
+    result
+        .await
+        .map_err(|_| "Transcription job was dropped".to_string())?
+}
+
+async fn stop_and_enqueue(
+    app: &AppHandle,
+    diarize: bool,
+    max_speakers: Option<usize>,
+) -> Result<(u64, tokio::sync::oneshot::Receiver<transcription_queue::JobResult>), String> {
+    // Check the diarization model before the recording is consumed
+    let speaker_embedder = if diarize {
+        Some(diarization::speaker_embedder(app)?)
+    } else {
+        None
+    };
+
+    // Flush any native capture into the buffer before it's taken
+    let capture_app = app.clone();
+    tauri::async_runtime::spawn_blocking(move || audio_capture::stop_capture(&capture_app))
+        .await
+        .map_err(|e| format!("Audio capture task failed: {}", e))??;
+
+    let state = app
+        .try_state::<stt::SharedSttState>()
+        .ok_or("STT state not found")?;
+
+    let (audio, inference, checkpoint) = {
+        let mut state = state.lock().await;
+        let audio = state.stop_recording();
+        let checkpoint = if audio.is_empty() {
+            None
+        } else {
+            recording_recovery::hand_off(app, state.recording_id())
+        };
+        let inference = state.inference();
+        (audio, inference, checkpoint)
+    };
+    app_state::publish_stt(app).await;
+    let inference = inference?;
+
+    let queue = app
+        .try_state::<transcription_queue::TranscriptionQueue>()
+        .ok_or("Transcription queue not found")?;
+    queue.enqueue(
+        app,
+        transcription_queue::TranscriptionJob {
+            audio,
+            inference,
+            speaker_embedder,
+            max_speakers,
+            dictation_session: dictation::reserve_segment(app),
+            checkpoint,
+        },
+    )
+}
+
+/// The server couldn't be started or reached
+fn server_error(app: &AppHandle, message: String) -> AppError {
+    AppError::network(message).with_remediation(i18n::t(app, "remediation.checkServer"))
 }
 
 #[tauri::command]
-async fn ensure_server_ready(state: State<'_, ServerState>) -> Result<ServerReadyData, String> {
+async fn ensure_server_ready(
+    app: AppHandle,
+    state: State<'_, ServerState>,
+) -> Result<ServerReadyData, AppError> {
     state
         .status
         .clone()
         .await
         .map_err(|_| "Failed to get server status".to_string())?
+        .map_err(|e| server_error(&app, e))
 }
 
 #[tauri::command]
-async fn ensure_server_started(state: State<'_, ServerState>) -> Result<(), String> {
+async fn ensure_server_started(state: State<'_, ServerState>) -> Result<(), AppError> {
     state
         .status
         .clone()
@@ -285,69 +524,78 @@ async fn ensure_server_started(state: State<'_, ServerState>) -> Result<(), Stri
 }
 
 #[tauri::command]
-fn get_default_server_url(app: AppHandle) -> Result<Option<String>, String> {
-    let store = app
-        .store(SETTINGS_STORE)
-        .map_err(|e| format!("Failed to open settings store: {}", e))?;
-
-    let value = store.get(DEFAULT_SERVER_URL_KEY);
-    match value {
-        Some(v) => Ok(v.as_str().map(String::from)),
-        None => Ok(None),
+fn get_default_server_url(app: AppHandle) -> Result<Option<String>, AppError> {
+    if let Some(url) = policy::current(&app).server_url {
+        return Ok(Some(url));
     }
+    Ok(settings_service::snapshot(&app).default_server_url)
 }
 
 #[tauri::command]
-async fn set_default_server_url(app: AppHandle, url: Option<String>) -> Result<(), String> {
-    let store = app
-        .store(SETTINGS_STORE)
-        .map_err(|e| format!("Failed to open settings store: {}", e))?;
-
-    match url {
-        Some(u) => {
-            store.set(DEFAULT_SERVER_URL_KEY, serde_json::Value::String(u));
-        }
-        None => {
-            store.delete(DEFAULT_SERVER_URL_KEY);
-        }
-    }
+async fn set_default_server_url(
+    app: AppHandle,
+    webview: tauri::Webview,
+    url: Option<String>,
+) -> Result<(), AppError> {
+    let args = serde_json::json!({ "url": url });
+    let result = save_default_server_url(&app, url).await;
+    audit::record(&webview, "set_default_server_url", args, &result);
+    result
+}
 
-    store
-        .save()
-        .map_err(|e| format!("Failed to save settings: {}", e))?;
+async fn save_default_server_url(app: &AppHandle, url: Option<String>) -> Result<(), AppError> {
+    if policy::current(app).server_url.is_some() {
+        return Err(policy::locked_error(app));
+    }
+    settings_service::set(
+        app,
+        SETTINGS_STORE,
+        DEFAULT_SERVER_URL_KEY,
+        url.map(serde_json::Value::String),
+    )
+    .await?;
 
     Ok(())
 }
 
-fn get_sidecar_port() -> u32 {
+/// The port set with `OPENCODE_PORT`, which the local server is shared on
+fn fixed_sidecar_port() -> Option<u32> {
     option_env!("OPENCODE_PORT")
         .map(|s| s.to_string())
         .or_else(|| std::env::var("OPENCODE_PORT").ok())
         .and_then(|port_str| port_str.parse().ok())
-        .unwrap_or_else(|| {
-            TcpListener::bind("127.0.0.1:0")
-                .expect("Failed to bind to find free port")
-                .local_addr()
-                .expect("Failed to get local address")
-                .port()
-        }) as u32
 }
 
-fn spawn_sidecar(app: &AppHandle, port: u32, password: Option<&str>) -> CommandChild {
+fn get_sidecar_port(app: &AppHandle) -> u32 {
+    fixed_sidecar_port().unwrap_or_else(|| user_scope::local_server_port(app) as u32)
+}
+
+/// Spawn the sidecar, with a receiver that gets how it exited once it has
+fn spawn_sidecar(
+    app: &AppHandle,
+    sidecar: cli::Sidecar,
+    port: u32,
+    password: Option<&str>,
+) -> Result<(CommandChild, oneshot::Receiver<TerminatedPayload>), String> {
     let log_state = app.state::<LogState>();
     let log_state_clone = log_state.inner().clone();
 
     let args = format!("serve --port {port}");
-    let mut command = cli::create_command(app, &args);
+    let mut command = sidecar_sandbox::sidecar_command(app, sidecar, &args)?;
     if let Some(password) = password {
         command = command.env("OPENCODE_SERVER_PASSWORD", password);
     }
+    if privacy::telemetry_disabled(app) {
+        command = command.env("DO_NOT_TRACK", "1");
+    }
 
     let (mut rx, child) = command
         .spawn()
-        .expect("Failed to spawn opencode");
+        .map_err(|e| format!("Failed to spawn opencode: {}", e))?;
 
+    let (exit_tx, exit_rx) = oneshot::channel();
     tauri::async_runtime::spawn(async move {
+        let mut exit_tx = Some(exit_tx);
         while let Some(event) = rx.recv().await {
             match event {
                 CommandEvent::Stdout(line_bytes) => {
@@ -376,12 +624,17 @@ fn spawn_sidecar(app: &AppHandle, port: u32, password: Option<&str>) -> CommandC
                         }
                     }
                 }
+                CommandEvent::Terminated(payload) => {
+                    if let Some(exit_tx) = exit_tx.take() {
+                        let _ = exit_tx.send(payload);
+                    }
+                }
                 _ => {}
             }
         }
     });
 
-    child
+    Ok((child, exit_rx))
 }
 
 fn url_is_localhost(url: &reqwest::Url) -> bool {
@@ -393,18 +646,26 @@ fn url_is_localhost(url: &reqwest::Url) -> bool {
     })
 }
 
-async fn check_server_health(url: &str, password: Option<&str>) -> bool {
+async fn check_server_health(
+    app: &AppHandle,
+    url: &str,
+    password: Option<&str>,
+    policy: &RetryPolicy,
+) -> bool {
     let Ok(url) = reqwest::Url::parse(url) else {
         return false;
     };
 
     let mut builder = reqwest::Client::builder().timeout(Duration::from_secs(3));
+    builder = host_overrides::apply(builder, &host_overrides::for_server(app, &url));
 
     if url_is_localhost(&url) {
         // Some environments set proxy variables (HTTP_PROXY/HTTPS_PROXY/ALL_PROXY) without
         // excluding loopback. reqwest respects these by default, which can prevent the desktop
         // app from reaching its own local sidecar server.
         builder = builder.no_proxy();
+    } else {
+        builder = proxy::apply(app, builder, &url);
     };
 
     let Ok(client) = builder.build() else {
@@ -420,12 +681,103 @@ async fn check_server_health(url: &str, password: Option<&str>) -> bool {
         req = req.basic_auth("opencode", Some(password));
     }
 
-    req.send()
+    retry::send(policy, req)
         .await
         .map(|r| r.status().is_success())
         .unwrap_or(false)
 }
 
+/// The server the app is connected to, once it's ready
+async fn connected_server(app: &AppHandle) -> Result<ServerReadyData, AppError> {
+    let status = app.state::<ServerState>().status.clone();
+    status
+        .await
+        .map_err(|_| AppError::from("Failed to get server status"))?
+        .map_err(|e| server_error(app, e))
+}
+
+/// A client for requests to the server at `url`, through the proxy unless
+/// it's local
+fn server_client(
+    app: &AppHandle,
+    url: &reqwest::Url,
+    timeout: Duration,
+) -> Result<reqwest::Client, String> {
+    let mut builder = reqwest::Client::builder().timeout(timeout);
+    if url_is_localhost(url) {
+        builder = builder.no_proxy();
+    } else {
+        builder = proxy::apply(app, builder, url);
+    }
+    builder
+        .build()
+        .map_err(|e| format!("Failed to create HTTP client: {}", e))
+}
+
+/// The endpoint of the server at `url` at path `segments`. Each segment is
+/// percent-encoded, so an id can't reach another endpoint; `.` and `..`,
+/// which would be dropped, are refused.
+fn server_endpoint(url: &reqwest::Url, segments: &[&str]) -> Result<reqwest::Url, String> {
+    if let Some(segment) = segments
+        .iter()
+        .find(|segment| matches!(**segment, "" | "." | ".."))
+    {
+        return Err(format!("Invalid path segment {:?}", segment));
+    }
+    let mut endpoint = url.clone();
+    endpoint.set_query(None);
+    endpoint.set_fragment(None);
+    endpoint
+        .path_segments_mut()
+        .map_err(|_| format!("Invalid server URL {}", url))?
+        .clear()
+        .extend(segments);
+    Ok(endpoint)
+}
+
+/// Queue a text prompt on a server session. Uses the async prompt endpoint so the
+/// call returns as soon as the server accepts the message instead of waiting for
+/// the agent to finish responding.
+async fn send_prompt_to_session(
+    app: &AppHandle,
+    server: &ServerReadyData,
+    session_id: &str,
+    text: &str,
+) -> Result<(), String> {
+    let url = reqwest::Url::parse(&server.url)
+        .map_err(|e| format!("Invalid server URL {}: {}", server.url, e))?;
+    let endpoint = server_endpoint(&url, &["session", session_id, "prompt_async"])
+        .map_err(|e| format!("Invalid session id {}: {}", session_id, e))?;
+
+    let client = server_client(app, &url, Duration::from_secs(10))?;
+
+    let body = serde_json::json!({
+        "parts": [{ "type": "text", "text": text }]
+    });
+
+    let mut req = client
+        .post(endpoint)
+        .header(reqwest::header::CONTENT_TYPE, "application/json")
+        .body(body.to_string());
+    if let Some(password) = &server.password {
+        req = req.basic_auth("opencode", Some(password));
+    }
+
+    let response = req
+        .send()
+        .await
+        .map_err(|e| format!("Failed to send prompt to server: {}", e))?;
+    if !response.status().is_success() {
+        return Err(format!(
+            "Server rejected prompt for session {}: HTTP {}",
+            session_id,
+            response.status()
+        ));
+    }
+
+    Ok(())
+}
+
 /// Converts a bind address hostname to a valid URL hostname for connection.
 /// - `0.0.0.0` and `::` are wildcard bind addresses, not valid connect targets
 /// - IPv6 addresses need brackets in URLs (e.g., `::1` -> `[::1]`)
@@ -462,9 +814,11 @@ async fn setup_server_connection(
     custom_url: Option<String>,
     local_port: u32,
 ) -> Result<(Option<CommandChild>, ServerReadyData), String> {
+    let local_server_disabled = policy::current(app).disable_local_server;
+
     if let Some(url) = custom_url {
         loop {
-            if check_server_health(&url, None).await {
+            if check_server_health(app, &url, None, &RetryPolicy::HEALTH).await {
                 println!("Connected to custom server: {}", url);
                 return Ok((
                     None,
@@ -475,23 +829,31 @@ async fn setup_server_connection(
                 ));
             }
 
-            const RETRY: &str = "Retry";
+            let retry = i18n::t(app, "dialog.connectionFailed.retry");
+            let fallback = if local_server_disabled {
+                i18n::t(app, "common.cancel")
+            } else {
+                i18n::t(app, "dialog.connectionFailed.startLocal")
+            };
 
-            let res = app
+            let dialog = app
                 .dialog()
-                .message(format!(
-                    "Could not connect to configured server:\n{}\n\nWould you like to retry or start a local server instead?",
-                    url
+                .message(i18n::t_with(
+                    app,
+                    "dialog.connectionFailed.message",
+                    &[("url", &url)],
                 ))
-                .title("Connection Failed")
+                .title(i18n::t(app, "dialog.connectionFailed.title"))
+                .kind(MessageDialogKind::Error)
                 .buttons(MessageDialogButtons::OkCancelCustom(
-                    RETRY.to_string(),
-                    "Start Local".to_string(),
-                ))
-                .blocking_show_with_result();
+                    retry.clone(),
+                    fallback,
+                ));
+            let res = test_mode::dialog_answer(app, "connectionFailed")
+                .unwrap_or_else(|| dialog.blocking_show_with_result());
 
             match res {
-                MessageDialogResult::Custom(name) if name == RETRY => {
+                MessageDialogResult::Custom(name) if name == retry => {
                     continue;
                 }
                 _ => {
@@ -501,9 +863,16 @@ async fn setup_server_connection(
         }
     }
 
+    if local_server_disabled {
+        return Err(i18n::t(app, "error.localServerDisabledByPolicy"));
+    }
+
     let local_url = format!("http://127.0.0.1:{local_port}");
 
-    if !check_server_health(&local_url, None).await {
+    // A server that answers on a port of our own may be another user's; only
+    // one on the port set with OPENCODE_PORT is meant to be shared
+    let shared = fixed_sidecar_port().is_some();
+    if !shared || !check_server_health(app, &local_url, None, &RetryPolicy::HEALTH).await {
         let password = uuid::Uuid::new_v4().to_string();
 
         match spawn_local_server(app, local_port, &password).await {
@@ -527,29 +896,169 @@ async fn setup_server_connection(
     }
 }
 
+/// Why the sidecar didn't become ready
+enum SidecarFailure {
+    /// It couldn't be spawned or exited right away, which another copy of
+    /// it may not
+    Exited(String),
+    TimedOut(String),
+}
+
+impl From<SidecarFailure> for String {
+    fn from(failure: SidecarFailure) -> Self {
+        match failure {
+            SidecarFailure::Exited(err) | SidecarFailure::TimedOut(err) => err,
+        }
+    }
+}
+
+/// Tell the user the bundled sidecar was started because of `err`
+fn notify_sidecar_fallback(app: &AppHandle, err: &str) {
+    eprintln!("Falling back to the bundled sidecar: {}", err);
+    if test_mode::skip_notice(app, "sidecarFallback") {
+        return;
+    }
+    app.dialog()
+        .message(i18n::t_with(
+            app,
+            "dialog.sidecarFallback.message",
+            &[("error", err)],
+        ))
+        .title(i18n::t(app, "dialog.sidecarFallback.title"))
+        .kind(MessageDialogKind::Warning)
+        .show(|_| {});
+}
+
 async fn spawn_local_server(
     app: &AppHandle,
     port: u32,
     password: &str,
 ) -> Result<CommandChild, String> {
-    let child = spawn_sidecar(app, port, Some(password));
+    let has_fallback = cli::get_fallback_sidecar_path(app).is_some();
+    let primary = cli::Sidecar::Primary.binary_path(app)?;
+    if has_fallback && !primary.exists() {
+        let err = format!("{} is missing", primary.display());
+        let err =
+            security_software::explain(app, &primary, security_software::Exit::default(), err)
+                .await;
+        notify_sidecar_fallback(app, &err);
+        return Ok(start_sidecar(app, cli::Sidecar::Fallback, port, password).await?);
+    }
+
+    // Hashing the sidecar takes a moment
+    let verify_app = app.clone();
+    let verified = tauri::async_runtime::spawn_blocking(move || cli::verify_sidecar(&verify_app))
+        .await
+        .map_err(panic_guard::join_error)?;
+    if let Err(err) = verified {
+        eprintln!("Sidecar verification failed: {}", err);
+
+        let resync = i18n::t(app, "dialog.cliVerification.resync");
+
+        let dialog = app
+            .dialog()
+            .message(i18n::t_with(
+                app,
+                "dialog.cliVerification.message",
+                &[("error", &err)],
+            ))
+            .title(i18n::t(app, "dialog.cliVerification.title"))
+            .kind(MessageDialogKind::Error)
+            .buttons(MessageDialogButtons::OkCancelCustom(
+                resync.clone(),
+                i18n::t(app, "common.cancel"),
+            ));
+        let res = match test_mode::dialog_answer(app, "cliVerification") {
+            Some(answer) => answer,
+            None => {
+                tauri::async_runtime::spawn_blocking(move || dialog.blocking_show_with_result())
+                    .await
+                    .map_err(panic_guard::join_error)?
+            }
+        };
+
+        match res {
+            MessageDialogResult::Custom(name) if name == resync => {
+                let resync_app = app.clone();
+                tauri::async_runtime::spawn_blocking(move || cli::resync_sidecar(&resync_app))
+                    .await
+                    .map_err(panic_guard::join_error)??;
+            }
+            _ => return Err(format!("Sidecar verification failed: {}", err)),
+        }
+    }
+
+    match start_sidecar(app, cli::Sidecar::Primary, port, password).await {
+        Err(SidecarFailure::Exited(err)) if has_fallback => {
+            notify_sidecar_fallback(app, &err);
+            Ok(start_sidecar(app, cli::Sidecar::Fallback, port, password).await?)
+        }
+        result => Ok(result?),
+    }
+}
+
+/// Spawn `sidecar` and wait for its server to be ready
+async fn start_sidecar(
+    app: &AppHandle,
+    sidecar: cli::Sidecar,
+    port: u32,
+    password: &str,
+) -> Result<CommandChild, SidecarFailure> {
+    let binary = sidecar.binary_path(app).map_err(SidecarFailure::Exited)?;
+    let (child, mut exited) = match spawn_sidecar(app, sidecar, port, Some(password)) {
+        Ok(spawned) => spawned,
+        Err(err) => {
+            let exit = security_software::Exit {
+                spawn_error: Some(err.clone()),
+                ..Default::default()
+            };
+            return Err(SidecarFailure::Exited(
+                security_software::explain(app, &binary, exit, err).await,
+            ));
+        }
+    };
     let url = format!("http://127.0.0.1:{port}");
 
+    let timeout = test_mode::sidecar_timeout().unwrap_or(Duration::from_secs(30));
     let timestamp = Instant::now();
     let mut delay = Duration::from_millis(10);
     let max_delay = Duration::from_millis(200);
 
     loop {
-        if timestamp.elapsed() > Duration::from_secs(30) {
-            break Err(format!(
+        if timestamp.elapsed() > timeout {
+            break Err(SidecarFailure::TimedOut(format!(
                 "Failed to spawn OpenCode Server. Logs:\n{}",
-                get_logs(app.clone()).await.unwrap()
-            ));
+                collect_logs(app).unwrap_or_default()
+            )));
         }
 
         tokio::time::sleep(delay).await;
 
-        if check_server_health(&url, Some(password)).await {
+        // Rather than waiting out the timeout for a server that's gone
+        match exited.try_recv() {
+            Err(oneshot::error::TryRecvError::Empty) => {}
+            exit => {
+                let exit = exit
+                    .map(|payload| security_software::Exit {
+                        code: payload.code,
+                        signal: payload.signal,
+                        spawn_error: None,
+                    })
+                    .unwrap_or_default();
+                let err = format!(
+                    "OpenCode Server exited before it was ready (exit code {}). Logs:\n{}",
+                    exit.code
+                        .map_or_else(|| "none".to_string(), |code| code.to_string()),
+                    collect_logs(app).unwrap_or_default()
+                );
+                break Err(SidecarFailure::Exited(
+                    security_software::explain(app, &binary, exit, err).await,
+                ));
+            }
+        }
+
+        // Polled with its own backoff
+        if check_server_health(app, &url, Some(password), &RetryPolicy::NONE).await {
             println!("Server ready after {:?}", timestamp.elapsed());
             break Ok(child);
         }
@@ -565,23 +1074,31 @@ async fn spawn_local_server(
 
 #[cfg_attr(mobile, tauri::mobile_entry_point)]
 pub fn run() {
-    let updater_enabled = option_env!("TAURI_SIGNING_PRIVATE_KEY").is_some();
+    let updater_enabled = update_source::updater_enabled();
+
+    let policy = policy::PolicyState::load();
+    policy.apply_proxy();
+
+    // Before the single-instance plugin hands the arguments to a running instance
+    stdin_bridge::spool_stdin();
 
     #[cfg(target_os = "macos")]
-    let _ = std::process::Command::new("killall")
-        .arg("opencode-cli")
-        .output();
+    release_channel::kill_stale_sidecars();
 
     let mut builder = tauri::Builder::default()
-        .plugin(tauri_plugin_single_instance::init(|app, _args, _cwd| {
+        .manage(policy)
+        .plugin(user_scope::single_instance(|app, args, cwd| {
             // Focus existing window when another instance is launched
             if let Some(window) = app.get_webview_window("main") {
                 let _ = window.set_focus();
                 let _ = window.unminimize();
             }
+            stdin_bridge::handle_args(app, &args, &cwd);
         }))
         .plugin(tauri_plugin_os::init())
         .plugin(tauri_plugin_window_state::Builder::new().build())
+        // Before the store plugin, to save the stores at exit in its place
+        .plugin(atomic_store::AtomicStorePlugin)
         .plugin(tauri_plugin_store::Builder::new().build())
         .plugin(tauri_plugin_dialog::init())
         .plugin(tauri_plugin_shell::init())
@@ -591,68 +1108,293 @@ pub fn run() {
         .plugin(tauri_plugin_http::init())
         .plugin(tauri_plugin_notification::init())
         .plugin(PinchZoomDisablePlugin)
+        .plugin(WebviewPermissionsPlugin)
         .plugin(tauri_plugin_decorum::init())
-        .invoke_handler(tauri::generate_handler![
+        .register_asynchronous_uri_scheme_protocol(asset_cache::SCHEME, asset_cache::handle)
+        .register_asynchronous_uri_scheme_protocol(file_stream::SCHEME, file_stream::handle)
+        .on_window_event(|window, event| {
+            match event {
+                WindowEvent::ScaleFactorChanged { .. } => {
+                    window_placement::ensure_on_screen(window);
+                }
+                WindowEvent::Resized(_) => window_controls::on_resized(window),
+                WindowEvent::Destroyed => {
+                    window_controls::on_destroyed(window.app_handle(), window.label())
+                }
+                _ => {}
+            }
+        })
+        .invoke_handler(panic_guard::catch_panics(perf::instrument(tauri::generate_handler![
             kill_sidecar,
             copy_logs_to_clipboard,
             get_logs,
             install_cli,
+            actions::list_actions,
+            actions::invoke_action,
+            cli_sync::cli_sync_now,
+            cli_sync::get_cli_sync_status,
+            cli_sync::set_cli_sync_policy,
+            changelog::get_changelog,
+            i18n::get_app_language,
+            i18n::list_app_languages,
+            i18n::set_app_language,
+            perf::perf_start,
+            perf::perf_stop,
+            perf::get_perf_status,
+            perf::perf_mark,
+            perf::perf_measure,
+            perf::perf_export,
+            policy::get_policy,
+            audit::get_audit_log,
+            audit::get_audit_log_enabled,
+            audit::set_audit_log_enabled,
+            update_source::get_update_source,
+            update_source::check_for_update,
+            update_source::set_update_source,
+            redact::get_log_redaction_patterns,
+            redact::set_log_redaction_patterns,
+            notifications::coalesce_notification,
+            focus::get_focus_state,
+            link_preview::fetch_link_preview,
+            asset_cache::purge_asset_cache,
+            file_stream::stream_file,
+            file_stream::revoke_file_stream,
+            transfers::list_transfers,
+            transfers::pause_transfer,
+            transfers::resume_transfer,
+            transfers::cancel_transfer,
+            transfers::clear_finished_transfers,
+            host_overrides::get_server_host_overrides,
+            host_overrides::set_server_host_overrides,
+            proxy::get_network_proxy,
+            proxy::set_network_proxy,
+            connection_diagnostics::diagnose_connection,
+            atomic_store::save_store,
+            server_profiles::export_server_profiles,
+            server_profiles::import_server_profiles,
+            workspace_trust::get_workspace_trust,
+            workspace_trust::request_workspace_trust,
+            workspace_trust::set_workspace_trust,
+            sidecar_sandbox::get_sidecar_sandbox,
+            sidecar_sandbox::set_sidecar_sandbox,
+            memory_watchdog::get_memory_usage,
+            self_test::run_self_test,
+            macros::list_macros,
+            macros::macro_start_recording,
+            macros::macro_stop_recording,
+            macros::save_macro,
+            macros::delete_macro,
+            macros::run_macro,
+            scratchpad::scratchpad_append,
+            scratchpad::scratchpad_get,
+            scratchpad::scratchpad_clear,
+            conversation_import::import_conversations,
+            vault_export::export_session_to_vault,
+            meeting_calendar::list_upcoming_meetings,
+            meeting_calendar::meeting_start_transcription,
+            meeting_calendar::meeting_stop_transcription,
+            window_placement::list_monitors,
+            window_placement::move_window_to_monitor,
+            window_placement::get_window_monitor_rules,
+            window_placement::set_window_monitor_rule,
+            window_controls::window_minimize,
+            window_controls::window_toggle_maximize,
+            window_controls::window_close,
+            window_controls::window_drag,
+            window_controls::window_double_click_action,
+            window_controls::window_show_system_menu,
+            context_menu::show_context_menu,
+            session_index::search_sessions,
+            session_index::reindex_sessions,
+            server_cache::server_cache_get,
+            server_cache::purge_server_cache,
+            outbox::outbox_submit,
+            outbox::outbox_list,
+            outbox::outbox_replay,
+            outbox::outbox_discard,
+            retention::run_retention_cleanup,
+            retention::get_storage_breakdown,
+            typography::get_window_typography,
+            typography::set_window_typography,
+            accessibility::set_accessibility_announcement,
+            app_settings::get_settings,
+            app_settings::patch_settings,
+            app_state::get_app_state,
+            tasks::list_tasks,
+            tasks::cancel_task,
+            release_channel::get_release_channel,
+            test_mode::test_script_dialogs,
+            test_mode::test_set_transcript,
+            stt_remote::stt_get_remote_status,
+            stt_remote::stt_set_remote_key,
+            stt_remote::stt_request_remote_consent,
+            stt_remote::stt_revoke_remote_consent,
+            audio_levels::stt_subscribe_levels,
+            recording_recovery::stt_recover_recording,
+            recording_recovery::stt_discard_recovered_recording,
+            stt_profiles::stt_set_app_profile,
+            stt_profiles::stt_format_for_target_app,
+            stt_updates::stt_check_model_updates,
+            stt_updates::stt_update_model,
             ensure_server_started,
             ensure_server_ready,
             get_default_server_url,
             set_default_server_url,
+            project_windows::open_project_window,
+            project_windows::list_project_windows,
+            project_windows::merge_project_windows,
+            settings::get_effective_settings,
+            stdin_bridge::take_piped_attachments,
+            webview_permissions::list_webview_permissions,
+            webview_permissions::revoke_webview_permission,
             stt_get_status,
             stt_download_model,
+            stt_reload_models,
             stt_start_recording,
             stt_push_audio,
+            stt_pause_recording,
+            stt_resume_recording,
+            stt_get_recording_limits,
+            stt_set_recording_limits,
             stt_stop_and_transcribe,
-            markdown::parse_markdown_command
-        ])
+            stt_stop_and_transcribe_detailed,
+            stt_stop_and_enqueue,
+            model_source::get_model_source,
+            model_source::set_model_source,
+            metered::get_network_status,
+            metered::set_metered_network_allowed,
+            dictation::stt_begin_session,
+            dictation::stt_end_session,
+            stt_transcribe_and_send,
+            markdown::parse_markdown_command,
+            markdown::parse_markdown_outline_command,
+            markdown::copy_rich,
+            markdown::extract_code_blocks_command,
+            markdown::copy_code_block,
+            diff::render_diff,
+            hash::hash_file,
+            hash::hash_text,
+            images::generate_thumbnail,
+            images::get_image_metadata,
+            window_thumbnail::capture_window_thumbnail,
+            window_customizer::set_window_opacity,
+            window_customizer::set_click_through,
+            window_customizer::snap_window,
+            window_customizer::list_snap_presets,
+            window_customizer::save_snap_preset,
+            pip::pip_open,
+            pip::pip_close,
+            pip::pip_set_content,
+            presentation::toggle_fullscreen,
+            presentation::get_presentation_mode,
+            presentation::set_presentation_mode,
+            power::prevent_sleep,
+            power::allow_sleep,
+            power::get_keep_awake_state,
+            privacy::get_data_inventory,
+            privacy::purge_all_local_data,
+            privacy::get_telemetry_enabled,
+            privacy::set_telemetry_enabled,
+            trash::list_trash_operations,
+            trash::undo_last_operation,
+            trash::empty_trash,
+            pdf::extract_pdf_text,
+            ocr::ocr_get_status,
+            ocr::ocr_download_model,
+            ocr::ocr_image,
+            embeddings::embeddings_get_status,
+            embeddings::embeddings_download_model,
+            embeddings::embed_texts,
+            semantic_search::semantic_search,
+            semantic_search::semantic_index_documents,
+            audio_capture::stt_list_capture_sources,
+            audio_capture::stt_start_capture,
+            audio_capture::stt_stop_capture,
+            audio_capture::stt_get_capture_state,
+            diarization::diarization_get_status,
+            diarization::diarization_download_model
+        ])))
         .setup(move |app| {
             let app = app.handle().clone();
+            panic_guard::install(&app);
+
+            // Before anything reads the settings
+            atomic_store::recover(&app);
+            settings_service::init(&app);
 
             // Initialize log state
             app.manage(LogState(Arc::new(Mutex::new(VecDeque::new()))));
-            app.manage(AllowedServerState::default());
+            app.manage(i18n::I18nState::default());
+            app.manage(perf::PerfState::default());
+            app.manage(audit::AuditState::default());
+            app.manage(notifications::NotificationState::default());
+            app.manage(focus::FocusState::default());
+            app.manage(pip::PipState::default());
+            app.manage(file_stream::StreamState::default());
+            app.manage(transfers::TransferState::default());
+            app.manage(audio_levels::LevelState::default());
+            app.manage(tasks::TaskRegistry::default());
+            app.manage(macros::MacroRecorder::default());
+            app.manage(window_controls::WindowControlsState::default());
+            app.manage(context_menu::ContextMenuState::default());
+            app.on_menu_event(|app, event| context_menu::handle_menu_event(app, &event));
+            i18n::init(&app);
+            // Nothing else starts when the managed policy can't be applied
+            if let Some(error) = app.state::<policy::PolicyState>().error() {
+                policy::refuse_to_start(&app, error);
+                return Ok(());
+            }
+            app.manage(presentation::PresentationState::default());
+            app.manage(power::KeepAwakeState::default());
+            app.manage(cli_sync::CliSyncState::default());
+            app.manage(stdin_bridge::PipedAttachmentState::default());
+            app.manage(trash::TrashState::default());
+            app.manage(test_mode::init_dialog_script());
+            trash::cleanup(&app);
+            stdin_bridge::handle_args(
+                &app,
+                &std::env::args().collect::<Vec<_>>(),
+                &std::env::current_dir().unwrap_or_default().to_string_lossy(),
+            );
 
             // Initialize STT state
             app.manage(stt::init_stt_state(&app));
+            app.manage(audio_capture::AudioCaptureState::default());
+            app.manage(diarization::init_diarization_state(&app));
+            app.manage(transcription_queue::init_transcription_queue(&app));
+            app.manage(dictation::DictationState::default());
+            app.manage(meeting_calendar::MeetingCalendarState::default());
+
+            // Initialize OCR state
+            app.manage(ocr::init_ocr_state(&app));
+
+            // Initialize embedding model state
+            app.manage(embeddings::init_embedding_state(&app));
+            app.manage(semantic_search::init_semantic_index_state(&app));
+            app.manage(session_index::init_session_index_state(&app));
+            app.manage(server_cache::ServerCacheState::default());
+            app.manage(outbox::OutboxState::default());
+            app_state::init(&app);
+            recording_recovery::init(&app);
 
             #[cfg(windows)]
             app.manage(JobObjectState::new());
 
             // Get port and create window immediately for faster perceived startup
-            let port = get_sidecar_port();
+            let port = get_sidecar_port(&app);
 
-            let primary_monitor = app.primary_monitor().ok().flatten();
-            let size = primary_monitor
-                .map(|m| m.size().to_logical(m.scale_factor()))
-                .unwrap_or(LogicalSize::new(1920, 1080));
+            let (size, position) = window_placement::initial_window_bounds(&app, "main");
 
             let app_for_nav = app.clone();
             let mut window_builder =
                 WebviewWindow::builder(&app, "main", WebviewUrl::App("/".into()))
                     .title("Aura")
-                    .inner_size(size.width as f64, size.height as f64)
+                    .inner_size(size.width, size.height)
                     .decorations(true)
                     .zoom_hotkeys_enabled(true)
                     .disable_drag_drop_handler()
-                    .on_navigation(move |url| {
-                        // Allow internal navigation (tauri:// scheme)
-                        if url.scheme() == "tauri" {
-                            return true;
-                        }
-                        // Allow navigation to configured servers (localhost, 127.0.0.1, or remote)
-                        if is_allowed_server(&app_for_nav, url) {
-                            return true;
-                        }
-                        // Open external http/https URLs in default browser
-                        if url.scheme() == "http" || url.scheme() == "https" {
-                            let _ = app_for_nav.shell().open(url.as_str(), None);
-                            return false; // Cancel internal navigation
-                        }
-                        true
-                    })
+                    .on_navigation(move |url| allow_navigation(&app_for_nav, url))
+                    .initialization_script(typography::init_script(&app, "main"))
                     .initialization_script(format!(
                         r#"
                       window.__OPENCODE__ ??= {{}};
@@ -665,19 +1407,47 @@ pub fn run() {
             {
                 window_builder = window_builder
                     .title_bar_style(tauri::TitleBarStyle::Overlay)
-                    .hidden_title(true);
+                    .hidden_title(true)
+                    .tabbing_identifier(project_windows::TABBING_IDENTIFIER);
             }
 
+            if let Some(position) = position {
+                window_builder = window_builder.position(position.x, position.y);
+            }
+
+            let window_builder = proxy::with_webview_proxy(&app, window_builder);
+
             #[cfg(windows)]
-            let window_builder = window_builder.decorations(false);
+            let window_builder = host_overrides::with_browser_args(
+                &app,
+                window_builder
+                    .decorations(false)
+                    .initialization_script(accessibility::titlebar_script(&app)),
+            );
 
             let window = window_builder.build().expect("Failed to create window");
 
+            // The restored window state may point at a display that is gone
+            window_placement::ensure_on_screen(&window.as_ref().window());
+            window_placement::apply_monitor_rule(&window.as_ref().window());
+            window_placement::watch_displays(&app);
+
             #[cfg(windows)]
-            let _ = window.create_overlay_titlebar();
+            {
+                let _ = window.create_overlay_titlebar();
+                window_controls::prepare_frameless(&window);
+            }
 
             let (tx, rx) = oneshot::channel();
             app.manage(ServerState::new(None, rx));
+            memory_watchdog::watch(&app);
+            meeting_calendar::watch(&app);
+            session_index::watch(&app);
+            outbox::watch(&app);
+            retention::watch(&app);
+            if self_test::requested() {
+                self_test::run_and_exit(&app);
+            }
 
             {
                 let app = app.clone();
@@ -685,7 +1455,9 @@ pub fn run() {
                 tauri::async_runtime::spawn(async move {
                     let mut custom_url = get_default_server_url(app.clone()).ok().flatten();
 
-                    if custom_url.is_none() {
+                    if policy::current(&app).server_url.is_some() {
+                        println!("Using server URL from policy");
+                    } else if custom_url.is_none() {
                         if let Some(cli_config) = cli::get_config(&app).await {
                             if let Some(url) = get_server_url_from_config(&cli_config) {
                                 println!("Using custom server URL from config: {}", url);
@@ -716,22 +1488,31 @@ pub fn run() {
                             }
 
                             let _ = window.eval("window.__OPENCODE__.serverReady = true;");
+                            accessibility::announce(
+                                &app,
+                                &i18n::t(&app, "a11y.serverReady"),
+                                accessibility::Priority::Polite,
+                            );
 
                             data
                         });
 
+                    app_state::publish_server(
+                        &app,
+                        match &res {
+                            Ok(data) => app_state::ServerStatus::Ready {
+                                url: data.url.clone(),
+                            },
+                            Err(e) => app_state::ServerStatus::Failed { error: e.clone() },
+                        },
+                    );
                     let _ = tx.send(res);
                 });
             }
 
-            {
-                let app = app.clone();
-                tauri::async_runtime::spawn(async move {
-                    if let Err(e) = sync_cli(app) {
-                        eprintln!("Failed to sync CLI: {e}");
-                    }
-                });
-            }
+            cli_sync::start(&app);
+            focus::start(&app);
+            changelog::check_app_update(&app);
 
             Ok(())
         });
@@ -0,0 +1,481 @@
+//! Previews of links pasted in chats.
+//!
+//! The webview can't fetch other origins, and shouldn't: a page can point
+//! anywhere, including at services on the user's machine or network. Pages
+//! are fetched here instead, with short timeouts and a size limit, and only
+//! from public addresses. The host is resolved once and the request pinned to
+//! the checked addresses, and redirects are followed by hand so every hop is
+//! checked the same way. Hosts on private networks (an intranet wiki) can be
+//! allowed in the settings (`linkPreviewAllowedHosts`).
+//!
+//! Previews are cached on disk for a day.
+
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::HashMap,
+    net::{IpAddr, SocketAddr},
+    path::PathBuf,
+    sync::LazyLock,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+use tauri::{AppHandle, Manager, path::BaseDirectory};
+
+use crate::{
+    app_settings,
+    error::AppError,
+    retry::{self, RetryPolicy},
+};
+
+const CACHE_DIR: &str = "link-previews";
+const CACHE_TTL_SECS: u64 = 24 * 60 * 60;
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(3);
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(8);
+const MAX_REDIRECTS: usize = 5;
+/// Metadata is in the head, so the rest of a page isn't read
+const MAX_BODY_BYTES: usize = 512 * 1024;
+/// Longer titles and descriptions are cut
+const MAX_TEXT_CHARS: usize = 300;
+
+static TITLE: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"(?is)<title[^>]*>(.*?)</title>").expect("valid title regex"));
+static TAG: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"(?is)<(meta|link)\b([^>]*)>").expect("valid meta tag regex"));
+static ATTRIBUTE: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(r#"(?s)([a-zA-Z_:][-a-zA-Z0-9_:.]*)\s*=\s*(?:"([^"]*)"|'([^']*)'|([^\s"'>]+))"#)
+        .expect("valid attribute regex")
+});
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct LinkPreview {
+    /// Where the link led after redirects
+    pub url: String,
+    pub title: Option<String>,
+    pub description: Option<String>,
+    pub site_name: Option<String>,
+    pub image: Option<String>,
+    pub favicon: Option<String>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct CachedPreview {
+    fetched_at: u64,
+    preview: LinkPreview,
+}
+
+fn now_secs() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0)
+}
+
+/// Loopback, private, link-local, shared, multicast and reserved addresses
+fn is_private(ip: IpAddr) -> bool {
+    match ip {
+        IpAddr::V4(ip) => {
+            let [a, b, c, _] = ip.octets();
+            ip.is_private()
+                || ip.is_loopback()
+                || ip.is_link_local()
+                || ip.is_broadcast()
+                || ip.is_documentation()
+                || ip.is_unspecified()
+                || ip.is_multicast()
+                || a == 0
+                // Shared address space (carrier-grade NAT)
+                || (a == 100 && (64..128).contains(&b))
+                || (a == 192 && b == 0 && c == 0)
+                // Benchmarking
+                || (a == 198 && (18..20).contains(&b))
+                || a >= 240
+        }
+        IpAddr::V6(ip) => {
+            if let Some(v4) = ip.to_ipv4_mapped() {
+                return is_private(IpAddr::V4(v4));
+            }
+            let first = ip.segments()[0];
+            ip.is_loopback()
+                || ip.is_unspecified()
+                || ip.is_multicast()
+                // Unique local
+                || (first & 0xfe00) == 0xfc00
+                // Link-local
+                || (first & 0xffc0) == 0xfe80
+                // Documentation
+                || (first == 0x2001 && ip.segments()[1] == 0x0db8)
+        }
+    }
+}
+
+/// Hosts on private networks that may be fetched from
+pub fn allowed_hosts(app: &AppHandle) -> Vec<String> {
+    app_settings::load(app)
+        .link_preview_allowed_hosts
+        .into_iter()
+        .map(|host| host.to_lowercase())
+        .collect()
+}
+
+/// Addresses of the URL's host, refused if any of them is private and the
+/// host isn't allowed
+async fn checked_addresses(
+    url: &reqwest::Url,
+    allowed: &[String],
+) -> Result<Vec<SocketAddr>, AppError> {
+    if !matches!(url.scheme(), "http" | "https") {
+        return Err(AppError::invalid_input(format!(
+            "Only http and https links can be previewed: {}",
+            url
+        )));
+    }
+    let host = url
+        .host_str()
+        .ok_or_else(|| AppError::invalid_input(format!("Link has no host: {}", url)))?;
+    let port = url.port_or_known_default().unwrap_or(443);
+    let host = host.trim_start_matches('[').trim_end_matches(']');
+
+    let addresses: Vec<SocketAddr> = match host.parse::<IpAddr>() {
+        Ok(ip) => vec![SocketAddr::new(ip, port)],
+        Err(_) => tokio::net::lookup_host((host, port))
+            .await
+            .map_err(|e| AppError::network(format!("Failed to resolve {}: {}", host, e)))?
+            .collect(),
+    };
+    if addresses.is_empty() {
+        return Err(AppError::network(format!("Failed to resolve {}", host)));
+    }
+    if addresses.iter().any(|address| is_private(address.ip()))
+        && !allowed
+            .iter()
+            .any(|allowed| allowed == &host.to_lowercase())
+    {
+        return Err(AppError::permission_denied(format!(
+            "{} is on a private network",
+            host
+        )));
+    }
+    Ok(addresses)
+}
+
+/// A response body, or its start
+pub struct Fetched {
+    /// Where the request led after redirects
+    pub url: reqwest::Url,
+    pub content_type: String,
+    pub body: Vec<u8>,
+    /// Whether the body was longer than asked for
+    pub truncated: bool,
+}
+
+/// GET `url` from public addresses, or hosts in `allowed`, following
+/// redirects the same way. At most `max_bytes` of the body are read.
+pub async fn fetch(
+    url: reqwest::Url,
+    allowed: &[String],
+    accept: &str,
+    max_bytes: usize,
+) -> Result<Fetched, AppError> {
+    let mut url = url;
+    for _ in 0..=MAX_REDIRECTS {
+        let addresses = checked_addresses(&url, allowed).await?;
+        let mut builder = reqwest::Client::builder()
+            .connect_timeout(CONNECT_TIMEOUT)
+            .timeout(REQUEST_TIMEOUT)
+            .redirect(reqwest::redirect::Policy::none());
+        if let Some(host) = url.host_str() {
+            // Connect to what was checked, not to what DNS answers next
+            builder = builder.resolve_to_addrs(host, &addresses);
+        }
+        let client = builder
+            .build()
+            .map_err(|e| format!("Failed to create HTTP client: {}", e))?;
+        let request = client
+            .get(url.clone())
+            .header(reqwest::header::USER_AGENT, "Aura-Desktop")
+            .header(reqwest::header::ACCEPT, accept);
+        let mut response = retry::send(&RetryPolicy::PREVIEW, request)
+            .await
+            .map_err(|e| AppError::network(format!("Failed to fetch {}: {}", url, e)))?;
+
+        if response.status().is_redirection() {
+            let location = response
+                .headers()
+                .get(reqwest::header::LOCATION)
+                .and_then(|location| location.to_str().ok())
+                .ok_or_else(|| {
+                    AppError::network(format!("Redirect without location from {}", url))
+                })?;
+            url = url
+                .join(location)
+                .map_err(|e| AppError::network(format!("Invalid redirect from {}: {}", url, e)))?;
+            continue;
+        }
+        if matches!(
+            response.status(),
+            reqwest::StatusCode::NOT_FOUND | reqwest::StatusCode::GONE
+        ) {
+            return Err(AppError::not_found(format!("{} not found", url)));
+        }
+        if !response.status().is_success() {
+            return Err(AppError::network(format!(
+                "Failed to fetch {}: HTTP {}",
+                url,
+                response.status()
+            )));
+        }
+        let content_type = response
+            .headers()
+            .get(reqwest::header::CONTENT_TYPE)
+            .and_then(|value| value.to_str().ok())
+            .unwrap_or_default()
+            .to_lowercase();
+
+        let mut body = Vec::new();
+        let mut truncated = false;
+        while let Some(chunk) = response
+            .chunk()
+            .await
+            .map_err(|e| AppError::network(format!("Failed to read {}: {}", url, e)))?
+        {
+            body.extend_from_slice(&chunk);
+            if body.len() > max_bytes {
+                body.truncate(max_bytes);
+                truncated = true;
+                break;
+            }
+        }
+        return Ok(Fetched {
+            url,
+            content_type,
+            body,
+            truncated,
+        });
+    }
+    Err(AppError::network(format!(
+        "Too many redirects from {}",
+        url
+    )))
+}
+
+fn decode_entities(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    let mut rest = text;
+    while let Some(at) = rest.find('&') {
+        out.push_str(&rest[..at]);
+        rest = &rest[at..];
+        let Some(end) = rest.find(';').filter(|end| *end <= 10) else {
+            out.push('&');
+            rest = &rest[1..];
+            continue;
+        };
+        let entity = &rest[1..end];
+        let decoded = match entity {
+            "amp" => Some('&'),
+            "lt" => Some('<'),
+            "gt" => Some('>'),
+            "quot" => Some('"'),
+            "apos" => Some('\''),
+            "nbsp" => Some(' '),
+            _ => entity
+                .strip_prefix("#x")
+                .or_else(|| entity.strip_prefix("#X"))
+                .and_then(|hex| u32::from_str_radix(hex, 16).ok())
+                .or_else(|| entity.strip_prefix('#').and_then(|n| n.parse().ok()))
+                .and_then(char::from_u32),
+        };
+        match decoded {
+            Some(c) => {
+                out.push(c);
+                rest = &rest[end + 1..];
+            }
+            None => {
+                out.push('&');
+                rest = &rest[1..];
+            }
+        }
+    }
+    out.push_str(rest);
+    out
+}
+
+/// Decoded, with whitespace collapsed and cut to `MAX_TEXT_CHARS`
+fn clean_text(text: &str) -> Option<String> {
+    let text = decode_entities(text)
+        .split_whitespace()
+        .collect::<Vec<_>>()
+        .join(" ");
+    if text.is_empty() {
+        return None;
+    }
+    Some(match text.char_indices().nth(MAX_TEXT_CHARS) {
+        Some((at, _)) => format!("{}…", &text[..at]),
+        None => text,
+    })
+}
+
+fn parse_preview(url: &reqwest::Url, html: &str) -> LinkPreview {
+    let mut meta = HashMap::new();
+    let mut icon = None;
+    for tag in TAG.captures_iter(html) {
+        let attributes: HashMap<String, String> = ATTRIBUTE
+            .captures_iter(&tag[2])
+            .map(|attribute| {
+                let value = attribute
+                    .get(2)
+                    .or_else(|| attribute.get(3))
+                    .or_else(|| attribute.get(4))
+                    .map_or("", |value| value.as_str());
+                (attribute[1].to_lowercase(), decode_entities(value))
+            })
+            .collect();
+        if tag[1].eq_ignore_ascii_case("link") {
+            let is_icon = attributes.get("rel").is_some_and(|rel| {
+                rel.split_whitespace()
+                    .any(|rel| rel.eq_ignore_ascii_case("icon"))
+            });
+            if is_icon && icon.is_none() {
+                icon = attributes.get("href").cloned();
+            }
+            continue;
+        }
+        let key = attributes
+            .get("property")
+            .or_else(|| attributes.get("name"))
+            .map(|key| key.to_lowercase());
+        if let (Some(key), Some(content)) = (key, attributes.get("content")) {
+            meta.entry(key).or_insert_with(|| content.clone());
+        }
+    }
+
+    let first = |keys: &[&str]| keys.iter().find_map(|key| meta.get(*key).cloned());
+    let absolute = |link: String| url.join(link.trim()).ok().map(String::from);
+    LinkPreview {
+        url: url.to_string(),
+        title: first(&["og:title", "twitter:title"])
+            .or_else(|| TITLE.captures(html).map(|title| title[1].to_string()))
+            .and_then(|title| clean_text(&title)),
+        description: first(&["og:description", "twitter:description", "description"])
+            .and_then(|description| clean_text(&description)),
+        site_name: first(&["og:site_name"]).and_then(|name| clean_text(&name)),
+        image: first(&["og:image", "og:image:url", "twitter:image"]).and_then(absolute),
+        favicon: icon
+            .and_then(absolute)
+            .or_else(|| url.join("/favicon.ico").ok().map(String::from)),
+    }
+}
+
+fn cache_path(app: &AppHandle, url: &str) -> Result<PathBuf, String> {
+    let name = format!("{}.json", blake3::hash(url.as_bytes()).to_hex());
+    app.path()
+        .resolve(format!("{}/{}", CACHE_DIR, name), BaseDirectory::AppCache)
+        .map_err(|e| format!("Failed to resolve link preview cache path: {}", e))
+}
+
+fn read_cache(app: &AppHandle, url: &str) -> Option<LinkPreview> {
+    let contents = std::fs::read_to_string(cache_path(app, url).ok()?).ok()?;
+    let cached: CachedPreview = serde_json::from_str(&contents).ok()?;
+    (now_secs().saturating_sub(cached.fetched_at) < CACHE_TTL_SECS).then_some(cached.preview)
+}
+
+fn write_cache(app: &AppHandle, url: &str, preview: &LinkPreview) -> Result<(), String> {
+    let path = cache_path(app, url)?;
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent)
+            .map_err(|e| format!("Failed to create {}: {}", parent.display(), e))?;
+    }
+    let contents = serde_json::to_string(&CachedPreview {
+        fetched_at: now_secs(),
+        preview: preview.clone(),
+    })
+    .map_err(|e| format!("Failed to serialize link preview: {}", e))?;
+    std::fs::write(&path, contents)
+        .map_err(|e| format!("Failed to write link preview cache: {}", e))
+}
+
+/// Title, description, image and favicon of the page a link points to
+#[tauri::command]
+pub async fn fetch_link_preview(app: AppHandle, url: String) -> Result<LinkPreview, AppError> {
+    let parsed = reqwest::Url::parse(url.trim())
+        .map_err(|e| AppError::invalid_input(format!("Invalid link {}: {}", url, e)))?;
+    if let Some(preview) = read_cache(&app, parsed.as_str()) {
+        return Ok(preview);
+    }
+
+    let page = fetch(
+        parsed.clone(),
+        &allowed_hosts(&app),
+        "text/html,application/xhtml+xml",
+        MAX_BODY_BYTES,
+    )
+    .await?;
+    if !page.content_type.contains("html") {
+        return Err(AppError::unavailable(format!(
+            "{} is not a web page",
+            page.url
+        )));
+    }
+    let preview = parse_preview(&page.url, &String::from_utf8_lossy(&page.body));
+    if let Err(e) = write_cache(&app, parsed.as_str(), &preview) {
+        eprintln!("{}", e);
+    }
+    Ok(preview)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_private_addresses() {
+        for ip in [
+            "127.0.0.1",
+            "10.1.2.3",
+            "172.16.0.1",
+            "192.168.1.1",
+            "169.254.169.254",
+            "100.64.0.1",
+            "0.0.0.0",
+            "::1",
+            "fd00::1",
+            "fe80::1",
+            "::ffff:127.0.0.1",
+        ] {
+            assert!(is_private(ip.parse().unwrap()), "{}", ip);
+        }
+        for ip in ["93.184.216.34", "1.1.1.1", "2606:4700::1111"] {
+            assert!(!is_private(ip.parse().unwrap()), "{}", ip);
+        }
+    }
+
+    #[test]
+    fn test_parse_preview() {
+        let url = reqwest::Url::parse("https://example.com/post/1").unwrap();
+        let html = r#"<html><head>
+            <title>Fallback</title>
+            <meta property="og:title" content="Hello &amp; welcome">
+            <meta name='description' content="A   post">
+            <meta property="og:image" content="/img/cover.png" />
+            <link rel="shortcut icon" href="/static/icon.png">
+            </head>"#;
+        let preview = parse_preview(&url, html);
+        assert_eq!(preview.title.as_deref(), Some("Hello & welcome"));
+        assert_eq!(preview.description.as_deref(), Some("A post"));
+        assert_eq!(
+            preview.image.as_deref(),
+            Some("https://example.com/img/cover.png")
+        );
+        assert_eq!(
+            preview.favicon.as_deref(),
+            Some("https://example.com/static/icon.png")
+        );
+
+        let preview = parse_preview(&url, "<title>Only &#x27;title&#39;</title>");
+        assert_eq!(preview.title.as_deref(), Some("Only 'title'"));
+        assert_eq!(
+            preview.favicon.as_deref(),
+            Some("https://example.com/favicon.ico")
+        );
+    }
+}
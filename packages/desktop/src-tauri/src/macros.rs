@@ -0,0 +1,306 @@
+//! Macros: named sequences of native actions replayed in one go.
+//!
+//! Between `macro_start_recording` and `macro_stop_recording` every native
+//! action run through `invoke_action`, and every transcript sent with
+//! `stt_transcribe_and_send`, is recorded as a step. Macros are kept under
+//! `macros` in the settings, where steps can also be edited by hand:
+//!
+//! ```json
+//! [{ "name": "Dictate left", "shortcut": "CmdOrCtrl+Alt+D", "steps": [
+//!   { "type": "action", "id": "window.snapLeft" },
+//!   { "type": "action", "id": "stt.toggleRecording" },
+//!   { "type": "wait", "ms": 10000 },
+//!   { "type": "sendTranscript", "sessionId": "ses_123" },
+//!   { "type": "notify", "title": "Sent", "body": "Dictation sent" }
+//! ] }]
+//! ```
+//!
+//! `run_macro` runs the steps in order and stops at the first that fails.
+//! Notifications go to the frontend as `macro:notification`, to be shown
+//! like its own. The frontend binds each macro's `shortcut` to `run_macro`.
+
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use std::{sync::Mutex, time::Duration};
+use tauri::{AppHandle, Emitter, Manager, Webview};
+use tauri_plugin_store::StoreExt;
+
+use crate::{SETTINGS_STORE, actions, atomic_store, audit, error::AppError};
+
+const MACROS_KEY: &str = "macros";
+/// Longest single wait, so a typo can't stall a macro for hours
+const MAX_WAIT_MS: u64 = 60_000;
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(
+    tag = "type",
+    rename_all = "camelCase",
+    rename_all_fields = "camelCase"
+)]
+pub enum MacroStep {
+    /// A native action, by id
+    Action {
+        id: String,
+    },
+    /// Stop recording and send the transcript to a session
+    SendTranscript {
+        session_id: String,
+        prefix: Option<String>,
+    },
+    Notify {
+        title: String,
+        body: Option<String>,
+    },
+    Wait {
+        ms: u64,
+    },
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Macro {
+    pub name: String,
+    /// Accelerator the frontend runs the macro with
+    #[serde(default)]
+    pub shortcut: Option<String>,
+    pub steps: Vec<MacroStep>,
+}
+
+/// Steps recorded so far, while recording
+#[derive(Default)]
+pub struct MacroRecorder(Mutex<Option<Vec<MacroStep>>>);
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct MacroNotification {
+    name: String,
+    title: String,
+    body: Option<String>,
+}
+
+fn load(app: &AppHandle) -> Vec<Macro> {
+    app.store(SETTINGS_STORE)
+        .ok()
+        .and_then(|store| store.get(MACROS_KEY))
+        .and_then(|value| serde_json::from_value(value).ok())
+        .unwrap_or_default()
+}
+
+fn store_macros(app: &AppHandle, macros: &[Macro]) -> Result<(), AppError> {
+    let store = app
+        .store(SETTINGS_STORE)
+        .map_err(|e| format!("Failed to open settings store: {}", e))?;
+    let value =
+        serde_json::to_value(macros).map_err(|e| format!("Failed to serialize macros: {}", e))?;
+    store.set(MACROS_KEY, value);
+    atomic_store::save(app, SETTINGS_STORE)
+        .map_err(|e| AppError::io(format!("Failed to save settings: {}", e)))
+}
+
+/// Add `new`, replacing a macro of the same name
+fn upsert(macros: &mut Vec<Macro>, new: Macro) {
+    match macros.iter_mut().find(|existing| existing.name == new.name) {
+        Some(existing) => *existing = new,
+        None => macros.push(new),
+    }
+}
+
+fn recorder(app: &AppHandle) -> Result<tauri::State<'_, MacroRecorder>, AppError> {
+    Ok(app
+        .try_state::<MacroRecorder>()
+        .ok_or("Macro recorder not found")?)
+}
+
+/// Add `step` to the macro being recorded, if any
+pub fn record(app: &AppHandle, step: MacroStep) {
+    let Some(recorder) = app.try_state::<MacroRecorder>() else {
+        return;
+    };
+    if let Ok(mut steps) = recorder.0.lock()
+        && let Some(steps) = steps.as_mut()
+    {
+        steps.push(step);
+    }
+}
+
+async fn run_step(
+    app: &AppHandle,
+    webview: &Webview,
+    name: &str,
+    step: &MacroStep,
+) -> Result<(), AppError> {
+    match step {
+        MacroStep::Action { id } => actions::run(app, webview, id).await,
+        MacroStep::SendTranscript { session_id, prefix } => {
+            crate::transcribe_and_send(app, session_id, prefix.as_deref())
+                .await
+                .map(|_| ())
+        }
+        MacroStep::Notify { title, body } => {
+            let notification = MacroNotification {
+                name: name.to_string(),
+                title: title.clone(),
+                body: body.clone(),
+            };
+            webview
+                .emit_to(webview.label(), "macro:notification", notification)
+                .map_err(|e| AppError::from(format!("Failed to emit notification: {}", e)))
+        }
+        MacroStep::Wait { ms } => {
+            tokio::time::sleep(Duration::from_millis((*ms).min(MAX_WAIT_MS))).await;
+            Ok(())
+        }
+    }
+}
+
+async fn run(app: &AppHandle, webview: &Webview, name: &str) -> Result<(), AppError> {
+    let found = load(app)
+        .into_iter()
+        .find(|found| found.name == name)
+        .ok_or_else(|| AppError::not_found(format!("No macro named {}", name)))?;
+    for (index, step) in found.steps.iter().enumerate() {
+        run_step(app, webview, name, step)
+            .await
+            .map_err(|e| AppError {
+                message: format!("Step {} of {} failed: {}", index + 1, name, e.message),
+                ..e
+            })?;
+    }
+    Ok(())
+}
+
+#[tauri::command]
+pub fn list_macros(app: AppHandle) -> Vec<Macro> {
+    load(&app)
+}
+
+#[tauri::command]
+pub fn macro_start_recording(app: AppHandle) -> Result<(), AppError> {
+    let recorder = recorder(&app)?;
+    let mut steps = recorder
+        .0
+        .lock()
+        .map_err(|e| format!("Lock error: {}", e))?;
+    if steps.is_some() {
+        return Err(AppError::busy("A macro is already being recorded"));
+    }
+    *steps = Some(Vec::new());
+    Ok(())
+}
+
+/// Stop recording and save what was recorded as `name`, replacing a macro
+/// of that name. Without a name the recording is discarded.
+#[tauri::command]
+pub fn macro_stop_recording(
+    app: AppHandle,
+    webview: Webview,
+    name: Option<String>,
+    shortcut: Option<String>,
+) -> Result<Option<Macro>, AppError> {
+    let steps = {
+        let recorder = recorder(&app)?;
+        let mut steps = recorder
+            .0
+            .lock()
+            .map_err(|e| format!("Lock error: {}", e))?;
+        steps
+            .take()
+            .ok_or_else(|| AppError::invalid_input("No macro is being recorded"))?
+    };
+    let Some(name) = name.filter(|name| !name.trim().is_empty()) else {
+        return Ok(None);
+    };
+    if steps.is_empty() {
+        return Err(AppError::invalid_input("Nothing was recorded"));
+    }
+
+    let recorded = Macro {
+        name,
+        shortcut,
+        steps,
+    };
+    let result = save(&app, recorded.clone()).map(|_| Some(recorded));
+    audit::record(
+        &webview,
+        "macro_stop_recording",
+        json!({ "macro": result.as_ref().ok() }),
+        &result,
+    );
+    result
+}
+
+fn save(app: &AppHandle, new: Macro) -> Result<(), AppError> {
+    if new.name.trim().is_empty() {
+        return Err(AppError::invalid_input("A macro needs a name"));
+    }
+    let mut macros = load(app);
+    upsert(&mut macros, new);
+    store_macros(app, &macros)
+}
+
+/// Add a macro, or replace the one of the same name
+#[tauri::command]
+pub fn save_macro(app: AppHandle, webview: Webview, definition: Macro) -> Result<(), AppError> {
+    let args = json!({ "definition": definition });
+    let result = save(&app, definition);
+    audit::record(&webview, "save_macro", args, &result);
+    result
+}
+
+#[tauri::command]
+pub fn delete_macro(app: AppHandle, webview: Webview, name: String) -> Result<(), AppError> {
+    let mut macros = load(&app);
+    let count = macros.len();
+    macros.retain(|existing| existing.name != name);
+    let result = if macros.len() == count {
+        Err(AppError::not_found(format!("No macro named {}", name)))
+    } else {
+        store_macros(&app, &macros)
+    };
+    audit::record(&webview, "delete_macro", json!({ "name": name }), &result);
+    result
+}
+
+#[tauri::command]
+pub async fn run_macro(app: AppHandle, webview: Webview, name: String) -> Result<(), AppError> {
+    let result = run(&app, &webview, &name).await;
+    audit::record(&webview, "run_macro", json!({ "name": name }), &result);
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_macro_steps_format() {
+        let value = json!({
+            "name": "Dictate left",
+            "steps": [
+                { "type": "action", "id": "window.snapLeft" },
+                { "type": "sendTranscript", "sessionId": "ses_1" },
+                { "type": "wait", "ms": 500 },
+            ],
+        });
+        let parsed: Macro = serde_json::from_value(value).unwrap();
+        assert_eq!(parsed.shortcut, None);
+        assert_eq!(
+            parsed.steps[1],
+            MacroStep::SendTranscript {
+                session_id: "ses_1".to_string(),
+                prefix: None,
+            }
+        );
+
+        let mut macros = vec![parsed.clone()];
+        upsert(
+            &mut macros,
+            Macro {
+                steps: Vec::new(),
+                ..parsed
+            },
+        );
+        assert_eq!(macros.len(), 1);
+        assert!(macros[0].steps.is_empty());
+    }
+}
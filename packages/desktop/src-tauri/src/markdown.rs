@@ -1,17 +1,359 @@
-use comrak::{markdown_to_html, Options};
+use comrak::{
+    markdown_to_commonmark, markdown_to_html,
+    nodes::{AstNode, NodeValue},
+    parse_document, Arena, Options,
+};
+use std::collections::HashSet;
+use serde::Serialize;
+use tauri::{ipc::Response, AppHandle};
+use tauri_plugin_clipboard_manager::ClipboardExt;
 
-pub fn parse_markdown(input: &str) -> String {
+use crate::{
+    diff::{self, DiffView},
+    error::AppError,
+    ipc_payload::{self, Delivery},
+    perf,
+};
+
+fn options() -> Options<'static> {
     let mut options = Options::default();
     options.extension.strikethrough = true;
     options.extension.table = true;
     options.extension.tasklist = true;
     options.extension.autolink = true;
+    options
+}
+
+pub fn parse_markdown(input: &str) -> String {
+    let mut options = options();
     options.render.r#unsafe = true;
 
     markdown_to_html(input, &options)
 }
 
+/// Like `parse_markdown`, but raw HTML in the input is left out, for views
+/// that show agent output without the frontend's sanitizing
+pub fn parse_markdown_safe(input: &str) -> String {
+    markdown_to_html(input, &options())
+}
+
+/// `input` reformatted as plain CommonMark, for writing markdown where other
+/// tools read it
+pub fn normalize_markdown(input: &str) -> String {
+    markdown_to_commonmark(input, &options())
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CodeBlock {
+    /// Position among the code blocks of the document, from 0
+    pub index: usize,
+    /// First word of the info string, e.g. `rust` for ```rust
+    pub language: Option<String>,
+    /// The whole info string
+    pub info: String,
+    pub code: String,
+    /// Line of the document the block starts on, from 1
+    pub line: usize,
+    /// Indented rather than fenced
+    pub indented: bool,
+}
+
+/// Code blocks of a markdown document, in order
+pub fn extract_code_blocks(input: &str) -> Vec<CodeBlock> {
+    let arena = Arena::new();
+    let root = parse_document(&arena, input, &options());
+    root.descendants()
+        .filter_map(|node| {
+            let data = node.data.borrow();
+            match &data.value {
+                NodeValue::CodeBlock(block) => Some((
+                    block.info.clone(),
+                    block.literal.clone(),
+                    data.sourcepos.start.line,
+                    !block.fenced,
+                )),
+                _ => None,
+            }
+        })
+        .enumerate()
+        .map(|(index, (info, code, line, indented))| CodeBlock {
+            index,
+            language: info.split_whitespace().next().map(str::to_string),
+            info,
+            code,
+            line,
+            indented,
+        })
+        .collect()
+}
+
+/// Replace the code blocks of `html`, rendered from `input`, that hold a
+/// diff (```diff or ```patch, or an unlabeled unified diff) with the diff
+/// rendered as a table. Blocks are found by their exact rendering, so any
+/// that can't be are left as they were.
+pub fn render_diff_blocks(input: &str, html: String, view: DiffView) -> String {
+    let mut out = String::with_capacity(html.len());
+    let mut rest = html.as_str();
+    for block in extract_code_blocks(input) {
+        let is_diff = match block.language.as_deref() {
+            Some(language) => matches!(language, "diff" | "patch"),
+            None => diff::is_unified_diff(&block.code),
+        };
+        if !is_diff {
+            continue;
+        }
+        let open = match &block.language {
+            Some(language) => format!("<pre><code class=\"language-{}\">", diff::escape(language)),
+            None => "<pre><code>".to_string(),
+        };
+        let rendered = format!("{}{}</code></pre>\n", open, diff::escape(&block.code));
+        if let Some(at) = rest.find(&rendered) {
+            out.push_str(&rest[..at]);
+            out.push_str(&diff::render(&block.code, view));
+            rest = &rest[at + rendered.len()..];
+        }
+    }
+    out.push_str(rest);
+    out
+}
+
+/// Prefix of heading ids, so they don't clash with ids of the app
+const ANCHOR_PREFIX: &str = "md-";
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Heading {
+    pub level: u8,
+    pub text: String,
+    /// Id of the heading in the HTML of `parse_markdown_outline`
+    pub anchor: String,
+    /// Line of the document the heading is on, from 1
+    pub line: usize,
+    /// Headings of lower levels up to the next one of this level or above
+    pub children: Vec<Heading>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct MarkdownOutline {
+    pub html: String,
+    pub headings: Vec<Heading>,
+}
+
+fn heading_text<'a>(node: &'a AstNode<'a>, text: &mut String) {
+    match &node.data.borrow().value {
+        NodeValue::Text(literal) => text.push_str(literal),
+        NodeValue::Code(code) => text.push_str(&code.literal),
+        NodeValue::LineBreak | NodeValue::SoftBreak => text.push(' '),
+        _ => {
+            for child in node.children() {
+                heading_text(child, text);
+            }
+        }
+    }
+}
+
+/// GitHub's anchors, as comrak generates them: lowercased, spaces to
+/// dashes, punctuation dropped, and a counter added to repeated ones
+fn anchor(text: &str, used: &mut HashSet<String>) -> String {
+    let base: String = text
+        .to_lowercase()
+        .chars()
+        .filter(|c| c.is_alphanumeric() || matches!(c, ' ' | '-' | '_'))
+        .map(|c| if c == ' ' { '-' } else { c })
+        .collect();
+    let mut anchor = base.clone();
+    let mut count = 0;
+    while used.contains(&anchor) {
+        count += 1;
+        anchor = format!("{}-{}", base, count);
+    }
+    used.insert(anchor.clone());
+    anchor
+}
+
+/// Nest headings under the closest previous heading of a higher level
+fn nest(flat: Vec<Heading>) -> Vec<Heading> {
+    let mut roots: Vec<Heading> = Vec::new();
+    // Open headings, from the outermost
+    let mut open: Vec<Heading> = Vec::new();
+    for heading in flat {
+        while open.last().is_some_and(|last| last.level >= heading.level) {
+            close(&mut open, &mut roots);
+        }
+        open.push(heading);
+    }
+    while !open.is_empty() {
+        close(&mut open, &mut roots);
+    }
+    roots
+}
+
+fn close(open: &mut Vec<Heading>, roots: &mut Vec<Heading>) {
+    if let Some(heading) = open.pop() {
+        match open.last_mut() {
+            Some(parent) => parent.children.push(heading),
+            None => roots.push(heading),
+        }
+    }
+}
+
+/// Headings of a markdown document as a tree, with the anchors
+/// `parse_markdown_outline` gives them in the HTML
+pub fn extract_headings(input: &str) -> Vec<Heading> {
+    let arena = Arena::new();
+    let root = parse_document(&arena, input, &options());
+    let mut used = HashSet::new();
+    let flat = root
+        .descendants()
+        .filter_map(|node| {
+            let (level, line) = {
+                let data = node.data.borrow();
+                match &data.value {
+                    NodeValue::Heading(heading) => (heading.level, data.sourcepos.start.line),
+                    _ => return None,
+                }
+            };
+            let mut text = String::new();
+            heading_text(node, &mut text);
+            Some(Heading {
+                level,
+                anchor: format!("{}{}", ANCHOR_PREFIX, anchor(&text, &mut used)),
+                text: text.trim().to_string(),
+                line,
+                children: Vec::new(),
+            })
+        })
+        .collect();
+    nest(flat)
+}
+
+/// `parse_markdown` with ids on the headings, and the headings as a tree for
+/// an outline of the response
+pub fn parse_markdown_outline(input: &str) -> MarkdownOutline {
+    let mut options = options();
+    options.render.r#unsafe = true;
+    options.extension.header_ids = Some(ANCHOR_PREFIX.to_string());
+
+    MarkdownOutline {
+        html: markdown_to_html(input, &options),
+        headings: extract_headings(input),
+    }
+}
+
+#[tauri::command]
+pub async fn parse_markdown_command(
+    app: AppHandle,
+    markdown: String,
+    delivery: Option<Delivery>,
+    diff_view: Option<DiffView>,
+) -> Result<Response, AppError> {
+    let _span = perf::span(&app, "markdown", "parse_markdown");
+    let mut html = parse_markdown(&markdown);
+    if let Some(view) = diff_view {
+        html = render_diff_blocks(&markdown, html, view);
+    }
+    Ok(ipc_payload::deliver(&html, delivery)?)
+}
+
+#[tauri::command]
+pub async fn parse_markdown_outline_command(app: AppHandle, markdown: String) -> MarkdownOutline {
+    let _span = perf::span(&app, "markdown", "parse_markdown_outline");
+    parse_markdown_outline(&markdown)
+}
+
+#[tauri::command]
+pub fn extract_code_blocks_command(markdown: String) -> Vec<CodeBlock> {
+    extract_code_blocks(&markdown)
+}
+
+/// Copy the code of the `index`th code block, as `extract_code_blocks`
+/// counts them
 #[tauri::command]
-pub async fn parse_markdown_command(markdown: String) -> Result<String, String> {
-    Ok(parse_markdown(&markdown))
+pub async fn copy_code_block(
+    app: AppHandle,
+    markdown: String,
+    index: usize,
+) -> Result<(), AppError> {
+    let block = extract_code_blocks(&markdown)
+        .into_iter()
+        .nth(index)
+        .ok_or_else(|| AppError::not_found(format!("No code block {}", index)))?;
+    app.clipboard()
+        .write_text(block.code)
+        .map_err(|e| format!("Failed to copy to clipboard: {}", e))?;
+    Ok(())
+}
+
+/// Copy a response as HTML, so pasting into mail or a document keeps its
+/// formatting, with the markdown itself as the plain text flavor
+#[tauri::command]
+pub async fn copy_rich(app: AppHandle, markdown: String) -> Result<(), AppError> {
+    let html = parse_markdown_safe(&markdown);
+    app.clipboard()
+        .write_html(html, Some(markdown))
+        .map_err(|e| format!("Failed to copy to clipboard: {}", e))?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_extract_code_blocks() {
+        let input = "Intro\n\n```rust title=main.rs\nfn main() {}\n```\n\n    indented\n\n````\n```nested```\n````\n";
+        let blocks = extract_code_blocks(input);
+        assert_eq!(blocks.len(), 3);
+
+        assert_eq!(blocks[0].language.as_deref(), Some("rust"));
+        assert_eq!(blocks[0].info, "rust title=main.rs");
+        assert_eq!(blocks[0].code, "fn main() {}\n");
+        assert_eq!(blocks[0].line, 3);
+
+        assert!(blocks[1].indented);
+        assert_eq!(blocks[1].code, "indented\n");
+
+        assert_eq!(blocks[2].index, 2);
+        assert_eq!(blocks[2].language, None);
+        assert_eq!(blocks[2].code, "```nested```\n");
+    }
+
+    #[test]
+    fn test_headings_nest_and_match_html_ids() {
+        let input = "# Intro\n\n## Setup `npm`\n\n### Linux\n\n## Setup `npm`\n\n# What's next?\n";
+        let outline = parse_markdown_outline(input);
+        let headings = &outline.headings;
+
+        assert_eq!(headings.len(), 2);
+        assert_eq!(headings[0].text, "Intro");
+        assert_eq!(headings[0].children.len(), 2);
+        assert_eq!(headings[0].children[0].text, "Setup npm");
+        assert_eq!(headings[0].children[0].anchor, "md-setup-npm");
+        assert_eq!(headings[0].children[0].children[0].level, 3);
+        assert_eq!(headings[0].children[1].anchor, "md-setup-npm-1");
+        assert_eq!(headings[1].anchor, "md-whats-next");
+        assert_eq!(headings[1].line, 9);
+
+        for anchor in ["md-intro", "md-setup-npm", "md-linux", "md-setup-npm-1", "md-whats-next"] {
+            assert!(
+                outline.html.contains(&format!("id=\"{}\"", anchor)),
+                "{} in {}",
+                anchor,
+                outline.html
+            );
+        }
+    }
+
+    #[test]
+    fn test_render_diff_blocks() {
+        let input = "Change:\n\n```diff\n-a <b>\n+a <i>\n```\n\n```rust\nlet x = 1;\n```\n\n```\n--- a/x\n+++ b/x\n@@ -1 +1 @@\n-one\n+two\n```\n";
+        let html = render_diff_blocks(input, parse_markdown(input), DiffView::Inline);
+
+        assert_eq!(html.matches("<table class=\"diff diff-inline\">").count(), 2);
+        assert!(!html.contains("language-diff"));
+        assert!(html.contains("<pre><code class=\"language-rust\">let x = 1;"));
+        assert!(html.contains("<mark>i</mark>"));
+    }
 }
@@ -0,0 +1,613 @@
+//! Calendar-aware prompts to transcribe meetings.
+//!
+//! With `meetingCalendar` enabled in the settings, the app reads the
+//! calendar file at its `icsPath` (an `.ics` export or a synced copy of a
+//! calendar) every half minute, and emits `meeting:starting` when an event
+//! is about to start, for the frontend to ask whether to transcribe it. On
+//! confirmation `meeting_start_transcription` starts capturing the system
+//! audio and microphone, and `meeting_stop_transcription` returns the
+//! transcript together with the meeting it belongs to.
+//!
+//! Daily and weekly recurring events are expanded, with their exceptions.
+//! Times with a `TZID` are read as local time; all-day events are ignored.
+
+use chrono::{
+    DateTime, Datelike, Local, NaiveDate, NaiveDateTime, TimeDelta, TimeZone, Utc, Weekday,
+};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use std::{collections::HashSet, sync::Mutex, time::Duration};
+use tauri::{AppHandle, Emitter, Manager, Webview};
+
+use crate::{
+    app_settings,
+    audio_capture::{self, CaptureSource, CaptureSourceConfig},
+    audit,
+    error::AppError,
+    i18n, stt,
+};
+
+const POLL_INTERVAL: Duration = Duration::from_secs(30);
+/// A meeting that started this long ago is still offered, e.g. right after
+/// the app starts
+const GRACE: TimeDelta = TimeDelta::minutes(5);
+/// How far ahead `list_upcoming_meetings` looks
+const UPCOMING: TimeDelta = TimeDelta::hours(24);
+/// Bound on the periods a recurring event is expanded over
+const MAX_PERIODS: i64 = 5_000;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct CalendarSettings {
+    enabled: bool,
+    ics_path: Option<String>,
+    /// Minutes before the start to prompt
+    lead_minutes: i64,
+    sources: Vec<CaptureSourceConfig>,
+    diarize: bool,
+}
+
+impl Default for CalendarSettings {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            ics_path: None,
+            lead_minutes: 1,
+            sources: vec![
+                CaptureSourceConfig {
+                    source: CaptureSource::System,
+                    gain: 1.0,
+                },
+                CaptureSourceConfig {
+                    source: CaptureSource::Microphone,
+                    gain: 1.0,
+                },
+            ],
+            diarize: true,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Frequency {
+    Daily,
+    Weekly,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Recurrence {
+    frequency: Frequency,
+    interval: i64,
+    count: Option<usize>,
+    until: Option<NaiveDateTime>,
+    by_day: Vec<Weekday>,
+}
+
+/// An event's time, in UTC or the local time zone
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct EventTime {
+    naive: NaiveDateTime,
+    utc: bool,
+}
+
+impl EventTime {
+    fn resolve(self, naive: NaiveDateTime) -> Option<DateTime<Utc>> {
+        if self.utc {
+            return Some(Utc.from_utc_datetime(&naive));
+        }
+        Local
+            .from_local_datetime(&naive)
+            .earliest()
+            .map(|time| time.with_timezone(&Utc))
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct CalendarEvent {
+    uid: String,
+    title: String,
+    start: EventTime,
+    duration: Option<TimeDelta>,
+    recurrence: Option<Recurrence>,
+    exceptions: Vec<DateTime<Utc>>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Meeting {
+    /// Identifies this occurrence of the event
+    pub id: String,
+    pub title: String,
+    pub starts_at: DateTime<Utc>,
+    pub ends_at: Option<DateTime<Utc>>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct MeetingPrompt {
+    pub meeting: Meeting,
+    /// In the app language
+    pub title: String,
+    pub body: String,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct MeetingTranscript {
+    pub meeting: Meeting,
+    pub transcription: stt::Transcription,
+}
+
+#[derive(Default)]
+struct MeetingState {
+    /// Meetings already offered, so each is offered once
+    prompted: HashSet<String>,
+    /// The meeting being transcribed
+    active: Option<Meeting>,
+}
+
+#[derive(Default)]
+pub struct MeetingCalendarState(Mutex<MeetingState>);
+
+fn settings(app: &AppHandle) -> CalendarSettings {
+    app_settings::load(app).meeting_calendar
+}
+
+/// Lines of `contents` with folded lines joined back
+fn unfold(contents: &str) -> Vec<String> {
+    let mut lines: Vec<String> = Vec::new();
+    for line in contents.lines() {
+        match (line.strip_prefix([' ', '\t']), lines.last_mut()) {
+            (Some(rest), Some(last)) => last.push_str(rest),
+            _ => lines.push(line.to_string()),
+        }
+    }
+    lines
+}
+
+fn unescape(text: &str) -> String {
+    let mut unescaped = String::with_capacity(text.len());
+    let mut chars = text.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            unescaped.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some('n' | 'N') => unescaped.push('\n'),
+            Some(other) => unescaped.push(other),
+            None => {}
+        }
+    }
+    unescaped
+}
+
+fn parse_date(value: &str) -> Option<NaiveDate> {
+    NaiveDate::parse_from_str(value, "%Y%m%d").ok()
+}
+
+/// A `DTSTART`-style value; None for dates without a time
+fn parse_time(value: &str) -> Option<EventTime> {
+    let (value, utc) = match value.strip_suffix('Z') {
+        Some(value) => (value, true),
+        None => (value, false),
+    };
+    let naive = NaiveDateTime::parse_from_str(value, "%Y%m%dT%H%M%S").ok()?;
+    Some(EventTime { naive, utc })
+}
+
+/// An ISO 8601 duration like `PT1H30M`, in weeks, days, hours, minutes and
+/// seconds
+fn parse_duration(value: &str) -> Option<TimeDelta> {
+    let value = value.strip_prefix('+').unwrap_or(value).strip_prefix('P')?;
+    let mut total = TimeDelta::zero();
+    let mut number = String::new();
+    for c in value.chars() {
+        if c.is_ascii_digit() {
+            number.push(c);
+            continue;
+        }
+        let n = if c == 'T' { 0 } else { number.parse().ok()? };
+        number.clear();
+        total += match c {
+            'W' => TimeDelta::weeks(n),
+            'D' => TimeDelta::days(n),
+            'H' => TimeDelta::hours(n),
+            'M' => TimeDelta::minutes(n),
+            'S' => TimeDelta::seconds(n),
+            'T' => TimeDelta::zero(),
+            _ => return None,
+        };
+    }
+    Some(total)
+}
+
+fn parse_weekday(value: &str) -> Option<Weekday> {
+    // Ordinals like `1MO` only make sense for monthly rules
+    match value.trim_start_matches(|c: char| c.is_ascii_digit() || c == '+' || c == '-') {
+        "MO" => Some(Weekday::Mon),
+        "TU" => Some(Weekday::Tue),
+        "WE" => Some(Weekday::Wed),
+        "TH" => Some(Weekday::Thu),
+        "FR" => Some(Weekday::Fri),
+        "SA" => Some(Weekday::Sat),
+        "SU" => Some(Weekday::Sun),
+        _ => None,
+    }
+}
+
+/// A daily or weekly `RRULE`; None for rules that aren't expanded
+fn parse_recurrence(value: &str) -> Option<Recurrence> {
+    let mut recurrence = Recurrence {
+        frequency: Frequency::Daily,
+        interval: 1,
+        count: None,
+        until: None,
+        by_day: Vec::new(),
+    };
+    let mut frequency = None;
+    for part in value.split(';') {
+        let (key, value) = part.split_once('=')?;
+        match key {
+            "FREQ" => {
+                frequency = match value {
+                    "DAILY" => Some(Frequency::Daily),
+                    "WEEKLY" => Some(Frequency::Weekly),
+                    _ => return None,
+                }
+            }
+            "INTERVAL" => recurrence.interval = value.parse().ok().filter(|n| *n > 0)?,
+            "COUNT" => recurrence.count = Some(value.parse().ok()?),
+            "UNTIL" => {
+                recurrence.until = match parse_time(value) {
+                    Some(time) => Some(time.naive),
+                    None => parse_date(value)?.and_hms_opt(23, 59, 59),
+                }
+            }
+            "BYDAY" => {
+                recurrence.by_day = value.split(',').map(parse_weekday).collect::<Option<_>>()?
+            }
+            "WKST" => {}
+            _ => return None,
+        }
+    }
+    recurrence.frequency = frequency?;
+    if recurrence.frequency == Frequency::Daily && !recurrence.by_day.is_empty() {
+        return None;
+    }
+    Some(recurrence)
+}
+
+/// Timed, not cancelled events of an iCalendar file
+fn parse_ics(contents: &str) -> Vec<CalendarEvent> {
+    let mut events = Vec::new();
+    let mut current: Option<Vec<(String, String, String)>> = None;
+    for line in unfold(contents) {
+        match line.as_str() {
+            "BEGIN:VEVENT" => current = Some(Vec::new()),
+            "END:VEVENT" => {
+                if let Some(event) = current.take().and_then(|props| parse_event(&props)) {
+                    events.push(event);
+                }
+            }
+            _ => {
+                let Some(props) = current.as_mut() else {
+                    continue;
+                };
+                let Some((name, value)) = line.split_once(':') else {
+                    continue;
+                };
+                let (name, params) = name.split_once(';').unwrap_or((name, ""));
+                props.push((
+                    name.to_ascii_uppercase(),
+                    params.to_string(),
+                    value.to_string(),
+                ));
+            }
+        }
+    }
+    events
+}
+
+/// `props` are name, parameters and value
+fn parse_event(props: &[(String, String, String)]) -> Option<CalendarEvent> {
+    let get = |name: &str| {
+        props
+            .iter()
+            .find(|(prop, _, _)| prop == name)
+            .map(|(_, _, value)| value.as_str())
+    };
+    if get("STATUS") == Some("CANCELLED") {
+        return None;
+    }
+    let start = parse_time(get("DTSTART")?)?;
+    let duration = match get("DTEND").and_then(parse_time) {
+        Some(end) => Some(end.naive - start.naive),
+        None => get("DURATION").and_then(parse_duration),
+    };
+    let exceptions = props
+        .iter()
+        .filter(|(prop, _, _)| prop == "EXDATE")
+        .flat_map(|(_, _, value)| value.split(','))
+        .filter_map(parse_time)
+        .filter_map(|time| time.resolve(time.naive))
+        .collect();
+    Some(CalendarEvent {
+        uid: get("UID").unwrap_or_default().to_string(),
+        title: get("SUMMARY")
+            .map(unescape)
+            .filter(|title| !title.trim().is_empty())
+            .unwrap_or_else(|| "Meeting".to_string()),
+        start,
+        duration,
+        recurrence: get("RRULE").and_then(parse_recurrence),
+        exceptions,
+    })
+}
+
+/// Start times of the `period`th period of a recurring event, in order
+fn period_starts(start: NaiveDateTime, recurrence: &Recurrence, period: i64) -> Vec<NaiveDateTime> {
+    let step = period * recurrence.interval;
+    match recurrence.frequency {
+        Frequency::Daily => vec![start + TimeDelta::days(step)],
+        Frequency::Weekly if recurrence.by_day.is_empty() => vec![start + TimeDelta::weeks(step)],
+        Frequency::Weekly => {
+            let monday = start - TimeDelta::days(start.weekday().num_days_from_monday().into())
+                + TimeDelta::weeks(step);
+            let mut days: Vec<u32> = recurrence
+                .by_day
+                .iter()
+                .map(Weekday::num_days_from_monday)
+                .collect();
+            days.sort_unstable();
+            days.dedup();
+            days.into_iter()
+                .map(|day| monday + TimeDelta::days(day.into()))
+                .filter(|time| *time >= start)
+                .collect()
+        }
+    }
+}
+
+/// Occurrences of `event` starting between `from` and `to`
+fn occurrences(event: &CalendarEvent, from: DateTime<Utc>, to: DateTime<Utc>) -> Vec<Meeting> {
+    let meeting = |naive: NaiveDateTime| {
+        let starts_at = event.start.resolve(naive)?;
+        Some(Meeting {
+            id: format!("{}@{}", event.uid, starts_at.timestamp()),
+            title: event.title.clone(),
+            starts_at,
+            ends_at: event.duration.map(|duration| starts_at + duration),
+        })
+    };
+    let in_range = |meeting: &Meeting| meeting.starts_at >= from && meeting.starts_at <= to;
+
+    let Some(recurrence) = &event.recurrence else {
+        return meeting(event.start.naive)
+            .filter(in_range)
+            .into_iter()
+            .collect();
+    };
+    let mut found = Vec::new();
+    let mut emitted = 0;
+    for period in 0..MAX_PERIODS {
+        for naive in period_starts(event.start.naive, recurrence, period) {
+            if recurrence.until.is_some_and(|until| naive > until)
+                || recurrence.count.is_some_and(|count| emitted >= count)
+            {
+                return found;
+            }
+            emitted += 1;
+            let Some(meeting) = meeting(naive) else {
+                continue;
+            };
+            if meeting.starts_at > to {
+                return found;
+            }
+            if in_range(&meeting) && !event.exceptions.contains(&meeting.starts_at) {
+                found.push(meeting);
+            }
+        }
+    }
+    found
+}
+
+fn meetings_between(
+    settings: &CalendarSettings,
+    from: DateTime<Utc>,
+    to: DateTime<Utc>,
+) -> Result<Vec<Meeting>, AppError> {
+    let Some(path) = settings.ics_path.as_deref() else {
+        return Ok(Vec::new());
+    };
+    let contents = std::fs::read_to_string(path)
+        .map_err(|e| AppError::io(format!("Failed to read calendar {}: {}", path, e)))?;
+    let mut meetings: Vec<Meeting> = parse_ics(&contents)
+        .iter()
+        .flat_map(|event| occurrences(event, from, to))
+        .collect();
+    meetings.sort_by_key(|meeting| meeting.starts_at);
+    Ok(meetings)
+}
+
+fn state(app: &AppHandle) -> Result<tauri::State<'_, MeetingCalendarState>, AppError> {
+    Ok(app
+        .try_state::<MeetingCalendarState>()
+        .ok_or("Meeting calendar state not found")?)
+}
+
+fn prompt_due(app: &AppHandle) -> Result<(), AppError> {
+    let settings = settings(app);
+    if !settings.enabled {
+        return Ok(());
+    }
+    let now = Utc::now();
+    let due = meetings_between(
+        &settings,
+        now - GRACE,
+        now + TimeDelta::minutes(settings.lead_minutes.max(0)),
+    )?;
+
+    let state = state(app)?;
+    let mut state = state.0.lock().map_err(|e| format!("Lock error: {}", e))?;
+    for meeting in due {
+        if state.active.is_some() || !state.prompted.insert(meeting.id.clone()) {
+            continue;
+        }
+        let prompt = MeetingPrompt {
+            title: i18n::t(app, "meeting.startingTitle"),
+            body: i18n::t_with(app, "meeting.startingBody", &[("title", &meeting.title)]),
+            meeting,
+        };
+        let _ = app.emit("meeting:starting", prompt);
+    }
+    Ok(())
+}
+
+/// Start watching the calendar, for the life of the app
+pub fn watch(app: &AppHandle) {
+    let app = app.clone();
+    tauri::async_runtime::spawn(async move {
+        loop {
+            tokio::time::sleep(POLL_INTERVAL).await;
+            if let Err(e) = prompt_due(&app) {
+                eprintln!("Failed to check the meeting calendar: {}", e);
+            }
+        }
+    });
+}
+
+/// Meetings of the calendar in the next day, including those just started
+#[tauri::command]
+pub fn list_upcoming_meetings(app: AppHandle) -> Result<Vec<Meeting>, AppError> {
+    let now = Utc::now();
+    meetings_between(&settings(&app), now - GRACE, now + UPCOMING)
+}
+
+async fn start(app: &AppHandle, meeting_id: &str) -> Result<Meeting, AppError> {
+    let settings = settings(app);
+    let now = Utc::now();
+    let meeting = meetings_between(&settings, now - UPCOMING, now + UPCOMING)?
+        .into_iter()
+        .find(|meeting| meeting.id == meeting_id)
+        .ok_or_else(|| AppError::not_found("The meeting is no longer in the calendar"))?;
+    if state(app)?
+        .0
+        .lock()
+        .map_err(|e| format!("Lock error: {}", e))?
+        .active
+        .is_some()
+    {
+        return Err(AppError::busy("A meeting is already being transcribed"));
+    }
+
+    audio_capture::stt_start_capture(app.clone(), settings.sources).await?;
+    state(app)?
+        .0
+        .lock()
+        .map_err(|e| format!("Lock error: {}", e))?
+        .active = Some(meeting.clone());
+    Ok(meeting)
+}
+
+/// Start capturing a meeting offered by `meeting:starting`
+#[tauri::command]
+pub async fn meeting_start_transcription(
+    app: AppHandle,
+    webview: Webview,
+    meeting_id: String,
+) -> Result<Meeting, AppError> {
+    let result = start(&app, &meeting_id).await;
+    audit::record(
+        &webview,
+        "meeting_start_transcription",
+        json!({ "meetingId": meeting_id }),
+        &result,
+    );
+    result
+}
+
+/// Stop capturing the meeting and transcribe it
+#[tauri::command]
+pub async fn meeting_stop_transcription(app: AppHandle) -> Result<MeetingTranscript, AppError> {
+    let meeting = state(&app)?
+        .0
+        .lock()
+        .map_err(|e| format!("Lock error: {}", e))?
+        .active
+        .take()
+        .ok_or_else(|| AppError::invalid_input("No meeting is being transcribed"))?;
+    let transcription =
+        crate::stop_and_transcribe_detailed(&app, settings(&app).diarize, None).await?;
+    Ok(MeetingTranscript {
+        meeting,
+        transcription,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CALENDAR: &str = "BEGIN:VCALENDAR\r\n\
+        BEGIN:VEVENT\r\n\
+        UID:standup\r\n\
+        SUMMARY:Team standup\\, daily\r\n\
+        DTSTART:20251013T090000Z\r\n\
+        DURATION:PT15M\r\n\
+        RRULE:FREQ=WEEKLY;BYDAY=MO,WE,FR;COUNT=5\r\n\
+        EXDATE:20251015T090000Z\r\n\
+        END:VEVENT\r\n\
+        BEGIN:VEVENT\r\n\
+        UID:offsite\r\n\
+        SUMMARY:Offsite\r\n\
+        DTSTART;VALUE=DATE:20251014\r\n\
+        END:VEVENT\r\n\
+        BEGIN:VEVENT\r\n\
+        UID:review\r\n\
+        SUMMARY:Design\r\n  review\r\n\
+        DTSTART:20251014T130000Z\r\n\
+        DTEND:20251014T140000Z\r\n\
+        STATUS:CANCELLED\r\n\
+        END:VEVENT\r\n\
+        END:VCALENDAR\r\n";
+
+    fn utc(value: &str) -> DateTime<Utc> {
+        Utc.from_utc_datetime(&parse_time(value).unwrap().naive)
+    }
+
+    #[test]
+    fn test_parse_ics() {
+        let events = parse_ics(CALENDAR);
+        assert_eq!(events.len(), 1);
+        assert_eq!(events[0].title, "Team standup, daily");
+        assert_eq!(events[0].duration, Some(TimeDelta::minutes(15)));
+        assert_eq!(
+            parse_ics(
+                "BEGIN:VEVENT\nSUMMARY:Design\n  review\nDTSTART:20251014T130000Z\nEND:VEVENT"
+            )[0]
+            .title,
+            "Design review"
+        );
+    }
+
+    #[test]
+    fn test_weekly_occurrences() {
+        let events = parse_ics(CALENDAR);
+        let starts: Vec<_> =
+            occurrences(&events[0], utc("20251001T000000Z"), utc("20251231T000000Z"))
+                .into_iter()
+                .map(|meeting| meeting.starts_at)
+                .collect();
+        // Five occurrences from the Monday, less the excluded Wednesday
+        assert_eq!(
+            starts,
+            vec![
+                utc("20251013T090000Z"),
+                utc("20251017T090000Z"),
+                utc("20251020T090000Z"),
+                utc("20251022T090000Z"),
+            ]
+        );
+    }
+}
@@ -0,0 +1,286 @@
+//! Alerts when the app or the sidecar use too much memory.
+//!
+//! Every `intervalSecs` the watchdog measures the resident memory of the app
+//! and of the sidecar with the processes it started, and emits a
+//! `memory:warning` event when either goes over its threshold, both set
+//! under `memoryWatchdog` in the settings. The event carries the native
+//! actions that free that memory, unloading the speech model or restarting
+//! the server, for the toast the frontend shows to offer as buttons; they
+//! run through `invoke_action`. A process warns once when it crosses its
+//! threshold, and again only after dropping below 90% of it.
+//!
+//! On Windows only the sidecar process itself is measured.
+
+use serde::{Deserialize, Serialize};
+use std::time::Duration;
+use tauri::{AppHandle, Emitter};
+
+use crate::{
+    actions::{self, ActionInfo},
+    app_settings, i18n, sidecar_pid,
+};
+
+const MIN_INTERVAL_SECS: u64 = 5;
+/// A warned process warns again after dropping below this share of its
+/// threshold, so hovering around it doesn't repeat the warning
+const REARM_RATIO: f64 = 0.9;
+const MB: u64 = 1024 * 1024;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct WatchdogSettings {
+    enabled: bool,
+    interval_secs: u64,
+    app_threshold_mb: u64,
+    sidecar_threshold_mb: u64,
+}
+
+impl Default for WatchdogSettings {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            interval_secs: 30,
+            app_threshold_mb: 2048,
+            sidecar_threshold_mb: 3072,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub enum WatchedProcess {
+    App,
+    Sidecar,
+}
+
+impl WatchedProcess {
+    /// Actions that free memory of this process
+    fn remedies(self) -> &'static [&'static str] {
+        match self {
+            WatchedProcess::App => &["stt.unloadModels"],
+            WatchedProcess::Sidecar => &["server.restart"],
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct MemoryUsage {
+    pub app_mb: Option<u64>,
+    /// None while no sidecar runs
+    pub sidecar_mb: Option<u64>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct MemoryWarning {
+    pub process: WatchedProcess,
+    pub rss_mb: u64,
+    pub threshold_mb: u64,
+    /// In the app language
+    pub title: String,
+    pub body: String,
+    pub actions: Vec<ActionInfo>,
+}
+
+fn settings(app: &AppHandle) -> WatchdogSettings {
+    app_settings::load(app).memory_watchdog
+}
+
+#[cfg(target_os = "linux")]
+fn rss_bytes(pid: u32) -> Option<u64> {
+    let status = std::fs::read_to_string(format!("/proc/{}/status", pid)).ok()?;
+    let kb = status
+        .lines()
+        .find_map(|line| line.strip_prefix("VmRSS:"))?
+        .trim()
+        .trim_end_matches("kB")
+        .trim()
+        .parse::<u64>()
+        .ok()?;
+    Some(kb * 1024)
+}
+
+/// Children started by any thread of `pid`
+#[cfg(target_os = "linux")]
+fn child_pids(pid: u32) -> Vec<u32> {
+    let Ok(tasks) = std::fs::read_dir(format!("/proc/{}/task", pid)) else {
+        return Vec::new();
+    };
+    tasks
+        .flatten()
+        .filter_map(|task| std::fs::read_to_string(task.path().join("children")).ok())
+        .flat_map(|children| {
+            children
+                .split_whitespace()
+                .filter_map(|child| child.parse().ok())
+                .collect::<Vec<u32>>()
+        })
+        .collect()
+}
+
+#[cfg(target_os = "macos")]
+fn rss_bytes(pid: u32) -> Option<u64> {
+    // SAFETY: `info` is a plain C struct and the size passed is its own
+    unsafe {
+        let mut info: libc::proc_taskinfo = std::mem::zeroed();
+        let size = std::mem::size_of::<libc::proc_taskinfo>() as libc::c_int;
+        let read = libc::proc_pidinfo(
+            pid as libc::c_int,
+            libc::PROC_PIDTASKINFO,
+            0,
+            &mut info as *mut _ as *mut libc::c_void,
+            size,
+        );
+        (read == size).then_some(info.pti_resident_size)
+    }
+}
+
+#[cfg(target_os = "macos")]
+fn child_pids(pid: u32) -> Vec<u32> {
+    let mut pids: Vec<libc::pid_t> = vec![0; 256];
+    // SAFETY: the buffer size passed is the size of `pids` in bytes
+    let count = unsafe {
+        libc::proc_listchildpids(
+            pid as libc::pid_t,
+            pids.as_mut_ptr() as *mut libc::c_void,
+            (pids.len() * std::mem::size_of::<libc::pid_t>()) as libc::c_int,
+        )
+    };
+    pids.truncate(count.max(0) as usize);
+    pids.into_iter().map(|pid| pid as u32).collect()
+}
+
+#[cfg(windows)]
+fn rss_bytes(pid: u32) -> Option<u64> {
+    use windows::Win32::Foundation::CloseHandle;
+    use windows::Win32::System::ProcessStatus::{GetProcessMemoryInfo, PROCESS_MEMORY_COUNTERS};
+    use windows::Win32::System::Threading::{OpenProcess, PROCESS_QUERY_LIMITED_INFORMATION};
+
+    unsafe {
+        let process = OpenProcess(PROCESS_QUERY_LIMITED_INFORMATION, false, pid).ok()?;
+        let mut counters = PROCESS_MEMORY_COUNTERS::default();
+        let result = GetProcessMemoryInfo(
+            process,
+            &mut counters,
+            std::mem::size_of::<PROCESS_MEMORY_COUNTERS>() as u32,
+        );
+        let _ = CloseHandle(process);
+        result.ok().map(|_| counters.WorkingSetSize as u64)
+    }
+}
+
+#[cfg(windows)]
+fn child_pids(_pid: u32) -> Vec<u32> {
+    Vec::new()
+}
+
+/// Resident memory of `pid` and everything it started
+fn tree_rss_bytes(pid: u32) -> Option<u64> {
+    let own = rss_bytes(pid)?;
+    let children: u64 = child_pids(pid).into_iter().filter_map(tree_rss_bytes).sum();
+    Some(own + children)
+}
+
+fn usage(app: &AppHandle) -> MemoryUsage {
+    MemoryUsage {
+        app_mb: rss_bytes(std::process::id()).map(|bytes| bytes / MB),
+        sidecar_mb: sidecar_pid(app)
+            .and_then(tree_rss_bytes)
+            .map(|bytes| bytes / MB),
+    }
+}
+
+/// Whether `rss_mb` calls for a warning, given whether one was already sent
+/// for this crossing in `warned`
+fn crossed(warned: &mut bool, rss_mb: u64, threshold_mb: u64) -> bool {
+    if rss_mb > threshold_mb {
+        let due = !*warned;
+        *warned = true;
+        return due;
+    }
+    if (rss_mb as f64) < threshold_mb as f64 * REARM_RATIO {
+        *warned = false;
+    }
+    false
+}
+
+fn warn(app: &AppHandle, process: WatchedProcess, rss_mb: u64, threshold_mb: u64) {
+    let name = match process {
+        WatchedProcess::App => i18n::t(app, "memory.app"),
+        WatchedProcess::Sidecar => i18n::t(app, "memory.sidecar"),
+    };
+    let warning = MemoryWarning {
+        process,
+        rss_mb,
+        threshold_mb,
+        title: i18n::t(app, "memory.warningTitle"),
+        body: i18n::t_with(
+            app,
+            "memory.warningBody",
+            &[("name", &name), ("size", &rss_mb.to_string())],
+        ),
+        actions: actions::enabled_actions(app, process.remedies()),
+    };
+    if let Err(e) = app.emit("memory:warning", warning) {
+        eprintln!("Failed to emit memory warning: {}", e);
+    }
+}
+
+/// Start watching memory use, for the life of the app
+pub fn watch(app: &AppHandle) {
+    let app = app.clone();
+    tauri::async_runtime::spawn(async move {
+        let mut app_warned = false;
+        let mut sidecar_warned = false;
+        loop {
+            let settings = settings(&app);
+            let interval = settings.interval_secs.max(MIN_INTERVAL_SECS);
+            tokio::time::sleep(Duration::from_secs(interval)).await;
+            if !settings.enabled {
+                continue;
+            }
+
+            let usage = usage(&app);
+            if let Some(rss_mb) = usage.app_mb
+                && crossed(&mut app_warned, rss_mb, settings.app_threshold_mb)
+            {
+                warn(&app, WatchedProcess::App, rss_mb, settings.app_threshold_mb);
+            }
+            if let Some(rss_mb) = usage.sidecar_mb
+                && crossed(&mut sidecar_warned, rss_mb, settings.sidecar_threshold_mb)
+            {
+                warn(
+                    &app,
+                    WatchedProcess::Sidecar,
+                    rss_mb,
+                    settings.sidecar_threshold_mb,
+                );
+            }
+        }
+    });
+}
+
+/// Current memory use of the app and the sidecar
+#[tauri::command]
+pub fn get_memory_usage(app: AppHandle) -> MemoryUsage {
+    usage(&app)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_warns_once_per_crossing() {
+        let mut warned = false;
+        assert!(!crossed(&mut warned, 900, 1000));
+        assert!(crossed(&mut warned, 1100, 1000));
+        assert!(!crossed(&mut warned, 1200, 1000));
+        // Not far enough below to warn again
+        assert!(!crossed(&mut warned, 950, 1000));
+        assert!(!crossed(&mut warned, 1050, 1000));
+        assert!(!crossed(&mut warned, 850, 1000));
+        assert!(crossed(&mut warned, 1050, 1000));
+    }
+}
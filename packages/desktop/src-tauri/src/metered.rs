@@ -0,0 +1,273 @@
+//! Metered connection detection for large downloads.
+//!
+//! The speech model alone is several GB, so before a model download starts on
+//! a metered or cellular connection the user is asked first. Networks can be
+//! allowed permanently, keyed by network name.
+//!
+//! Detection per platform:
+//! - Windows: the connection cost of the internet connection profile
+//! - macOS: heuristics on the default route (cellular interfaces, iPhone/iPad
+//!   USB or Bluetooth tethering, Wi-Fi names of phone hotspots)
+//! - Linux: NetworkManager's `Metered` property
+
+use serde::Serialize;
+use tauri::AppHandle;
+use tauri_plugin_dialog::{
+    DialogExt, MessageDialogButtons, MessageDialogKind, MessageDialogResult,
+};
+use tauri_plugin_store::StoreExt;
+use tokio::sync::oneshot;
+
+use crate::{SETTINGS_STORE, atomic_store, error::AppError, i18n, test_mode};
+
+const ALLOWED_NETWORKS_KEY: &str = "meteredAllowedNetworks";
+
+#[derive(Debug, Clone, Default, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct NetworkStatus {
+    pub metered: bool,
+    /// Wi-Fi name or connection profile, when it can be determined
+    pub network: Option<String>,
+    /// Whether large downloads were allowed on this network for good
+    pub allowed: bool,
+}
+
+#[cfg(any(target_os = "macos", target_os = "linux"))]
+fn command_output(program: &str, args: &[&str]) -> Option<String> {
+    std::process::Command::new(program)
+        .args(args)
+        .output()
+        .ok()
+        .filter(|output| output.status.success())
+        .map(|output| String::from_utf8_lossy(&output.stdout).into_owned())
+}
+
+#[cfg(windows)]
+fn detect() -> (bool, Option<String>) {
+    use windows::Networking::Connectivity::{NetworkCostType, NetworkInformation};
+
+    let Ok(profile) = NetworkInformation::GetInternetConnectionProfile() else {
+        return (false, None);
+    };
+    let network = profile.ProfileName().ok().map(|name| name.to_string());
+    let metered = profile.GetConnectionCost().is_ok_and(|cost| {
+        matches!(
+            cost.NetworkCostType(),
+            Ok(NetworkCostType::Fixed | NetworkCostType::Variable)
+        ) || cost.Roaming().unwrap_or(false)
+            || cost.OverDataLimit().unwrap_or(false)
+    });
+    (metered, network)
+}
+
+/// Hardware port name ("Wi-Fi", "iPhone USB", ...) of a device in the output
+/// of `networksetup -listallhardwareports`
+#[cfg(any(target_os = "macos", test))]
+fn hardware_port(ports: &str, device: &str) -> Option<String> {
+    let mut port = None;
+    for line in ports.lines() {
+        if let Some(name) = line.strip_prefix("Hardware Port: ") {
+            port = Some(name.trim());
+        } else if line.strip_prefix("Device: ").map(str::trim) == Some(device) {
+            return port.map(str::to_string);
+        }
+    }
+    None
+}
+
+#[cfg(any(target_os = "macos", test))]
+fn looks_metered(interface: &str, port: Option<&str>, ssid: Option<&str>) -> bool {
+    const TETHERED_PORTS: &[&str] = &["iPhone", "iPad", "Bluetooth PAN", "RNDIS"];
+    const HOTSPOT_NAMES: &[&str] = &["iPhone", "iPad", "Android", "Galaxy", "Pixel", "Hotspot"];
+
+    interface.starts_with("pdp_ip")
+        || port.is_some_and(|port| TETHERED_PORTS.iter().any(|p| port.contains(p)))
+        || ssid.is_some_and(|ssid| {
+            HOTSPOT_NAMES
+                .iter()
+                .any(|name| ssid.to_lowercase().contains(&name.to_lowercase()))
+        })
+}
+
+#[cfg(target_os = "macos")]
+fn detect() -> (bool, Option<String>) {
+    let Some(interface) = command_output("route", &["-n", "get", "default"]).and_then(|out| {
+        out.lines()
+            .find_map(|line| line.trim().strip_prefix("interface:"))
+            .map(|interface| interface.trim().to_string())
+    }) else {
+        return (false, None);
+    };
+
+    let port = command_output("networksetup", &["-listallhardwareports"])
+        .and_then(|ports| hardware_port(&ports, &interface));
+    let ssid =
+        command_output("networksetup", &["-getairportnetwork", &interface]).and_then(|out| {
+            out.trim()
+                .strip_prefix("Current Wi-Fi Network: ")
+                .map(str::to_string)
+        });
+
+    let metered = looks_metered(&interface, port.as_deref(), ssid.as_deref());
+    (metered, ssid.or(port))
+}
+
+#[cfg(target_os = "linux")]
+fn detect() -> (bool, Option<String>) {
+    // NMMetered: 1 = yes, 3 = guess-yes
+    let metered = command_output(
+        "busctl",
+        &[
+            "get-property",
+            "org.freedesktop.NetworkManager",
+            "/org/freedesktop/NetworkManager",
+            "org.freedesktop.NetworkManager",
+            "Metered",
+        ],
+    )
+    .is_some_and(|out| matches!(out.trim(), "u 1" | "u 3"));
+    let network = command_output(
+        "nmcli",
+        &["-t", "-f", "NAME", "connection", "show", "--active"],
+    )
+    .and_then(|out| out.lines().next().map(str::to_string))
+    .filter(|name| !name.is_empty());
+    (metered, network)
+}
+
+fn allowed_networks(app: &AppHandle) -> Vec<String> {
+    app.store(SETTINGS_STORE)
+        .ok()
+        .and_then(|store| store.get(ALLOWED_NETWORKS_KEY))
+        .and_then(|value| serde_json::from_value(value).ok())
+        .unwrap_or_default()
+}
+
+fn save_allowed_networks(app: &AppHandle, networks: Vec<String>) -> Result<(), String> {
+    let store = app
+        .store(SETTINGS_STORE)
+        .map_err(|e| format!("Failed to open settings store: {}", e))?;
+    store.set(ALLOWED_NETWORKS_KEY, serde_json::json!(networks));
+    atomic_store::save(app, SETTINGS_STORE).map_err(|e| format!("Failed to save settings: {}", e))
+}
+
+async fn network_status(app: &AppHandle) -> Result<NetworkStatus, String> {
+    let (metered, network) = tauri::async_runtime::spawn_blocking(detect)
+        .await
+        .map_err(|e| format!("Network detection task failed: {}", e))?;
+    let allowed = network
+        .as_ref()
+        .is_some_and(|network| allowed_networks(app).contains(network));
+    Ok(NetworkStatus {
+        metered,
+        network,
+        allowed,
+    })
+}
+
+/// Ask before a large download on a metered connection. Errors if the user
+/// declines.
+pub async fn confirm_large_download(app: &AppHandle, what: &str) -> Result<(), AppError> {
+    let status = network_status(app).await?;
+    if !status.metered || status.allowed {
+        return Ok(());
+    }
+
+    let network = status
+        .network
+        .as_deref()
+        .map(|name| format!(" ({})", name))
+        .unwrap_or_default();
+    let download = i18n::t(app, "dialog.metered.download");
+    let always_allow = i18n::t(app, "dialog.metered.alwaysAllow");
+    let cancel = i18n::t(app, "common.cancel");
+    let buttons = if status.network.is_some() {
+        MessageDialogButtons::YesNoCancelCustom(download.clone(), always_allow.clone(), cancel)
+    } else {
+        MessageDialogButtons::OkCancelCustom(download.clone(), cancel)
+    };
+
+    let dialog = app
+        .dialog()
+        .message(i18n::t_with(
+            app,
+            "dialog.metered.message",
+            &[("network", &network), ("what", what)],
+        ))
+        .title(i18n::t(app, "dialog.metered.title"))
+        .kind(MessageDialogKind::Warning)
+        .buttons(buttons);
+    let result = match test_mode::dialog_answer(app, "metered") {
+        Some(answer) => answer,
+        None => {
+            let (tx, rx) = oneshot::channel();
+            dialog.show_with_result(move |result| {
+                let _ = tx.send(result);
+            });
+            rx.await.unwrap_or_default()
+        }
+    };
+
+    match result {
+        MessageDialogResult::Yes | MessageDialogResult::Ok => Ok(()),
+        MessageDialogResult::Custom(choice) if choice == download => Ok(()),
+        MessageDialogResult::No => allow_network(app, status.network),
+        MessageDialogResult::Custom(choice) if choice == always_allow => {
+            allow_network(app, status.network)
+        }
+        _ => Err(AppError::cancelled(i18n::t_with(
+            app,
+            "error.downloadCancelled",
+            &[("what", what)],
+        ))),
+    }
+}
+
+fn allow_network(app: &AppHandle, network: Option<String>) -> Result<(), AppError> {
+    match network {
+        Some(network) => set_metered_network_allowed(app.clone(), network, true),
+        None => Ok(()),
+    }
+}
+
+#[tauri::command]
+pub async fn get_network_status(app: AppHandle) -> Result<NetworkStatus, AppError> {
+    Ok(network_status(&app).await?)
+}
+
+/// Allow (or stop allowing) large downloads on a metered network without asking
+#[tauri::command]
+pub fn set_metered_network_allowed(
+    app: AppHandle,
+    network: String,
+    allowed: bool,
+) -> Result<(), AppError> {
+    if network.is_empty() {
+        return Err(AppError::invalid_input("Network name is empty"));
+    }
+    let mut networks = allowed_networks(&app);
+    networks.retain(|n| n != &network);
+    if allowed {
+        networks.push(network);
+    }
+    Ok(save_allowed_networks(&app, networks)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_hardware_port_and_heuristics() {
+        let ports = "\nHardware Port: Wi-Fi\nDevice: en0\nEthernet Address: aa\n\n\
+                     Hardware Port: iPhone USB\nDevice: en8\nEthernet Address: bb\n";
+        assert_eq!(hardware_port(ports, "en0").as_deref(), Some("Wi-Fi"));
+        assert_eq!(hardware_port(ports, "en8").as_deref(), Some("iPhone USB"));
+        assert_eq!(hardware_port(ports, "en9"), None);
+
+        assert!(looks_metered("en8", Some("iPhone USB"), None));
+        assert!(looks_metered("pdp_ip0", None, None));
+        assert!(looks_metered("en0", Some("Wi-Fi"), Some("Sam's iPhone")));
+        assert!(!looks_metered("en0", Some("Wi-Fi"), Some("Office")));
+    }
+}
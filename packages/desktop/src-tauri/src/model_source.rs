@@ -0,0 +1,174 @@
+//! Where local model files are downloaded from.
+//!
+//! Models come from HuggingFace by default. On networks where HuggingFace is
+//! blocked but the configured opencode server is reachable, the files can be
+//! relayed through the server instead: `https://huggingface.co/<path>` is
+//! fetched as `<server>/proxy/huggingface/<path>`. Only servers that expose
+//! that endpoint support this, which is reported when the first file fails.
+
+use serde::{Deserialize, Serialize};
+use std::time::Duration;
+use tauri::{AppHandle, Manager, Webview};
+use tauri_plugin_store::StoreExt;
+
+use crate::{
+    SETTINGS_STORE, ServerState, atomic_store, audit,
+    error::AppError,
+    proxy,
+    retry::{self, RetryPolicy},
+    url_is_localhost,
+};
+
+const MODEL_SOURCE_KEY: &str = "modelSource";
+const HUGGINGFACE_ORIGIN: &str = "https://huggingface.co/";
+const RELAY_PATH: &str = "proxy/huggingface/";
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum ModelSource {
+    #[default]
+    HuggingFace,
+    ServerRelay,
+}
+
+fn model_source(app: &AppHandle) -> ModelSource {
+    app.store(SETTINGS_STORE)
+        .ok()
+        .and_then(|store| store.get(MODEL_SOURCE_KEY))
+        .and_then(|value| serde_json::from_value(value).ok())
+        .unwrap_or_default()
+}
+
+struct Relay {
+    base: reqwest::Url,
+    password: Option<String>,
+}
+
+/// HTTP client for model downloads that honours the configured model source
+pub(crate) struct ModelDownloader {
+    client: reqwest::Client,
+    relay: Option<Relay>,
+}
+
+impl ModelDownloader {
+    pub async fn new(app: &AppHandle) -> Result<Self, String> {
+        if model_source(app) == ModelSource::HuggingFace {
+            return Ok(Self {
+                client: reqwest::Client::new(),
+                relay: None,
+            });
+        }
+
+        let status = app
+            .try_state::<ServerState>()
+            .ok_or("Server state not found")?
+            .status
+            .clone();
+        let server = status
+            .await
+            .map_err(|_| "Failed to get server status".to_string())?
+            .map_err(|e| format!("Model relay needs a connected server: {}", e))?;
+
+        let base = reqwest::Url::parse(&server.url)
+            .and_then(|url| url.join(RELAY_PATH))
+            .map_err(|e| format!("Invalid server URL {}: {}", server.url, e))?;
+        let mut builder = reqwest::Client::builder().connect_timeout(Duration::from_secs(10));
+        if url_is_localhost(&base) {
+            builder = builder.no_proxy();
+        } else {
+            builder = proxy::apply(app, builder, &base);
+        }
+        let client = builder
+            .build()
+            .map_err(|e| format!("Failed to create HTTP client: {}", e))?;
+
+        Ok(Self {
+            client,
+            relay: Some(Relay {
+                base,
+                password: server.password,
+            }),
+        })
+    }
+
+    /// Start downloading `url` from byte `offset`, failing on a non-success
+    /// status. Servers that ignore the range answer with the whole file.
+    pub async fn get(&self, url: &str, offset: u64) -> Result<reqwest::Response, String> {
+        let request = match &self.relay {
+            None => self.client.get(url),
+            Some(relay) => {
+                let path = url
+                    .strip_prefix(HUGGINGFACE_ORIGIN)
+                    .ok_or_else(|| format!("Cannot relay {} through the server", url))?;
+                let relayed = relay
+                    .base
+                    .join(path)
+                    .map_err(|e| format!("Invalid relay URL for {}: {}", url, e))?;
+                let request = self.client.get(relayed);
+                match &relay.password {
+                    Some(password) => request.basic_auth("opencode", Some(password)),
+                    None => request,
+                }
+            }
+        };
+
+        let request = if offset > 0 {
+            request.header(reqwest::header::RANGE, format!("bytes={}-", offset))
+        } else {
+            request
+        };
+        let response = retry::send(&RetryPolicy::DOWNLOAD, request)
+            .await
+            .map_err(|e| format!("Failed to download {}: {}", url, e))?;
+
+        let status = response.status();
+        if self.relay.is_some()
+            && matches!(
+                status,
+                reqwest::StatusCode::NOT_FOUND | reqwest::StatusCode::METHOD_NOT_ALLOWED
+            )
+        {
+            return Err(format!(
+                "The connected server does not relay model downloads (HTTP {}). \
+                 Switch the model source back to HuggingFace.",
+                status
+            ));
+        }
+        if !status.is_success() {
+            return Err(format!("Failed to download {}: HTTP {}", url, status));
+        }
+        Ok(response)
+    }
+}
+
+#[tauri::command]
+pub fn get_model_source(app: AppHandle) -> ModelSource {
+    model_source(&app)
+}
+
+#[tauri::command]
+pub fn set_model_source(
+    app: AppHandle,
+    webview: Webview,
+    source: ModelSource,
+) -> Result<(), AppError> {
+    let result = save_model_source(&app, source);
+    audit::record(
+        &webview,
+        "set_model_source",
+        serde_json::json!({ "source": source }),
+        &result,
+    );
+    result
+}
+
+fn save_model_source(app: &AppHandle, source: ModelSource) -> Result<(), AppError> {
+    let store = app
+        .store(SETTINGS_STORE)
+        .map_err(|e| format!("Failed to open settings store: {}", e))?;
+    let value = serde_json::to_value(source)
+        .map_err(|e| format!("Failed to serialize model source: {}", e))?;
+    store.set(MODEL_SOURCE_KEY, value);
+    atomic_store::save(app, SETTINGS_STORE)
+        .map_err(|e| AppError::io(format!("Failed to save settings: {}", e)))
+}
@@ -0,0 +1,231 @@
+//! Coalescing of bursts of notifications.
+//!
+//! The frontend asks `coalesce_notification` before showing a notification,
+//! which is refused while the OS is in Do Not Disturb (see `focus`).
+//! Within a window of a few seconds the first notifications of a category are
+//! shown as they come; past the category's threshold the rest are held and,
+//! when the window closes, summarized in a single `notification:summary`
+//! event the frontend shows instead. The summary goes to the webview that
+//! sent the last held notification, so it is shown once. Windows and
+//! thresholds can be set per category in the settings
+//! (`notificationThresholds`).
+
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::HashMap,
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+use tauri::{AppHandle, Emitter, Manager, Webview};
+
+use crate::{app_settings, error::AppError, focus, i18n};
+
+/// Held notifications listed in a summary; the rest are counted
+const SUMMARY_ITEMS: usize = 3;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default, rename_all = "camelCase")]
+pub struct Threshold {
+    pub window_seconds: u64,
+    /// Notifications shown individually per window
+    pub max_shown: u32,
+}
+
+impl Default for Threshold {
+    fn default() -> Self {
+        Self {
+            window_seconds: 10,
+            max_shown: 2,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+struct Held {
+    /// Label of the webview that sent it
+    webview: String,
+    title: String,
+    body: Option<String>,
+    href: Option<String>,
+}
+
+#[derive(Debug)]
+struct Bucket {
+    started: Instant,
+    shown: u32,
+    held: Vec<Held>,
+}
+
+impl Bucket {
+    fn new(now: Instant) -> Self {
+        Self {
+            started: now,
+            shown: 0,
+            held: Vec::new(),
+        }
+    }
+
+    fn expired(&self, now: Instant, threshold: Threshold) -> bool {
+        now.duration_since(self.started) >= Duration::from_secs(threshold.window_seconds)
+    }
+
+    /// Whether a notification arriving now is shown; held otherwise
+    fn admit(&mut self, now: Instant, threshold: Threshold) -> bool {
+        if self.expired(now, threshold) && self.held.is_empty() {
+            *self = Self::new(now);
+        }
+        if self.shown < threshold.max_shown {
+            self.shown += 1;
+            true
+        } else {
+            false
+        }
+    }
+}
+
+#[derive(Default)]
+pub struct NotificationState(Mutex<HashMap<String, Bucket>>);
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct NotificationSummary {
+    pub category: String,
+    pub count: usize,
+    pub title: String,
+    pub body: String,
+    /// Link of the most recent held notification
+    pub href: Option<String>,
+}
+
+fn threshold(app: &AppHandle, category: &str) -> Threshold {
+    app_settings::load(app)
+        .notification_thresholds
+        .get(category)
+        .copied()
+        .unwrap_or_default()
+}
+
+fn summarize(app: &AppHandle, category: String, held: Vec<Held>) -> NotificationSummary {
+    let count = held.len();
+    let mut lines: Vec<String> = held
+        .iter()
+        .take(SUMMARY_ITEMS)
+        .map(|item| match &item.body {
+            Some(body) if !body.is_empty() => format!("{}: {}", item.title, body),
+            _ => item.title.clone(),
+        })
+        .collect();
+    if count > SUMMARY_ITEMS {
+        let more = (count - SUMMARY_ITEMS).to_string();
+        lines.push(i18n::t_with(
+            app,
+            "notification.andMore",
+            &[("count", &more)],
+        ));
+    }
+    NotificationSummary {
+        title: i18n::t_with(
+            app,
+            "notification.summaryTitle",
+            &[("count", &count.to_string())],
+        ),
+        body: lines.join("\n"),
+        href: held.into_iter().rev().find_map(|item| item.href),
+        category,
+        count,
+    }
+}
+
+/// Emit the summary of what was held in `category` once its window closes
+fn schedule_flush(app: &AppHandle, category: String, delay: Duration) {
+    let app = app.clone();
+    tauri::async_runtime::spawn(async move {
+        tokio::time::sleep(delay).await;
+        let Some(state) = app.try_state::<NotificationState>() else {
+            return;
+        };
+        let held = match state.0.lock() {
+            Ok(mut buckets) => buckets
+                .remove(&category)
+                .map(|bucket| bucket.held)
+                .unwrap_or_default(),
+            Err(_) => return,
+        };
+        let Some(target) = held.last().map(|item| item.webview.clone()) else {
+            return;
+        };
+        if focus::is_active(&app) {
+            return;
+        }
+        let summary = summarize(&app, category, held);
+        let _ = app.emit_to(target.as_str(), "notification:summary", &summary);
+    });
+}
+
+/// Whether to show a notification now. When not, it is held and included in
+/// the `notification:summary` for its category.
+#[tauri::command]
+pub fn coalesce_notification(
+    app: AppHandle,
+    webview: Webview,
+    category: Option<String>,
+    title: String,
+    body: Option<String>,
+    href: Option<String>,
+) -> Result<bool, AppError> {
+    // Not held either: what comes in during focus mode isn't summarized later
+    if focus::is_active(&app) {
+        return Ok(false);
+    }
+    let category = category.unwrap_or_else(|| "general".to_string());
+    let threshold = threshold(&app, &category);
+    let state = app
+        .try_state::<NotificationState>()
+        .ok_or("Notification state not found")?;
+    let mut buckets = state.0.lock().map_err(|e| format!("Lock error: {}", e))?;
+
+    let now = Instant::now();
+    let bucket = buckets
+        .entry(category.clone())
+        .or_insert_with(|| Bucket::new(now));
+    if bucket.admit(now, threshold) {
+        return Ok(true);
+    }
+    if bucket.held.is_empty() {
+        let remaining = Duration::from_secs(threshold.window_seconds)
+            .saturating_sub(now.duration_since(bucket.started));
+        schedule_flush(&app, category, remaining);
+    }
+    bucket.held.push(Held {
+        webview: webview.label().to_string(),
+        title,
+        body,
+        href,
+    });
+    Ok(false)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_bucket_holds_past_threshold_until_window_ends() {
+        let threshold = Threshold {
+            window_seconds: 10,
+            max_shown: 2,
+        };
+        let start = Instant::now();
+        let mut bucket = Bucket::new(start);
+
+        assert!(bucket.admit(start, threshold));
+        assert!(bucket.admit(start + Duration::from_secs(1), threshold));
+        assert!(!bucket.admit(start + Duration::from_secs(2), threshold));
+
+        // A new window starts once the previous one expired with nothing held
+        let mut bucket = Bucket::new(start);
+        bucket.shown = 2;
+        assert!(bucket.admit(start + Duration::from_secs(10), threshold));
+        assert_eq!(bucket.shown, 1);
+    }
+}
@@ -0,0 +1,603 @@
+//! Optical character recognition using PaddleOCR ONNX models.
+//!
+//! Runs a two-stage pipeline on ONNX Runtime (shared with the STT module):
+//! a DB text detector finds text regions, then a CTC recognizer reads each
+//! region. Models are downloaded on demand like the STT model.
+
+use image::{RgbImage, imageops::FilterType};
+use ort::{session::Session, value::TensorRef};
+use serde::Serialize;
+use std::{
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
+};
+use tauri::{AppHandle, Emitter, Manager, path::BaseDirectory};
+use tauri_plugin_clipboard_manager::ClipboardExt;
+
+use crate::{
+    error::AppError,
+    onnx_runtime,
+    stt::{ModelStatus, download_model_files},
+};
+
+const MODEL_NAME: &str = "paddleocr-v5-en";
+const HF_BASE_URL: &str = "https://huggingface.co/monkt/paddleocr-onnx/resolve/main";
+
+/// (remote path, local file name)
+const MODEL_FILES: &[(&str, &str)] = &[
+    ("detection/v5/det.onnx", "det.onnx"),
+    ("languages/english/rec.onnx", "rec.onnx"),
+    ("languages/english/dict.txt", "dict.txt"),
+];
+
+/// Longest side fed to the detector; larger images are downscaled
+const DET_MAX_SIDE: u32 = 960;
+/// Probability above which a detector pixel counts as text
+const DET_THRESHOLD: f32 = 0.3;
+/// Minimum mean probability for a detected region to be kept
+const DET_BOX_THRESHOLD: f32 = 0.5;
+/// How far regions are grown to cover glyph edges the detector shrinks away
+const DET_UNCLIP_RATIO: f32 = 1.5;
+const REC_HEIGHT: u32 = 48;
+const REC_MAX_WIDTH: u32 = 1280;
+
+#[derive(Debug, Clone, Copy, Serialize)]
+pub struct OcrBox {
+    pub x: u32,
+    pub y: u32,
+    pub width: u32,
+    pub height: u32,
+}
+
+/// A detected text region, usually a word or a short run of words
+#[derive(Debug, Clone, Serialize)]
+pub struct OcrWord {
+    pub text: String,
+    pub confidence: f32,
+    #[serde(rename = "box")]
+    pub bbox: OcrBox,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct OcrResult {
+    pub text: String,
+    pub words: Vec<OcrWord>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct OcrStatus {
+    pub model_status: ModelStatus,
+}
+
+pub struct OcrState {
+    detector: Option<Arc<Mutex<Session>>>,
+    recognizer: Option<Arc<Mutex<Session>>>,
+    /// Recognizer classes, index 0 is the CTC blank
+    dict: Arc<Vec<String>>,
+    model_status: ModelStatus,
+    model_dir: PathBuf,
+}
+
+impl OcrState {
+    pub fn new(model_dir: PathBuf) -> Self {
+        let mut state = Self {
+            detector: None,
+            recognizer: None,
+            dict: Arc::new(Vec::new()),
+            model_status: ModelStatus::NotDownloaded,
+            model_dir,
+        };
+
+        if Self::are_models_downloaded(&state.model_dir)
+            && let Err(e) = state.load_models()
+        {
+            state.model_status = ModelStatus::Error { message: e };
+        }
+
+        state
+    }
+
+    fn are_models_downloaded(model_dir: &Path) -> bool {
+        MODEL_FILES
+            .iter()
+            .all(|(_, file)| model_dir.join(file).exists())
+    }
+
+    pub fn get_status(&self) -> OcrStatus {
+        OcrStatus {
+            model_status: self.model_status.clone(),
+        }
+    }
+
+    fn load_dict(model_dir: &Path) -> Result<Vec<String>, String> {
+        let content = std::fs::read_to_string(model_dir.join("dict.txt"))
+            .map_err(|e| format!("Failed to read OCR dictionary: {}", e))?;
+
+        let mut dict = vec![String::new()];
+        dict.extend(content.lines().map(|line| line.to_string()));
+        // PaddleOCR appends the space character after the dictionary entries
+        dict.push(" ".to_string());
+        Ok(dict)
+    }
+
+    pub fn load_models(&mut self) -> Result<(), String> {
+        if !Self::are_models_downloaded(&self.model_dir) {
+            return Err("Models not downloaded".to_string());
+        }
+
+        let dict = Self::load_dict(&self.model_dir)?;
+        let detector = onnx_runtime::build_session(&self.model_dir.join("det.onnx"), "detector")?;
+        let recognizer =
+            onnx_runtime::build_session(&self.model_dir.join("rec.onnx"), "recognizer")?;
+
+        self.detector = Some(Arc::new(Mutex::new(detector)));
+        self.recognizer = Some(Arc::new(Mutex::new(recognizer)));
+        self.dict = Arc::new(dict);
+        self.model_status = ModelStatus::Ready;
+        Ok(())
+    }
+
+    pub fn engine(&self) -> Result<OcrEngine, String> {
+        Ok(OcrEngine {
+            detector: self.detector.as_ref().ok_or("Detector not loaded")?.clone(),
+            recognizer: self
+                .recognizer
+                .as_ref()
+                .ok_or("Recognizer not loaded")?
+                .clone(),
+            dict: self.dict.clone(),
+        })
+    }
+}
+
+pub struct OcrEngine {
+    detector: Arc<Mutex<Session>>,
+    recognizer: Arc<Mutex<Session>>,
+    dict: Arc<Vec<String>>,
+}
+
+impl OcrEngine {
+    pub fn recognize(&self, image: &RgbImage) -> Result<OcrResult, String> {
+        let boxes = self.detect(image)?;
+
+        let mut words = Vec::new();
+        for bbox in boxes {
+            let crop = image::imageops::crop_imm(image, bbox.x, bbox.y, bbox.width, bbox.height)
+                .to_image();
+            let (text, confidence) = self.read_region(&crop)?;
+            if text.trim().is_empty() {
+                continue;
+            }
+            words.push(OcrWord {
+                text: text.trim().to_string(),
+                confidence,
+                bbox,
+            });
+        }
+
+        Ok(OcrResult {
+            text: join_lines(&words),
+            words,
+        })
+    }
+
+    /// Run the DB detector and turn its probability map into bounding boxes
+    fn detect(&self, image: &RgbImage) -> Result<Vec<OcrBox>, String> {
+        let (width, height) = image.dimensions();
+        if width == 0 || height == 0 {
+            return Ok(Vec::new());
+        }
+
+        let scale = (DET_MAX_SIDE as f32 / width.max(height) as f32).min(1.0);
+        let det_width = round_to_32((width as f32 * scale) as u32);
+        let det_height = round_to_32((height as f32 * scale) as u32);
+        let resized = image::imageops::resize(image, det_width, det_height, FilterType::Triangle);
+
+        const MEAN: [f32; 3] = [0.485, 0.456, 0.406];
+        const STD: [f32; 3] = [0.229, 0.224, 0.225];
+        let mut input =
+            ndarray::Array4::<f32>::zeros((1, 3, det_height as usize, det_width as usize));
+        for (x, y, pixel) in resized.enumerate_pixels() {
+            for c in 0..3 {
+                input[[0, c, y as usize, x as usize]] =
+                    (pixel[c] as f32 / 255.0 - MEAN[c]) / STD[c];
+            }
+        }
+
+        let input_tensor = TensorRef::from_array_view(input.view())
+            .map_err(|e| format!("Failed to create detector input tensor: {}", e))?;
+
+        let mut detector = self
+            .detector
+            .lock()
+            .map_err(|e| format!("Lock error: {}", e))?;
+        let outputs = detector
+            .run(ort::inputs![input_tensor])
+            .map_err(|e| format!("Failed to run detector: {}", e))?;
+        let (shape, probs) = outputs[0]
+            .try_extract_tensor::<f32>()
+            .map_err(|e| format!("Failed to extract detector output: {}", e))?;
+
+        // Output is [1, 1, H, W]
+        let map_height = shape[2] as usize;
+        let map_width = shape[3] as usize;
+        let scale_x = width as f32 / map_width as f32;
+        let scale_y = height as f32 / map_height as f32;
+
+        let mut boxes = Vec::new();
+        for region in find_regions(probs, map_width, map_height) {
+            if region.mean_score < DET_BOX_THRESHOLD {
+                continue;
+            }
+
+            let w = (region.max_x - region.min_x + 1) as f32;
+            let h = (region.max_y - region.min_y + 1) as f32;
+            let distance = (w * h * DET_UNCLIP_RATIO) / (2.0 * (w + h));
+
+            let x0 = ((region.min_x as f32 - distance) * scale_x).max(0.0);
+            let y0 = ((region.min_y as f32 - distance) * scale_y).max(0.0);
+            let x1 = ((region.max_x as f32 + 1.0 + distance) * scale_x).min(width as f32);
+            let y1 = ((region.max_y as f32 + 1.0 + distance) * scale_y).min(height as f32);
+
+            let bbox = OcrBox {
+                x: x0 as u32,
+                y: y0 as u32,
+                width: (x1 - x0) as u32,
+                height: (y1 - y0) as u32,
+            };
+            if bbox.width >= 4 && bbox.height >= 4 {
+                boxes.push(bbox);
+            }
+        }
+
+        Ok(boxes)
+    }
+
+    /// Run the CTC recognizer over a single cropped region
+    fn read_region(&self, crop: &RgbImage) -> Result<(String, f32), String> {
+        let (width, height) = crop.dimensions();
+        let rec_width = ((width as f32 * REC_HEIGHT as f32 / height as f32).round() as u32)
+            .clamp(REC_HEIGHT / 4, REC_MAX_WIDTH);
+        let resized = image::imageops::resize(crop, rec_width, REC_HEIGHT, FilterType::Triangle);
+
+        let mut input =
+            ndarray::Array4::<f32>::zeros((1, 3, REC_HEIGHT as usize, rec_width as usize));
+        for (x, y, pixel) in resized.enumerate_pixels() {
+            for c in 0..3 {
+                input[[0, c, y as usize, x as usize]] = (pixel[c] as f32 / 255.0 - 0.5) / 0.5;
+            }
+        }
+
+        let input_tensor = TensorRef::from_array_view(input.view())
+            .map_err(|e| format!("Failed to create recognizer input tensor: {}", e))?;
+
+        let mut recognizer = self
+            .recognizer
+            .lock()
+            .map_err(|e| format!("Lock error: {}", e))?;
+        let outputs = recognizer
+            .run(ort::inputs![input_tensor])
+            .map_err(|e| format!("Failed to run recognizer: {}", e))?;
+        let (shape, probs) = outputs[0]
+            .try_extract_tensor::<f32>()
+            .map_err(|e| format!("Failed to extract recognizer output: {}", e))?;
+
+        // Output is [1, T, classes]
+        let steps = shape[1] as usize;
+        let classes = shape[2] as usize;
+        Ok(ctc_greedy_decode(probs, steps, classes, &self.dict))
+    }
+}
+
+struct Region {
+    min_x: usize,
+    min_y: usize,
+    max_x: usize,
+    max_y: usize,
+    mean_score: f32,
+}
+
+/// Find 4-connected components of above-threshold pixels in the probability map
+fn find_regions(probs: &[f32], width: usize, height: usize) -> Vec<Region> {
+    let mut visited = vec![false; width * height];
+    let mut regions = Vec::new();
+    let mut stack = Vec::new();
+
+    for start in 0..width * height {
+        if visited[start] || probs[start] < DET_THRESHOLD {
+            continue;
+        }
+
+        let mut region = Region {
+            min_x: usize::MAX,
+            min_y: usize::MAX,
+            max_x: 0,
+            max_y: 0,
+            mean_score: 0.0,
+        };
+        let mut count = 0usize;
+        let mut score = 0f32;
+
+        visited[start] = true;
+        stack.push(start);
+        while let Some(index) = stack.pop() {
+            let (x, y) = (index % width, index / width);
+            region.min_x = region.min_x.min(x);
+            region.min_y = region.min_y.min(y);
+            region.max_x = region.max_x.max(x);
+            region.max_y = region.max_y.max(y);
+            count += 1;
+            score += probs[index];
+
+            let mut visit = |next: usize| {
+                if !visited[next] && probs[next] >= DET_THRESHOLD {
+                    visited[next] = true;
+                    stack.push(next);
+                }
+            };
+            if x > 0 {
+                visit(index - 1);
+            }
+            if x + 1 < width {
+                visit(index + 1);
+            }
+            if y > 0 {
+                visit(index - width);
+            }
+            if y + 1 < height {
+                visit(index + width);
+            }
+        }
+
+        // Skip specks that can't hold a glyph
+        if count < 10 || region.max_x - region.min_x < 2 || region.max_y - region.min_y < 2 {
+            continue;
+        }
+
+        region.mean_score = score / count as f32;
+        regions.push(region);
+    }
+
+    regions
+}
+
+/// Greedy CTC decoding: take the best class per step, collapse repeats, drop blanks
+fn ctc_greedy_decode(
+    probs: &[f32],
+    steps: usize,
+    classes: usize,
+    dict: &[String],
+) -> (String, f32) {
+    let mut text = String::new();
+    let mut confidence_sum = 0f32;
+    let mut emitted = 0usize;
+    let mut previous = 0usize;
+
+    for step in 0..steps {
+        let row = &probs[step * classes..(step + 1) * classes];
+        let (best, best_prob) = row
+            .iter()
+            .enumerate()
+            .max_by(|(_, a), (_, b)| a.partial_cmp(b).unwrap_or(std::cmp::Ordering::Equal))
+            .map(|(i, p)| (i, *p))
+            .unwrap_or((0, 0.0));
+
+        if best != 0
+            && best != previous
+            && let Some(token) = dict.get(best)
+        {
+            text.push_str(token);
+            confidence_sum += best_prob;
+            emitted += 1;
+        }
+        previous = best;
+    }
+
+    let confidence = if emitted == 0 {
+        0.0
+    } else {
+        confidence_sum / emitted as f32
+    };
+    (text, confidence)
+}
+
+fn round_to_32(value: u32) -> u32 {
+    (((value + 16) / 32) * 32).max(32)
+}
+
+/// Arrange words into reading order: top-to-bottom lines, left-to-right within a line
+fn join_lines(words: &[OcrWord]) -> String {
+    let mut sorted: Vec<&OcrWord> = words.iter().collect();
+    sorted.sort_by_key(|w| (w.bbox.y + w.bbox.height / 2, w.bbox.x));
+
+    let mut lines: Vec<Vec<&OcrWord>> = Vec::new();
+    for word in sorted {
+        let center = word.bbox.y + word.bbox.height / 2;
+        let same_line = lines
+            .last()
+            .and_then(|line| line.first())
+            .is_some_and(|first| {
+                let first_center = first.bbox.y + first.bbox.height / 2;
+                center.abs_diff(first_center) <= first.bbox.height / 2
+            });
+        match lines.last_mut() {
+            Some(line) if same_line => line.push(word),
+            _ => lines.push(vec![word]),
+        }
+    }
+
+    lines
+        .into_iter()
+        .map(|mut line| {
+            line.sort_by_key(|w| w.bbox.x);
+            line.iter()
+                .map(|w| w.text.as_str())
+                .collect::<Vec<_>>()
+                .join(" ")
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+pub type SharedOcrState = Arc<Mutex<OcrState>>;
+
+pub fn get_model_dir(app: &AppHandle) -> PathBuf {
+    app.path()
+        .resolve(
+            format!("models/{}", MODEL_NAME),
+            BaseDirectory::AppLocalData,
+        )
+        .expect("Failed to resolve OCR model directory")
+}
+
+pub fn init_ocr_state(app: &AppHandle) -> SharedOcrState {
+    Arc::new(Mutex::new(OcrState::new(get_model_dir(app))))
+}
+
+#[tauri::command]
+pub async fn ocr_get_status(app: AppHandle) -> Result<OcrStatus, AppError> {
+    let state = app
+        .try_state::<SharedOcrState>()
+        .ok_or("OCR state not found")?;
+    let state = state.lock().map_err(|e| format!("Lock error: {}", e))?;
+    Ok(state.get_status())
+}
+
+#[tauri::command]
+pub async fn ocr_download_model(app: AppHandle) -> Result<(), AppError> {
+    {
+        let state = app.state::<SharedOcrState>();
+        let mut state = state.lock().map_err(|e| format!("Lock error: {}", e))?;
+        if matches!(state.model_status, ModelStatus::Ready) {
+            return Ok(());
+        }
+        state.model_status = ModelStatus::Downloading { progress: 0.0 };
+    }
+
+    let model_dir = get_model_dir(&app);
+    let result = download_model_files(&app, HF_BASE_URL, MODEL_FILES, &model_dir, |progress| {
+        let _ = app.emit("ocr:download-progress", progress);
+        if let Ok(mut state) = app.state::<SharedOcrState>().lock() {
+            state.model_status = ModelStatus::Downloading { progress };
+        }
+    })
+    .await;
+
+    let state = app.state::<SharedOcrState>();
+    let mut state = state.lock().map_err(|e| format!("Lock error: {}", e))?;
+    state.model_dir = model_dir;
+    if let Err(e) = result.and_then(|_| Ok(state.load_models()?)) {
+        state.model_status = ModelStatus::Error {
+            message: e.message.clone(),
+        };
+        return Err(e);
+    }
+    Ok(())
+}
+
+/// An image to recognize, before it's decoded
+enum Source {
+    File(String),
+    Clipboard {
+        width: u32,
+        height: u32,
+        rgba: Vec<u8>,
+    },
+}
+
+impl Source {
+    fn decode(self) -> Result<RgbImage, AppError> {
+        Ok(match self {
+            Source::File(path) => image::open(&path)
+                .map_err(|e| {
+                    AppError::invalid_input(format!("Failed to open image {}: {}", path, e))
+                })?
+                .to_rgb8(),
+            Source::Clipboard {
+                width,
+                height,
+                rgba,
+            } => {
+                let rgba = image::RgbaImage::from_raw(width, height, rgba)
+                    .ok_or("Clipboard image has an invalid size")?;
+                image::DynamicImage::ImageRgba8(rgba).to_rgb8()
+            }
+        })
+    }
+}
+
+/// Recognize text in an image file, or in the clipboard image when `path` is omitted
+#[tauri::command]
+pub async fn ocr_image(app: AppHandle, path: Option<String>) -> Result<OcrResult, AppError> {
+    let source = match path {
+        Some(path) => Source::File(path),
+        None => {
+            let clipboard = app
+                .clipboard()
+                .read_image()
+                .map_err(|e| format!("Failed to read clipboard image: {}", e))?;
+            Source::Clipboard {
+                width: clipboard.width(),
+                height: clipboard.height(),
+                rgba: clipboard.rgba().to_vec(),
+            }
+        }
+    };
+
+    let engine = {
+        let state = app
+            .try_state::<SharedOcrState>()
+            .ok_or("OCR state not found")?;
+        let state = state.lock().map_err(|e| format!("Lock error: {}", e))?;
+        if !matches!(state.model_status, ModelStatus::Ready) {
+            return Err(AppError::unavailable(
+                "OCR model not ready. Please download the model first.",
+            ));
+        }
+        state.engine()?
+    };
+
+    // Decoding a large image takes as long as recognizing it
+    tauri::async_runtime::spawn_blocking(move || Ok(engine.recognize(&source.decode()?)?))
+        .await
+        .map_err(|e| format!("OCR task failed: {}", e))?
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_ctc_greedy_decode_collapses_repeats_and_blanks() {
+        let dict: Vec<String> = ["", "a", "b"].iter().map(|s| s.to_string()).collect();
+        // steps: a, a, blank, a, b
+        let probs = [
+            0.1, 0.8, 0.1, //
+            0.1, 0.8, 0.1, //
+            0.9, 0.05, 0.05, //
+            0.1, 0.7, 0.2, //
+            0.1, 0.1, 0.8,
+        ];
+        let (text, confidence) = ctc_greedy_decode(&probs, 5, 3, &dict);
+        assert_eq!(text, "aab");
+        assert!((confidence - (0.8 + 0.7 + 0.8) / 3.0).abs() < 1e-6);
+    }
+
+    #[test]
+    fn test_find_regions_separates_components() {
+        let mut probs = vec![0.0f32; 20 * 10];
+        for y in 1..5 {
+            for x in 1..6 {
+                probs[y * 20 + x] = 0.9;
+            }
+            for x in 10..16 {
+                probs[y * 20 + x] = 0.8;
+            }
+        }
+        let regions = find_regions(&probs, 20, 10);
+        assert_eq!(regions.len(), 2);
+        assert_eq!((regions[0].min_x, regions[0].max_x), (1, 5));
+        assert_eq!((regions[1].min_x, regions[1].max_x), (10, 15));
+    }
+}
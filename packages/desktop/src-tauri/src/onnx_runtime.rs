@@ -0,0 +1,89 @@
+//! Shared ONNX Runtime setup for the local models (STT, OCR, embeddings,
+//! diarization).
+//!
+//! Loading the runtime can fail on machines with missing or outdated system
+//! libraries, and `ort` reports some of those failures by panicking. A panic
+//! while a model state mutex is held would poison it and break every later
+//! command, so init and session creation run behind `catch_unwind` and the
+//! init outcome is cached: a broken runtime becomes a `ModelStatus::Error`
+//! with remediation steps, and the rest of the app keeps working.
+
+use ort::session::{Session, builder::GraphOptimizationLevel};
+use std::{
+    any::Any,
+    panic::{self, AssertUnwindSafe},
+    path::Path,
+    sync::OnceLock,
+};
+
+static INIT_RESULT: OnceLock<Result<(), String>> = OnceLock::new();
+
+fn remediation() -> &'static str {
+    if cfg!(target_os = "windows") {
+        "Install the latest Microsoft Visual C++ Redistributable (x64) from \
+         https://aka.ms/vs/17/release/vc_redist.x64.exe, then restart the app."
+    } else if cfg!(target_os = "macos") {
+        "Update macOS to the latest version, then restart the app."
+    } else {
+        "Local models need a recent glibc and libstdc++. Update your system \
+         libraries (or use a newer distribution), then restart the app."
+    }
+}
+
+fn panic_message(payload: Box<dyn Any + Send>) -> String {
+    payload
+        .downcast_ref::<&str>()
+        .map(|s| s.to_string())
+        .or_else(|| payload.downcast_ref::<String>().cloned())
+        .unwrap_or_else(|| "unknown panic".to_string())
+}
+
+/// Run `f`, turning a panic into an error that explains how to fix the runtime
+fn guarded<T>(what: &str, f: impl FnOnce() -> Result<T, String>) -> Result<T, String> {
+    panic::catch_unwind(AssertUnwindSafe(f)).unwrap_or_else(|payload| {
+        let message = panic_message(payload);
+        eprintln!("ONNX Runtime panicked while {}: {}", what, message);
+        Err(format!(
+            "ONNX Runtime failed while {} ({}). {}",
+            what,
+            message,
+            remediation()
+        ))
+    })
+}
+
+/// Initialize the global ONNX Runtime environment once per process
+pub fn init() -> Result<(), String> {
+    INIT_RESULT
+        .get_or_init(|| {
+            guarded("initializing", || {
+                ort::init()
+                    .with_name("opencode")
+                    .commit()
+                    .map(|_| ())
+                    .map_err(|e| {
+                        format!(
+                            "ONNX Runtime failed to initialize ({}). {}",
+                            e,
+                            remediation()
+                        )
+                    })
+            })
+        })
+        .clone()
+}
+
+/// Load a model with the settings shared by all local models
+pub fn build_session(path: &Path, what: &str) -> Result<Session, String> {
+    init()?;
+    guarded(&format!("loading the {} model", what), || {
+        Session::builder()
+            .map_err(|e| format!("Failed to create {} session builder: {}", what, e))?
+            .with_optimization_level(GraphOptimizationLevel::Level3)
+            .map_err(|e| format!("Failed to set optimization level: {}", e))?
+            .with_intra_threads(4)
+            .map_err(|e| format!("Failed to set intra threads: {}", e))?
+            .commit_from_file(path)
+            .map_err(|e| format!("Failed to load {} model: {}", what, e))
+    })
+}
@@ -0,0 +1,498 @@
+//! Prompts written while the server can't be reached, kept to be sent later.
+//!
+//! `outbox_submit` sends a prompt to a session like the frontend would, but
+//! when the server doesn't answer its health check the prompt is queued in
+//! `outbox.json` instead of being lost. Prompts for a session that already
+//! has some queued are queued behind them, to keep their order. While the
+//! outbox isn't empty the server is polled, and `outbox:reconnected` tells
+//! the frontend once it is back, so the user can review the queue and
+//! confirm `outbox_replay`.
+//!
+//! Replaying sends the prompts oldest first. A session that was deleted
+//! meanwhile, or that changed since the user last saw it, is a conflict: its
+//! prompts stay queued until replayed with `force` or discarded. Prompts
+//! queued behind one that was sent follow it, so the session changing with
+//! the reply isn't a conflict for them. Every change to the queue is emitted
+//! as `outbox:changed`.
+
+use serde::{Deserialize, Serialize};
+use serde_json::{Value, json};
+use std::{
+    collections::HashSet,
+    path::{Path, PathBuf},
+    sync::{
+        Mutex,
+        atomic::{AtomicBool, Ordering},
+    },
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+use tauri::{AppHandle, Emitter, Manager, Webview, path::BaseDirectory};
+use tokio::sync::Notify;
+
+use crate::{
+    ServerReadyData, audit,
+    error::{AppError, ErrorKind},
+    retry::RetryPolicy,
+    vault_export,
+};
+
+pub const OUTBOX_FILE: &str = "outbox.json";
+const POLL_INTERVAL: Duration = Duration::from_secs(15);
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(10);
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct QueuedPrompt {
+    pub id: String,
+    pub session_id: String,
+    pub text: String,
+    /// Milliseconds since the Unix epoch
+    pub queued_at: u64,
+    /// `time.updated` of the session as the user last saw it
+    pub session_updated: Option<u64>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(tag = "status", rename_all = "camelCase")]
+pub enum Submission {
+    Sent,
+    Queued { prompt: QueuedPrompt },
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub enum ConflictReason {
+    /// The session no longer exists
+    Deleted,
+    /// The session has messages the user didn't see when writing the prompt
+    Changed,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Conflict {
+    pub id: String,
+    pub session_id: String,
+    pub reason: ConflictReason,
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ReplayReport {
+    pub sent: Vec<String>,
+    pub conflicts: Vec<Conflict>,
+    /// Why replaying stopped early, if it did
+    pub error: Option<String>,
+}
+
+#[derive(Default)]
+pub struct OutboxState {
+    /// Held while the file is read and written
+    file: Mutex<()>,
+    replaying: AtomicBool,
+    /// Whether the user was told about the current reconnection
+    announced: AtomicBool,
+    wake: Notify,
+}
+
+fn now_ms() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_millis() as u64)
+        .unwrap_or(0)
+}
+
+fn outbox_path(app: &AppHandle) -> Result<PathBuf, String> {
+    app.path()
+        .resolve(OUTBOX_FILE, BaseDirectory::AppLocalData)
+        .map_err(|e| format!("Failed to resolve outbox path: {}", e))
+}
+
+fn state(app: &AppHandle) -> Result<tauri::State<'_, OutboxState>, String> {
+    app.try_state::<OutboxState>()
+        .ok_or_else(|| "Outbox state not found".to_string())
+}
+
+fn read(path: &Path) -> Result<Vec<QueuedPrompt>, String> {
+    match std::fs::read_to_string(path) {
+        Ok(contents) => serde_json::from_str(&contents)
+            .map_err(|e| format!("Failed to parse {}: {}", path.display(), e)),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(Vec::new()),
+        Err(e) => Err(format!("Failed to read {}: {}", path.display(), e)),
+    }
+}
+
+fn write(path: &Path, prompts: &[QueuedPrompt]) -> Result<(), String> {
+    if prompts.is_empty() {
+        return match std::fs::remove_file(path) {
+            Err(e) if e.kind() != std::io::ErrorKind::NotFound => {
+                Err(format!("Failed to remove {}: {}", path.display(), e))
+            }
+            _ => Ok(()),
+        };
+    }
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent)
+            .map_err(|e| format!("Failed to create {}: {}", parent.display(), e))?;
+    }
+    let contents = serde_json::to_string_pretty(prompts)
+        .map_err(|e| format!("Failed to serialize outbox: {}", e))?;
+    // Written aside and moved in place, so a crash never loses the queue
+    let partial = path.with_extension("json.partial");
+    std::fs::write(&partial, contents)
+        .and_then(|_| std::fs::rename(&partial, path))
+        .map_err(|e| format!("Failed to write {}: {}", path.display(), e))
+}
+
+fn queued(app: &AppHandle) -> Result<Vec<QueuedPrompt>, String> {
+    let state = state(app)?;
+    let _file = state
+        .file
+        .lock()
+        .map_err(|e| format!("Lock error: {}", e))?;
+    read(&outbox_path(app)?)
+}
+
+/// Change the queue on disk, announcing the result
+fn modify<R>(app: &AppHandle, f: impl FnOnce(&mut Vec<QueuedPrompt>) -> R) -> Result<R, String> {
+    let state = state(app)?;
+    let _file = state
+        .file
+        .lock()
+        .map_err(|e| format!("Lock error: {}", e))?;
+    let path = outbox_path(app)?;
+    let mut prompts = read(&path)?;
+    let result = f(&mut prompts);
+    write(&path, &prompts)?;
+    let _ = app.emit("outbox:changed", &prompts);
+    Ok(result)
+}
+
+async fn reachable(app: &AppHandle, server: &ServerReadyData) -> bool {
+    crate::check_server_health(
+        app,
+        &server.url,
+        server.password.as_deref(),
+        &RetryPolicy::NONE,
+    )
+    .await
+}
+
+fn enqueue(
+    app: &AppHandle,
+    session_id: &str,
+    text: &str,
+    session_updated: Option<u64>,
+) -> Result<QueuedPrompt, String> {
+    let prompt = QueuedPrompt {
+        id: uuid::Uuid::new_v4().to_string(),
+        session_id: session_id.to_string(),
+        text: text.to_string(),
+        queued_at: now_ms(),
+        session_updated,
+    };
+    modify(app, |prompts| prompts.push(prompt.clone()))?;
+    let state = state(app)?;
+    state.announced.store(false, Ordering::Relaxed);
+    state.wake.notify_one();
+    Ok(prompt)
+}
+
+/// Whether `session` changed since the user saw it at `seen`
+fn changed(seen: Option<u64>, session: &Value) -> Option<ConflictReason> {
+    let updated = session.pointer("/time/updated").and_then(Value::as_u64);
+    match (seen, updated) {
+        (Some(seen), Some(updated)) if updated > seen => Some(ConflictReason::Changed),
+        _ => None,
+    }
+}
+
+/// Drop `sent` from the queue. The prompts queued behind it in its session
+/// now follow it rather than what the user saw.
+fn remove_sent(prompts: &mut Vec<QueuedPrompt>, sent: &QueuedPrompt) {
+    prompts.retain(|queued| queued.id != sent.id);
+    for queued in prompts.iter_mut() {
+        if queued.session_id == sent.session_id {
+            queued.session_updated = None;
+        }
+    }
+}
+
+/// What replaying does with each prompt, taken in queue order
+#[derive(Debug, PartialEq, Eq)]
+enum Step {
+    /// Check the session for conflicts before sending, at its first prompt
+    Check,
+    Send,
+    /// Leave queued, behind a prompt of the session that conflicted
+    Skip,
+}
+
+#[derive(Default)]
+struct ReplayOrder {
+    checked: HashSet<String>,
+    /// Sessions whose next prompt stays queued, and the ones after it too
+    held: HashSet<String>,
+}
+
+impl ReplayOrder {
+    fn step(&mut self, prompt: &QueuedPrompt) -> Step {
+        if self.held.contains(&prompt.session_id) {
+            Step::Skip
+        } else if self.checked.insert(prompt.session_id.clone()) {
+            Step::Check
+        } else {
+            Step::Send
+        }
+    }
+
+    fn hold(&mut self, session_id: &str) {
+        self.held.insert(session_id.to_string());
+    }
+}
+
+/// Why the prompts of a session can't be sent as they are, if they can't
+async fn conflict(
+    app: &AppHandle,
+    server: &ServerReadyData,
+    prompt: &QueuedPrompt,
+) -> Result<Option<ConflictReason>, AppError> {
+    let url = reqwest::Url::parse(&server.url)
+        .map_err(|e| format!("Invalid server URL {}: {}", server.url, e))?;
+    let client = crate::server_client(app, &url, REQUEST_TIMEOUT)?;
+    let path = ["session", prompt.session_id.as_str()];
+    let session = match vault_export::get_json(&client, server, &url, &path).await {
+        Ok(session) => session,
+        Err(e) if e.kind == ErrorKind::NotFound => return Ok(Some(ConflictReason::Deleted)),
+        Err(e) => return Err(e),
+    };
+    Ok(changed(prompt.session_updated, &session))
+}
+
+async fn replay(
+    app: &AppHandle,
+    ids: Option<&[String]>,
+    force: bool,
+) -> Result<ReplayReport, AppError> {
+    let server = crate::connected_server(app).await?;
+    let prompts: Vec<QueuedPrompt> = queued(app)?
+        .into_iter()
+        .filter(|prompt| ids.is_none_or(|ids| ids.contains(&prompt.id)))
+        .collect();
+
+    let mut report = ReplayReport::default();
+    let mut order = ReplayOrder::default();
+    for prompt in prompts {
+        match order.step(&prompt) {
+            Step::Skip => continue,
+            Step::Send => {}
+            Step::Check => {
+                let reason = match conflict(app, &server, &prompt).await {
+                    Ok(reason) => reason,
+                    Err(e) => {
+                        report.error = Some(e.message);
+                        break;
+                    }
+                };
+                // A deleted session can't take the prompt, even when forced
+                if let Some(reason) =
+                    reason.filter(|reason| !force || *reason == ConflictReason::Deleted)
+                {
+                    order.hold(&prompt.session_id);
+                    report.conflicts.push(Conflict {
+                        id: prompt.id,
+                        session_id: prompt.session_id,
+                        reason,
+                    });
+                    continue;
+                }
+            }
+        }
+        if let Err(e) =
+            crate::send_prompt_to_session(app, &server, &prompt.session_id, &prompt.text).await
+        {
+            report.error = Some(e);
+            break;
+        }
+        modify(app, |prompts| remove_sent(prompts, &prompt))?;
+        report.sent.push(prompt.id);
+    }
+    Ok(report)
+}
+
+/// Poll the server while prompts are queued, telling the frontend when it
+/// can be reached again
+pub fn watch(app: &AppHandle) {
+    let app = app.clone();
+    tauri::async_runtime::spawn(async move {
+        loop {
+            let Ok(state) = state(&app) else {
+                return;
+            };
+            let prompts = queued(&app).unwrap_or_default();
+            if prompts.is_empty() {
+                state.wake.notified().await;
+                continue;
+            }
+            if let Ok(server) = crate::connected_server(&app).await {
+                if !reachable(&app, &server).await {
+                    state.announced.store(false, Ordering::Relaxed);
+                } else if !state.announced.swap(true, Ordering::Relaxed) {
+                    let _ = app.emit("outbox:reconnected", &prompts);
+                }
+            }
+            let _ = tokio::time::timeout(POLL_INTERVAL, state.wake.notified()).await;
+        }
+    });
+}
+
+/// Send a prompt to a session, or queue it if the server can't be reached
+#[tauri::command]
+pub async fn outbox_submit(
+    app: AppHandle,
+    session_id: String,
+    text: String,
+    session_updated: Option<u64>,
+) -> Result<Submission, AppError> {
+    if text.trim().is_empty() {
+        return Err(AppError::invalid_input("The prompt is empty"));
+    }
+    let server = crate::connected_server(&app).await?;
+
+    let behind = queued(&app)?
+        .iter()
+        .any(|prompt| prompt.session_id == session_id);
+    if !behind {
+        let Err(e) = crate::send_prompt_to_session(&app, &server, &session_id, &text).await else {
+            return Ok(Submission::Sent);
+        };
+        // Only a server that is away is waited for; a rejection is final
+        if reachable(&app, &server).await {
+            return Err(crate::server_error(&app, e));
+        }
+    }
+    let prompt = enqueue(&app, &session_id, &text, session_updated)?;
+    Ok(Submission::Queued { prompt })
+}
+
+/// Queued prompts, oldest first
+#[tauri::command]
+pub fn outbox_list(app: AppHandle) -> Result<Vec<QueuedPrompt>, AppError> {
+    Ok(queued(&app)?)
+}
+
+/// Send the queued prompts with the given ids, or all, in the order they
+/// were written
+#[tauri::command]
+pub async fn outbox_replay(
+    app: AppHandle,
+    webview: Webview,
+    ids: Option<Vec<String>>,
+    force: Option<bool>,
+) -> Result<ReplayReport, AppError> {
+    let state = state(&app)?;
+    if state.replaying.swap(true, Ordering::AcqRel) {
+        return Err(AppError::busy("The outbox is already being sent"));
+    }
+    let result = replay(&app, ids.as_deref(), force.unwrap_or(false)).await;
+    state.replaying.store(false, Ordering::Release);
+    audit::record(
+        &webview,
+        "outbox_replay",
+        json!({ "ids": ids, "force": force }),
+        &result,
+    );
+    result
+}
+
+/// Drop a queued prompt without sending it
+#[tauri::command]
+pub fn outbox_discard(app: AppHandle, webview: Webview, id: String) -> Result<(), AppError> {
+    let result = modify(&app, |prompts| {
+        let before = prompts.len();
+        prompts.retain(|prompt| prompt.id != id);
+        before != prompts.len()
+    })
+    .map_err(AppError::from)
+    .and_then(|removed| {
+        if removed {
+            Ok(())
+        } else {
+            Err(AppError::not_found(format!("No queued prompt {}", id)))
+        }
+    });
+    audit::record(&webview, "outbox_discard", json!({ "id": id }), &result);
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn prompt(id: &str, session_id: &str, session_updated: Option<u64>) -> QueuedPrompt {
+        QueuedPrompt {
+            id: id.to_string(),
+            session_id: session_id.to_string(),
+            text: id.to_string(),
+            queued_at: 0,
+            session_updated,
+        }
+    }
+
+    #[test]
+    fn test_replay_order_holds_conflicting_sessions_only() {
+        let prompts = [
+            prompt("a1", "a", None),
+            prompt("b1", "b", None),
+            prompt("a2", "a", None),
+            prompt("c1", "c", None),
+            prompt("b2", "b", None),
+        ];
+        let mut order = ReplayOrder::default();
+        let mut steps = Vec::new();
+        for prompt in &prompts {
+            let step = order.step(prompt);
+            // Session a conflicts
+            if step == Step::Check && prompt.session_id == "a" {
+                order.hold(&prompt.session_id);
+            }
+            steps.push((prompt.id.as_str(), step));
+        }
+        assert_eq!(
+            steps,
+            [
+                ("a1", Step::Check),
+                ("b1", Step::Check),
+                ("a2", Step::Skip),
+                ("c1", Step::Check),
+                ("b2", Step::Send),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_sent_prompts_dont_conflict_with_the_ones_behind() {
+        let session = json!({ "id": "a", "time": { "updated": 200 } });
+        assert_eq!(changed(Some(100), &session), Some(ConflictReason::Changed));
+        assert_eq!(changed(Some(200), &session), None);
+        assert_eq!(changed(None, &session), None);
+
+        let mut prompts = vec![
+            prompt("a1", "a", Some(100)),
+            prompt("b1", "b", Some(100)),
+            prompt("a2", "a", Some(100)),
+        ];
+        let sent = prompts[0].clone();
+        remove_sent(&mut prompts, &sent);
+        assert_eq!(
+            prompts,
+            [prompt("b1", "b", Some(100)), prompt("a2", "a", None)]
+        );
+        // Sending a1 changed the session, which a2 is written to follow
+        assert_eq!(changed(prompts[1].session_updated, &session), None);
+        assert_eq!(
+            changed(prompts[0].session_updated, &session),
+            Some(ConflictReason::Changed)
+        );
+    }
+}
@@ -0,0 +1,161 @@
+//! Keeping a panic in one command from taking others down with it.
+//!
+//! `catch_panics` wraps the command dispatcher: a command that panics while
+//! it runs is answered with an `Internal` error instead of leaving the
+//! frontend's promise pending. Async commands are past the dispatcher once
+//! they start, but their heavy work runs in `spawn_blocking`, where a panic
+//! comes back as a join error that `join_error` describes.
+//!
+//! A panic while a mutex is held poisons it, and every later `lock` of it
+//! failed, so one bad recording left speech-to-text unusable until restart.
+//! The model sessions are taken with `lock`, which clears the poison
+//! instead; the speech-to-text state is behind an async mutex, which isn't
+//! poisoned.
+//!
+//! `install` writes a report of every panic to the crash reports directory,
+//! which `retention` prunes.
+
+use std::{
+    any::Any,
+    cell::RefCell,
+    fmt::Write as _,
+    panic::AssertUnwindSafe,
+    path::Path,
+    sync::{Mutex, MutexGuard},
+    time::{SystemTime, UNIX_EPOCH},
+};
+use tauri::{AppHandle, Manager, Wry, ipc::Invoke, path::BaseDirectory};
+
+use crate::{
+    error::{AppError, ErrorKind},
+    retention::CRASH_REPORTS_DIR,
+};
+
+thread_local! {
+    /// The command being dispatched on this thread, for the crash report
+    static COMMAND: RefCell<Option<String>> = const { RefCell::new(None) };
+}
+
+fn panic_message(payload: &(dyn Any + Send)) -> String {
+    payload
+        .downcast_ref::<&str>()
+        .map(|message| message.to_string())
+        .or_else(|| payload.downcast_ref::<String>().cloned())
+        .unwrap_or_else(|| "Unknown panic".to_string())
+}
+
+fn report(info: &std::panic::PanicHookInfo, version: &str) -> String {
+    let thread = std::thread::current();
+    let mut report = format!(
+        "Aura {} panicked on thread '{}'",
+        version,
+        thread.name().unwrap_or("<unnamed>")
+    );
+    if let Some(location) = info.location() {
+        let _ = write!(report, " at {}", location);
+    }
+    let _ = write!(report, "\n{}\n", panic_message(info.payload()));
+    if let Some(command) = COMMAND.with(|command| command.borrow().clone()) {
+        let _ = write!(report, "\nWhile running command {}\n", command);
+    }
+    let _ = write!(report, "\n{}\n", std::backtrace::Backtrace::force_capture());
+    report
+}
+
+fn write_report(dir: &Path, report: &str) {
+    let millis = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_millis())
+        .unwrap_or(0);
+    let path = dir.join(format!("panic-{}.txt", millis));
+    if let Err(e) = std::fs::create_dir_all(dir).and_then(|_| std::fs::write(&path, report)) {
+        eprintln!("Failed to write crash report {}: {}", path.display(), e);
+    }
+}
+
+/// Write a crash report for every panic, then report it as before
+pub fn install(app: &AppHandle) {
+    // Resolved now: a panic may come from code that holds what resolving
+    // would need
+    let Ok(dir) = app
+        .path()
+        .resolve(CRASH_REPORTS_DIR, BaseDirectory::AppLocalData)
+    else {
+        return;
+    };
+    let version = app.package_info().version.to_string();
+    let previous = std::panic::take_hook();
+    std::panic::set_hook(Box::new(move |info| {
+        write_report(&dir, &report(info, &version));
+        previous(info);
+    }));
+}
+
+/// Answer a command that panics with an error rather than not at all
+pub fn catch_panics<F>(handler: F) -> impl Fn(Invoke<Wry>) -> bool + Send + Sync + 'static
+where
+    F: Fn(Invoke<Wry>) -> bool + Send + Sync + 'static,
+{
+    move |invoke| {
+        let command = invoke.message.command().to_string();
+        let resolver = invoke.resolver.clone();
+        COMMAND.with(|current| *current.borrow_mut() = Some(command.clone()));
+        let handled = std::panic::catch_unwind(AssertUnwindSafe(|| handler(invoke)));
+        COMMAND.with(|current| *current.borrow_mut() = None);
+        match handled {
+            Ok(handled) => handled,
+            Err(payload) => {
+                // A no-op when the command had answered before panicking
+                resolver.reject(AppError::new(
+                    ErrorKind::Internal,
+                    format!(
+                        "{} failed unexpectedly: {}",
+                        command,
+                        panic_message(payload.as_ref())
+                    ),
+                ));
+                true
+            }
+        }
+    }
+}
+
+/// What went wrong in a `spawn_blocking` task
+pub fn join_error(error: tauri::Error) -> String {
+    match error {
+        tauri::Error::JoinError(error) if error.is_panic() => format!(
+            "Failed unexpectedly: {}",
+            panic_message(error.into_panic().as_ref())
+        ),
+        error => error.to_string(),
+    }
+}
+
+/// Lock `mutex`, clearing the poison a panic while it was held left
+pub fn lock<T>(mutex: &Mutex<T>) -> MutexGuard<'_, T> {
+    mutex.lock().unwrap_or_else(|poisoned| {
+        mutex.clear_poison();
+        poisoned.into_inner()
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_lock_clears_poison() {
+        let mutex = std::sync::Arc::new(Mutex::new(1));
+        let poisoner = mutex.clone();
+        let _ = std::thread::spawn(move || {
+            let _guard = poisoner.lock().unwrap();
+            panic!("while locked");
+        })
+        .join();
+        assert!(mutex.is_poisoned());
+
+        *lock(&mutex) += 1;
+        assert!(!mutex.is_poisoned());
+        assert_eq!(*mutex.lock().unwrap(), 2);
+    }
+}
@@ -0,0 +1,103 @@
+//! PDF text extraction for prompt attachments.
+//!
+//! Text is extracted locally, page by page, with line breaks preserved so
+//! specs and papers can be attached to a prompt as plain text.
+
+use pdf_extract::{Document, PlainTextOutput};
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+
+use crate::error::AppError;
+
+/// 1-based, inclusive page range
+#[derive(Debug, Clone, Copy, Deserialize)]
+pub struct PageRange {
+    pub start: u32,
+    pub end: u32,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PdfPage {
+    pub page: u32,
+    pub text: String,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PdfText {
+    pub page_count: u32,
+    pub pages: Vec<PdfPage>,
+}
+
+/// Collapse the runs of blank lines the layout pass tends to produce
+fn normalize_page_text(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    let mut blank_lines = 0;
+    for line in text.lines() {
+        let line = line.trim_end();
+        if line.trim().is_empty() {
+            blank_lines += 1;
+            if blank_lines > 1 {
+                continue;
+            }
+        } else {
+            blank_lines = 0;
+        }
+        out.push_str(line);
+        out.push('\n');
+    }
+    out.trim().to_string()
+}
+
+pub fn extract_text(path: &Path, range: Option<PageRange>) -> Result<PdfText, String> {
+    let mut doc = Document::load(path)
+        .map_err(|e| format!("Failed to open PDF {}: {}", path.display(), e))?;
+
+    if doc.is_encrypted() {
+        // Many PDFs are "encrypted" with an empty user password purely to set permissions
+        doc.decrypt("")
+            .map_err(|_| "PDF is password protected".to_string())?;
+    }
+
+    let page_count = doc.get_pages().len() as u32;
+    let (start, end) = match range {
+        Some(range) => (range.start.max(1), range.end.min(page_count)),
+        None => (1, page_count),
+    };
+    if page_count > 0 && start > end {
+        return Err(format!(
+            "Invalid page range {}-{} for a {} page document",
+            start, end, page_count
+        ));
+    }
+
+    let mut pages = Vec::new();
+    for page in start..=end {
+        let mut text = String::new();
+        {
+            let mut output = PlainTextOutput::new(&mut text);
+            pdf_extract::output_doc_page(&doc, &mut output, page)
+                .map_err(|e| format!("Failed to extract page {}: {}", page, e))?;
+        }
+        pages.push(PdfPage {
+            page,
+            text: normalize_page_text(&text),
+        });
+    }
+
+    Ok(PdfText { page_count, pages })
+}
+
+#[tauri::command]
+pub async fn extract_pdf_text(
+    path: String,
+    page_range: Option<PageRange>,
+) -> Result<PdfText, AppError> {
+    // The parser can panic on malformed files; spawn_blocking turns that into an error
+    Ok(
+        tauri::async_runtime::spawn_blocking(move || extract_text(Path::new(&path), page_range))
+            .await
+            .map_err(|e| format!("PDF extraction failed: {}", e))??,
+    )
+}
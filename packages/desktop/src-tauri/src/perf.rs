@@ -0,0 +1,414 @@
+//! Performance traces for diagnosing UI jank.
+//!
+//! While a trace is recorded (`perf_start` to `perf_stop`), every command is
+//! timed as it is dispatched and Rust code can time its own work with
+//! `span`. The frontend adds its marks and measures with `perf_mark` and
+//! `perf_measure`; a mark sent with the time it was made also records how
+//! long it took to arrive, which is the IPC latency. `perf_export` writes
+//! everything as one trace in the Trace Event Format, for chrome://tracing
+//! or Perfetto.
+//!
+//! Timestamps on both sides are wall-clock time, the frontend's being
+//! `performance.timeOrigin + performance.now()`, so they line up without a
+//! handshake.
+//!
+//! Async commands return to the dispatcher right away, so their dispatch
+//! time is recorded but not their full duration; wrap their work in a
+//! `span` to see it.
+
+use serde::{Deserialize, Serialize};
+use serde_json::{Value, json};
+use std::{
+    collections::{BTreeMap, VecDeque},
+    path::PathBuf,
+    sync::{
+        Mutex,
+        atomic::{AtomicBool, AtomicU64, Ordering},
+    },
+    time::{Instant, SystemTime, UNIX_EPOCH},
+};
+use tauri::{AppHandle, Manager, Webview, Wry, ipc::Invoke};
+
+use crate::{audit, error::AppError, user_scope};
+
+/// Oldest events are dropped past this, so a forgotten trace can't grow
+/// without bound
+const MAX_EVENTS: usize = 200_000;
+/// Process id of the frontend in the trace, so it shows as its own track
+const WEBVIEW_PID: u32 = 0;
+
+#[derive(Debug, Clone, Serialize)]
+struct TraceEvent {
+    name: String,
+    cat: String,
+    ph: &'static str,
+    /// Microseconds since the Unix epoch
+    ts: u64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    dur: Option<u64>,
+    pid: u32,
+    tid: u64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    args: Option<Value>,
+}
+
+#[derive(Default)]
+struct Trace {
+    events: VecDeque<TraceEvent>,
+    /// Track names by (pid, tid)
+    tracks: BTreeMap<(u32, u64), String>,
+    dropped: u64,
+}
+
+impl Trace {
+    fn push(&mut self, event: TraceEvent) {
+        if self.events.len() >= MAX_EVENTS {
+            self.events.pop_front();
+            self.dropped += 1;
+        }
+        self.events.push_back(event);
+    }
+
+    fn to_json(&self) -> Value {
+        let metadata = self.tracks.iter().map(|((pid, tid), name)| {
+            json!({
+                "name": "thread_name",
+                "ph": "M",
+                "pid": pid,
+                "tid": tid,
+                "args": { "name": name },
+            })
+        });
+        let process_names =
+            [(WEBVIEW_PID, "Webview"), (std::process::id(), "Aura")].map(|(pid, name)| {
+                json!({
+                    "name": "process_name",
+                    "ph": "M",
+                    "pid": pid,
+                    "tid": 0,
+                    "args": { "name": name },
+                })
+            });
+        let events = self
+            .events
+            .iter()
+            .map(|event| serde_json::to_value(event).unwrap_or(Value::Null));
+        json!({
+            "traceEvents": process_names
+                .into_iter()
+                .chain(metadata)
+                .chain(events)
+                .collect::<Vec<_>>(),
+            "displayTimeUnit": "ms",
+            "otherData": { "droppedEvents": self.dropped },
+        })
+    }
+}
+
+#[derive(Default)]
+pub struct PerfState {
+    recording: AtomicBool,
+    trace: Mutex<Trace>,
+}
+
+impl PerfState {
+    fn record(&self, event: TraceEvent, track: Option<String>) {
+        if let Ok(mut trace) = self.trace.lock() {
+            if let Some(track) = track {
+                trace.tracks.entry((event.pid, event.tid)).or_insert(track);
+            }
+            trace.push(event);
+        }
+    }
+}
+
+/// A frontend measure, in milliseconds since the Unix epoch
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PerfSpan {
+    pub name: String,
+    pub start: f64,
+    pub end: f64,
+    pub category: Option<String>,
+    pub args: Option<Value>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PerfStatus {
+    pub recording: bool,
+    pub events: usize,
+    pub dropped: u64,
+}
+
+fn now_micros() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_micros() as u64)
+        .unwrap_or(0)
+}
+
+fn ms_to_micros(ms: f64) -> u64 {
+    (ms.max(0.0) * 1000.0) as u64
+}
+
+/// Small stable ids for threads, which std doesn't expose
+fn thread_id() -> u64 {
+    static NEXT: AtomicU64 = AtomicU64::new(1);
+    thread_local! {
+        static ID: u64 = NEXT.fetch_add(1, Ordering::Relaxed);
+    }
+    ID.with(|id| *id)
+}
+
+fn thread_track() -> String {
+    std::thread::current()
+        .name()
+        .map(String::from)
+        .unwrap_or_else(|| format!("thread {}", thread_id()))
+}
+
+/// Webview tracks are keyed by window label
+fn webview_tid(label: &str) -> u64 {
+    let digest = blake3::hash(label.as_bytes());
+    u64::from_le_bytes(digest.as_bytes()[..8].try_into().unwrap_or_default()) >> 12
+}
+
+fn recording_state(app: &AppHandle) -> Option<tauri::State<'_, PerfState>> {
+    app.try_state::<PerfState>()
+        .filter(|state| state.recording.load(Ordering::Relaxed))
+}
+
+/// Times Rust work until dropped
+pub struct Span {
+    app: AppHandle,
+    category: &'static str,
+    name: String,
+    start: u64,
+    started: Instant,
+}
+
+impl Drop for Span {
+    fn drop(&mut self) {
+        if let Some(state) = recording_state(&self.app) {
+            state.record(
+                TraceEvent {
+                    name: std::mem::take(&mut self.name),
+                    cat: self.category.to_string(),
+                    ph: "X",
+                    ts: self.start,
+                    dur: Some(self.started.elapsed().as_micros() as u64),
+                    pid: std::process::id(),
+                    tid: thread_id(),
+                    args: None,
+                },
+                Some(thread_track()),
+            );
+        }
+    }
+}
+
+/// Time work under `name` while a trace is recorded
+pub fn span(app: &AppHandle, category: &'static str, name: &str) -> Option<Span> {
+    recording_state(app)?;
+    Some(Span {
+        app: app.clone(),
+        category,
+        name: name.to_string(),
+        start: now_micros(),
+        started: Instant::now(),
+    })
+}
+
+/// Time the dispatch of every command
+pub fn instrument<F>(handler: F) -> impl Fn(Invoke<Wry>) -> bool + Send + Sync + 'static
+where
+    F: Fn(Invoke<Wry>) -> bool + Send + Sync + 'static,
+{
+    move |invoke| {
+        let webview: &Webview<Wry> = invoke.message.webview_ref();
+        let _span = span(webview.app_handle(), "command", invoke.message.command());
+        handler(invoke)
+    }
+}
+
+/// Start recording a new trace, discarding the previous one
+#[tauri::command]
+pub fn perf_start(app: AppHandle) -> Result<(), AppError> {
+    let state = app.try_state::<PerfState>().ok_or("Perf state not found")?;
+    *state
+        .trace
+        .lock()
+        .map_err(|e| format!("Lock error: {}", e))? = Trace::default();
+    state.recording.store(true, Ordering::Relaxed);
+    Ok(())
+}
+
+/// Stop recording; the trace is kept for `perf_export`
+#[tauri::command]
+pub fn perf_stop(app: AppHandle) -> Result<PerfStatus, AppError> {
+    let state = app.try_state::<PerfState>().ok_or("Perf state not found")?;
+    state.recording.store(false, Ordering::Relaxed);
+    get_perf_status(app)
+}
+
+#[tauri::command]
+pub fn get_perf_status(app: AppHandle) -> Result<PerfStatus, AppError> {
+    let state = app.try_state::<PerfState>().ok_or("Perf state not found")?;
+    let trace = state
+        .trace
+        .lock()
+        .map_err(|e| format!("Lock error: {}", e))?;
+    Ok(PerfStatus {
+        recording: state.recording.load(Ordering::Relaxed),
+        events: trace.events.len(),
+        dropped: trace.dropped,
+    })
+}
+
+/// Record a frontend mark. With `timestamp` (ms since the Unix epoch, when
+/// the mark was made) the time until it arrived is recorded as IPC latency.
+#[tauri::command]
+pub fn perf_mark(app: AppHandle, webview: Webview, name: String, timestamp: Option<f64>) {
+    let Some(state) = recording_state(&app) else {
+        return;
+    };
+    let received = now_micros();
+    let sent = timestamp.map(ms_to_micros);
+    let tid = webview_tid(webview.label());
+    let track = Some(format!("webview {}", webview.label()));
+
+    if let Some(sent) = sent {
+        state.record(
+            TraceEvent {
+                name: format!("ipc {}", name),
+                cat: "ipc".to_string(),
+                ph: "X",
+                ts: sent,
+                dur: Some(received.saturating_sub(sent)),
+                pid: WEBVIEW_PID,
+                tid,
+                args: None,
+            },
+            track.clone(),
+        );
+    }
+    state.record(
+        TraceEvent {
+            name,
+            cat: "mark".to_string(),
+            ph: "i",
+            ts: sent.unwrap_or(received),
+            dur: None,
+            pid: WEBVIEW_PID,
+            tid,
+            args: None,
+        },
+        track,
+    );
+}
+
+/// Record a frontend measure
+#[tauri::command]
+pub fn perf_measure(app: AppHandle, webview: Webview, span: PerfSpan) {
+    let Some(state) = recording_state(&app) else {
+        return;
+    };
+    let start = ms_to_micros(span.start);
+    state.record(
+        TraceEvent {
+            name: span.name,
+            cat: span.category.unwrap_or_else(|| "measure".to_string()),
+            ph: "X",
+            ts: start,
+            dur: Some(ms_to_micros(span.end).saturating_sub(start)),
+            pid: WEBVIEW_PID,
+            tid: webview_tid(webview.label()),
+            args: span.args,
+        },
+        Some(format!("webview {}", webview.label())),
+    );
+}
+
+/// Write the recorded trace as JSON to `path`, or to a file in the user's
+/// temp directory, and return where it went
+#[tauri::command]
+pub fn perf_export(
+    app: AppHandle,
+    webview: Webview,
+    path: Option<String>,
+) -> Result<String, AppError> {
+    let args = json!({ "path": path });
+    let result = export(&app, path);
+    audit::record(&webview, "perf_export", args, &result);
+    result
+}
+
+fn export(app: &AppHandle, path: Option<String>) -> Result<String, AppError> {
+    let state = app.try_state::<PerfState>().ok_or("Perf state not found")?;
+    let content = {
+        let trace = state
+            .trace
+            .lock()
+            .map_err(|e| format!("Lock error: {}", e))?;
+        serde_json::to_vec(&trace.to_json())
+            .map_err(|e| format!("Failed to serialize trace: {}", e))?
+    };
+    let path = match path {
+        Some(path) => PathBuf::from(path),
+        None => {
+            user_scope::temp_dir()?.join(format!("aura-trace-{}.json", now_micros() / 1_000_000))
+        }
+    };
+    std::fs::write(&path, content)
+        .map_err(|e| format!("Failed to write {}: {}", path.display(), e))?;
+    Ok(path.to_string_lossy().to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn event(ts: u64) -> TraceEvent {
+        TraceEvent {
+            name: "render".to_string(),
+            cat: "measure".to_string(),
+            ph: "X",
+            ts,
+            dur: Some(5),
+            pid: WEBVIEW_PID,
+            tid: 1,
+            args: None,
+        }
+    }
+
+    #[test]
+    fn test_trace_json_has_track_names_and_events() {
+        let mut trace = Trace::default();
+        trace
+            .tracks
+            .insert((WEBVIEW_PID, 1), "webview main".to_string());
+        trace.push(event(1_000));
+
+        let json = trace.to_json();
+        let events = json["traceEvents"].as_array().unwrap();
+        assert!(events.iter().any(|event| event["ph"] == "M"
+            && event["tid"] == 1
+            && event["args"]["name"] == "webview main"));
+        let last = events.last().unwrap();
+        assert_eq!(last["ts"], 1_000);
+        assert_eq!(last["dur"], 5);
+        assert!(last.get("args").is_none());
+    }
+
+    #[test]
+    fn test_trace_drops_oldest_events() {
+        let mut trace = Trace::default();
+        for ts in 0..MAX_EVENTS as u64 + 2 {
+            trace.push(event(ts));
+        }
+        assert_eq!(trace.events.len(), MAX_EVENTS);
+        assert_eq!(trace.dropped, 2);
+        assert_eq!(trace.events.front().map(|event| event.ts), Some(2));
+    }
+}
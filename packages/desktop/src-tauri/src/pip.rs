@@ -0,0 +1,169 @@
+//! Picture-in-picture viewer for the latest agent response.
+//!
+//! A small always-on-top window that shows the response the frontend feeds
+//! it with `pip_set_content`, so it can be followed while another app is
+//! maximized. The window is read-only: it is a static page without access to
+//! commands, the markdown is rendered without raw HTML, and it can't navigate
+//! away. Content is kept while the window is closed and shown when it opens.
+
+use base64::{Engine, engine::general_purpose::STANDARD};
+use std::sync::Mutex;
+use tauri::{AppHandle, Manager, WebviewUrl, WebviewWindow};
+
+use crate::{error::AppError, i18n, markdown, window_placement};
+
+pub const PIP_LABEL: &str = "pip";
+const PIP_WIDTH: f64 = 380.0;
+const PIP_HEIGHT: f64 = 260.0;
+/// Distance from the corner of the display
+const PIP_MARGIN: f64 = 24.0;
+
+/// `render(html)` replaces the content and keeps following the end of the
+/// response unless the user scrolled up
+const PIP_PAGE: &str = r#"<!doctype html>
+<html>
+<head>
+<meta charset="utf-8">
+<meta http-equiv="Content-Security-Policy" content="default-src 'none'; style-src 'unsafe-inline'; script-src 'unsafe-inline'; img-src data:">
+<style>
+  :root { color-scheme: light dark; font: 13px/1.5 system-ui, sans-serif; }
+  body { margin: 0; padding: 10px 14px; overflow-wrap: anywhere; }
+  pre { overflow-x: auto; padding: 8px; border-radius: 6px; background: rgba(127, 127, 127, 0.12); }
+  code { font: 12px ui-monospace, monospace; }
+  table { border-collapse: collapse; }
+  td, th { border: 1px solid rgba(127, 127, 127, 0.3); padding: 2px 6px; }
+  #empty { opacity: 0.6; }
+</style>
+</head>
+<body><div id="content"></div>
+<script>
+  window.render = (html) => {
+    const atBottom = window.innerHeight + window.scrollY >= document.body.scrollHeight - 24;
+    document.getElementById("content").innerHTML = html;
+    if (atBottom) window.scrollTo(0, document.body.scrollHeight);
+  };
+  window.render(__INITIAL__);
+</script>
+</body>
+</html>"#;
+
+#[derive(Default)]
+pub struct PipState(Mutex<String>);
+
+fn placeholder(app: &AppHandle) -> String {
+    format!("<p id=\"empty\">{}</p>", i18n::t(app, "pip.empty"))
+}
+
+fn render_script(html: &str) -> Result<String, String> {
+    serde_json::to_string(html)
+        .map(|html| format!("window.render({})", html))
+        .map_err(|e| format!("Failed to encode content: {}", e))
+}
+
+fn current_html(app: &AppHandle) -> Result<String, String> {
+    let state = app.try_state::<PipState>().ok_or("PiP state not found")?;
+    let html = state.0.lock().map_err(|e| format!("Lock error: {}", e))?;
+    Ok(if html.is_empty() {
+        placeholder(app)
+    } else {
+        html.clone()
+    })
+}
+
+fn page_url(html: &str) -> Result<tauri::Url, String> {
+    let initial =
+        serde_json::to_string(html).map_err(|e| format!("Failed to encode content: {}", e))?;
+    // A "</script>" in the content would end the script early
+    let page = PIP_PAGE.replace("__INITIAL__", &initial.replace("</", "<\\/"));
+    format!("data:text/html;base64,{}", STANDARD.encode(page))
+        .parse()
+        .map_err(|e| format!("Failed to build page URL: {}", e))
+}
+
+/// Bottom-right corner of the display the viewer opens on
+fn corner_position(app: &AppHandle) -> Option<(f64, f64)> {
+    let monitor = window_placement::target_monitor(app, PIP_LABEL)?;
+    let area = monitor.work_area();
+    let position = area.position.to_logical::<f64>(monitor.scale_factor());
+    let size = area.size.to_logical::<f64>(monitor.scale_factor());
+    Some((
+        position.x + size.width - PIP_WIDTH - PIP_MARGIN,
+        position.y + size.height - PIP_HEIGHT - PIP_MARGIN,
+    ))
+}
+
+fn open(app: &AppHandle) -> Result<WebviewWindow, String> {
+    if let Some(window) = app.get_webview_window(PIP_LABEL) {
+        let _ = window.show();
+        return Ok(window);
+    }
+
+    let url = page_url(&current_html(app)?)?;
+    let mut builder = WebviewWindow::builder(app, PIP_LABEL, WebviewUrl::External(url))
+        .title(i18n::t(app, "pip.title"))
+        .inner_size(PIP_WIDTH, PIP_HEIGHT)
+        .min_inner_size(200.0, 120.0)
+        .always_on_top(true)
+        .visible_on_all_workspaces(true)
+        .skip_taskbar(true)
+        .focused(false)
+        // Links in the response would otherwise open inside the viewer
+        .on_navigation(|url| url.scheme() == "data");
+    if let Some((x, y)) = corner_position(app) {
+        builder = builder.position(x, y);
+    }
+    // WebView2 needs the same arguments for all webviews of the app
+    let builder = crate::proxy::with_webview_proxy(app, builder);
+    #[cfg(windows)]
+    let builder = crate::host_overrides::with_browser_args(app, builder);
+    builder
+        .build()
+        .map_err(|e| format!("Failed to open picture-in-picture window: {}", e))
+}
+
+/// Open the viewer, or show it if it is open already. Async, as creating a
+/// window from a sync command deadlocks on Windows.
+#[tauri::command]
+pub async fn pip_open(app: AppHandle) -> Result<(), AppError> {
+    open(&app)?;
+    Ok(())
+}
+
+#[tauri::command]
+pub fn pip_close(app: AppHandle) -> Result<(), AppError> {
+    if let Some(window) = app.get_webview_window(PIP_LABEL) {
+        window
+            .close()
+            .map_err(|e| format!("Failed to close picture-in-picture window: {}", e))?;
+    }
+    Ok(())
+}
+
+/// Open the viewer if it is closed, close it otherwise
+pub async fn toggle(app: &AppHandle) -> Result<(), AppError> {
+    match app.get_webview_window(PIP_LABEL) {
+        Some(_) => pip_close(app.clone()),
+        None => pip_open(app.clone()).await,
+    }
+}
+
+/// Show `markdown` as the latest response. Called again as the response
+/// streams in; the viewer keeps following the end unless scrolled up.
+#[tauri::command]
+pub fn pip_set_content(app: AppHandle, markdown: String) -> Result<(), AppError> {
+    let html = markdown::parse_markdown_safe(&markdown);
+    let state = app.try_state::<PipState>().ok_or("PiP state not found")?;
+    *state.0.lock().map_err(|e| format!("Lock error: {}", e))? = html.clone();
+
+    if let Some(window) = app.get_webview_window(PIP_LABEL) {
+        let html = if html.is_empty() {
+            placeholder(&app)
+        } else {
+            html
+        };
+        window
+            .eval(render_script(&html)?)
+            .map_err(|e| format!("Failed to update picture-in-picture window: {}", e))?;
+    }
+    Ok(())
+}
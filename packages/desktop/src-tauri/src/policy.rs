@@ -0,0 +1,277 @@
+//! Policy set by an administrator.
+//!
+//! Managed deployments can pin parts of the configuration with a JSON file in
+//! a system location users can't write to (see `policy_path`):
+//!
+//! ```json
+//! {
+//!   "serverUrl": "https://opencode.example.com",
+//!   "disableLocalServer": true,
+//!   "disableModelDownloads": true,
+//!   "proxy": "http://proxy.example.com:3128",
+//!   "auditLog": true,
+//!   "disableRemoteTranscription": true,
+//!   "updateEndpoint": "https://updates.example.com/aura/latest.json",
+//!   "updatePubkey": "dW50cnVzdGVkIGNvbW1lbnQ6..."
+//! }
+//! ```
+//!
+//! All fields are optional. The file is read once at startup; if it is there
+//! but can't be read or parsed, the app says why and quits rather than run
+//! without the restrictions. `get_policy` reports what is locked so the
+//! frontend can grey those settings out; the commands changing them refuse as
+//! well.
+
+use serde::{Deserialize, Serialize};
+use std::path::PathBuf;
+use tauri::{AppHandle, Manager};
+use tauri_plugin_dialog::{DialogExt, MessageDialogKind};
+
+use crate::{error::AppError, i18n, update_source::CustomUpdateSource};
+
+/// Hosts that keep bypassing a forced proxy, so the app still reaches its
+/// own local server
+const NO_PROXY_HOSTS: &str = "localhost,127.0.0.1,::1";
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default, rename_all = "camelCase")]
+pub struct Policy {
+    /// The only server the app connects to
+    pub server_url: Option<String>,
+    /// Never start the bundled server
+    pub disable_local_server: bool,
+    /// Refuse to download speech, OCR, diarization and embedding models
+    pub disable_model_downloads: bool,
+    /// Proxy for all HTTP(S) traffic of the app and its server
+    pub proxy: Option<String>,
+    /// Keep the audit log on
+    pub audit_log: bool,
+    /// Never send recordings to a speech server
+    pub disable_remote_transcription: bool,
+    /// Release manifest to fetch updates from
+    pub update_endpoint: Option<String>,
+    /// Key update signatures are checked against
+    pub update_pubkey: Option<String>,
+}
+
+impl Policy {
+    fn validate(&self) -> Result<(), String> {
+        if let Some(url) = &self.server_url {
+            reqwest::Url::parse(url).map_err(|e| format!("Invalid serverUrl {}: {}", url, e))?;
+        }
+        if let Some(proxy) = &self.proxy {
+            reqwest::Proxy::all(proxy).map_err(|e| format!("Invalid proxy {}: {}", proxy, e))?;
+        }
+        self.update_source().validate()
+    }
+
+    pub fn update_source(&self) -> CustomUpdateSource {
+        CustomUpdateSource {
+            endpoint: self.update_endpoint.clone(),
+            pubkey: self.update_pubkey.clone(),
+        }
+    }
+
+    /// What the policy locks: settings store keys where the setting has one,
+    /// feature names otherwise
+    fn locked(&self) -> Vec<&'static str> {
+        [
+            (self.server_url.is_some(), "defaultServerUrl"),
+            (self.disable_local_server, "localServer"),
+            (self.disable_model_downloads, "modelDownloads"),
+            (self.proxy.is_some(), "networkProxy"),
+            (self.audit_log, "auditLogEnabled"),
+            (self.disable_remote_transcription, "sttRemote"),
+            (
+                self.update_endpoint.is_some() || self.update_pubkey.is_some(),
+                "updateSource",
+            ),
+        ]
+        .into_iter()
+        .filter_map(|(locked, name)| locked.then_some(name))
+        .collect()
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PolicyStatus {
+    /// Where the policy is read from
+    pub path: String,
+    /// Whether a policy file was found
+    pub present: bool,
+    /// Why the file couldn't be used
+    pub error: Option<String>,
+    pub policy: Policy,
+    pub locked: Vec<&'static str>,
+}
+
+pub struct PolicyState {
+    path: PathBuf,
+    present: bool,
+    error: Option<String>,
+    policy: Policy,
+}
+
+fn policy_path() -> PathBuf {
+    #[cfg(target_os = "macos")]
+    return PathBuf::from("/Library/Application Support/Aura/policy.json");
+
+    #[cfg(windows)]
+    return std::env::var_os("ProgramData")
+        .map(PathBuf::from)
+        .unwrap_or_else(|| PathBuf::from(r"C:\ProgramData"))
+        .join("Aura")
+        .join("policy.json");
+
+    #[cfg(not(any(target_os = "macos", windows)))]
+    return PathBuf::from("/etc/aura/policy.json");
+}
+
+fn parse(content: &str) -> Result<Policy, String> {
+    let policy: Policy =
+        serde_json::from_str(content).map_err(|e| format!("Failed to parse policy: {}", e))?;
+    policy.validate()?;
+    Ok(policy)
+}
+
+impl PolicyState {
+    /// Read the policy file. A file that can't be used is kept in `error`,
+    /// for the app to refuse to start.
+    pub fn load() -> Self {
+        let path = policy_path();
+        let (present, loaded) = match std::fs::read_to_string(&path) {
+            Ok(content) => (true, parse(&content)),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => (false, Ok(Policy::default())),
+            Err(e) => (
+                true,
+                Err(format!("Failed to read {}: {}", path.display(), e)),
+            ),
+        };
+        let (policy, error) = match loaded {
+            Ok(policy) => (policy, None),
+            Err(e) => {
+                eprintln!("Unusable policy {}: {}", path.display(), e);
+                (Policy::default(), Some(e))
+            }
+        };
+        if present && error.is_none() {
+            println!("Applying policy from {}", path.display());
+        }
+        Self {
+            path,
+            present,
+            error,
+            policy,
+        }
+    }
+
+    pub fn policy(&self) -> &Policy {
+        &self.policy
+    }
+
+    /// Why the policy file couldn't be used, if it couldn't
+    pub fn error(&self) -> Option<&str> {
+        self.error.as_deref()
+    }
+
+    /// Route the traffic of this process, and of the server it spawns, through
+    /// the forced proxy. Must run before any threads are started.
+    pub fn apply_proxy(&self) {
+        let Some(proxy) = &self.policy.proxy else {
+            return;
+        };
+        for key in ["HTTP_PROXY", "HTTPS_PROXY", "ALL_PROXY"] {
+            // Safety: called during startup before any threads are spawned, so
+            // mutating the process environment is safe.
+            unsafe {
+                std::env::set_var(key, proxy);
+                std::env::set_var(key.to_lowercase(), proxy);
+            }
+        }
+        // Safety: as above
+        unsafe {
+            std::env::set_var("NO_PROXY", NO_PROXY_HOSTS);
+            std::env::set_var("no_proxy", NO_PROXY_HOSTS);
+        }
+    }
+}
+
+/// The policy in effect, default when none is managed
+pub fn current(app: &AppHandle) -> Policy {
+    app.try_state::<PolicyState>()
+        .map(|state| state.policy().clone())
+        .unwrap_or_default()
+}
+
+/// Tell the user the policy file couldn't be used because of `error`, then
+/// quit: running without it would lift what it locks
+pub fn refuse_to_start(app: &AppHandle, error: &str) {
+    let path = policy_path();
+    let app_handle = app.clone();
+    app.dialog()
+        .message(i18n::t_with(
+            app,
+            "dialog.policyError.message",
+            &[("path", &path.to_string_lossy()), ("error", error)],
+        ))
+        .title(i18n::t(app, "dialog.policyError.title"))
+        .kind(MessageDialogKind::Error)
+        .show(move |_| app_handle.exit(1));
+}
+
+/// The error for changing something the policy locks
+pub fn locked_error(app: &AppHandle) -> AppError {
+    AppError::permission_denied(i18n::t(app, "error.lockedByPolicy"))
+}
+
+/// Errors if the policy disables model downloads
+pub fn ensure_downloads_allowed(app: &AppHandle) -> Result<(), AppError> {
+    if current(app).disable_model_downloads {
+        return Err(AppError::permission_denied(i18n::t(
+            app,
+            "error.downloadsDisabledByPolicy",
+        )));
+    }
+    Ok(())
+}
+
+#[tauri::command]
+pub fn get_policy(app: AppHandle) -> Result<PolicyStatus, AppError> {
+    let state = app
+        .try_state::<PolicyState>()
+        .ok_or("Policy state not found")?;
+    Ok(PolicyStatus {
+        path: state.path.to_string_lossy().to_string(),
+        present: state.present,
+        error: state.error.clone(),
+        locked: state.policy.locked(),
+        policy: state.policy.clone(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_policy_and_locked_settings() {
+        let policy = parse(
+            r#"{
+                "serverUrl": "https://opencode.example.com",
+                "disableModelDownloads": true,
+                "futureSetting": 1
+            }"#,
+        )
+        .unwrap();
+        assert_eq!(
+            policy.server_url.as_deref(),
+            Some("https://opencode.example.com")
+        );
+        assert!(!policy.disable_local_server);
+        assert_eq!(policy.locked(), vec!["defaultServerUrl", "modelDownloads"]);
+
+        assert!(parse(r#"{ "proxy": "not a url" }"#).is_err());
+        assert!(Policy::default().locked().is_empty());
+    }
+}
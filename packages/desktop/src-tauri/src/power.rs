@@ -0,0 +1,312 @@
+//! Power management assertions that keep the machine (and optionally the
+//! display) awake.
+//!
+//! A `SleepGuard` holds the assertion and releases it when dropped:
+//! - macOS: an IOKit power assertion
+//! - Windows: `SetThreadExecutionState` on a dedicated thread, since the
+//!   execution state belongs to the thread that set it
+//! - Linux: a `systemd-inhibit` child process holding a logind inhibitor lock
+//!
+//! Long-running work (agent runs from the frontend, model downloads here)
+//! takes a keep-awake hold with a reason. One system sleep assertion is kept
+//! while any hold is active, and `power:keep-awake` reports the active
+//! reasons so the UI can show an indicator.
+
+use serde::Serialize;
+use std::{collections::BTreeMap, sync::Mutex};
+use tauri::{AppHandle, Emitter, Manager};
+
+use crate::error::AppError;
+
+/// Keeps the machine awake until dropped
+pub struct SleepGuard {
+    _assertion: platform::Assertion,
+}
+
+/// Prevent idle sleep, and also display sleep when `display` is set
+pub fn sleep_guard(reason: &str, display: bool) -> Result<SleepGuard, String> {
+    Ok(SleepGuard {
+        _assertion: platform::Assertion::acquire(reason, display)?,
+    })
+}
+
+#[derive(Default)]
+struct KeepAwake {
+    next_id: u64,
+    reasons: BTreeMap<u64, String>,
+    guard: Option<SleepGuard>,
+}
+
+#[derive(Default)]
+pub struct KeepAwakeState(Mutex<KeepAwake>);
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct KeepAwakeStatus {
+    pub active: bool,
+    pub reasons: Vec<String>,
+}
+
+impl KeepAwake {
+    fn status(&self) -> KeepAwakeStatus {
+        KeepAwakeStatus {
+            active: self.guard.is_some(),
+            reasons: self.reasons.values().cloned().collect(),
+        }
+    }
+}
+
+fn emit_status(app: &AppHandle, keep_awake: &KeepAwake) {
+    let _ = app.emit("power:keep-awake", keep_awake.status());
+}
+
+fn acquire(app: &AppHandle, reason: &str) -> Result<u64, String> {
+    let state = app
+        .try_state::<KeepAwakeState>()
+        .ok_or("Keep-awake state not found")?;
+    let mut keep_awake = state.0.lock().map_err(|e| format!("Lock error: {}", e))?;
+
+    if keep_awake.guard.is_none() {
+        keep_awake.guard = Some(sleep_guard(reason, false)?);
+    }
+    keep_awake.next_id += 1;
+    let id = keep_awake.next_id;
+    keep_awake.reasons.insert(id, reason.to_string());
+    emit_status(app, &keep_awake);
+    Ok(id)
+}
+
+/// Release one hold, or every hold when `id` is `None`
+fn release(app: &AppHandle, id: Option<u64>) -> Result<(), String> {
+    let state = app
+        .try_state::<KeepAwakeState>()
+        .ok_or("Keep-awake state not found")?;
+    let mut keep_awake = state.0.lock().map_err(|e| format!("Lock error: {}", e))?;
+
+    match id {
+        Some(id) => {
+            keep_awake.reasons.remove(&id);
+        }
+        None => keep_awake.reasons.clear(),
+    }
+    if keep_awake.reasons.is_empty() {
+        keep_awake.guard = None;
+    }
+    emit_status(app, &keep_awake);
+    Ok(())
+}
+
+/// Keep-awake hold for work done in Rust, released when dropped
+pub struct KeepAwakeHold {
+    app: AppHandle,
+    id: u64,
+}
+
+impl Drop for KeepAwakeHold {
+    fn drop(&mut self) {
+        let _ = release(&self.app, Some(self.id));
+    }
+}
+
+/// Keep the machine awake while the returned hold is alive. Failing to get a
+/// power assertion isn't fatal to the work itself, so this only logs.
+pub fn hold(app: &AppHandle, reason: &str) -> Option<KeepAwakeHold> {
+    acquire(app, reason)
+        .inspect_err(|e| eprintln!("Failed to keep the machine awake: {}", e))
+        .ok()
+        .map(|id| KeepAwakeHold {
+            app: app.clone(),
+            id,
+        })
+}
+
+/// Keep the machine awake until `allow_sleep` is called with the returned id
+#[tauri::command]
+pub fn prevent_sleep(app: AppHandle, reason: String) -> Result<u64, AppError> {
+    Ok(acquire(&app, &reason)?)
+}
+
+/// Release a hold from `prevent_sleep`, or all of them without an id
+#[tauri::command]
+pub fn allow_sleep(app: AppHandle, id: Option<u64>) -> Result<(), AppError> {
+    Ok(release(&app, id)?)
+}
+
+#[tauri::command]
+pub fn get_keep_awake_state(app: AppHandle) -> Result<KeepAwakeStatus, AppError> {
+    let state = app
+        .try_state::<KeepAwakeState>()
+        .ok_or("Keep-awake state not found")?;
+    let keep_awake = state.0.lock().map_err(|e| format!("Lock error: {}", e))?;
+    Ok(keep_awake.status())
+}
+
+#[cfg(target_os = "macos")]
+mod platform {
+    use std::ffi::{CString, c_char, c_void};
+
+    type CFStringRef = *const c_void;
+
+    const K_CF_STRING_ENCODING_UTF8: u32 = 0x0800_0100;
+    const K_IOPM_ASSERTION_LEVEL_ON: u32 = 255;
+
+    #[link(name = "CoreFoundation", kind = "framework")]
+    unsafe extern "C" {
+        fn CFStringCreateWithCString(
+            alloc: *const c_void,
+            c_str: *const c_char,
+            encoding: u32,
+        ) -> CFStringRef;
+        fn CFRelease(cf: *const c_void);
+    }
+
+    #[link(name = "IOKit", kind = "framework")]
+    unsafe extern "C" {
+        fn IOPMAssertionCreateWithName(
+            assertion_type: CFStringRef,
+            level: u32,
+            name: CFStringRef,
+            id: *mut u32,
+        ) -> i32;
+        fn IOPMAssertionRelease(id: u32) -> i32;
+    }
+
+    fn cf_string(value: &str) -> Result<CFStringRef, String> {
+        let value = CString::new(value).map_err(|e| format!("Invalid string: {}", e))?;
+        let string = unsafe {
+            CFStringCreateWithCString(std::ptr::null(), value.as_ptr(), K_CF_STRING_ENCODING_UTF8)
+        };
+        if string.is_null() {
+            return Err("Failed to create CFString".to_string());
+        }
+        Ok(string)
+    }
+
+    pub struct Assertion(u32);
+
+    impl Assertion {
+        pub fn acquire(reason: &str, display: bool) -> Result<Self, String> {
+            let assertion_type = if display {
+                "PreventUserIdleDisplaySleep"
+            } else {
+                "PreventUserIdleSystemSleep"
+            };
+            let assertion_type = cf_string(assertion_type)?;
+            let name = match cf_string(reason) {
+                Ok(name) => name,
+                Err(e) => {
+                    unsafe { CFRelease(assertion_type) };
+                    return Err(e);
+                }
+            };
+
+            let mut id = 0;
+            let result = unsafe {
+                let result = IOPMAssertionCreateWithName(
+                    assertion_type,
+                    K_IOPM_ASSERTION_LEVEL_ON,
+                    name,
+                    &mut id,
+                );
+                CFRelease(assertion_type);
+                CFRelease(name);
+                result
+            };
+            if result != 0 {
+                return Err(format!("Failed to create power assertion: {}", result));
+            }
+            Ok(Self(id))
+        }
+    }
+
+    impl Drop for Assertion {
+        fn drop(&mut self) {
+            unsafe { IOPMAssertionRelease(self.0) };
+        }
+    }
+}
+
+#[cfg(windows)]
+mod platform {
+    use std::{sync::mpsc, thread::JoinHandle};
+    use windows::Win32::System::Power::{
+        ES_CONTINUOUS, ES_DISPLAY_REQUIRED, ES_SYSTEM_REQUIRED, SetThreadExecutionState,
+    };
+
+    pub struct Assertion {
+        release_tx: Option<mpsc::Sender<()>>,
+        thread: Option<JoinHandle<()>>,
+    }
+
+    impl Assertion {
+        pub fn acquire(_reason: &str, display: bool) -> Result<Self, String> {
+            let mut flags = ES_CONTINUOUS | ES_SYSTEM_REQUIRED;
+            if display {
+                flags |= ES_DISPLAY_REQUIRED;
+            }
+
+            let (ready_tx, ready_rx) = mpsc::channel();
+            let (release_tx, release_rx) = mpsc::channel::<()>();
+            let thread = std::thread::Builder::new()
+                .name("sleep-guard".to_string())
+                .spawn(move || {
+                    let previous = unsafe { SetThreadExecutionState(flags) };
+                    let _ = ready_tx.send(previous.0 != 0);
+                    // Hold the execution state until released or the guard is dropped
+                    let _ = release_rx.recv();
+                    unsafe { SetThreadExecutionState(ES_CONTINUOUS) };
+                })
+                .map_err(|e| format!("Failed to spawn sleep guard thread: {}", e))?;
+
+            if !ready_rx.recv().unwrap_or(false) {
+                let _ = thread.join();
+                return Err("Failed to set thread execution state".to_string());
+            }
+            Ok(Self {
+                release_tx: Some(release_tx),
+                thread: Some(thread),
+            })
+        }
+    }
+
+    impl Drop for Assertion {
+        fn drop(&mut self) {
+            drop(self.release_tx.take());
+            if let Some(thread) = self.thread.take() {
+                let _ = thread.join();
+            }
+        }
+    }
+}
+
+#[cfg(target_os = "linux")]
+mod platform {
+    use std::process::{Child, Command, Stdio};
+
+    pub struct Assertion(Child);
+
+    impl Assertion {
+        pub fn acquire(reason: &str, display: bool) -> Result<Self, String> {
+            let what = if display { "idle:sleep" } else { "sleep" };
+            let child = Command::new("systemd-inhibit")
+                .arg(format!("--what={}", what))
+                .arg("--who=Aura")
+                .arg(format!("--why={}", reason))
+                .arg("--mode=block")
+                .args(["sleep", "infinity"])
+                .stdin(Stdio::null())
+                .stdout(Stdio::null())
+                .stderr(Stdio::null())
+                .spawn()
+                .map_err(|e| format!("Failed to run systemd-inhibit: {}", e))?;
+            Ok(Self(child))
+        }
+    }
+
+    impl Drop for Assertion {
+        fn drop(&mut self) {
+            let _ = self.0.kill();
+            let _ = self.0.wait();
+        }
+    }
+}
@@ -0,0 +1,144 @@
+//! Full-screen and presentation mode for demoing sessions on a projector.
+//!
+//! Presentation mode makes a window full-screen, hides the overlay titlebar
+//! and can keep the display awake; notifications are skipped while it is
+//! active. Leaving it puts the window back the way it was.
+
+use serde::Serialize;
+use std::sync::Mutex;
+use tauri::{AppHandle, Emitter, Manager, WebviewWindow};
+
+use crate::{
+    error::AppError,
+    power::{self, SleepGuard},
+};
+
+/// Hides the decorum overlay titlebar (Windows) while presenting
+const HIDE_TITLEBAR_SCRIPT: &str = r#"
+  document.documentElement.dataset.presentation = "true";
+  document.querySelectorAll("[data-tauri-decorum-tb]").forEach((el) => (el.style.display = "none"));
+"#;
+const SHOW_TITLEBAR_SCRIPT: &str = r#"
+  delete document.documentElement.dataset.presentation;
+  document.querySelectorAll("[data-tauri-decorum-tb]").forEach((el) => (el.style.display = ""));
+"#;
+
+struct Presentation {
+    label: String,
+    was_fullscreen: bool,
+    sleep_guard: Option<SleepGuard>,
+}
+
+#[derive(Default)]
+pub struct PresentationState(Mutex<Option<Presentation>>);
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PresentationStatus {
+    pub active: bool,
+    pub label: Option<String>,
+    pub keep_awake: bool,
+}
+
+impl PresentationState {
+    fn status(&self) -> Result<PresentationStatus, String> {
+        let presentation = self.0.lock().map_err(|e| format!("Lock error: {}", e))?;
+        Ok(match presentation.as_ref() {
+            Some(p) => PresentationStatus {
+                active: true,
+                label: Some(p.label.clone()),
+                keep_awake: p.sleep_guard.is_some(),
+            },
+            None => PresentationStatus {
+                active: false,
+                label: None,
+                keep_awake: false,
+            },
+        })
+    }
+}
+
+fn get_window(app: &AppHandle, label: Option<String>) -> Result<WebviewWindow, AppError> {
+    let label = label.unwrap_or_else(|| "main".to_string());
+    app.get_webview_window(&label)
+        .ok_or_else(|| AppError::not_found(format!("Window {} not found", label)))
+}
+
+fn emit_status(app: &AppHandle, state: &PresentationState) -> Result<PresentationStatus, String> {
+    let status = state.status()?;
+    let _ = app.emit("window:presentation-mode", &status);
+    Ok(status)
+}
+
+/// Toggle full-screen for a window (the main window by default), returning
+/// whether it is now full-screen
+#[tauri::command]
+pub fn toggle_fullscreen(app: AppHandle, label: Option<String>) -> Result<bool, AppError> {
+    let window = get_window(&app, label)?;
+    let fullscreen = !window
+        .is_fullscreen()
+        .map_err(|e| format!("Failed to get fullscreen state: {}", e))?;
+    window
+        .set_fullscreen(fullscreen)
+        .map_err(|e| format!("Failed to set fullscreen: {}", e))?;
+    Ok(fullscreen)
+}
+
+#[tauri::command]
+pub fn get_presentation_mode(app: AppHandle) -> Result<PresentationStatus, AppError> {
+    app.try_state::<PresentationState>()
+        .ok_or("Presentation state not found")?
+        .status()
+        .map_err(AppError::from)
+}
+
+/// Enter or leave presentation mode. `keep_awake` holds a display power
+/// assertion for as long as the presentation lasts.
+#[tauri::command]
+pub fn set_presentation_mode(
+    app: AppHandle,
+    enabled: bool,
+    keep_awake: Option<bool>,
+    label: Option<String>,
+) -> Result<PresentationStatus, AppError> {
+    let state = app
+        .try_state::<PresentationState>()
+        .ok_or("Presentation state not found")?;
+
+    let previous = state
+        .0
+        .lock()
+        .map_err(|e| format!("Lock error: {}", e))?
+        .take();
+    if let Some(previous) = previous
+        && let Some(window) = app.get_webview_window(&previous.label)
+    {
+        let _ = window.set_fullscreen(previous.was_fullscreen);
+        let _ = window.eval(SHOW_TITLEBAR_SCRIPT);
+    }
+
+    if enabled {
+        let window = get_window(&app, label)?;
+        let was_fullscreen = window.is_fullscreen().unwrap_or(false);
+        window
+            .set_fullscreen(true)
+            .map_err(|e| format!("Failed to set fullscreen: {}", e))?;
+        let _ = window.eval(HIDE_TITLEBAR_SCRIPT);
+
+        let sleep_guard = if keep_awake.unwrap_or(false) {
+            power::sleep_guard("Presenting", true)
+                .inspect_err(|e| eprintln!("Failed to keep the display awake: {}", e))
+                .ok()
+        } else {
+            None
+        };
+
+        *state.0.lock().map_err(|e| format!("Lock error: {}", e))? = Some(Presentation {
+            label: window.label().to_string(),
+            was_fullscreen,
+            sleep_guard,
+        });
+    }
+
+    Ok(emit_status(&app, &state)?)
+}
@@ -0,0 +1,454 @@
+//! What the app keeps on this machine, and wiping it in one go.
+//!
+//! `get_data_inventory` lists every place the app stores data with its size,
+//! and `purge_all_local_data` wipes logs, transcripts, history, caches and
+//! credentials for users leaving a shared machine. Downloaded models hold no
+//! personal data and are kept. Files go to the app trash so the purge can be
+//! undone; `empty_trash` gets rid of them for good. The purge returns a report
+//! of what was removed, which is also emitted as `privacy:purged`.
+//!
+//! Telemetry of the bundled CLI can be switched off; the sidecar is then
+//! started with `DO_NOT_TRACK=1`.
+
+use serde::Serialize;
+use serde_json::Value;
+use std::path::{Path, PathBuf};
+use tauri::{AppHandle, Emitter, Manager, Webview, path::BaseDirectory};
+use tauri_plugin_store::StoreExt;
+
+use crate::{
+    LogState, SETTINGS_STORE, atomic_store, audit, changelog,
+    error::AppError,
+    outbox, recording_recovery, retention, scratchpad,
+    semantic_search::SemanticIndexState,
+    server_cache,
+    session_index::SessionIndexState,
+    stt_remote,
+    trash::{self, TrashOperation},
+};
+
+const TELEMETRY_DISABLED_KEY: &str = "telemetryDisabled";
+const STORE_EXTENSION: &str = "dat";
+const WINDOW_STATE_FILE: &str = ".window-state.json";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub enum DataCategory {
+    Settings,
+    History,
+    Transcripts,
+    Logs,
+    Caches,
+    Credentials,
+    Models,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DataLocation {
+    pub category: DataCategory,
+    pub description: String,
+    /// None for data only held in memory
+    pub path: Option<String>,
+    pub bytes: u64,
+    /// Whether `purge_all_local_data` removes it
+    pub purgeable: bool,
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PurgeReport {
+    pub removed: Vec<DataLocation>,
+    pub bytes_freed: u64,
+    pub errors: Vec<String>,
+    /// Trash operation that `undo_last_operation` restores the files from
+    pub undo_id: Option<u64>,
+}
+
+/// Total size of a file or directory, 0 if it doesn't exist
+fn disk_usage(path: &Path) -> u64 {
+    let Ok(metadata) = std::fs::symlink_metadata(path) else {
+        return 0;
+    };
+    if !metadata.is_dir() {
+        return metadata.len();
+    }
+    std::fs::read_dir(path)
+        .map(|entries| {
+            entries
+                .filter_map(Result::ok)
+                .map(|entry| disk_usage(&entry.path()))
+                .sum()
+        })
+        .unwrap_or(0)
+}
+
+fn resolve(app: &AppHandle, path: &str, base: BaseDirectory) -> Option<PathBuf> {
+    app.path().resolve(path, base).ok()
+}
+
+/// Store files (settings, and history and other state of the frontend)
+fn store_files(app: &AppHandle) -> Vec<PathBuf> {
+    let Some(dir) = resolve(app, "", BaseDirectory::AppData) else {
+        return Vec::new();
+    };
+    std::fs::read_dir(dir)
+        .map(|entries| {
+            entries
+                .filter_map(Result::ok)
+                .map(|entry| entry.path())
+                .filter(|path| path.extension().is_some_and(|ext| ext == STORE_EXTENSION))
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+/// CLI credentials live with the CLI's own data, which the desktop shares
+fn cli_auth_file() -> Option<PathBuf> {
+    let data_home = std::env::var_os("XDG_DATA_HOME")
+        .map(PathBuf::from)
+        .or_else(|| {
+            std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".local/share"))
+        })?;
+    Some(data_home.join("opencode").join("auth.json"))
+}
+
+fn log_bytes(app: &AppHandle) -> u64 {
+    app.try_state::<LogState>()
+        .and_then(|state| {
+            state
+                .0
+                .lock()
+                .ok()
+                .map(|logs| logs.iter().map(|line| line.len() as u64).sum())
+        })
+        .unwrap_or(0)
+}
+
+fn location(
+    category: DataCategory,
+    description: &str,
+    path: Option<PathBuf>,
+    purgeable: bool,
+) -> Option<DataLocation> {
+    let path = path?;
+    Some(DataLocation {
+        category,
+        description: description.to_string(),
+        bytes: disk_usage(&path),
+        path: Some(path.to_string_lossy().to_string()),
+        purgeable,
+    })
+}
+
+pub(crate) fn inventory(app: &AppHandle) -> Vec<DataLocation> {
+    let mut locations: Vec<DataLocation> = store_files(app)
+        .into_iter()
+        .filter_map(|path| {
+            let is_settings = path.file_name().is_some_and(|name| name == SETTINGS_STORE);
+            let (category, description) = if is_settings {
+                (DataCategory::Settings, "App settings")
+            } else {
+                (DataCategory::History, "Workspace state and history")
+            };
+            location(category, description, Some(path), true)
+        })
+        .collect();
+
+    locations.extend(
+        [
+            location(
+                DataCategory::Settings,
+                "Window positions",
+                resolve(app, WINDOW_STATE_FILE, BaseDirectory::AppConfig),
+                true,
+            ),
+            location(
+                DataCategory::History,
+                "Sessions and history of the bundled CLI",
+                resolve(app, "opencode", BaseDirectory::AppLocalData),
+                true,
+            ),
+            location(
+                DataCategory::History,
+                "Scratchpad notes",
+                resolve(
+                    app,
+                    scratchpad::SCRATCHPAD_FILE,
+                    BaseDirectory::AppLocalData,
+                ),
+                true,
+            ),
+            location(
+                DataCategory::History,
+                "Prompts waiting to be sent",
+                resolve(app, outbox::OUTBOX_FILE, BaseDirectory::AppLocalData),
+                true,
+            ),
+            location(
+                DataCategory::Transcripts,
+                "Search index of transcripts and documents",
+                app.try_state::<SemanticIndexState>()
+                    .map(|state| state.path().to_path_buf()),
+                true,
+            ),
+            location(
+                DataCategory::Transcripts,
+                "Damaged search index, set aside",
+                app.try_state::<SemanticIndexState>()
+                    .map(|state| state.corrupt_path()),
+                true,
+            ),
+            location(
+                DataCategory::Transcripts,
+                "Recordings rescued after a crash",
+                resolve(
+                    app,
+                    recording_recovery::RECOVERY_DIR,
+                    BaseDirectory::AppLocalData,
+                ),
+                true,
+            ),
+            location(
+                DataCategory::History,
+                "Search index of server sessions",
+                app.try_state::<SessionIndexState>()
+                    .map(|state| state.path().to_path_buf()),
+                true,
+            ),
+            location(
+                DataCategory::Logs,
+                "Crash reports",
+                resolve(
+                    app,
+                    retention::CRASH_REPORTS_DIR,
+                    BaseDirectory::AppLocalData,
+                ),
+                true,
+            ),
+            // Not the whole cache dir, which is the local data dir on Windows
+            location(
+                DataCategory::Caches,
+                "Release notes cache",
+                resolve(app, changelog::CACHE_FILE, BaseDirectory::AppCache),
+                true,
+            ),
+            location(
+                DataCategory::Caches,
+                "Cached server responses",
+                resolve(app, server_cache::CACHE_DIR, BaseDirectory::AppCache),
+                true,
+            ),
+            location(
+                DataCategory::Credentials,
+                "Provider credentials of the CLI",
+                cli_auth_file(),
+                true,
+            ),
+            // Emptied with `empty_trash` rather than by the purge
+            location(
+                DataCategory::Caches,
+                "Deleted files that can still be restored",
+                resolve(app, "trash", BaseDirectory::AppLocalData),
+                false,
+            ),
+            location(
+                DataCategory::Models,
+                "Downloaded speech, OCR and embedding models",
+                resolve(app, "models", BaseDirectory::AppLocalData),
+                false,
+            ),
+        ]
+        .into_iter()
+        .flatten(),
+    );
+
+    locations.push(DataLocation {
+        category: DataCategory::Logs,
+        description: "Server logs (in memory)".to_string(),
+        path: None,
+        bytes: log_bytes(app),
+        purgeable: true,
+    });
+
+    locations
+}
+
+fn purge(
+    app: &AppHandle,
+    trash: &mut TrashOperation,
+    location: &DataLocation,
+) -> Result<(), String> {
+    let path = location.path.as_deref().map(Path::new);
+    match (location.category, path) {
+        (DataCategory::Logs, _) => {
+            if let Some(state) = app.try_state::<LogState>() {
+                state
+                    .0
+                    .lock()
+                    .map_err(|e| format!("Lock error: {}", e))?
+                    .clear();
+            }
+            Ok(())
+        }
+        (DataCategory::Transcripts, Some(path))
+            if app
+                .try_state::<SemanticIndexState>()
+                .is_some_and(|state| state.path() == path) =>
+        {
+            trash.remove(path)?;
+            app.try_state::<SemanticIndexState>()
+                .ok_or("Semantic index state not found")?
+                .reset()
+        }
+        (DataCategory::History, Some(path))
+            if app
+                .try_state::<SessionIndexState>()
+                .is_some_and(|state| state.path() == path) =>
+        {
+            let index = app
+                .try_state::<SessionIndexState>()
+                .ok_or("Session index state not found")?;
+            // The open index holds its files, which Windows won't move
+            index.close()?;
+            let removed = trash.remove(path);
+            index.reopen()?;
+            removed
+        }
+        // Stores stay loaded by the store plugin, so clear them through it
+        (_, Some(path)) if path.extension().is_some_and(|ext| ext == STORE_EXTENSION) => {
+            let store = app
+                .store(path)
+                .map_err(|e| format!("Failed to open {}: {}", path.display(), e))?;
+            trash.snapshot(path)?;
+            // Opting out of telemetry survives the purge
+            let telemetry_disabled = store.get(TELEMETRY_DISABLED_KEY);
+            store.clear();
+            if let Some(value) = telemetry_disabled {
+                store.set(TELEMETRY_DISABLED_KEY, value);
+            }
+            atomic_store::save(app, path)
+                .map_err(|e| format!("Failed to save {}: {}", path.display(), e))
+        }
+        (_, Some(path)) => trash.remove(path),
+        (_, None) => Ok(()),
+    }
+}
+
+fn clear_browsing_data(app: &AppHandle) -> Result<(), String> {
+    for (label, window) in app.webview_windows() {
+        window
+            .clear_all_browsing_data()
+            .map_err(|e| format!("Failed to clear browsing data of {}: {}", label, e))?;
+    }
+    Ok(())
+}
+
+pub fn telemetry_disabled(app: &AppHandle) -> bool {
+    app.store(SETTINGS_STORE)
+        .ok()
+        .and_then(|store| store.get(TELEMETRY_DISABLED_KEY))
+        .and_then(|value| value.as_bool())
+        .unwrap_or(false)
+}
+
+/// Everything the app stores locally, with sizes
+#[tauri::command]
+pub fn get_data_inventory(app: AppHandle) -> Vec<DataLocation> {
+    inventory(&app)
+}
+
+/// Wipe logs, transcripts, history, caches and credentials. Keeps going
+/// past failures and reports them.
+#[tauri::command]
+pub async fn purge_all_local_data(
+    app: AppHandle,
+    webview: Webview,
+) -> Result<PurgeReport, AppError> {
+    let result = purge_all(app).await;
+    audit::record(&webview, "purge_all_local_data", Value::Null, &result);
+    result
+}
+
+async fn purge_all(app: AppHandle) -> Result<PurgeReport, AppError> {
+    let task_app = app.clone();
+    let report = tauri::async_runtime::spawn_blocking(move || {
+        let mut report = PurgeReport::default();
+        let mut trash = match trash::begin(&task_app, "Purge local data") {
+            Ok(trash) => trash,
+            Err(e) => {
+                report.errors.push(e);
+                return report;
+            }
+        };
+        for location in inventory(&task_app)
+            .into_iter()
+            .filter(|location| location.purgeable)
+        {
+            match purge(&task_app, &mut trash, &location) {
+                Ok(()) => {
+                    report.bytes_freed += location.bytes;
+                    report.removed.push(location);
+                }
+                Err(e) => report.errors.push(e),
+            }
+        }
+        match trash.commit() {
+            Ok(undo_id) => report.undo_id = undo_id,
+            Err(e) => report.errors.push(e),
+        }
+        if let Err(e) = clear_browsing_data(&task_app) {
+            report.errors.push(e);
+        }
+        if let Err(e) = stt_remote::forget_key(&task_app) {
+            report.errors.push(e);
+        }
+        recording_recovery::forget_all(&task_app);
+        report
+    })
+    .await
+    .map_err(|e| format!("Purge task failed: {}", e))?;
+
+    println!(
+        "Purged local data: {} locations, {} bytes, {} errors",
+        report.removed.len(),
+        report.bytes_freed,
+        report.errors.len()
+    );
+    for error in &report.errors {
+        eprintln!("Purge error: {}", error);
+    }
+    let _ = app.emit("privacy:purged", &report);
+    Ok(report)
+}
+
+#[tauri::command]
+pub fn get_telemetry_enabled(app: AppHandle) -> bool {
+    !telemetry_disabled(&app)
+}
+
+/// Turn telemetry of the bundled CLI on or off, from the next server start
+#[tauri::command]
+pub fn set_telemetry_enabled(
+    app: AppHandle,
+    webview: Webview,
+    enabled: bool,
+) -> Result<(), AppError> {
+    let result = save_telemetry_enabled(&app, enabled);
+    audit::record(
+        &webview,
+        "set_telemetry_enabled",
+        serde_json::json!({ "enabled": enabled }),
+        &result,
+    );
+    result
+}
+
+fn save_telemetry_enabled(app: &AppHandle, enabled: bool) -> Result<(), AppError> {
+    let store = app
+        .store(SETTINGS_STORE)
+        .map_err(|e| format!("Failed to open settings store: {}", e))?;
+    store.set(TELEMETRY_DISABLED_KEY, serde_json::json!(!enabled));
+    atomic_store::save(app, SETTINGS_STORE)
+        .map_err(|e| AppError::io(format!("Failed to save settings: {}", e)))
+}
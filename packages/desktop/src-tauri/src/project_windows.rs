@@ -0,0 +1,197 @@
+//! A window per project, grouped as native tabs on macOS.
+//!
+//! `open_project_window` opens a project directory in its own window, or
+//! focuses the one already showing it. On macOS all app windows share a
+//! tabbing identifier and project windows prefer tabs, so projects open as
+//! tabs of the frontmost window; Window > Merge All Windows (added by AppKit
+//! to the Window menu) and `merge_project_windows` gather scattered windows
+//! back into one frame.
+
+use base64::Engine;
+use tauri::{AppHandle, Manager, WebviewUrl, WebviewWindow};
+
+use crate::{
+    error::AppError,
+    hash::{HashAlgorithm, hash_bytes},
+    window_placement, workspace_trust,
+};
+
+/// Windows sharing this identifier can be tabbed together
+#[cfg(target_os = "macos")]
+pub const TABBING_IDENTIFIER: &str = "aura-projects";
+const LABEL_PREFIX: &str = "project-";
+
+fn project_label(directory: &str) -> String {
+    format!(
+        "{}{}",
+        LABEL_PREFIX,
+        &hash_bytes(directory.as_bytes(), HashAlgorithm::Blake3)[..16]
+    )
+}
+
+/// Route of a project's sessions, encoded the way the frontend router
+/// expects (`base64Encode` of `@opencode-ai/util/encode`)
+fn project_route(directory: &str) -> String {
+    format!(
+        "/{}/session",
+        base64::engine::general_purpose::URL_SAFE_NO_PAD.encode(directory)
+    )
+}
+
+fn window_title(directory: &str) -> String {
+    let name = std::path::Path::new(directory)
+        .file_name()
+        .map(|name| name.to_string_lossy().to_string())
+        .unwrap_or_else(|| directory.to_string());
+    format!("{} — Aura", name)
+}
+
+fn focus(window: &WebviewWindow) {
+    let _ = window.unminimize();
+    let _ = window.show();
+    let _ = window.set_focus();
+}
+
+#[cfg(target_os = "macos")]
+fn prefer_tabs(window: &WebviewWindow) -> Result<(), String> {
+    let ns_window = window
+        .ns_window()
+        .map_err(|e| format!("Failed to get NSWindow: {}", e))?;
+    unsafe {
+        let ns_window: &objc2_app_kit::NSWindow = &*ns_window.cast();
+        ns_window.setTabbingMode(objc2_app_kit::NSWindowTabbingMode::Preferred);
+    }
+    Ok(())
+}
+
+/// Open `directory` in a project window, returning the window label
+#[tauri::command]
+pub async fn open_project_window(app: AppHandle, directory: String) -> Result<String, AppError> {
+    if !std::path::Path::new(&directory).is_dir() {
+        return Err(AppError::invalid_input(format!(
+            "{} is not a directory",
+            directory
+        )));
+    }
+
+    let label = project_label(&directory);
+    if let Some(window) = app.get_webview_window(&label) {
+        focus(&window);
+        return Ok(label);
+    }
+    // Opens either way; untrusted folders in restricted mode
+    workspace_trust::prompt(&app, std::path::Path::new(&directory)).await?;
+
+    let (size, _) = window_placement::initial_window_bounds(&app, &label);
+    let app_for_nav = app.clone();
+    let window_builder = WebviewWindow::builder(
+        &app,
+        &label,
+        WebviewUrl::App(project_route(&directory).into()),
+    )
+    .title(window_title(&directory))
+    .inner_size(size.width, size.height)
+    .decorations(true)
+    .zoom_hotkeys_enabled(true)
+    .disable_drag_drop_handler()
+    .on_navigation(move |url| crate::allow_navigation(&app_for_nav, url))
+    .initialization_script(crate::typography::init_script(&app, &label));
+
+    #[cfg(target_os = "macos")]
+    let window_builder = window_builder
+        .title_bar_style(tauri::TitleBarStyle::Overlay)
+        .hidden_title(true)
+        .tabbing_identifier(TABBING_IDENTIFIER);
+
+    let window_builder = crate::proxy::with_webview_proxy(&app, window_builder);
+
+    #[cfg(windows)]
+    let window_builder = crate::host_overrides::with_browser_args(
+        &app,
+        window_builder
+            .decorations(false)
+            .initialization_script(crate::accessibility::titlebar_script(&app)),
+    );
+
+    let window = window_builder
+        .build()
+        .map_err(|e| format!("Failed to open project window: {}", e))?;
+    window_placement::ensure_on_screen(&window.as_ref().window());
+    window_placement::apply_monitor_rule(&window.as_ref().window());
+
+    #[cfg(windows)]
+    {
+        use tauri_plugin_decorum::WebviewWindowExt;
+        let _ = window.create_overlay_titlebar();
+        crate::window_controls::prepare_frameless(&window);
+    }
+
+    #[cfg(target_os = "macos")]
+    if let Err(e) = prefer_tabs(&window) {
+        eprintln!("{}", e);
+    }
+
+    focus(&window);
+    Ok(label)
+}
+
+/// Labels of the open project windows
+#[tauri::command]
+pub fn list_project_windows(app: AppHandle) -> Vec<String> {
+    let mut labels: Vec<String> = app
+        .webview_windows()
+        .into_keys()
+        .filter(|label| label.starts_with(LABEL_PREFIX))
+        .collect();
+    labels.sort();
+    labels
+}
+
+/// Gather all app windows as tabs of the focused one (macOS only)
+#[tauri::command]
+pub fn merge_project_windows(app: AppHandle) -> Result<(), AppError> {
+    #[cfg(target_os = "macos")]
+    {
+        let window = app
+            .webview_windows()
+            .into_values()
+            .find(|window| window.is_focused().unwrap_or(false))
+            .or_else(|| app.get_webview_window("main"))
+            .ok_or("No window to merge into")?;
+        let target = window.clone();
+        window
+            .run_on_main_thread(move || {
+                let Ok(ns_window) = target.ns_window() else {
+                    return;
+                };
+                unsafe {
+                    let ns_window: &objc2_app_kit::NSWindow = &*ns_window.cast();
+                    ns_window.mergeAllWindows(None);
+                }
+            })
+            .map_err(|e| format!("Failed to merge windows: {}", e))
+    }
+
+    #[cfg(not(target_os = "macos"))]
+    {
+        let _ = app;
+        Err(AppError::unavailable(
+            "Window tabs are only supported on macOS",
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_project_route_matches_frontend_encoding() {
+        // base64Encode("/home/dev/my app?") in the frontend
+        assert_eq!(
+            project_route("/home/dev/my app?"),
+            "/L2hvbWUvZGV2L215IGFwcD8/session"
+        );
+        assert_eq!(project_route("/a>"), "/L2E-/session");
+    }
+}
@@ -0,0 +1,195 @@
+//! Proxy for connections to remote servers.
+//!
+//! Users who reach their server through a tunnel, often an SSH dynamic
+//! forward (`ssh -D 1080`), set an HTTP or SOCKS5 proxy under `networkProxy`
+//! in the settings:
+//!
+//! ```json
+//! { "url": "socks5h://127.0.0.1:1080", "username": "me", "password": "..." }
+//! ```
+//!
+//! The app's own requests to remote servers go through it; the local server
+//! is always reached directly. Webviews go through it too on Windows and
+//! Linux, from the next window opened, but can't authenticate, so a proxy
+//! with credentials only carries the app's requests. A proxy forced by the
+//! policy takes precedence and can't be changed here.
+
+use serde::{Deserialize, Serialize};
+use tauri::{AppHandle, Webview};
+use tauri_plugin_store::StoreExt;
+
+use crate::{SETTINGS_STORE, atomic_store, audit, error::AppError, policy, url_is_localhost};
+
+const NETWORK_PROXY_KEY: &str = "networkProxy";
+const SCHEMES: &[&str] = &["http", "https", "socks5", "socks5h"];
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ProxySettings {
+    /// `socks5h` resolves host names on the proxy, `socks5` locally
+    pub url: String,
+    pub username: Option<String>,
+    pub password: Option<String>,
+}
+
+impl ProxySettings {
+    fn validate(&self) -> Result<reqwest::Url, AppError> {
+        let url = reqwest::Url::parse(self.url.trim())
+            .map_err(|e| AppError::invalid_input(format!("Invalid proxy {}: {}", self.url, e)))?;
+        if !SCHEMES.contains(&url.scheme()) {
+            return Err(AppError::invalid_input(format!(
+                "Unsupported proxy scheme {}, expected one of {}",
+                url.scheme(),
+                SCHEMES.join(", ")
+            )));
+        }
+        if url.host_str().is_none() || url.port_or_known_default().is_none() {
+            return Err(AppError::invalid_input(format!(
+                "Proxy {} needs a host and port",
+                self.url
+            )));
+        }
+        Ok(url)
+    }
+
+    fn to_proxy(&self) -> Result<reqwest::Proxy, String> {
+        let proxy = reqwest::Proxy::all(self.url.trim())
+            .map_err(|e| format!("Invalid proxy {}: {}", self.url, e))?;
+        Ok(match &self.username {
+            Some(username) => {
+                proxy.basic_auth(username, self.password.as_deref().unwrap_or_default())
+            }
+            None => proxy,
+        })
+    }
+}
+
+/// The proxy set in the settings
+pub fn configured(app: &AppHandle) -> Option<ProxySettings> {
+    app.store(SETTINGS_STORE)
+        .ok()?
+        .get(NETWORK_PROXY_KEY)
+        .and_then(|value| serde_json::from_value(value).ok())
+}
+
+/// Route `builder`'s client through the configured proxy, for requests to
+/// `url`. The policy's proxy already applies to all clients.
+pub fn apply(
+    app: &AppHandle,
+    builder: reqwest::ClientBuilder,
+    url: &reqwest::Url,
+) -> reqwest::ClientBuilder {
+    if url_is_localhost(url) || policy::current(app).proxy.is_some() {
+        return builder;
+    }
+    let Some(settings) = configured(app) else {
+        return builder;
+    };
+    match settings.to_proxy() {
+        Ok(proxy) => builder.proxy(proxy),
+        Err(e) => {
+            eprintln!("{}", e);
+            builder
+        }
+    }
+}
+
+/// The proxy for webviews, when there is one they can use
+#[cfg_attr(target_os = "macos", allow(dead_code))]
+pub fn webview_proxy(app: &AppHandle) -> Option<tauri::Url> {
+    if policy::current(app).proxy.is_some() {
+        return None;
+    }
+    let settings = configured(app).filter(|settings| settings.username.is_none())?;
+    let mut url = settings.validate().ok()?;
+    // Webviews take `http` and `socks5` only; both resolve on the proxy
+    let scheme = match url.scheme() {
+        "socks5" | "socks5h" => "socks5",
+        _ => "http",
+    };
+    url.set_scheme(scheme).ok()?;
+    Some(url)
+}
+
+/// Set the webview proxy of a new window. WKWebView only supports one from
+/// macOS 14, behind a feature this app doesn't build with.
+pub fn with_webview_proxy<'a, R: tauri::Runtime, M: tauri::Manager<R>>(
+    app: &AppHandle,
+    builder: tauri::WebviewWindowBuilder<'a, R, M>,
+) -> tauri::WebviewWindowBuilder<'a, R, M> {
+    #[cfg(not(target_os = "macos"))]
+    if let Some(url) = webview_proxy(app) {
+        return builder.proxy_url(url);
+    }
+    #[cfg(target_os = "macos")]
+    let _ = app;
+    builder
+}
+
+#[tauri::command]
+pub fn get_network_proxy(app: AppHandle) -> Option<ProxySettings> {
+    configured(&app)
+}
+
+/// Set or, with none, remove the proxy
+#[tauri::command]
+pub fn set_network_proxy(
+    app: AppHandle,
+    webview: Webview,
+    proxy: Option<ProxySettings>,
+) -> Result<(), AppError> {
+    let result = save_proxy(&app, proxy.as_ref());
+    audit::record(
+        &webview,
+        "set_network_proxy",
+        serde_json::json!({ "proxy": proxy }),
+        &result,
+    );
+    result
+}
+
+fn save_proxy(app: &AppHandle, proxy: Option<&ProxySettings>) -> Result<(), AppError> {
+    if policy::current(app).proxy.is_some() {
+        return Err(policy::locked_error(app));
+    }
+    let store = app
+        .store(SETTINGS_STORE)
+        .map_err(|e| format!("Failed to open settings store: {}", e))?;
+    match proxy {
+        Some(proxy) => {
+            proxy.validate()?;
+            proxy.to_proxy()?;
+            let value = serde_json::to_value(proxy)
+                .map_err(|e| format!("Failed to serialize proxy: {}", e))?;
+            store.set(NETWORK_PROXY_KEY, value);
+        }
+        None => {
+            store.delete(NETWORK_PROXY_KEY);
+        }
+    }
+    atomic_store::save(app, SETTINGS_STORE)
+        .map_err(|e| AppError::io(format!("Failed to save settings: {}", e)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn proxy(url: &str) -> ProxySettings {
+        ProxySettings {
+            url: url.to_string(),
+            username: Some("me".to_string()),
+            password: Some("secret".to_string()),
+        }
+    }
+
+    #[test]
+    fn test_validate_proxy() {
+        assert!(proxy("socks5h://127.0.0.1:1080").validate().is_ok());
+        assert!(proxy("http://proxy.example.com").validate().is_ok());
+        assert!(proxy("socks5://127.0.0.1").validate().is_err());
+        assert!(proxy("ftp://proxy.example.com:21").validate().is_err());
+        assert!(proxy("127.0.0.1:1080").validate().is_err());
+        assert!(proxy("socks5h://127.0.0.1:1080").to_proxy().is_ok());
+    }
+}
@@ -0,0 +1,430 @@
+//! Rescuing recordings a crash cut short.
+//!
+//! While recording, the audio is appended every few seconds to a WAV file of
+//! its own in `RECOVERY_DIR`, which goes once the recording was transcribed
+//! or its audio dropped. A recording whose transcription failed is offered
+//! for recovery at once; files still there at launch are from recordings the
+//! app didn't live to transcribe. They're published as
+//! `recoveredRecordings` in the app state, for the frontend to offer
+//! `stt_recover_recording`, which transcribes one, or
+//! `stt_discard_recovered_recording`. Either moves the file to the trash,
+//! and a recording put back from there is offered again.
+
+use serde::Serialize;
+use std::{
+    collections::HashMap,
+    fs::File,
+    io::{Seek, SeekFrom, Write},
+    path::{Path, PathBuf},
+    sync::Mutex,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+use tauri::{AppHandle, Manager, path::BaseDirectory};
+use tokio::sync::oneshot;
+
+use crate::{
+    app_state,
+    error::AppError,
+    stt::{self, Transcription},
+    transcription_queue::{TranscriptionJob, TranscriptionQueue},
+    trash,
+};
+
+pub const RECOVERY_DIR: &str = "recovery";
+const CHECKPOINT_INTERVAL: Duration = Duration::from_secs(5);
+const HEADER_LEN: usize = 44;
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RecoveredRecording {
+    /// When the recording started, in milliseconds since the epoch
+    pub id: String,
+    pub duration_secs: f32,
+}
+
+/// The recordings found at launch, until each is recovered or discarded
+#[derive(Default)]
+pub struct RecoveryState(Mutex<Vec<RecoveredRecording>>);
+
+/// Recordings whose audio was taken for transcription, by recording, with
+/// what tells their checkpoint whether it succeeded
+#[derive(Default)]
+pub struct HandOffState(Mutex<HashMap<u64, oneshot::Receiver<bool>>>);
+
+fn recovery_dir(app: &AppHandle) -> Result<PathBuf, String> {
+    app.path()
+        .resolve(RECOVERY_DIR, BaseDirectory::AppLocalData)
+        .map_err(|e| format!("Failed to resolve the recovery directory: {}", e))
+}
+
+/// Header of a 16 kHz mono 16-bit PCM WAV file with `data_len` bytes of audio
+fn wav_header(data_len: u32) -> Vec<u8> {
+    let mut header = Vec::with_capacity(HEADER_LEN);
+    header.extend(b"RIFF");
+    header.extend((36 + data_len).to_le_bytes());
+    header.extend(b"WAVEfmt ");
+    header.extend(16u32.to_le_bytes());
+    header.extend(1u16.to_le_bytes());
+    header.extend(1u16.to_le_bytes());
+    header.extend(16_000u32.to_le_bytes());
+    header.extend(32_000u32.to_le_bytes());
+    header.extend(2u16.to_le_bytes());
+    header.extend(16u16.to_le_bytes());
+    header.extend(b"data");
+    header.extend(data_len.to_le_bytes());
+    header
+}
+
+fn pcm16(samples: &[f32]) -> Vec<u8> {
+    samples
+        .iter()
+        .flat_map(|sample| ((sample.clamp(-1.0, 1.0) * i16::MAX as f32) as i16).to_le_bytes())
+        .collect()
+}
+
+/// The samples of a file written by `Checkpoint`. The lengths in its header
+/// may lag behind the audio after a crash, so everything past it is read.
+fn read_samples(bytes: &[u8]) -> Result<Vec<f32>, String> {
+    if bytes.len() < HEADER_LEN || &bytes[..4] != b"RIFF" || &bytes[36..40] != b"data" {
+        return Err("Not a recovered recording".to_string());
+    }
+    Ok(bytes[HEADER_LEN..]
+        .chunks_exact(2)
+        .map(|pair| i16::from_le_bytes([pair[0], pair[1]]) as f32 / i16::MAX as f32)
+        .collect())
+}
+
+/// The WAV file a recording is saved to as it goes
+struct Checkpoint {
+    file: File,
+    data_len: u32,
+}
+
+impl Checkpoint {
+    fn create(path: &Path) -> Result<Self, String> {
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)
+                .map_err(|e| format!("Failed to create {}: {}", parent.display(), e))?;
+        }
+        let mut file = File::create(path)
+            .map_err(|e| format!("Failed to create {}: {}", path.display(), e))?;
+        file.write_all(&wav_header(0))
+            .map_err(|e| format!("Failed to write {}: {}", path.display(), e))?;
+        Ok(Self { file, data_len: 0 })
+    }
+
+    fn append(&mut self, samples: &[f32]) -> std::io::Result<()> {
+        let data = pcm16(samples);
+        self.file.seek(SeekFrom::End(0))?;
+        self.file.write_all(&data)?;
+        self.data_len = self.data_len.saturating_add(data.len() as u32);
+        let header = wav_header(self.data_len);
+        self.file.seek(SeekFrom::Start(0))?;
+        self.file.write_all(&header)?;
+        self.file.sync_data()
+    }
+
+    /// Append `samples` to the checkpoint at `path`, creating it first
+    fn save(checkpoint: &mut Option<Self>, path: &Path, samples: &[f32]) -> Result<(), String> {
+        if checkpoint.is_none() {
+            *checkpoint = Some(Self::create(path)?);
+        }
+        match checkpoint {
+            Some(checkpoint) => checkpoint
+                .append(samples)
+                .map_err(|e| format!("Failed to write {}: {}", path.display(), e)),
+            None => Ok(()),
+        }
+    }
+}
+
+/// Keep the checkpoint of `recording` until its transcription is done, which
+/// is to be sent through the returned sender. Call as its audio is taken from
+/// the buffer, under the same lock, so the checkpoint sees the hand-off
+pub fn hand_off(app: &AppHandle, recording: u64) -> Option<oneshot::Sender<bool>> {
+    let (sender, receiver) = oneshot::channel();
+    app.try_state::<HandOffState>()?
+        .0
+        .lock()
+        .ok()?
+        .insert(recording, receiver);
+    Some(sender)
+}
+
+/// Save recording `recording` as it goes, until it was transcribed or its
+/// audio dropped
+pub fn spawn_checkpoints(app: &AppHandle, recording: u64) {
+    let app = app.clone();
+    tauri::async_runtime::spawn(async move {
+        let id = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_millis())
+            .unwrap_or_default();
+        let path = match recovery_dir(&app) {
+            Ok(dir) => dir.join(format!("{}.wav", id)),
+            Err(e) => {
+                eprintln!("Recording won't be recoverable: {}", e);
+                return;
+            }
+        };
+        let mut checkpoint = None;
+        let mut saved = 0;
+        loop {
+            tokio::time::sleep(CHECKPOINT_INTERVAL).await;
+            let Some(state) = app.try_state::<stt::SharedSttState>() else {
+                return;
+            };
+            let samples = state
+                .lock()
+                .await
+                .recorded_since(recording, saved)
+                .map(|samples| samples.to_vec());
+            let Some(samples) = samples else {
+                break;
+            };
+            if samples.is_empty() {
+                continue;
+            }
+            let len = samples.len();
+            // Writing and syncing would hold up a runtime worker
+            let mut pending = checkpoint.take();
+            let pending_path = path.clone();
+            let written = tauri::async_runtime::spawn_blocking(move || {
+                let result = Checkpoint::save(&mut pending, &pending_path, &samples);
+                (pending, result)
+            })
+            .await;
+            let result = match written {
+                Ok((returned, result)) => {
+                    checkpoint = returned;
+                    result
+                }
+                Err(e) => {
+                    eprintln!("Recording won't be recoverable: {}", e);
+                    let _ = std::fs::remove_file(&path);
+                    return;
+                }
+            };
+            match result {
+                Ok(()) => saved += len,
+                Err(e) => eprintln!("Failed to save the recording for recovery: {}", e),
+            }
+        }
+        let handed_off = app
+            .try_state::<HandOffState>()
+            .and_then(|state| state.0.lock().ok()?.remove(&recording));
+        // Closed first, as Windows won't delete an open file
+        if checkpoint.take().is_none() {
+            return;
+        }
+        // A sender dropped unused means the job never ran
+        let transcribed = match handed_off {
+            Some(done) => done.await.unwrap_or(false),
+            None => true,
+        };
+        if transcribed {
+            let _ = std::fs::remove_file(&path);
+        } else if let Some(recording) = recovered(&path) {
+            offer(&app, recording);
+        }
+    });
+}
+
+/// The recording saved at `path`, unless it was cut short before any audio
+/// was, in which case the file goes
+fn recovered(path: &Path) -> Option<RecoveredRecording> {
+    if path.extension()? != "wav" {
+        return None;
+    }
+    let id = path.file_stem()?.to_str()?.to_string();
+    let len = std::fs::metadata(path).ok()?.len() as usize;
+    if len <= HEADER_LEN {
+        let _ = std::fs::remove_file(path);
+        return None;
+    }
+    Some(RecoveredRecording {
+        id,
+        duration_secs: (len - HEADER_LEN) as f32 / 32_000.0,
+    })
+}
+
+/// Offer `recording` for recovery, unless it already is
+fn offer(app: &AppHandle, recording: RecoveredRecording) {
+    let Some(state) = app.try_state::<RecoveryState>() else {
+        return;
+    };
+    let Ok(mut recordings) = state.0.lock() else {
+        return;
+    };
+    if !recordings.iter().any(|known| known.id == recording.id) {
+        app_state::publish(app, &["recoveredRecordings", &recording.id], &recording);
+        recordings.push(recording);
+    }
+}
+
+/// The recordings in the recovery directory
+fn scan(app: &AppHandle) -> Vec<RecoveredRecording> {
+    let mut recordings: Vec<RecoveredRecording> = recovery_dir(app)
+        .and_then(|dir| std::fs::read_dir(&dir).map_err(|e| e.to_string()))
+        .map(|entries| {
+            entries
+                .filter_map(|entry| recovered(&entry.ok()?.path()))
+                .collect()
+        })
+        .unwrap_or_default();
+    recordings.sort_by(|a, b| a.id.cmp(&b.id));
+    recordings
+}
+
+/// Find the recordings left from before this launch; run before recording
+/// can start
+pub fn init(app: &AppHandle) {
+    let recordings = scan(app);
+    for recording in &recordings {
+        app_state::publish(app, &["recoveredRecordings", &recording.id], recording);
+    }
+    app.manage(RecoveryState(Mutex::new(recordings)));
+    app.manage(HandOffState::default());
+}
+
+/// Offer the recordings again once `path`, the recovery directory or a
+/// recording in it, came back from the trash
+pub fn restored(app: &AppHandle, path: &Path) {
+    let Ok(dir) = recovery_dir(app) else {
+        return;
+    };
+    if path != dir && path.parent() != Some(dir.as_path()) {
+        return;
+    }
+    // Recordings in progress have checkpoints here too
+    let in_progress = app
+        .try_state::<stt::SharedSttState>()
+        .and_then(|state| Some(state.try_lock().ok()?.get_status().is_recording))
+        .unwrap_or(true);
+    if in_progress {
+        return;
+    }
+    for recording in scan(app) {
+        offer(app, recording);
+    }
+}
+
+/// Path of recovered recording `id`, if there is one
+fn recording_path(app: &AppHandle, id: &str) -> Result<PathBuf, AppError> {
+    let state = app
+        .try_state::<RecoveryState>()
+        .ok_or("Recovery state not found")?;
+    let known = state
+        .0
+        .lock()
+        .map_err(|e| format!("Lock error: {}", e))?
+        .iter()
+        .any(|recording| recording.id == id);
+    if !known {
+        return Err(AppError::not_found(format!(
+            "No recovered recording {}",
+            id
+        )));
+    }
+    Ok(recovery_dir(app)?.join(format!("{}.wav", id)))
+}
+
+/// Move recovered recording `id` to the trash, returning the id of the
+/// operation that puts it back
+fn remove(app: &AppHandle, id: &str) -> Result<Option<u64>, AppError> {
+    let path = recording_path(app, id)?;
+    let mut trash = trash::begin(app, "Discard recovered recording")?;
+    trash.remove(&path)?;
+    let undo_id = trash.commit()?;
+    if let Some(state) = app.try_state::<RecoveryState>()
+        && let Ok(mut recordings) = state.0.lock()
+    {
+        recordings.retain(|recording| recording.id != id);
+    }
+    app_state::publish(
+        app,
+        &["recoveredRecordings", id],
+        None::<RecoveredRecording>,
+    );
+    Ok(undo_id)
+}
+
+/// Forget the recordings, once the privacy purge removed their files
+pub fn forget_all(app: &AppHandle) {
+    let Some(state) = app.try_state::<RecoveryState>() else {
+        return;
+    };
+    let Ok(mut recordings) = state.0.lock() else {
+        return;
+    };
+    for recording in recordings.drain(..) {
+        app_state::publish(
+            app,
+            &["recoveredRecordings", &recording.id],
+            None::<RecoveredRecording>,
+        );
+    }
+}
+
+/// Transcribe recovered recording `id`, which is then discarded
+#[tauri::command]
+pub async fn stt_recover_recording(app: AppHandle, id: String) -> Result<Transcription, AppError> {
+    let path = recording_path(&app, &id)?;
+    let audio = tauri::async_runtime::spawn_blocking(move || {
+        let bytes = std::fs::read(&path)
+            .map_err(|e| AppError::io(format!("Failed to read {}: {}", path.display(), e)))?;
+        Ok::<_, AppError>(read_samples(&bytes)?)
+    })
+    .await
+    .map_err(|e| format!("Recovery task failed: {}", e))??;
+
+    let inference = stt::loaded_inference(&app).await?;
+    let queue = app
+        .try_state::<TranscriptionQueue>()
+        .ok_or("Transcription queue not found")?;
+    let (_, result) = queue.enqueue(
+        &app,
+        TranscriptionJob {
+            audio,
+            inference,
+            speaker_embedder: None,
+            max_speakers: None,
+            dictation_session: None,
+            checkpoint: None,
+        },
+    )?;
+    let transcription = result
+        .await
+        .map_err(|_| "Transcription job was dropped".to_string())??;
+
+    remove(&app, &id)?;
+    Ok(transcription)
+}
+
+/// Move recovered recording `id` to the trash, returning the id of the
+/// operation that puts it back
+#[tauri::command]
+pub fn stt_discard_recovered_recording(
+    app: AppHandle,
+    id: String,
+) -> Result<Option<u64>, AppError> {
+    remove(&app, &id)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_read_samples() {
+        let samples = [0.0, 0.5, -0.5, 1.0];
+        let mut wav = wav_header(0);
+        wav.extend(pcm16(&samples));
+        assert_eq!(wav.len(), HEADER_LEN + samples.len() * 2);
+
+        let read = read_samples(&wav).unwrap();
+        assert_eq!(read.len(), samples.len());
+        assert!(read.iter().zip(samples).all(|(a, b)| (a - b).abs() < 1e-4));
+        assert!(read_samples(b"RIFF").is_err());
+    }
+}
@@ -0,0 +1,80 @@
+//! Which release channel this build is, so a beta can run next to stable.
+//!
+//! The channel comes from the app identifier: stable builds are
+//! `ai.Aura.desktop`, beta and dev builds add `.beta` and `.dev`. What is
+//! kept under the identifier is each channel's own: the settings and other
+//! stores, downloaded models and the sidecar's state, as are the
+//! single-instance lock and local server port `user_scope` derives from it.
+//! What the channels do share is handled here:
+//!
+//! - On macOS the app killed every `opencode-cli` at launch, the other
+//!   channel's sidecar too. It now only kills sidecars of its own install.
+//! - There is one installed CLI, so only stable syncs it on launch by
+//!   default; the other channels sync it on request.
+//!
+//! The app registers no deep-link scheme yet; one should take the channel's
+//! suffix too.
+
+use serde::Serialize;
+use tauri::AppHandle;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub enum Channel {
+    Stable,
+    Beta,
+    Dev,
+}
+
+impl Channel {
+    fn from_identifier(identifier: &str) -> Self {
+        match identifier.rsplit('.').next() {
+            Some("beta") => Self::Beta,
+            Some("dev") => Self::Dev,
+            _ => Self::Stable,
+        }
+    }
+}
+
+pub fn current(app: &AppHandle) -> Channel {
+    Channel::from_identifier(&app.config().identifier)
+}
+
+/// Kill sidecars a previous run of this install left behind, leaving those
+/// of other installs running
+#[cfg(target_os = "macos")]
+pub fn kill_stale_sidecars() {
+    let Some(sidecar) = std::env::current_exe()
+        .ok()
+        .and_then(|exe| Some(exe.parent()?.join("opencode-cli")))
+    else {
+        return;
+    };
+    let _ = std::process::Command::new("pkill")
+        .arg("-f")
+        .arg(regex::escape(&sidecar.to_string_lossy()))
+        .output();
+}
+
+#[tauri::command]
+pub fn get_release_channel(app: AppHandle) -> Channel {
+    current(&app)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_from_identifier() {
+        assert_eq!(Channel::from_identifier("ai.Aura.desktop"), Channel::Stable);
+        assert_eq!(
+            Channel::from_identifier("ai.Aura.desktop.beta"),
+            Channel::Beta
+        );
+        assert_eq!(
+            Channel::from_identifier("ai.Aura.desktop.dev"),
+            Channel::Dev
+        );
+    }
+}
@@ -0,0 +1,378 @@
+//! How long logs, crash reports, transcripts and caches are kept.
+//!
+//! Each category has a maximum age and size, set under `retention` in the
+//! settings; `null` keeps it forever or at any size. A cleanup runs shortly
+//! after startup and every few hours after, removing what is past its age and
+//! then the oldest files until the category fits in its size, and emits
+//! `retention:cleaned` when it removed anything. `run_retention_cleanup` runs
+//! it now. Transcripts are only kept in the semantic index, so they are
+//! pruned from it rather than from disk.
+//!
+//! `get_storage_breakdown` shows what takes up the disk, by category, with
+//! the policies in force.
+
+use serde::{Deserialize, Serialize};
+use std::{
+    cmp::Reverse,
+    path::{Path, PathBuf},
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+use tauri::{AppHandle, Emitter, Manager, Webview, path::BaseDirectory};
+
+use crate::{
+    app_settings, audit, changelog,
+    error::AppError,
+    privacy::{self, DataCategory, DataLocation},
+    semantic_search::SemanticIndexState,
+    server_cache, user_scope,
+};
+
+pub const CRASH_REPORTS_DIR: &str = "crash-reports";
+/// Written by the "open server logs" action
+const SERVER_LOG_FILE: &str = "aura-server.log";
+const FIRST_CLEANUP_DELAY: Duration = Duration::from_secs(2 * 60);
+const CLEANUP_INTERVAL: Duration = Duration::from_secs(6 * 60 * 60);
+const DAY_SECS: u64 = 24 * 60 * 60;
+const MB: u64 = 1024 * 1024;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Policy {
+    pub max_age_days: Option<u64>,
+    pub max_mb: Option<u64>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct RetentionSettings {
+    pub logs: Policy,
+    pub crash_reports: Policy,
+    pub transcripts: Policy,
+    pub caches: Policy,
+}
+
+impl Default for RetentionSettings {
+    fn default() -> Self {
+        Self {
+            logs: Policy {
+                max_age_days: Some(14),
+                max_mb: Some(100),
+            },
+            crash_reports: Policy {
+                max_age_days: Some(90),
+                max_mb: Some(50),
+            },
+            // What users said is theirs to delete
+            transcripts: Policy {
+                max_age_days: None,
+                max_mb: None,
+            },
+            caches: Policy {
+                max_age_days: Some(30),
+                max_mb: Some(1024),
+            },
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CleanupReport {
+    pub files_removed: usize,
+    pub bytes_freed: u64,
+    pub transcripts_removed: usize,
+    pub errors: Vec<String>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CategoryUsage {
+    pub category: DataCategory,
+    pub bytes: u64,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct StorageBreakdown {
+    pub total_bytes: u64,
+    /// Largest first
+    pub categories: Vec<CategoryUsage>,
+    /// Largest first
+    pub locations: Vec<DataLocation>,
+    pub retention: RetentionSettings,
+}
+
+struct AgedFile {
+    path: PathBuf,
+    modified: SystemTime,
+    bytes: u64,
+}
+
+fn settings(app: &AppHandle) -> RetentionSettings {
+    app_settings::load(app).retention
+}
+
+fn resolve(app: &AppHandle, path: &str, base: BaseDirectory) -> Option<PathBuf> {
+    app.path().resolve(path, base).ok()
+}
+
+/// Files and directories the files of each category are in
+fn log_paths(app: &AppHandle) -> Vec<PathBuf> {
+    [
+        // The bundled CLI writes its logs with its own data
+        resolve(app, "opencode/log", BaseDirectory::AppLocalData),
+        user_scope::temp_dir()
+            .ok()
+            .map(|dir| dir.join(SERVER_LOG_FILE)),
+    ]
+    .into_iter()
+    .flatten()
+    .collect()
+}
+
+fn crash_report_paths(app: &AppHandle) -> Vec<PathBuf> {
+    resolve(app, CRASH_REPORTS_DIR, BaseDirectory::AppLocalData)
+        .into_iter()
+        .collect()
+}
+
+fn cache_paths(app: &AppHandle) -> Vec<PathBuf> {
+    [
+        "asset-cache",
+        "link-previews",
+        server_cache::CACHE_DIR,
+        changelog::CACHE_FILE,
+    ]
+    .into_iter()
+    .filter_map(|path| resolve(app, path, BaseDirectory::AppCache))
+    .collect()
+}
+
+/// Cache files pruning leaves, as they aren't caches themselves
+fn kept_cache_files(app: &AppHandle) -> Vec<PathBuf> {
+    resolve(app, server_cache::CACHE_DIR, BaseDirectory::AppCache)
+        .map(|dir| dir.join(server_cache::LAST_ORIGIN_FILE))
+        .into_iter()
+        .collect()
+}
+
+fn collect_files(path: &Path, files: &mut Vec<AgedFile>) {
+    let Ok(metadata) = std::fs::symlink_metadata(path) else {
+        return;
+    };
+    if metadata.is_dir() {
+        if let Ok(entries) = std::fs::read_dir(path) {
+            for entry in entries.flatten() {
+                collect_files(&entry.path(), files);
+            }
+        }
+    } else if metadata.is_file() {
+        files.push(AgedFile {
+            path: path.to_path_buf(),
+            modified: metadata.modified().unwrap_or(UNIX_EPOCH),
+            bytes: metadata.len(),
+        });
+    }
+}
+
+/// Indices of the files `policy` removes at `now`: those past the maximum
+/// age, then the oldest until the rest fit
+fn expired(files: &[AgedFile], policy: &Policy, now: SystemTime) -> Vec<usize> {
+    let mut order: Vec<usize> = (0..files.len()).collect();
+    order.sort_by_key(|&i| files[i].modified);
+    let max_age = policy
+        .max_age_days
+        .map(|days| Duration::from_secs(days * DAY_SECS));
+    let max_bytes = policy.max_mb.map(|mb| mb * MB);
+    let mut bytes: u64 = files.iter().map(|file| file.bytes).sum();
+
+    order
+        .into_iter()
+        .take_while(|&i| {
+            let age = now.duration_since(files[i].modified).unwrap_or_default();
+            let too_old = max_age.is_some_and(|max| age > max);
+            let too_big = max_bytes.is_some_and(|max| bytes > max);
+            bytes = bytes.saturating_sub(files[i].bytes);
+            too_old || too_big
+        })
+        .collect()
+}
+
+fn prune(paths: &[PathBuf], kept: &[PathBuf], policy: &Policy, report: &mut CleanupReport) {
+    if policy.max_age_days.is_none() && policy.max_mb.is_none() {
+        return;
+    }
+    let mut files = Vec::new();
+    for path in paths {
+        collect_files(path, &mut files);
+    }
+    files.retain(|file| !kept.contains(&file.path));
+    for i in expired(&files, policy, SystemTime::now()) {
+        let file = &files[i];
+        match std::fs::remove_file(&file.path) {
+            Ok(()) => {
+                report.files_removed += 1;
+                report.bytes_freed += file.bytes;
+            }
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => {}
+            Err(e) => {
+                report
+                    .errors
+                    .push(format!("Failed to remove {}: {}", file.path.display(), e))
+            }
+        }
+    }
+}
+
+fn prune_transcripts(app: &AppHandle, policy: &Policy, report: &mut CleanupReport) {
+    if policy.max_age_days.is_none() && policy.max_mb.is_none() {
+        return;
+    }
+    let Some(index) = app.try_state::<SemanticIndexState>() else {
+        return;
+    };
+    let now_ms = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_millis() as u64)
+        .unwrap_or(0);
+    let cutoff = policy
+        .max_age_days
+        .map(|days| now_ms.saturating_sub(days * DAY_SECS * 1000));
+    match index.prune_transcripts(cutoff, policy.max_mb.map(|mb| mb * MB)) {
+        Ok(removed) => report.transcripts_removed += removed,
+        Err(e) => report.errors.push(e),
+    }
+}
+
+fn cleanup(app: &AppHandle) -> CleanupReport {
+    let settings = settings(app);
+    let mut report = CleanupReport::default();
+    prune(&log_paths(app), &[], &settings.logs, &mut report);
+    prune(
+        &crash_report_paths(app),
+        &[],
+        &settings.crash_reports,
+        &mut report,
+    );
+    prune(
+        &cache_paths(app),
+        &kept_cache_files(app),
+        &settings.caches,
+        &mut report,
+    );
+    prune_transcripts(app, &settings.transcripts, &mut report);
+
+    if report.files_removed + report.transcripts_removed > 0 || !report.errors.is_empty() {
+        let _ = app.emit("retention:cleaned", &report);
+    }
+    report
+}
+
+/// Enforce the retention policies now and then, for the life of the app
+pub fn watch(app: &AppHandle) {
+    let app = app.clone();
+    tauri::async_runtime::spawn(async move {
+        tokio::time::sleep(FIRST_CLEANUP_DELAY).await;
+        loop {
+            let app_for_cleanup = app.clone();
+            let _ = tauri::async_runtime::spawn_blocking(move || cleanup(&app_for_cleanup)).await;
+            tokio::time::sleep(CLEANUP_INTERVAL).await;
+        }
+    });
+}
+
+/// Remove what the retention policies no longer keep
+#[tauri::command]
+pub async fn run_retention_cleanup(
+    app: AppHandle,
+    webview: Webview,
+) -> Result<CleanupReport, AppError> {
+    let app_for_cleanup = app.clone();
+    let result = tauri::async_runtime::spawn_blocking(move || cleanup(&app_for_cleanup))
+        .await
+        .map_err(|e| AppError::from(format!("Cleanup failed: {}", e)));
+    audit::record(
+        &webview,
+        "run_retention_cleanup",
+        serde_json::Value::Null,
+        &result,
+    );
+    result
+}
+
+/// Disk used by the app, by category and by location
+#[tauri::command]
+pub async fn get_storage_breakdown(app: AppHandle) -> Result<StorageBreakdown, AppError> {
+    // Sizing the models and caches walks every file in them
+    Ok(
+        tauri::async_runtime::spawn_blocking(move || storage_breakdown(&app))
+            .await
+            .map_err(|e| format!("Failed to measure storage: {}", e))?,
+    )
+}
+
+fn storage_breakdown(app: &AppHandle) -> StorageBreakdown {
+    let mut locations = privacy::inventory(app);
+    locations.sort_by_key(|location| Reverse(location.bytes));
+
+    let mut categories: Vec<CategoryUsage> = Vec::new();
+    for location in &locations {
+        match categories
+            .iter_mut()
+            .find(|usage| usage.category == location.category)
+        {
+            Some(usage) => usage.bytes += location.bytes,
+            None => categories.push(CategoryUsage {
+                category: location.category,
+                bytes: location.bytes,
+            }),
+        }
+    }
+    categories.sort_by_key(|usage| Reverse(usage.bytes));
+
+    StorageBreakdown {
+        total_bytes: locations.iter().map(|location| location.bytes).sum(),
+        categories,
+        locations,
+        retention: settings(app),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_expired_by_age_then_size() {
+        let now = UNIX_EPOCH + Duration::from_secs(100 * DAY_SECS);
+        let file = |name: &str, days_old: u64, mb: u64| AgedFile {
+            path: PathBuf::from(name),
+            modified: now - Duration::from_secs(days_old * DAY_SECS),
+            bytes: mb * MB,
+        };
+        let files = vec![
+            file("new", 1, 3),
+            file("old", 40, 1),
+            file("middle", 10, 3),
+            file("newest", 0, 3),
+        ];
+
+        let by_age = Policy {
+            max_age_days: Some(30),
+            max_mb: None,
+        };
+        assert_eq!(expired(&files, &by_age, now), vec![1]);
+
+        let by_size = Policy {
+            max_age_days: Some(30),
+            max_mb: Some(6),
+        };
+        assert_eq!(expired(&files, &by_size, now), vec![1, 2]);
+
+        let forever = Policy {
+            max_age_days: None,
+            max_mb: None,
+        };
+        assert!(expired(&files, &forever, now).is_empty());
+    }
+}
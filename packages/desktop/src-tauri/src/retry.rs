@@ -0,0 +1,128 @@
+//! Retries for network calls.
+//!
+//! A dropped connection or a server that is restarting shouldn't fail a
+//! health check, a model download or a link preview outright. Requests sent
+//! through `send` are tried again after connection errors, timeouts and
+//! statuses that say "later" (408, 429, 502, 503, 504), up to the attempts
+//! of their `RetryPolicy`. Delays grow exponentially and are jittered, so
+//! clients that failed together don't come back together. Only idempotent
+//! requests are retried.
+
+use reqwest::{RequestBuilder, Response, StatusCode};
+use std::time::Duration;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RetryPolicy {
+    /// Including the first
+    pub max_attempts: u32,
+    pub base_delay: Duration,
+    pub max_delay: Duration,
+}
+
+impl RetryPolicy {
+    /// Send once
+    pub const NONE: Self = Self {
+        max_attempts: 1,
+        base_delay: Duration::ZERO,
+        max_delay: Duration::ZERO,
+    };
+    /// Health checks, which someone is waiting on
+    pub const HEALTH: Self = Self {
+        max_attempts: 3,
+        base_delay: Duration::from_millis(100),
+        max_delay: Duration::from_secs(1),
+    };
+    /// Link previews and favicons
+    pub const PREVIEW: Self = Self {
+        max_attempts: 2,
+        base_delay: Duration::from_millis(250),
+        max_delay: Duration::from_secs(2),
+    };
+    /// Model downloads, which take a while anyway
+    pub const DOWNLOAD: Self = Self {
+        max_attempts: 5,
+        base_delay: Duration::from_millis(500),
+        max_delay: Duration::from_secs(15),
+    };
+
+    /// Delay before retry `retry`, from 1: between half and all of
+    /// `base_delay * 2^(retry - 1)`, at most `max_delay`
+    pub fn delay(&self, retry: u32) -> Duration {
+        let window = self
+            .base_delay
+            .saturating_mul(1 << retry.saturating_sub(1).min(16))
+            .min(self.max_delay);
+        window / 2 + (window / 2).mul_f64(fastrand::f64())
+    }
+}
+
+/// Statuses that may well be different on the next try
+pub fn is_transient(status: StatusCode) -> bool {
+    matches!(
+        status,
+        StatusCode::REQUEST_TIMEOUT
+            | StatusCode::TOO_MANY_REQUESTS
+            | StatusCode::BAD_GATEWAY
+            | StatusCode::SERVICE_UNAVAILABLE
+            | StatusCode::GATEWAY_TIMEOUT
+    )
+}
+
+/// `Retry-After` in seconds; dates are ignored
+fn retry_after(response: &Response) -> Option<Duration> {
+    response
+        .headers()
+        .get(reqwest::header::RETRY_AFTER)?
+        .to_str()
+        .ok()?
+        .trim()
+        .parse()
+        .ok()
+        .map(Duration::from_secs)
+}
+
+/// Send `request`, trying again as `policy` allows. Requests that aren't
+/// idempotent, or whose body can't be sent twice, are sent once. The last
+/// response is returned whatever its status.
+pub async fn send(policy: &RetryPolicy, request: RequestBuilder) -> reqwest::Result<Response> {
+    let (client, request) = request.build_split();
+    let request = request?;
+    let idempotent = request.method().is_idempotent();
+    let mut attempt = 1;
+    loop {
+        let copy = (idempotent && attempt < policy.max_attempts)
+            .then(|| request.try_clone())
+            .flatten();
+        let Some(copy) = copy else {
+            return client.execute(request).await;
+        };
+        let delay = match client.execute(copy).await {
+            Ok(response) if is_transient(response.status()) => retry_after(&response)
+                .map(|delay| delay.min(policy.max_delay))
+                .unwrap_or_else(|| policy.delay(attempt)),
+            Err(e) if e.is_connect() || e.is_timeout() => policy.delay(attempt),
+            result => return result,
+        };
+        tokio::time::sleep(delay).await;
+        attempt += 1;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_delays_grow_and_are_capped() {
+        let policy = RetryPolicy::DOWNLOAD;
+        for _ in 0..20 {
+            let first = policy.delay(1);
+            assert!(first >= Duration::from_millis(250) && first <= Duration::from_millis(500));
+            let third = policy.delay(3);
+            assert!(third >= Duration::from_secs(1) && third <= Duration::from_secs(2));
+            let late = policy.delay(40);
+            assert!(late >= policy.max_delay / 2 && late <= policy.max_delay);
+        }
+        assert_eq!(RetryPolicy::NONE.delay(1), Duration::ZERO);
+    }
+}
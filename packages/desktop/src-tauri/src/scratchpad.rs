@@ -0,0 +1,195 @@
+//! A local scratchpad for jotting thoughts down without a server.
+//!
+//! Notes are appended to `scratchpad.jsonl` in the app's local data, one JSON
+//! object per line, with `scratchpad_append` for typed notes. The
+//! `scratchpad.dictate` action captures a spoken one: run once it starts
+//! recording, run again it transcribes locally and appends the text, so it
+//! works bound to a shortcut or in a macro. Every new note is announced with
+//! a `scratchpad:appended` event. Clearing the notes moves them to the trash,
+//! where `undo_last_operation` brings them back.
+
+use serde::{Deserialize, Serialize};
+use std::{
+    io::{Read, Seek, SeekFrom, Write},
+    path::PathBuf,
+    time::{SystemTime, UNIX_EPOCH},
+};
+use tauri::{AppHandle, Emitter, Manager, path::BaseDirectory};
+
+use crate::{error::AppError, trash};
+
+pub const SCRATCHPAD_FILE: &str = "scratchpad.jsonl";
+const MAX_NOTE_CHARS: usize = 100_000;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum NoteSource {
+    Typed,
+    Dictated,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Note {
+    pub id: String,
+    /// Milliseconds since the Unix epoch
+    pub created_at: u64,
+    pub source: NoteSource,
+    pub text: String,
+}
+
+fn scratchpad_path(app: &AppHandle) -> Result<PathBuf, String> {
+    app.path()
+        .resolve(SCRATCHPAD_FILE, BaseDirectory::AppLocalData)
+        .map_err(|e| format!("Failed to resolve scratchpad path: {}", e))
+}
+
+/// Notes in `contents`, skipping lines that don't parse, like one cut short
+/// by a crash
+fn parse_notes(contents: &str) -> Vec<Note> {
+    contents
+        .lines()
+        .filter_map(|line| serde_json::from_str(line).ok())
+        .collect()
+}
+
+/// Append `text` as a note from `source`
+pub fn append(app: &AppHandle, text: &str, source: NoteSource) -> Result<Note, AppError> {
+    let text = text.trim();
+    if text.is_empty() {
+        return Err(AppError::invalid_input("The note is empty"));
+    }
+    if text.chars().count() > MAX_NOTE_CHARS {
+        return Err(AppError::invalid_input(format!(
+            "Notes are limited to {} characters",
+            MAX_NOTE_CHARS
+        )));
+    }
+
+    let note = Note {
+        id: uuid::Uuid::new_v4().to_string(),
+        created_at: SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_millis() as u64)
+            .unwrap_or(0),
+        source,
+        text: text.to_string(),
+    };
+    let mut line =
+        serde_json::to_string(&note).map_err(|e| format!("Failed to serialize note: {}", e))?;
+    line.push('\n');
+
+    let path = scratchpad_path(app)?;
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent)
+            .map_err(|e| AppError::io(format!("Failed to create {}: {}", parent.display(), e)))?;
+    }
+    // One write per note, so concurrent appends don't interleave
+    std::fs::OpenOptions::new()
+        .create(true)
+        .read(true)
+        .append(true)
+        .open(&path)
+        .and_then(|mut file| {
+            // A write cut short leaves a partial line the note mustn't join
+            if !ends_in_newline(&mut file)? {
+                line.insert(0, '\n');
+            }
+            file.write_all(line.as_bytes())
+        })
+        .map_err(|e| AppError::io(format!("Failed to write {}: {}", path.display(), e)))?;
+
+    let _ = app.emit("scratchpad:appended", &note);
+    Ok(note)
+}
+
+/// Whether `file` is empty or ends in a newline
+fn ends_in_newline(file: &mut std::fs::File) -> std::io::Result<bool> {
+    if file.metadata()?.len() == 0 {
+        return Ok(true);
+    }
+    let mut last = [0u8];
+    file.seek(SeekFrom::End(-1))?;
+    file.read_exact(&mut last)?;
+    Ok(last[0] == b'\n')
+}
+
+/// Start dictating a note or, while recording, transcribe and append it
+pub async fn dictate(app: &AppHandle) -> Result<(), AppError> {
+    let recording = match app.try_state::<crate::stt::SharedSttState>() {
+        Some(state) => state.lock().await.get_status().is_recording,
+        None => false,
+    };
+    if !recording {
+        return crate::stt_start_recording(app.clone()).await;
+    }
+    let text = crate::stop_and_transcribe(app).await?;
+    if !text.trim().is_empty() {
+        append(app, &text, NoteSource::Dictated)?;
+    }
+    Ok(())
+}
+
+#[tauri::command]
+pub fn scratchpad_append(app: AppHandle, text: String) -> Result<Note, AppError> {
+    append(&app, &text, NoteSource::Typed)
+}
+
+/// All notes, oldest first
+#[tauri::command]
+pub fn scratchpad_get(app: AppHandle) -> Result<Vec<Note>, AppError> {
+    let path = scratchpad_path(&app)?;
+    match std::fs::read_to_string(&path) {
+        Ok(contents) => Ok(parse_notes(&contents)),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(Vec::new()),
+        Err(e) => Err(AppError::io(format!(
+            "Failed to read {}: {}",
+            path.display(),
+            e
+        ))),
+    }
+}
+
+/// Move the notes to the trash, returning the id of the operation that
+/// restores them, if there were any
+#[tauri::command]
+pub fn scratchpad_clear(app: AppHandle) -> Result<Option<u64>, AppError> {
+    let path = scratchpad_path(&app)?;
+    let mut trash = trash::begin(&app, "Clear scratchpad")?;
+    trash.remove(&path)?;
+    Ok(trash.commit()?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_notes_skips_partial_lines() {
+        let contents = concat!(
+            r#"{"id":"a","createdAt":1,"source":"typed","text":"first"}"#,
+            "\n",
+            r#"{"id":"b","createdAt":2,"source":"dictated","text":"second"}"#,
+            "\n",
+            r#"{"id":"c","createdAt":3,"sou"#,
+        );
+        let notes = parse_notes(contents);
+        assert_eq!(notes.len(), 2);
+        assert_eq!(notes[1].source, NoteSource::Dictated);
+        assert_eq!(notes[1].text, "second");
+    }
+
+    #[test]
+    fn test_ends_in_newline() {
+        let path = std::env::temp_dir().join(format!("scratchpad-{}", uuid::Uuid::new_v4()));
+        let open = |contents: &str| {
+            std::fs::write(&path, contents).unwrap();
+            let mut file = std::fs::File::open(&path).unwrap();
+            ends_in_newline(&mut file).unwrap()
+        };
+        assert!(open(""));
+        assert!(open("{}\n"));
+        assert!(!open("{}\n{\"id\""));
+        std::fs::remove_file(&path).unwrap();
+    }
+}
@@ -0,0 +1,219 @@
+//! Telling when security software stopped the sidecar.
+//!
+//! Antivirus products and Gatekeeper don't say when they stop a binary: the
+//! sidecar is deleted, can't be spawned or dies at once, and startup failed
+//! with a bare exit code. `explain` looks for their signs — the exit codes
+//! and spawn errors they leave, the quarantine attribute macOS puts on
+//! downloaded files and Defender's detections of the sidecar — and when it
+//! finds one, tells the user what blocked the sidecar and how to let it run.
+//! The last two are only looked at when the sidecar is missing or ended the
+//! way a blocked one does.
+
+use std::path::Path;
+use tauri::AppHandle;
+
+use crate::i18n;
+
+/// NTSTATUS codes of a process Windows refused to run: access denied, virus
+/// infected, virus deleted
+const BLOCKED_STATUSES: &[u32] = &[0xC000_0022, 0xC000_0906, 0xC000_0907];
+/// Exit codes of a shell whose command couldn't run (126) or was killed
+/// (128 + SIGKILL)
+const BLOCKED_SHELL_CODES: &[i32] = &[126, 137];
+const SIGKILL: i32 = 9;
+/// OS errors of a spawn that was refused: access denied, virus infected,
+/// virus deleted
+#[cfg(windows)]
+const BLOCKED_SPAWN_ERRORS: &[i32] = &[5, 225, 226];
+/// OS errors of a spawn that was refused: operation not permitted,
+/// permission denied
+#[cfg(not(windows))]
+const BLOCKED_SPAWN_ERRORS: &[i32] = &[1, 13];
+
+#[cfg(windows)]
+const DEFENDER_LOG: &str = "Microsoft-Windows-Windows Defender/Operational";
+/// Malware detected, action taken, action failed, remediation failed
+#[cfg(windows)]
+const DEFENDER_EVENTS: &str = "1116,1117,1118,1119";
+/// A quarantine at install time leaves the sidecar missing for good
+#[cfg(windows)]
+const DEFENDER_LOOKBACK_DAYS: u32 = 30;
+/// Quarantine flag macOS sets once the user allowed the file to open
+const QUARANTINE_USER_APPROVED: u32 = 0x40;
+
+/// What stopped the sidecar
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Blocker {
+    Defender,
+    Gatekeeper,
+    /// Some security software, going by how the sidecar ended
+    Unknown,
+}
+
+/// How the sidecar ended
+#[derive(Debug, Clone, Default)]
+pub struct Exit {
+    pub code: Option<i32>,
+    pub signal: Option<i32>,
+    /// Why it couldn't be spawned
+    pub spawn_error: Option<String>,
+}
+
+/// Whether the sidecar ended the way a blocked one does
+fn blocked_exit(exit: &Exit) -> bool {
+    if let Some(error) = &exit.spawn_error {
+        return BLOCKED_SPAWN_ERRORS
+            .iter()
+            .any(|code| error.contains(&format!("(os error {})", code)));
+    }
+    exit.code.is_some_and(|code| {
+        BLOCKED_STATUSES.contains(&(code as u32)) || BLOCKED_SHELL_CODES.contains(&code)
+    }) || exit.signal == Some(SIGKILL)
+}
+
+/// Whether the quarantine attribute `value`, as `flags;time;agent;id`, still
+/// has Gatekeeper check the file
+#[cfg_attr(not(target_os = "macos"), allow(dead_code))]
+fn quarantine_blocks(value: &str) -> bool {
+    value
+        .split(';')
+        .next()
+        .and_then(|flags| u32::from_str_radix(flags.trim(), 16).ok())
+        .is_none_or(|flags| flags & QUARANTINE_USER_APPROVED == 0)
+}
+
+/// Whether macOS marked `sidecar` as downloaded, and the user didn't allow
+/// it since, so Gatekeeper checks it
+#[cfg(target_os = "macos")]
+fn quarantined(sidecar: &Path) -> bool {
+    std::process::Command::new("xattr")
+        .args(["-p", "com.apple.quarantine"])
+        .arg(sidecar)
+        .output()
+        .is_ok_and(|output| {
+            output.status.success() && quarantine_blocks(&String::from_utf8_lossy(&output.stdout))
+        })
+}
+
+#[cfg(not(target_os = "macos"))]
+fn quarantined(_sidecar: &Path) -> bool {
+    false
+}
+
+/// Whether Defender logged a detection of `sidecar`
+#[cfg(windows)]
+fn defender_detected(sidecar: &Path) -> bool {
+    use std::os::windows::process::CommandExt;
+
+    const CREATE_NO_WINDOW: u32 = 0x0800_0000;
+
+    let Some(name) = sidecar.file_name() else {
+        return false;
+    };
+    let script = format!(
+        "Get-WinEvent -FilterHashtable @{{ LogName = '{}'; Id = {}; StartTime = (Get-Date).AddDays(-{}) }} \
+         -ErrorAction SilentlyContinue | ForEach-Object {{ $_.Message }}",
+        DEFENDER_LOG, DEFENDER_EVENTS, DEFENDER_LOOKBACK_DAYS
+    );
+    std::process::Command::new("powershell")
+        .args(["-NoProfile", "-NonInteractive", "-Command", &script])
+        .creation_flags(CREATE_NO_WINDOW)
+        .output()
+        .is_ok_and(|output| {
+            String::from_utf8_lossy(&output.stdout)
+                .to_lowercase()
+                .contains(&name.to_string_lossy().to_lowercase())
+        })
+}
+
+#[cfg(not(windows))]
+fn defender_detected(_sidecar: &Path) -> bool {
+    false
+}
+
+/// What blocked `sidecar`, if anything seems to have
+pub fn diagnose(sidecar: &Path, exit: &Exit) -> Option<Blocker> {
+    let blocked = blocked_exit(exit);
+    // An old detection or a leftover attribute doesn't explain a sidecar that
+    // ran and failed on its own
+    if !blocked && sidecar.exists() {
+        return None;
+    }
+    if defender_detected(sidecar) {
+        return Some(Blocker::Defender);
+    }
+    if quarantined(sidecar) {
+        return Some(Blocker::Gatekeeper);
+    }
+    if !blocked {
+        return None;
+    }
+    // Gatekeeper and XProtect kill a binary they reject at launch
+    if cfg!(target_os = "macos") {
+        Some(Blocker::Gatekeeper)
+    } else {
+        Some(Blocker::Unknown)
+    }
+}
+
+/// What to tell the user `blocker` stopped `sidecar`, with the steps to let
+/// it run
+pub fn message(app: &AppHandle, blocker: Blocker, sidecar: &Path) -> String {
+    let key = match blocker {
+        Blocker::Defender => "error.sidecarBlocked.defender",
+        Blocker::Gatekeeper => "error.sidecarBlocked.gatekeeper",
+        Blocker::Unknown => "error.sidecarBlocked.unknown",
+    };
+    i18n::t_with(app, key, &[("path", &sidecar.display().to_string())])
+}
+
+/// `error`, or what blocked `sidecar` when security software seems to have
+pub async fn explain(app: &AppHandle, sidecar: &Path, exit: Exit, error: String) -> String {
+    let path = sidecar.to_path_buf();
+    // The Defender query takes a moment
+    let blocker = tauri::async_runtime::spawn_blocking(move || diagnose(&path, &exit))
+        .await
+        .ok()
+        .flatten();
+    match blocker {
+        Some(blocker) => {
+            eprintln!("Sidecar blocked by {:?}: {}", blocker, error);
+            message(app, blocker, sidecar)
+        }
+        None => error,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_blocked_exit() {
+        let exit = |code, signal| Exit {
+            code,
+            signal,
+            spawn_error: None,
+        };
+        assert!(blocked_exit(&exit(Some(0xC000_0906_u32 as i32), None)));
+        assert!(blocked_exit(&exit(Some(137), None)));
+        assert!(blocked_exit(&exit(None, Some(SIGKILL))));
+        assert!(!blocked_exit(&exit(Some(1), None)));
+        assert!(!blocked_exit(&exit(None, None)));
+
+        let spawn_error = |error: &str| Exit {
+            spawn_error: Some(error.to_string()),
+            ..Exit::default()
+        };
+        let refused = format!("Access is denied. (os error {})", BLOCKED_SPAWN_ERRORS[0]);
+        assert!(blocked_exit(&spawn_error(&refused)));
+        assert!(!blocked_exit(&spawn_error("No such file (os error 2)")));
+    }
+
+    #[test]
+    fn test_quarantine_blocks() {
+        assert!(quarantine_blocks("0083;6512a0c4;Safari;F2B7A6E1"));
+        assert!(!quarantine_blocks("00c3;6512a0c4;Safari;F2B7A6E1"));
+        assert!(quarantine_blocks("garbage"));
+    }
+}
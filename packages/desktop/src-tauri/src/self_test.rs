@@ -0,0 +1,195 @@
+//! Quick checks that the app works where it is installed.
+//!
+//! `run_self_test` runs fast internal checks and reports each as passed,
+//! failed or skipped with a detail, for support to ask users for and for
+//! smoke tests of packaged builds. With `sentAt` (ms since the Unix epoch,
+//! when the frontend sent the call) the IPC latency is checked too. Started
+//! with `--self-test`, the app runs the checks once it's up, prints the
+//! report as JSON and exits with status 1 if a check failed.
+
+use serde::Serialize;
+use serde_json::Value;
+use std::time::{Instant, SystemTime, UNIX_EPOCH};
+use tauri::AppHandle;
+use tauri_plugin_clipboard_manager::ClipboardExt;
+use tauri_plugin_notification::{NotificationExt, PermissionState};
+use tauri_plugin_store::StoreExt;
+
+use crate::{SETTINGS_STORE, atomic_store, focus};
+
+const SELF_TEST_FLAG: &str = "--self-test";
+const SELF_TEST_STORE: &str = "self-test.json";
+/// Above this an IPC call is slow enough to notice
+const MAX_IPC_LATENCY_MS: f64 = 250.0;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub enum CheckStatus {
+    Passed,
+    Failed,
+    Skipped,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CheckResult {
+    pub name: &'static str,
+    pub status: CheckStatus,
+    pub duration_ms: u64,
+    pub detail: String,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SelfTestReport {
+    /// Whether no check failed
+    pub passed: bool,
+    pub version: String,
+    pub platform: String,
+    pub checks: Vec<CheckResult>,
+}
+
+/// Outcome of a check: the status and what was found
+type Outcome = (CheckStatus, String);
+
+fn run_check(name: &'static str, check: impl FnOnce() -> Result<Outcome, String>) -> CheckResult {
+    let started = Instant::now();
+    let (status, detail) = check().unwrap_or_else(|e| (CheckStatus::Failed, e));
+    CheckResult {
+        name,
+        status,
+        duration_ms: started.elapsed().as_millis() as u64,
+        detail,
+    }
+}
+
+fn check_store(app: &AppHandle) -> Result<Outcome, String> {
+    app.store(SETTINGS_STORE)
+        .map_err(|e| format!("Failed to open settings store: {}", e))?;
+
+    let store = app
+        .store(SELF_TEST_STORE)
+        .map_err(|e| format!("Failed to open {}: {}", SELF_TEST_STORE, e))?;
+    let probe = Value::String(uuid::Uuid::new_v4().to_string());
+    store.set("probe", probe.clone());
+    atomic_store::save(app, SELF_TEST_STORE)
+        .map_err(|e| format!("Failed to save {}: {}", SELF_TEST_STORE, e))?;
+    store
+        .reload()
+        .map_err(|e| format!("Failed to read {}: {}", SELF_TEST_STORE, e))?;
+    let read = store.get("probe");
+    store.delete("probe");
+    let _ = atomic_store::save(app, SELF_TEST_STORE);
+
+    if read != Some(probe) {
+        return Err(format!("{} read back a different value", SELF_TEST_STORE));
+    }
+    Ok((CheckStatus::Passed, "Written and read back".to_string()))
+}
+
+fn check_ipc(sent_at: Option<f64>) -> Result<Outcome, String> {
+    let Some(sent_at) = sent_at else {
+        return Ok((
+            CheckStatus::Skipped,
+            "Not called from the frontend".to_string(),
+        ));
+    };
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs_f64() * 1000.0)
+        .unwrap_or(0.0);
+    let latency = (now - sent_at).max(0.0);
+    let detail = format!("{:.1} ms from the frontend", latency);
+    if latency > MAX_IPC_LATENCY_MS {
+        return Ok((CheckStatus::Failed, detail));
+    }
+    Ok((CheckStatus::Passed, detail))
+}
+
+fn check_temp_dir() -> Result<Outcome, String> {
+    let dir = std::env::temp_dir().join(format!("aura-self-test-{}", uuid::Uuid::new_v4()));
+    std::fs::create_dir_all(&dir)
+        .map_err(|e| format!("Failed to create {}: {}", dir.display(), e))?;
+    let file = dir.join("probe");
+    let result = std::fs::write(&file, b"probe")
+        .and_then(|_| std::fs::read(&file))
+        .map_err(|e| format!("Failed to use {}: {}", dir.display(), e));
+    let _ = std::fs::remove_dir_all(&dir);
+
+    if result? != b"probe" {
+        return Err(format!("{} read back different contents", file.display()));
+    }
+    Ok((CheckStatus::Passed, dir.display().to_string()))
+}
+
+/// Reads only, so whatever the user copied stays
+fn check_clipboard(app: &AppHandle) -> Result<Outcome, String> {
+    match app.clipboard().read_text() {
+        Ok(text) => Ok((
+            CheckStatus::Passed,
+            format!("{} characters of text", text.chars().count()),
+        )),
+        // An empty clipboard, or one holding an image
+        Err(e) if e.to_string().contains("not available") => {
+            Ok((CheckStatus::Passed, "No text on the clipboard".to_string()))
+        }
+        Err(e) => Err(format!("Failed to read the clipboard: {}", e)),
+    }
+}
+
+fn check_notifications(app: &AppHandle) -> Result<Outcome, String> {
+    let state = app
+        .notification()
+        .permission_state()
+        .map_err(|e| format!("Failed to read notification permission: {}", e))?;
+    let mut outcome = match state {
+        PermissionState::Granted => (CheckStatus::Passed, "Allowed".to_string()),
+        PermissionState::Denied => (CheckStatus::Failed, "Denied".to_string()),
+        _ => (CheckStatus::Skipped, "Not asked yet".to_string()),
+    };
+    if focus::is_active(app) {
+        outcome.1.push_str(", held while Do Not Disturb is on");
+    }
+    Ok(outcome)
+}
+
+pub fn run(app: &AppHandle, sent_at: Option<f64>) -> SelfTestReport {
+    let checks = vec![
+        run_check("store", || check_store(app)),
+        run_check("ipc", || check_ipc(sent_at)),
+        run_check("tempDir", check_temp_dir),
+        run_check("clipboard", || check_clipboard(app)),
+        run_check("notifications", || check_notifications(app)),
+    ];
+    SelfTestReport {
+        passed: checks
+            .iter()
+            .all(|check| check.status != CheckStatus::Failed),
+        version: app.package_info().version.to_string(),
+        platform: format!("{} {}", std::env::consts::OS, std::env::consts::ARCH),
+        checks,
+    }
+}
+
+/// Whether the app was started with `--self-test`
+pub fn requested() -> bool {
+    std::env::args().any(|arg| arg == SELF_TEST_FLAG)
+}
+
+/// Run the checks for `--self-test`, print the report and exit
+pub fn run_and_exit(app: &AppHandle) {
+    let app = app.clone();
+    tauri::async_runtime::spawn(async move {
+        let report = run(&app, None);
+        match serde_json::to_string_pretty(&report) {
+            Ok(json) => println!("{}", json),
+            Err(e) => eprintln!("Failed to serialize self-test report: {}", e),
+        }
+        app.exit(if report.passed { 0 } else { 1 });
+    });
+}
+
+#[tauri::command]
+pub async fn run_self_test(app: AppHandle, sent_at: Option<f64>) -> SelfTestReport {
+    run(&app, sent_at)
+}
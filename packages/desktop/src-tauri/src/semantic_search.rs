@@ -0,0 +1,448 @@
+//! Semantic search over dictated transcripts and exported conversations.
+//!
+//! Documents are embedded with the local embedding model and stored together
+//! with an HNSW graph in a single JSON file under AppLocalData, so the index
+//! survives restarts without re-embedding anything. The file is replaced
+//! atomically on each change, and one that doesn't parse is set aside rather
+//! than overwritten, so the embeddings in it aren't lost for good.
+
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::HashSet,
+    path::{Path, PathBuf},
+    sync::Mutex,
+    time::{SystemTime, UNIX_EPOCH},
+};
+use tauri::{AppHandle, Manager, ipc::Response, path::BaseDirectory};
+
+use crate::{
+    atomic_store, embeddings,
+    error::AppError,
+    hnsw::Hnsw,
+    ipc_payload::{self, Delivery},
+    panic_guard, perf,
+};
+
+const INDEX_FILE: &str = "semantic-index.json";
+/// Extension of an index file that failed to parse, once set aside
+const CORRUPT_EXTENSION: &str = "corrupt";
+const DEFAULT_LIMIT: usize = 10;
+/// Candidate list size used while searching; larger is slower but more accurate
+const SEARCH_EF: usize = 64;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum DocumentScope {
+    Transcript,
+    Conversation,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct IndexedDocument {
+    id: String,
+    scope: DocumentScope,
+    title: Option<String>,
+    text: String,
+    created_at: u64,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct NewDocument {
+    pub id: String,
+    pub scope: DocumentScope,
+    pub title: Option<String>,
+    pub text: String,
+    pub created_at: Option<u64>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SearchHit {
+    pub id: String,
+    pub scope: DocumentScope,
+    pub title: Option<String>,
+    pub text: String,
+    pub created_at: u64,
+    /// Cosine similarity in `[-1, 1]`, higher is more relevant
+    pub score: f32,
+}
+
+/// Documents and their HNSW graph; node ids are indices into `documents`
+#[derive(Default, Serialize, Deserialize)]
+struct SemanticIndex {
+    documents: Vec<IndexedDocument>,
+    graph: Hnsw,
+    /// Counts changes, so an older snapshot never overwrites a newer one
+    #[serde(skip)]
+    generation: u64,
+}
+
+/// The serialized index as of a change
+struct Snapshot {
+    generation: u64,
+    bytes: Vec<u8>,
+}
+
+impl SemanticIndex {
+    /// Note a change, returning the index as it now is
+    fn snapshot(&mut self) -> Result<Snapshot, String> {
+        self.generation += 1;
+        let bytes = serde_json::to_vec(self)
+            .map_err(|e| format!("Failed to serialize semantic index: {}", e))?;
+        Ok(Snapshot {
+            generation: self.generation,
+            bytes,
+        })
+    }
+
+    /// An empty index that carries on counting changes from this one
+    fn cleared(&self) -> Self {
+        Self {
+            generation: self.generation,
+            ..Self::default()
+        }
+    }
+}
+
+pub struct SemanticIndexState {
+    index: Mutex<SemanticIndex>,
+    /// Generation of the snapshot last written; writes take turns on it
+    written: Mutex<u64>,
+    path: PathBuf,
+}
+
+/// Read the index at `path`. One that doesn't parse is set aside, so the
+/// next write doesn't destroy what may yet be rescued from it.
+fn read_index(path: &Path) -> SemanticIndex {
+    let Ok(content) = std::fs::read(path) else {
+        return SemanticIndex::default();
+    };
+    match serde_json::from_slice(&content) {
+        Ok(index) => index,
+        Err(e) => {
+            let aside = corrupt_path(path);
+            match std::fs::rename(path, &aside) {
+                Ok(()) => eprintln!(
+                    "Failed to parse semantic index ({}), moved it to {} and starting fresh",
+                    e,
+                    aside.display()
+                ),
+                Err(rename_error) => eprintln!(
+                    "Failed to parse semantic index ({}) or set it aside: {}",
+                    e, rename_error
+                ),
+            }
+            SemanticIndex::default()
+        }
+    }
+}
+
+fn corrupt_path(path: &Path) -> PathBuf {
+    let mut name = path.as_os_str().to_os_string();
+    name.push(".");
+    name.push(CORRUPT_EXTENSION);
+    PathBuf::from(name)
+}
+
+impl SemanticIndexState {
+    fn load(path: PathBuf) -> Self {
+        Self {
+            index: Mutex::new(read_index(&path)),
+            written: Mutex::new(0),
+            path,
+        }
+    }
+
+    /// Write `snapshot`, unless a later one was written already. This blocks
+    /// on the disk, so async callers run it with `spawn_blocking`.
+    fn write(&self, snapshot: Snapshot) -> Result<(), String> {
+        let mut written = self
+            .written
+            .lock()
+            .map_err(|e| format!("Lock error: {}", e))?;
+        if *written >= snapshot.generation {
+            return Ok(());
+        }
+        atomic_store::write_atomic(&self.path, &snapshot.bytes)
+            .map_err(|e| format!("Failed to write semantic index: {}", e))?;
+        *written = snapshot.generation;
+        Ok(())
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Where an index file that failed to parse was set aside
+    pub fn corrupt_path(&self) -> PathBuf {
+        corrupt_path(&self.path)
+    }
+
+    /// Drop every indexed document from memory, after the file was removed
+    pub fn reset(&self) -> Result<(), String> {
+        let mut index = self
+            .index
+            .lock()
+            .map_err(|e| format!("Lock error: {}", e))?;
+        *index = index.cleared();
+        Ok(())
+    }
+
+    /// Remove transcripts created before `cutoff` (ms since the Unix epoch),
+    /// then the oldest until their text fits in `max_bytes`. Returns how many
+    /// were removed.
+    pub fn prune_transcripts(
+        &self,
+        cutoff: Option<u64>,
+        max_bytes: Option<u64>,
+    ) -> Result<usize, String> {
+        let mut index = self
+            .index
+            .lock()
+            .map_err(|e| format!("Lock error: {}", e))?;
+        let mut transcripts: Vec<(u64, usize)> = index
+            .documents
+            .iter()
+            .enumerate()
+            .filter(|(_, d)| d.scope == DocumentScope::Transcript)
+            .map(|(i, d)| (d.created_at, i))
+            .collect();
+        transcripts.sort();
+
+        let mut bytes: u64 = index
+            .documents
+            .iter()
+            .filter(|d| d.scope == DocumentScope::Transcript)
+            .map(|d| d.text.len() as u64)
+            .sum();
+        let mut removed = HashSet::new();
+        for (created_at, i) in transcripts {
+            let expired = cutoff.is_some_and(|cutoff| created_at < cutoff);
+            let over = max_bytes.is_some_and(|max| bytes > max);
+            if !expired && !over {
+                break;
+            }
+            bytes = bytes.saturating_sub(index.documents[i].text.len() as u64);
+            removed.insert(i);
+        }
+        if removed.is_empty() {
+            return Ok(0);
+        }
+
+        let graph = index.graph.retain(|id| !removed.contains(&(id as usize)));
+        let mut position = 0;
+        index.documents.retain(|_| {
+            position += 1;
+            !removed.contains(&(position - 1))
+        });
+        index.graph = graph;
+        let snapshot = index.snapshot()?;
+        drop(index);
+        self.write(snapshot)?;
+        Ok(removed.len())
+    }
+
+    /// Load the index from disk again, after the file was restored
+    pub fn reload(&self) -> Result<(), String> {
+        let loaded = read_index(&self.path);
+        let mut index = self
+            .index
+            .lock()
+            .map_err(|e| format!("Lock error: {}", e))?;
+        *index = SemanticIndex {
+            generation: index.generation,
+            ..loaded
+        };
+        Ok(())
+    }
+}
+
+pub fn init_semantic_index_state(app: &AppHandle) -> SemanticIndexState {
+    let path = app
+        .path()
+        .resolve(INDEX_FILE, BaseDirectory::AppLocalData)
+        .expect("Failed to resolve semantic index path");
+    SemanticIndexState::load(path)
+}
+
+fn now_millis() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_millis() as u64)
+        .unwrap_or(0)
+}
+
+/// Embed and add documents, skipping ids that are already indexed
+pub async fn index_documents(
+    app: &AppHandle,
+    documents: Vec<NewDocument>,
+) -> Result<usize, String> {
+    let state = app
+        .try_state::<SemanticIndexState>()
+        .ok_or("Semantic index state not found")?;
+
+    let documents: Vec<NewDocument> = {
+        let index = state
+            .index
+            .lock()
+            .map_err(|e| format!("Lock error: {}", e))?;
+        let existing: HashSet<&str> = index.documents.iter().map(|d| d.id.as_str()).collect();
+        let mut seen = HashSet::new();
+        documents
+            .into_iter()
+            .filter(|d| !d.text.trim().is_empty())
+            .filter(|d| !existing.contains(d.id.as_str()) && seen.insert(d.id.clone()))
+            .collect()
+    };
+    if documents.is_empty() {
+        return Ok(0);
+    }
+
+    let texts = documents.iter().map(|d| d.text.clone()).collect();
+    let vectors = embeddings::embed(app, texts).await?;
+
+    let mut added = 0;
+    let snapshot = {
+        let mut index = state
+            .index
+            .lock()
+            .map_err(|e| format!("Lock error: {}", e))?;
+        // Another call may have indexed the same documents while these embedded
+        let existing: HashSet<String> = index.documents.iter().map(|d| d.id.clone()).collect();
+        for (document, vector) in documents.into_iter().zip(vectors) {
+            if existing.contains(&document.id) {
+                continue;
+            }
+            added += 1;
+            index.graph.insert(vector);
+            index.documents.push(IndexedDocument {
+                id: document.id,
+                scope: document.scope,
+                title: document.title,
+                text: document.text,
+                created_at: document.created_at.unwrap_or_else(now_millis),
+            });
+        }
+        if added == 0 {
+            return Ok(0);
+        }
+        index.snapshot()?
+    };
+    let app = app.clone();
+    tauri::async_runtime::spawn_blocking(move || {
+        app.try_state::<SemanticIndexState>()
+            .ok_or("Semantic index state not found")?
+            .write(snapshot)
+    })
+    .await
+    .map_err(panic_guard::join_error)??;
+
+    Ok(added)
+}
+
+/// Add a finished dictation to the index if the embedding model is available
+pub fn index_transcript_in_background(app: &AppHandle, text: &str) {
+    if text.trim().is_empty() {
+        return;
+    }
+
+    let app = app.clone();
+    let created_at = now_millis();
+    let document = NewDocument {
+        id: format!("transcript-{}", uuid::Uuid::new_v4()),
+        scope: DocumentScope::Transcript,
+        title: None,
+        text: text.to_string(),
+        created_at: Some(created_at),
+    };
+    tauri::async_runtime::spawn(async move {
+        if let Err(e) = index_documents(&app, vec![document]).await {
+            println!("Skipping transcript indexing: {e}");
+        }
+    });
+}
+
+#[tauri::command]
+pub async fn semantic_index_documents(
+    app: AppHandle,
+    documents: Vec<NewDocument>,
+) -> Result<usize, AppError> {
+    Ok(index_documents(&app, documents).await?)
+}
+
+async fn search(
+    app: &AppHandle,
+    query: String,
+    scope: Option<DocumentScope>,
+    limit: Option<usize>,
+) -> Result<Vec<SearchHit>, String> {
+    let limit = limit.unwrap_or(DEFAULT_LIMIT).max(1);
+
+    let state = app
+        .try_state::<SemanticIndexState>()
+        .ok_or("Semantic index state not found")?;
+    // Nothing to search, so don't pay for embedding the query
+    if state
+        .index
+        .lock()
+        .map_err(|e| format!("Lock error: {}", e))?
+        .graph
+        .is_empty()
+    {
+        return Ok(Vec::new());
+    }
+
+    let query_vector = embeddings::embed(app, vec![query])
+        .await?
+        .pop()
+        .ok_or("Failed to embed query")?;
+
+    let index = state
+        .index
+        .lock()
+        .map_err(|e| format!("Lock error: {}", e))?;
+
+    // Over-fetch when filtering so the scope filter still leaves enough hits
+    let k = if scope.is_some() {
+        (limit * 8).min(index.graph.len())
+    } else {
+        limit
+    };
+    let hits = index
+        .graph
+        .search(&query_vector, k, SEARCH_EF.max(k))
+        .into_iter()
+        .filter_map(|(id, distance)| {
+            let document = index.documents.get(id as usize)?;
+            if scope.is_some_and(|scope| scope != document.scope) {
+                return None;
+            }
+            Some(SearchHit {
+                id: document.id.clone(),
+                scope: document.scope,
+                title: document.title.clone(),
+                text: document.text.clone(),
+                created_at: document.created_at,
+                score: 1.0 - distance,
+            })
+        })
+        .take(limit)
+        .collect();
+
+    Ok(hits)
+}
+
+#[tauri::command]
+pub async fn semantic_search(
+    app: AppHandle,
+    query: String,
+    scope: Option<DocumentScope>,
+    limit: Option<usize>,
+    delivery: Option<Delivery>,
+) -> Result<Response, AppError> {
+    let _span = perf::span(&app, "search", "semantic_search");
+    let hits = search(&app, query, scope, limit).await?;
+    Ok(ipc_payload::deliver(&hits, delivery)?)
+}
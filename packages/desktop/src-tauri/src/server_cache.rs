@@ -0,0 +1,355 @@
+//! Read-through cache of the server's read-only API responses.
+//!
+//! Session lists, file trees and the like are slow to come from a remote
+//! server and unavailable offline. `server_cache_get` answers from the disk
+//! cache at once when it can, without waiting for the server to be up, and
+//! revalidates in the background with the ETag the server sent: when the
+//! response changed, `server-cache:updated` carries the new one. Without a
+//! cached response it waits for the server. A response kept because the
+//! server could not be reached comes back with `stale` set.
+//!
+//! Only GET endpoints that don't change anything are cached, by the origin
+//! of the server and the path with its query. The cache is trimmed to
+//! `MAX_CACHE_BYTES`, oldest first. `purge_server_cache` empties it.
+
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::{
+    collections::HashSet,
+    path::{Path, PathBuf},
+    sync::Mutex,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+use tauri::{AppHandle, Emitter, Manager, path::BaseDirectory};
+
+use crate::{error::AppError, url_origin};
+
+pub(crate) const CACHE_DIR: &str = "server-cache";
+/// Origin of the server last answered, for answering before it is up
+pub(crate) const LAST_ORIGIN_FILE: &str = "origin";
+const MAX_CACHE_BYTES: u64 = 64 * 1024 * 1024;
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(30);
+/// First segments of the paths that are cached
+const CACHEABLE: &[&str] = &[
+    "agent", "config", "file", "path", "project", "provider", "session",
+];
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Entry {
+    origin: String,
+    path: String,
+    etag: Option<String>,
+    last_modified: Option<String>,
+    fetched_at: u64,
+    body: Value,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CachedResponse {
+    pub path: String,
+    pub body: Value,
+    /// When the server last confirmed the response, in seconds since the
+    /// Unix epoch
+    pub fetched_at: u64,
+    /// Not confirmed by the server this time, which is being asked or could
+    /// not be reached
+    pub stale: bool,
+}
+
+/// Keys being revalidated, so repeated requests don't pile up
+#[derive(Default)]
+pub struct ServerCacheState {
+    revalidating: Mutex<HashSet<String>>,
+}
+
+fn now_secs() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0)
+}
+
+/// Whether GET `path` is safe to cache: it must read, not stream or act
+fn is_cacheable(path: &str) -> bool {
+    let Some(rest) = path.strip_prefix('/') else {
+        return false;
+    };
+    let segment = rest.split(['/', '?']).next().unwrap_or_default();
+    CACHEABLE.contains(&segment) && !rest.contains("..") && !path.contains("/event")
+}
+
+fn cache_name(origin: &str, path: &str) -> String {
+    blake3::hash(format!("{}\n{}", origin, path).as_bytes())
+        .to_hex()
+        .to_string()
+}
+
+fn cache_dir(app: &AppHandle) -> Result<PathBuf, String> {
+    app.path()
+        .resolve(CACHE_DIR, BaseDirectory::AppCache)
+        .map_err(|e| format!("Failed to resolve server cache path: {}", e))
+}
+
+fn read_cached(dir: &Path, origin: &str, path: &str) -> Option<Entry> {
+    let name = cache_name(origin, path);
+    let contents = std::fs::read_to_string(dir.join(format!("{}.json", name))).ok()?;
+    serde_json::from_str(&contents).ok()
+}
+
+fn write_cached(dir: &Path, entry: &Entry) -> Result<(), String> {
+    std::fs::create_dir_all(dir)
+        .map_err(|e| format!("Failed to create {}: {}", dir.display(), e))?;
+    let contents = serde_json::to_string(entry)
+        .map_err(|e| format!("Failed to serialize cached response: {}", e))?;
+    let path = dir.join(format!("{}.json", cache_name(&entry.origin, &entry.path)));
+    // Written aside and moved in place, so a reader never sees half of it
+    let partial = path.with_extension("json.partial");
+    std::fs::write(&partial, contents)
+        .and_then(|_| std::fs::rename(&partial, &path))
+        .map_err(|e| format!("Failed to write cached response: {}", e))?;
+    std::fs::write(dir.join(LAST_ORIGIN_FILE), &entry.origin)
+        .map_err(|e| format!("Failed to write cached response: {}", e))
+}
+
+/// Remove the least recently written responses past `MAX_CACHE_BYTES`
+fn trim(dir: &Path) {
+    let Ok(entries) = std::fs::read_dir(dir) else {
+        return;
+    };
+    let mut responses: Vec<(SystemTime, u64, PathBuf)> = entries
+        .flatten()
+        .filter(|entry| entry.path().extension().is_some_and(|ext| ext == "json"))
+        .filter_map(|entry| {
+            let metadata = entry.metadata().ok()?;
+            Some((metadata.modified().ok()?, metadata.len(), entry.path()))
+        })
+        .collect();
+    let mut total: u64 = responses.iter().map(|(_, size, _)| size).sum();
+    responses.sort();
+    for (_, size, path) in responses {
+        if total <= MAX_CACHE_BYTES {
+            break;
+        }
+        if std::fs::remove_file(&path).is_ok() {
+            total = total.saturating_sub(size);
+        }
+    }
+}
+
+fn header(response: &reqwest::Response, name: reqwest::header::HeaderName) -> Option<String> {
+    response
+        .headers()
+        .get(name)
+        .and_then(|value| value.to_str().ok())
+        .map(String::from)
+}
+
+/// GET `path` from the connected server, or `None` when `cached` is still
+/// current
+async fn fetch(
+    app: &AppHandle,
+    path: &str,
+    cached: Option<&Entry>,
+) -> Result<Option<Entry>, AppError> {
+    let server = crate::connected_server(app).await?;
+    let url = reqwest::Url::parse(&server.url)
+        .map_err(|e| format!("Invalid server URL {}: {}", server.url, e))?;
+    let origin = url_origin(&url);
+    let endpoint = url
+        .join(path)
+        .map_err(|e| AppError::invalid_input(format!("Invalid server path {}: {}", path, e)))?;
+    let client = crate::server_client(app, &url, REQUEST_TIMEOUT)?;
+
+    let mut request = client.get(endpoint);
+    if let Some(password) = &server.password {
+        request = request.basic_auth("opencode", Some(password));
+    }
+    // Validators only apply to what this server sent
+    if let Some(cached) = cached.filter(|cached| cached.origin == origin) {
+        if let Some(etag) = &cached.etag {
+            request = request.header(reqwest::header::IF_NONE_MATCH, etag);
+        }
+        if let Some(last_modified) = &cached.last_modified {
+            request = request.header(reqwest::header::IF_MODIFIED_SINCE, last_modified);
+        }
+    }
+    let response = request
+        .send()
+        .await
+        .map_err(|e| AppError::network(format!("Request to the server failed: {}", e)))?;
+
+    if response.status() == reqwest::StatusCode::NOT_MODIFIED {
+        return Ok(None);
+    }
+    if response.status() == reqwest::StatusCode::NOT_FOUND {
+        return Err(AppError::not_found(format!(
+            "Not found on the server: {}",
+            path
+        )));
+    }
+    if !response.status().is_success() {
+        return Err(AppError::network(format!(
+            "The server refused: HTTP {}",
+            response.status()
+        )));
+    }
+    let etag = header(&response, reqwest::header::ETAG);
+    let last_modified = header(&response, reqwest::header::LAST_MODIFIED);
+    let body = response
+        .json()
+        .await
+        .map_err(|e| AppError::network(format!("Invalid response from the server: {}", e)))?;
+    Ok(Some(Entry {
+        origin,
+        path: path.to_string(),
+        etag,
+        last_modified,
+        fetched_at: now_secs(),
+        body,
+    }))
+}
+
+/// Fetch `path` and cache it, returning the response if it's new
+async fn refresh(
+    app: &AppHandle,
+    dir: &Path,
+    path: &str,
+    cached: Option<Entry>,
+) -> Result<Option<Entry>, AppError> {
+    let entry = match fetch(app, path, cached.as_ref()).await? {
+        Some(entry) => entry,
+        None => {
+            // Still current: only note that it was confirmed
+            let Some(mut entry) = cached else {
+                return Ok(None);
+            };
+            entry.fetched_at = now_secs();
+            write_cached(dir, &entry)?;
+            return Ok(None);
+        }
+    };
+    write_cached(dir, &entry)?;
+    trim(dir);
+    let changed = cached.is_none_or(|cached| cached.body != entry.body);
+    Ok(changed.then_some(entry))
+}
+
+fn revalidate_in_background(app: &AppHandle, dir: PathBuf, path: String, cached: Entry) {
+    let Some(state) = app.try_state::<ServerCacheState>() else {
+        return;
+    };
+    let key = cache_name(&cached.origin, &path);
+    let started = state
+        .revalidating
+        .lock()
+        .is_ok_and(|mut revalidating| revalidating.insert(key.clone()));
+    if !started {
+        return;
+    }
+
+    let app = app.clone();
+    tauri::async_runtime::spawn(async move {
+        match refresh(&app, &dir, &path, Some(cached)).await {
+            Ok(Some(entry)) => {
+                let _ = app.emit(
+                    "server-cache:updated",
+                    CachedResponse {
+                        path: entry.path,
+                        body: entry.body,
+                        fetched_at: entry.fetched_at,
+                        stale: false,
+                    },
+                );
+            }
+            Ok(None) => {}
+            Err(e) => eprintln!("Failed to revalidate {}: {}", path, e),
+        }
+        if let Some(state) = app.try_state::<ServerCacheState>()
+            && let Ok(mut revalidating) = state.revalidating.lock()
+        {
+            revalidating.remove(&key);
+        }
+    });
+}
+
+/// GET a read-only endpoint of the connected server, from the cache when it
+/// has the response
+#[tauri::command]
+pub async fn server_cache_get(app: AppHandle, path: String) -> Result<CachedResponse, AppError> {
+    if !is_cacheable(&path) {
+        return Err(AppError::invalid_input(format!(
+            "{} is not a cacheable server path",
+            path
+        )));
+    }
+    let dir = cache_dir(&app)?;
+    let last_origin = std::fs::read_to_string(dir.join(LAST_ORIGIN_FILE)).ok();
+    let cached = last_origin.and_then(|origin| read_cached(&dir, &origin, &path));
+
+    if let Some(cached) = cached {
+        revalidate_in_background(&app, dir, path.clone(), cached.clone());
+        return Ok(CachedResponse {
+            path,
+            body: cached.body,
+            fetched_at: cached.fetched_at,
+            stale: true,
+        });
+    }
+
+    let entry = refresh(&app, &dir, &path, None)
+        .await?
+        .ok_or("The server returned no response")?;
+    Ok(CachedResponse {
+        path,
+        body: entry.body,
+        fetched_at: entry.fetched_at,
+        stale: false,
+    })
+}
+
+/// Remove all cached server responses. Returns the bytes freed.
+#[tauri::command]
+pub fn purge_server_cache(app: AppHandle) -> Result<u64, AppError> {
+    let dir = cache_dir(&app)?;
+    let freed = std::fs::read_dir(&dir)
+        .map(|entries| {
+            entries
+                .flatten()
+                .filter_map(|entry| entry.metadata().ok())
+                .map(|metadata| metadata.len())
+                .sum()
+        })
+        .unwrap_or(0);
+    match std::fs::remove_dir_all(&dir) {
+        Ok(()) => Ok(freed),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(0),
+        Err(e) => Err(AppError::io(format!(
+            "Failed to remove {}: {}",
+            dir.display(),
+            e
+        ))),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_is_cacheable() {
+        assert!(is_cacheable("/session"));
+        assert!(is_cacheable("/session/ses_1/message"));
+        assert!(is_cacheable("/file?path=src"));
+        assert!(!is_cacheable("/event"));
+        assert!(!is_cacheable("/global/event"));
+        assert!(!is_cacheable("/tui/open-help"));
+        assert!(!is_cacheable("session"));
+        assert!(!is_cacheable("/file/../tui"));
+        assert_ne!(
+            cache_name("http://a", "/session"),
+            cache_name("http://b", "/session")
+        );
+    }
+}
@@ -0,0 +1,754 @@
+//! Full-text search across the sessions of the connected server.
+//!
+//! A background indexer lists the server's sessions every few minutes and
+//! pulls the messages of those updated since they were last indexed, so
+//! `search_sessions` answers locally and at once, however slow the server.
+//! Sessions are kept per server in a tantivy index under AppLocalData, with
+//! their text stored for snippets. Results are ranked with BM25; the last
+//! word of the query also matches as a prefix, for search as you type.
+//!
+//! `sessionIndex` in the settings turns the indexer off or changes how often
+//! it runs. `reindex_sessions` runs it now.
+
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::{
+    collections::{HashMap, HashSet},
+    path::{Path, PathBuf},
+    sync::Mutex,
+    time::Duration,
+};
+use tantivy::{
+    Index, IndexReader, IndexWriter, ReloadPolicy, TantivyDocument, Term,
+    collector::{DocSetCollector, TopDocs},
+    directory::{Directory, MmapDirectory},
+    doc,
+    query::{BooleanQuery, FuzzyTermQuery, Occur, Query, TermQuery},
+    schema::{
+        Field, IndexRecordOption, STORED, STRING, Schema, TextFieldIndexing, TextOptions,
+        Value as _,
+    },
+    tokenizer::{RemoveLongFilter, TextAnalyzer, Token, TokenStream, Tokenizer},
+};
+use tauri::{AppHandle, Emitter, Manager, path::BaseDirectory};
+use tokio::sync::Notify;
+
+use crate::{
+    app_settings,
+    error::AppError,
+    i18n, panic_guard,
+    tasks::{self, TaskKind},
+    url_origin, vault_export,
+};
+
+const INDEX_DIR: &str = "session-index";
+const MIN_INTERVAL_MINUTES: u64 = 1;
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(60);
+const DEFAULT_LIMIT: usize = 20;
+/// Characters of context on each side of the first match in a snippet
+const SNIPPET_CONTEXT: usize = 80;
+/// Name the word tokenizer is registered under
+const TOKENIZER: &str = "words";
+/// Longer words, as in encoded data, aren't indexed
+const MAX_WORD_BYTES: usize = 64;
+/// Tantivy's minimum for a writer with one thread
+const WRITER_MEMORY_BYTES: usize = 15_000_000;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct IndexSettings {
+    enabled: bool,
+    interval_minutes: u64,
+}
+
+impl Default for IndexSettings {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            interval_minutes: 5,
+        }
+    }
+}
+
+struct IndexedSession {
+    title: String,
+    directory: Option<String>,
+    /// `time.updated` of the session when indexed, ms since the Unix epoch
+    updated: u64,
+    text: String,
+}
+
+#[derive(Clone, Copy)]
+struct Fields {
+    /// Server origin and session id, which name a session across servers
+    key: Field,
+    origin: Field,
+    id: Field,
+    title: Field,
+    directory: Field,
+    updated: Field,
+    text: Field,
+}
+
+struct OpenIndex {
+    fields: Fields,
+    reader: IndexReader,
+    writer: IndexWriter,
+}
+
+pub struct SessionIndexState {
+    /// `None` while closed, to be moved or replaced on disk
+    index: Mutex<Option<OpenIndex>>,
+    path: PathBuf,
+    wake: Notify,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SessionHit {
+    pub session_id: String,
+    pub title: String,
+    pub directory: Option<String>,
+    pub updated: u64,
+    pub score: f64,
+    pub snippet: String,
+    /// Matches in `snippet`, as UTF-16 offsets for the frontend
+    pub highlights: Vec<(usize, usize)>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct IndexUpdate {
+    pub indexed: usize,
+    pub removed: usize,
+    pub total: usize,
+}
+
+fn schema() -> (Schema, Fields) {
+    let words = TextOptions::default()
+        .set_indexing_options(
+            TextFieldIndexing::default()
+                .set_tokenizer(TOKENIZER)
+                .set_index_option(IndexRecordOption::WithFreqs),
+        )
+        .set_stored();
+    let mut builder = Schema::builder();
+    let fields = Fields {
+        key: builder.add_text_field("key", STRING),
+        origin: builder.add_text_field("origin", STRING),
+        id: builder.add_text_field("id", STORED),
+        title: builder.add_text_field("title", words.clone()),
+        directory: builder.add_text_field("directory", STORED),
+        updated: builder.add_u64_field("updated", STORED),
+        text: builder.add_text_field("text", words),
+    };
+    (builder.build(), fields)
+}
+
+fn open_in(directory: impl Directory) -> tantivy::Result<OpenIndex> {
+    let (schema, fields) = schema();
+    let index = Index::open_or_create(directory, schema)?;
+    index.tokenizers().register(
+        TOKENIZER,
+        TextAnalyzer::builder(WordTokenizer)
+            .filter(RemoveLongFilter::limit(MAX_WORD_BYTES))
+            .build(),
+    );
+    let writer = index.writer_with_num_threads(1, WRITER_MEMORY_BYTES)?;
+    let reader = index
+        .reader_builder()
+        .reload_policy(ReloadPolicy::Manual)
+        .try_into()?;
+    Ok(OpenIndex {
+        fields,
+        reader,
+        writer,
+    })
+}
+
+/// Open the index at `path`, starting a new one when it can't be read: it
+/// only holds what the server has
+fn open(path: &Path) -> Result<OpenIndex, String> {
+    let open_at = || -> Result<OpenIndex, String> {
+        std::fs::create_dir_all(path)
+            .map_err(|e| format!("Failed to create {}: {}", path.display(), e))?;
+        let directory = MmapDirectory::open(path)
+            .map_err(|e| format!("Failed to open {}: {}", path.display(), e))?;
+        open_in(directory).map_err(|e| format!("Failed to open the session index: {}", e))
+    };
+    open_at().or_else(|e| {
+        eprintln!("{}, starting fresh", e);
+        std::fs::remove_dir_all(path)
+            .map_err(|e| format!("Failed to remove {}: {}", path.display(), e))?;
+        open_at()
+    })
+}
+
+impl SessionIndexState {
+    fn load(path: PathBuf) -> Self {
+        let index = open(&path).inspect_err(|e| eprintln!("{}", e)).ok();
+        Self {
+            index: Mutex::new(index),
+            path,
+            wake: Notify::new(),
+        }
+    }
+
+    fn with_index<T>(
+        &self,
+        f: impl FnOnce(&mut OpenIndex) -> Result<T, String>,
+    ) -> Result<T, String> {
+        let mut index = self
+            .index
+            .lock()
+            .map_err(|e| format!("Lock error: {}", e))?;
+        f(index.as_mut().ok_or("The session index isn't open")?)
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Release the index's files, so the directory can be moved
+    pub fn close(&self) -> Result<(), String> {
+        *self
+            .index
+            .lock()
+            .map_err(|e| format!("Lock error: {}", e))? = None;
+        Ok(())
+    }
+
+    /// Open the index again, after its directory was removed or restored
+    pub fn reopen(&self) -> Result<(), String> {
+        let mut index = self
+            .index
+            .lock()
+            .map_err(|e| format!("Lock error: {}", e))?;
+        // The writer holds a lock on the directory until dropped
+        *index = None;
+        *index = Some(open(&self.path)?);
+        Ok(())
+    }
+}
+
+pub fn init_session_index_state(app: &AppHandle) -> SessionIndexState {
+    let path = app
+        .path()
+        .resolve(INDEX_DIR, BaseDirectory::AppLocalData)
+        .expect("Failed to resolve session index path");
+    SessionIndexState::load(path)
+}
+
+fn is_cjk(c: char) -> bool {
+    matches!(c,
+        '\u{3040}'..='\u{30ff}' // Hiragana, Katakana
+        | '\u{3400}'..='\u{4dbf}'
+        | '\u{4e00}'..='\u{9fff}'
+        | '\u{ac00}'..='\u{d7af}' // Hangul
+        | '\u{f900}'..='\u{faff}')
+}
+
+/// Lowercased words of `text` with their byte ranges. Scripts written
+/// without spaces are split into single characters.
+fn tokens(text: &str) -> Vec<(String, usize, usize)> {
+    let mut tokens = Vec::new();
+    let mut start = None;
+    for (i, c) in text.char_indices() {
+        let in_word = c.is_alphanumeric() && !is_cjk(c);
+        match (in_word, start) {
+            (true, None) => start = Some(i),
+            (false, Some(s)) => {
+                tokens.push((text[s..i].to_lowercase(), s, i));
+                start = None;
+            }
+            _ => {}
+        }
+        if is_cjk(c) {
+            tokens.push((c.to_string(), i, i + c.len_utf8()));
+        }
+    }
+    if let Some(s) = start {
+        tokens.push((text[s..].to_lowercase(), s, text.len()));
+    }
+    tokens
+}
+
+/// Splits text as `tokens` does
+#[derive(Clone)]
+struct WordTokenizer;
+
+struct WordStream {
+    tokens: Vec<Token>,
+    /// Of the current token, plus one
+    next: usize,
+}
+
+impl TokenStream for WordStream {
+    fn advance(&mut self) -> bool {
+        self.next += 1;
+        self.next <= self.tokens.len()
+    }
+
+    fn token(&self) -> &Token {
+        &self.tokens[self.next - 1]
+    }
+
+    fn token_mut(&mut self) -> &mut Token {
+        &mut self.tokens[self.next - 1]
+    }
+}
+
+impl Tokenizer for WordTokenizer {
+    type TokenStream<'a> = WordStream;
+
+    fn token_stream<'a>(&'a mut self, text: &'a str) -> WordStream {
+        let tokens = tokens(text)
+            .into_iter()
+            .enumerate()
+            .map(|(position, (text, offset_from, offset_to))| Token {
+                offset_from,
+                offset_to,
+                position,
+                text,
+                position_length: 1,
+            })
+            .collect();
+        WordStream { tokens, next: 0 }
+    }
+}
+
+fn key_term(fields: &Fields, origin: &str, id: &str) -> Term {
+    Term::from_field_text(fields.key, &format!("{}\n{}", origin, id))
+}
+
+fn stored_text(document: &TantivyDocument, field: Field) -> Option<String> {
+    document
+        .get_first(field)
+        .and_then(|value| value.as_str())
+        .map(String::from)
+}
+
+/// `time.updated` of the sessions of server `origin` in the index, by id
+fn indexed(index: &OpenIndex, origin: &str) -> Result<HashMap<String, u64>, String> {
+    let fields = &index.fields;
+    let searcher = index.reader.searcher();
+    let query = TermQuery::new(
+        Term::from_field_text(fields.origin, origin),
+        IndexRecordOption::Basic,
+    );
+    let addresses = searcher
+        .search(&query, &DocSetCollector)
+        .map_err(|e| format!("Failed to read the session index: {}", e))?;
+    let mut sessions = HashMap::new();
+    for address in addresses {
+        let document: TantivyDocument = searcher
+            .doc(address)
+            .map_err(|e| format!("Failed to read the session index: {}", e))?;
+        if let Some(id) = stored_text(&document, fields.id) {
+            let updated = document
+                .get_first(fields.updated)
+                .and_then(|value| value.as_u64())
+                .unwrap_or(0);
+            sessions.insert(id, updated);
+        }
+    }
+    Ok(sessions)
+}
+
+/// Replace the sessions of server `origin` that were listed with those
+/// `fetched`, dropping those no longer listed
+fn store_fetched(
+    index: &mut OpenIndex,
+    origin: &str,
+    listed_ids: &HashSet<String>,
+    fetched: Vec<(String, IndexedSession)>,
+) -> Result<IndexUpdate, String> {
+    let fields = index.fields;
+    let mut ids: HashSet<String> = indexed(index, origin)?.into_keys().collect();
+    let before = ids.len();
+    ids.retain(|id| {
+        let listed = listed_ids.contains(id);
+        if !listed {
+            index.writer.delete_term(key_term(&fields, origin, id));
+        }
+        listed
+    });
+    let removed = before - ids.len();
+    let indexed = fetched.len();
+
+    for (id, session) in fetched {
+        index.writer.delete_term(key_term(&fields, origin, &id));
+        let mut document = doc!(
+            fields.key => format!("{}\n{}", origin, id),
+            fields.origin => origin,
+            fields.id => id.as_str(),
+            fields.title => session.title,
+            fields.updated => session.updated,
+            fields.text => session.text,
+        );
+        if let Some(directory) = session.directory {
+            document.add_text(fields.directory, directory);
+        }
+        index
+            .writer
+            .add_document(document)
+            .map_err(|e| format!("Failed to index session {}: {}", id, e))?;
+        ids.insert(id);
+    }
+    if indexed + removed > 0 {
+        // A commit replaces the index's metadata in one rename, so a crash
+        // leaves the previous commit
+        index
+            .writer
+            .commit()
+            .map_err(|e| format!("Failed to write the session index: {}", e))?;
+        index
+            .reader
+            .reload()
+            .map_err(|e| format!("Failed to reload the session index: {}", e))?;
+    }
+    Ok(IndexUpdate {
+        indexed,
+        removed,
+        total: ids.len(),
+    })
+}
+
+/// Sessions of server `origin` with all of `query`'s words, the last of them
+/// as a prefix
+fn parse_query(fields: &Fields, origin: &str, query: &str) -> Option<BooleanQuery> {
+    let words: Vec<String> = tokens(query).into_iter().map(|(word, _, _)| word).collect();
+    let last = words.len().checked_sub(1)?;
+    let mut clauses: Vec<(Occur, Box<dyn Query>)> = vec![(
+        Occur::Must,
+        Box::new(TermQuery::new(
+            Term::from_field_text(fields.origin, origin),
+            IndexRecordOption::Basic,
+        )),
+    )];
+    for (position, word) in words.iter().enumerate() {
+        let mut matches: Vec<(Occur, Box<dyn Query>)> = Vec::new();
+        for field in [fields.title, fields.text] {
+            let term = Term::from_field_text(field, word);
+            matches.push((
+                Occur::Should,
+                Box::new(TermQuery::new(term.clone(), IndexRecordOption::WithFreqs)),
+            ));
+            // The last word may still be being typed
+            if position == last {
+                matches.push((
+                    Occur::Should,
+                    Box::new(FuzzyTermQuery::new_prefix(term, 0, true)),
+                ));
+            }
+        }
+        clauses.push((Occur::Must, Box::new(BooleanQuery::new(matches))));
+    }
+    Some(BooleanQuery::new(clauses))
+}
+
+/// The sessions of server `origin` that match `query`, best first
+fn search(
+    index: &OpenIndex,
+    origin: &str,
+    query: &str,
+    limit: usize,
+) -> Result<Vec<SessionHit>, String> {
+    let fields = &index.fields;
+    let Some(parsed) = parse_query(fields, origin, query) else {
+        return Ok(Vec::new());
+    };
+    let searcher = index.reader.searcher();
+    let top = searcher
+        .search(&parsed, &TopDocs::with_limit(limit))
+        .map_err(|e| format!("Failed to search sessions: {}", e))?;
+    top.into_iter()
+        .map(|(score, address)| {
+            let document: TantivyDocument = searcher
+                .doc(address)
+                .map_err(|e| format!("Failed to read the session index: {}", e))?;
+            let text = stored_text(&document, fields.text).unwrap_or_default();
+            let (snippet, highlights) = snippet(&text, query);
+            Ok(SessionHit {
+                session_id: stored_text(&document, fields.id).unwrap_or_default(),
+                title: stored_text(&document, fields.title).unwrap_or_default(),
+                directory: stored_text(&document, fields.directory),
+                updated: document
+                    .get_first(fields.updated)
+                    .and_then(|value| value.as_u64())
+                    .unwrap_or(0),
+                score: score as f64,
+                snippet,
+                highlights,
+            })
+        })
+        .collect()
+}
+
+fn utf16_len(text: &str) -> usize {
+    text.encode_utf16().count()
+}
+
+/// Text around the first match of `query` in `text`, with the matches in it
+fn snippet(text: &str, query: &str) -> (String, Vec<(usize, usize)>) {
+    let words: Vec<String> = tokens(query).into_iter().map(|(word, _, _)| word).collect();
+    let matches: Vec<(usize, usize)> = tokens(text)
+        .into_iter()
+        .filter(|(token, _, _)| words.iter().any(|word| token.starts_with(word.as_str())))
+        .map(|(_, start, end)| (start, end))
+        .collect();
+    let first = matches.first().map(|(start, _)| *start).unwrap_or(0);
+
+    let start = text[..first]
+        .char_indices()
+        .rev()
+        .nth(SNIPPET_CONTEXT.saturating_sub(1))
+        .map(|(i, _)| i)
+        .unwrap_or(0);
+    let end = text[first..]
+        .char_indices()
+        .nth(SNIPPET_CONTEXT * 2)
+        .map(|(i, _)| first + i)
+        .unwrap_or(text.len());
+
+    let prefix = if start > 0 { "…" } else { "" };
+    let suffix = if end < text.len() { "…" } else { "" };
+    let body = &text[start..end];
+    let offset = utf16_len(prefix);
+    let highlights = matches
+        .into_iter()
+        .filter(|(s, e)| *s >= start && *e <= end)
+        .map(|(s, e)| {
+            let from = offset + utf16_len(&text[start..s]);
+            (from, from + utf16_len(&text[s..e]))
+        })
+        .collect();
+    (
+        format!("{}{}{}", prefix, body.replace('\n', " "), suffix),
+        highlights,
+    )
+}
+
+fn settings(app: &AppHandle) -> IndexSettings {
+    app_settings::load(app).session_index
+}
+
+fn state(app: &AppHandle) -> Result<tauri::State<'_, SessionIndexState>, String> {
+    app.try_state::<SessionIndexState>()
+        .ok_or_else(|| "Session index state not found".to_string())
+}
+
+/// Run `f` on the open index off the async runtime, as it reads and writes
+/// the index's files
+async fn blocking<T: Send + 'static>(
+    app: &AppHandle,
+    f: impl FnOnce(&mut OpenIndex) -> Result<T, String> + Send + 'static,
+) -> Result<T, String> {
+    let app = app.clone();
+    tauri::async_runtime::spawn_blocking(move || state(&app)?.with_index(f))
+        .await
+        .map_err(panic_guard::join_error)?
+}
+
+fn session_text(messages: &[Value]) -> String {
+    messages
+        .iter()
+        .map(vault_export::message_text)
+        .filter(|text| !text.is_empty())
+        .collect::<Vec<_>>()
+        .join("\n\n")
+}
+
+/// Bring the index of the connected server up to date
+async fn update(app: &AppHandle) -> Result<IndexUpdate, AppError> {
+    let server = crate::connected_server(app).await?;
+    let url = reqwest::Url::parse(&server.url)
+        .map_err(|e| format!("Invalid server URL {}: {}", server.url, e))?;
+    let origin = url_origin(&url);
+    let client = crate::server_client(app, &url, REQUEST_TIMEOUT)?;
+
+    let listed = vault_export::get_json(&client, &server, &url, &["session"]).await?;
+    let listed: Vec<(String, String, Option<String>, u64)> = listed
+        .as_array()
+        .map(Vec::as_slice)
+        .unwrap_or_default()
+        .iter()
+        .filter_map(|session| {
+            Some((
+                session.get("id")?.as_str()?.to_string(),
+                session
+                    .get("title")
+                    .and_then(Value::as_str)
+                    .unwrap_or_default()
+                    .to_string(),
+                session
+                    .get("directory")
+                    .and_then(Value::as_str)
+                    .map(String::from),
+                session
+                    .pointer("/time/updated")
+                    .and_then(Value::as_u64)
+                    .unwrap_or(0),
+            ))
+        })
+        .collect();
+
+    let known = {
+        let origin = origin.clone();
+        blocking(app, move |index| indexed(index, &origin)).await?
+    };
+    let stale: Vec<_> = listed
+        .iter()
+        .filter(|(id, _, _, updated)| known.get(id).is_none_or(|known| known < updated))
+        .cloned()
+        .collect();
+
+    // Only runs that fetch something are worth listing
+    let task = (!stale.is_empty())
+        .then(|| tasks::start(app, TaskKind::Indexing, i18n::t(app, "task.sessionIndex")));
+    let stale_count = stale.len();
+    let mut fetched = Vec::new();
+    for (done, (id, title, directory, updated)) in stale.into_iter().enumerate() {
+        if let Some(task) = &task {
+            if task.is_cancelled() {
+                break;
+            }
+            task.set_progress(done as f32 / stale_count as f32);
+        }
+        let path = ["session", id.as_str(), "message"];
+        // A session deleted meanwhile, or one the server fails on, is
+        // tried again next time
+        let Ok(messages) = vault_export::get_json(&client, &server, &url, &path).await else {
+            continue;
+        };
+        let messages = messages.as_array().map(Vec::as_slice).unwrap_or_default();
+        fetched.push((
+            id,
+            IndexedSession {
+                title,
+                directory,
+                updated,
+                text: session_text(messages),
+            },
+        ));
+    }
+
+    let listed_ids: HashSet<String> = listed.into_iter().map(|(id, ..)| id).collect();
+    let result = blocking(app, move |index| {
+        store_fetched(index, &origin, &listed_ids, fetched)
+    })
+    .await;
+    // What was fetched before a cancel is kept all the same
+    if let Some(task) = &task {
+        match &result {
+            Ok(_) => task.finish(&task.check()),
+            Err(e) => task.finish::<(), _>(&Err(e)),
+        }
+    }
+    Ok(result?)
+}
+
+/// Keep the index of the connected server up to date, for the life of the app
+pub fn watch(app: &AppHandle) {
+    let app = app.clone();
+    tauri::async_runtime::spawn(async move {
+        loop {
+            let settings = settings(&app);
+            if settings.enabled {
+                match update(&app).await {
+                    Ok(update) => {
+                        let _ = app.emit("session-index:updated", update);
+                    }
+                    Err(e) => eprintln!("Failed to index sessions: {}", e),
+                }
+            }
+            let Ok(index) = state(&app) else {
+                return;
+            };
+            let interval = settings.interval_minutes.max(MIN_INTERVAL_MINUTES) * 60;
+            let _ =
+                tokio::time::timeout(Duration::from_secs(interval), index.wake.notified()).await;
+        }
+    });
+}
+
+/// Search the sessions of the connected server indexed so far
+#[tauri::command]
+pub async fn search_sessions(
+    app: AppHandle,
+    query: String,
+    limit: Option<usize>,
+) -> Result<Vec<SessionHit>, AppError> {
+    let server = crate::connected_server(&app).await?;
+    let url = reqwest::Url::parse(&server.url)
+        .map_err(|e| format!("Invalid server URL {}: {}", server.url, e))?;
+    let origin = url_origin(&url);
+    let limit = limit.unwrap_or(DEFAULT_LIMIT).max(1);
+
+    Ok(blocking(&app, move |index| search(index, &origin, &query, limit)).await?)
+}
+
+/// Index the connected server's sessions now rather than at the next interval
+#[tauri::command]
+pub fn reindex_sessions(app: AppHandle) -> Result<(), AppError> {
+    state(&app)?.wake.notify_one();
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn session(title: &str, text: &str) -> (String, IndexedSession) {
+        (
+            title.to_lowercase(),
+            IndexedSession {
+                title: title.to_string(),
+                directory: None,
+                updated: 0,
+                text: text.to_string(),
+            },
+        )
+    }
+
+    #[test]
+    fn test_search_needs_every_word_and_prefixes_the_last() {
+        let mut index = open_in(tantivy::directory::RamDirectory::create()).unwrap();
+        let fetched = vec![
+            session("Parser", "The tokenizer drops trailing commas"),
+            session("Build", "Tokenizer tests for the lexer, tokenizer again"),
+            session("日本語", "東京で会議"),
+        ];
+        let listed: HashSet<String> = fetched.iter().map(|(id, _)| id.clone()).collect();
+        let update = store_fetched(&mut index, "http://a", &listed, fetched).unwrap();
+        assert_eq!((update.indexed, update.total), (3, 3));
+
+        let ids = |index: &OpenIndex, origin: &str, query: &str| -> Vec<String> {
+            search(index, origin, query, 10)
+                .unwrap()
+                .into_iter()
+                .map(|hit| hit.session_id)
+                .collect()
+        };
+        assert_eq!(ids(&index, "http://a", "tokenizer"), ["build", "parser"]);
+        assert_eq!(ids(&index, "http://a", "tokenizer comm"), ["parser"]);
+        assert_eq!(ids(&index, "http://a", "東京"), ["日本語"]);
+        assert!(ids(&index, "http://a", "").is_empty());
+        assert!(ids(&index, "http://b", "tokenizer").is_empty());
+
+        let listed = HashSet::from(["parser".to_string()]);
+        let update = store_fetched(&mut index, "http://a", &listed, Vec::new()).unwrap();
+        assert_eq!((update.removed, update.total), (2, 1));
+        assert_eq!(ids(&index, "http://a", "tokenizer"), ["parser"]);
+    }
+
+    #[test]
+    fn test_snippet_highlights_in_utf16() {
+        let text = format!("{} é then the tokenizer broke", "word ".repeat(40));
+        let (snippet, highlights) = snippet(&text, "Tokenizer");
+        assert!(snippet.starts_with('…'));
+        let utf16: Vec<u16> = snippet.encode_utf16().collect();
+        let (start, end) = highlights[0];
+        assert_eq!(String::from_utf16(&utf16[start..end]).unwrap(), "tokenizer");
+    }
+}
@@ -0,0 +1,193 @@
+//! Settings resolution: global settings with a per-project overlay.
+//!
+//! A project can commit `.aura/settings.json` to share settings such as the
+//! server URL or hotkeys with the team. Its values are merged over the global
+//! settings store: objects merge key by key, anything else replaces the global
+//! value and `null` unsets it. Machine-local settings can't be overridden by a
+//! project, and projects in folders the user doesn't trust override nothing.
+
+use serde::Serialize;
+use serde_json::{Map, Value};
+use std::path::{Path, PathBuf};
+use tauri::AppHandle;
+use tauri_plugin_store::StoreExt;
+
+use crate::{
+    SETTINGS_STORE,
+    error::AppError,
+    workspace_trust::{self, Trust},
+};
+
+pub const PROJECT_SETTINGS_FILE: &str = ".aura/settings.json";
+
+/// Settings tied to this machine or user rather than to a project
+const LOCAL_ONLY_KEYS: &[&str] = &[
+    "telemetryDisabled",
+    "meteredAllowedNetworks",
+    "modelSource",
+    "cliSyncPolicy",
+    "cliLastSync",
+    "lastSeenVersion",
+    "webviewPermissions",
+    "appLanguage",
+    "auditLogEnabled",
+    "updateSource",
+    "logRedactionPatterns",
+    "notificationThresholds",
+    "windowSnapPresets",
+    "linkPreviewAllowedHosts",
+    "serverHostOverrides",
+    "networkProxy",
+    "trustedWorkspaces",
+    "sidecarSandbox",
+    "memoryWatchdog",
+    "macros",
+    "importedConversations",
+    "meetingCalendar",
+    "windowMonitors",
+    "sessionIndex",
+    "retention",
+    "windowTypography",
+];
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct EffectiveSettings {
+    pub settings: Value,
+    /// The project settings file, if the project has one
+    pub project_file: Option<String>,
+    /// Top-level keys set by the project
+    pub overridden: Vec<String>,
+    /// The project's folder isn't trusted, so its settings weren't applied
+    pub restricted: bool,
+}
+
+pub fn global_settings(app: &AppHandle) -> Result<Map<String, Value>, String> {
+    let store = app
+        .store(SETTINGS_STORE)
+        .map_err(|e| format!("Failed to open settings store: {}", e))?;
+    Ok(store.entries().into_iter().collect())
+}
+
+pub struct ProjectSettings {
+    pub path: PathBuf,
+    pub settings: Map<String, Value>,
+}
+
+/// Read a project's settings overlay, if it has one
+pub fn project_settings(project: &Path) -> Result<Option<ProjectSettings>, String> {
+    let path = project.join(PROJECT_SETTINGS_FILE);
+    let content = match std::fs::read_to_string(&path) {
+        Ok(content) => content,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+        Err(e) => return Err(format!("Failed to read {}: {}", path.display(), e)),
+    };
+    match serde_json::from_str(&content) {
+        Ok(Value::Object(settings)) => Ok(Some(ProjectSettings { path, settings })),
+        Ok(_) => Err(format!("{} must contain a JSON object", path.display())),
+        Err(e) => Err(format!("Failed to parse {}: {}", path.display(), e)),
+    }
+}
+
+/// Merge `overlay` into `base`
+pub(crate) fn merge(base: &mut Map<String, Value>, overlay: Map<String, Value>) {
+    for (key, value) in overlay {
+        match (base.get_mut(&key), value) {
+            (_, Value::Null) => {
+                base.remove(&key);
+            }
+            (Some(Value::Object(base)), Value::Object(overlay)) => merge(base, overlay),
+            (_, value) => {
+                base.insert(key, value);
+            }
+        }
+    }
+}
+
+/// Apply a project overlay to the global settings, returning the keys it set
+fn resolve(global: &mut Map<String, Value>, mut project: Map<String, Value>) -> Vec<String> {
+    project.retain(|key, _| !LOCAL_ONLY_KEYS.contains(&key.as_str()));
+    let mut overridden: Vec<String> = project.keys().cloned().collect();
+    overridden.sort();
+    merge(global, project);
+    overridden
+}
+
+pub fn effective_settings(
+    app: &AppHandle,
+    project: Option<&Path>,
+) -> Result<EffectiveSettings, String> {
+    let mut settings = global_settings(app)?;
+    let overlay = match project {
+        Some(project) => project_settings(project)?,
+        None => None,
+    };
+    let restricted = overlay.is_some()
+        && project.is_some_and(|project| workspace_trust::trust(app, project) != Trust::Trusted);
+
+    let (project_file, overridden) = match overlay {
+        Some(overlay) if !restricted => (
+            Some(overlay.path.to_string_lossy().to_string()),
+            resolve(&mut settings, overlay.settings),
+        ),
+        Some(overlay) => (Some(overlay.path.to_string_lossy().to_string()), Vec::new()),
+        None => (None, Vec::new()),
+    };
+
+    Ok(EffectiveSettings {
+        settings: Value::Object(settings),
+        project_file,
+        overridden,
+        restricted,
+    })
+}
+
+/// Global settings with the overlay of `project` (a project directory)
+/// applied
+#[tauri::command]
+pub fn get_effective_settings(
+    app: AppHandle,
+    project: Option<String>,
+) -> Result<EffectiveSettings, AppError> {
+    Ok(effective_settings(&app, project.as_deref().map(Path::new))?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn object(value: Value) -> Map<String, Value> {
+        match value {
+            Value::Object(map) => map,
+            _ => panic!("not an object"),
+        }
+    }
+
+    #[test]
+    fn test_project_overlay_merges_over_global() {
+        let mut global = object(json!({
+            "defaultServerUrl": "http://localhost:4096",
+            "hotkeys": { "dictate": "Ctrl+D", "search": "Ctrl+K" },
+            "theme": "dark",
+            "telemetryDisabled": true,
+        }));
+        let project = object(json!({
+            "defaultServerUrl": "https://opencode.team.example",
+            "hotkeys": { "dictate": "Ctrl+Shift+D" },
+            "theme": null,
+            "telemetryDisabled": false,
+        }));
+
+        let overridden = resolve(&mut global, project);
+        assert_eq!(overridden, ["defaultServerUrl", "hotkeys", "theme"]);
+        assert_eq!(
+            Value::Object(global),
+            json!({
+                "defaultServerUrl": "https://opencode.team.example",
+                "hotkeys": { "dictate": "Ctrl+Shift+D", "search": "Ctrl+K" },
+                "telemetryDisabled": true,
+            })
+        );
+    }
+}
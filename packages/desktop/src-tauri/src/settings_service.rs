@@ -0,0 +1,260 @@
+//! One owner for changes to the settings stores.
+//!
+//! Commands used to open a store, change a key and save it on their own, so
+//! two of them at once could both read the old value and the last to save
+//! won, while the navigation callback read the server list the frontend was
+//! rewriting. `SettingsService` runs a single task that applies changes one
+//! at a time: callers send it a change to the entries of a store and wait
+//! for the result.
+//!
+//! Reads go to a typed snapshot the task refreshes after each change. A
+//! change the frontend makes through the plugin is applied to it from the
+//! plugin's event as the change is made, so readers such as the navigation
+//! callback never open a store, wait on the task or see the old value.
+
+use serde_json::{Map, Value};
+use std::{path::Path, sync::RwLock};
+use tauri::{AppHandle, Listener, Manager};
+use tauri_plugin_store::StoreExt;
+use tokio::sync::{mpsc, oneshot};
+
+use crate::{
+    DEFAULT_SERVER_URL_KEY, GLOBAL_STORAGE, SETTINGS_STORE, atomic_store, error::AppError,
+    url_origin,
+};
+
+/// Where the frontend keeps the servers it connects to, in `GLOBAL_STORAGE`
+const SERVER_KEY: &str = "server";
+/// The keys the snapshot is made of, by store
+const WATCHED_KEYS: &[(&str, &str)] = &[
+    (SETTINGS_STORE, DEFAULT_SERVER_URL_KEY),
+    (GLOBAL_STORAGE, SERVER_KEY),
+];
+
+type Update = Box<dyn FnOnce(&mut Map<String, Value>) -> Result<(), AppError> + Send>;
+
+struct Message {
+    store: &'static str,
+    update: Update,
+    reply: oneshot::Sender<Result<(), AppError>>,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct SettingsSnapshot {
+    pub default_server_url: Option<String>,
+    /// Origins of the servers the frontend lists
+    pub server_origins: Vec<String>,
+}
+
+pub struct SettingsService {
+    sender: mpsc::UnboundedSender<Message>,
+    snapshot: RwLock<SettingsSnapshot>,
+}
+
+fn default_server_url(value: Option<&Value>) -> Option<String> {
+    value.and_then(|value| value.as_str().map(String::from))
+}
+
+fn server_origins(server: Option<&Value>) -> Vec<String> {
+    server
+        .and_then(|server| server.get("list").and_then(|v| v.as_array()))
+        .map(Vec::as_slice)
+        .unwrap_or_default()
+        .iter()
+        .filter_map(|server| server.as_str())
+        .filter_map(|server| tauri::Url::parse(server).ok())
+        .map(|url| url_origin(&url))
+        .collect()
+}
+
+fn read_snapshot(app: &AppHandle) -> SettingsSnapshot {
+    let get = |store: &str, key: &str| app.store(store).ok().and_then(|store| store.get(key));
+    SettingsSnapshot {
+        default_server_url: default_server_url(
+            get(SETTINGS_STORE, DEFAULT_SERVER_URL_KEY).as_ref(),
+        ),
+        server_origins: server_origins(get(GLOBAL_STORAGE, SERVER_KEY).as_ref()),
+    }
+}
+
+fn refresh(app: &AppHandle) {
+    let snapshot = read_snapshot(app);
+    if let Some(service) = app.try_state::<SettingsService>() {
+        match service.snapshot.write() {
+            Ok(mut current) => *current = snapshot,
+            Err(e) => eprintln!("Failed to refresh settings: Lock error: {}", e),
+        }
+    }
+}
+
+fn apply(app: &AppHandle, store: &str, update: Update) -> Result<(), AppError> {
+    let handle = app
+        .store(store)
+        .map_err(|e| format!("Failed to open settings store: {}", e))?;
+    let before: Map<String, Value> = handle.entries().into_iter().collect();
+    let mut entries = before.clone();
+    update(&mut entries)?;
+    if entries == before {
+        return Ok(());
+    }
+
+    for key in before.keys().filter(|key| !entries.contains_key(*key)) {
+        handle.delete(key);
+    }
+    for (key, value) in entries {
+        if before.get(&key) != Some(&value) {
+            handle.set(key, value);
+        }
+    }
+    atomic_store::save(app, store)
+        .map_err(|e| AppError::io(format!("Failed to save settings: {}", e)))
+}
+
+async fn run(app: AppHandle, mut receiver: mpsc::UnboundedReceiver<Message>) {
+    while let Some(Message {
+        store,
+        update,
+        reply,
+    }) = receiver.recv().await
+    {
+        let result = apply(&app, store, update);
+        refresh(&app);
+        let _ = reply.send(result);
+    }
+}
+
+/// The file name of the store a `store://change` event of the store plugin
+/// is about
+pub fn changed_store(payload: &str) -> Option<String> {
+    let payload = serde_json::from_str::<Value>(payload).ok()?;
+    let path = payload.get("path")?.as_str()?;
+    Some(Path::new(path).file_name()?.to_str()?.to_string())
+}
+
+/// The key of the snapshot a `store://change` event of the store plugin is
+/// about, with its new value
+fn watched_change(payload: &str) -> Option<(&'static str, Option<Value>)> {
+    let store = changed_store(payload)?;
+    let mut payload = serde_json::from_str::<Value>(payload).ok()?;
+    let key = payload.get("key")?.as_str()?;
+    let &(_, watched_key) = WATCHED_KEYS
+        .iter()
+        .find(|&&(watched_store, watched_key)| watched_store == store && watched_key == key)?;
+    let value = payload
+        .get_mut("value")
+        .map(Value::take)
+        .filter(|value| !value.is_null());
+    Some((watched_key, value))
+}
+
+/// Apply the new `value` of `key` to the snapshot. The plugin announces a
+/// change holding the store's locks, so the store isn't read here.
+fn apply_change(app: &AppHandle, key: &str, value: Option<&Value>) {
+    let Some(service) = app.try_state::<SettingsService>() else {
+        return;
+    };
+    let mut snapshot = match service.snapshot.write() {
+        Ok(snapshot) => snapshot,
+        Err(e) => {
+            eprintln!("Failed to refresh settings: Lock error: {}", e);
+            return;
+        }
+    };
+    match key {
+        DEFAULT_SERVER_URL_KEY => snapshot.default_server_url = default_server_url(value),
+        SERVER_KEY => snapshot.server_origins = server_origins(value),
+        _ => {}
+    }
+}
+
+/// Start the service; run after `atomic_store::recover`
+pub fn init(app: &AppHandle) {
+    let (sender, receiver) = mpsc::unbounded_channel();
+    app.manage(SettingsService {
+        sender,
+        snapshot: RwLock::new(read_snapshot(app)),
+    });
+    tauri::async_runtime::spawn(run(app.clone(), receiver));
+
+    let listener_app = app.clone();
+    app.listen_any("store://change", move |event| {
+        if let Some((key, value)) = watched_change(event.payload()) {
+            apply_change(&listener_app, key, value.as_ref());
+        }
+    });
+}
+
+/// The current settings, as of the last change
+pub fn snapshot(app: &AppHandle) -> SettingsSnapshot {
+    app.try_state::<SettingsService>()
+        .and_then(|service| service.snapshot.read().ok().map(|s| s.clone()))
+        .unwrap_or_else(|| read_snapshot(app))
+}
+
+/// Change the entries of `store` with `change` and save the store, after
+/// every change sent before; nothing is saved if `change` fails
+pub async fn update(
+    app: &AppHandle,
+    store: &'static str,
+    change: impl FnOnce(&mut Map<String, Value>) -> Result<(), AppError> + Send + 'static,
+) -> Result<(), AppError> {
+    let service = app
+        .try_state::<SettingsService>()
+        .ok_or("Settings service not found")?;
+    let (reply, result) = oneshot::channel();
+    service
+        .sender
+        .send(Message {
+            store,
+            update: Box::new(change),
+            reply,
+        })
+        .map_err(|_| "Settings service stopped")?;
+    result.await.map_err(|_| "Settings service stopped")?
+}
+
+/// Set `key` in `store` to `value`, or remove it
+pub async fn set(
+    app: &AppHandle,
+    store: &'static str,
+    key: &'static str,
+    value: Option<Value>,
+) -> Result<(), AppError> {
+    update(app, store, move |entries| {
+        match value {
+            Some(value) => entries.insert(key.to_string(), value),
+            None => entries.remove(key),
+        };
+        Ok(())
+    })
+    .await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_watched_change() {
+        let (key, value) = watched_change(
+            r#"{"path":"/data/opencode.global.dat","resourceId":1,"key":"server","value":{"list":["https://a.example.com/x"]},"exists":true}"#,
+        )
+        .unwrap();
+        assert_eq!(key, SERVER_KEY);
+        assert_eq!(
+            server_origins(value.as_ref()),
+            vec!["https://a.example.com".to_string()]
+        );
+        assert_eq!(
+            watched_change(
+                r#"{"path":"/data/opencode.settings.dat","key":"defaultServerUrl","value":null,"exists":false}"#
+            ),
+            Some((DEFAULT_SERVER_URL_KEY, None))
+        );
+        assert!(
+            watched_change(r#"{"path":"/data/opencode.global.dat","key":"layout","exists":true}"#)
+                .is_none()
+        );
+        assert!(watched_change("not json").is_none());
+    }
+}
@@ -0,0 +1,240 @@
+//! Restricted launch of the managed sidecar.
+//!
+//! The sidecar normally starts through the user's login shell, with their
+//! whole environment: tokens in variables, every directory on `PATH`. Users
+//! who run untrusted agent plugins through it can set `sidecarSandbox` in the
+//! settings to start it with a scrubbed environment instead:
+//!
+//! ```json
+//! { "trusted": "off", "untrusted": "isolated", "allowEnv": ["GIT_SSH"], "path": ["/opt/git/bin"] }
+//! ```
+//!
+//! `restricted` keeps a few basic variables and a `PATH` of system
+//! directories, plus what is listed. `isolated` also runs the sidecar in
+//! firejail, which is Linux only. One sidecar serves every folder, so it runs
+//! at the `untrusted` level once any folder isn't trusted; changes apply the
+//! next time it starts. Windows already keeps the sidecar in a job object;
+//! running it in an AppContainer isn't supported.
+
+use serde::{Deserialize, Serialize};
+use std::{ffi::OsString, path::PathBuf};
+use tauri::{AppHandle, Webview};
+use tauri_plugin_shell::{ShellExt, process::Command};
+use tauri_plugin_store::StoreExt;
+
+use crate::{SETTINGS_STORE, atomic_store, audit, cli, error::AppError, workspace_trust};
+
+const SIDECAR_SANDBOX_KEY: &str = "sidecarSandbox";
+
+#[cfg(not(windows))]
+const BASE_ENV: &[&str] = &[
+    "HOME", "USER", "LOGNAME", "LANG", "LC_ALL", "LC_CTYPE", "TERM", "TMPDIR", "TZ",
+];
+#[cfg(windows)]
+const BASE_ENV: &[&str] = &[
+    "SYSTEMROOT",
+    "SYSTEMDRIVE",
+    "WINDIR",
+    "COMSPEC",
+    "PATHEXT",
+    "USERNAME",
+    "USERPROFILE",
+    "HOMEDRIVE",
+    "HOMEPATH",
+    "APPDATA",
+    "LOCALAPPDATA",
+    "PROGRAMDATA",
+    "TEMP",
+    "TMP",
+];
+
+#[cfg(target_os = "linux")]
+const FIREJAIL_PATHS: &[&str] = &["/usr/bin/firejail", "/usr/local/bin/firejail"];
+const FIREJAIL_ARGS: &[&str] = &["--quiet", "--nonewprivs", "--caps.drop=all", "--seccomp"];
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum SandboxLevel {
+    /// The user's login environment
+    #[default]
+    Off,
+    /// A scrubbed environment and a limited `PATH`
+    Restricted,
+    /// Restricted, inside firejail
+    Isolated,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct SandboxSettings {
+    /// Level while every folder answered about is trusted
+    pub trusted: SandboxLevel,
+    /// Level once any folder isn't trusted
+    pub untrusted: SandboxLevel,
+    /// Variables kept besides the basic ones
+    pub allow_env: Vec<String>,
+    /// Directories added to the limited `PATH`
+    pub path: Vec<String>,
+}
+
+fn settings(app: &AppHandle) -> SandboxSettings {
+    app.store(SETTINGS_STORE)
+        .ok()
+        .and_then(|store| store.get(SIDECAR_SANDBOX_KEY))
+        .and_then(|value| serde_json::from_value(value).ok())
+        .unwrap_or_default()
+}
+
+/// The variables of `vars` that are kept
+fn kept_env(
+    vars: impl Iterator<Item = (String, String)>,
+    allow: &[String],
+) -> Vec<(String, String)> {
+    vars.filter(|(key, _)| {
+        BASE_ENV
+            .iter()
+            .copied()
+            .chain(allow.iter().map(String::as_str))
+            .any(|kept| kept.eq_ignore_ascii_case(key))
+    })
+    .collect()
+}
+
+#[cfg(not(windows))]
+fn system_path() -> Vec<PathBuf> {
+    ["/usr/local/bin", "/usr/bin", "/bin", "/usr/sbin", "/sbin"]
+        .iter()
+        .map(PathBuf::from)
+        .collect()
+}
+
+#[cfg(windows)]
+fn system_path() -> Vec<PathBuf> {
+    let root = PathBuf::from(std::env::var("SYSTEMROOT").unwrap_or_else(|_| "C:\\Windows".into()));
+    vec![root.join("System32"), root]
+}
+
+fn limited_path(extra: &[String]) -> Result<OsString, String> {
+    std::env::join_paths(
+        system_path()
+            .into_iter()
+            .chain(extra.iter().map(PathBuf::from)),
+    )
+    .map_err(|e| format!("Invalid sandbox PATH: {}", e))
+}
+
+#[cfg(target_os = "linux")]
+fn firejail() -> Result<PathBuf, String> {
+    FIREJAIL_PATHS
+        .iter()
+        .map(PathBuf::from)
+        .find(|path| path.exists())
+        .ok_or_else(|| "Isolating the sidecar needs firejail, which isn't installed".to_string())
+}
+
+#[cfg(not(target_os = "linux"))]
+fn firejail() -> Result<PathBuf, String> {
+    Err("Isolating the sidecar needs firejail, which is only available on Linux".to_string())
+}
+
+/// The level the sidecar starts at
+pub fn level(app: &AppHandle) -> SandboxLevel {
+    let settings = settings(app);
+    if workspace_trust::any_untrusted(app) {
+        settings.untrusted
+    } else {
+        settings.trusted
+    }
+}
+
+/// The command that starts `sidecar` with `args`, sandboxed as configured
+pub fn sidecar_command(
+    app: &AppHandle,
+    sidecar: cli::Sidecar,
+    args: &str,
+) -> Result<Command, String> {
+    let level = level(app);
+    if level == SandboxLevel::Off {
+        return cli::create_command_for(app, sidecar, args);
+    }
+
+    let settings = settings(app);
+    let sidecar = sidecar.binary_path(app)?;
+    let command = if level == SandboxLevel::Isolated {
+        app.shell()
+            .command(firejail()?)
+            .args(FIREJAIL_ARGS)
+            .arg("--")
+            .arg(&sidecar)
+    } else {
+        app.shell().command(&sidecar)
+    };
+    Ok(command
+        .env_clear()
+        .envs(kept_env(std::env::vars(), &settings.allow_env))
+        .env("PATH", limited_path(&settings.path)?)
+        .envs(cli::sidecar_env(app))
+        .args(args.split_whitespace()))
+}
+
+#[tauri::command]
+pub fn get_sidecar_sandbox(app: AppHandle) -> SandboxSettings {
+    settings(&app)
+}
+
+#[tauri::command]
+pub fn set_sidecar_sandbox(
+    app: AppHandle,
+    webview: Webview,
+    sandbox: SandboxSettings,
+) -> Result<(), AppError> {
+    let result = save_settings(&app, &sandbox);
+    audit::record(
+        &webview,
+        "set_sidecar_sandbox",
+        serde_json::json!({ "sandbox": sandbox }),
+        &result,
+    );
+    result
+}
+
+fn save_settings(app: &AppHandle, sandbox: &SandboxSettings) -> Result<(), AppError> {
+    if sandbox.trusted == SandboxLevel::Isolated || sandbox.untrusted == SandboxLevel::Isolated {
+        firejail().map_err(AppError::unavailable)?;
+    }
+    limited_path(&sandbox.path).map_err(AppError::invalid_input)?;
+
+    let store = app
+        .store(SETTINGS_STORE)
+        .map_err(|e| format!("Failed to open settings store: {}", e))?;
+    let value = serde_json::to_value(sandbox)
+        .map_err(|e| format!("Failed to serialize sandbox settings: {}", e))?;
+    store.set(SIDECAR_SANDBOX_KEY, value);
+    atomic_store::save(app, SETTINGS_STORE)
+        .map_err(|e| AppError::io(format!("Failed to save settings: {}", e)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_kept_env() {
+        let vars = [
+            ("HOME", "/home/me"),
+            ("GITHUB_TOKEN", "secret"),
+            ("GIT_SSH", "ssh"),
+            ("PATH", "/home/me/bin"),
+        ]
+        .into_iter()
+        .map(|(key, value)| (key.to_string(), value.to_string()));
+        let kept: Vec<String> = kept_env(vars, &["git_ssh".to_string()])
+            .into_iter()
+            .map(|(key, _)| key)
+            .collect();
+        #[cfg(not(windows))]
+        assert_eq!(kept, ["HOME", "GIT_SSH"]);
+        #[cfg(windows)]
+        assert_eq!(kept, ["GIT_SSH"]);
+    }
+}
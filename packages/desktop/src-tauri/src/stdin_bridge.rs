@@ -0,0 +1,307 @@
+//! Piping content into the app from a terminal.
+//!
+//! `git diff | aura --stdin --prompt "review this"` forwards the piped content
+//! to the running instance as an attachment. The single-instance channel only
+//! carries arguments and the working directory, so the launching process
+//! spools stdin to a temp file keyed by its working directory, and the
+//! running instance picks spooled files for that directory up in order.
+//! `--pipe <path>` reads from a named pipe (or any file) instead.
+//!
+//! Attachments are queued and announced with `stdin:attachment`; the
+//! frontend drains the queue with `take_piped_attachments`, also on startup
+//! for content piped into a fresh launch.
+
+use serde::Serialize;
+use std::{
+    io::{IsTerminal, Read},
+    path::{Path, PathBuf},
+    sync::Mutex,
+    time::{Duration, SystemTime},
+};
+use tauri::{AppHandle, Emitter, Manager};
+
+use crate::{
+    error::AppError,
+    hash::{HashAlgorithm, hash_bytes},
+    user_scope,
+};
+
+/// Piped content larger than this is cut off
+const MAX_PIPED_BYTES: u64 = 10 * 1024 * 1024;
+/// Spool files nobody picked up are removed after this long
+const SPOOL_MAX_AGE: Duration = Duration::from_secs(60 * 60);
+const SPOOL_DIR: &str = "aura-stdin";
+
+#[derive(Debug, Default, PartialEq, Eq)]
+struct PipeArgs {
+    stdin: bool,
+    pipe: Option<PathBuf>,
+    prompt: Option<String>,
+}
+
+fn parse_args(args: &[String]) -> PipeArgs {
+    let mut parsed = PipeArgs::default();
+    let mut args = args.iter().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--stdin" => parsed.stdin = true,
+            "--pipe" => parsed.pipe = args.next().map(PathBuf::from),
+            "--prompt" => parsed.prompt = args.next().cloned(),
+            _ => {
+                if let Some(pipe) = arg.strip_prefix("--pipe=") {
+                    parsed.pipe = Some(PathBuf::from(pipe));
+                } else if let Some(prompt) = arg.strip_prefix("--prompt=") {
+                    parsed.prompt = Some(prompt.to_string());
+                }
+            }
+        }
+    }
+    parsed
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub enum AttachmentSource {
+    Stdin,
+    Pipe,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PipedAttachment {
+    pub id: u64,
+    pub source: AttachmentSource,
+    pub content: String,
+    pub truncated: bool,
+    pub prompt: Option<String>,
+    /// Working directory of the command that piped the content
+    pub cwd: String,
+}
+
+#[derive(Default)]
+struct Pending {
+    next_id: u64,
+    attachments: Vec<PipedAttachment>,
+}
+
+#[derive(Default)]
+pub struct PipedAttachmentState(Mutex<Pending>);
+
+fn spool_dir() -> Result<PathBuf, String> {
+    Ok(user_scope::temp_dir()?.join(SPOOL_DIR))
+}
+
+fn spool_prefix(cwd: &str) -> String {
+    hash_bytes(cwd.as_bytes(), HashAlgorithm::Blake3)[..16].to_string()
+}
+
+/// Read up to `MAX_PIPED_BYTES`, reporting whether there was more
+fn read_limited(reader: impl Read) -> Result<(Vec<u8>, bool), String> {
+    let mut content = Vec::new();
+    reader
+        .take(MAX_PIPED_BYTES + 1)
+        .read_to_end(&mut content)
+        .map_err(|e| format!("Failed to read piped content: {}", e))?;
+    let truncated = content.len() as u64 > MAX_PIPED_BYTES;
+    content.truncate(MAX_PIPED_BYTES as usize);
+    Ok((content, truncated))
+}
+
+/// Save piped stdin for whichever instance ends up handling the arguments.
+/// Call before the single-instance plugin, which exits secondary instances.
+pub fn spool_stdin() {
+    let args: Vec<String> = std::env::args().collect();
+    if !parse_args(&args).stdin || std::io::stdin().is_terminal() {
+        return;
+    }
+
+    let result = read_limited(std::io::stdin().lock()).and_then(|(mut content, truncated)| {
+        // Flag truncation for the reader with a trailing marker byte
+        content.push(truncated as u8);
+        let cwd = std::env::current_dir().unwrap_or_default();
+        let name = format!(
+            "{}-{}-{}",
+            spool_prefix(cwd.to_str().unwrap_or_default()),
+            SystemTime::now()
+                .duration_since(SystemTime::UNIX_EPOCH)
+                .map(|d| d.as_nanos())
+                .unwrap_or(0),
+            std::process::id()
+        );
+        let dir = spool_dir()?;
+        std::fs::create_dir_all(&dir)
+            .map_err(|e| format!("Failed to create {}: {}", dir.display(), e))?;
+        // The temp dir can be shared with other users
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            std::fs::set_permissions(&dir, std::fs::Permissions::from_mode(0o700))
+                .map_err(|e| format!("Failed to restrict {}: {}", dir.display(), e))?;
+        }
+        std::fs::write(dir.join(name), content).map_err(|e| format!("Failed to spool stdin: {}", e))
+    });
+    if let Err(e) = result {
+        eprintln!("{}", e);
+    }
+}
+
+/// Take the oldest spool file for `cwd`, removing stale ones on the way
+fn take_spooled(cwd: &str) -> Option<(Vec<u8>, bool)> {
+    let prefix = format!("{}-", spool_prefix(cwd));
+    let mut candidates: Vec<(String, PathBuf)> = Vec::new();
+    let dir = spool_dir().ok()?;
+    for entry in std::fs::read_dir(dir).ok()?.filter_map(Result::ok) {
+        let path = entry.path();
+        let stale = entry
+            .metadata()
+            .and_then(|metadata| metadata.modified())
+            .ok()
+            .and_then(|modified| modified.elapsed().ok())
+            .is_some_and(|age| age > SPOOL_MAX_AGE);
+        if stale {
+            let _ = std::fs::remove_file(&path);
+            continue;
+        }
+        let name = entry.file_name().to_string_lossy().to_string();
+        if name.starts_with(&prefix) {
+            candidates.push((name, path));
+        }
+    }
+    // Names sort by timestamp within a prefix
+    candidates.sort();
+
+    let (_, path) = candidates.into_iter().next()?;
+    let mut content = std::fs::read(&path).ok()?;
+    let _ = std::fs::remove_file(&path);
+    let truncated = content.pop() == Some(1);
+    Some((content, truncated))
+}
+
+fn queue(
+    app: &AppHandle,
+    source: AttachmentSource,
+    content: Vec<u8>,
+    truncated: bool,
+    prompt: Option<String>,
+    cwd: &str,
+) -> Result<(), String> {
+    let state = app
+        .try_state::<PipedAttachmentState>()
+        .ok_or("Piped attachment state not found")?;
+    let mut pending = state.0.lock().map_err(|e| format!("Lock error: {}", e))?;
+    pending.next_id += 1;
+    let attachment = PipedAttachment {
+        id: pending.next_id,
+        source,
+        content: String::from_utf8_lossy(&content).into_owned(),
+        truncated,
+        prompt,
+        cwd: cwd.to_string(),
+    };
+    let id = attachment.id;
+    pending.attachments.push(attachment);
+    let _ = app.emit("stdin:attachment", id);
+    Ok(())
+}
+
+fn read_pipe(path: &Path) -> Result<(Vec<u8>, bool), String> {
+    let file = std::fs::File::open(path)
+        .map_err(|e| format!("Failed to open {}: {}", path.display(), e))?;
+    read_limited(file)
+}
+
+/// Pick up content piped along with a launch, from this process's own
+/// arguments at startup or from a second instance
+pub fn handle_args(app: &AppHandle, args: &[String], cwd: &str) {
+    let parsed = parse_args(args);
+
+    if parsed.stdin {
+        match take_spooled(cwd) {
+            Some((content, truncated)) => {
+                if let Err(e) = queue(
+                    app,
+                    AttachmentSource::Stdin,
+                    content,
+                    truncated,
+                    parsed.prompt.clone(),
+                    cwd,
+                ) {
+                    eprintln!("Failed to queue piped content: {}", e);
+                }
+            }
+            None => eprintln!("No piped content found for {}", cwd),
+        }
+    }
+
+    if let Some(pipe) = parsed.pipe {
+        // Relative to where the command ran; reading blocks until the writer
+        // closes the pipe
+        let pipe = Path::new(cwd).join(pipe);
+        let app = app.clone();
+        let cwd = cwd.to_string();
+        let prompt = parsed.prompt;
+        let spawned = std::thread::Builder::new()
+            .name("pipe-reader".to_string())
+            .spawn(move || {
+                let result = read_pipe(&pipe).and_then(|(content, truncated)| {
+                    queue(
+                        &app,
+                        AttachmentSource::Pipe,
+                        content,
+                        truncated,
+                        prompt,
+                        &cwd,
+                    )
+                });
+                if let Err(e) = result {
+                    eprintln!("Failed to read from pipe: {}", e);
+                }
+            });
+        if let Err(e) = spawned {
+            eprintln!("Failed to start pipe reader: {}", e);
+        }
+    }
+}
+
+/// Take every attachment piped in that the frontend hasn't picked up yet
+#[tauri::command]
+pub fn take_piped_attachments(app: AppHandle) -> Result<Vec<PipedAttachment>, AppError> {
+    let state = app
+        .try_state::<PipedAttachmentState>()
+        .ok_or("Piped attachment state not found")?;
+    let mut pending = state.0.lock().map_err(|e| format!("Lock error: {}", e))?;
+    Ok(std::mem::take(&mut pending.attachments))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(args: &[&str]) -> Vec<String> {
+        std::iter::once("aura")
+            .chain(args.iter().copied())
+            .map(str::to_string)
+            .collect()
+    }
+
+    #[test]
+    fn test_parse_args() {
+        assert_eq!(
+            parse_args(&args(&["--stdin", "--prompt", "review this"])),
+            PipeArgs {
+                stdin: true,
+                pipe: None,
+                prompt: Some("review this".to_string()),
+            }
+        );
+        assert_eq!(
+            parse_args(&args(&["--pipe=/tmp/fifo", "--prompt=explain"])),
+            PipeArgs {
+                stdin: false,
+                pipe: Some(PathBuf::from("/tmp/fifo")),
+                prompt: Some("explain".to_string()),
+            }
+        );
+        assert_eq!(parse_args(&args(&[])), PipeArgs::default());
+    }
+}
@@ -4,25 +4,37 @@
 //! Parakeet TDT model running via ONNX Runtime.
 
 use ort::{
-    session::{builder::GraphOptimizationLevel, Session},
-    value::TensorRef,
+    memory::MemoryInfo,
+    session::Session,
+    value::{DynValue, Tensor, TensorRef},
 };
 use serde::{Deserialize, Serialize};
 use std::{
     collections::HashMap,
-    path::PathBuf,
+    path::{Path, PathBuf},
     sync::{Arc, Mutex},
 };
 use tauri::{path::BaseDirectory, AppHandle, Emitter, Manager};
-use futures_util::StreamExt;
-use tokio::io::AsyncWriteExt;
 
-const MODEL_NAME: &str = "parakeet-tdt-0.6b-v3";
-const HF_BASE_URL: &str =
-    "https://huggingface.co/istupakov/parakeet-tdt-0.6b-v3-onnx/resolve/main";
+use crate::{
+    app_settings, app_state, audio_levels,
+    error::AppError,
+    i18n,
+    input_gain::{InputGain, InputHint},
+    metered,
+    model_source::ModelDownloader,
+    onnx_runtime, panic_guard, policy, power, recording_recovery,
+    tasks::{self, TaskHandle, TaskKind},
+    stt_dev, stt_remote, stt_updates, transfers,
+    vocabulary_bias::VocabularyBias,
+};
+
+pub(crate) const MODEL_NAME: &str = "parakeet-tdt-0.6b-v3";
+/// HuggingFace repository the model is downloaded from
+pub(crate) const HF_REPO: &str = "istupakov/parakeet-tdt-0.6b-v3-onnx";
 
 /// Model files required for inference
-const MODEL_FILES: &[&str] = &[
+pub(crate) const MODEL_FILES: &[&str] = &[
     "nemo128.onnx",
     "encoder-model.onnx",
     "encoder-model.onnx.data", // ~2.4GB weights file
@@ -40,11 +52,105 @@ pub enum ModelStatus {
     Error { message: String },
 }
 
+/// Duration of one encoder frame (10 ms preprocessor hop, 8x subsampling)
+const ENCODER_FRAME_SECONDS: f32 = 0.08;
+/// Input samples per encoder frame at 16 kHz
+const SAMPLES_PER_FRAME: usize = 1280;
+/// A pause between words at least this long starts a new segment
+const SEGMENT_PAUSE_SECONDS: f32 = 0.8;
+/// Words the model is less sure of than this are flagged for a second look
+const LOW_CONFIDENCE: f32 = 0.6;
+/// Recordings longer than this are transcribed in overlapping chunks so
+/// memory and latency stay bounded
+const MAX_SINGLE_PASS_SAMPLES: usize = 60 * 16_000;
+/// Chunk length and overlap for long-form audio, both whole encoder frames
+const CHUNK_SAMPLES: usize = 50 * 16_000;
+const CHUNK_OVERLAP_SAMPLES: usize = 4 * 16_000;
+/// Warn this long before a recording hits its limit
+const RECORDING_WARNING_LEAD_SAMPLES: usize = 30 * 16_000;
+
+/// Emitted `(token, confidence)` pairs with the encoder frame of each
+type DecodedTokens = Vec<((i64, f32), usize)>;
+
+/// Caps on a single recording, so one that is accidentally left running stops
+/// instead of growing the buffer until the process runs out of memory. The
+/// stricter of the two limits wins.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RecordingLimits {
+    pub max_duration_secs: u32,
+    pub max_buffer_mb: u32,
+}
+
+impl Default for RecordingLimits {
+    fn default() -> Self {
+        Self {
+            max_duration_secs: 60 * 60,
+            max_buffer_mb: 512,
+        }
+    }
+}
+
+impl RecordingLimits {
+    fn max_samples(&self) -> usize {
+        let by_duration = self.max_duration_secs as usize * 16_000;
+        let by_size = self.max_buffer_mb as usize * 1024 * 1024 / std::mem::size_of::<f32>();
+        by_duration.min(by_size).max(16_000)
+    }
+}
+
+/// Payload of `stt:recording-warning`
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RecordingWarning {
+    /// `false` when the limit is close, `true` once recording was auto-stopped
+    pub stopped: bool,
+    pub elapsed_seconds: f32,
+    pub limit_seconds: f32,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TranscriptSegment {
+    /// Start time in seconds from the beginning of the recording
+    pub start: f32,
+    pub end: f32,
+    pub text: String,
+    /// "Speaker 1", "Speaker 2", ... when diarization ran
+    pub speaker: Option<String>,
+    /// How sure the model is of the words on average, from 0 to 1, when the
+    /// backend tells
+    #[serde(default)]
+    pub confidence: Option<f32>,
+    /// The words of `text`, split at whitespace, below `LOW_CONFIDENCE`
+    #[serde(default)]
+    pub uncertain_words: Vec<UncertainWord>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct UncertainWord {
+    pub index: usize,
+    pub confidence: f32,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Transcription {
+    pub text: String,
+    pub segments: Vec<TranscriptSegment>,
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub struct SttStatus {
     pub model_status: ModelStatus,
     pub is_recording: bool,
+    pub is_paused: bool,
+    /// Whether recordings are sent to the user's speech server
+    pub remote: bool,
+    /// What is wrong with the input level of the recording
+    pub input_hint: Option<InputHint>,
 }
 
 /// State for the STT engine
@@ -53,6 +159,16 @@ pub struct SttState {
     audio_buffer: Vec<f32>,
     /// Whether currently recording
     is_recording: bool,
+    /// Whether incoming audio is dropped until the recording is resumed
+    is_paused: bool,
+    /// Counts recordings, so the audio in the buffer can be told apart
+    recording_id: u64,
+    recording_limits: RecordingLimits,
+    /// Whether the approaching-limit warning was already sent for this recording
+    limit_warned: bool,
+    input_gain: InputGain,
+    /// Whether the input hint changed since `take_hint_change`
+    hint_changed: bool,
     /// ONNX session for the preprocessor (nemo128)
     preprocessor_session: Option<Arc<Mutex<Session>>>,
     /// ONNX session for the encoder
@@ -65,10 +181,14 @@ pub struct SttState {
     vocab_size: usize,
     /// Blank token index
     blank_idx: i64,
+    /// Terms of the user's that decoding is biased towards
+    vocabulary: Vec<String>,
     /// Model status
     model_status: ModelStatus,
     /// Path to model directory
     model_dir: PathBuf,
+    /// What transcribes recordings in place of the model, as in development
+    backend: Option<SttInference>,
 }
 
 impl SttState {
@@ -76,14 +196,22 @@ impl SttState {
         let mut state = Self {
             audio_buffer: Vec::new(),
             is_recording: false,
+            is_paused: false,
+            recording_id: 0,
+            recording_limits: RecordingLimits::default(),
+            limit_warned: false,
+            input_gain: InputGain::default(),
+            hint_changed: false,
             preprocessor_session: None,
             encoder_session: None,
             decoder_session: None,
             vocab: Arc::new(HashMap::new()),
             vocab_size: 0,
             blank_idx: 0,
+            vocabulary: Vec::new(),
             model_status: ModelStatus::NotDownloaded,
             model_dir,
+            backend: None,
         };
 
         // If models are already downloaded, load them
@@ -104,28 +232,126 @@ impl SttState {
         SttStatus {
             model_status: self.model_status.clone(),
             is_recording: self.is_recording,
+            is_paused: self.is_paused,
+            remote: self.backend.as_ref().is_some_and(|backend| backend.is_remote()),
+            input_hint: self.input_gain.hint(),
         }
     }
 
-    pub fn start_recording(&mut self) -> Result<(), String> {
+    pub fn start_recording(&mut self) -> Result<(), AppError> {
         if !matches!(self.model_status, ModelStatus::Ready) {
-            return Err("Model not ready. Please download the model first.".to_string());
+            return Err(AppError::unavailable("Model not ready. Please download the model first."));
         }
         self.audio_buffer.clear();
         self.is_recording = true;
+        self.is_paused = false;
+        self.recording_id += 1;
+        self.limit_warned = false;
+        self.input_gain.reset();
         Ok(())
     }
 
-    pub fn push_audio(&mut self, samples: Vec<f32>) -> Result<(), String> {
+    /// Stop accepting audio without discarding what was recorded so far, so
+    /// the pause doesn't end up as a long silent gap in the transcript
+    pub fn pause_recording(&mut self) -> Result<(), AppError> {
+        if !self.is_recording {
+            return Err(AppError::invalid_input("Not recording"));
+        }
+        self.is_paused = true;
+        Ok(())
+    }
+
+    /// Whether pushed audio goes into the recording
+    pub fn accepts_audio(&self) -> bool {
+        self.is_recording && !self.is_paused
+    }
+
+    pub fn recording_id(&self) -> u64 {
+        self.recording_id
+    }
+
+    /// Audio of recording `id` from sample `from` on; `None` once it left the
+    /// buffer, taken for transcription or dropped for another recording
+    pub fn recorded_since(&self, id: u64, from: usize) -> Option<&[f32]> {
+        if id != self.recording_id || (!self.is_recording && self.audio_buffer.is_empty()) {
+            return None;
+        }
+        Some(&self.audio_buffer[from.min(self.audio_buffer.len())..])
+    }
+
+    pub fn resume_recording(&mut self) -> Result<(), AppError> {
         if !self.is_recording {
-            return Err("Not recording".to_string());
+            return Err(AppError::invalid_input("Not recording"));
         }
-        self.audio_buffer.extend(samples);
+        self.is_paused = false;
         Ok(())
     }
 
+    pub fn recording_limits(&self) -> RecordingLimits {
+        self.recording_limits
+    }
+
+    pub fn set_recording_limits(&mut self, limits: RecordingLimits) {
+        self.recording_limits = limits;
+    }
+
+    pub fn set_vocabulary(&mut self, terms: Vec<String>) {
+        self.vocabulary = terms;
+    }
+
+    pub fn set_auto_gain(&mut self, enabled: bool) {
+        self.input_gain.set_enabled(enabled);
+    }
+
+    /// Whether the input hint changed since last asked, so the status needs
+    /// publishing
+    pub fn take_hint_change(&mut self) -> bool {
+        std::mem::take(&mut self.hint_changed)
+    }
+
+    /// Append samples, gain-controlled, returning a warning when the recording
+    /// nears or hits its limit. At the limit recording stops; the buffer is
+    /// kept for transcription.
+    pub fn push_audio(
+        &mut self,
+        mut samples: Vec<f32>,
+    ) -> Result<Option<RecordingWarning>, AppError> {
+        if !self.is_recording {
+            return Err(AppError::invalid_input("Not recording"));
+        }
+        if self.is_paused {
+            return Ok(None);
+        }
+        if self.input_gain.process(&mut samples) {
+            self.hint_changed = true;
+        }
+
+        let max_samples = self.recording_limits.max_samples();
+        let room = max_samples.saturating_sub(self.audio_buffer.len());
+        self.audio_buffer
+            .extend_from_slice(&samples[..samples.len().min(room)]);
+
+        let warning = |stopped| RecordingWarning {
+            stopped,
+            elapsed_seconds: self.audio_buffer.len() as f32 / 16_000.0,
+            limit_seconds: max_samples as f32 / 16_000.0,
+        };
+        if self.audio_buffer.len() >= max_samples {
+            self.is_recording = false;
+            return Ok(Some(warning(true)));
+        }
+        if !self.limit_warned
+            && self.audio_buffer.len() + RECORDING_WARNING_LEAD_SAMPLES >= max_samples
+        {
+            self.limit_warned = true;
+            return Ok(Some(warning(false)));
+        }
+        Ok(None)
+    }
+
     pub fn stop_recording(&mut self) -> Vec<f32> {
         self.is_recording = false;
+        self.is_paused = false;
         std::mem::take(&mut self.audio_buffer)
     }
 
@@ -164,41 +390,14 @@ impl SttState {
         let encoder_path = model_dir.join("encoder-model.onnx");
         let decoder_path = model_dir.join("decoder_joint-model.onnx");
 
-        // Initialize ONNX Runtime
-        ort::init()
-            .with_name("opencode-stt")
-            .commit()
-            .map_err(|e| format!("Failed to initialize ONNX Runtime: {}", e))?;
-
         // Load preprocessor session
-        let preprocessor_session = Session::builder()
-            .map_err(|e| format!("Failed to create preprocessor session builder: {}", e))?
-            .with_optimization_level(GraphOptimizationLevel::Level3)
-            .map_err(|e| format!("Failed to set optimization level: {}", e))?
-            .with_intra_threads(4)
-            .map_err(|e| format!("Failed to set intra threads: {}", e))?
-            .commit_from_file(&preprocessor_path)
-            .map_err(|e| format!("Failed to load preprocessor model: {}", e))?;
+        let preprocessor_session = onnx_runtime::build_session(&preprocessor_path, "preprocessor")?;
 
         // Load encoder session
-        let encoder_session = Session::builder()
-            .map_err(|e| format!("Failed to create encoder session builder: {}", e))?
-            .with_optimization_level(GraphOptimizationLevel::Level3)
-            .map_err(|e| format!("Failed to set optimization level: {}", e))?
-            .with_intra_threads(4)
-            .map_err(|e| format!("Failed to set intra threads: {}", e))?
-            .commit_from_file(&encoder_path)
-            .map_err(|e| format!("Failed to load encoder model: {}", e))?;
+        let encoder_session = onnx_runtime::build_session(&encoder_path, "encoder")?;
 
         // Load decoder session
-        let decoder_session = Session::builder()
-            .map_err(|e| format!("Failed to create decoder session builder: {}", e))?
-            .with_optimization_level(GraphOptimizationLevel::Level3)
-            .map_err(|e| format!("Failed to set optimization level: {}", e))?
-            .with_intra_threads(4)
-            .map_err(|e| format!("Failed to set intra threads: {}", e))?
-            .commit_from_file(&decoder_path)
-            .map_err(|e| format!("Failed to load decoder model: {}", e))?;
+        let decoder_session = onnx_runtime::build_session(&decoder_path, "decoder")?;
 
         Ok(LoadedModels {
             preprocessor: Arc::new(Mutex::new(preprocessor_session)),
@@ -218,6 +417,10 @@ impl SttState {
         self.vocab_size = models.vocab_size;
         self.blank_idx = models.blank_idx;
         self.model_status = ModelStatus::Ready;
+        // The speech server only stands in until the model is there
+        if self.backend.as_ref().is_some_and(|backend| backend.is_remote()) {
+            self.backend = None;
+        }
     }
 
     pub fn load_models(&mut self) -> Result<(), String> {
@@ -226,7 +429,71 @@ impl SttState {
         Ok(())
     }
 
+    /// Whether the model is in memory
+    pub fn models_loaded(&self) -> bool {
+        self.preprocessor_session.is_some() || self.backend.is_some()
+    }
+
+    /// Transcribe recordings with `backend` instead of the model
+    pub fn use_backend(&mut self, backend: SttInference) {
+        self.backend = Some(backend);
+        self.model_status = ModelStatus::Ready;
+    }
+
+    /// Transcribe recordings with the model again
+    pub fn use_model(&mut self) {
+        if self.backend.take().is_none() {
+            return;
+        }
+        self.model_status = if Self::are_models_downloaded(&self.model_dir) {
+            ModelStatus::Ready
+        } else {
+            ModelStatus::NotDownloaded
+        };
+    }
+
+    /// Free the memory of the model. It stays ready and is loaded again when
+    /// a recording starts; transcriptions in progress hold on to it until
+    /// they finish.
+    pub fn unload_models(&mut self) -> Result<(), AppError> {
+        if self.is_recording {
+            return Err(AppError::busy("Can't unload the speech model while recording"));
+        }
+        self.preprocessor_session = None;
+        self.encoder_session = None;
+        self.decoder_session = None;
+        self.vocab = Arc::new(HashMap::new());
+        Ok(())
+    }
+
+    /// Unload the model so its files can be replaced. The weights are
+    /// memory-mapped, and Windows won't replace a mapped file, so this fails
+    /// while a transcription still holds the sessions.
+    pub(crate) fn release_models(&mut self) -> Result<(), AppError> {
+        let in_use = [
+            &self.preprocessor_session,
+            &self.encoder_session,
+            &self.decoder_session,
+        ]
+        .iter()
+        .any(|session| session.as_ref().is_some_and(|session| Arc::strong_count(session) > 1));
+        if in_use {
+            return Err(AppError::busy(
+                "Can't reload the speech model while a transcription is running",
+            ));
+        }
+        self.unload_models()
+    }
+
+    /// Load the model from `dir` from now on, returning where it was
+    pub(crate) fn replace_model_dir(&mut self, dir: PathBuf) -> PathBuf {
+        std::mem::replace(&mut self.model_dir, dir)
+    }
+
     pub fn inference(&self) -> Result<SttInference, String> {
+        if let Some(backend) = &self.backend {
+            return Ok(backend.clone());
+        }
         let preprocessor = self
             .preprocessor_session
             .as_ref()
@@ -243,14 +510,15 @@ impl SttState {
             .ok_or("Decoder not loaded")?
             .clone();
 
-        Ok(SttInference {
+        Ok(Arc::new(OnnxBackend {
             preprocessor,
             encoder,
             decoder,
             vocab: self.vocab.clone(),
             vocab_size: self.vocab_size,
             blank_idx: self.blank_idx,
-        })
+            bias: VocabularyBias::new(&self.vocabulary, &self.vocab),
+        }))
     }
 }
 
@@ -263,21 +531,112 @@ struct LoadedModels {
     blank_idx: i64,
 }
 
-pub struct SttInference {
+/// Something that turns a recording into text
+pub trait SttBackend: Send + Sync {
+    /// Transcribe 16 kHz mono `audio`. `on_progress` receives the fraction
+    /// done, and an error from it stops the transcription there.
+    fn transcribe(
+        &self,
+        audio: &[f32],
+        on_progress: &mut dyn FnMut(f32) -> Result<(), String>,
+    ) -> Result<Transcription, String>;
+
+    /// Whether recordings leave the device
+    fn is_remote(&self) -> bool {
+        false
+    }
+}
+
+/// The backend a recording is transcribed with
+pub type SttInference = Arc<dyn SttBackend>;
+
+/// The Parakeet model, run with ONNX Runtime
+pub struct OnnxBackend {
     preprocessor: Arc<Mutex<Session>>,
     encoder: Arc<Mutex<Session>>,
     decoder: Arc<Mutex<Session>>,
     vocab: Arc<HashMap<i64, String>>,
     vocab_size: usize,
     blank_idx: i64,
+    bias: VocabularyBias,
+}
+
+/// Encoder output for one pass, frame-major: `frames[t * dim..(t + 1) * dim]`
+struct EncodedAudio {
+    frames: Vec<f32>,
+    dim: usize,
+    /// Number of valid frames
+    len: usize,
+}
+
+impl SttBackend for OnnxBackend {
+    /// Transcribe and split the result into timed segments at pauses and sentence ends.
+    ///
+    /// Long recordings are split into overlapping chunks that are decoded one
+    /// after another; `on_progress` receives the fraction of chunks done.
+    fn transcribe(
+        &self,
+        audio: &[f32],
+        on_progress: &mut dyn FnMut(f32) -> Result<(), String>,
+    ) -> Result<Transcription, String> {
+        let tokens = if audio.len() <= MAX_SINGLE_PASS_SAMPLES {
+            self.decode(audio)?
+        } else {
+            let chunks = chunk_ranges(audio.len());
+            let mut chunk_tokens = Vec::with_capacity(chunks.len());
+            for (index, &(start, end)) in chunks.iter().enumerate() {
+                chunk_tokens.push(self.decode(&audio[start..end])?);
+                on_progress((index + 1) as f32 / chunks.len() as f32)?;
+            }
+            stitch_chunks(&chunks, chunk_tokens, |(token, _)| {
+                self.vocab
+                    .get(&token)
+                    .is_some_and(|token| token.starts_with(' '))
+            })
+        };
+        on_progress(1.0)?;
+
+        // Decode tokens to text
+        let mut text = String::new();
+        for ((token_id, _), _) in &tokens {
+            if let Some(token_str) = self.vocab.get(token_id) {
+                text.push_str(token_str);
+            }
+        }
+
+        // Clean up whitespace (SentencePiece style)
+        let text = text.split_whitespace().collect::<Vec<_>>().join(" ");
+
+        let timed_tokens: Vec<(&str, usize, f32)> = tokens
+            .iter()
+            .filter_map(|((token_id, confidence), frame)| {
+                self.vocab
+                    .get(token_id)
+                    .map(|token| (token.as_str(), *frame, *confidence))
+            })
+            .collect();
+
+        Ok(Transcription {
+            text,
+            segments: build_segments(&timed_tokens),
+        })
+    }
 }
 
-impl SttInference {
-    pub fn transcribe(&self, audio: &[f32]) -> Result<String, String> {
+impl OnnxBackend {
+    /// Run a single preprocess/encode/decode pass, returning each emitted token
+    /// and its confidence with the encoder frame it was emitted at
+    fn decode(&self, audio: &[f32]) -> Result<DecodedTokens, String> {
         if audio.is_empty() {
-            return Ok(String::new());
+            return Ok(Vec::new());
         }
+        let encoded = self.encode(audio)?;
+        self.decode_frames(&encoded)
+    }
 
+    /// Preprocess and encode audio. The preprocessor and encoder locks are
+    /// released before decoding starts.
+    fn encode(&self, audio: &[f32]) -> Result<EncodedAudio, String> {
         // Step 1: Preprocess audio to mel features using nemo128.onnx
         // Input: waveforms [batch, samples], waveforms_lens [batch]
         // Output: features [batch, frames, 128], features_lens [batch]
@@ -292,10 +651,7 @@ impl SttInference {
         let waveforms_lens_tensor = TensorRef::from_array_view(waveforms_lens.view())
             .map_err(|e| format!("Failed to create waveforms_lens tensor: {}", e))?;
 
-        let mut preprocessor = self
-            .preprocessor
-            .lock()
-            .map_err(|e| format!("Lock error: {}", e))?;
+        let mut preprocessor = panic_guard::lock(&self.preprocessor);
         let preprocessor_outputs = preprocessor
             .run(ort::inputs![
                 "waveforms" => waveforms_tensor,
@@ -322,7 +678,7 @@ impl SttInference {
         let features_lens_tensor = TensorRef::from_array_view(features_lens)
             .map_err(|e| format!("Failed to create features_lens tensor: {}", e))?;
 
-        let mut encoder = self.encoder.lock().map_err(|e| format!("Lock error: {}", e))?;
+        let mut encoder = panic_guard::lock(&self.encoder);
         let encoder_outputs = encoder
             .run(ort::inputs![
                 "audio_signal" => features_tensor,
@@ -337,136 +693,141 @@ impl SttInference {
             .try_extract_tensor::<i64>()
             .map_err(|e| format!("Failed to extract encoded_lengths: {}", e))?;
 
-        let encoder_shape: Vec<usize> = encoder_out_data.0.iter().map(|&x| x as usize).collect();
-        let encoder_out = ndarray::ArrayViewD::from_shape(encoder_shape.clone(), encoder_out_data.1)
-            .map_err(|e| format!("Failed to create encoder array: {}", e))?;
-        let encoder_lens = ndarray::ArrayView1::from(encoder_lens_data.1);
-
         // Get encoder output shape - [batch, dim, frames]
-        let encoded_dim = encoder_shape[1];
+        let encoder_shape: Vec<usize> = encoder_out_data.0.iter().map(|&x| x as usize).collect();
+        let dim = encoder_shape[1];
         let num_frames = encoder_shape[2];
+        let len = (encoder_lens_data.1[0] as usize).min(num_frames);
+
+        // Transpose to frame-major once so each decoder step reads one
+        // contiguous slice instead of gathering `dim` strided values
+        let mut frames = vec![0f32; len * dim];
+        for (d, row) in encoder_out_data.1.chunks(num_frames.max(1)).take(dim).enumerate() {
+            for (t, &value) in row[..len].iter().enumerate() {
+                frames[t * dim + d] = value;
+            }
+        }
+
+        Ok(EncodedAudio { frames, dim, len })
+    }
 
-        // Step 3: TDT Decoding
-        // Initialize LSTM hidden states
+    /// TDT greedy decoding over the encoder frames.
+    ///
+    /// This is the hot loop (one decoder run per frame or emitted token), so
+    /// all inputs are allocated once and bound through a reused `IoBinding`;
+    /// per step only the changed inputs are rewritten in place and re-bound,
+    /// and the LSTM states returned by the decoder are bound straight back as
+    /// inputs instead of being copied.
+    fn decode_frames(&self, encoded: &EncodedAudio) -> Result<DecodedTokens, String> {
         // Parakeet TDT uses 2 LSTM layers with hidden_size=640
         // State shape: [num_layers, batch_size, hidden_size]
         const NUM_LSTM_LAYERS: usize = 2;
         const LSTM_HIDDEN_SIZE: usize = 640;
+        const MAX_TOKENS_PER_STEP: usize = 10;
+
+        let state_shape = [NUM_LSTM_LAYERS, 1, LSTM_HIDDEN_SIZE];
+        let state_len = NUM_LSTM_LAYERS * LSTM_HIDDEN_SIZE;
+        let mut encoder_frame = Tensor::from_array(([1, encoded.dim, 1], vec![0f32; encoded.dim]))
+            .map_err(|e| format!("Failed to create encoder_frame tensor: {}", e))?;
+        let mut targets = Tensor::from_array(([1, 1], vec![self.blank_idx as i32]))
+            .map_err(|e| format!("Failed to create targets tensor: {}", e))?;
+        let target_length = Tensor::from_array(([1], vec![1i32]))
+            .map_err(|e| format!("Failed to create target_length tensor: {}", e))?;
+        let mut state1: DynValue = Tensor::from_array((state_shape, vec![0f32; state_len]))
+            .map_err(|e| format!("Failed to create state1 tensor: {}", e))?
+            .into_dyn();
+        let mut state2: DynValue = Tensor::from_array((state_shape, vec![0f32; state_len]))
+            .map_err(|e| format!("Failed to create state2 tensor: {}", e))?
+            .into_dyn();
+
+        let mut decoder = panic_guard::lock(&self.decoder);
+        let mut binding = decoder
+            .create_binding()
+            .map_err(|e| format!("Failed to create decoder binding: {}", e))?;
+        let bind_error = |e: ort::Error| format!("Failed to bind decoder input: {}", e);
+        binding
+            .bind_input("target_length", &target_length)
+            .map_err(bind_error)?;
+        binding
+            .bind_input("input_states_1", &state1)
+            .map_err(bind_error)?;
+        binding
+            .bind_input("input_states_2", &state2)
+            .map_err(bind_error)?;
+        for output in ["outputs", "output_states_1", "output_states_2"] {
+            binding
+                .bind_output_to_device(output, &MemoryInfo::default())
+                .map_err(|e| format!("Failed to bind decoder output: {}", e))?;
+        }
 
-        let mut state1 = ndarray::Array3::<f32>::zeros((NUM_LSTM_LAYERS, 1, LSTM_HIDDEN_SIZE));
-        let mut state2 = ndarray::Array3::<f32>::zeros((NUM_LSTM_LAYERS, 1, LSTM_HIDDEN_SIZE));
-
-        let mut tokens: Vec<i64> = Vec::new();
+        let mut tokens: DecodedTokens = Vec::new();
         let mut t = 0usize;
-        let max_tokens_per_step = 10;
         let mut emitted_tokens = 0;
-        let encoded_len = encoder_lens[0] as usize;
-
-        let mut encoder_frame = ndarray::Array3::<f32>::zeros((1, encoded_dim, 1));
-        let mut targets = ndarray::Array2::<i32>::zeros((1, 1));
-        let target_length = ndarray::arr1(&[1i32]);
-        let mut decoder = self.decoder.lock().map_err(|e| format!("Lock error: {}", e))?;
-
-        while t < encoded_len && t < num_frames {
-            // Get encoder output at frame t: shape [1, dim, 1]
-            {
-                let encoder_frame_slice = encoder_frame
-                    .as_slice_mut()
-                    .ok_or("Failed to access encoder frame slice")?;
-                for d in 0..encoded_dim {
-                    encoder_frame_slice[d] = encoder_out[[0, d, t]];
-                }
+        let mut loaded_frame = None;
+        let mut prev_token = self.blank_idx;
+        // Terms of the vocabulary the last tokens partly match
+        let mut active_terms = Vec::new();
+
+        while t < encoded.len {
+            if loaded_frame != Some(t) {
+                let (_, frame) = encoder_frame.extract_tensor_mut();
+                frame.copy_from_slice(&encoded.frames[t * encoded.dim..(t + 1) * encoded.dim]);
+                binding
+                    .bind_input("encoder_outputs", &encoder_frame)
+                    .map_err(bind_error)?;
+                loaded_frame = Some(t);
             }
+            let (_, target) = targets.extract_tensor_mut();
+            target[0] = prev_token as i32;
+            binding.bind_input("targets", &targets).map_err(bind_error)?;
 
-            let prev_token = if tokens.is_empty() {
-                self.blank_idx as i32
-            } else {
-                tokens[tokens.len() - 1] as i32
-            };
-            targets[[0, 0]] = prev_token;
-
-            // Create tensors for decoder
-            let encoder_frame_tensor = TensorRef::from_array_view(encoder_frame.view())
-                .map_err(|e| format!("Failed to create encoder_frame tensor: {}", e))?;
-            let targets_tensor = TensorRef::from_array_view(targets.view())
-                .map_err(|e| format!("Failed to create targets tensor: {}", e))?;
-            let target_length_tensor = TensorRef::from_array_view(target_length.view())
-                .map_err(|e| format!("Failed to create target_length tensor: {}", e))?;
-            let state1_tensor = TensorRef::from_array_view(state1.view())
-                .map_err(|e| format!("Failed to create state1 tensor: {}", e))?;
-            let state2_tensor = TensorRef::from_array_view(state2.view())
-                .map_err(|e| format!("Failed to create state2 tensor: {}", e))?;
-
-            let decoder_outputs = decoder
-                .run(ort::inputs![
-                    "encoder_outputs" => encoder_frame_tensor,
-                    "targets" => targets_tensor,
-                    "target_length" => target_length_tensor,
-                    "input_states_1" => state1_tensor,
-                    "input_states_2" => state2_tensor
-                ])
+            let mut decoder_outputs = decoder
+                .run_binding(&binding)
                 .map_err(|e| format!("Failed to run decoder: {}", e))?;
 
-            // Access outputs by name to ensure correct order
-            let outputs_data = decoder_outputs["outputs"]
-                .try_extract_tensor::<f32>()
-                .map_err(|e| format!("Failed to extract decoder outputs: {}", e))?;
-            let new_state1_data = decoder_outputs["output_states_1"]
-                .try_extract_tensor::<f32>()
-                .map_err(|e| format!("Failed to extract state1: {}", e))?;
-            let new_state2_data = decoder_outputs["output_states_2"]
-                .try_extract_tensor::<f32>()
-                .map_err(|e| format!("Failed to extract state2: {}", e))?;
-
-            let outputs_flat: &[f32] = outputs_data.1;
-
-            // TDT: first vocab_size elements are token logits, rest are duration info
-            let token_logits = &outputs_flat[..self.vocab_size];
-            let duration_logits = &outputs_flat[self.vocab_size..];
-
-            // Get best token
-            let token = token_logits
-                .iter()
-                .enumerate()
-                .max_by(|(_, a): &(usize, &f32), (_, b): &(usize, &f32)| {
-                    a.partial_cmp(b).unwrap_or(std::cmp::Ordering::Equal)
-                })
-                .map(|(i, _)| i as i64)
-                .unwrap_or(self.blank_idx);
-
-            // Get step from duration logits (TDT specific)
-            let step = if duration_logits.is_empty() {
-                0
-            } else {
-                duration_logits
-                    .iter()
-                    .enumerate()
-                    .max_by(|(_, a): &(usize, &f32), (_, b): &(usize, &f32)| {
-                        a.partial_cmp(b).unwrap_or(std::cmp::Ordering::Equal)
-                    })
-                    .map(|(i, _)| i)
-                    .unwrap_or(0)
+            let (token, confidence, step) = {
+                let (_, outputs_flat) = decoder_outputs["outputs"]
+                    .try_extract_tensor::<f32>()
+                    .map_err(|e| format!("Failed to extract decoder outputs: {}", e))?;
+
+                // TDT: first vocab_size elements are token logits, rest are duration info
+                let token = self
+                    .bias
+                    .pick(
+                        &outputs_flat[..self.vocab_size],
+                        self.blank_idx as usize,
+                        &active_terms,
+                    )
+                    .map(|i| i as i64)
+                    .unwrap_or(self.blank_idx);
+                let step = argmax(&outputs_flat[self.vocab_size..]).unwrap_or(0);
+                let confidence = if token == self.blank_idx {
+                    0.0
+                } else {
+                    softmax_at(&outputs_flat[..self.vocab_size], token as usize)
+                };
+                (token, confidence, step)
             };
 
             if token != self.blank_idx {
                 // Update state only when emitting a token
-                let state1_slice = state1
-                    .as_slice_mut()
-                    .ok_or("Failed to access state1 slice")?;
-                if state1_slice.len() != new_state1_data.1.len() {
-                    return Err("State1 size mismatch".to_string());
-                }
-                state1_slice.copy_from_slice(new_state1_data.1);
-
-                let state2_slice = state2
-                    .as_slice_mut()
-                    .ok_or("Failed to access state2 slice")?;
-                if state2_slice.len() != new_state2_data.1.len() {
-                    return Err("State2 size mismatch".to_string());
-                }
-                state2_slice.copy_from_slice(new_state2_data.1);
-
-                tokens.push(token);
+                state1 = decoder_outputs
+                    .remove("output_states_1")
+                    .ok_or("Decoder returned no output_states_1")?;
+                state2 = decoder_outputs
+                    .remove("output_states_2")
+                    .ok_or("Decoder returned no output_states_2")?;
+                drop(decoder_outputs);
+                binding
+                    .bind_input("input_states_1", &state1)
+                    .map_err(bind_error)?;
+                binding
+                    .bind_input("input_states_2", &state2)
+                    .map_err(bind_error)?;
+
+                tokens.push(((token, confidence), t));
+                self.bias.advance(&mut active_terms, token as usize);
+                prev_token = token;
                 emitted_tokens += 1;
             }
 
@@ -476,112 +837,413 @@ impl SttInference {
                 emitted_tokens = 0;
                 continue;
             }
-            if token == self.blank_idx || emitted_tokens >= max_tokens_per_step {
+            if token == self.blank_idx || emitted_tokens >= MAX_TOKENS_PER_STEP {
                 t += 1;
                 emitted_tokens = 0;
             }
         }
 
-        // Decode tokens to text
-        let mut text = String::new();
-        for token_id in tokens {
-            if let Some(token_str) = self.vocab.get(&token_id) {
-                text.push_str(token_str);
+        Ok(tokens)
+    }
+}
+
+fn argmax(values: &[f32]) -> Option<usize> {
+    values
+        .iter()
+        .enumerate()
+        .max_by(|(_, a), (_, b)| a.total_cmp(b))
+        .map(|(i, _)| i)
+}
+
+/// The probability softmax gives `logits[index]`
+fn softmax_at(logits: &[f32], index: usize) -> f32 {
+    let max = logits.iter().copied().fold(f32::NEG_INFINITY, f32::max);
+    let sum: f32 = logits.iter().map(|logit| (logit - max).exp()).sum();
+    logits.get(index).map_or(0.0, |logit| (logit - max).exp() / sum)
+}
+
+/// Sample ranges of the overlapping chunks covering `len` samples
+fn chunk_ranges(len: usize) -> Vec<(usize, usize)> {
+    let step = CHUNK_SAMPLES - CHUNK_OVERLAP_SAMPLES;
+    let mut ranges = Vec::new();
+    let mut start = 0;
+    loop {
+        let end = (start + CHUNK_SAMPLES).min(len);
+        ranges.push((start, end));
+        if end == len {
+            break;
+        }
+        start += step;
+    }
+    ranges
+}
+
+/// Merge per-chunk tokens into one stream with absolute frames. Each overlap
+/// is split at its midpoint and a word is kept by whichever chunk it starts
+/// in, so words cut off at a chunk edge come from the neighbouring chunk.
+fn stitch_chunks<T: Copy>(
+    chunks: &[(usize, usize)],
+    chunk_tokens: Vec<Vec<(T, usize)>>,
+    starts_word: impl Fn(T) -> bool,
+) -> Vec<(T, usize)> {
+    let half_overlap = CHUNK_OVERLAP_SAMPLES / 2 / SAMPLES_PER_FRAME;
+    let mut stitched = Vec::new();
+
+    for (index, (&(start, _), tokens)) in chunks.iter().zip(chunk_tokens).enumerate() {
+        let offset = start / SAMPLES_PER_FRAME;
+        let keep_from = if index == 0 { 0 } else { offset + half_overlap };
+        let keep_until = match chunks.get(index + 1) {
+            Some(&(next_start, _)) => next_start / SAMPLES_PER_FRAME + half_overlap,
+            None => usize::MAX,
+        };
+
+        let mut keep_word = false;
+        for (position, (token, frame)) in tokens.into_iter().enumerate() {
+            let frame = frame + offset;
+            if position == 0 || starts_word(token) {
+                keep_word = (keep_from..keep_until).contains(&frame);
+            }
+            if keep_word {
+                stitched.push((token, frame));
             }
         }
+    }
 
-        // Clean up whitespace (SentencePiece style)
-        let text = text.trim().split_whitespace().collect::<Vec<_>>().join(" ");
+    stitched
+}
 
-        Ok(text)
+/// Group `(token, frame, confidence)` into segments, breaking at
+/// sentence-ending punctuation or long pauses between words. A word is as
+/// certain as its least certain token.
+fn build_segments(tokens: &[(&str, usize, f32)]) -> Vec<TranscriptSegment> {
+    let pause_frames = (SEGMENT_PAUSE_SECONDS / ENCODER_FRAME_SECONDS) as usize;
+    let mut segments = Vec::new();
+    let mut current = String::new();
+    let mut words: Vec<f32> = Vec::new();
+    let mut start_frame = 0;
+    let mut last_frame = 0;
+
+    let mut flush = |current: &mut String, words: &mut Vec<f32>, start_frame, last_frame| {
+        let text = current.split_whitespace().collect::<Vec<_>>().join(" ");
+        if !text.is_empty() {
+            segments.push(TranscriptSegment {
+                start: start_frame as f32 * ENCODER_FRAME_SECONDS,
+                end: (last_frame + 1) as f32 * ENCODER_FRAME_SECONDS,
+                text,
+                speaker: None,
+                confidence: Some(words.iter().sum::<f32>() / words.len().max(1) as f32),
+                uncertain_words: words
+                    .iter()
+                    .enumerate()
+                    .filter(|(_, confidence)| **confidence < LOW_CONFIDENCE)
+                    .map(|(index, &confidence)| UncertainWord { index, confidence })
+                    .collect(),
+            });
+        }
+        current.clear();
+        words.clear();
+    };
+
+    for &(token, frame, confidence) in tokens {
+        let starts_word = token.starts_with(' ');
+        if starts_word && !current.trim().is_empty() {
+            let sentence_end = current.trim_end().ends_with(['.', '?', '!']);
+            if sentence_end || frame.saturating_sub(last_frame) >= pause_frames {
+                flush(&mut current, &mut words, start_frame, last_frame);
+            }
+        }
+        if current.trim().is_empty() {
+            start_frame = frame;
+        }
+        if !token.trim().is_empty() {
+            // A word starts after whitespace, as `split_whitespace` has it
+            match words.last_mut() {
+                Some(word) if !current.ends_with(char::is_whitespace) && !starts_word => {
+                    *word = word.min(confidence);
+                }
+                _ => words.push(confidence),
+            }
+        }
+        current.push_str(token);
+        last_frame = frame;
     }
+    flush(&mut current, &mut words, start_frame, last_frame);
+
+    segments
 }
 
-pub type SharedSttState = Arc<Mutex<SttState>>;
+/// Behind an async mutex, so callers wait for a model load without holding
+/// a runtime thread, and a panic while it is held doesn't poison it
+pub type SharedSttState = Arc<tokio::sync::Mutex<SttState>>;
+
+pub fn emit_recording_warning(app: &AppHandle, warning: &RecordingWarning) {
+    let _ = app.emit("stt:recording-warning", warning);
+    if warning.stopped {
+        let app = app.clone();
+        tauri::async_runtime::spawn(async move { app_state::publish_stt(&app).await });
+    }
+}
 
 /// Get the model directory path
 pub fn get_model_dir(app: &AppHandle) -> PathBuf {
-    app.path()
-        .resolve(
-            format!("models/{}", MODEL_NAME),
-            BaseDirectory::AppLocalData,
-        )
-        .expect("Failed to resolve model directory")
+    let models = app
+        .path()
+        .resolve("models", BaseDirectory::AppLocalData)
+        .expect("Failed to resolve model directory");
+    stt_updates::model_dir(&models)
 }
 
-/// Initialize STT state
-pub fn init_stt_state(app: &AppHandle) -> SharedSttState {
-    let model_dir = get_model_dir(app);
-    Arc::new(Mutex::new(SttState::new(model_dir)))
+/// Start recording, loading the models first if they were unloaded. That
+/// takes seconds, so it runs off the async runtime.
+pub async fn start_recording(app: &AppHandle) -> Result<(), AppError> {
+    let state = app
+        .try_state::<SharedSttState>()
+        .ok_or("STT state not found")?
+        .inner()
+        .clone();
+    let recording = tauri::async_runtime::spawn_blocking(move || {
+        let mut state = state.blocking_lock();
+        if matches!(state.model_status, ModelStatus::Ready) && !state.models_loaded() {
+            state.load_models()?;
+        }
+        state.start_recording()?;
+        Ok::<_, AppError>(state.recording_id())
+    })
+    .await
+    .map_err(panic_guard::join_error)??;
+    audio_levels::reset(app);
+    recording_recovery::spawn_checkpoints(app, recording);
+    Ok(())
 }
 
-/// Download a single model file with streaming (avoids loading entire file into memory)
-async fn download_file(client: &reqwest::Client, url: &str, path: &PathBuf) -> Result<(), String> {
-    let response = client
-        .get(url)
-        .send()
+/// What transcribes a recording, loading the models first if they were
+/// unloaded. That takes seconds, so it runs off the async runtime.
+pub async fn loaded_inference(app: &AppHandle) -> Result<SttInference, AppError> {
+    let state = app
+        .try_state::<SharedSttState>()
+        .ok_or("STT state not found")?
+        .inner()
+        .clone();
+    tauri::async_runtime::spawn_blocking(move || {
+        let mut state = state.blocking_lock();
+        if matches!(state.model_status, ModelStatus::Ready) && !state.models_loaded() {
+            state.load_models()?;
+        }
+        state.inference().map_err(AppError::unavailable)
+    })
+    .await
+    .map_err(panic_guard::join_error)?
+}
+
+/// Load the model from disk again, as after its files were replaced or
+/// repaired. The old sessions go first, so nothing maps the files while the
+/// new ones load.
+pub async fn reload_models(app: &AppHandle) -> Result<(), AppError> {
+    let state = app
+        .try_state::<SharedSttState>()
+        .ok_or("STT state not found")?
+        .inner()
+        .clone();
+    let model_dir = {
+        let mut state = state.lock().await;
+        if matches!(state.model_status, ModelStatus::Downloading { .. }) {
+            return Err(AppError::busy("The speech model is downloading"));
+        }
+        state.release_models()?;
+        state.model_dir.clone()
+    };
+
+    let models = tauri::async_runtime::spawn_blocking(move || SttState::build_models(&model_dir))
         .await
-        .map_err(|e| format!("Failed to download {}: {}", url, e))?;
+        .map_err(panic_guard::join_error)?;
+
+    let result = {
+        let mut state = state.lock().await;
+        match models {
+            Ok(models) => {
+                state.apply_models(models);
+                Ok(())
+            }
+            Err(e) => {
+                // A stand-in backend still transcribes without the model
+                if state.backend.is_none() {
+                    state.model_status = if SttState::are_models_downloaded(&state.model_dir) {
+                        ModelStatus::Error { message: e.clone() }
+                    } else {
+                        ModelStatus::NotDownloaded
+                    };
+                }
+                Err(AppError::unavailable(e))
+            }
+        }
+    };
+    app_state::publish_stt(app).await;
+    result
+}
 
-    if !response.status().is_success() {
-        return Err(format!(
-            "Failed to download {}: HTTP {}",
-            url,
-            response.status()
-        ));
+/// Initialize STT state, with the persisted recording limits
+pub fn init_stt_state(app: &AppHandle) -> SharedSttState {
+    let mut state = SttState::new(get_model_dir(app));
+    let settings = app_settings::load(app);
+    state.set_recording_limits(settings.stt_recording_limits);
+    state.set_auto_gain(!settings.stt_auto_gain_disabled);
+    state.set_vocabulary(settings.stt_vocabulary);
+    if let Some(backend) = replacement_backend(app, &state.model_dir) {
+        state.use_backend(backend);
     }
+    Arc::new(tokio::sync::Mutex::new(state))
+}
 
-    let mut file = tokio::fs::File::create(path)
-        .await
-        .map_err(|e| format!("Failed to create file: {}", e))?;
+/// What transcribes in place of the model: a development stand-in, or the
+/// user's speech server while the model isn't downloaded
+fn replacement_backend(app: &AppHandle, model_dir: &PathBuf) -> Option<SttInference> {
+    stt_dev::selected_backend(app).or_else(|| {
+        if SttState::are_models_downloaded(model_dir) {
+            None
+        } else {
+            stt_remote::backend(app)
+        }
+    })
+}
 
-    let mut stream = response.bytes_stream();
-    while let Some(chunk) = stream.next().await {
-        let chunk = chunk.map_err(|e| format!("Download error: {}", e))?;
-        file.write_all(&chunk)
-            .await
-            .map_err(|e| format!("Write error: {}", e))?;
+/// Apply the persisted vocabulary, once changed
+pub async fn apply_vocabulary(app: &AppHandle) {
+    let terms = app_settings::load(app).stt_vocabulary;
+    if let Some(state) = app.try_state::<SharedSttState>() {
+        state.lock().await.set_vocabulary(terms);
     }
+}
 
-    file.flush()
-        .await
-        .map_err(|e| format!("Flush error: {}", e))?;
+/// Apply the persisted auto gain setting, once changed
+pub async fn apply_auto_gain(app: &AppHandle) {
+    let enabled = !app_settings::load(app).stt_auto_gain_disabled;
+    if let Some(state) = app.try_state::<SharedSttState>() {
+        state.lock().await.set_auto_gain(enabled);
+    }
+}
+
+/// Switch to the backend now selected
+pub async fn apply_backend(app: &AppHandle) {
+    let Some(state) = app.try_state::<SharedSttState>() else {
+        return;
+    };
+    let model_dir = state.lock().await.model_dir.clone();
+    let backend = replacement_backend(app, &model_dir);
+    {
+        let mut state = state.lock().await;
+        match backend {
+            Some(backend) => state.use_backend(backend),
+            None => state.use_model(),
+        }
+    }
+    app_state::publish_stt(app).await;
+}
+
+/// Download a single model file with streaming (avoids loading entire file into memory)
+pub(crate) async fn download_file(
+    app: &AppHandle,
+    downloader: &ModelDownloader,
+    url: &str,
+    path: &Path,
+    task: &TaskHandle,
+) -> Result<(), AppError> {
+    task.check()?;
+    transfers::download(app, downloader, url, path, task.cancellation()).await
+}
+
+/// Download `(remote path, local file name)` pairs from `base_url` into `model_dir`,
+/// reporting the fraction of completed files before each download and at the end
+pub(crate) async fn download_model_files(
+    app: &AppHandle,
+    base_url: &str,
+    files: &[(&str, &str)],
+    model_dir: &Path,
+    mut on_progress: impl FnMut(f32),
+) -> Result<(), AppError> {
+    policy::ensure_downloads_allowed(app)?;
+    std::fs::create_dir_all(model_dir)
+        .map_err(|e| AppError::io(format!("Failed to create model directory: {}", e)))?;
+
+    metered::confirm_large_download(app, &i18n::t(app, "download.modelFiles")).await?;
+    let downloader = ModelDownloader::new(app).await.map_err(AppError::network)?;
+    let _awake = power::hold(app, "Downloading models");
+    let task = tasks::start(app, TaskKind::Download, i18n::t(app, "task.modelDownload"));
+    let mut result = Ok(());
+    for (index, (remote, file)) in files.iter().enumerate() {
+        let progress = index as f32 / files.len() as f32;
+        on_progress(progress);
+        task.set_progress(progress);
+        let url = format!("{}/{}", base_url, remote);
+        result = download_file(app, &downloader, &url, &model_dir.join(file), &task).await;
+        if result.is_err() {
+            break;
+        }
+    }
+    task.finish(&result);
+    result?;
+    on_progress(1.0);
 
     Ok(())
 }
 
 /// Download all model files
-pub async fn download_models(app: AppHandle) -> Result<(), String> {
+pub async fn download_models(app: AppHandle) -> Result<(), AppError> {
     // Check if models are already loaded - can't overwrite memory-mapped files
     {
         let state = app.state::<SharedSttState>();
-        let state = state.lock().map_err(|e| format!("Lock error: {}", e))?;
+        let state = state.lock().await;
         if matches!(state.model_status, ModelStatus::Ready) && state.preprocessor_session.is_some() {
             return Ok(());
         }
     }
 
+    policy::ensure_downloads_allowed(&app)?;
     let model_dir = get_model_dir(&app);
 
     // Create model directory
     std::fs::create_dir_all(&model_dir)
-        .map_err(|e| format!("Failed to create model directory: {}", e))?;
+        .map_err(|e| AppError::io(format!("Failed to create model directory: {}", e)))?;
+
+    metered::confirm_large_download(&app, &i18n::t(&app, "download.speechModel")).await?;
+    let downloader = ModelDownloader::new(&app).await.map_err(AppError::network)?;
+    let _awake = power::hold(&app, "Downloading the speech model");
+    let task = tasks::start(&app, TaskKind::Download, i18n::t(&app, "task.speechModel"));
+    let result = download_speech_model(&app, &downloader, &model_dir, &task).await;
+    task.finish(&result);
+    if result.is_err() {
+        // A download that stopped midway leaves the model to download again
+        {
+            let state = app.state::<SharedSttState>();
+            let mut state = state.lock().await;
+            if matches!(state.model_status, ModelStatus::Downloading { .. }) {
+                state.model_status = ModelStatus::NotDownloaded;
+            }
+        }
+        app_state::publish_stt(&app).await;
+    }
+    result
+}
 
+async fn download_speech_model(
+    app: &AppHandle,
+    downloader: &ModelDownloader,
+    model_dir: &Path,
+    task: &TaskHandle,
+) -> Result<(), AppError> {
     // Update state to downloading
     {
         let state = app.state::<SharedSttState>();
-        let mut state = state.lock().map_err(|e| format!("Lock error: {}", e))?;
+        let mut state = state.lock().await;
         state.model_status = ModelStatus::Downloading { progress: 0.0 };
     }
-
-    let client = reqwest::Client::new();
+    app_state::publish_stt(app).await;
 
     let total_files = MODEL_FILES.len();
     let mut downloaded = 0;
 
     // Download all model files
     for file in MODEL_FILES.iter() {
-        let url = format!("{}/{}", HF_BASE_URL, file);
+        let url = format!("https://huggingface.co/{}/resolve/main/{}", HF_REPO, file);
         let path = model_dir.join(file);
 
         // Emit progress
@@ -591,11 +1253,13 @@ pub async fn download_models(app: AppHandle) -> Result<(), String> {
 
         {
             let state = app.state::<SharedSttState>();
-            let mut state = state.lock().map_err(|e| format!("Lock error: {}", e))?;
+            let mut state = state.lock().await;
             state.model_status = ModelStatus::Downloading { progress };
         }
+        app_state::publish_stt(app).await;
+        task.set_progress(progress);
 
-        download_file(&client, &url, &path).await?;
+        download_file(app, downloader, &url, &path, task).await?;
         downloaded += 1;
     }
 
@@ -604,18 +1268,102 @@ pub async fn download_models(app: AppHandle) -> Result<(), String> {
         .map_err(|e| format!("Failed to emit progress: {}", e))?;
 
     // Load models off-lock
-    let model_dir_for_load = model_dir.clone();
+    let model_dir_for_load = model_dir.to_path_buf();
     let models = tokio::task::spawn_blocking(move || SttState::build_models(&model_dir_for_load))
         .await
-        .map_err(|e| format!("Failed to load models: {}", e))??;
+        .map_err(|e| format!("Failed to load models: {}", e))
+        .and_then(|result| result);
 
-    // Update state to ready
-    {
+    // Update state to ready, or surface why the runtime couldn't load the models
+    let result = {
         let state = app.state::<SharedSttState>();
-        let mut state = state.lock().map_err(|e| format!("Lock error: {}", e))?;
-        state.model_dir = model_dir;
-        state.apply_models(models);
+        let mut state = state.lock().await;
+        state.model_dir = model_dir.to_path_buf();
+        match models {
+            Ok(models) => {
+                state.apply_models(models);
+                Ok(())
+            }
+            Err(e) => {
+                state.model_status = ModelStatus::Error { message: e.clone() };
+                Err(AppError::unavailable(e))
+            }
+        }
+    };
+    app_state::publish_stt(app).await;
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_chunk_ranges_overlap_and_cover_audio() {
+        let ranges = chunk_ranges(120 * 16_000);
+        assert_eq!(
+            ranges,
+            vec![(0, 800_000), (736_000, 1_536_000), (1_472_000, 1_920_000)]
+        );
     }
 
-    Ok(())
+    #[test]
+    fn test_stitch_chunks_keeps_each_word_once() {
+        // Token ids >= 100 start a word. The overlap is frames 575..625, split
+        // at frame 600, so the word at 610/611 comes from the second chunk.
+        let chunks = [(0, 800_000), (736_000, 1_200_000)];
+        let first = vec![(100, 10), (1, 11), (101, 590), (102, 610), (2, 611)];
+        let second = vec![(3, 2), (102, 36), (2, 37), (103, 80)];
+
+        let stitched = stitch_chunks(&chunks, vec![first, second], |token| token >= 100);
+        assert_eq!(
+            stitched,
+            vec![(100, 10), (1, 11), (101, 590), (102, 611), (2, 612), (103, 655)]
+        );
+    }
+
+    #[test]
+    fn test_build_segments_flags_uncertain_words() {
+        let tokens = [
+            (" Deploy", 0, 0.9),
+            (" to", 1, 0.95),
+            (" kube", 2, 0.8),
+            ("rnetes", 3, 0.3),
+            (".", 4, 0.9),
+            (" Done", 20, 0.5),
+        ];
+        let segments = build_segments(&tokens);
+        assert_eq!(segments.len(), 2);
+        assert_eq!(segments[0].text, "Deploy to kubernetes.");
+        let uncertain: Vec<(usize, f32)> = segments[0]
+            .uncertain_words
+            .iter()
+            .map(|word| (word.index, word.confidence))
+            .collect();
+        assert_eq!(uncertain, vec![(2, 0.3)]);
+        assert!((segments[0].confidence.unwrap() - (0.9 + 0.95 + 0.3) / 3.0).abs() < 1e-6);
+        assert_eq!(segments[1].uncertain_words.len(), 1);
+
+        assert!((softmax_at(&[1.0, 1.0], 0) - 0.5).abs() < 1e-6);
+    }
+
+    #[test]
+    fn test_push_audio_warns_then_stops_at_limit() {
+        let mut state = SttState::new(std::env::temp_dir().join("stt-limits-test"));
+        state.model_status = ModelStatus::Ready;
+        state.set_recording_limits(RecordingLimits {
+            max_duration_secs: 2,
+            max_buffer_mb: 512,
+        });
+        state.start_recording().unwrap();
+
+        let warning = state.push_audio(vec![0.0; 16_000]).unwrap().unwrap();
+        assert!(!warning.stopped);
+        assert!(state.push_audio(vec![0.0; 8_000]).unwrap().is_none());
+
+        let warning = state.push_audio(vec![0.0; 16_000]).unwrap().unwrap();
+        assert!(warning.stopped);
+        assert!(state.push_audio(vec![0.0; 10]).is_err());
+        assert_eq!(state.stop_recording().len(), 32_000);
+    }
 }
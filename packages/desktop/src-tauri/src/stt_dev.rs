@@ -0,0 +1,276 @@
+//! Stand-ins for the speech model, for working on dictation without it.
+//!
+//! The model takes gigabytes to download and a fast machine to run. With a
+//! stand-in selected, speech-to-text is ready at once and each recording is
+//! transcribed, in turn, as:
+//!
+//! - `mock`: one of a few canned transcripts, or the ones given
+//! - `replay`: one of the recordings in a directory, `.wav` files with a
+//!   `.txt` of the same name beside each holding its transcript, timed like
+//!   the recording
+//!
+//! The `sttBackend` setting selects one, and `AURA_STT_BACKEND` (`onnx`,
+//! `mock` or `replay:<dir>`) overrides it.
+
+use serde::{Deserialize, Serialize};
+use std::{
+    path::{Path, PathBuf},
+    sync::{
+        Arc,
+        atomic::{AtomicUsize, Ordering},
+    },
+};
+use tauri::AppHandle;
+
+use crate::{
+    app_settings,
+    stt::{SttBackend, SttInference, TranscriptSegment, Transcription},
+    test_mode,
+};
+
+const CANNED_TRANSCRIPTS: &[&str] = &[
+    "The quick brown fox jumps over the lazy dog.",
+    "Let's move the meeting to Thursday afternoon. Can you send the invite?",
+    "Refactor the settings loader so a bad value only resets that one field.",
+];
+
+/// Which backend transcribes recordings
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "camelCase")]
+pub enum BackendChoice {
+    /// The speech model
+    #[default]
+    Onnx,
+    Mock {
+        /// Canned ones when empty
+        #[serde(default)]
+        transcripts: Vec<String>,
+    },
+    Replay {
+        dir: PathBuf,
+    },
+}
+
+impl BackendChoice {
+    /// The choice `AURA_STT_BACKEND` is set to
+    fn parse_env(value: &str) -> Option<Self> {
+        match value {
+            "onnx" => Some(Self::Onnx),
+            "mock" => Some(Self::Mock {
+                transcripts: Vec::new(),
+            }),
+            _ => value
+                .strip_prefix("replay:")
+                .filter(|dir| !dir.is_empty())
+                .map(|dir| Self::Replay { dir: dir.into() }),
+        }
+    }
+}
+
+/// The item of `items` whose turn it is
+fn in_turn<'a, T>(items: &'a [T], next: &AtomicUsize) -> &'a T {
+    &items[next.fetch_add(1, Ordering::Relaxed) % items.len()]
+}
+
+/// `text` as one segment lasting `duration` seconds
+fn transcription(text: &str, duration: f32) -> Transcription {
+    Transcription {
+        text: text.to_string(),
+        segments: vec![TranscriptSegment {
+            start: 0.0,
+            end: duration,
+            text: text.to_string(),
+            speaker: None,
+            confidence: None,
+            uncertain_words: Vec::new(),
+        }],
+    }
+}
+
+pub struct MockBackend {
+    transcripts: Vec<String>,
+    next: AtomicUsize,
+}
+
+impl MockBackend {
+    pub fn new(transcripts: Vec<String>) -> Self {
+        let transcripts = if transcripts.is_empty() {
+            CANNED_TRANSCRIPTS
+                .iter()
+                .map(|text| text.to_string())
+                .collect()
+        } else {
+            transcripts
+        };
+        Self {
+            transcripts,
+            next: AtomicUsize::new(0),
+        }
+    }
+}
+
+impl SttBackend for MockBackend {
+    fn transcribe(
+        &self,
+        audio: &[f32],
+        on_progress: &mut dyn FnMut(f32) -> Result<(), String>,
+    ) -> Result<Transcription, String> {
+        on_progress(1.0)?;
+        let text = in_turn(&self.transcripts, &self.next);
+        Ok(transcription(text, audio.len() as f32 / 16_000.0))
+    }
+}
+
+struct Recording {
+    text: String,
+    /// In seconds
+    duration: f32,
+}
+
+pub struct ReplayBackend {
+    recordings: Vec<Recording>,
+    next: AtomicUsize,
+}
+
+impl ReplayBackend {
+    /// The recordings in `dir`, by file name
+    pub fn load(dir: &Path) -> Result<Self, String> {
+        let mut wavs: Vec<PathBuf> = std::fs::read_dir(dir)
+            .map_err(|e| format!("Failed to read {}: {}", dir.display(), e))?
+            .filter_map(|entry| Some(entry.ok()?.path()))
+            .filter(|path| path.extension().is_some_and(|ext| ext == "wav"))
+            .collect();
+        wavs.sort();
+
+        let recordings = wavs
+            .iter()
+            .map(|wav| {
+                let transcript = wav.with_extension("txt");
+                let text = std::fs::read_to_string(&transcript)
+                    .map_err(|e| format!("Failed to read {}: {}", transcript.display(), e))?;
+                let bytes = std::fs::read(wav)
+                    .map_err(|e| format!("Failed to read {}: {}", wav.display(), e))?;
+                let duration =
+                    wav_duration(&bytes).map_err(|e| format!("{}: {}", wav.display(), e))?;
+                Ok(Recording {
+                    text: text.trim().to_string(),
+                    duration,
+                })
+            })
+            .collect::<Result<Vec<_>, String>>()?;
+        if recordings.is_empty() {
+            return Err(format!("No recordings in {}", dir.display()));
+        }
+        Ok(Self {
+            recordings,
+            next: AtomicUsize::new(0),
+        })
+    }
+}
+
+impl SttBackend for ReplayBackend {
+    fn transcribe(
+        &self,
+        _audio: &[f32],
+        on_progress: &mut dyn FnMut(f32) -> Result<(), String>,
+    ) -> Result<Transcription, String> {
+        on_progress(1.0)?;
+        let recording = in_turn(&self.recordings, &self.next);
+        Ok(transcription(&recording.text, recording.duration))
+    }
+}
+
+/// Length in seconds of the WAV file `bytes`, from its headers
+fn wav_duration(bytes: &[u8]) -> Result<f32, String> {
+    if bytes.len() < 12 || &bytes[..4] != b"RIFF" || &bytes[8..12] != b"WAVE" {
+        return Err("Not a WAV file".to_string());
+    }
+    let mut byte_rate = None;
+    let mut data_len = None;
+    let mut offset = 12;
+    while offset + 8 <= bytes.len() {
+        let id = &bytes[offset..offset + 4];
+        let len = u32::from_le_bytes([
+            bytes[offset + 4],
+            bytes[offset + 5],
+            bytes[offset + 6],
+            bytes[offset + 7],
+        ]) as usize;
+        let body = offset + 8;
+        match id {
+            b"fmt " if body + 12 <= bytes.len() => {
+                byte_rate = Some(u32::from_le_bytes([
+                    bytes[body + 8],
+                    bytes[body + 9],
+                    bytes[body + 10],
+                    bytes[body + 11],
+                ]));
+            }
+            // The length of a streamed file's data may be left unset
+            b"data" => data_len = Some(len.min(bytes.len() - body)),
+            _ => {}
+        }
+        // Chunks are padded to an even length
+        offset = body.saturating_add(len).saturating_add(len % 2);
+    }
+    match (byte_rate, data_len) {
+        (Some(rate), Some(len)) if rate > 0 => Ok(len as f32 / rate as f32),
+        (None, _) | (Some(0), _) => Err("No format in the WAV file".to_string()),
+        _ => Err("No audio in the WAV file".to_string()),
+    }
+}
+
+/// The backend to use in place of the model, if one is selected
+pub fn selected_backend(app: &AppHandle) -> Option<SttInference> {
+    if let Some(text) = test_mode::transcript() {
+        return Some(Arc::new(MockBackend::new(vec![text])));
+    }
+    let choice = std::env::var("AURA_STT_BACKEND")
+        .ok()
+        .and_then(|value| {
+            let choice = BackendChoice::parse_env(&value);
+            if choice.is_none() {
+                eprintln!("Unknown AURA_STT_BACKEND: {}", value);
+            }
+            choice
+        })
+        .unwrap_or_else(|| app_settings::load(app).stt_backend);
+    match choice {
+        BackendChoice::Onnx => None,
+        BackendChoice::Mock { transcripts } => Some(Arc::new(MockBackend::new(transcripts))),
+        BackendChoice::Replay { dir } => match ReplayBackend::load(&dir) {
+            Ok(backend) => Some(Arc::new(backend)),
+            Err(e) => {
+                eprintln!("Failed to load the STT replay: {}", e);
+                None
+            }
+        },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_wav_duration() {
+        // 16 kHz mono 16-bit: 32000 bytes a second
+        let mut wav = b"RIFF\0\0\0\0WAVEfmt \x10\0\0\0\x01\0\x01\0".to_vec();
+        wav.extend(16_000u32.to_le_bytes());
+        wav.extend(32_000u32.to_le_bytes());
+        wav.extend([2, 0, 16, 0]);
+        wav.extend(b"data");
+        wav.extend(16_000u32.to_le_bytes());
+        wav.extend(vec![0; 16_000]);
+        assert_eq!(wav_duration(&wav), Ok(0.5));
+        assert!(wav_duration(b"RIFF\0\0\0\0WAVE").is_err());
+
+        assert_eq!(
+            BackendChoice::parse_env("replay:/tmp/takes"),
+            Some(BackendChoice::Replay {
+                dir: "/tmp/takes".into()
+            })
+        );
+        assert_eq!(BackendChoice::parse_env("replay:"), None);
+    }
+}
@@ -0,0 +1,259 @@
+//! Formatting dictation for the app it goes into.
+//!
+//! Text dictated into another app suits it better formatted its way: a
+//! terminal wants commands, without capitals or a closing period, and an
+//! editor may want Markdown. `stt_set_app_profile` sets the `AppProfile` of
+//! an app, by macOS bundle id, Windows executable name (`code.exe`) or Linux
+//! process name, in the `sttAppProfiles` setting. Whatever inserts dictated
+//! text into the frontmost app passes it through `stt_format_for_target_app`
+//! first, which detects that app and applies its profile.
+
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use tauri::{AppHandle, Webview};
+
+use crate::{app_settings, audit, error::AppError};
+
+/// Spoken commands turned into Markdown, and what they become
+const MARKDOWN_COMMANDS: &[(&str, &str)] = &[
+    ("new paragraph", "\n\n"),
+    ("new line", "\n"),
+    ("bullet point", "\n- "),
+];
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct AppProfile {
+    /// Whether sentences start with a capital
+    pub capitalize: bool,
+    /// Whether a closing period is kept
+    pub end_punctuation: bool,
+    /// Whether "new line", "new paragraph" and "bullet point" are turned into
+    /// Markdown
+    pub markdown: bool,
+}
+
+impl Default for AppProfile {
+    fn default() -> Self {
+        Self {
+            capitalize: true,
+            end_punctuation: true,
+            markdown: false,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TargetedText {
+    /// The app the text is for, if it could be told
+    pub app_id: Option<String>,
+    pub text: String,
+}
+
+/// Whether `words` start with the words of `phrase`
+fn starts_with_phrase(words: &[&str], phrase: &str) -> bool {
+    let phrase: Vec<&str> = phrase.split(' ').collect();
+    words.len() >= phrase.len()
+        && phrase.iter().zip(words).all(|(expected, word)| {
+            word.trim_matches(|c: char| !c.is_alphanumeric())
+                .eq_ignore_ascii_case(expected)
+        })
+}
+
+/// `text` with its spoken Markdown commands carried out
+fn apply_markdown(text: &str) -> String {
+    let words: Vec<&str> = text.split_whitespace().collect();
+    let mut markdown = String::new();
+    let mut i = 0;
+    while i < words.len() {
+        match MARKDOWN_COMMANDS
+            .iter()
+            .find(|(phrase, _)| starts_with_phrase(&words[i..], phrase))
+        {
+            Some((phrase, markup)) => {
+                // The model punctuates the pause before a command
+                let kept = markdown.trim_end_matches([',', ' ']).len();
+                markdown.truncate(kept);
+                markdown.push_str(markup);
+                i += phrase.split(' ').count();
+            }
+            None => {
+                if !markdown.is_empty() && !markdown.ends_with(['\n', ' ']) {
+                    markdown.push(' ');
+                }
+                markdown.push_str(words[i]);
+                i += 1;
+            }
+        }
+    }
+    markdown.trim_start_matches('\n').to_string()
+}
+
+/// `text` with the first letter of each sentence in lower case
+fn decapitalize(text: &str) -> String {
+    let mut result = String::with_capacity(text.len());
+    let mut sentence_start = true;
+    for c in text.chars() {
+        if sentence_start && c.is_alphabetic() {
+            result.extend(c.to_lowercase());
+            sentence_start = false;
+            continue;
+        }
+        if matches!(c, '.' | '!' | '?' | '\n') {
+            sentence_start = true;
+        } else if !c.is_whitespace() {
+            sentence_start = false;
+        }
+        result.push(c);
+    }
+    result
+}
+
+/// `text` formatted as `profile` has it
+pub fn format(profile: &AppProfile, text: &str) -> String {
+    let mut text = if profile.markdown {
+        apply_markdown(text)
+    } else {
+        text.to_string()
+    };
+    if !profile.capitalize {
+        text = decapitalize(&text);
+    }
+    if !profile.end_punctuation && text.ends_with('.') && !text.ends_with("..") {
+        text.pop();
+    }
+    text
+}
+
+/// The frontmost app, by bundle id
+#[cfg(target_os = "macos")]
+fn frontmost_app() -> Option<String> {
+    let output = std::process::Command::new("osascript")
+        .args([
+            "-e",
+            "id of application (path to frontmost application as text)",
+        ])
+        .output()
+        .ok()?;
+    let id = String::from_utf8_lossy(&output.stdout).trim().to_string();
+    (output.status.success() && !id.is_empty()).then_some(id)
+}
+
+/// The executable of the foreground window, such as `code.exe`
+#[cfg(windows)]
+fn frontmost_app() -> Option<String> {
+    use windows::Win32::Foundation::CloseHandle;
+    use windows::Win32::System::Threading::{
+        OpenProcess, PROCESS_NAME_WIN32, PROCESS_QUERY_LIMITED_INFORMATION,
+        QueryFullProcessImageNameW,
+    };
+    use windows::Win32::UI::WindowsAndMessaging::{GetForegroundWindow, GetWindowThreadProcessId};
+    use windows::core::PWSTR;
+
+    unsafe {
+        let window = GetForegroundWindow();
+        let mut pid = 0;
+        GetWindowThreadProcessId(window, Some(&mut pid as *mut u32));
+        if pid == 0 {
+            return None;
+        }
+        let process = OpenProcess(PROCESS_QUERY_LIMITED_INFORMATION, false, pid).ok()?;
+        let mut buffer = [0u16; 1024];
+        let mut len = buffer.len() as u32;
+        let result = QueryFullProcessImageNameW(
+            process,
+            PROCESS_NAME_WIN32,
+            PWSTR(buffer.as_mut_ptr()),
+            &mut len,
+        );
+        let _ = CloseHandle(process);
+        result.ok()?;
+        let path = String::from_utf16_lossy(&buffer[..len as usize]);
+        let name = std::path::Path::new(&path).file_name()?.to_str()?;
+        Some(name.to_lowercase())
+    }
+}
+
+/// The process name of the active X11 window; Wayland doesn't tell
+#[cfg(not(any(target_os = "macos", windows)))]
+fn frontmost_app() -> Option<String> {
+    let output = std::process::Command::new("xdotool")
+        .args(["getactivewindow", "getwindowpid"])
+        .output()
+        .ok()?;
+    let pid: u32 = String::from_utf8_lossy(&output.stdout)
+        .trim()
+        .parse()
+        .ok()?;
+    let name = std::fs::read_to_string(format!("/proc/{}/comm", pid)).ok()?;
+    Some(name.trim().to_string())
+}
+
+/// Set the profile of app `app_id`, or clear it with `None`
+#[tauri::command]
+pub async fn stt_set_app_profile(
+    app: AppHandle,
+    webview: Webview,
+    app_id: String,
+    profile: Option<AppProfile>,
+) -> Result<(), AppError> {
+    let args = json!({ "appId": app_id, "profile": profile });
+    let result = if app_id.trim().is_empty() {
+        Err(AppError::invalid_input("The app id is empty"))
+    } else {
+        app_settings::apply(&app, json!({ "sttAppProfiles": { app_id: profile } }))
+            .await
+            .map(|_| ())
+    };
+    audit::record(&webview, "stt_set_app_profile", args, &result);
+    result
+}
+
+/// Format dictated `text` for the frontmost app, as its profile has it
+#[tauri::command]
+pub async fn stt_format_for_target_app(app: AppHandle, text: String) -> TargetedText {
+    let app_id = tauri::async_runtime::spawn_blocking(frontmost_app)
+        .await
+        .ok()
+        .flatten();
+    let text = match app_id
+        .as_ref()
+        .and_then(|id| app_settings::load(&app).stt_app_profiles.remove(id))
+    {
+        Some(profile) => format(&profile, &text),
+        None => text,
+    };
+    TargetedText { app_id, text }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_format() {
+        let terminal = AppProfile {
+            capitalize: false,
+            end_punctuation: false,
+            markdown: false,
+        };
+        assert_eq!(
+            format(&terminal, "Git status. Then git pull."),
+            "git status. then git pull"
+        );
+
+        let editor = AppProfile {
+            markdown: true,
+            ..AppProfile::default()
+        };
+        assert_eq!(
+            format(
+                &editor,
+                "Groceries, bullet point, milk. Bullet point eggs. New paragraph Done."
+            ),
+            "Groceries\n- milk.\n- eggs.\n\nDone."
+        );
+        assert_eq!(format(&AppProfile::default(), "As is."), "As is.");
+    }
+}
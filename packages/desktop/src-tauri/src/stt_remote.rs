@@ -0,0 +1,445 @@
+//! Transcribing on the user's own speech server while the model isn't
+//! downloaded.
+//!
+//! Users who can't or won't download the model can point the `sttRemote`
+//! setting at a Whisper server of their own with the OpenAI transcription
+//! API (`POST .../v1/audio/transcriptions`), as the whisper.cpp and
+//! faster-whisper servers have. Its API key, if it needs one, is kept in the
+//! system keychain rather than the settings. The server must use HTTPS
+//! unless it runs on the user's own machine, as the key and recordings
+//! would otherwise go out unencrypted.
+//!
+//! Recordings then leave the device, so the server is only used once the
+//! user agreed to send them there in a dialog saying so. The consent is for
+//! that endpoint: changing it asks again. The server is only used while the
+//! model isn't downloaded, and never when an administrator's policy turns it
+//! off.
+
+use serde::{Deserialize, Serialize};
+use serde_json::{Value, json};
+use std::{sync::Arc, time::Duration};
+use tauri::{AppHandle, Manager, Webview};
+use tauri_plugin_dialog::{
+    DialogExt, MessageDialogButtons, MessageDialogKind, MessageDialogResult,
+};
+use tauri_plugin_store::StoreExt;
+use tokio::sync::oneshot;
+
+use crate::{
+    SETTINGS_STORE, app_settings, atomic_store, audit,
+    error::AppError,
+    i18n, keychain, policy, proxy,
+    stt::{self, SttBackend, SttInference, TranscriptSegment, Transcription},
+    test_mode,
+};
+
+/// Name of the API key in the keychain
+const KEY_NAME: &str = "stt-remote-key";
+/// The endpoint the user agreed to send recordings to
+const CONSENT_KEY: &str = "sttRemoteConsent";
+const DEFAULT_MODEL: &str = "whisper-1";
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(300);
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct RemoteSettings {
+    /// The transcription URL, as `https://host/v1/audio/transcriptions`
+    pub endpoint: Option<String>,
+    /// The model the server is asked for, `whisper-1` when unset
+    pub model: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RemoteStatus {
+    pub endpoint: Option<String>,
+    pub has_key: bool,
+    /// Whether the user agreed to send recordings to `endpoint`
+    pub consented: bool,
+    pub disabled_by_policy: bool,
+    /// Whether recordings are transcribed there now
+    pub active: bool,
+}
+
+#[derive(Deserialize)]
+struct WhisperResponse {
+    text: String,
+    #[serde(default)]
+    segments: Vec<WhisperSegment>,
+}
+
+#[derive(Deserialize)]
+struct WhisperSegment {
+    start: f32,
+    end: f32,
+    text: String,
+    avg_logprob: Option<f32>,
+}
+
+impl From<WhisperResponse> for Transcription {
+    fn from(response: WhisperResponse) -> Self {
+        let segments = response
+            .segments
+            .into_iter()
+            .map(|segment| TranscriptSegment {
+                start: segment.start,
+                end: segment.end,
+                text: segment.text.trim().to_string(),
+                speaker: None,
+                confidence: segment.avg_logprob.map(f32::exp),
+                uncertain_words: Vec::new(),
+            })
+            .collect();
+        Transcription {
+            text: response.text.trim().to_string(),
+            segments,
+        }
+    }
+}
+
+pub struct RemoteBackend {
+    client: reqwest::Client,
+    endpoint: reqwest::Url,
+    model: String,
+    key: Option<String>,
+}
+
+impl RemoteBackend {
+    async fn request(&self, audio: &[f32]) -> Result<Transcription, String> {
+        let boundary = format!("aura-{}", uuid::Uuid::new_v4().simple());
+        let body = multipart_body(
+            &boundary,
+            &[("model", &self.model), ("response_format", "verbose_json")],
+            &wav_bytes(audio),
+        );
+        let mut request = self
+            .client
+            .post(self.endpoint.clone())
+            .header(
+                reqwest::header::CONTENT_TYPE,
+                format!("multipart/form-data; boundary={}", boundary),
+            )
+            .body(body);
+        if let Some(key) = &self.key {
+            request = request.bearer_auth(key);
+        }
+        let response = request
+            .send()
+            .await
+            .map_err(|e| format!("Failed to reach the speech server: {}", e))?;
+        if !response.status().is_success() {
+            return Err(format!(
+                "The speech server answered HTTP {}",
+                response.status()
+            ));
+        }
+        let response: WhisperResponse = response
+            .json()
+            .await
+            .map_err(|e| format!("Invalid answer from the speech server: {}", e))?;
+        Ok(response.into())
+    }
+}
+
+impl SttBackend for RemoteBackend {
+    fn transcribe(
+        &self,
+        audio: &[f32],
+        on_progress: &mut dyn FnMut(f32) -> Result<(), String>,
+    ) -> Result<Transcription, String> {
+        on_progress(0.0)?;
+        let transcription = tauri::async_runtime::block_on(self.request(audio))?;
+        on_progress(1.0)?;
+        Ok(transcription)
+    }
+
+    fn is_remote(&self) -> bool {
+        true
+    }
+}
+
+/// 16 kHz mono `audio` as a 16-bit PCM WAV file
+fn wav_bytes(audio: &[f32]) -> Vec<u8> {
+    let data_len = (audio.len() * 2) as u32;
+    let mut wav = Vec::with_capacity(44 + data_len as usize);
+    wav.extend(b"RIFF");
+    wav.extend((36 + data_len).to_le_bytes());
+    wav.extend(b"WAVEfmt ");
+    wav.extend(16u32.to_le_bytes());
+    // PCM, one channel, 16 kHz, 32000 bytes a second, 2-byte frames of 16 bits
+    wav.extend(1u16.to_le_bytes());
+    wav.extend(1u16.to_le_bytes());
+    wav.extend(16_000u32.to_le_bytes());
+    wav.extend(32_000u32.to_le_bytes());
+    wav.extend(2u16.to_le_bytes());
+    wav.extend(16u16.to_le_bytes());
+    wav.extend(b"data");
+    wav.extend(data_len.to_le_bytes());
+    for sample in audio {
+        wav.extend(((sample.clamp(-1.0, 1.0) * i16::MAX as f32) as i16).to_le_bytes());
+    }
+    wav
+}
+
+/// A `multipart/form-data` body with `fields` and `wav` as the file
+fn multipart_body(boundary: &str, fields: &[(&str, &str)], wav: &[u8]) -> Vec<u8> {
+    let mut body = Vec::with_capacity(wav.len() + 512);
+    for (name, value) in fields {
+        body.extend(
+            format!(
+                "--{}\r\nContent-Disposition: form-data; name=\"{}\"\r\n\r\n{}\r\n",
+                boundary, name, value
+            )
+            .as_bytes(),
+        );
+    }
+    body.extend(
+        format!(
+            "--{}\r\nContent-Disposition: form-data; name=\"file\"; filename=\"recording.wav\"\r\n\
+             Content-Type: audio/wav\r\n\r\n",
+            boundary
+        )
+        .as_bytes(),
+    );
+    body.extend(wav);
+    body.extend(format!("\r\n--{}--\r\n", boundary).as_bytes());
+    body
+}
+
+fn disabled_by_policy(app: &AppHandle) -> bool {
+    policy::current(app).disable_remote_transcription
+}
+
+/// The endpoint the user agreed to send recordings to
+fn consented_endpoint(app: &AppHandle) -> Option<String> {
+    app.store(SETTINGS_STORE)
+        .ok()?
+        .get(CONSENT_KEY)
+        .and_then(|value| value.as_str().map(String::from))
+}
+
+fn save_consent(app: &AppHandle, endpoint: Option<&str>) -> Result<(), AppError> {
+    let store = app
+        .store(SETTINGS_STORE)
+        .map_err(|e| AppError::io(format!("Failed to open settings store: {}", e)))?;
+    match endpoint {
+        Some(endpoint) => store.set(CONSENT_KEY, json!(endpoint)),
+        None => {
+            store.delete(CONSENT_KEY);
+        }
+    }
+    atomic_store::save(app, SETTINGS_STORE)
+        .map_err(|e| AppError::io(format!("Failed to save settings: {}", e)))
+}
+
+/// Parse `endpoint`, refusing plain HTTP to anywhere but this machine
+pub fn parse_endpoint(endpoint: &str) -> Result<reqwest::Url, String> {
+    let url =
+        reqwest::Url::parse(endpoint).map_err(|e| format!("Invalid URL {}: {}", endpoint, e))?;
+    match url.scheme() {
+        "https" => Ok(url),
+        "http" if crate::url_is_localhost(&url) => Ok(url),
+        "http" => Err(format!("The speech server must use HTTPS: {}", endpoint)),
+        _ => Err(format!("Not an HTTP URL: {}", endpoint)),
+    }
+}
+
+/// The endpoint recordings may be sent to: set, agreed to and allowed
+fn usable_endpoint(app: &AppHandle) -> Option<String> {
+    if disabled_by_policy(app) {
+        return None;
+    }
+    let endpoint = app_settings::load(app).stt_remote.endpoint?;
+    (consented_endpoint(app).as_deref() == Some(endpoint.as_str())).then_some(endpoint)
+}
+
+/// The user's speech server, if they set one up and agreed to use it
+pub fn backend(app: &AppHandle) -> Option<SttInference> {
+    let endpoint = usable_endpoint(app)?;
+    let endpoint = match parse_endpoint(&endpoint) {
+        Ok(endpoint) => endpoint,
+        Err(e) => {
+            eprintln!("Not using the speech server: {}", e);
+            return None;
+        }
+    };
+    let key = keychain::get(app, KEY_NAME).unwrap_or_else(|e| {
+        eprintln!("Failed to read the speech server key: {}", e);
+        None
+    });
+    let builder = reqwest::Client::builder().timeout(REQUEST_TIMEOUT);
+    let client = match proxy::apply(app, builder, &endpoint).build() {
+        Ok(client) => client,
+        Err(e) => {
+            eprintln!("Failed to create HTTP client: {}", e);
+            return None;
+        }
+    };
+    Some(Arc::new(RemoteBackend {
+        client,
+        endpoint,
+        model: app_settings::load(app)
+            .stt_remote
+            .model
+            .unwrap_or_else(|| DEFAULT_MODEL.to_string()),
+        key,
+    }))
+}
+
+/// Forget the API key, as when wiping local data
+pub fn forget_key(app: &AppHandle) -> Result<(), String> {
+    keychain::delete(app, KEY_NAME)
+}
+
+/// Ask the user whether recordings may be sent to `endpoint`
+async fn ask_consent(app: &AppHandle, endpoint: &str) -> bool {
+    let host = reqwest::Url::parse(endpoint)
+        .ok()
+        .and_then(|url| url.host_str().map(String::from))
+        .unwrap_or_else(|| endpoint.to_string());
+    let allow = i18n::t(app, "dialog.sttRemoteConsent.allow");
+    let dialog = app
+        .dialog()
+        .message(i18n::t_with(
+            app,
+            "dialog.sttRemoteConsent.message",
+            &[("host", &host)],
+        ))
+        .title(i18n::t_with(
+            app,
+            "dialog.sttRemoteConsent.title",
+            &[("host", &host)],
+        ))
+        .kind(MessageDialogKind::Warning)
+        .buttons(MessageDialogButtons::OkCancelCustom(
+            allow.clone(),
+            i18n::t(app, "common.cancel"),
+        ));
+    let result = match test_mode::dialog_answer(app, "sttRemoteConsent") {
+        Some(answer) => answer,
+        None => {
+            let (tx, rx) = oneshot::channel();
+            dialog.show_with_result(move |result| {
+                let _ = tx.send(result);
+            });
+            rx.await.unwrap_or_default()
+        }
+    };
+    matches!(result, MessageDialogResult::Custom(choice) if choice == allow)
+}
+
+async fn request_consent(app: &AppHandle) -> Result<bool, AppError> {
+    if disabled_by_policy(app) {
+        return Err(policy::locked_error(app));
+    }
+    let endpoint = app_settings::load(app)
+        .stt_remote
+        .endpoint
+        .ok_or_else(|| AppError::invalid_input("No speech server is set"))?;
+    if consented_endpoint(app).as_deref() == Some(endpoint.as_str()) {
+        return Ok(true);
+    }
+    if !ask_consent(app, &endpoint).await {
+        return Ok(false);
+    }
+    save_consent(app, Some(&endpoint))?;
+    stt::apply_backend(app).await;
+    Ok(true)
+}
+
+#[tauri::command]
+pub async fn stt_get_remote_status(app: AppHandle) -> RemoteStatus {
+    let active = match app.try_state::<stt::SharedSttState>() {
+        Some(state) => state.lock().await.get_status().remote,
+        None => false,
+    };
+    let endpoint = app_settings::load(&app).stt_remote.endpoint;
+    let consented =
+        endpoint.is_some() && consented_endpoint(&app).as_deref() == endpoint.as_deref();
+    RemoteStatus {
+        has_key: matches!(keychain::get(&app, KEY_NAME), Ok(Some(_))),
+        consented,
+        disabled_by_policy: disabled_by_policy(&app),
+        active,
+        endpoint,
+    }
+}
+
+/// Keep `key` in the keychain as the speech server's API key, or forget the
+/// key when `None`
+#[tauri::command]
+pub async fn stt_set_remote_key(
+    app: AppHandle,
+    webview: Webview,
+    key: Option<String>,
+) -> Result<(), AppError> {
+    let result = match key.as_deref().filter(|key| !key.is_empty()) {
+        Some(key) => keychain::set(&app, KEY_NAME, key),
+        None => keychain::delete(&app, KEY_NAME),
+    }
+    .map_err(AppError::from);
+    if result.is_ok() {
+        stt::apply_backend(&app).await;
+    }
+    audit::record(
+        &webview,
+        "stt_set_remote_key",
+        json!({ "set": key.is_some() }),
+        &result,
+    );
+    result
+}
+
+/// Ask the user to agree to sending recordings to the speech server, unless
+/// they already did. Returns whether they agreed.
+#[tauri::command]
+pub async fn stt_request_remote_consent(
+    app: AppHandle,
+    webview: Webview,
+) -> Result<bool, AppError> {
+    let result = request_consent(&app).await;
+    audit::record(&webview, "stt_request_remote_consent", Value::Null, &result);
+    result
+}
+
+/// Stop sending recordings to the speech server until the user agrees again
+#[tauri::command]
+pub async fn stt_revoke_remote_consent(app: AppHandle, webview: Webview) -> Result<(), AppError> {
+    let result = save_consent(&app, None);
+    if result.is_ok() {
+        stt::apply_backend(&app).await;
+    }
+    audit::record(&webview, "stt_revoke_remote_consent", Value::Null, &result);
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_upload_body() {
+        let wav = wav_bytes(&[0.0, 1.0, -1.0]);
+        assert_eq!(wav.len(), 44 + 6);
+        assert_eq!(&wav[40..44], &6u32.to_le_bytes());
+        assert_eq!(&wav[44..], &[0, 0, 0xff, 0x7f, 0x01, 0x80]);
+
+        let body =
+            String::from_utf8(multipart_body("b", &[("model", "whisper-1")], b"RIFF")).unwrap();
+        assert_eq!(
+            body,
+            "--b\r\nContent-Disposition: form-data; name=\"model\"\r\n\r\nwhisper-1\r\n\
+             --b\r\nContent-Disposition: form-data; name=\"file\"; filename=\"recording.wav\"\r\n\
+             Content-Type: audio/wav\r\n\r\nRIFF\r\n--b--\r\n"
+        );
+    }
+
+    #[test]
+    fn test_parse_endpoint_requires_https_off_this_machine() {
+        assert!(parse_endpoint("https://speech.example.com/v1/audio/transcriptions").is_ok());
+        assert!(parse_endpoint("http://localhost:8080/v1/audio/transcriptions").is_ok());
+        assert!(parse_endpoint("http://127.0.0.1:8080/v1/audio/transcriptions").is_ok());
+        assert!(parse_endpoint("http://speech.example.com/v1/audio/transcriptions").is_err());
+        assert!(parse_endpoint("ftp://localhost/v1").is_err());
+    }
+}
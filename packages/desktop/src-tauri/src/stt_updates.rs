@@ -0,0 +1,303 @@
+//! Updating the speech model to newer revisions of its repository.
+//!
+//! The model's HuggingFace repository gets new revisions as its conversion
+//! is fixed or improved. `stt_check_model_updates` fetches the manifest of
+//! the latest one, its commit and the size of each file, and compares it
+//! with what is installed. `stt_update_model` downloads that revision into a
+//! directory of its own next to the installed model, then points the
+//! model's active file at it with an atomic rename and reloads the model
+//! from there. A download that fails or is cut short leaves the installed model
+//! as it was; the previous directory goes once the new one loads.
+//!
+//! Models downloaded before revisions were recorded live in the directory
+//! named after the model and count as current while their file sizes match
+//! the latest manifest.
+
+use serde::{Deserialize, Serialize};
+use std::{
+    path::{Path, PathBuf},
+    sync::atomic::{AtomicBool, Ordering},
+};
+use tauri::{AppHandle, Manager, path::BaseDirectory};
+
+use crate::{
+    atomic_store,
+    error::AppError,
+    model_source::ModelDownloader,
+    stt::{self, HF_REPO, MODEL_FILES, MODEL_NAME},
+};
+
+/// Length of the commit prefix naming a revision's directory
+const REVISION_DIR_LEN: usize = 12;
+
+/// Only one update downloads at a time
+static UPDATING: AtomicBool = AtomicBool::new(false);
+
+#[derive(Debug, Deserialize)]
+struct RepoManifest {
+    sha: String,
+    siblings: Vec<RepoFile>,
+}
+
+#[derive(Debug, Deserialize)]
+struct RepoFile {
+    rfilename: String,
+    size: Option<u64>,
+}
+
+/// What the active file holds
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Installed {
+    revision: String,
+    dir: String,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ModelUpdate {
+    /// The installed revision, when it was installed by an update
+    pub installed_revision: Option<String>,
+    pub latest_revision: String,
+    pub available: bool,
+    /// Size of the download, in bytes
+    pub download_size: u64,
+}
+
+fn models_dir(app: &AppHandle) -> Result<PathBuf, String> {
+    app.path()
+        .resolve("models", BaseDirectory::AppLocalData)
+        .map_err(|e| format!("Failed to resolve the models directory: {}", e))
+}
+
+/// The file naming the model directory in use, in `models`
+fn active_file(models: &Path) -> PathBuf {
+    models.join(format!("{}.json", MODEL_NAME))
+}
+
+/// The revision the active file in `models` points at, if it does
+fn installed(models: &Path) -> Option<Installed> {
+    let bytes = std::fs::read(active_file(models)).ok()?;
+    let installed: Installed = serde_json::from_slice(&bytes).ok()?;
+    is_revision_dir(&installed.dir).then_some(installed)
+}
+
+/// Directory of the model in `models`: the revision an update installed, or
+/// the one the model was first downloaded to
+pub fn model_dir(models: &Path) -> PathBuf {
+    match installed(models) {
+        Some(installed) => models.join(installed.dir),
+        None => models.join(MODEL_NAME),
+    }
+}
+
+fn revision_dir(revision: &str) -> String {
+    let prefix: String = revision.chars().take(REVISION_DIR_LEN).collect();
+    format!("{}@{}", MODEL_NAME, prefix)
+}
+
+/// Whether `dir` names a revision's directory, and nothing outside `models`
+fn is_revision_dir(dir: &str) -> bool {
+    dir.strip_prefix(MODEL_NAME)
+        .and_then(|rest| rest.strip_prefix('@'))
+        .is_some_and(|revision| {
+            !revision.is_empty() && revision.chars().all(|c| c.is_ascii_alphanumeric())
+        })
+}
+
+/// Sizes of the model's files in `manifest`, in the order of `MODEL_FILES`;
+/// `None` when the revision lacks one
+fn file_sizes(manifest: &RepoManifest) -> Option<Vec<u64>> {
+    MODEL_FILES
+        .iter()
+        .map(|file| {
+            manifest
+                .siblings
+                .iter()
+                .find(|sibling| sibling.rfilename == *file)?
+                .size
+        })
+        .collect()
+}
+
+/// Whether the model files in `dir` have `sizes`
+fn has_sizes(dir: &Path, sizes: &[u64]) -> bool {
+    MODEL_FILES.iter().zip(sizes).all(|(file, size)| {
+        std::fs::metadata(dir.join(file)).is_ok_and(|metadata| metadata.len() == *size)
+    })
+}
+
+async fn fetch_manifest(app: &AppHandle) -> Result<RepoManifest, AppError> {
+    let url = format!(
+        "https://huggingface.co/api/models/{}/revision/main?blobs=true",
+        HF_REPO
+    );
+    let downloader = ModelDownloader::new(app).await.map_err(AppError::network)?;
+    downloader
+        .get(&url, 0)
+        .await
+        .map_err(AppError::network)?
+        .json()
+        .await
+        .map_err(|e| AppError::network(format!("Failed to read the model manifest: {}", e)))
+}
+
+/// Compare the installed model with the latest revision
+async fn check(app: &AppHandle) -> Result<(ModelUpdate, RepoManifest), AppError> {
+    let manifest = fetch_manifest(app).await?;
+    let models = models_dir(app)?;
+    let installed = installed(&models);
+    let dir = model_dir(&models);
+    let sizes = file_sizes(&manifest);
+    let downloaded = MODEL_FILES.iter().all(|file| dir.join(file).exists());
+
+    let available = match (&sizes, &installed) {
+        // The app can't run a revision without all of its files
+        (None, _) => false,
+        _ if !downloaded => false,
+        (Some(_), Some(installed)) => installed.revision != manifest.sha,
+        (Some(sizes), None) => !has_sizes(&dir, sizes),
+    };
+    let update = ModelUpdate {
+        installed_revision: installed.map(|installed| installed.revision),
+        latest_revision: manifest.sha.clone(),
+        available,
+        download_size: sizes.map(|sizes| sizes.iter().sum()).unwrap_or_default(),
+    };
+    Ok((update, manifest))
+}
+
+/// Point the active file at `installed`, or back at the first download
+fn activate(models: &Path, installed: Option<&Installed>) -> Result<(), String> {
+    let path = active_file(models);
+    match installed {
+        Some(installed) => {
+            let bytes = serde_json::to_vec_pretty(installed)
+                .map_err(|e| format!("Failed to serialize the model revision: {}", e))?;
+            atomic_store::write_atomic(&path, &bytes)
+        }
+        None => match std::fs::remove_file(&path) {
+            Err(e) if e.kind() != std::io::ErrorKind::NotFound => {
+                Err(format!("Failed to remove {}: {}", path.display(), e))
+            }
+            _ => Ok(()),
+        },
+    }
+}
+
+/// Load the model from the directory of `installed`, going back to the
+/// previous one if it doesn't load
+async fn switch(
+    app: &AppHandle,
+    models: &Path,
+    installed: Option<&Installed>,
+) -> Result<(), AppError> {
+    let state = app
+        .try_state::<stt::SharedSttState>()
+        .ok_or("STT state not found")?
+        .inner()
+        .clone();
+    let dir = match installed {
+        Some(installed) => models.join(&installed.dir),
+        None => models.join(MODEL_NAME),
+    };
+    let previous = self::installed(models);
+    let previous_dir = {
+        let mut state = state.lock().await;
+        state.release_models()?;
+        activate(models, installed)?;
+        state.replace_model_dir(dir.clone())
+    };
+
+    if let Err(e) = stt::reload_models(app).await {
+        activate(models, previous.as_ref())?;
+        state.lock().await.replace_model_dir(previous_dir);
+        let _ = stt::reload_models(app).await;
+        return Err(e);
+    }
+    if previous_dir != dir {
+        let _ = tauri::async_runtime::spawn_blocking(move || std::fs::remove_dir_all(previous_dir))
+            .await;
+    }
+    Ok(())
+}
+
+async fn update(app: &AppHandle) -> Result<Option<String>, AppError> {
+    let (update, manifest) = check(app).await?;
+    if !update.available {
+        return Ok(None);
+    }
+    let sizes = file_sizes(&manifest).ok_or("The model manifest lacks files")?;
+    let models = models_dir(app)?;
+    let installed = Installed {
+        revision: manifest.sha.clone(),
+        dir: revision_dir(&manifest.sha),
+    };
+    let dir = models.join(&installed.dir);
+
+    let base_url = format!(
+        "https://huggingface.co/{}/resolve/{}",
+        HF_REPO, manifest.sha
+    );
+    let files: Vec<(&str, &str)> = MODEL_FILES.iter().map(|file| (*file, *file)).collect();
+    stt::download_model_files(app, &base_url, &files, &dir, |_| {}).await?;
+    if !has_sizes(&dir, &sizes) {
+        let _ = std::fs::remove_dir_all(&dir);
+        return Err(AppError::network(
+            "The downloaded model files don't match the manifest",
+        ));
+    }
+
+    switch(app, &models, Some(&installed)).await?;
+    Ok(Some(installed.revision))
+}
+
+/// Whether a newer revision of the speech model is available
+#[tauri::command]
+pub async fn stt_check_model_updates(app: AppHandle) -> Result<ModelUpdate, AppError> {
+    Ok(check(&app).await?.0)
+}
+
+/// Download the latest revision of the speech model and switch to it,
+/// returning the revision, or `None` when already up to date
+#[tauri::command]
+pub async fn stt_update_model(app: AppHandle) -> Result<Option<String>, AppError> {
+    if UPDATING.swap(true, Ordering::SeqCst) {
+        return Err(AppError::busy("The speech model is already updating"));
+    }
+    let result = update(&app).await;
+    UPDATING.store(false, Ordering::SeqCst);
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_manifest_and_revision_dirs() {
+        let mut manifest = RepoManifest {
+            sha: "0123456789abcdef0123".to_string(),
+            siblings: MODEL_FILES
+                .iter()
+                .enumerate()
+                .map(|(i, file)| RepoFile {
+                    rfilename: file.to_string(),
+                    size: Some(i as u64 + 1),
+                })
+                .collect(),
+        };
+        assert_eq!(
+            file_sizes(&manifest),
+            Some((1..=MODEL_FILES.len() as u64).collect())
+        );
+        manifest.siblings.pop();
+        assert_eq!(file_sizes(&manifest), None);
+
+        let dir = revision_dir(&manifest.sha);
+        assert_eq!(dir, format!("{}@0123456789ab", MODEL_NAME));
+        assert!(is_revision_dir(&dir));
+        assert!(!is_revision_dir(MODEL_NAME));
+        assert!(!is_revision_dir(&format!("{}@../x", MODEL_NAME)));
+    }
+}
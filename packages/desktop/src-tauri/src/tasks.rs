@@ -0,0 +1,312 @@
+//! Long-running jobs, listed in one place.
+//!
+//! Model downloads, transcriptions, session indexing and vault exports each
+//! start a task when they begin. The task gets an id, reports progress
+//! through its `TaskHandle` and is marked finished with the job's result. The
+//! frontend lists tasks with `list_tasks`, follows them through
+//! `task:updated` events and the `tasks` section of the app state, and stops
+//! one with `cancel_task`.
+//!
+//! Cancelling only asks: each job checks its handle where it can stop
+//! cleanly, between files, chunks or sessions, and a download passes the
+//! request on to its transfer.
+
+use serde::Serialize;
+use serde_json::Value;
+use std::sync::Mutex;
+use tauri::{AppHandle, Emitter, Manager};
+use tokio::sync::watch;
+
+use crate::{app_state, error::AppError};
+
+/// Finished tasks kept for the list
+const MAX_FINISHED: usize = 50;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub enum TaskKind {
+    Download,
+    Transcription,
+    Indexing,
+    Export,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(tag = "state", rename_all = "camelCase")]
+pub enum TaskStatus {
+    Queued,
+    Running,
+    Completed,
+    Failed { error: String },
+    Cancelled,
+}
+
+impl TaskStatus {
+    fn finished(&self) -> bool {
+        matches!(
+            self,
+            Self::Completed | Self::Failed { .. } | Self::Cancelled
+        )
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Task {
+    pub id: String,
+    pub kind: TaskKind,
+    pub label: String,
+    /// From 0 to 1; `None` until the job can tell
+    pub progress: Option<f32>,
+    pub status: TaskStatus,
+    /// Unix seconds
+    pub created_at: u64,
+}
+
+struct Entry {
+    task: Task,
+    cancel: Option<watch::Sender<bool>>,
+}
+
+#[derive(Default)]
+pub struct TaskRegistry {
+    entries: Mutex<Vec<Entry>>,
+}
+
+/// Whether a task was asked to stop
+#[derive(Clone)]
+pub struct Cancellation(watch::Receiver<bool>);
+
+impl Cancellation {
+    pub fn is_cancelled(&self) -> bool {
+        *self.0.borrow()
+    }
+
+    /// Wait until the task is asked to stop; never returns once it can no
+    /// longer be
+    pub async fn cancelled(&mut self) {
+        if self.0.wait_for(|cancelled| *cancelled).await.is_err() {
+            std::future::pending::<()>().await;
+        }
+    }
+}
+
+/// The job's side of a task
+#[derive(Clone)]
+pub struct TaskHandle {
+    app: AppHandle,
+    id: String,
+    cancellation: Cancellation,
+}
+
+impl TaskHandle {
+    pub fn set_running(&self) {
+        update(&self.app, &self.id, |task| {
+            task.status = TaskStatus::Running
+        });
+    }
+
+    pub fn set_progress(&self, progress: f32) {
+        update(&self.app, &self.id, |task| {
+            task.progress = Some(progress.clamp(0.0, 1.0))
+        });
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.cancellation.is_cancelled()
+    }
+
+    /// An error to stop with once the task was asked to
+    pub fn check(&self) -> Result<(), AppError> {
+        if self.is_cancelled() {
+            return Err(AppError::cancelled("Task cancelled"));
+        }
+        Ok(())
+    }
+
+    pub fn cancellation(&self) -> Cancellation {
+        self.cancellation.clone()
+    }
+
+    /// Record how the job ended; an error after `cancel_task` counts as
+    /// cancelled
+    pub fn finish<T, E: ToString>(&self, result: &Result<T, E>) {
+        let cancelled = self.is_cancelled();
+        update(&self.app, &self.id, |task| {
+            task.status = match result {
+                Ok(_) => TaskStatus::Completed,
+                Err(_) if cancelled => TaskStatus::Cancelled,
+                Err(error) => TaskStatus::Failed {
+                    error: error.to_string(),
+                },
+            };
+            if task.status == TaskStatus::Completed {
+                task.progress = Some(1.0);
+            }
+        });
+    }
+}
+
+fn now_secs() -> u64 {
+    std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0)
+}
+
+/// Drop the oldest finished tasks past `MAX_FINISHED`, returning their ids
+fn prune(entries: &mut Vec<Entry>) -> Vec<String> {
+    let finished = entries
+        .iter()
+        .filter(|entry| entry.task.status.finished())
+        .count();
+    let mut excess = finished.saturating_sub(MAX_FINISHED);
+    let mut dropped = Vec::new();
+    entries.retain(|entry| {
+        if excess > 0 && entry.task.status.finished() {
+            excess -= 1;
+            dropped.push(entry.task.id.clone());
+            return false;
+        }
+        true
+    });
+    dropped
+}
+
+fn publish(app: &AppHandle, task: &Task) {
+    let _ = app.emit("task:updated", task);
+    app_state::publish(app, &["tasks", &task.id], task);
+}
+
+fn update(app: &AppHandle, id: &str, change: impl FnOnce(&mut Task)) {
+    let Some(registry) = app.try_state::<TaskRegistry>() else {
+        return;
+    };
+    let (task, dropped) = {
+        let Ok(mut entries) = registry.entries.lock() else {
+            return;
+        };
+        let Some(entry) = entries.iter_mut().find(|entry| entry.task.id == id) else {
+            return;
+        };
+        let before = entry.task.clone();
+        change(&mut entry.task);
+        if entry.task.status == before.status && entry.task.progress == before.progress {
+            return;
+        }
+        if entry.task.status.finished() {
+            entry.cancel = None;
+        }
+        let task = entry.task.clone();
+        (task, prune(&mut entries))
+    };
+    publish(app, &task);
+    for id in dropped {
+        app_state::publish(app, &["tasks", &id], Value::Null);
+    }
+}
+
+fn register(app: &AppHandle, kind: TaskKind, label: String, status: TaskStatus) -> TaskHandle {
+    let (cancel, receiver) = watch::channel(false);
+    let task = Task {
+        id: uuid::Uuid::new_v4().to_string(),
+        kind,
+        label,
+        progress: None,
+        status,
+        created_at: now_secs(),
+    };
+    // Without the registry the job still runs, just unlisted
+    if let Some(registry) = app.try_state::<TaskRegistry>()
+        && let Ok(mut entries) = registry.entries.lock()
+    {
+        entries.push(Entry {
+            task: task.clone(),
+            cancel: Some(cancel),
+        });
+    }
+    publish(app, &task);
+    TaskHandle {
+        app: app.clone(),
+        id: task.id,
+        cancellation: Cancellation(receiver),
+    }
+}
+
+/// Start a task for a job that runs now
+pub fn start(app: &AppHandle, kind: TaskKind, label: String) -> TaskHandle {
+    register(app, kind, label, TaskStatus::Running)
+}
+
+/// Start a task for a job that waits its turn; it runs from `set_running`
+pub fn queue(app: &AppHandle, kind: TaskKind, label: String) -> TaskHandle {
+    register(app, kind, label, TaskStatus::Queued)
+}
+
+#[tauri::command]
+pub fn list_tasks(app: AppHandle) -> Result<Vec<Task>, AppError> {
+    let registry = app
+        .try_state::<TaskRegistry>()
+        .ok_or("Task registry not found")?;
+    let entries = registry
+        .entries
+        .lock()
+        .map_err(|e| format!("Lock error: {}", e))?;
+    Ok(entries.iter().map(|entry| entry.task.clone()).collect())
+}
+
+/// Ask task `id` to stop; it shows as cancelled once it has
+#[tauri::command]
+pub fn cancel_task(app: AppHandle, id: String) -> Result<(), AppError> {
+    let registry = app
+        .try_state::<TaskRegistry>()
+        .ok_or("Task registry not found")?;
+    let entries = registry
+        .entries
+        .lock()
+        .map_err(|e| format!("Lock error: {}", e))?;
+    let entry = entries
+        .iter()
+        .find(|entry| entry.task.id == id)
+        .ok_or_else(|| AppError::not_found(format!("No task {}", id)))?;
+    let cancel = entry
+        .cancel
+        .as_ref()
+        .ok_or_else(|| AppError::invalid_input(format!("Task {} has already finished", id)))?;
+    cancel.send_replace(true);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(status: TaskStatus) -> Entry {
+        Entry {
+            task: Task {
+                id: uuid::Uuid::new_v4().to_string(),
+                kind: TaskKind::Transcription,
+                label: String::new(),
+                progress: None,
+                status,
+                created_at: 0,
+            },
+            cancel: None,
+        }
+    }
+
+    #[test]
+    fn test_prune_drops_oldest_finished() {
+        let mut entries: Vec<Entry> = (0..MAX_FINISHED + 2)
+            .map(|_| entry(TaskStatus::Cancelled))
+            .collect();
+        entries.insert(1, entry(TaskStatus::Queued));
+        let oldest = entries[0].task.id.clone();
+
+        let dropped = prune(&mut entries);
+        assert_eq!(dropped.len(), 2);
+        assert_eq!(dropped[0], oldest);
+        assert_eq!(entries.len(), MAX_FINISHED + 1);
+        assert_eq!(entries[0].task.status, TaskStatus::Queued);
+    }
+}
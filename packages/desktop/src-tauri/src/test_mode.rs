@@ -0,0 +1,188 @@
+//! A test mode for driving a packaged build from an end-to-end suite.
+//!
+//! Set `AURA_TEST_MODE=1` to turn it on. The parts of the app that depend on
+//! the machine or wait on the user can then be made deterministic:
+//!
+//! - `AURA_TEST_SIDECAR` runs the given executable as the sidecar, directly
+//!   rather than through the login shell and without verifying it, and
+//!   `AURA_TEST_SIDECAR_TIMEOUT_MS` shortens how long its server is waited
+//!   for.
+//! - `AURA_TEST_TRANSCRIPT` stands in for the speech model: it is ready
+//!   without a download and transcribes every recording as the given text.
+//!   `test_set_transcript` changes the text.
+//! - Dialogs that ask the user something take their answers from a script
+//!   instead of being shown: `AURA_TEST_DIALOGS` (comma separated) and
+//!   `test_script_dialogs` queue answers. An answer is `ok`, `cancel`, `yes`,
+//!   `no`, or a button's label or its message key. Once the script runs out
+//!   dialogs are cancelled, so a run never waits on one. Notices that only
+//!   inform aren't shown either and take no answer. Each of them emits
+//!   `test:dialog`.
+//!
+//! The commands fail unless test mode is on.
+
+use serde::Serialize;
+use std::{
+    collections::VecDeque,
+    path::PathBuf,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+use tauri::{AppHandle, Emitter, Manager};
+use tauri_plugin_dialog::MessageDialogResult;
+
+use crate::{app_state, error::AppError, i18n, stt, stt_dev};
+
+#[derive(Default)]
+pub struct DialogScript(Mutex<VecDeque<String>>);
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct DialogEvent<'a> {
+    dialog: &'a str,
+    answer: &'a str,
+}
+
+pub fn enabled() -> bool {
+    std::env::var("AURA_TEST_MODE").is_ok_and(|value| value == "1")
+}
+
+/// `var`, when test mode is on and it is set
+fn var(var: &str) -> Option<String> {
+    if !enabled() {
+        return None;
+    }
+    std::env::var(var).ok().filter(|value| !value.is_empty())
+}
+
+/// The executable to run in place of the sidecar
+pub fn mock_sidecar() -> Option<PathBuf> {
+    var("AURA_TEST_SIDECAR").map(PathBuf::from)
+}
+
+/// How long to wait for the sidecar's server, instead of the default
+pub fn sidecar_timeout() -> Option<Duration> {
+    var("AURA_TEST_SIDECAR_TIMEOUT_MS")
+        .and_then(|ms| ms.parse().ok())
+        .map(Duration::from_millis)
+}
+
+/// The text the scripted speech model transcribes every recording as
+pub fn transcript() -> Option<String> {
+    var("AURA_TEST_TRANSCRIPT")
+}
+
+/// The dialog script, with the answers from the environment queued
+pub fn init_dialog_script() -> DialogScript {
+    let answers = var("AURA_TEST_DIALOGS")
+        .map(|answers| parse_answers(&answers))
+        .unwrap_or_default();
+    DialogScript(Mutex::new(answers))
+}
+
+fn parse_answers(answers: &str) -> VecDeque<String> {
+    answers
+        .split(',')
+        .map(str::trim)
+        .filter(|answer| !answer.is_empty())
+        .map(String::from)
+        .collect()
+}
+
+fn dialog_result(app: &AppHandle, answer: &str) -> MessageDialogResult {
+    match answer {
+        "ok" => MessageDialogResult::Ok,
+        "cancel" => MessageDialogResult::Cancel,
+        "yes" => MessageDialogResult::Yes,
+        "no" => MessageDialogResult::No,
+        // A message key, or a label as it is (a missing key translates to itself)
+        label => MessageDialogResult::Custom(i18n::t(app, label)),
+    }
+}
+
+/// In test mode, the scripted answer to dialog `dialog` to use instead of
+/// showing it
+pub fn dialog_answer(app: &AppHandle, dialog: &str) -> Option<MessageDialogResult> {
+    if !enabled() {
+        return None;
+    }
+    let answer = app
+        .try_state::<DialogScript>()
+        .and_then(|script| script.0.lock().ok()?.pop_front())
+        .unwrap_or_else(|| "cancel".to_string());
+    let _ = app.emit(
+        "test:dialog",
+        DialogEvent {
+            dialog,
+            answer: &answer,
+        },
+    );
+    Some(dialog_result(app, &answer))
+}
+
+/// In test mode, whether notice `dialog` is skipped rather than shown
+pub fn skip_notice(app: &AppHandle, dialog: &str) -> bool {
+    if !enabled() {
+        return false;
+    }
+    let _ = app.emit(
+        "test:dialog",
+        DialogEvent {
+            dialog,
+            answer: "ok",
+        },
+    );
+    true
+}
+
+fn ensure_enabled() -> Result<(), AppError> {
+    if enabled() {
+        Ok(())
+    } else {
+        Err(AppError::permission_denied("Test mode is off"))
+    }
+}
+
+/// Queue answers for the next dialogs
+#[tauri::command]
+pub fn test_script_dialogs(app: AppHandle, answers: Vec<String>) -> Result<(), AppError> {
+    ensure_enabled()?;
+    let script = app
+        .try_state::<DialogScript>()
+        .ok_or_else(|| AppError::unavailable("Dialog script not found"))?;
+    let mut queued = script
+        .0
+        .lock()
+        .map_err(|_| AppError::from("Dialog script lock poisoned".to_string()))?;
+    queued.extend(answers);
+    Ok(())
+}
+
+/// Transcribe every recording as `text` from now on, in place of the speech
+/// model
+#[tauri::command]
+pub async fn test_set_transcript(app: AppHandle, text: String) -> Result<(), AppError> {
+    ensure_enabled()?;
+    let state = app
+        .try_state::<stt::SharedSttState>()
+        .ok_or_else(|| AppError::unavailable("STT state not found"))?;
+    state
+        .lock()
+        .await
+        .use_backend(Arc::new(stt_dev::MockBackend::new(vec![text])));
+    app_state::publish_stt(&app).await;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_answers() {
+        assert_eq!(
+            parse_answers("ok, dialog.connectionFailed.retry,,cancel "),
+            ["ok", "dialog.connectionFailed.retry", "cancel"]
+        );
+        assert!(parse_answers("").is_empty());
+    }
+}
@@ -0,0 +1,175 @@
+//! Filters run over transcripts before anything else sees them.
+//!
+//! Users dictating where transcripts are kept or reviewed may need
+//! profanity masked, or card numbers and US social security numbers kept
+//! out of them. Each filter of the `sttTranscriptFilters` setting is off
+//! until turned on. They run as a transcription job finishes, so the text
+//! returned, indexed, added to a dictation or sent as a prompt is already
+//! filtered. Numbers are only caught when transcribed as digits.
+
+use regex::{Captures, Regex};
+use serde::{Deserialize, Serialize};
+use std::sync::LazyLock;
+use tauri::AppHandle;
+
+use crate::{app_settings, stt::Transcription};
+
+const PROFANITY: &[&str] = &[
+    "asshole",
+    "bastard",
+    "bitch",
+    "bitches",
+    "bullshit",
+    "cunt",
+    "damn",
+    "dickhead",
+    "fuck",
+    "fucked",
+    "fucker",
+    "fucking",
+    "goddamn",
+    "motherfucker",
+    "shit",
+    "shitty",
+    "twat",
+    "wanker",
+];
+
+static CARD_NUMBER: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"\b\d(?:[ -]?\d){12,18}\b").expect("valid card number pattern"));
+static SSN: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"\b(\d{3})[- ](\d{2})[- ](\d{4})\b").expect("valid SSN pattern"));
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct TranscriptFilters {
+    pub mask_profanity: bool,
+    /// Masked along with the built-in words
+    pub profanity: Vec<String>,
+    pub redact_card_numbers: bool,
+    pub redact_ssns: bool,
+}
+
+/// Whether `digits` pass the Luhn check card numbers carry
+fn luhn(digits: &[u32]) -> bool {
+    let sum: u32 = digits
+        .iter()
+        .rev()
+        .enumerate()
+        .map(|(i, &digit)| match i % 2 {
+            0 => digit,
+            _ if digit > 4 => digit * 2 - 9,
+            _ => digit * 2,
+        })
+        .sum();
+    sum.is_multiple_of(10)
+}
+
+/// `word` with all but its first letter masked
+fn mask(word: &str) -> String {
+    let mut chars = word.chars();
+    chars.next().into_iter().chain(chars.map(|_| '*')).collect()
+}
+
+impl TranscriptFilters {
+    fn is_empty(&self) -> bool {
+        !self.mask_profanity && !self.redact_card_numbers && !self.redact_ssns
+    }
+
+    fn profanity_regex(&self) -> Option<Regex> {
+        let words: Vec<String> = PROFANITY
+            .iter()
+            .map(|word| word.to_string())
+            .chain(self.profanity.iter().map(|word| word.trim().to_string()))
+            .filter(|word| !word.is_empty())
+            .map(|word| regex::escape(&word))
+            .collect();
+        Regex::new(&format!(r"(?i)\b(?:{})\b", words.join("|"))).ok()
+    }
+
+    /// `text` as the filters have it
+    pub fn filter(&self, text: &str) -> String {
+        let mut text = text.to_string();
+        if self.redact_card_numbers {
+            text = CARD_NUMBER
+                .replace_all(&text, |caps: &Captures| {
+                    let digits: Vec<u32> = caps[0].chars().filter_map(|c| c.to_digit(10)).collect();
+                    if luhn(&digits) {
+                        "[card number]".to_string()
+                    } else {
+                        caps[0].to_string()
+                    }
+                })
+                .into_owned();
+        }
+        if self.redact_ssns {
+            text = SSN
+                .replace_all(&text, |caps: &Captures| {
+                    // Never issued, so more likely something else
+                    let area = &caps[1];
+                    if area == "000" || area == "666" || area.starts_with('9') {
+                        caps[0].to_string()
+                    } else {
+                        "[SSN]".to_string()
+                    }
+                })
+                .into_owned();
+        }
+        if self.mask_profanity
+            && let Some(regex) = self.profanity_regex()
+        {
+            text = regex
+                .replace_all(&text, |caps: &Captures| mask(&caps[0]))
+                .into_owned();
+        }
+        text
+    }
+}
+
+/// Run the configured filters over `transcription`
+pub fn apply(app: &AppHandle, transcription: &mut Transcription) {
+    let filters = app_settings::load(app).stt_transcript_filters;
+    if filters.is_empty() {
+        return;
+    }
+    transcription.text = filters.filter(&transcription.text);
+    for segment in &mut transcription.segments {
+        let text = filters.filter(&segment.text);
+        // Redacted numbers take fewer words, which moves the ones after
+        if text.split_whitespace().count() != segment.text.split_whitespace().count() {
+            segment.uncertain_words.clear();
+        }
+        segment.text = text;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_filter() {
+        let filters = TranscriptFilters {
+            mask_profanity: true,
+            profanity: vec!["frak".to_string()],
+            redact_card_numbers: true,
+            redact_ssns: true,
+        };
+        assert_eq!(
+            filters.filter("Shit, the frakking build. Frak it."),
+            "S***, the frakking build. F*** it."
+        );
+        assert_eq!(
+            filters.filter("Card 4111 1111 1111 1111, order 4111 1111 1111 1112."),
+            "Card [card number], order 4111 1111 1111 1112."
+        );
+        assert_eq!(
+            filters.filter("SSN 123-45-6789, not 900-12-3456."),
+            "SSN [SSN], not 900-12-3456."
+        );
+        assert_eq!(
+            TranscriptFilters::default().filter("Shit, 123-45-6789."),
+            "Shit, 123-45-6789."
+        );
+    }
+}
@@ -0,0 +1,206 @@
+//! Serial queue for transcription jobs.
+//!
+//! Every stop-and-transcribe becomes a job with an id that is processed by a
+//! single worker, one at a time. Overlapping requests (e.g. hotkey spam) wait
+//! their turn instead of contending on the model session mutexes and piling
+//! up on the blocking pool. Progress is reported through `stt:job` events.
+//! Each job is also a task, so `cancel_task` drops it from the queue or stops
+//! it between chunks.
+
+use serde::Serialize;
+use std::sync::{
+    Arc,
+    atomic::{AtomicU64, AtomicUsize, Ordering},
+};
+use tauri::{AppHandle, Emitter};
+use tokio::sync::{mpsc, oneshot};
+
+use crate::{
+    accessibility,
+    diarization::SpeakerEmbedder,
+    dictation, i18n, panic_guard, semantic_search,
+    stt::{SttInference, Transcription},
+    tasks::{self, TaskHandle, TaskKind},
+    transcript_filters,
+};
+
+pub struct TranscriptionJob {
+    pub audio: Vec<f32>,
+    pub inference: SttInference,
+    pub speaker_embedder: Option<SpeakerEmbedder>,
+    pub max_speakers: Option<usize>,
+    /// Dictation session this recording is a segment of
+    pub dictation_session: Option<String>,
+    /// Told whether the job succeeded, so the recording's checkpoint is kept
+    /// until then, see `recording_recovery::hand_off`
+    pub checkpoint: Option<oneshot::Sender<bool>>,
+}
+
+impl TranscriptionJob {
+    fn run(self, app: &AppHandle, task: &TaskHandle) -> Result<Transcription, String> {
+        let mut transcription = self.inference.transcribe(&self.audio, &mut |progress| {
+            let _ = app.emit("stt:transcribe-progress", progress);
+            task.set_progress(progress);
+            task.check().map_err(|e| e.message)
+        })?;
+        if let Some(embedder) = self.speaker_embedder {
+            embedder.label_speakers(&self.audio, &mut transcription.segments, self.max_speakers)?;
+        }
+        transcript_filters::apply(app, &mut transcription);
+        Ok(transcription)
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(tag = "type", rename_all = "camelCase")]
+pub enum JobStatus {
+    /// Waiting behind `position` other jobs
+    Queued {
+        position: usize,
+    },
+    Running,
+    Completed {
+        transcription: Transcription,
+    },
+    Failed {
+        error: String,
+    },
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct JobEvent {
+    pub id: u64,
+    pub status: JobStatus,
+}
+
+pub type JobResult = Result<Transcription, String>;
+
+struct QueuedJob {
+    id: u64,
+    job: TranscriptionJob,
+    task: TaskHandle,
+    reply: oneshot::Sender<JobResult>,
+}
+
+pub struct TranscriptionQueue {
+    sender: mpsc::UnboundedSender<QueuedJob>,
+    next_id: AtomicU64,
+    /// Jobs enqueued but not yet picked up by the worker
+    pending: Arc<AtomicUsize>,
+}
+
+impl TranscriptionQueue {
+    /// Queue a job; the receiver resolves once it has run. Callers that only
+    /// need the id can drop the receiver and rely on `stt:job` events.
+    pub fn enqueue(
+        &self,
+        app: &AppHandle,
+        job: TranscriptionJob,
+    ) -> Result<(u64, oneshot::Receiver<JobResult>), String> {
+        let id = self.next_id.fetch_add(1, Ordering::Relaxed);
+        let (reply, receiver) = oneshot::channel();
+        let position = self.pending.fetch_add(1, Ordering::SeqCst);
+
+        emit_job(app, id, JobStatus::Queued { position });
+        let task = tasks::queue(
+            app,
+            TaskKind::Transcription,
+            i18n::t(app, "task.transcription"),
+        );
+        if let Err(mpsc::error::SendError(queued)) = self.sender.send(QueuedJob {
+            id,
+            job,
+            task,
+            reply,
+        }) {
+            if let Some(session) = &queued.job.dictation_session {
+                dictation::finish_segment(app, session, None);
+            }
+            let error = "Transcription queue has shut down".to_string();
+            queued.task.finish::<(), _>(&Err(error.clone()));
+            return Err(error);
+        }
+
+        Ok((id, receiver))
+    }
+}
+
+fn emit_job(app: &AppHandle, id: u64, status: JobStatus) {
+    let _ = app.emit("stt:job", JobEvent { id, status });
+}
+
+async fn run_worker(
+    app: AppHandle,
+    mut receiver: mpsc::UnboundedReceiver<QueuedJob>,
+    pending: Arc<AtomicUsize>,
+) {
+    while let Some(QueuedJob {
+        id,
+        mut job,
+        task,
+        reply,
+    }) = receiver.recv().await
+    {
+        pending.fetch_sub(1, Ordering::SeqCst);
+        let dictation_session = job.dictation_session.clone();
+        let checkpoint = job.checkpoint.take();
+        // Cancelled while waiting its turn
+        let result = match task.check() {
+            Err(e) => Err(e.message),
+            Ok(()) => {
+                emit_job(&app, id, JobStatus::Running);
+                task.set_running();
+                let job_app = app.clone();
+                let job_task = task.clone();
+                tauri::async_runtime::spawn_blocking(move || job.run(&job_app, &job_task))
+                    .await
+                    .map_err(|e| format!("Transcription failed: {}", panic_guard::join_error(e)))
+                    .and_then(|result| result)
+            }
+        };
+        task.finish(&result);
+
+        match &result {
+            Ok(transcription) => {
+                semantic_search::index_transcript_in_background(&app, &transcription.text);
+                // Dictation shows its text as it goes
+                if dictation_session.is_none() {
+                    accessibility::announce(
+                        &app,
+                        &i18n::t(&app, "a11y.transcriptionDone"),
+                        accessibility::Priority::Polite,
+                    );
+                }
+                emit_job(
+                    &app,
+                    id,
+                    JobStatus::Completed {
+                        transcription: transcription.clone(),
+                    },
+                );
+            }
+            Err(e) => emit_job(&app, id, JobStatus::Failed { error: e.clone() }),
+        }
+        if let Some(session) = &dictation_session {
+            let text = result.as_ref().ok().map(|t| t.text.as_str());
+            dictation::finish_segment(&app, session, text);
+        }
+        if let Some(checkpoint) = checkpoint {
+            let _ = checkpoint.send(result.is_ok());
+        }
+        let _ = reply.send(result);
+    }
+}
+
+pub fn init_transcription_queue(app: &AppHandle) -> TranscriptionQueue {
+    let (sender, receiver) = mpsc::unbounded_channel();
+    let pending = Arc::new(AtomicUsize::new(0));
+    tauri::async_runtime::spawn(run_worker(app.clone(), receiver, pending.clone()));
+
+    TranscriptionQueue {
+        sender,
+        next_id: AtomicU64::new(1),
+        pending,
+    }
+}
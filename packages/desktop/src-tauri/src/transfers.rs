@@ -0,0 +1,514 @@
+//! Background file transfers.
+//!
+//! Model downloads and CLI syncs go through here, so the downloads panel can
+//! list them in one place (`list_transfers`) and follow them through
+//! `transfer:updated` events. At most `MAX_PARALLEL` transfers run at once;
+//! the rest wait in line. Downloads can be paused, which frees their slot,
+//! and resume with a range request where they stopped, as they do after a
+//! broken connection. Other transfers, like copying the CLI, are only
+//! tracked.
+
+use futures_util::StreamExt;
+use serde::Serialize;
+use serde_json::Value;
+use std::{
+    path::Path,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
+use tauri::{AppHandle, Emitter, Manager};
+use tokio::{
+    io::AsyncWriteExt,
+    sync::{OwnedSemaphorePermit, Semaphore, watch},
+};
+
+use crate::{
+    app_state,
+    error::{AppError, ErrorKind},
+    model_source::ModelDownloader,
+    retry::RetryPolicy,
+    tasks::Cancellation,
+};
+
+const MAX_PARALLEL: usize = 2;
+/// Finished transfers kept for the list
+const MAX_FINISHED: usize = 50;
+/// Progress events of a transfer are at least this far apart
+const PROGRESS_INTERVAL: Duration = Duration::from_millis(250);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub enum TransferKind {
+    Download,
+    Copy,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(tag = "state", rename_all = "camelCase")]
+pub enum TransferStatus {
+    Queued,
+    Running,
+    Paused,
+    Completed,
+    Failed { error: String },
+    Cancelled,
+}
+
+impl TransferStatus {
+    fn finished(&self) -> bool {
+        matches!(
+            self,
+            Self::Completed | Self::Failed { .. } | Self::Cancelled
+        )
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Transfer {
+    pub id: String,
+    pub kind: TransferKind,
+    pub label: String,
+    pub source: Option<String>,
+    pub destination: Option<String>,
+    pub bytes_done: u64,
+    /// Unknown until the transfer starts, or when the source doesn't say
+    pub bytes_total: Option<u64>,
+    /// Whether it can be paused and cancelled
+    pub pausable: bool,
+    pub status: TransferStatus,
+    /// Unix seconds
+    pub created_at: u64,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Control {
+    Run,
+    Pause,
+    Cancel,
+}
+
+struct Entry {
+    transfer: Transfer,
+    control: Option<watch::Sender<Control>>,
+}
+
+pub struct TransferState {
+    entries: Mutex<Vec<Entry>>,
+    slots: Arc<Semaphore>,
+}
+
+impl Default for TransferState {
+    fn default() -> Self {
+        Self {
+            entries: Mutex::new(Vec::new()),
+            slots: Arc::new(Semaphore::new(MAX_PARALLEL)),
+        }
+    }
+}
+
+fn now_secs() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0)
+}
+
+fn state(app: &AppHandle) -> Result<tauri::State<'_, TransferState>, String> {
+    app.try_state::<TransferState>()
+        .ok_or_else(|| "Transfer state not found".to_string())
+}
+
+/// Drop the oldest finished transfers past `MAX_FINISHED`, returning their
+/// ids
+fn prune(entries: &mut Vec<Entry>) -> Vec<String> {
+    let finished = entries
+        .iter()
+        .filter(|entry| entry.transfer.status.finished())
+        .count();
+    let mut excess = finished.saturating_sub(MAX_FINISHED);
+    let mut dropped = Vec::new();
+    entries.retain(|entry| {
+        if excess > 0 && entry.transfer.status.finished() {
+            excess -= 1;
+            dropped.push(entry.transfer.id.clone());
+            return false;
+        }
+        true
+    });
+    dropped
+}
+
+fn update(app: &AppHandle, id: &str, change: impl FnOnce(&mut Transfer)) {
+    let Ok(state) = state(app) else {
+        return;
+    };
+    let (transfer, dropped) = {
+        let Ok(mut entries) = state.entries.lock() else {
+            return;
+        };
+        let Some(entry) = entries.iter_mut().find(|entry| entry.transfer.id == id) else {
+            return;
+        };
+        change(&mut entry.transfer);
+        if entry.transfer.status.finished() {
+            entry.control = None;
+        }
+        let transfer = entry.transfer.clone();
+        (transfer, prune(&mut entries))
+    };
+    let _ = app.emit("transfer:updated", &transfer);
+    app_state::publish(app, &["transfers", &transfer.id], &transfer);
+    for id in dropped {
+        app_state::publish(app, &["transfers", &id], Value::Null);
+    }
+}
+
+fn register(
+    app: &AppHandle,
+    kind: TransferKind,
+    label: String,
+    source: Option<String>,
+    destination: Option<String>,
+    pausable: bool,
+) -> Result<(String, watch::Receiver<Control>), String> {
+    let (control, receiver) = watch::channel(Control::Run);
+    let transfer = Transfer {
+        id: uuid::Uuid::new_v4().to_string(),
+        kind,
+        label,
+        source,
+        destination,
+        bytes_done: 0,
+        bytes_total: None,
+        pausable,
+        status: TransferStatus::Queued,
+        created_at: now_secs(),
+    };
+    let state = state(app)?;
+    state
+        .entries
+        .lock()
+        .map_err(|e| format!("Lock error: {}", e))?
+        .push(Entry {
+            transfer: transfer.clone(),
+            control: pausable.then_some(control),
+        });
+    let _ = app.emit("transfer:updated", &transfer);
+    app_state::publish(app, &["transfers", &transfer.id], &transfer);
+    Ok((transfer.id, receiver))
+}
+
+/// Record how a transfer ended
+pub fn finish(app: &AppHandle, id: &str, error: Option<&AppError>) {
+    update(app, id, |transfer| {
+        transfer.status = match error {
+            None => TransferStatus::Completed,
+            Some(error) if error.kind == ErrorKind::Cancelled => TransferStatus::Cancelled,
+            Some(error) => TransferStatus::Failed {
+                error: error.message.clone(),
+            },
+        };
+    });
+}
+
+/// Track a transfer that runs elsewhere and can't be paused. It is shown as
+/// running until `finish`.
+pub fn track(app: &AppHandle, kind: TransferKind, label: String) -> Result<String, String> {
+    let (id, _) = register(app, kind, label, None, None, false)?;
+    update(app, &id, |transfer| {
+        transfer.status = TransferStatus::Running
+    });
+    Ok(id)
+}
+
+fn cancelled() -> AppError {
+    AppError::cancelled("Transfer cancelled")
+}
+
+/// A free slot, once there is one. None when paused meanwhile; cancelling
+/// gives up.
+async fn acquire(
+    slots: &Arc<Semaphore>,
+    control: &mut watch::Receiver<Control>,
+) -> Result<Option<OwnedSemaphorePermit>, AppError> {
+    loop {
+        match *control.borrow_and_update() {
+            Control::Run => {}
+            Control::Pause => return Ok(None),
+            Control::Cancel => return Err(cancelled()),
+        }
+        tokio::select! {
+            permit = slots.clone().acquire_owned() => {
+                return permit
+                    .map(Some)
+                    .map_err(|e| AppError::from(format!("Transfer slots closed: {}", e)));
+            }
+            changed = control.changed() => {
+                if changed.is_err() {
+                    return Err(cancelled());
+                }
+            }
+        }
+    }
+}
+
+/// Wait while paused. Errors when cancelled.
+async fn wait_resumed(control: &mut watch::Receiver<Control>) -> Result<(), AppError> {
+    loop {
+        match *control.borrow_and_update() {
+            Control::Run => return Ok(()),
+            Control::Cancel => return Err(cancelled()),
+            Control::Pause => {}
+        }
+        control.changed().await.map_err(|_| cancelled())?;
+    }
+}
+
+enum Stopped {
+    Done,
+    Paused,
+    /// The connection broke; what arrived so far is kept
+    Interrupted(AppError),
+}
+
+/// One stretch of a download, from `offset` until done or paused
+async fn download_from(
+    app: &AppHandle,
+    id: &str,
+    control: &mut watch::Receiver<Control>,
+    downloader: &ModelDownloader,
+    url: &str,
+    path: &Path,
+    offset: &mut u64,
+) -> Result<Stopped, AppError> {
+    let response = downloader
+        .get(url, *offset)
+        .await
+        .map_err(AppError::network)?;
+
+    let resumed = *offset > 0 && response.status() == reqwest::StatusCode::PARTIAL_CONTENT;
+    if !resumed {
+        *offset = 0;
+    }
+    let total = response.content_length().map(|len| len + *offset);
+    let mut file = tokio::fs::OpenOptions::new()
+        .create(true)
+        .write(true)
+        .append(resumed)
+        .truncate(!resumed)
+        .open(path)
+        .await
+        .map_err(|e| AppError::io(format!("Failed to create file: {}", e)))?;
+    let done = *offset;
+    update(app, id, |transfer| {
+        transfer.status = TransferStatus::Running;
+        transfer.bytes_done = done;
+        transfer.bytes_total = total;
+    });
+
+    let mut stream = response.bytes_stream();
+    let mut last_progress = Instant::now();
+    let mut stopped = Stopped::Done;
+    while let Some(chunk) = stream.next().await {
+        let chunk = match chunk {
+            Ok(chunk) => chunk,
+            Err(e) => {
+                stopped = Stopped::Interrupted(AppError::network(format!("Download error: {}", e)));
+                break;
+            }
+        };
+        file.write_all(&chunk)
+            .await
+            .map_err(|e| AppError::io(format!("Write error: {}", e)))?;
+        *offset += chunk.len() as u64;
+
+        match *control.borrow() {
+            Control::Run => {}
+            Control::Pause => {
+                stopped = Stopped::Paused;
+                break;
+            }
+            Control::Cancel => return Err(cancelled()),
+        }
+        if last_progress.elapsed() >= PROGRESS_INTERVAL {
+            last_progress = Instant::now();
+            let done = *offset;
+            update(app, id, |transfer| transfer.bytes_done = done);
+        }
+    }
+
+    file.flush()
+        .await
+        .map_err(|e| AppError::io(format!("Flush error: {}", e)))?;
+    let done = *offset;
+    update(app, id, |transfer| transfer.bytes_done = done);
+    Ok(stopped)
+}
+
+async fn run_download(
+    app: &AppHandle,
+    id: &str,
+    control: &mut watch::Receiver<Control>,
+    downloader: &ModelDownloader,
+    url: &str,
+    path: &Path,
+) -> Result<(), AppError> {
+    let slots = state(app)?.slots.clone();
+    let policy = RetryPolicy::DOWNLOAD;
+    let mut offset = 0;
+    let mut retries = 0;
+    loop {
+        let Some(permit) = acquire(&slots, control).await? else {
+            update(app, id, |transfer| transfer.status = TransferStatus::Paused);
+            wait_resumed(control).await?;
+            update(app, id, |transfer| transfer.status = TransferStatus::Queued);
+            continue;
+        };
+        let stopped = download_from(app, id, control, downloader, url, path, &mut offset).await?;
+        drop(permit);
+        match stopped {
+            Stopped::Done => return Ok(()),
+            // The slot is free for others until resumed
+            Stopped::Paused => {}
+            Stopped::Interrupted(error) => {
+                retries += 1;
+                if retries >= policy.max_attempts {
+                    return Err(error);
+                }
+                // Picks up where it broke off
+                tokio::time::sleep(policy.delay(retries)).await;
+            }
+        }
+    }
+}
+
+/// Download `url` to `path` as a transfer, waiting for a free slot first;
+/// `cancellation` cancels the transfer as `cancel_transfer` does
+pub async fn download(
+    app: &AppHandle,
+    downloader: &ModelDownloader,
+    url: &str,
+    path: &Path,
+    mut cancellation: Cancellation,
+) -> Result<(), AppError> {
+    let label = path
+        .file_name()
+        .map(|name| name.to_string_lossy().into_owned())
+        .unwrap_or_else(|| url.to_string());
+    let (id, mut control) = register(
+        app,
+        TransferKind::Download,
+        label,
+        Some(url.to_string()),
+        Some(path.to_string_lossy().into_owned()),
+        true,
+    )?;
+
+    let forward = {
+        let app = app.clone();
+        let id = id.clone();
+        tauri::async_runtime::spawn(async move {
+            cancellation.cancelled().await;
+            let _ = send_control(&app, &id, Control::Cancel);
+        })
+    };
+    let result = run_download(app, &id, &mut control, downloader, url, path).await;
+    forward.abort();
+    if matches!(&result, Err(e) if e.kind == ErrorKind::Cancelled) {
+        let _ = tokio::fs::remove_file(path).await;
+    }
+    finish(app, &id, result.as_ref().err());
+    result
+}
+
+fn send_control(app: &AppHandle, id: &str, control: Control) -> Result<(), AppError> {
+    let state = state(app)?;
+    let entries = state
+        .entries
+        .lock()
+        .map_err(|e| format!("Lock error: {}", e))?;
+    let entry = entries
+        .iter()
+        .find(|entry| entry.transfer.id == id)
+        .ok_or_else(|| AppError::not_found(format!("No transfer {}", id)))?;
+    let sender = entry.control.as_ref().ok_or_else(|| {
+        AppError::invalid_input(format!("Transfer {} can't be paused or cancelled", id))
+    })?;
+    sender.send_replace(control);
+    Ok(())
+}
+
+#[tauri::command]
+pub fn list_transfers(app: AppHandle) -> Result<Vec<Transfer>, AppError> {
+    let state = state(&app)?;
+    let entries = state
+        .entries
+        .lock()
+        .map_err(|e| format!("Lock error: {}", e))?;
+    Ok(entries.iter().map(|entry| entry.transfer.clone()).collect())
+}
+
+#[tauri::command]
+pub fn pause_transfer(app: AppHandle, id: String) -> Result<(), AppError> {
+    send_control(&app, &id, Control::Pause)
+}
+
+#[tauri::command]
+pub fn resume_transfer(app: AppHandle, id: String) -> Result<(), AppError> {
+    send_control(&app, &id, Control::Run)
+}
+
+#[tauri::command]
+pub fn cancel_transfer(app: AppHandle, id: String) -> Result<(), AppError> {
+    send_control(&app, &id, Control::Cancel)
+}
+
+/// Remove completed, failed and cancelled transfers from the list
+#[tauri::command]
+pub fn clear_finished_transfers(app: AppHandle) -> Result<(), AppError> {
+    let state = state(&app)?;
+    state
+        .entries
+        .lock()
+        .map_err(|e| format!("Lock error: {}", e))?
+        .retain(|entry| !entry.transfer.status.finished());
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(status: TransferStatus) -> Entry {
+        Entry {
+            transfer: Transfer {
+                id: String::new(),
+                kind: TransferKind::Download,
+                label: String::new(),
+                source: None,
+                destination: None,
+                bytes_done: 0,
+                bytes_total: None,
+                pausable: true,
+                status,
+                created_at: 0,
+            },
+            control: None,
+        }
+    }
+
+    #[test]
+    fn test_prune_keeps_active_transfers() {
+        let mut entries: Vec<Entry> = (0..MAX_FINISHED + 3)
+            .map(|_| entry(TransferStatus::Completed))
+            .collect();
+        entries.insert(1, entry(TransferStatus::Running));
+        entries.insert(2, entry(TransferStatus::Paused));
+        prune(&mut entries);
+
+        assert_eq!(entries.len(), MAX_FINISHED + 2);
+        assert_eq!(entries[0].transfer.status, TransferStatus::Running);
+        assert_eq!(entries[1].transfer.status, TransferStatus::Paused);
+    }
+}
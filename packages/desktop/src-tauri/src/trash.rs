@@ -0,0 +1,529 @@
+//! Undoable deletions.
+//!
+//! Files deleted from Rust are moved into a trash directory under the app's
+//! local data instead of being removed, grouped into operations that
+//! `undo_last_operation` puts back. Only the last few operations are kept;
+//! older ones are handed to the system trash where there is one, and removed
+//! otherwise. `empty_trash` removes everything for good.
+//!
+//! Files that have to stay in place (stores the store plugin holds open) are
+//! snapshotted instead, and reloaded after an undo.
+
+use serde::{Deserialize, Serialize};
+use std::{
+    path::{Path, PathBuf},
+    sync::Mutex,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+use tauri::{AppHandle, Emitter, Manager, Webview, path::BaseDirectory};
+use tauri_plugin_store::StoreExt;
+
+use crate::{
+    audit, error::AppError, recording_recovery, semantic_search::SemanticIndexState,
+    session_index::SessionIndexState,
+};
+
+const TRASH_DIR: &str = "trash";
+const JOURNAL_FILE: &str = "operations.json";
+const MAX_OPERATIONS: usize = 5;
+const MAX_AGE: Duration = Duration::from_secs(7 * 24 * 60 * 60);
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct TrashedItem {
+    original: PathBuf,
+    stored: PathBuf,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Operation {
+    id: u64,
+    label: String,
+    created_at: u64,
+    items: Vec<TrashedItem>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct OperationInfo {
+    pub id: u64,
+    pub label: String,
+    pub created_at: u64,
+    pub paths: Vec<String>,
+}
+
+impl From<&Operation> for OperationInfo {
+    fn from(operation: &Operation) -> Self {
+        Self {
+            id: operation.id,
+            label: operation.label.clone(),
+            created_at: operation.created_at,
+            paths: operation
+                .items
+                .iter()
+                .map(|item| item.original.to_string_lossy().to_string())
+                .collect(),
+        }
+    }
+}
+
+#[derive(Default)]
+pub struct TrashState(Mutex<()>);
+
+fn now_secs() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0)
+}
+
+fn trash_dir(app: &AppHandle) -> Result<PathBuf, String> {
+    app.path()
+        .resolve(TRASH_DIR, BaseDirectory::AppLocalData)
+        .map_err(|e| format!("Failed to resolve trash directory: {}", e))
+}
+
+fn read_journal(app: &AppHandle) -> Vec<Operation> {
+    trash_dir(app)
+        .ok()
+        .and_then(|dir| std::fs::read_to_string(dir.join(JOURNAL_FILE)).ok())
+        .and_then(|content| serde_json::from_str(&content).ok())
+        .unwrap_or_default()
+}
+
+fn write_journal(app: &AppHandle, operations: &[Operation]) -> Result<(), String> {
+    let dir = trash_dir(app)?;
+    std::fs::create_dir_all(&dir)
+        .map_err(|e| format!("Failed to create {}: {}", dir.display(), e))?;
+    let content = serde_json::to_string_pretty(operations)
+        .map_err(|e| format!("Failed to serialize trash journal: {}", e))?;
+    std::fs::write(dir.join(JOURNAL_FILE), content)
+        .map_err(|e| format!("Failed to write trash journal: {}", e))
+}
+
+fn copy_recursive(from: &Path, to: &Path) -> std::io::Result<()> {
+    if from.is_dir() {
+        std::fs::create_dir_all(to)?;
+        for entry in std::fs::read_dir(from)? {
+            let entry = entry?;
+            copy_recursive(&entry.path(), &to.join(entry.file_name()))?;
+        }
+        Ok(())
+    } else {
+        std::fs::copy(from, to).map(|_| ())
+    }
+}
+
+fn remove_path(path: &Path) -> std::io::Result<()> {
+    if path.is_dir() {
+        std::fs::remove_dir_all(path)
+    } else {
+        std::fs::remove_file(path)
+    }
+}
+
+/// Rename, falling back to copy and remove across volumes
+fn move_path(from: &Path, to: &Path) -> Result<(), String> {
+    if let Some(parent) = to.parent() {
+        std::fs::create_dir_all(parent)
+            .map_err(|e| format!("Failed to create {}: {}", parent.display(), e))?;
+    }
+    if std::fs::rename(from, to).is_ok() {
+        return Ok(());
+    }
+    copy_recursive(from, to)
+        .and_then(|_| remove_path(from))
+        .map_err(|e| {
+            let _ = remove_path(to);
+            format!("Failed to move {}: {}", from.display(), e)
+        })
+}
+
+/// A group of deletions undone together. Nothing is recorded until
+/// `commit`; dropping an uncommitted operation puts its files back.
+pub struct TrashOperation {
+    app: AppHandle,
+    operation: Operation,
+    dir: PathBuf,
+    committed: bool,
+}
+
+impl TrashOperation {
+    fn stored_path(&self, path: &Path) -> PathBuf {
+        let name = path
+            .file_name()
+            .map(|name| name.to_string_lossy().to_string())
+            .unwrap_or_else(|| "item".to_string());
+        self.dir
+            .join(format!("{}-{}", self.operation.items.len(), name))
+    }
+
+    /// Move a file or directory into the trash. Missing paths are skipped.
+    pub fn remove(&mut self, path: &Path) -> Result<(), String> {
+        if std::fs::symlink_metadata(path).is_err() {
+            return Ok(());
+        }
+        let stored = self.stored_path(path);
+        move_path(path, &stored)?;
+        self.operation.items.push(TrashedItem {
+            original: path.to_path_buf(),
+            stored,
+        });
+        Ok(())
+    }
+
+    /// Keep a copy of a file that is about to be changed in place
+    pub fn snapshot(&mut self, path: &Path) -> Result<(), String> {
+        if !path.exists() {
+            return Ok(());
+        }
+        let stored = self.stored_path(path);
+        if let Some(parent) = stored.parent() {
+            std::fs::create_dir_all(parent)
+                .map_err(|e| format!("Failed to create {}: {}", parent.display(), e))?;
+        }
+        copy_recursive(path, &stored)
+            .map_err(|e| format!("Failed to snapshot {}: {}", path.display(), e))?;
+        self.operation.items.push(TrashedItem {
+            original: path.to_path_buf(),
+            stored,
+        });
+        Ok(())
+    }
+
+    /// Record the operation for undo. Returns its id, or None if nothing was
+    /// trashed.
+    pub fn commit(mut self) -> Result<Option<u64>, String> {
+        if self.operation.items.is_empty() {
+            self.committed = true;
+            let _ = std::fs::remove_dir_all(&self.dir);
+            return Ok(None);
+        }
+
+        let state = self
+            .app
+            .try_state::<TrashState>()
+            .ok_or("Trash state not found")?;
+        let guard = state.0.lock().map_err(|e| format!("Lock error: {}", e))?;
+        let mut operations = read_journal(&self.app);
+        operations.push(self.operation.clone());
+        let expired = split_expired(&mut operations, now_secs());
+        // Without a journal entry the files couldn't be found again, so they
+        // are put back when this fails
+        write_journal(&self.app, &operations)?;
+        self.committed = true;
+        drop(guard);
+
+        dispose(expired);
+        emit_changed(&self.app, &operations);
+        Ok(Some(self.operation.id))
+    }
+}
+
+impl Drop for TrashOperation {
+    fn drop(&mut self) {
+        if !self.committed {
+            restore(&self.app, &self.operation);
+        }
+    }
+}
+
+/// Start a group of deletions
+pub fn begin(app: &AppHandle, label: &str) -> Result<TrashOperation, String> {
+    // Nanoseconds keep ids unique across restarts without a counter
+    let id = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_nanos() as u64)
+        .unwrap_or(0);
+    let dir = trash_dir(app)?.join(id.to_string());
+    Ok(TrashOperation {
+        app: app.clone(),
+        operation: Operation {
+            id,
+            label: label.to_string(),
+            created_at: now_secs(),
+            items: Vec::new(),
+        },
+        dir,
+        committed: false,
+    })
+}
+
+/// Take the operations past the undo history out of `operations`
+fn split_expired(operations: &mut Vec<Operation>, now: u64) -> Vec<Operation> {
+    let (mut keep, mut expired): (Vec<_>, Vec<_>) = std::mem::take(operations)
+        .into_iter()
+        .partition(|operation| now.saturating_sub(operation.created_at) < MAX_AGE.as_secs());
+    if keep.len() > MAX_OPERATIONS {
+        let excess = keep.len() - MAX_OPERATIONS;
+        expired.extend(keep.drain(..excess));
+    }
+    *operations = keep;
+    expired
+}
+
+/// Hand expired operations to the system trash, in the background since
+/// they can be large
+fn dispose(expired: Vec<Operation>) {
+    if expired.is_empty() {
+        return;
+    }
+    std::thread::spawn(move || {
+        for operation in expired {
+            for item in &operation.items {
+                if let Err(e) = platform::move_to_system_trash(&item.stored) {
+                    eprintln!("{}, deleting instead", e);
+                    let _ = remove_path(&item.stored);
+                }
+            }
+            if let Some(dir) = operation
+                .items
+                .first()
+                .and_then(|item| item.stored.parent())
+            {
+                let _ = std::fs::remove_dir_all(dir);
+            }
+        }
+    });
+}
+
+/// Put an operation's files back, returning the paths that failed
+fn restore(app: &AppHandle, operation: &Operation) -> Vec<String> {
+    let mut errors = Vec::new();
+    for item in operation.items.iter().rev() {
+        before_restore(app, &item.original);
+        // Snapshots replace whatever is there now
+        if item.original.exists() {
+            let _ = remove_path(&item.original);
+        }
+        if let Err(e) = move_path(&item.stored, &item.original) {
+            errors.push(e);
+        }
+        // What was loaded from the path follows it either way
+        after_restore(app, &item.original);
+    }
+    if let Some(dir) = operation
+        .items
+        .first()
+        .and_then(|item| item.stored.parent())
+    {
+        let _ = std::fs::remove_dir_all(dir);
+    }
+    errors
+}
+
+/// Release what holds files open at `path`, before it's replaced
+fn before_restore(app: &AppHandle, path: &Path) {
+    if let Some(index) = app.try_state::<SessionIndexState>()
+        && index.path() == path
+        && let Err(e) = index.close()
+    {
+        eprintln!("Failed to close session index: {}", e);
+    }
+}
+
+/// Reload state that was loaded from a restored file
+fn after_restore(app: &AppHandle, path: &Path) {
+    if path.extension().is_some_and(|ext| ext == "dat")
+        && let Ok(store) = app.store(path)
+        && let Err(e) = store.reload()
+    {
+        eprintln!("Failed to reload {}: {}", path.display(), e);
+    }
+    if let Some(index) = app.try_state::<SemanticIndexState>()
+        && index.path() == path
+        && let Err(e) = index.reload()
+    {
+        eprintln!("Failed to reload semantic index: {}", e);
+    }
+    if let Some(index) = app.try_state::<SessionIndexState>()
+        && index.path() == path
+        && let Err(e) = index.reopen()
+    {
+        eprintln!("Failed to reopen session index: {}", e);
+    }
+    recording_recovery::restored(app, path);
+}
+
+fn emit_changed(app: &AppHandle, operations: &[Operation]) {
+    let infos: Vec<OperationInfo> = operations.iter().map(OperationInfo::from).collect();
+    let _ = app.emit("trash:changed", infos);
+}
+
+/// Drop expired operations left over from earlier runs
+pub fn cleanup(app: &AppHandle) {
+    let Some(state) = app.try_state::<TrashState>() else {
+        return;
+    };
+    let Ok(_guard) = state.0.lock() else {
+        return;
+    };
+    let mut operations = read_journal(app);
+    let expired = split_expired(&mut operations, now_secs());
+    if !expired.is_empty() && write_journal(app, &operations).is_ok() {
+        dispose(expired);
+    }
+}
+
+/// Deletions that can still be undone, oldest first
+#[tauri::command]
+pub fn list_trash_operations(app: AppHandle) -> Vec<OperationInfo> {
+    read_journal(&app).iter().map(OperationInfo::from).collect()
+}
+
+/// Put back the files of the most recent deletion
+#[tauri::command]
+pub async fn undo_last_operation(app: AppHandle) -> Result<Option<OperationInfo>, AppError> {
+    Ok(tauri::async_runtime::spawn_blocking(move || {
+        let state = app
+            .try_state::<TrashState>()
+            .ok_or("Trash state not found")?;
+        let _guard = state.0.lock().map_err(|e| format!("Lock error: {}", e))?;
+        let mut operations = read_journal(&app);
+        let Some(operation) = operations.pop() else {
+            return Ok(None);
+        };
+
+        let errors = restore(&app, &operation);
+        write_journal(&app, &operations)?;
+        emit_changed(&app, &operations);
+        if !errors.is_empty() {
+            return Err(format!(
+                "Failed to restore part of \"{}\": {}",
+                operation.label,
+                errors.join("; ")
+            ));
+        }
+        Ok(Some(OperationInfo::from(&operation)))
+    })
+    .await
+    .map_err(|e| format!("Undo task failed: {}", e))??)
+}
+
+/// Permanently delete everything in the trash
+#[tauri::command]
+pub async fn empty_trash(app: AppHandle, webview: Webview) -> Result<(), AppError> {
+    let result = empty(app).await;
+    audit::record(&webview, "empty_trash", serde_json::Value::Null, &result);
+    result
+}
+
+async fn empty(app: AppHandle) -> Result<(), AppError> {
+    Ok(tauri::async_runtime::spawn_blocking(move || {
+        let state = app
+            .try_state::<TrashState>()
+            .ok_or("Trash state not found")?;
+        let _guard = state.0.lock().map_err(|e| format!("Lock error: {}", e))?;
+        let dir = trash_dir(&app)?;
+        match std::fs::remove_dir_all(&dir) {
+            Err(e) if e.kind() != std::io::ErrorKind::NotFound => {
+                return Err(format!("Failed to empty trash: {}", e));
+            }
+            _ => {}
+        }
+        emit_changed(&app, &[]);
+        Ok(())
+    })
+    .await
+    .map_err(|e| format!("Empty trash task failed: {}", e))??)
+}
+
+#[cfg(target_os = "linux")]
+mod platform {
+    use std::path::Path;
+
+    pub fn move_to_system_trash(path: &Path) -> Result<(), String> {
+        let status = std::process::Command::new("gio")
+            .arg("trash")
+            .arg(path)
+            .status()
+            .map_err(|e| format!("Failed to run gio: {}", e))?;
+        if !status.success() {
+            return Err(format!("gio trash failed for {}", path.display()));
+        }
+        Ok(())
+    }
+}
+
+#[cfg(target_os = "macos")]
+mod platform {
+    use std::path::Path;
+
+    pub fn move_to_system_trash(path: &Path) -> Result<(), String> {
+        let script = format!(
+            "tell application \"Finder\" to delete POSIX file \"{}\"",
+            path.to_string_lossy()
+                .replace('\\', "\\\\")
+                .replace('"', "\\\"")
+        );
+        let status = std::process::Command::new("osascript")
+            .args(["-e", &script])
+            .stdout(std::process::Stdio::null())
+            .status()
+            .map_err(|e| format!("Failed to run osascript: {}", e))?;
+        if !status.success() {
+            return Err(format!("Finder failed to trash {}", path.display()));
+        }
+        Ok(())
+    }
+}
+
+#[cfg(windows)]
+mod platform {
+    use std::{os::windows::process::CommandExt, path::Path};
+
+    const CREATE_NO_WINDOW: u32 = 0x0800_0000;
+
+    pub fn move_to_system_trash(path: &Path) -> Result<(), String> {
+        let method = if path.is_dir() {
+            "DeleteDirectory"
+        } else {
+            "DeleteFile"
+        };
+        let script = format!(
+            "Add-Type -AssemblyName Microsoft.VisualBasic; \
+             [Microsoft.VisualBasic.FileIO.FileSystem]::{}('{}', 'OnlyErrorDialogs', 'SendToRecycleBin')",
+            method,
+            path.to_string_lossy().replace('\'', "''")
+        );
+        let status = std::process::Command::new("powershell")
+            .args(["-NoProfile", "-NonInteractive", "-Command", &script])
+            .creation_flags(CREATE_NO_WINDOW)
+            .status()
+            .map_err(|e| format!("Failed to run powershell: {}", e))?;
+        if !status.success() {
+            return Err(format!("Failed to recycle {}", path.display()));
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn operation(id: u64, created_at: u64) -> Operation {
+        Operation {
+            id,
+            label: String::new(),
+            created_at,
+            items: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn test_split_expired_keeps_recent_history() {
+        let now = MAX_AGE.as_secs() * 2;
+        let mut operations: Vec<Operation> = (0..7).map(|id| operation(id, now - 10)).collect();
+        operations.insert(0, operation(99, now - MAX_AGE.as_secs()));
+
+        let expired: Vec<u64> = split_expired(&mut operations, now)
+            .iter()
+            .map(|operation| operation.id)
+            .collect();
+        let kept: Vec<u64> = operations.iter().map(|operation| operation.id).collect();
+        assert_eq!(expired, [99, 0, 1]);
+        assert_eq!(kept, [2, 3, 4, 5, 6]);
+    }
+}
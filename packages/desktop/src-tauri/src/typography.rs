@@ -0,0 +1,268 @@
+//! UI font and emoji rendering, per window.
+//!
+//! Settings under `windowTypography` are keyed by window label or kind, like
+//! the display rules of `window_placement`: a label's own settings win over
+//! those of its kind. They override the font variables of the UI's theme
+//! from a style sheet injected before the page loads, so the first paint
+//! already uses them, and are applied to open windows when changed.
+//!
+//! WebView2 draws emoji in monochrome when the font stack reaches Segoe UI
+//! Symbol before Segoe UI Emoji, which most CJK and custom fonts make it do.
+//! `colorEmoji` puts the color emoji fonts ahead of the fallbacks.
+
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use tauri::{AppHandle, Manager};
+use tauri_plugin_store::StoreExt;
+
+use crate::{SETTINGS_STORE, app_settings, atomic_store, error::AppError};
+
+const TYPOGRAPHY_KEY: &str = "windowTypography";
+const STYLE_ID: &str = "aura-typography";
+const MIN_FONT_SIZE: f64 = 8.0;
+const MAX_FONT_SIZE: f64 = 32.0;
+const MAX_FAMILY_CHARS: usize = 200;
+const GENERIC_FAMILIES: &[&str] = &[
+    "serif",
+    "sans-serif",
+    "monospace",
+    "cursive",
+    "fantasy",
+    "system-ui",
+    "ui-serif",
+    "ui-sans-serif",
+    "ui-monospace",
+    "ui-rounded",
+    "emoji",
+    "math",
+];
+/// The families and sizes of the UI's theme, which the settings override
+const APP_SANS_FONTS: &str = "\"Inter\", \"Inter Fallback\"";
+const APP_MONO_FONTS: &str = "\"IBM Plex Mono\", \"IBM Plex Mono Fallback\"";
+const BASE_FONT_SIZE: f64 = 14.0;
+const SIZE_VARIABLES: &[(&str, f64)] = &[
+    ("--font-size-small", 13.0),
+    ("--font-size-base", 14.0),
+    ("--font-size-large", 16.0),
+    ("--font-size-x-large", 20.0),
+];
+const COLOR_EMOJI_FONTS: &[&str] = &["Segoe UI Emoji", "Apple Color Emoji", "Noto Color Emoji"];
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct Typography {
+    /// CSS font families, comma separated; `None` keeps the app's
+    pub font_family: Option<String>,
+    /// Base font size in CSS pixels, the others scaled along; `None` keeps
+    /// the app's
+    pub font_size: Option<f64>,
+    pub color_emoji: bool,
+}
+
+fn all_settings(app: &AppHandle) -> HashMap<String, Typography> {
+    app_settings::load(app).window_typography
+}
+
+/// The settings of window `label`: its own, else those of its kind
+fn settings_for(settings: &HashMap<String, Typography>, label: &str) -> Typography {
+    let kind = label.split('-').next().unwrap_or(label);
+    settings
+        .get(label)
+        .or_else(|| settings.get(kind))
+        .cloned()
+        .unwrap_or_default()
+}
+
+/// `family` as a CSS font family list, each name quoted unless generic
+fn font_stack(family: &str) -> Result<Vec<String>, String> {
+    if family.chars().count() > MAX_FAMILY_CHARS {
+        return Err(format!(
+            "Font families are limited to {} characters",
+            MAX_FAMILY_CHARS
+        ));
+    }
+    family
+        .split(',')
+        .map(|name| name.trim().trim_matches(['"', '\'']).trim())
+        .filter(|name| !name.is_empty())
+        .map(|name| {
+            if name.chars().any(|c| {
+                c.is_control() || matches!(c, '"' | '\'' | '\\' | ';' | '{' | '}' | '<' | '>')
+            }) {
+                Err(format!("Invalid font family {}", name))
+            } else if GENERIC_FAMILIES.contains(&name.to_lowercase().as_str()) {
+                Ok(name.to_lowercase())
+            } else {
+                Ok(format!("\"{}\"", name))
+            }
+        })
+        .collect()
+}
+
+pub fn validate(typography: &Typography) -> Result<(), String> {
+    if let Some(family) = &typography.font_family {
+        font_stack(family)?;
+    }
+    match typography.font_size {
+        Some(size) if !(MIN_FONT_SIZE..=MAX_FONT_SIZE).contains(&size) => Err(format!(
+            "Font sizes go from {} to {} pixels",
+            MIN_FONT_SIZE, MAX_FONT_SIZE
+        )),
+        _ => Ok(()),
+    }
+}
+
+fn style_sheet(typography: &Typography) -> String {
+    let mut variables = Vec::new();
+    let custom = typography
+        .font_family
+        .as_deref()
+        .and_then(|family| font_stack(family).ok())
+        .filter(|stack| !stack.is_empty());
+    let emoji: Vec<String> = if typography.color_emoji {
+        COLOR_EMOJI_FONTS
+            .iter()
+            .map(|font| format!("\"{}\"", font))
+            .collect()
+    } else {
+        Vec::new()
+    };
+    if custom.is_some() || !emoji.is_empty() {
+        let mut sans = custom.unwrap_or_else(|| vec![APP_SANS_FONTS.to_string()]);
+        sans.extend(emoji.iter().cloned());
+        sans.push("sans-serif".to_string());
+        variables.push(format!("--font-family-sans: {}", sans.join(", ")));
+    }
+    if !emoji.is_empty() {
+        variables.push(format!(
+            "--font-family-mono: {}, {}, monospace",
+            APP_MONO_FONTS,
+            emoji.join(", ")
+        ));
+    }
+    if let Some(size) = typography.font_size {
+        let scale = size / BASE_FONT_SIZE;
+        for (variable, default) in SIZE_VARIABLES {
+            variables.push(format!(
+                "{}: {}px",
+                variable,
+                (default * scale * 10.0).round() / 10.0
+            ));
+        }
+    }
+
+    let mut rules: Vec<String> = variables
+        .iter()
+        .map(|variable| format!(":root {{ {} !important; }}", variable))
+        .collect();
+    if typography.color_emoji {
+        rules.push(":root { font-variant-emoji: emoji; }".to_string());
+    }
+    rules.join("\n")
+}
+
+/// Script that puts the style sheet of `typography` in the page, replacing
+/// an earlier one, and again once the page has a head if it had none yet
+fn script(typography: &Typography) -> String {
+    let css = serde_json::to_string(&style_sheet(typography)).unwrap_or_default();
+    format!(
+        r#"(() => {{
+  const apply = () => {{
+    let style = document.getElementById("{id}");
+    if (!style) {{
+      style = document.createElement("style");
+      style.id = "{id}";
+    }}
+    style.textContent = {css};
+    const parent = document.head || document.documentElement;
+    if (parent && style.parentNode !== parent) parent.appendChild(style);
+  }};
+  apply();
+  document.addEventListener("DOMContentLoaded", apply, {{ once: true }});
+}})();"#,
+        id = STYLE_ID,
+        css = css
+    )
+}
+
+/// Initialization script for a new window `label`
+pub fn init_script(app: &AppHandle, label: &str) -> String {
+    script(&settings_for(&all_settings(app), label))
+}
+
+/// Apply the current settings to every open window they concern
+pub fn apply_to_open_windows(app: &AppHandle) {
+    let settings = all_settings(app);
+    for (label, window) in app.webview_windows() {
+        if let Err(e) = window.eval(script(&settings_for(&settings, &label))) {
+            eprintln!("Failed to apply typography to {}: {}", label, e);
+        }
+    }
+}
+
+#[tauri::command]
+pub fn get_window_typography(app: AppHandle) -> HashMap<String, Typography> {
+    all_settings(&app)
+}
+
+/// Set the UI font and emoji rendering of window `window` (a label or a
+/// kind), or with `None` go back to the app's
+#[tauri::command]
+pub fn set_window_typography(
+    app: AppHandle,
+    window: String,
+    typography: Option<Typography>,
+) -> Result<(), AppError> {
+    if window.trim().is_empty() {
+        return Err(AppError::invalid_input("No window given"));
+    }
+    if let Some(typography) = &typography {
+        validate(typography).map_err(AppError::invalid_input)?;
+    }
+    let mut settings = all_settings(&app);
+    match typography.filter(|typography| *typography != Typography::default()) {
+        Some(typography) => settings.insert(window, typography),
+        None => settings.remove(&window),
+    };
+
+    let store = app
+        .store(SETTINGS_STORE)
+        .map_err(|e| format!("Failed to open settings store: {}", e))?;
+    let value = serde_json::to_value(&settings)
+        .map_err(|e| format!("Failed to serialize typography: {}", e))?;
+    store.set(TYPOGRAPHY_KEY, value);
+    atomic_store::save(&app, SETTINGS_STORE)
+        .map_err(|e| AppError::io(format!("Failed to save settings: {}", e)))?;
+
+    apply_to_open_windows(&app);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_style_sheet() {
+        let typography = Typography {
+            font_family: Some("Noto Sans JP, 'Inter', sans-serif".to_string()),
+            font_size: Some(15.0),
+            color_emoji: true,
+        };
+        assert!(validate(&typography).is_ok());
+        let css = style_sheet(&typography);
+        assert!(css.contains(
+            "--font-family-sans: \"Noto Sans JP\", \"Inter\", sans-serif, \"Segoe UI Emoji\""
+        ));
+        assert!(css.contains("--font-size-base: 15px"));
+        assert!(css.contains("--font-size-small: 13.9px"));
+
+        assert!(font_stack("Inter; } body { display: none").is_err());
+        let too_big = Typography {
+            font_size: Some(100.0),
+            ..Typography::default()
+        };
+        assert!(validate(&too_big).is_err());
+        assert_eq!(style_sheet(&Typography::default()), "");
+    }
+}
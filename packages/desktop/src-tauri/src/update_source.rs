@@ -0,0 +1,321 @@
+//! Where app updates come from.
+//!
+//! Updates are fetched from the endpoint bundled with the release and their
+//! signatures checked against the bundled public key. Organizations mirroring
+//! releases on an internal server can point the updater there, from the
+//! settings or the policy file; the policy wins. An endpoint set without a
+//! key keeps the bundled key, so a mirror still has to serve releases signed
+//! by us.
+//!
+//! The updater plugin's own `check` only knows the bundled configuration, so
+//! the frontend checks with `check_for_update`. The metadata it returns backs
+//! an `Update` from `@tauri-apps/plugin-updater`, which downloads and
+//! installs as usual.
+
+use base64::{Engine, engine::general_purpose::STANDARD};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use tauri::{AppHandle, Manager, ResourceId, Webview};
+use tauri_plugin_store::StoreExt;
+use tauri_plugin_updater::UpdaterExt;
+
+use crate::{SETTINGS_STORE, atomic_store, audit, error::AppError, policy};
+
+const UPDATE_SOURCE_KEY: &str = "updateSource";
+/// Decoded minisign public key: signature algorithm, key id, Ed25519 key
+const MINISIGN_KEY_LEN: usize = 42;
+
+/// Endpoint and key replacing the bundled ones
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default, rename_all = "camelCase")]
+pub struct CustomUpdateSource {
+    /// URL of the release manifest (`latest.json`)
+    pub endpoint: Option<String>,
+    /// Public key in the form of the `pubkey` updater setting
+    pub pubkey: Option<String>,
+}
+
+impl CustomUpdateSource {
+    pub fn validate(&self) -> Result<(), String> {
+        if let Some(endpoint) = &self.endpoint {
+            let url = reqwest::Url::parse(endpoint)
+                .map_err(|e| format!("Invalid update endpoint {}: {}", endpoint, e))?;
+            if url.scheme() != "https" {
+                return Err(format!("Update endpoint {} must use https", endpoint));
+            }
+        }
+        if let Some(pubkey) = &self.pubkey
+            && key_id(pubkey).is_none()
+        {
+            return Err("Update public key is not a minisign public key".to_string());
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub enum Origin {
+    Bundled,
+    Settings,
+    Policy,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct UpdateSource {
+    /// Whether this build can update itself at all
+    pub enabled: bool,
+    pub endpoints: Vec<String>,
+    pub endpoint_origin: Origin,
+    /// Id of the key update signatures are checked against
+    pub key_id: Option<String>,
+    pub key_origin: Origin,
+}
+
+/// Matches what the updater plugin's `check` returns, so the frontend can
+/// build an `Update` from it
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct UpdateMetadata {
+    rid: ResourceId,
+    current_version: String,
+    version: String,
+    date: Option<String>,
+    body: Option<String>,
+    raw_json: Value,
+}
+
+/// Release builds signed for the updater ship it
+pub fn updater_enabled() -> bool {
+    option_env!("TAURI_SIGNING_PRIVATE_KEY").is_some()
+}
+
+/// Id of a minisign public key, given as the base64 encoded key file the
+/// updater takes, or as the bare key line
+fn key_id(pubkey: &str) -> Option<String> {
+    let pubkey = pubkey.trim();
+    let file = STANDARD
+        .decode(pubkey)
+        .ok()
+        .and_then(|bytes| String::from_utf8(bytes).ok())
+        .filter(|text| text.starts_with("untrusted comment:"));
+    let line = match &file {
+        Some(text) => text.lines().rfind(|line| !line.trim().is_empty())?,
+        None => pubkey,
+    };
+    let key = STANDARD.decode(line.trim()).ok()?;
+    if key.len() != MINISIGN_KEY_LEN || &key[..2] != b"Ed" {
+        return None;
+    }
+    Some(
+        key[2..10]
+            .iter()
+            .rev()
+            .map(|b| format!("{:02X}", b))
+            .collect(),
+    )
+}
+
+/// The policy's value, else the settings', else none
+fn pick(policy: &Option<String>, settings: &Option<String>) -> Option<(String, Origin)> {
+    policy
+        .clone()
+        .map(|value| (value, Origin::Policy))
+        .or_else(|| settings.clone().map(|value| (value, Origin::Settings)))
+}
+
+fn saved_source(app: &AppHandle) -> CustomUpdateSource {
+    app.store(SETTINGS_STORE)
+        .ok()
+        .and_then(|store| store.get(UPDATE_SOURCE_KEY))
+        .and_then(|value| serde_json::from_value(value).ok())
+        .unwrap_or_default()
+}
+
+fn bundled_config(app: &AppHandle) -> (Vec<String>, Option<String>) {
+    let config = app.config().plugins.0.get("updater");
+    let endpoints = config
+        .and_then(|config| config.get("endpoints"))
+        .and_then(Value::as_array)
+        .map(|endpoints| {
+            endpoints
+                .iter()
+                .filter_map(|endpoint| endpoint.as_str().map(String::from))
+                .collect()
+        })
+        .unwrap_or_default();
+    let pubkey = config
+        .and_then(|config| config.get("pubkey"))
+        .and_then(Value::as_str)
+        .map(String::from);
+    (endpoints, pubkey)
+}
+
+struct Resolved {
+    endpoints: Vec<String>,
+    endpoint_origin: Origin,
+    pubkey: Option<String>,
+    key_origin: Origin,
+}
+
+fn resolve(app: &AppHandle) -> Resolved {
+    let managed = policy::current(app).update_source();
+    let saved = saved_source(app);
+    let (bundled_endpoints, bundled_pubkey) = bundled_config(app);
+
+    let (endpoints, endpoint_origin) = match pick(&managed.endpoint, &saved.endpoint) {
+        Some((endpoint, origin)) => (vec![endpoint], origin),
+        None => (bundled_endpoints, Origin::Bundled),
+    };
+    let (pubkey, key_origin) = match pick(&managed.pubkey, &saved.pubkey) {
+        Some((pubkey, origin)) => (Some(pubkey), origin),
+        None => (bundled_pubkey, Origin::Bundled),
+    };
+    Resolved {
+        endpoints,
+        endpoint_origin,
+        pubkey,
+        key_origin,
+    }
+}
+
+/// Where updates come from and which key they must be signed with
+#[tauri::command]
+pub fn get_update_source(app: AppHandle) -> UpdateSource {
+    let resolved = resolve(&app);
+    UpdateSource {
+        enabled: updater_enabled(),
+        endpoints: resolved.endpoints,
+        endpoint_origin: resolved.endpoint_origin,
+        key_id: resolved.pubkey.as_deref().and_then(key_id),
+        key_origin: resolved.key_origin,
+    }
+}
+
+/// Check the configured source for a newer release
+#[tauri::command]
+pub async fn check_for_update(
+    app: AppHandle,
+    webview: Webview,
+) -> Result<Option<UpdateMetadata>, AppError> {
+    if !updater_enabled() {
+        return Err(AppError::unavailable(
+            "Updates are not enabled in this build",
+        ));
+    }
+    let resolved = resolve(&app);
+
+    let mut builder = app.updater_builder();
+    if resolved.endpoint_origin != Origin::Bundled {
+        let endpoints = resolved
+            .endpoints
+            .iter()
+            .map(|endpoint| reqwest::Url::parse(endpoint))
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(|e| AppError::invalid_input(format!("Invalid update endpoint: {}", e)))?;
+        builder = builder
+            .endpoints(endpoints)
+            .map_err(|e| AppError::invalid_input(format!("Invalid update endpoint: {}", e)))?;
+    }
+    if resolved.key_origin != Origin::Bundled
+        && let Some(pubkey) = resolved.pubkey
+    {
+        builder = builder.pubkey(pubkey);
+    }
+
+    let update = builder
+        .build()
+        .map_err(|e| format!("Failed to create updater: {}", e))?
+        .check()
+        .await
+        .map_err(|e| AppError::network(format!("Failed to check for updates: {}", e)))?;
+    let Some(update) = update else {
+        return Ok(None);
+    };
+    Ok(Some(UpdateMetadata {
+        current_version: update.current_version.clone(),
+        version: update.version.clone(),
+        date: update
+            .raw_json
+            .get("pub_date")
+            .and_then(Value::as_str)
+            .map(String::from),
+        body: update.body.clone(),
+        raw_json: update.raw_json.clone(),
+        rid: webview.resources_table().add(update),
+    }))
+}
+
+#[tauri::command]
+pub fn set_update_source(
+    app: AppHandle,
+    webview: Webview,
+    source: CustomUpdateSource,
+) -> Result<(), AppError> {
+    let args = serde_json::json!({
+        "endpoint": source.endpoint,
+        "keyId": source.pubkey.as_deref().and_then(key_id),
+    });
+    let result = save_update_source(&app, source);
+    audit::record(&webview, "set_update_source", args, &result);
+    result
+}
+
+fn save_update_source(app: &AppHandle, source: CustomUpdateSource) -> Result<(), AppError> {
+    if policy::current(app).update_source() != CustomUpdateSource::default() {
+        return Err(policy::locked_error(app));
+    }
+    source.validate().map_err(AppError::invalid_input)?;
+
+    let store = app
+        .store(SETTINGS_STORE)
+        .map_err(|e| format!("Failed to open settings store: {}", e))?;
+    if source == CustomUpdateSource::default() {
+        store.delete(UPDATE_SOURCE_KEY);
+    } else {
+        let value = serde_json::to_value(&source)
+            .map_err(|e| format!("Failed to serialize update source: {}", e))?;
+        store.set(UPDATE_SOURCE_KEY, value);
+    }
+    atomic_store::save(app, SETTINGS_STORE)
+        .map_err(|e| AppError::io(format!("Failed to save settings: {}", e)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PUBKEY: &str = "dW50cnVzdGVkIGNvbW1lbnQ6IG1pbmlzaWduIHB1YmxpYyBrZXk6IDcxNTQwMkJBMjIxM0VBMDEKUldRQjZoTWl1Z0pVY1g4T3FGdkVmNzJIQ013Y20rZ1pENmx1THBJako2b2lmS3pmTWFQdFk0VUgK";
+
+    #[test]
+    fn test_key_id_of_minisign_keys() {
+        assert_eq!(key_id(PUBKEY).as_deref(), Some("715402BA2213EA01"));
+        assert_eq!(
+            key_id("RWQB6hMiugJUcX8OqFvEf72HCMwcm+gZD6luLpIjJ6oifKzfMaPtY4UH").as_deref(),
+            Some("715402BA2213EA01")
+        );
+        assert_eq!(key_id("not a key"), None);
+    }
+
+    #[test]
+    fn test_validate_and_pick() {
+        let source = CustomUpdateSource {
+            endpoint: Some("http://updates.example.com/latest.json".to_string()),
+            pubkey: None,
+        };
+        assert!(source.validate().is_err());
+
+        let managed = Some("https://a.example.com".to_string());
+        let saved = Some("https://b.example.com".to_string());
+        assert_eq!(
+            pick(&managed, &saved),
+            Some(("https://a.example.com".to_string(), Origin::Policy))
+        );
+        assert_eq!(
+            pick(&None, &saved).map(|(_, origin)| origin),
+            Some(Origin::Settings)
+        );
+        assert_eq!(pick(&None, &None), None);
+    }
+}
@@ -0,0 +1,236 @@
+//! Keeping users of one machine apart.
+//!
+//! Two users signed in at once, as with fast user switching, each run their
+//! own app and local server, and neither may reach the other's. What lives
+//! outside their own profile is namespaced by user and, where the app is at
+//! hand, by channel (the app identifier, so a dev build and a release don't
+//! meet either):
+//!
+//! - The local server's port: each user and channel prefers a port of its
+//!   own, and only a server on a port set with `OPENCODE_PORT` is attached
+//!   to without being started, since one that answers elsewhere may be
+//!   another user's.
+//! - The single-instance lock: a session mutex on Windows and the session
+//!   bus on Linux are each user's own already, but the single-instance
+//!   plugin puts its socket in `/tmp` on macOS, so there it's kept in the
+//!   user's `$TMPDIR` instead.
+//! - Temp files: `/tmp` is shared on Linux, so they go in a directory of the
+//!   user's own, and of the install's, since they're written before the
+//!   channel is known. It's in `$XDG_RUNTIME_DIR` where there is one, and
+//!   anywhere else is only used if the user made it and alone can open it,
+//!   so another user can't make it first to read or plant what goes in.
+
+use std::{
+    net::TcpListener,
+    path::{Path, PathBuf},
+};
+use tauri::{AppHandle, Wry, plugin::TauriPlugin};
+
+use crate::hash::{HashAlgorithm, hash_bytes};
+
+/// Ports the local server prefers one of
+const PORT_BASE: u16 = 20000;
+const PORT_SPAN: u16 = 10000;
+
+/// Who the app runs as
+fn os_user() -> String {
+    ["USERDOMAIN", "USERNAME", "USER", "LOGNAME"]
+        .iter()
+        .filter_map(|var| std::env::var(var).ok())
+        .collect::<Vec<_>>()
+        .join("\\")
+}
+
+/// A short key for `parts`, to put in names
+fn key(parts: &[&str]) -> String {
+    hash_bytes(parts.join("\0").as_bytes(), HashAlgorithm::Blake3)[..16].to_string()
+}
+
+/// Where the temp directory goes: the user's runtime directory on Linux,
+/// where there is one. `$TMPDIR` on macOS and the temp dir on Windows are
+/// the user's own already.
+fn temp_base() -> PathBuf {
+    #[cfg(target_os = "linux")]
+    if let Some(dir) = std::env::var_os("XDG_RUNTIME_DIR")
+        .map(PathBuf::from)
+        .filter(|dir| dir.is_absolute())
+    {
+        return dir;
+    }
+    std::env::temp_dir()
+}
+
+/// Create `dir` for the user alone, or check that the one there is theirs
+#[cfg(unix)]
+fn create_private_dir(dir: &Path) -> Result<(), String> {
+    use std::os::unix::fs::{DirBuilderExt, MetadataExt, PermissionsExt};
+
+    match std::fs::DirBuilder::new().mode(0o700).create(dir) {
+        // The umask may have taken more than asked
+        Ok(()) => {
+            return std::fs::set_permissions(dir, std::fs::Permissions::from_mode(0o700))
+                .map_err(|e| format!("Failed to restrict {}: {}", dir.display(), e));
+        }
+        Err(e) if e.kind() == std::io::ErrorKind::AlreadyExists => {}
+        Err(e) => return Err(format!("Failed to create {}: {}", dir.display(), e)),
+    }
+    let metadata = std::fs::symlink_metadata(dir)
+        .map_err(|e| format!("Failed to inspect {}: {}", dir.display(), e))?;
+    let uid = unsafe { libc::geteuid() };
+    if !metadata.file_type().is_dir() || metadata.uid() != uid || metadata.mode() & 0o777 != 0o700 {
+        return Err(format!(
+            "Refusing to use {}, which another user can reach",
+            dir.display()
+        ));
+    }
+    Ok(())
+}
+
+#[cfg(not(unix))]
+fn create_private_dir(dir: &Path) -> Result<(), String> {
+    std::fs::create_dir_all(dir).map_err(|e| format!("Failed to create {}: {}", dir.display(), e))
+}
+
+/// The user's own directory in the temp dir for this install, readable by
+/// them alone
+pub fn temp_dir() -> Result<PathBuf, String> {
+    let install = std::env::current_exe().unwrap_or_default();
+    let dir = temp_base().join(format!(
+        "aura-{}",
+        key(&[&os_user(), &install.to_string_lossy()])
+    ));
+    create_private_dir(&dir)?;
+    Ok(dir)
+}
+
+fn port_for(key: &str) -> u16 {
+    let value = u64::from_str_radix(&key[..8], 16).unwrap_or(0);
+    PORT_BASE + (value % PORT_SPAN as u64) as u16
+}
+
+/// A free port for the local server: the user's own for this channel when
+/// it's free, else any
+pub fn local_server_port(app: &AppHandle) -> u16 {
+    let preferred = port_for(&key(&[&app.config().identifier, &os_user()]));
+    TcpListener::bind(("127.0.0.1", preferred))
+        .or_else(|_| TcpListener::bind("127.0.0.1:0"))
+        .expect("Failed to bind to find free port")
+        .local_addr()
+        .expect("Failed to get local address")
+        .port()
+}
+
+/// The single-instance plugin, with a lock of the user's own
+pub fn single_instance<F>(callback: F) -> TauriPlugin<Wry>
+where
+    F: Fn(&AppHandle, Vec<String>, String) + Send + Sync + 'static,
+{
+    #[cfg(target_os = "macos")]
+    return macos::single_instance(callback);
+
+    #[cfg(not(target_os = "macos"))]
+    return tauri_plugin_single_instance::init(callback);
+}
+
+#[cfg(target_os = "macos")]
+mod macos {
+    use std::{
+        io::{ErrorKind, Read, Write},
+        os::unix::net::{UnixListener, UnixStream},
+        path::{Path, PathBuf},
+    };
+    use tauri::{
+        AppHandle, RunEvent, Wry,
+        plugin::{Builder, TauriPlugin},
+    };
+
+    fn socket_path(app: &AppHandle) -> PathBuf {
+        // $TMPDIR is the user's own on macOS
+        std::env::temp_dir().join(format!(
+            "{}_si.sock",
+            app.config().identifier.replace(['.', '-'], "_")
+        ))
+    }
+
+    /// Hand this launch's working directory and arguments to the running
+    /// instance, in the plugin's format
+    fn notify(socket: &Path) -> std::io::Result<()> {
+        let mut stream = UnixStream::connect(socket)?;
+        let cwd = std::env::current_dir().unwrap_or_default();
+        let args: Vec<String> = std::env::args().collect();
+        let message = format!("{}\0\0{}", cwd.to_string_lossy(), args.join("\0"));
+        stream.write_all(message.as_bytes())
+    }
+
+    fn listen<F>(socket: &Path, app: AppHandle, callback: F)
+    where
+        F: Fn(&AppHandle, Vec<String>, String) + Send + Sync + 'static,
+    {
+        let listener = match UnixListener::bind(socket) {
+            Ok(listener) => listener,
+            Err(e) => {
+                eprintln!("Failed to listen for other instances: {}", e);
+                return;
+            }
+        };
+        std::thread::spawn(move || {
+            for mut stream in listener.incoming().filter_map(Result::ok) {
+                let mut message = String::new();
+                if stream.read_to_string(&mut message).is_err() {
+                    continue;
+                }
+                let (cwd, args) = message.split_once("\0\0").unwrap_or_default();
+                callback(
+                    &app,
+                    args.split('\0').map(String::from).collect(),
+                    cwd.to_string(),
+                );
+            }
+        });
+    }
+
+    pub fn single_instance<F>(callback: F) -> TauriPlugin<Wry>
+    where
+        F: Fn(&AppHandle, Vec<String>, String) + Send + Sync + 'static,
+    {
+        Builder::new("user-single-instance")
+            .setup(move |app, _api| {
+                let socket = socket_path(app);
+                match notify(&socket) {
+                    Ok(()) => std::process::exit(0),
+                    Err(e)
+                        if matches!(
+                            e.kind(),
+                            ErrorKind::NotFound | ErrorKind::ConnectionRefused
+                        ) =>
+                    {
+                        // No instance, or the socket of one that didn't exit cleanly
+                        let _ = std::fs::remove_file(&socket);
+                        listen(&socket, app.clone(), callback);
+                    }
+                    Err(e) => eprintln!("Failed to reach the running instance: {}", e),
+                }
+                Ok(())
+            })
+            .on_event(|app, event| {
+                if let RunEvent::Exit = event {
+                    let _ = std::fs::remove_file(socket_path(app));
+                }
+            })
+            .build()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_port_for_stays_in_range() {
+        let ours = port_for(&key(&["ai.Aura.desktop", "alice"]));
+        assert_eq!(ours, port_for(&key(&["ai.Aura.desktop", "alice"])));
+        assert!((PORT_BASE..PORT_BASE + PORT_SPAN).contains(&ours));
+        assert_ne!(ours, port_for(&key(&["ai.Aura.desktop", "bob"])));
+        assert_ne!(ours, port_for(&key(&["ai.Aura.desktop.dev", "alice"])));
+    }
+}
@@ -0,0 +1,366 @@
+//! Export of session transcripts to a folder of markdown notes, such as an
+//! Obsidian vault.
+//!
+//! `export_session_to_vault` writes a session of the connected server to
+//! `<vault>/<title>.md`, with YAML frontmatter carrying the session's id and
+//! dates. The note body comes from a template where `{{title}}`,
+//! `{{session}}`, `{{created}}`, `{{updated}}`, `{{directory}}` and
+//! `{{transcript}}` are replaced. Exporting a session again overwrites its
+//! note, recognized by the `session` key of the frontmatter; a note of the
+//! same title from elsewhere is left alone and a number added to the name.
+
+use serde_json::{Value, json};
+use std::{
+    path::{Path, PathBuf},
+    time::Duration,
+};
+use tauri::{AppHandle, Webview};
+
+use crate::{
+    ServerReadyData, audit,
+    error::AppError,
+    i18n, markdown,
+    tasks::{self, TaskHandle, TaskKind},
+};
+
+const DEFAULT_TEMPLATE: &str = "# {{title}}\n\n{{transcript}}";
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(30);
+/// Characters not allowed in file names on some platform, or that Obsidian
+/// treats as link syntax
+const RESERVED: &[char] = &[
+    '/', '\\', ':', '*', '?', '"', '<', '>', '|', '#', '^', '[', ']',
+];
+const MAX_NAME_CHARS: usize = 100;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct SessionNote {
+    id: String,
+    title: String,
+    /// `YYYY-MM-DDTHH:MM:SSZ`
+    created: Option<String>,
+    updated: Option<String>,
+    directory: Option<String>,
+    /// The messages as markdown, a heading per message
+    transcript: String,
+}
+
+/// `ms` since the Unix epoch as an ISO 8601 UTC timestamp
+fn iso_timestamp(ms: u64) -> String {
+    let secs = ms / 1000;
+    let (days, rem) = (secs / 86_400, secs % 86_400);
+    // Days to a civil date, from Howard Hinnant's `civil_from_days`
+    let z = days as i64 + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z.rem_euclid(146_097);
+    let yoe = (doe - doe / 1460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + i64::from(month <= 2);
+    format!(
+        "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}Z",
+        year,
+        month,
+        day,
+        rem / 3600,
+        rem % 3600 / 60,
+        rem % 60
+    )
+}
+
+/// Text of a message's parts, leaving out tool calls and what the app added
+pub fn message_text(message: &Value) -> String {
+    message
+        .get("parts")
+        .and_then(Value::as_array)
+        .map(|parts| {
+            parts
+                .iter()
+                .filter(|part| part.get("type").and_then(Value::as_str) == Some("text"))
+                .filter(|part| part.get("synthetic").and_then(Value::as_bool) != Some(true))
+                .filter_map(|part| part.get("text").and_then(Value::as_str))
+                .filter(|text| !text.trim().is_empty())
+                .collect::<Vec<_>>()
+                .join("\n\n")
+        })
+        .unwrap_or_default()
+}
+
+fn transcript(messages: &[Value]) -> String {
+    messages
+        .iter()
+        .filter_map(|message| {
+            let heading = match message.pointer("/info/role").and_then(Value::as_str)? {
+                "user" => "User",
+                "assistant" => "Assistant",
+                _ => return None,
+            };
+            let text = message_text(message);
+            if text.is_empty() {
+                return None;
+            }
+            Some(format!(
+                "## {}\n\n{}",
+                heading,
+                markdown::normalize_markdown(&text).trim_end()
+            ))
+        })
+        .collect::<Vec<_>>()
+        .join("\n\n")
+}
+
+fn session_note(session: &Value, messages: &[Value]) -> Result<SessionNote, String> {
+    let id = session
+        .get("id")
+        .and_then(Value::as_str)
+        .ok_or("The server returned a session without an id")?;
+    let time = |key: &str| {
+        session
+            .pointer(&format!("/time/{}", key))
+            .and_then(Value::as_u64)
+            .map(iso_timestamp)
+    };
+    Ok(SessionNote {
+        id: id.to_string(),
+        title: session
+            .get("title")
+            .and_then(Value::as_str)
+            .filter(|title| !title.trim().is_empty())
+            .unwrap_or(id)
+            .to_string(),
+        created: time("created"),
+        updated: time("updated"),
+        directory: session
+            .get("directory")
+            .and_then(Value::as_str)
+            .map(String::from),
+        transcript: transcript(messages),
+    })
+}
+
+/// JSON strings are valid YAML scalars, quoted and escaped
+fn yaml_string(value: &str) -> String {
+    Value::String(value.to_string()).to_string()
+}
+
+fn frontmatter(note: &SessionNote) -> String {
+    let mut lines = vec![
+        "---".to_string(),
+        format!("title: {}", yaml_string(&note.title)),
+        format!("session: {}", yaml_string(&note.id)),
+    ];
+    for (key, value) in [
+        ("created", &note.created),
+        ("updated", &note.updated),
+        ("directory", &note.directory),
+    ] {
+        if let Some(value) = value {
+            lines.push(format!("{}: {}", key, yaml_string(value)));
+        }
+    }
+    lines.push("source: aura".to_string());
+    lines.push("---".to_string());
+    lines.join("\n")
+}
+
+fn render(note: &SessionNote, template: &str) -> String {
+    let body = template
+        .replace("{{title}}", &note.title)
+        .replace("{{session}}", &note.id)
+        .replace("{{created}}", note.created.as_deref().unwrap_or(""))
+        .replace("{{updated}}", note.updated.as_deref().unwrap_or(""))
+        .replace("{{directory}}", note.directory.as_deref().unwrap_or(""))
+        // Last, so text in the transcript that looks like a placeholder stays
+        .replace("{{transcript}}", &note.transcript);
+    format!("{}\n\n{}\n", frontmatter(note), body.trim_end())
+}
+
+/// `title` as a file name without the extension
+fn file_stem(title: &str) -> String {
+    let stem: String = title
+        .chars()
+        .map(|c| {
+            if RESERVED.contains(&c) || c.is_control() {
+                ' '
+            } else {
+                c
+            }
+        })
+        .collect::<String>()
+        .split_whitespace()
+        .collect::<Vec<_>>()
+        .join(" ")
+        .chars()
+        .take(MAX_NAME_CHARS)
+        .collect();
+    // Dot files are hidden, and Windows drops trailing dots
+    let stem = stem.trim_matches('.').trim();
+    if stem.is_empty() {
+        "Untitled session".to_string()
+    } else {
+        stem.to_string()
+    }
+}
+
+/// Whether the note at `path` was exported from session `id`
+fn is_note_of(path: &Path, id: &str) -> bool {
+    let Ok(contents) = std::fs::read_to_string(path) else {
+        return false;
+    };
+    let expected = format!("session: {}", yaml_string(id));
+    contents
+        .lines()
+        .skip(1)
+        .take_while(|line| *line != "---")
+        .any(|line| line == expected)
+}
+
+/// Where to write the note of session `id`: its earlier export if there is
+/// one, else the first free name
+fn note_path(vault: &Path, stem: &str, id: &str) -> PathBuf {
+    let mut path = vault.join(format!("{}.md", stem));
+    let mut number = 2;
+    while path.exists() && !is_note_of(&path, id) {
+        path = vault.join(format!("{} {}.md", stem, number));
+        number += 1;
+    }
+    path
+}
+
+/// GET `path` of the server as JSON
+pub async fn get_json(
+    client: &reqwest::Client,
+    server: &ServerReadyData,
+    url: &reqwest::Url,
+    segments: &[&str],
+) -> Result<Value, AppError> {
+    let path = format!("/{}", segments.join("/"));
+    let endpoint = crate::server_endpoint(url, segments)
+        .map_err(|e| AppError::invalid_input(format!("Invalid server path {}: {}", path, e)))?;
+    let mut request = client.get(endpoint);
+    if let Some(password) = &server.password {
+        request = request.basic_auth("opencode", Some(password));
+    }
+    let response = request
+        .send()
+        .await
+        .map_err(|e| AppError::network(format!("Request to the server failed: {}", e)))?;
+    if response.status() == reqwest::StatusCode::NOT_FOUND {
+        return Err(AppError::not_found(format!(
+            "Not found on the server: {}",
+            path
+        )));
+    }
+    if !response.status().is_success() {
+        return Err(AppError::network(format!(
+            "The server refused: HTTP {}",
+            response.status()
+        )));
+    }
+    response
+        .json()
+        .await
+        .map_err(|e| AppError::network(format!("Invalid response from the server: {}", e)))
+}
+
+async fn export(
+    app: &AppHandle,
+    session_id: &str,
+    vault_path: &str,
+    template: Option<&str>,
+    task: &TaskHandle,
+) -> Result<String, AppError> {
+    let vault = Path::new(vault_path);
+    if !vault.is_dir() {
+        return Err(AppError::invalid_input(format!(
+            "{} is not a folder",
+            vault_path
+        )));
+    }
+
+    let server = crate::connected_server(app).await?;
+    let url = reqwest::Url::parse(&server.url)
+        .map_err(|e| format!("Invalid server URL {}: {}", server.url, e))?;
+    let client = crate::server_client(app, &url, REQUEST_TIMEOUT)?;
+    let session = get_json(&client, &server, &url, &["session", session_id]).await?;
+    task.check()?;
+    task.set_progress(0.5);
+    let messages = get_json(&client, &server, &url, &["session", session_id, "message"]).await?;
+    let messages = messages.as_array().map(Vec::as_slice).unwrap_or_default();
+    task.check()?;
+
+    let note = session_note(&session, messages)?;
+    let contents = render(&note, template.unwrap_or(DEFAULT_TEMPLATE));
+    let path = note_path(vault, &file_stem(&note.title), &note.id);
+
+    // Written aside and moved in place, so a vault syncing the folder never
+    // picks up half a note
+    let partial = path.with_extension("md.partial");
+    std::fs::write(&partial, contents)
+        .and_then(|_| std::fs::rename(&partial, &path))
+        .map_err(|e| {
+            let _ = std::fs::remove_file(&partial);
+            AppError::io(format!("Failed to write {}: {}", path.display(), e))
+        })?;
+    Ok(path.display().to_string())
+}
+
+/// Write a session as a markdown note into `vault_path`, returning the path
+/// of the note
+#[tauri::command]
+pub async fn export_session_to_vault(
+    app: AppHandle,
+    webview: Webview,
+    session_id: String,
+    vault_path: String,
+    template: Option<String>,
+) -> Result<String, AppError> {
+    let task = tasks::start(&app, TaskKind::Export, i18n::t(&app, "task.export"));
+    let result = export(&app, &session_id, &vault_path, template.as_deref(), &task).await;
+    task.finish(&result);
+    audit::record(
+        &webview,
+        "export_session_to_vault",
+        json!({ "sessionId": session_id, "vaultPath": vault_path }),
+        &result,
+    );
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_iso_timestamp() {
+        assert_eq!(iso_timestamp(0), "1970-01-01T00:00:00Z");
+        assert_eq!(iso_timestamp(951_782_400_000), "2000-02-29T00:00:00Z");
+        assert_eq!(iso_timestamp(1_760_610_645_123), "2025-10-16T10:30:45Z");
+    }
+
+    #[test]
+    fn test_render_note() {
+        let session = json!({
+            "id": "ses_1",
+            "title": "Fix: the \"parser\"",
+            "time": { "created": 0 },
+        });
+        let messages = vec![
+            json!({ "info": { "role": "user" }, "parts": [{ "type": "text", "text": "Why?" }] }),
+            json!({ "info": { "role": "assistant" }, "parts": [
+                { "type": "tool", "tool": "read" },
+                { "type": "text", "text": "Because {{title}}" },
+            ] }),
+        ];
+        let note = session_note(&session, &messages).unwrap();
+        assert_eq!(
+            render(&note, DEFAULT_TEMPLATE),
+            "---\ntitle: \"Fix: the \\\"parser\\\"\"\nsession: \"ses_1\"\n\
+             created: \"1970-01-01T00:00:00Z\"\nsource: aura\n---\n\n\
+             # Fix: the \"parser\"\n\n## User\n\nWhy?\n\n## Assistant\n\nBecause {{title}}\n"
+        );
+        assert_eq!(file_stem(&note.title), "Fix the parser");
+        assert_eq!(file_stem("../.."), "Untitled session");
+    }
+}
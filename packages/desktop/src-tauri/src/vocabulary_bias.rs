@@ -0,0 +1,160 @@
+//! Biasing transcription towards the user's own terms.
+//!
+//! Project names, APIs and jargon are rare in what the model learned from,
+//! so it tends to hear them as more common words. The terms of the
+//! `sttVocabulary` setting are split into the model's tokens and kept in a
+//! trie. While decoding, tokens that start a term, or continue one the last
+//! emitted tokens began, get their logits boosted. Only the choice between
+//! tokens is biased, not whether one is emitted, so silence doesn't turn
+//! into terms.
+
+use std::collections::HashMap;
+
+/// Boost for a token that starts a term
+const START_BOOST: f32 = 1.5;
+/// Boost for a token that continues a term, larger as a partial match is
+/// good evidence for the rest
+const CONTINUE_BOOST: f32 = 3.0;
+
+#[derive(Default)]
+struct Node {
+    children: HashMap<usize, usize>,
+}
+
+/// The user's terms, as token sequences
+pub struct VocabularyBias {
+    /// The root first
+    nodes: Vec<Node>,
+}
+
+/// `text` as the model's tokens, longest first; `None` when part of it has
+/// no token
+fn tokenize(text: &str, pieces: &HashMap<&str, usize>, longest: usize) -> Option<Vec<usize>> {
+    let chars: Vec<(usize, char)> = text.char_indices().collect();
+    let mut tokens = Vec::new();
+    let mut i = 0;
+    while i < chars.len() {
+        let (len, token) = (1..=longest.min(chars.len() - i)).rev().find_map(|len| {
+            let start = chars[i].0;
+            let end = chars.get(i + len).map_or(text.len(), |(end, _)| *end);
+            pieces.get(&text[start..end]).map(|token| (len, *token))
+        })?;
+        tokens.push(token);
+        i += len;
+    }
+    Some(tokens)
+}
+
+impl VocabularyBias {
+    /// The trie of `terms` in the tokens of `vocab`. Terms its tokens can't
+    /// spell are left out.
+    pub fn new(terms: &[String], vocab: &HashMap<i64, String>) -> Self {
+        let pieces: HashMap<&str, usize> = vocab
+            .iter()
+            .filter(|(_, piece)| !piece.is_empty())
+            .map(|(id, piece)| (piece.as_str(), *id as usize))
+            .collect();
+        let longest = pieces.keys().map(|piece| piece.chars().count()).max();
+
+        let mut bias = Self {
+            nodes: vec![Node::default()],
+        };
+        let Some(longest) = longest else {
+            return bias;
+        };
+        for term in terms.iter().map(|term| term.trim()).filter(|term| !term.is_empty()) {
+            // Terms start a word, and the model's word pieces with a space
+            let Some(tokens) = tokenize(&format!(" {}", term), &pieces, longest) else {
+                continue;
+            };
+            let mut node = 0;
+            for token in tokens {
+                node = match bias.nodes[node].children.get(&token) {
+                    Some(&child) => child,
+                    None => {
+                        bias.nodes.push(Node::default());
+                        let child = bias.nodes.len() - 1;
+                        bias.nodes[node].children.insert(token, child);
+                        child
+                    }
+                };
+            }
+        }
+        bias
+    }
+
+    /// The token to emit given `logits`, or blank as the model has it.
+    /// `active` holds the nodes of the terms the last tokens partly match.
+    pub fn pick(&self, logits: &[f32], blank: usize, active: &[usize]) -> Option<usize> {
+        let best = logits
+            .iter()
+            .enumerate()
+            .max_by(|(_, a), (_, b)| a.total_cmp(b))
+            .map(|(i, _)| i)?;
+        if best == blank || self.nodes[0].children.is_empty() {
+            return Some(best);
+        }
+        let boosted = self.nodes[0]
+            .children
+            .keys()
+            .map(|&token| (token, START_BOOST))
+            .chain(active.iter().flat_map(|&node| {
+                self.nodes[node]
+                    .children
+                    .keys()
+                    .map(|&token| (token, CONTINUE_BOOST))
+            }));
+        let mut pick = (best, logits[best]);
+        for (token, boost) in boosted {
+            if let Some(logit) = logits.get(token)
+                && token != blank
+                && logit + boost > pick.1
+            {
+                pick = (token, logit + boost);
+            }
+        }
+        Some(pick.0)
+    }
+
+    /// Follow emitted `token` from the partial matches in `active`
+    pub fn advance(&self, active: &mut Vec<usize>, token: usize) {
+        let mut next: Vec<usize> = std::iter::once(0)
+            .chain(active.iter().copied())
+            .filter_map(|node| self.nodes[node].children.get(&token).copied())
+            .collect();
+        next.dedup();
+        *active = next;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_bias_follows_terms() {
+        let vocab: HashMap<i64, String> = [" k", "ube", "rnetes", " cube", "s", "<blk>"]
+            .iter()
+            .enumerate()
+            .map(|(id, piece)| (id as i64, piece.to_string()))
+            .collect();
+        let bias = VocabularyBias::new(&["kubernetes".to_string()], &vocab);
+        let blank = 5;
+
+        // " cube" narrowly beats the start of the term, which the boost turns
+        let mut active = Vec::new();
+        let logits = [4.0, 0.0, 0.0, 5.0, 0.0, 1.0];
+        assert_eq!(bias.pick(&logits, blank, &active), Some(0));
+        bias.advance(&mut active, 0);
+        let logits = [0.0, 3.0, 0.0, 0.0, 5.0, 1.0];
+        assert_eq!(bias.pick(&logits, blank, &active), Some(1));
+        bias.advance(&mut active, 1);
+        assert_eq!(active.len(), 1);
+
+        // Blank and clear winners are left alone
+        let logits = [4.0, 0.0, 0.0, 0.0, 0.0, 5.0];
+        assert_eq!(bias.pick(&logits, blank, &[]), Some(blank));
+        let logits = [0.0, 0.0, 0.0, 9.0, 0.0, 1.0];
+        assert_eq!(bias.pick(&logits, blank, &[]), Some(3));
+    }
+}
@@ -0,0 +1,446 @@
+//! Camera, microphone and location access of web content.
+//!
+//! Permission requests of the webview are answered here instead of by the
+//! platform default, which grants on some systems and silently denies on
+//! others. The first request of an origin shows a native prompt and the
+//! answer is remembered per origin and kind; `list_webview_permissions` and
+//! `revoke_webview_permission` let users review and reset them.
+//!
+//! Requests are intercepted on Linux (WebKitGTK) and Windows (WebView2). On
+//! macOS WKWebView grants capture itself, behind the system's per-app camera
+//! and microphone consent.
+
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use tauri::{AppHandle, Webview, Wry, plugin::Plugin};
+use tauri_plugin_dialog::{DialogExt, MessageDialogButtons, MessageDialogResult};
+use tauri_plugin_store::StoreExt;
+
+use crate::{SETTINGS_STORE, atomic_store, audit, error::AppError, i18n, test_mode};
+
+const PERMISSIONS_KEY: &str = "webviewPermissions";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum PermissionKind {
+    Camera,
+    Microphone,
+    Geolocation,
+}
+
+impl PermissionKind {
+    fn message_key(self) -> &'static str {
+        match self {
+            PermissionKind::Camera => "permission.camera",
+            PermissionKind::Microphone => "permission.microphone",
+            PermissionKind::Geolocation => "permission.geolocation",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum Decision {
+    Allow,
+    Deny,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PermissionEntry {
+    pub origin: String,
+    pub kind: PermissionKind,
+    pub decision: Decision,
+}
+
+type Decisions = BTreeMap<String, BTreeMap<PermissionKind, Decision>>;
+
+fn read_decisions(app: &AppHandle) -> Decisions {
+    app.store(SETTINGS_STORE)
+        .ok()
+        .and_then(|store| store.get(PERMISSIONS_KEY))
+        .and_then(|value| serde_json::from_value(value).ok())
+        .unwrap_or_default()
+}
+
+fn write_decisions(app: &AppHandle, decisions: &Decisions) -> Result<(), String> {
+    let store = app
+        .store(SETTINGS_STORE)
+        .map_err(|e| format!("Failed to open settings store: {}", e))?;
+    store.set(PERMISSIONS_KEY, serde_json::json!(decisions));
+    atomic_store::save(app, SETTINGS_STORE).map_err(|e| format!("Failed to save settings: {}", e))
+}
+
+/// Scheme, host and port of a page URL. App pages (`tauri://localhost`) have
+/// an opaque origin in the URL spec, so this doesn't use `Url::origin`.
+fn origin_of(url: &str) -> String {
+    let Ok(url) = tauri::Url::parse(url) else {
+        return url.to_string();
+    };
+    match (url.host_str(), url.port()) {
+        (Some(host), Some(port)) => format!("{}://{}:{}", url.scheme(), host, port),
+        (Some(host), None) => format!("{}://{}", url.scheme(), host),
+        _ => url.scheme().to_string(),
+    }
+}
+
+/// The remembered answer for a request, if every kind in it has one. A
+/// denied kind denies the whole request.
+fn stored_decision(
+    decisions: &Decisions,
+    origin: &str,
+    kinds: &[PermissionKind],
+) -> Option<Decision> {
+    let stored = decisions.get(origin)?;
+    let answers: Vec<Option<&Decision>> = kinds.iter().map(|kind| stored.get(kind)).collect();
+    if answers.contains(&Some(&Decision::Deny)) {
+        Some(Decision::Deny)
+    } else if answers.iter().all(Option::is_some) {
+        Some(Decision::Allow)
+    } else {
+        None
+    }
+}
+
+fn describe(app: &AppHandle, kinds: &[PermissionKind]) -> String {
+    let names: Vec<String> = kinds
+        .iter()
+        .map(|kind| i18n::t(app, kind.message_key()))
+        .collect();
+    match names.split_last() {
+        Some((last, [])) => last.clone(),
+        Some((last, rest)) => i18n::t_with(
+            app,
+            "permission.list",
+            &[("first", &rest.join(", ")), ("last", last)],
+        ),
+        None => String::new(),
+    }
+}
+
+/// Answer a request of `origin` for `kinds`, prompting when there is no
+/// remembered answer. `respond` may run on any thread.
+fn decide(
+    app: &AppHandle,
+    origin: String,
+    kinds: Vec<PermissionKind>,
+    respond: impl FnOnce(bool) + Send + 'static,
+) {
+    if let Some(decision) = stored_decision(&read_decisions(app), &origin, &kinds) {
+        respond(decision == Decision::Allow);
+        return;
+    }
+
+    let allow = i18n::t(app, "dialog.permission.allow");
+    let dialog = app
+        .dialog()
+        .message(i18n::t_with(
+            app,
+            "dialog.permission.message",
+            &[("origin", &origin), ("devices", &describe(app, &kinds))],
+        ))
+        .title(i18n::t(app, "dialog.permission.title"))
+        .buttons(MessageDialogButtons::OkCancelCustom(
+            allow.clone(),
+            i18n::t(app, "dialog.permission.deny"),
+        ));
+    let app_for_answer = app.clone();
+    let answer = move |allowed| {
+        let decision = if allowed {
+            Decision::Allow
+        } else {
+            Decision::Deny
+        };
+        let mut decisions = read_decisions(&app_for_answer);
+        let stored = decisions.entry(origin).or_default();
+        for kind in kinds {
+            stored.insert(kind, decision);
+        }
+        if let Err(e) = write_decisions(&app_for_answer, &decisions) {
+            eprintln!("Failed to remember permission: {}", e);
+        }
+        respond(allowed);
+    };
+    match test_mode::dialog_answer(app, "permission") {
+        Some(result) => answer(match result {
+            MessageDialogResult::Ok | MessageDialogResult::Yes => true,
+            MessageDialogResult::Custom(choice) => choice == allow,
+            _ => false,
+        }),
+        None => dialog.show(answer),
+    }
+}
+
+/// Hooks permission requests of every webview as it is created
+pub struct WebviewPermissionsPlugin;
+
+impl Plugin<Wry> for WebviewPermissionsPlugin {
+    fn name(&self) -> &'static str {
+        "webview-permissions"
+    }
+
+    fn webview_created(&mut self, webview: Webview<Wry>) {
+        platform::install(&webview);
+    }
+}
+
+#[cfg(target_os = "linux")]
+mod platform {
+    use gtk::glib::{self, prelude::Cast};
+    use tauri::{Manager, Webview, Wry};
+    use webkit2gtk::{
+        GeolocationPermissionRequest, PermissionRequestExt, UserMediaPermissionRequest,
+        UserMediaPermissionRequestExt, WebViewExt,
+    };
+
+    use super::{PermissionKind, decide, origin_of};
+
+    pub fn install(webview: &Webview<Wry>) {
+        let app = webview.app_handle().clone();
+        let _ = webview.with_webview(move |platform| {
+            platform
+                .inner()
+                .connect_permission_request(move |view, request| {
+                    let kinds =
+                        if let Some(media) = request.downcast_ref::<UserMediaPermissionRequest>() {
+                            let mut kinds = Vec::new();
+                            if media.is_for_video_device() {
+                                kinds.push(PermissionKind::Camera);
+                            }
+                            if media.is_for_audio_device() {
+                                kinds.push(PermissionKind::Microphone);
+                            }
+                            kinds
+                        } else if request
+                            .downcast_ref::<GeolocationPermissionRequest>()
+                            .is_some()
+                        {
+                            vec![PermissionKind::Geolocation]
+                        } else {
+                            Vec::new()
+                        };
+                    // Leave other requests to the default handler
+                    if kinds.is_empty() {
+                        return false;
+                    }
+
+                    let origin = view.uri().map(|uri| origin_of(&uri)).unwrap_or_default();
+                    let (tx, rx) = tokio::sync::oneshot::channel();
+                    decide(&app, origin, kinds, move |allowed| {
+                        let _ = tx.send(allowed);
+                    });
+                    // The request stays pending until answered on the main
+                    // thread
+                    let request = request.clone();
+                    glib::MainContext::default().spawn_local(async move {
+                        if rx.await.unwrap_or(false) {
+                            request.allow();
+                        } else {
+                            request.deny();
+                        }
+                    });
+                    true
+                });
+        });
+    }
+}
+
+#[cfg(windows)]
+mod platform {
+    use tauri::{AppHandle, Manager, Webview, Wry};
+    use webview2_com::{
+        Microsoft::Web::WebView2::Win32::*, PermissionRequestedEventHandler, take_pwstr,
+    };
+    use windows::core::PWSTR;
+
+    use super::{PermissionKind, decide, origin_of};
+
+    /// COM objects handed back to the main thread, which they belong to
+    struct MainThreadOnly<T>(T);
+    unsafe impl<T> Send for MainThreadOnly<T> {}
+
+    impl<T> MainThreadOnly<T> {
+        fn into_inner(self) -> T {
+            self.0
+        }
+    }
+
+    fn permission_kind(kind: COREWEBVIEW2_PERMISSION_KIND) -> Option<PermissionKind> {
+        if kind == COREWEBVIEW2_PERMISSION_KIND_CAMERA {
+            Some(PermissionKind::Camera)
+        } else if kind == COREWEBVIEW2_PERMISSION_KIND_MICROPHONE {
+            Some(PermissionKind::Microphone)
+        } else if kind == COREWEBVIEW2_PERMISSION_KIND_GEOLOCATION {
+            Some(PermissionKind::Geolocation)
+        } else {
+            None
+        }
+    }
+
+    fn add_handler(
+        app: AppHandle,
+        controller: ICoreWebView2Controller,
+    ) -> windows::core::Result<()> {
+        unsafe {
+            let webview = controller.CoreWebView2()?;
+            let mut token = EventRegistrationToken::default();
+            webview.add_PermissionRequested(
+                &PermissionRequestedEventHandler::create(Box::new(move |_, args| {
+                    let Some(args) = args else { return Ok(()) };
+                    let mut kind = COREWEBVIEW2_PERMISSION_KIND::default();
+                    args.PermissionKind(&mut kind)?;
+                    // Leave other requests to the default handler
+                    let Some(kind) = permission_kind(kind) else {
+                        return Ok(());
+                    };
+                    let mut uri = PWSTR::null();
+                    args.Uri(&mut uri)?;
+                    let origin = origin_of(&take_pwstr(uri));
+
+                    let pending = MainThreadOnly((args.clone(), args.GetDeferral()?));
+                    let main_thread = app.clone();
+                    decide(&app, origin, vec![kind], move |allowed| {
+                        let _ = main_thread.run_on_main_thread(move || {
+                            let (args, deferral) = pending.into_inner();
+                            let state = if allowed {
+                                COREWEBVIEW2_PERMISSION_STATE_ALLOW
+                            } else {
+                                COREWEBVIEW2_PERMISSION_STATE_DENY
+                            };
+                            let _ = args.SetState(state);
+                            let _ = deferral.Complete();
+                        });
+                    });
+                    Ok(())
+                })),
+                &mut token,
+            )
+        }
+    }
+
+    pub fn install(webview: &Webview<Wry>) {
+        let app = webview.app_handle().clone();
+        let _ = webview.with_webview(move |platform| {
+            if let Err(e) = add_handler(app, platform.controller()) {
+                eprintln!("Failed to handle webview permission requests: {}", e);
+            }
+        });
+    }
+}
+
+#[cfg(target_os = "macos")]
+mod platform {
+    use tauri::{Webview, Wry};
+
+    pub fn install(_webview: &Webview<Wry>) {}
+}
+
+/// Remembered permission decisions of web content
+#[tauri::command]
+pub fn list_webview_permissions(app: AppHandle) -> Vec<PermissionEntry> {
+    read_decisions(&app)
+        .into_iter()
+        .flat_map(|(origin, stored)| {
+            stored
+                .into_iter()
+                .map(move |(kind, decision)| PermissionEntry {
+                    origin: origin.clone(),
+                    kind,
+                    decision,
+                })
+        })
+        .collect()
+}
+
+/// Forget the decisions for `origin`, or only the one for `kind`, so the
+/// next request prompts again
+#[tauri::command]
+pub fn revoke_webview_permission(
+    app: AppHandle,
+    webview: Webview,
+    origin: String,
+    kind: Option<PermissionKind>,
+) -> Result<(), AppError> {
+    let result = revoke(&app, &origin, kind);
+    audit::record(
+        &webview,
+        "revoke_webview_permission",
+        serde_json::json!({ "origin": origin, "kind": kind }),
+        &result,
+    );
+    result
+}
+
+fn revoke(app: &AppHandle, origin: &str, kind: Option<PermissionKind>) -> Result<(), AppError> {
+    let mut decisions = read_decisions(app);
+    match kind {
+        Some(kind) => {
+            if let Some(stored) = decisions.get_mut(origin) {
+                stored.remove(&kind);
+                if stored.is_empty() {
+                    decisions.remove(origin);
+                }
+            }
+        }
+        None => {
+            decisions.remove(origin);
+        }
+    }
+    Ok(write_decisions(app, &decisions)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_stored_decision() {
+        let mut decisions = Decisions::new();
+        decisions.insert(
+            "https://meet.example".to_string(),
+            BTreeMap::from([
+                (PermissionKind::Microphone, Decision::Allow),
+                (PermissionKind::Camera, Decision::Deny),
+            ]),
+        );
+        let origin = "https://meet.example";
+
+        assert_eq!(
+            stored_decision(&decisions, origin, &[PermissionKind::Microphone]),
+            Some(Decision::Allow)
+        );
+        assert_eq!(
+            stored_decision(
+                &decisions,
+                origin,
+                &[PermissionKind::Camera, PermissionKind::Microphone]
+            ),
+            Some(Decision::Deny)
+        );
+        assert_eq!(
+            stored_decision(&decisions, origin, &[PermissionKind::Geolocation]),
+            None
+        );
+        assert_eq!(
+            stored_decision(
+                &decisions,
+                "tauri://localhost",
+                &[PermissionKind::Microphone]
+            ),
+            None
+        );
+    }
+
+    #[test]
+    fn test_origin_of() {
+        assert_eq!(
+            origin_of("tauri://localhost/abc/session"),
+            "tauri://localhost"
+        );
+        assert_eq!(
+            origin_of("http://127.0.0.1:4096/path?q=1"),
+            "http://127.0.0.1:4096"
+        );
+        assert_eq!(origin_of("https://example.com:443/"), "https://example.com");
+    }
+}
@@ -0,0 +1,240 @@
+//! Window controls for the frameless title bar.
+//!
+//! On Windows the app draws its own title bar (the decorum overlay), which
+//! leaves the frontend to do what the native one would: the caption buttons
+//! call `window_minimize`, `window_toggle_maximize` and `window_close`, and
+//! a press in a drag region calls `window_drag` with the click count, so a
+//! double click maximizes like a native title bar and a single one starts
+//! the native move loop, with Aero Snap. `window_show_system_menu` opens the
+//! system menu on a right click. Windows get the frame styles Aero Snap and
+//! the minimize and maximize animations rely on, and `window:maximized` is
+//! emitted to a window when it's maximized or restored, to swap the icon of
+//! its maximize button.
+//!
+//! On macOS, where the title bar stays native, a double click does what the
+//! user set in System Settings.
+
+use serde::Serialize;
+use std::{collections::HashMap, sync::Mutex};
+use tauri::{AppHandle, Emitter, Manager, WebviewWindow, Window};
+
+use crate::error::AppError;
+
+/// Only macOS lets users choose anything but maximizing
+#[cfg_attr(not(target_os = "macos"), allow(dead_code))]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub enum DoubleClickAction {
+    Maximize,
+    Minimize,
+    None,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct MaximizedEvent {
+    pub maximized: bool,
+}
+
+/// Whether each window was maximized when last resized, by label
+#[derive(Default)]
+pub struct WindowControlsState(Mutex<HashMap<String, bool>>);
+
+/// What double clicking a title bar does, as set by the user
+#[cfg(target_os = "macos")]
+fn double_click_action() -> DoubleClickAction {
+    let output = std::process::Command::new("defaults")
+        .args(["read", "-g", "AppleActionOnDoubleClick"])
+        .output();
+    match output {
+        Ok(output) if output.status.success() => {
+            match String::from_utf8_lossy(&output.stdout).trim() {
+                "Minimize" => DoubleClickAction::Minimize,
+                "None" => DoubleClickAction::None,
+                _ => DoubleClickAction::Maximize,
+            }
+        }
+        // Not set: the default is to zoom
+        _ => DoubleClickAction::Maximize,
+    }
+}
+
+#[cfg(not(target_os = "macos"))]
+fn double_click_action() -> DoubleClickAction {
+    DoubleClickAction::Maximize
+}
+
+fn toggle_maximize(window: &WebviewWindow) -> Result<bool, String> {
+    let maximized = window
+        .is_maximized()
+        .map_err(|e| format!("Failed to read window state: {}", e))?;
+    let result = if maximized {
+        window.unmaximize()
+    } else {
+        window.maximize()
+    };
+    result.map_err(|e| format!("Failed to maximize window: {}", e))?;
+    Ok(!maximized)
+}
+
+/// Give a frameless window the styles Aero Snap, the system menu and the
+/// minimize and maximize animations need. The caption style stays invisible,
+/// as the window computes its own frame.
+#[cfg(windows)]
+pub fn prepare_frameless(window: &WebviewWindow) {
+    use windows::Win32::UI::WindowsAndMessaging::{
+        GWL_STYLE, GetWindowLongPtrW, SWP_FRAMECHANGED, SWP_NOACTIVATE, SWP_NOMOVE, SWP_NOSIZE,
+        SWP_NOZORDER, SetWindowLongPtrW, SetWindowPos, WS_CAPTION, WS_MAXIMIZEBOX, WS_MINIMIZEBOX,
+        WS_SYSMENU, WS_THICKFRAME,
+    };
+
+    let Ok(hwnd) = window.hwnd() else {
+        return;
+    };
+    let wanted = (WS_CAPTION | WS_THICKFRAME | WS_MINIMIZEBOX | WS_MAXIMIZEBOX | WS_SYSMENU).0;
+    unsafe {
+        let style = GetWindowLongPtrW(hwnd, GWL_STYLE);
+        if style & wanted as isize == wanted as isize {
+            return;
+        }
+        SetWindowLongPtrW(hwnd, GWL_STYLE, style | wanted as isize);
+        // Styles take effect once the frame is recomputed
+        if let Err(e) = SetWindowPos(
+            hwnd,
+            None,
+            0,
+            0,
+            0,
+            0,
+            SWP_FRAMECHANGED | SWP_NOMOVE | SWP_NOSIZE | SWP_NOZORDER | SWP_NOACTIVATE,
+        ) {
+            eprintln!("Failed to update window frame: {}", e);
+        }
+    }
+}
+
+#[cfg(windows)]
+fn show_system_menu(window: &WebviewWindow, x: i32, y: i32) -> Result<(), String> {
+    use windows::Win32::{
+        Foundation::{LPARAM, WPARAM},
+        UI::WindowsAndMessaging::{
+            GetSystemMenu, PostMessageW, TPM_RETURNCMD, TPM_RIGHTBUTTON, TrackPopupMenu,
+            WM_SYSCOMMAND,
+        },
+    };
+
+    let hwnd = window
+        .hwnd()
+        .map_err(|e| format!("Failed to get window handle: {}", e))?;
+    unsafe {
+        let menu = GetSystemMenu(hwnd, false);
+        let command = TrackPopupMenu(
+            menu,
+            TPM_RETURNCMD | TPM_RIGHTBUTTON,
+            x,
+            y,
+            None,
+            hwnd,
+            None,
+        );
+        if command.0 != 0 {
+            PostMessageW(
+                Some(hwnd),
+                WM_SYSCOMMAND,
+                WPARAM(command.0 as usize),
+                LPARAM(0),
+            )
+            .map_err(|e| format!("Failed to run menu command: {}", e))?;
+        }
+    }
+    Ok(())
+}
+
+#[cfg(not(windows))]
+fn show_system_menu(_window: &WebviewWindow, _x: i32, _y: i32) -> Result<(), String> {
+    Err("The system menu is only available on Windows".to_string())
+}
+
+/// Tell a window it was maximized or restored, when that changed with a
+/// resize
+pub fn on_resized(window: &Window) {
+    let Ok(maximized) = window.is_maximized() else {
+        return;
+    };
+    let Some(state) = window.try_state::<WindowControlsState>() else {
+        return;
+    };
+    let Ok(mut states) = state.0.lock() else {
+        return;
+    };
+    if states.insert(window.label().to_string(), maximized) != Some(maximized) {
+        let _ = window.emit_to(
+            window.label(),
+            "window:maximized",
+            MaximizedEvent { maximized },
+        );
+    }
+}
+
+/// Forget a closed window
+pub fn on_destroyed(app: &AppHandle, label: &str) {
+    if let Some(state) = app.try_state::<WindowControlsState>()
+        && let Ok(mut states) = state.0.lock()
+    {
+        states.remove(label);
+    }
+}
+
+#[tauri::command]
+pub fn window_minimize(window: WebviewWindow) -> Result<(), AppError> {
+    window
+        .minimize()
+        .map_err(|e| format!("Failed to minimize window: {}", e).into())
+}
+
+/// Maximize the window or restore it, returning whether it is now maximized
+#[tauri::command]
+pub fn window_toggle_maximize(window: WebviewWindow) -> Result<bool, AppError> {
+    Ok(toggle_maximize(&window)?)
+}
+
+/// Close the window the way its close button would, so close handlers run
+#[tauri::command]
+pub fn window_close(window: WebviewWindow) -> Result<(), AppError> {
+    window
+        .close()
+        .map_err(|e| format!("Failed to close window: {}", e).into())
+}
+
+/// Handle a press in a drag region of the title bar: the second click of a
+/// double click does the title bar's double click action, any other starts
+/// moving the window
+#[tauri::command]
+pub fn window_drag(window: WebviewWindow, clicks: Option<u32>) -> Result<(), AppError> {
+    if clicks.unwrap_or(1) < 2 {
+        return window
+            .start_dragging()
+            .map_err(|e| format!("Failed to drag window: {}", e).into());
+    }
+    match double_click_action() {
+        DoubleClickAction::Maximize => toggle_maximize(&window).map(|_| ())?,
+        DoubleClickAction::Minimize => window
+            .minimize()
+            .map_err(|e| format!("Failed to minimize window: {}", e))?,
+        DoubleClickAction::None => {}
+    }
+    Ok(())
+}
+
+/// What a double click on the title bar does, for title bars drawn by the
+/// frontend
+#[tauri::command]
+pub fn window_double_click_action() -> DoubleClickAction {
+    double_click_action()
+}
+
+/// Open the window's system menu at `x`, `y` in physical screen pixels
+#[tauri::command]
+pub fn window_show_system_menu(window: WebviewWindow, x: i32, y: i32) -> Result<(), AppError> {
+    show_system_menu(&window, x, y).map_err(AppError::unavailable)
+}
@@ -1,4 +1,90 @@
-use tauri::{plugin::Plugin, Manager, Runtime, Window};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use tauri::{
+    plugin::Plugin, AppHandle, Manager, PhysicalPosition, PhysicalSize, Runtime, Webview,
+    WebviewWindow, Window,
+};
+use tauri_plugin_store::StoreExt;
+
+use crate::{atomic_store, error::AppError, SETTINGS_STORE};
+
+/// Lowest opacity accepted, so a window can't be made invisible by accident
+const MIN_OPACITY: f64 = 0.1;
+/// User-defined snap presets, by id
+const SNAP_PRESETS_KEY: &str = "windowSnapPresets";
+
+/// Part of a display's work area, as fractions of its width and height
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SnapPreset {
+    pub x: f64,
+    pub y: f64,
+    pub width: f64,
+    pub height: f64,
+}
+
+impl SnapPreset {
+    const fn new(x: f64, y: f64, width: f64, height: f64) -> Self {
+        Self { x, y, width, height }
+    }
+
+    fn validate(&self) -> Result<(), String> {
+        let within = |start: f64, length: f64| {
+            start.is_finite()
+                && length.is_finite()
+                && start >= 0.0
+                && length > 0.0
+                && start + length <= 1.0 + f64::EPSILON
+        };
+        if within(self.x, self.width) && within(self.y, self.height) {
+            Ok(())
+        } else {
+            Err("Presets must lie within the display, as fractions from 0 to 1".to_string())
+        }
+    }
+
+    /// Outer bounds in a work area, in physical pixels. Edges are rounded the
+    /// same way for every preset, so adjacent presets tile without gaps.
+    fn bounds(
+        &self,
+        area: PhysicalPosition<i32>,
+        size: PhysicalSize<u32>,
+    ) -> (PhysicalPosition<i32>, PhysicalSize<u32>) {
+        let edge = |origin: i32, length: u32, fraction: f64| {
+            origin + (length as f64 * fraction.clamp(0.0, 1.0)).round() as i32
+        };
+        let left = edge(area.x, size.width, self.x);
+        let top = edge(area.y, size.height, self.y);
+        let right = edge(area.x, size.width, self.x + self.width);
+        let bottom = edge(area.y, size.height, self.y + self.height);
+        (
+            PhysicalPosition::new(left, top),
+            PhysicalSize::new((right - left).max(1) as u32, (bottom - top).max(1) as u32),
+        )
+    }
+}
+
+const BUILTIN_PRESETS: &[(&str, SnapPreset)] = &[
+    ("left-half", SnapPreset::new(0.0, 0.0, 0.5, 1.0)),
+    ("right-half", SnapPreset::new(0.5, 0.0, 0.5, 1.0)),
+    ("top-half", SnapPreset::new(0.0, 0.0, 1.0, 0.5)),
+    ("bottom-half", SnapPreset::new(0.0, 0.5, 1.0, 0.5)),
+    ("left-third", SnapPreset::new(0.0, 0.0, 1.0 / 3.0, 1.0)),
+    ("center-third", SnapPreset::new(1.0 / 3.0, 0.0, 1.0 / 3.0, 1.0)),
+    ("right-third", SnapPreset::new(2.0 / 3.0, 0.0, 1.0 / 3.0, 1.0)),
+    ("left-two-thirds", SnapPreset::new(0.0, 0.0, 2.0 / 3.0, 1.0)),
+    ("right-two-thirds", SnapPreset::new(1.0 / 3.0, 0.0, 2.0 / 3.0, 1.0)),
+    ("center-60", SnapPreset::new(0.2, 0.2, 0.6, 0.6)),
+    ("fill", SnapPreset::new(0.0, 0.0, 1.0, 1.0)),
+];
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SnapPresetInfo {
+    pub id: String,
+    pub builtin: bool,
+    pub preset: SnapPreset,
+}
 
 pub struct PinchZoomDisablePlugin;
 
@@ -32,3 +118,218 @@ impl<R: Runtime> Plugin<R> for PinchZoomDisablePlugin {
         });
     }
 }
+
+fn get_window(app: &AppHandle, label: &str) -> Result<WebviewWindow, AppError> {
+    app.get_webview_window(label)
+        .ok_or_else(|| AppError::not_found(format!("Window {} not found", label)))
+}
+
+#[cfg(target_os = "linux")]
+fn apply_opacity(window: &WebviewWindow, value: f64) -> Result<(), String> {
+    use gtk::prelude::WidgetExt;
+
+    window
+        .gtk_window()
+        .map_err(|e| format!("Failed to get GTK window: {}", e))?
+        .set_opacity(value);
+    Ok(())
+}
+
+#[cfg(target_os = "macos")]
+fn apply_opacity(window: &WebviewWindow, value: f64) -> Result<(), String> {
+    let ns_window = window
+        .ns_window()
+        .map_err(|e| format!("Failed to get NSWindow: {}", e))?;
+    unsafe {
+        let ns_window: &objc2_app_kit::NSWindow = &*ns_window.cast();
+        ns_window.setAlphaValue(value);
+    }
+    Ok(())
+}
+
+#[cfg(windows)]
+fn apply_opacity(window: &WebviewWindow, value: f64) -> Result<(), String> {
+    use windows::Win32::{
+        Foundation::COLORREF,
+        UI::WindowsAndMessaging::{
+            GetWindowLongPtrW, SetLayeredWindowAttributes, SetWindowLongPtrW, GWL_EXSTYLE,
+            LWA_ALPHA, WS_EX_LAYERED,
+        },
+    };
+
+    let hwnd = window
+        .hwnd()
+        .map_err(|e| format!("Failed to get window handle: {}", e))?;
+    unsafe {
+        let style = GetWindowLongPtrW(hwnd, GWL_EXSTYLE);
+        SetWindowLongPtrW(hwnd, GWL_EXSTYLE, style | WS_EX_LAYERED.0 as isize);
+        SetLayeredWindowAttributes(hwnd, COLORREF(0), (value * 255.0).round() as u8, LWA_ALPHA)
+            .map_err(|e| format!("Failed to set window opacity: {}", e))
+    }
+}
+
+/// Set the opacity of a whole window, e.g. a floating compact window hovering
+/// over other work. Sync so it runs on the main thread, as the native window
+/// APIs require.
+#[tauri::command]
+pub fn set_window_opacity(app: AppHandle, label: String, value: f64) -> Result<(), AppError> {
+    let window = get_window(&app, &label)?;
+    Ok(apply_opacity(&window, value.clamp(MIN_OPACITY, 1.0))?)
+}
+
+/// Let mouse events pass through a window to whatever is behind it
+#[tauri::command]
+pub fn set_click_through(app: AppHandle, label: String, enabled: bool) -> Result<(), AppError> {
+    get_window(&app, &label)?
+        .set_ignore_cursor_events(enabled)
+        .map_err(|e| format!("Failed to set click-through: {}", e).into())
+}
+
+fn user_presets(app: &AppHandle) -> BTreeMap<String, SnapPreset> {
+    app.store(SETTINGS_STORE)
+        .ok()
+        .and_then(|store| store.get(SNAP_PRESETS_KEY))
+        .and_then(|value| serde_json::from_value(value).ok())
+        .unwrap_or_default()
+}
+
+fn find_preset(app: &AppHandle, id: &str) -> Option<SnapPreset> {
+    BUILTIN_PRESETS
+        .iter()
+        .find(|(builtin, _)| *builtin == id)
+        .map(|(_, preset)| *preset)
+        .or_else(|| user_presets(app).get(id).copied())
+}
+
+/// Move and resize a window to a preset of the display it is on
+pub fn snap(app: &AppHandle, label: &str, preset: &str) -> Result<(), AppError> {
+    let window = get_window(app, label)?;
+    let preset = find_preset(app, preset)
+        .ok_or_else(|| AppError::not_found(format!("Unknown snap preset: {}", preset)))?;
+    let monitor = window
+        .current_monitor()
+        .ok()
+        .flatten()
+        .or_else(|| window.primary_monitor().ok().flatten())
+        .ok_or_else(|| AppError::unavailable("No display found"))?;
+
+    if window.is_fullscreen().unwrap_or(false) {
+        window
+            .set_fullscreen(false)
+            .map_err(|e| format!("Failed to leave full screen: {}", e))?;
+    }
+    if window.is_maximized().unwrap_or(false) {
+        window
+            .unmaximize()
+            .map_err(|e| format!("Failed to unmaximize window: {}", e))?;
+    }
+
+    let area = monitor.work_area();
+    let (position, size) = preset.bounds(area.position, area.size);
+    // set_size takes the inner size, so keep the decoration size out of it
+    let (outer, inner) = (
+        window
+            .outer_size()
+            .map_err(|e| format!("Failed to get window size: {}", e))?,
+        window
+            .inner_size()
+            .map_err(|e| format!("Failed to get window size: {}", e))?,
+    );
+    window
+        .set_size(PhysicalSize::new(
+            size.width.saturating_sub(outer.width.saturating_sub(inner.width)),
+            size.height.saturating_sub(outer.height.saturating_sub(inner.height)),
+        ))
+        .and_then(|_| window.set_position(position))
+        .map_err(|e| format!("Failed to move window: {}", e).into())
+}
+
+/// Snap a window (the calling one by default) to a built-in or user preset
+#[tauri::command]
+pub fn snap_window(
+    app: AppHandle,
+    webview: Webview,
+    preset: String,
+    label: Option<String>,
+) -> Result<(), AppError> {
+    let label = label.unwrap_or_else(|| webview.window().label().to_string());
+    snap(&app, &label, &preset)
+}
+
+/// Built-in presets followed by the user's
+#[tauri::command]
+pub fn list_snap_presets(app: AppHandle) -> Vec<SnapPresetInfo> {
+    let builtin = BUILTIN_PRESETS.iter().map(|(id, preset)| SnapPresetInfo {
+        id: id.to_string(),
+        builtin: true,
+        preset: *preset,
+    });
+    let user = user_presets(&app)
+        .into_iter()
+        .map(|(id, preset)| SnapPresetInfo {
+            id,
+            builtin: false,
+            preset,
+        });
+    builtin.chain(user).collect()
+}
+
+/// Save a user preset, or remove it when `preset` is null
+#[tauri::command]
+pub fn save_snap_preset(
+    app: AppHandle,
+    id: String,
+    preset: Option<SnapPreset>,
+) -> Result<(), AppError> {
+    let id = id.trim().to_string();
+    if id.is_empty() || BUILTIN_PRESETS.iter().any(|(builtin, _)| *builtin == id) {
+        return Err(AppError::invalid_input(format!(
+            "Preset name {:?} is empty or taken by a built-in preset",
+            id
+        )));
+    }
+    let mut presets = user_presets(&app);
+    match preset {
+        Some(preset) => {
+            preset.validate().map_err(AppError::invalid_input)?;
+            presets.insert(id, preset);
+        }
+        None => {
+            presets.remove(&id);
+        }
+    }
+
+    let store = app
+        .store(SETTINGS_STORE)
+        .map_err(|e| format!("Failed to open settings store: {}", e))?;
+    store.set(SNAP_PRESETS_KEY, serde_json::json!(presets));
+    atomic_store::save(&app, SETTINGS_STORE)
+        .map_err(|e| AppError::io(format!("Failed to save settings: {}", e)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_thirds_tile_the_work_area() {
+        let area = PhysicalPosition::new(-1000, 25);
+        let size = PhysicalSize::new(1000, 775);
+        let third = |id: &str| {
+            let (_, preset) = BUILTIN_PRESETS.iter().find(|(name, _)| *name == id).unwrap();
+            preset.bounds(area, size)
+        };
+
+        let (left, left_size) = third("left-third");
+        let (center, center_size) = third("center-third");
+        let (right, right_size) = third("right-third");
+        assert_eq!(left, PhysicalPosition::new(-1000, 25));
+        assert_eq!(left.x + left_size.width as i32, center.x);
+        assert_eq!(center.x + center_size.width as i32, right.x);
+        assert_eq!(right.x + right_size.width as i32, 0);
+        assert_eq!(right_size.height, 775);
+
+        assert!(SnapPreset::new(0.5, 0.0, 0.6, 1.0).validate().is_err());
+        assert!(SnapPreset::new(0.2, 0.2, 0.6, 0.6).validate().is_ok());
+    }
+}
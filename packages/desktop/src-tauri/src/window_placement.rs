@@ -0,0 +1,442 @@
+//! Keep windows on a connected display.
+//!
+//! Windows restored onto a display that has since been unplugged (docks,
+//! projectors) end up off-screen with no way to drag them back. Displays are
+//! polled for changes, and on a change, a scale-factor change or at startup
+//! every window that isn't reachable anymore is moved onto the display under
+//! the cursor and shrunk to fit its work area.
+//!
+//! Which display a new window opens on is set per window under
+//! `windowMonitors` in the settings, by label or by kind (`main`, `pip`,
+//! `project` for all project windows): `cursor` (the default), `primary`, or
+//! the id of a display as `list_monitors` reports it. A display that isn't
+//! connected falls back to the one under the cursor.
+
+use serde::Serialize;
+use std::{collections::HashMap, time::Duration};
+use tauri::{
+    AppHandle, LogicalPosition, LogicalSize, Manager, Monitor, PhysicalPosition, PhysicalSize,
+    Webview, Window,
+};
+use tauri_plugin_store::StoreExt;
+
+use crate::{SETTINGS_STORE, atomic_store, audit, error::AppError};
+
+const WINDOW_MONITORS_KEY: &str = "windowMonitors";
+const CURSOR_MONITOR: &str = "cursor";
+const PRIMARY_MONITOR: &str = "primary";
+const POLL_INTERVAL: Duration = Duration::from_secs(2);
+/// Height of the strip at the top of a window that must stay on a display,
+/// roughly where the title bar is
+const TITLE_STRIP_PX: u32 = 32;
+/// How much of that strip must be visible to grab the window
+const MIN_VISIBLE_PX: u32 = 64;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Rect {
+    x: i32,
+    y: i32,
+    width: u32,
+    height: u32,
+}
+
+impl Rect {
+    fn work_area(monitor: &Monitor) -> Self {
+        let area = monitor.work_area();
+        Self {
+            x: area.position.x,
+            y: area.position.y,
+            width: area.size.width,
+            height: area.size.height,
+        }
+    }
+
+    fn right(&self) -> i64 {
+        self.x as i64 + self.width as i64
+    }
+
+    fn bottom(&self) -> i64 {
+        self.y as i64 + self.height as i64
+    }
+
+    /// Width and height of the intersection with `other`
+    fn overlap(&self, other: &Rect) -> (u32, u32) {
+        let width = self.right().min(other.right()) - (self.x.max(other.x) as i64);
+        let height = self.bottom().min(other.bottom()) - (self.y.max(other.y) as i64);
+        (width.max(0) as u32, height.max(0) as u32)
+    }
+}
+
+/// Whether enough of the window's title strip is on some display to drag it
+fn is_reachable(window: Rect, displays: &[Rect]) -> bool {
+    let strip = Rect {
+        height: window.height.min(TITLE_STRIP_PX),
+        ..window
+    };
+    displays.iter().any(|display| {
+        let (width, height) = strip.overlap(display);
+        width >= MIN_VISIBLE_PX.min(strip.width) && height >= strip.height / 2
+    })
+}
+
+/// Center the window on `display`, shrinking it to fit if needed
+fn fit_into(window: Rect, display: Rect) -> Rect {
+    let width = window.width.min(display.width);
+    let height = window.height.min(display.height);
+    Rect {
+        x: display.x + ((display.width - width) / 2) as i32,
+        y: display.y + ((display.height - height) / 2) as i32,
+        width,
+        height,
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct MonitorInfo {
+    /// What `windowMonitors` and `move_window_to_monitor` refer to it by
+    pub id: String,
+    pub name: Option<String>,
+    pub primary: bool,
+    pub scale_factor: f64,
+    /// Physical pixels
+    pub x: i32,
+    pub y: i32,
+    pub width: u32,
+    pub height: u32,
+}
+
+/// The name the OS gives the display, or its geometry for displays without
+/// one
+fn monitor_id(monitor: &Monitor) -> String {
+    match monitor.name() {
+        Some(name) => name.clone(),
+        None => {
+            let (position, size) = (monitor.position(), monitor.size());
+            format!(
+                "{}x{}+{}+{}",
+                size.width, size.height, position.x, position.y
+            )
+        }
+    }
+}
+
+/// The monitor under the cursor, falling back to the primary monitor
+fn cursor_monitor(app: &AppHandle) -> Option<Monitor> {
+    app.cursor_position()
+        .ok()
+        .and_then(|cursor| app.monitor_from_point(cursor.x, cursor.y).ok().flatten())
+        .or_else(|| app.primary_monitor().ok().flatten())
+}
+
+fn find_monitor(app: &AppHandle, id: &str) -> Option<Monitor> {
+    app.available_monitors()
+        .unwrap_or_default()
+        .into_iter()
+        .find(|monitor| monitor_id(monitor) == id)
+}
+
+fn monitor_rules(app: &AppHandle) -> HashMap<String, String> {
+    app.store(SETTINGS_STORE)
+        .ok()
+        .and_then(|store| store.get(WINDOW_MONITORS_KEY))
+        .and_then(|value| serde_json::from_value(value).ok())
+        .unwrap_or_default()
+}
+
+/// The rule for window `label`: its own, else that of its kind, the part
+/// of the label before the first `-`
+fn rule_for<'a>(rules: &'a HashMap<String, String>, label: &str) -> Option<&'a str> {
+    let kind = label.split('-').next().unwrap_or(label);
+    rules
+        .get(label)
+        .or_else(|| rules.get(kind))
+        .map(String::as_str)
+}
+
+/// The monitor the rules put window `label` on, if they name one
+fn ruled_monitor(app: &AppHandle, label: &str) -> Option<Monitor> {
+    match rule_for(&monitor_rules(app), label)? {
+        CURSOR_MONITOR => None,
+        PRIMARY_MONITOR => app.primary_monitor().ok().flatten(),
+        id => find_monitor(app, id),
+    }
+}
+
+/// The monitor a new window `label` opens on
+pub fn target_monitor(app: &AppHandle, label: &str) -> Option<Monitor> {
+    ruled_monitor(app, label).or_else(|| cursor_monitor(app))
+}
+
+/// Size and position for a new window `label`: the work area of its display,
+/// by default the one the cursor is on, so it opens where the user is
+/// looking and clear of docks
+pub fn initial_window_bounds(
+    app: &AppHandle,
+    label: &str,
+) -> (LogicalSize<f64>, Option<LogicalPosition<f64>>) {
+    match target_monitor(app, label) {
+        Some(monitor) => {
+            let area = monitor.work_area();
+            let scale = monitor.scale_factor();
+            (
+                area.size.to_logical(scale),
+                Some(area.position.to_logical(scale)),
+            )
+        }
+        None => (LogicalSize::new(1920.0, 1080.0), None),
+    }
+}
+
+/// Move the window back onto a display if it isn't reachable anymore
+pub fn ensure_on_screen(window: &Window) {
+    if window.is_minimized().unwrap_or(false) {
+        return;
+    }
+    let (Ok(position), Ok(outer)) = (window.outer_position(), window.outer_size()) else {
+        return;
+    };
+    let app = window.app_handle();
+    let displays: Vec<Rect> = app
+        .available_monitors()
+        .unwrap_or_default()
+        .iter()
+        .map(Rect::work_area)
+        .collect();
+    let current = Rect {
+        x: position.x,
+        y: position.y,
+        width: outer.width,
+        height: outer.height,
+    };
+    if displays.is_empty() || is_reachable(current, &displays) {
+        return;
+    }
+
+    let Some(target) = cursor_monitor(app)
+        .map(|monitor| Rect::work_area(&monitor))
+        .or_else(|| displays.first().copied())
+    else {
+        return;
+    };
+    println!(
+        "Moving window {} back on screen: {:?} -> {:?}",
+        window.label(),
+        current,
+        target
+    );
+    place(window, target);
+}
+
+/// Center the window on `display`, shrinking it to fit
+fn place(window: &Window, display: Rect) {
+    let (Ok(position), Ok(outer), Ok(inner)) = (
+        window.outer_position(),
+        window.outer_size(),
+        window.inner_size(),
+    ) else {
+        return;
+    };
+    let current = Rect {
+        x: position.x,
+        y: position.y,
+        width: outer.width,
+        height: outer.height,
+    };
+    let fitted = fit_into(current, display);
+
+    // set_size takes the inner size, so keep the decoration size out of it
+    let frame_width = outer.width.saturating_sub(inner.width);
+    let frame_height = outer.height.saturating_sub(inner.height);
+    let _ = window.set_size(PhysicalSize::new(
+        fitted.width.saturating_sub(frame_width),
+        fitted.height.saturating_sub(frame_height),
+    ));
+    let _ = window.set_position(PhysicalPosition::new(fitted.x, fitted.y));
+}
+
+/// Move the window onto the display its rule names, for windows whose
+/// restored position may be elsewhere
+pub fn apply_monitor_rule(window: &Window) {
+    let Some(monitor) = ruled_monitor(window.app_handle(), window.label()) else {
+        return;
+    };
+    let on_it = window
+        .current_monitor()
+        .ok()
+        .flatten()
+        .is_some_and(|current| monitor_id(&current) == monitor_id(&monitor));
+    if !on_it {
+        place(window, Rect::work_area(&monitor));
+    }
+}
+
+fn ensure_all_on_screen(app: &AppHandle) {
+    for window in app.webview_windows().values() {
+        ensure_on_screen(&window.as_ref().window());
+    }
+}
+
+/// Displays as seen by the app, used to detect connects, disconnects and
+/// resolution or scale changes
+fn display_signature(app: &AppHandle) -> Vec<(Rect, u64)> {
+    app.available_monitors()
+        .unwrap_or_default()
+        .iter()
+        .map(|monitor| (Rect::work_area(monitor), monitor.scale_factor().to_bits()))
+        .collect()
+}
+
+/// Watch for display changes for the lifetime of the app
+pub fn watch_displays(app: &AppHandle) {
+    let app = app.clone();
+    let spawned = std::thread::Builder::new()
+        .name("display-watcher".to_string())
+        .spawn(move || {
+            let mut last = display_signature(&app);
+            loop {
+                std::thread::sleep(POLL_INTERVAL);
+                let current = display_signature(&app);
+                if current != last {
+                    ensure_all_on_screen(&app);
+                    last = current;
+                }
+            }
+        });
+    if let Err(e) = spawned {
+        eprintln!("Failed to start display watcher: {}", e);
+    }
+}
+
+/// Connected displays
+#[tauri::command]
+pub fn list_monitors(app: AppHandle) -> Vec<MonitorInfo> {
+    let primary = app.primary_monitor().ok().flatten().map(|m| monitor_id(&m));
+    app.available_monitors()
+        .unwrap_or_default()
+        .iter()
+        .map(|monitor| {
+            let id = monitor_id(monitor);
+            MonitorInfo {
+                primary: primary.as_ref() == Some(&id),
+                name: monitor.name().cloned(),
+                scale_factor: monitor.scale_factor(),
+                x: monitor.position().x,
+                y: monitor.position().y,
+                width: monitor.size().width,
+                height: monitor.size().height,
+                id,
+            }
+        })
+        .collect()
+}
+
+#[tauri::command]
+pub fn move_window_to_monitor(app: AppHandle, label: String, id: String) -> Result<(), AppError> {
+    let window = app
+        .get_webview_window(&label)
+        .ok_or_else(|| AppError::not_found(format!("No window {}", label)))?;
+    let monitor =
+        find_monitor(&app, &id).ok_or_else(|| AppError::not_found(format!("No display {}", id)))?;
+    if window.is_maximized().unwrap_or(false) {
+        let _ = window.unmaximize();
+    }
+    place(&window.as_ref().window(), Rect::work_area(&monitor));
+    Ok(())
+}
+
+#[tauri::command]
+pub fn get_window_monitor_rules(app: AppHandle) -> HashMap<String, String> {
+    monitor_rules(&app)
+}
+
+/// Set the display window `window` (a label or a kind) opens on, or with
+/// `None` go back to the display under the cursor
+#[tauri::command]
+pub fn set_window_monitor_rule(
+    app: AppHandle,
+    webview: Webview,
+    window: String,
+    monitor: Option<String>,
+) -> Result<(), AppError> {
+    let result = save_rule(&app, &window, monitor.as_deref());
+    audit::record(
+        &webview,
+        "set_window_monitor_rule",
+        serde_json::json!({ "window": window, "monitor": monitor }),
+        &result,
+    );
+    result
+}
+
+fn save_rule(app: &AppHandle, window: &str, monitor: Option<&str>) -> Result<(), AppError> {
+    if window.trim().is_empty() {
+        return Err(AppError::invalid_input("No window given"));
+    }
+    let mut rules = monitor_rules(app);
+    match monitor.filter(|monitor| *monitor != CURSOR_MONITOR) {
+        Some(monitor) => rules.insert(window.to_string(), monitor.to_string()),
+        None => rules.remove(window),
+    };
+
+    let store = app
+        .store(SETTINGS_STORE)
+        .map_err(|e| format!("Failed to open settings store: {}", e))?;
+    let value = serde_json::to_value(&rules)
+        .map_err(|e| format!("Failed to serialize window rules: {}", e))?;
+    store.set(WINDOW_MONITORS_KEY, value);
+    atomic_store::save(app, SETTINGS_STORE)
+        .map_err(|e| AppError::io(format!("Failed to save settings: {}", e)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rect(x: i32, y: i32, width: u32, height: u32) -> Rect {
+        Rect {
+            x,
+            y,
+            width,
+            height,
+        }
+    }
+
+    #[test]
+    fn test_is_reachable_needs_title_strip_on_a_display() {
+        let displays = [rect(0, 0, 1920, 1080), rect(1920, 0, 2560, 1440)];
+        assert!(is_reachable(rect(100, 100, 800, 600), &displays));
+        // Straddling both displays
+        assert!(is_reachable(rect(1500, 100, 800, 600), &displays));
+        // Only the bottom of the window is visible
+        assert!(!is_reachable(rect(100, -580, 800, 600), &displays));
+        // On a display to the left that was unplugged
+        assert!(!is_reachable(rect(-1800, 100, 800, 600), &displays));
+        // Just a sliver on the right edge
+        assert!(!is_reachable(rect(4450, 100, 800, 600), &displays));
+    }
+
+    #[test]
+    fn test_rule_for_falls_back_to_kind() {
+        let rules = HashMap::from([
+            ("project".to_string(), "primary".to_string()),
+            ("project-abc".to_string(), "HDMI-1".to_string()),
+        ]);
+        assert_eq!(rule_for(&rules, "project-abc"), Some("HDMI-1"));
+        assert_eq!(rule_for(&rules, "project-def"), Some("primary"));
+        assert_eq!(rule_for(&rules, "main"), None);
+    }
+
+    #[test]
+    fn test_fit_into_centers_and_shrinks() {
+        let display = rect(1920, 0, 1280, 800);
+        assert_eq!(
+            fit_into(rect(-3000, 50, 800, 600), display),
+            rect(2160, 100, 800, 600)
+        );
+        assert_eq!(
+            fit_into(rect(0, 0, 2560, 1440), display),
+            rect(1920, 0, 1280, 800)
+        );
+    }
+}
@@ -0,0 +1,99 @@
+//! Small previews of open windows for the window/session switcher.
+//!
+//! The OS window belonging to a Tauri window is found among this process's
+//! windows by title and geometry, captured with `xcap` and downscaled into a
+//! PNG thumbnail like attachment previews.
+
+use tauri::{AppHandle, Manager, WebviewWindow};
+
+use crate::{
+    error::AppError,
+    images::{self, Thumbnail},
+};
+
+const DEFAULT_THUMBNAIL_PX: u32 = 320;
+
+/// Distance between an OS window and the Tauri window's outer frame. xcap
+/// reports physical pixels on some platforms and logical points on others,
+/// so the closer of the two interpretations wins.
+fn frame_distance(window: &xcap::Window, target: &WebviewWindow) -> Option<i64> {
+    let position = target.outer_position().ok()?;
+    let size = target.outer_size().ok()?;
+    let scale = target.scale_factor().ok()?;
+
+    let frame = (
+        window.x().ok()? as i64,
+        window.y().ok()? as i64,
+        window.width().ok()? as i64,
+        window.height().ok()? as i64,
+    );
+    let distance = |x: f64, y: f64, width: f64, height: f64| {
+        (frame.0 - x as i64).abs()
+            + (frame.1 - y as i64).abs()
+            + (frame.2 - width as i64).abs()
+            + (frame.3 - height as i64).abs()
+    };
+
+    let physical = distance(
+        position.x as f64,
+        position.y as f64,
+        size.width as f64,
+        size.height as f64,
+    );
+    let logical = distance(
+        position.x as f64 / scale,
+        position.y as f64 / scale,
+        size.width as f64 / scale,
+        size.height as f64 / scale,
+    );
+    Some(physical.min(logical))
+}
+
+fn find_os_window(target: &WebviewWindow) -> Result<xcap::Window, String> {
+    let title = target.title().unwrap_or_default();
+    let pid = std::process::id();
+
+    xcap::Window::all()
+        .map_err(|e| format!("Failed to list windows: {}", e))?
+        .into_iter()
+        .filter(|window| window.pid().is_ok_and(|p| p == pid))
+        .filter_map(|window| {
+            let distance = frame_distance(&window, target)?;
+            let title_mismatch = window.title().map_or(true, |t| t != title);
+            Some(((title_mismatch, distance), window))
+        })
+        .min_by_key(|(key, _)| *key)
+        .map(|(_, window)| window)
+        .ok_or_else(|| format!("No native window found for {}", target.label()))
+}
+
+fn capture(target: &WebviewWindow, max_px: u32) -> Result<Thumbnail, String> {
+    if target.is_minimized().unwrap_or(false) {
+        return Err(format!("Window {} is minimized", target.label()));
+    }
+
+    let window = find_os_window(target)?;
+    let image = window
+        .capture_image()
+        .map_err(|e| format!("Failed to capture window {}: {}", target.label(), e))?;
+    images::encode_thumbnail(image::DynamicImage::ImageRgba8(image), max_px)
+}
+
+/// Render a small PNG of an open window
+#[tauri::command]
+pub async fn capture_window_thumbnail(
+    app: AppHandle,
+    label: String,
+    max_px: Option<u32>,
+) -> Result<Thumbnail, AppError> {
+    let window = app
+        .get_webview_window(&label)
+        .ok_or_else(|| AppError::not_found(format!("Window {} not found", label)))?;
+    let max_px = max_px.unwrap_or(DEFAULT_THUMBNAIL_PX);
+
+    Ok(
+        tauri::async_runtime::spawn_blocking(move || capture(&window, max_px))
+            .await
+            .map_err(|e| format!("Window capture task failed: {}", e))??,
+    )
+}
@@ -0,0 +1,174 @@
+//! Trust in project folders.
+//!
+//! A cloned repository can carry files that change what the app does, like
+//! `.aura/settings.json` pointing it at another server. The first time a
+//! folder is opened in a project window the user is asked whether they trust
+//! it, and the answer is kept under `trustedWorkspaces` in the settings, by
+//! canonical path. Trusting a folder trusts the folders in it, unless one of
+//! them was answered differently.
+//!
+//! Untrusted folders open in restricted mode, which so far covers two
+//! things: their project settings are ignored, and the sidecar starts at the
+//! sandbox level set for untrusted folders. `get_workspace_trust` reports
+//! the decision; nothing else, like the terminal, file watchers or dropped
+//! files, is held back by it yet.
+
+use serde::Serialize;
+use serde_json::{Map, Value};
+use std::path::{Path, PathBuf};
+use tauri::{AppHandle, Webview};
+use tauri_plugin_dialog::{
+    DialogExt, MessageDialogButtons, MessageDialogKind, MessageDialogResult,
+};
+use tauri_plugin_store::StoreExt;
+
+use crate::{SETTINGS_STORE, atomic_store, audit, error::AppError, i18n, test_mode};
+
+const TRUSTED_WORKSPACES_KEY: &str = "trustedWorkspaces";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub enum Trust {
+    Trusted,
+    Untrusted,
+    /// Not asked yet
+    Unknown,
+}
+
+fn canonical(path: &Path) -> PathBuf {
+    std::fs::canonicalize(path).unwrap_or_else(|_| path.to_path_buf())
+}
+
+fn decisions(app: &AppHandle) -> Map<String, Value> {
+    match app
+        .store(SETTINGS_STORE)
+        .ok()
+        .and_then(|store| store.get(TRUSTED_WORKSPACES_KEY))
+    {
+        Some(Value::Object(decisions)) => decisions,
+        _ => Map::new(),
+    }
+}
+
+/// The decision for `path` or, failing that, its closest decided parent
+fn resolve(decisions: &Map<String, Value>, path: &Path) -> Trust {
+    path.ancestors()
+        .find_map(|folder| decisions.get(folder.to_string_lossy().as_ref())?.as_bool())
+        .map(|trusted| {
+            if trusted {
+                Trust::Trusted
+            } else {
+                Trust::Untrusted
+            }
+        })
+        .unwrap_or(Trust::Unknown)
+}
+
+pub fn trust(app: &AppHandle, path: &Path) -> Trust {
+    resolve(&decisions(app), &canonical(path))
+}
+
+/// Whether the user declined to trust any folder
+pub fn any_untrusted(app: &AppHandle) -> bool {
+    decisions(app)
+        .values()
+        .any(|trusted| trusted.as_bool() == Some(false))
+}
+
+fn save_trust(app: &AppHandle, path: &Path, trusted: bool) -> Result<(), AppError> {
+    let store = app
+        .store(SETTINGS_STORE)
+        .map_err(|e| format!("Failed to open settings store: {}", e))?;
+    let mut decisions = decisions(app);
+    decisions.insert(
+        canonical(path).to_string_lossy().into_owned(),
+        Value::Bool(trusted),
+    );
+    store.set(TRUSTED_WORKSPACES_KEY, Value::Object(decisions));
+    atomic_store::save(app, SETTINGS_STORE)
+        .map_err(|e| AppError::io(format!("Failed to save settings: {}", e)))
+}
+
+/// Ask whether to trust `path` if that hasn't been answered yet
+pub async fn prompt(app: &AppHandle, path: &Path) -> Result<Trust, AppError> {
+    let current = trust(app, path);
+    if current != Trust::Unknown {
+        return Ok(current);
+    }
+
+    let trust_label = i18n::t(app, "dialog.workspaceTrust.trust");
+    let dialog = app
+        .dialog()
+        .message(i18n::t_with(
+            app,
+            "dialog.workspaceTrust.message",
+            &[("path", &path.to_string_lossy())],
+        ))
+        .title(i18n::t(app, "dialog.workspaceTrust.title"))
+        .kind(MessageDialogKind::Warning)
+        .buttons(MessageDialogButtons::OkCancelCustom(
+            trust_label.clone(),
+            i18n::t(app, "dialog.workspaceTrust.restricted"),
+        ));
+    let result = match test_mode::dialog_answer(app, "workspaceTrust") {
+        Some(answer) => answer,
+        None => tauri::async_runtime::spawn_blocking(move || dialog.blocking_show_with_result())
+            .await
+            .map_err(|e| format!("Trust prompt failed: {}", e))?,
+    };
+
+    let trusted = matches!(result, MessageDialogResult::Custom(name) if name == trust_label);
+    save_trust(app, path, trusted)?;
+    Ok(trust(app, path))
+}
+
+#[tauri::command]
+pub fn get_workspace_trust(app: AppHandle, directory: String) -> Trust {
+    trust(&app, Path::new(&directory))
+}
+
+/// Ask the user about `directory` unless they already answered
+#[tauri::command]
+pub async fn request_workspace_trust(app: AppHandle, directory: String) -> Result<Trust, AppError> {
+    prompt(&app, Path::new(&directory)).await
+}
+
+#[tauri::command]
+pub fn set_workspace_trust(
+    app: AppHandle,
+    webview: Webview,
+    directory: String,
+    trusted: bool,
+) -> Result<(), AppError> {
+    let result = save_trust(&app, Path::new(&directory), trusted);
+    audit::record(
+        &webview,
+        "set_workspace_trust",
+        serde_json::json!({ "directory": directory, "trusted": trusted }),
+        &result,
+    );
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_closest_decision_wins() {
+        let decisions = match json!({ "/work": true, "/work/vendor": false }) {
+            Value::Object(map) => map,
+            _ => unreachable!(),
+        };
+        assert_eq!(
+            resolve(&decisions, Path::new("/work/app/src")),
+            Trust::Trusted
+        );
+        assert_eq!(
+            resolve(&decisions, Path::new("/work/vendor/lib")),
+            Trust::Untrusted
+        );
+        assert_eq!(resolve(&decisions, Path::new("/home/me")), Trust::Unknown);
+    }
+}
@@ -1,5 +1,6 @@
 import { invoke } from "@tauri-apps/api/core"
 import { message } from "@tauri-apps/plugin-dialog"
+import { errorMessage } from "./error"
 
 export async function installCli(): Promise<void> {
   try {
@@ -8,6 +9,6 @@ export async function installCli(): Promise<void> {
       title: "CLI Installed",
     })
   } catch (e) {
-    await message(`Failed to install CLI: ${e}`, { title: "Installation Failed" })
+    await message(`Failed to install CLI: ${errorMessage(e)}`, { title: "Installation Failed" })
   }
 }
@@ -0,0 +1,29 @@
+// Errors rejected by native commands, see src-tauri/src/error.rs
+
+export type AppErrorKind =
+  | "notFound"
+  | "invalidInput"
+  | "permissionDenied"
+  | "network"
+  | "io"
+  | "busy"
+  | "unavailable"
+  | "cancelled"
+  | "internal"
+
+export type AppError = {
+  kind: AppErrorKind
+  message: string
+  retryable: boolean
+  remediation?: string
+}
+
+export function isAppError(e: unknown): e is AppError {
+  return typeof e === "object" && e !== null && "kind" in e && "message" in e
+}
+
+/** The message of a command error, followed by its remediation hint */
+export function errorMessage(e: unknown): string {
+  if (!isAppError(e)) return String(e)
+  return e.remediation ? `${e.message}\n\n${e.remediation}` : e.message
+}
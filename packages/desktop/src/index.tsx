@@ -5,7 +5,7 @@ import { AppBaseProviders, AppInterface, PlatformProvider, Platform } from "@ope
 import { open, save } from "@tauri-apps/plugin-dialog"
 import { open as shellOpen } from "@tauri-apps/plugin-shell"
 import { type as ostype } from "@tauri-apps/plugin-os"
-import { check, Update } from "@tauri-apps/plugin-updater"
+import { Update } from "@tauri-apps/plugin-updater"
 import { invoke } from "@tauri-apps/api/core"
 import { listen } from "@tauri-apps/api/event"
 import { getCurrentWindow } from "@tauri-apps/api/window"
@@ -15,10 +15,12 @@ import { AsyncStorage } from "@solid-primitives/storage"
 import { fetch as tauriFetch } from "@tauri-apps/plugin-http"
 import { Store } from "@tauri-apps/plugin-store"
 import { Splash } from "@opencode-ai/ui/logo"
+import { showToast } from "@opencode-ai/ui/toast"
 import { createSignal, Show, Accessor, JSX, createResource, onMount, onCleanup } from "solid-js"
 
-import { UPDATER_ENABLED } from "./updater"
+import { checkForUpdate, UPDATER_ENABLED } from "./updater"
 import { createMenu } from "./menu"
+import { errorMessage } from "./error"
 import pkg from "../package.json"
 import "./styles.css"
 
@@ -42,6 +44,69 @@ window.getComputedStyle = ((elt: Element, pseudoElt?: string | null) => {
 
 let update: Update | null = null
 
+async function canNotify() {
+  const presenting = await invoke<{ active: boolean }>("get_presentation_mode")
+    .then((status) => status.active)
+    .catch(() => false)
+  if (presenting) return false
+
+  const granted = await isPermissionGranted().catch(() => false)
+  const permission = granted ? "granted" : await requestPermission().catch(() => "denied")
+  if (permission !== "granted") return false
+
+  const focused = await getCurrentWindow()
+    .isFocused()
+    .catch(() => document.hasFocus())
+  return !focused
+}
+
+function showNotification(title: string, description?: string, href?: string) {
+  try {
+    const notification = new Notification(title, {
+      body: description ?? "",
+      icon: "https://opencode.ai/favicon-96x96.png",
+    })
+    notification.onclick = () => {
+      const win = getCurrentWindow()
+      void win.show().catch(() => undefined)
+      void win.unminimize().catch(() => undefined)
+      void win.setFocus().catch(() => undefined)
+      if (href) {
+        window.history.pushState(null, "", href)
+        window.dispatchEvent(new PopStateEvent("popstate"))
+      }
+      notification.close()
+    }
+  } catch {}
+}
+
+type NotificationSummary = { title: string; body: string; href?: string }
+
+// Sent to this window only, so a summary shows once
+void getCurrentWindow().listen<NotificationSummary>("notification:summary", async (event) => {
+  if (!(await canNotify())) return
+  showNotification(event.payload.title, event.payload.body, event.payload.href)
+})
+
+type MemoryWarning = { title: string; body: string; actions: { id: string; title: string }[] }
+
+// The actions free the memory, see src-tauri/src/memory_watchdog.rs
+void listen<MemoryWarning>("memory:warning", (event) => {
+  showToast({
+    title: event.payload.title,
+    description: event.payload.body,
+    persistent: true,
+    actions: event.payload.actions.map((action) => ({
+      label: action.title,
+      onClick: () => {
+        invoke("invoke_action", { id: action.id }).catch((e) =>
+          showToast({ variant: "error", description: errorMessage(e) }),
+        )
+      },
+    })),
+  })
+})
+
 const createPlatform = (password: Accessor<string | null>): Platform => ({
   platform: "desktop",
   os: (() => {
@@ -137,14 +202,17 @@ const createPlatform = (password: Accessor<string | null>): Platform => ({
       const cached = storeCache.get(name)
       if (cached) return cached
 
-      const store = Store.load(name).catch(() => {
-        const cached = memoryCache.get(name)
-        if (cached) return cached
+      // Saved by the app, which writes the file atomically, see src-tauri/src/atomic_store.rs
+      const store = Store.load(name, { autoSave: false })
+        .then((store) => Object.assign(store, { save: () => invoke("save_store", { name }) }))
+        .catch(() => {
+          const cached = memoryCache.get(name)
+          if (cached) return cached
 
-        const memory = createMemoryStore()
-        memoryCache.set(name, memory)
-        return memory
-      })
+          const memory = createMemoryStore()
+          memoryCache.set(name, memory)
+          return memory
+        })
 
       storeCache.set(name, store)
       return store
@@ -239,7 +307,7 @@ const createPlatform = (password: Accessor<string | null>): Platform => ({
 
   checkUpdate: async () => {
     if (!UPDATER_ENABLED) return { updateAvailable: false }
-    const next = await check().catch(() => null)
+    const next = await checkForUpdate().catch(() => null)
     if (!next) return { updateAvailable: false }
     const ok = await next
       .download()
@@ -261,34 +329,13 @@ const createPlatform = (password: Accessor<string | null>): Platform => ({
     await relaunch()
   },
 
-  notify: async (title, description, href) => {
-    const granted = await isPermissionGranted().catch(() => false)
-    const permission = granted ? "granted" : await requestPermission().catch(() => "denied")
-    if (permission !== "granted") return
-
-    const win = getCurrentWindow()
-    const focused = await win.isFocused().catch(() => document.hasFocus())
-    if (focused) return
-
-    await Promise.resolve()
-      .then(() => {
-        const notification = new Notification(title, {
-          body: description ?? "",
-          icon: "https://opencode.ai/favicon-96x96.png",
-        })
-        notification.onclick = () => {
-          const win = getCurrentWindow()
-          void win.show().catch(() => undefined)
-          void win.unminimize().catch(() => undefined)
-          void win.setFocus().catch(() => undefined)
-          if (href) {
-            window.history.pushState(null, "", href)
-            window.dispatchEvent(new PopStateEvent("popstate"))
-          }
-          notification.close()
-        }
-      })
-      .catch(() => undefined)
+  notify: async (title, description, href, category) => {
+    if (!(await canNotify())) return
+    // Bursts are held by the backend and come back as one "notification:summary"
+    const show = await invoke<boolean>("coalesce_notification", { category, title, body: description, href }).catch(
+      () => true,
+    )
+    if (show) showNotification(title, description, href)
   },
 
   fetch: ((input: RequestInfo | URL, init?: RequestInit) => {
@@ -1,4 +1,4 @@
-import { check } from "@tauri-apps/plugin-updater"
+import { Update } from "@tauri-apps/plugin-updater"
 import { relaunch } from "@tauri-apps/plugin-process"
 import { ask, message } from "@tauri-apps/plugin-dialog"
 import { invoke } from "@tauri-apps/api/core"
@@ -6,10 +6,18 @@ import { type as ostype } from "@tauri-apps/plugin-os"
 
 export const UPDATER_ENABLED = window.__OPENCODE__?.updaterEnabled ?? false
 
+type UpdateMetadata = ConstructorParameters<typeof Update>[0]
+
+// Checks the update source from the settings or policy, which the plugin's own check() doesn't know about
+export async function checkForUpdate() {
+  const metadata = await invoke<UpdateMetadata | null>("check_for_update")
+  return metadata ? new Update(metadata) : null
+}
+
 export async function runUpdater({ alertOnFail }: { alertOnFail: boolean }) {
   let update
   try {
-    update = await check()
+    update = await checkForUpdate()
   } catch {
     if (alertOnFail) await message("Failed to check for updates", { title: "Update Check Failed" })
     return